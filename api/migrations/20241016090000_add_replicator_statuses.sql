@@ -0,0 +1,3 @@
+alter table app.replicators
+    add column desired_status app.replicator_status not null default 'stopped',
+    add column status app.replicator_status not null default 'stopped';
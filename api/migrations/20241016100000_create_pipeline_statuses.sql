@@ -0,0 +1,12 @@
+create table
+    app.pipeline_statuses (
+        pipeline_id bigint primary key references app.pipelines (id) on delete cascade,
+        tenant_id text references app.tenants (id) not null,
+        phase text not null,
+        last_flushed_lsn text,
+        lag_bytes bigint,
+        lag_secs bigint,
+        tables jsonb not null,
+        last_error text,
+        reported_at timestamptz not null default now()
+    );
@@ -0,0 +1,7 @@
+create table
+    app.api_keys (
+        id bigint generated always as identity primary key,
+        tenant_id text references app.tenants (id) on delete cascade not null,
+        name text not null,
+        key_hash bytea not null unique
+    );
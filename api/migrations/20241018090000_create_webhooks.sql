@@ -0,0 +1,28 @@
+create table
+    app.webhooks (
+        id bigint generated always as identity primary key,
+        tenant_id text references app.tenants (id) on delete cascade not null,
+        name text not null,
+        url text not null,
+        events text[] not null,
+        lag_threshold_secs bigint,
+        secret jsonb not null
+    );
+
+create table
+    app.webhook_deliveries (
+        id bigint generated always as identity primary key,
+        webhook_id bigint references app.webhooks (id) on delete cascade not null,
+        tenant_id text references app.tenants (id) on delete cascade not null,
+        event text not null,
+        payload jsonb not null,
+        attempts integer not null default 0,
+        next_attempt_at timestamptz default now(),
+        delivered_at timestamptz,
+        last_error text,
+        created_at timestamptz not null default now()
+    );
+
+create index webhook_deliveries_next_attempt_at_idx
+    on app.webhook_deliveries (next_attempt_at)
+    where next_attempt_at is not null;
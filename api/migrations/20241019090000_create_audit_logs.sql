@@ -0,0 +1,15 @@
+-- No foreign key to the tenant, so that a tenant's audit log outlives it
+create table
+    app.audit_logs (
+        id bigint generated always as identity primary key,
+        tenant_id text not null,
+        actor text not null,
+        action text not null,
+        resource_type text not null,
+        resource_id text not null,
+        before jsonb,
+        after jsonb,
+        created_at timestamptz not null default now()
+    );
+
+create index audit_logs_tenant_id_id_idx on app.audit_logs (tenant_id, id);
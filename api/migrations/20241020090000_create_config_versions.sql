@@ -0,0 +1,49 @@
+alter table app.sources add column version integer not null default 1;
+
+alter table app.sinks add column version integer not null default 1;
+
+alter table app.pipelines add column version integer not null default 1;
+
+-- configs are stored as in their resource's table, with secrets encrypted
+create table
+    app.source_versions (
+        source_id bigint references app.sources (id) on delete cascade not null,
+        version integer not null,
+        name text not null,
+        config jsonb not null,
+        created_at timestamptz not null default now(),
+        primary key (source_id, version)
+    );
+
+create table
+    app.sink_versions (
+        sink_id bigint references app.sinks (id) on delete cascade not null,
+        version integer not null,
+        name text not null,
+        config jsonb not null,
+        created_at timestamptz not null default now(),
+        primary key (sink_id, version)
+    );
+
+-- source and sink ids are not foreign keys as a version may refer to a
+-- source or sink deleted since
+create table
+    app.pipeline_versions (
+        pipeline_id bigint references app.pipelines (id) on delete cascade not null,
+        version integer not null,
+        source_id bigint not null,
+        sink_id bigint not null,
+        publication_name text not null,
+        config jsonb not null,
+        created_at timestamptz not null default now(),
+        primary key (pipeline_id, version)
+    );
+
+insert into app.source_versions (source_id, version, name, config)
+select id, version, name, config from app.sources;
+
+insert into app.sink_versions (sink_id, version, name, config)
+select id, version, name, config from app.sinks;
+
+insert into app.pipeline_versions (pipeline_id, version, source_id, sink_id, publication_name, config)
+select id, version, source_id, sink_id, publication_name, config from app.pipelines;
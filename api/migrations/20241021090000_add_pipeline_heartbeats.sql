@@ -0,0 +1,6 @@
+alter table app.pipeline_statuses
+    add column pid integer,
+    add column host text,
+    add column health text not null default 'healthy',
+    add column restart_count integer not null default 0,
+    add column restarted_at timestamptz;
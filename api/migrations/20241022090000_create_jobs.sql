@@ -0,0 +1,21 @@
+create table
+    app.jobs (
+        id bigint generated always as identity primary key,
+        tenant_id text references app.tenants (id) on delete cascade not null,
+        kind text not null,
+        params jsonb not null,
+        status text not null default 'queued',
+        progress jsonb,
+        result jsonb,
+        error text,
+        -- a running job whose lease expired is run again, as the api
+        -- instance running it stopped
+        lease_expires_at timestamptz,
+        created_at timestamptz not null default now(),
+        started_at timestamptz,
+        finished_at timestamptz
+    );
+
+create index jobs_pending_idx
+    on app.jobs (id)
+    where status in ('queued', 'running');
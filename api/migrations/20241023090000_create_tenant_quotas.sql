@@ -0,0 +1,9 @@
+-- A limit which is null is not enforced
+create table
+    app.tenant_quotas (
+        tenant_id text primary key references app.tenants (id) on delete cascade,
+        max_pipelines integer,
+        max_tables_per_pipeline integer,
+        max_sinks integer,
+        updated_at timestamptz not null default now()
+    );
@@ -0,0 +1,11 @@
+-- Two pipelines replicating the same source to the same sink would fight
+-- over the source's replication slot
+create unique index pipelines_tenant_id_source_id_sink_id_idx
+    on app.pipelines (tenant_id, source_id, sink_id);
+
+-- Key sent by clients to retry creating a pipeline without creating it twice
+alter table app.pipelines
+    add column idempotency_key text;
+
+create unique index pipelines_tenant_id_idempotency_key_idx
+    on app.pipelines (tenant_id, idempotency_key);
@@ -0,0 +1,21 @@
+-- Deleted sources, sinks and pipelines are kept for a retention window, in
+-- which they can be restored, before they are purged
+alter table app.sources add column deleted_at timestamptz;
+
+alter table app.sinks add column deleted_at timestamptz;
+
+alter table app.pipelines add column deleted_at timestamptz;
+
+-- a deleted pipeline doesn't stop another one from being created with its
+-- source and sink or idempotency key, and then can't be restored
+drop index app.pipelines_tenant_id_source_id_sink_id_idx;
+
+create unique index pipelines_tenant_id_source_id_sink_id_idx
+    on app.pipelines (tenant_id, source_id, sink_id)
+    where deleted_at is null;
+
+drop index app.pipelines_tenant_id_idempotency_key_idx;
+
+create unique index pipelines_tenant_id_idempotency_key_idx
+    on app.pipelines (tenant_id, idempotency_key)
+    where deleted_at is null;
@@ -0,0 +1,11 @@
+-- a pinned replicator keeps its image when the tenant's pipelines are
+-- upgraded, others follow the default image
+alter table app.replicators
+    add column image_pinned boolean not null default false;
+
+-- only one image is the default one
+update app.images
+set is_default = false
+where is_default and id <> (select max(id) from app.images where is_default);
+
+create unique index images_single_default on app.images (is_default) where is_default;
@@ -0,0 +1,4 @@
+-- counters of the changes of each table replicated by a pipeline, as last
+-- reported by its replicator
+alter table app.pipeline_statuses
+    add column table_metrics jsonb not null default '[]';
@@ -0,0 +1,5 @@
+-- lag computed from the position of the source's wal, as last reported by
+-- the replicator
+alter table app.pipeline_statuses
+    add column source_wal_lsn text,
+    add column estimated_lag_secs bigint;
@@ -0,0 +1,4 @@
+-- keys the replicator of a pipeline reports its status with, which can't
+-- make any other request
+alter table app.api_keys
+    add column pipeline_id bigint references app.pipelines (id) on delete cascade;
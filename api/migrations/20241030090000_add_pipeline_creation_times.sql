@@ -0,0 +1,4 @@
+-- pipelines whose replicator never reported their status are overdue once
+-- they were created longer ago than the health thresholds
+alter table app.pipelines
+    add column created_at timestamptz not null default now();
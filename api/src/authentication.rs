@@ -1,14 +1,108 @@
-use actix_web::{dev::ServiceRequest, web::Data, Error};
+use actix_web::{
+    dev::ServiceRequest,
+    error::{ErrorForbidden, ErrorInternalServerError},
+    http::{
+        header::{HeaderName, HeaderValue},
+        Method,
+    },
+    web::Data,
+    Error, HttpMessage,
+};
 use actix_web_httpauth::extractors::{
     bearer::{BearerAuth, Config},
     AuthenticationError,
 };
+use aws_lc_rs::{
+    digest::{digest, SHA256},
+    error::Unspecified,
+    rand::fill,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
 use constant_time_eq::constant_time_eq_n;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{configuration::ApiKey, db, oidc::OidcValidator};
+
+/// Path prefixes under /v1 which are not scoped to a tenant, so only admins
+/// not restricted to a tenant can access them, and /metrics, whose series are
+/// labelled with the pipelines and tables of every tenant
+pub(crate) const ADMIN_ONLY_PATHS: [&str; 3] = ["/tenants", "/images", "/metrics"];
+
+/// Paths under /v1/tenants/{tenant_id} which identities restricted to that
+/// tenant can access too, with the role they need. Applying a config only
+/// makes changes editors can make anyway.
+const TENANT_PATHS: [(&str, Role); 2] = [("/audit", Role::Admin), ("/config", Role::Editor)];
+
+/// Path prefixes under /v1 which need the admin role even within a tenant
+const TENANT_ADMIN_PATHS: [&str; 2] = ["/api-keys", "/webhooks"];
+
+/// Roles of authenticated users, each granting everything the previous one
+/// grants: viewers can read, editors can also create, update and delete, also
+/// by applying a tenant config, and
+/// admins can also manage api keys and webhooks, read the audit log and, if
+/// not restricted to a tenant, tenants, images and the metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
 
-use crate::configuration::ApiKey;
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Who made a request, as recorded in the audit log: `admin` for the api key
+/// from the configuration, `api_key:<id>` for a tenant's api key and
+/// `user:<sub>` for a JWT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor(pub String);
+
+struct Authenticated {
+    role: Role,
+    tenant_id: Option<String>,
+    /// Pipeline whose status is all the identity can report
+    pipeline_id: Option<i64>,
+    actor: Actor,
+}
 
+/// Generates a new tenant api key. Returns the base64 encoded key, which is
+/// only ever shown to the user, and its hash, which is stored.
+pub fn generate_api_key() -> Result<(String, Vec<u8>), Unspecified> {
+    let mut key = [0u8; 32];
+    fill(&mut key)?;
+    let encoded_key = BASE64_STANDARD.encode(key);
+    Ok((encoded_key, hash_api_key(&ApiKey { key })))
+}
+
+fn hash_api_key(api_key: &ApiKey) -> Vec<u8> {
+    digest(&SHA256, &api_key.key).as_ref().to_vec()
+}
+
+/// Authenticates requests with the api key from the configuration, which is
+/// an admin of every tenant, a tenant's api key, which is an editor of that
+/// tenant or, if it belongs to a pipeline, can only report the pipeline's
+/// status, or a JWT from the configured OpenID Connect issuer, whose claims
+/// carry the role and optionally the tenant. Then checks that the role is
+/// allowed to make the request.
 pub async fn auth_validator(
-    req: ServiceRequest,
+    mut req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
     let config = req
@@ -17,26 +111,254 @@ pub async fn auth_validator(
         .unwrap_or_default()
         .scope("v1");
 
-    let api_key: &str = req.app_data::<Data<String>>().expect("missing api_key");
     let token = credentials.token();
-
-    let api_key: ApiKey = match api_key.try_into() {
-        Ok(api_key) => api_key,
-        Err(_) => {
+    let authenticated = if token.contains('.') {
+        authenticate_jwt(&req, token).await
+    } else {
+        authenticate_api_key(&req, token).await
+    };
+    let authenticated = match authenticated {
+        Ok(Some(authenticated)) => authenticated,
+        Ok(None) => {
             return Err((AuthenticationError::from(config).into(), req));
         }
+        Err(e) => {
+            return Err((e, req));
+        }
     };
 
-    let token: ApiKey = match token.try_into() {
-        Ok(token) => token,
-        Err(_) => {
-            return Err((AuthenticationError::from(config).into(), req));
+    if let Err(e) = authorize(
+        &mut req,
+        authenticated.role,
+        authenticated.tenant_id.as_deref(),
+    ) {
+        return Err((e, req));
+    }
+    if let Some(pipeline_id) = authenticated.pipeline_id {
+        if let Err(e) = restrict_to_pipeline_status(&req, pipeline_id) {
+            return Err((e, req));
         }
+    }
+    req.extensions_mut().insert(authenticated.actor);
+
+    Ok(req)
+}
+
+async fn authenticate_api_key(
+    req: &ServiceRequest,
+    token: &str,
+) -> Result<Option<Authenticated>, Error> {
+    let api_key: &str = req.app_data::<Data<String>>().expect("missing api_key");
+
+    let Ok(api_key) = ApiKey::try_from(api_key) else {
+        return Ok(None);
+    };
+    let Ok(token) = ApiKey::try_from(token) else {
+        return Ok(None);
     };
 
-    if !constant_time_eq_n(&api_key.key, &token.key) {
-        return Err((AuthenticationError::from(config).into(), req));
+    if constant_time_eq_n(&api_key.key, &token.key) {
+        return Ok(Some(Authenticated {
+            role: Role::Admin,
+            tenant_id: None,
+            pipeline_id: None,
+            actor: Actor("admin".to_string()),
+        }));
     }
 
-    Ok(req)
+    let pool: &PgPool = req.app_data::<Data<PgPool>>().expect("missing pool");
+    match db::api_keys::read_api_key_by_hash(pool, &hash_api_key(&token)).await {
+        Ok(api_key) => Ok(api_key.map(|api_key| Authenticated {
+            role: Role::Editor,
+            tenant_id: Some(api_key.tenant_id),
+            pipeline_id: api_key.pipeline_id,
+            actor: Actor(format!("api_key:{}", api_key.id)),
+        })),
+        Err(e) => {
+            error!("failed to read api key: {e}");
+            Err(ErrorInternalServerError("internal server error"))
+        }
+    }
+}
+
+async fn authenticate_jwt(
+    req: &ServiceRequest,
+    token: &str,
+) -> Result<Option<Authenticated>, Error> {
+    let Some(oidc_validator) = req.app_data::<Data<OidcValidator>>() else {
+        return Ok(None);
+    };
+
+    match oidc_validator.validate(token).await {
+        Ok(claims) => Ok(Some(Authenticated {
+            role: claims.role,
+            tenant_id: claims.tenant_id,
+            pipeline_id: None,
+            actor: Actor(format!(
+                "user:{}",
+                claims.subject.as_deref().unwrap_or("unknown")
+            )),
+        })),
+        Err(e) => {
+            info!("rejected token: {e}");
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the tenant id in the path and the role needed for paths in
+/// [TENANT_PATHS]
+fn tenant_path(path: &str) -> Option<(&str, Role)> {
+    let (tenant_id, tenant_path) = path.strip_prefix("/tenants/")?.split_once('/')?;
+    TENANT_PATHS
+        .iter()
+        .find(|(path, _)| path.strip_prefix('/') == Some(tenant_path))
+        .map(|(_, role)| (tenant_id, *role))
+}
+
+fn required_role(req: &ServiceRequest) -> Role {
+    let path = req.path().strip_prefix("/v1").unwrap_or(req.path());
+    let is_under = |prefixes: &[&str]| prefixes.iter().any(|prefix| path.starts_with(prefix));
+    if let Some((_, role)) = tenant_path(path) {
+        role
+    } else if is_under(&ADMIN_ONLY_PATHS) || is_under(&TENANT_ADMIN_PATHS) {
+        Role::Admin
+    } else if req.method() == Method::GET || req.method() == Method::HEAD {
+        Role::Viewer
+    } else {
+        Role::Editor
+    }
+}
+
+fn authorize(req: &mut ServiceRequest, role: Role, tenant_id: Option<&str>) -> Result<(), Error> {
+    if role < required_role(req) {
+        return Err(ErrorForbidden(format!(
+            "role {} can't access this resource",
+            role.as_str()
+        )));
+    }
+
+    if let Some(tenant_id) = tenant_id {
+        restrict_to_tenant(req, tenant_id)?;
+    }
+
+    Ok(())
+}
+
+/// Rejects requests outside of the tenant and sets the tenant id header so
+/// that requests authenticated as a tenant don't need to send it
+fn restrict_to_tenant(req: &mut ServiceRequest, tenant_id: &str) -> Result<(), Error> {
+    let path = req.path().strip_prefix("/v1").unwrap_or(req.path());
+    match tenant_path(path) {
+        Some((requested_tenant_id, _)) if requested_tenant_id != tenant_id => {
+            return Err(ErrorForbidden("this tenant can't be accessed"));
+        }
+        Some(_) => {}
+        None if ADMIN_ONLY_PATHS
+            .iter()
+            .any(|admin_path| path.starts_with(admin_path)) =>
+        {
+            return Err(ErrorForbidden(
+                "this resource can't be accessed from within a tenant",
+            ));
+        }
+        None => {}
+    }
+
+    let tenant_id_header = HeaderName::from_static("tenant_id");
+    if let Some(requested_tenant_id) = req.headers().get(&tenant_id_header) {
+        if requested_tenant_id.as_bytes() != tenant_id.as_bytes() {
+            return Err(ErrorForbidden("this tenant can't be accessed"));
+        }
+    }
+
+    let tenant_id = HeaderValue::from_str(tenant_id).map_err(ErrorInternalServerError)?;
+    req.headers_mut().insert(tenant_id_header, tenant_id);
+
+    Ok(())
+}
+
+/// Rejects requests other than the status reports of the pipeline, which
+/// are all the api key of a pipeline's replicator is for
+fn restrict_to_pipeline_status(req: &ServiceRequest, pipeline_id: i64) -> Result<(), Error> {
+    let path = req.path().strip_prefix("/v1").unwrap_or(req.path());
+    if req.method() != Method::POST || path != format!("/pipelines/{pipeline_id}/status") {
+        return Err(ErrorForbidden(
+            "this api key can only report the status of its pipeline",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::{required_role, restrict_to_pipeline_status, Role};
+
+    #[test]
+    fn reads_need_the_viewer_role() {
+        let req = TestRequest::get().uri("/v1/pipelines/1").to_srv_request();
+        assert_eq!(required_role(&req), Role::Viewer);
+    }
+
+    #[test]
+    fn writes_need_the_editor_role() {
+        let req = TestRequest::post().uri("/v1/pipelines/1").to_srv_request();
+        assert_eq!(required_role(&req), Role::Editor);
+        let req = TestRequest::delete().uri("/v1/sources/1").to_srv_request();
+        assert_eq!(required_role(&req), Role::Editor);
+    }
+
+    #[test]
+    fn tenants_images_api_keys_and_webhooks_need_the_admin_role() {
+        let req = TestRequest::get().uri("/v1/tenants").to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+        let req = TestRequest::get().uri("/v1/images/1").to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+        let req = TestRequest::post().uri("/v1/api-keys").to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+        let req = TestRequest::get().uri("/v1/webhooks").to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+    }
+
+    #[test]
+    fn the_metrics_need_the_admin_role() {
+        let req = TestRequest::get().uri("/metrics").to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+    }
+
+    #[test]
+    fn applying_a_tenant_config_needs_the_editor_role() {
+        let req = TestRequest::put()
+            .uri("/v1/tenants/abcdefghijklmnopqrst/config")
+            .to_srv_request();
+        assert_eq!(required_role(&req), Role::Editor);
+    }
+
+    #[test]
+    fn a_pipelines_api_key_can_only_report_its_status() {
+        let req = TestRequest::post()
+            .uri("/v1/pipelines/1/status")
+            .to_srv_request();
+        assert!(restrict_to_pipeline_status(&req, 1).is_ok());
+        let req = TestRequest::post()
+            .uri("/v1/pipelines/2/status")
+            .to_srv_request();
+        assert!(restrict_to_pipeline_status(&req, 1).is_err());
+        let req = TestRequest::get()
+            .uri("/v1/pipelines/1/status")
+            .to_srv_request();
+        assert!(restrict_to_pipeline_status(&req, 1).is_err());
+        let req = TestRequest::get().uri("/v1/sources").to_srv_request();
+        assert!(restrict_to_pipeline_status(&req, 1).is_err());
+    }
+
+    #[test]
+    fn the_audit_log_needs_the_admin_role() {
+        let req = TestRequest::get()
+            .uri("/v1/tenants/abcdefghijklmnopqrst/audit")
+            .to_srv_request();
+        assert_eq!(required_role(&req), Role::Admin);
+    }
 }
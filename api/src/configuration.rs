@@ -12,13 +12,59 @@ use thiserror::Error;
 #[derive(serde::Deserialize, Clone)]
 pub struct EncryptionKey {
     pub id: u32,
+
+    /// base64 encoded key, or with `kms` set, the base64 encoded ciphertext
+    /// of the key encrypted with the kms key
     pub key: String,
+
+    /// kms key which decrypts `key` at startup
+    #[serde(default)]
+    pub kms: Option<KmsSettings>,
 }
 
 impl Display for EncryptionKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "    id: {}", self.id)?;
-        writeln!(f, "    key: REDACTED")
+        writeln!(f, "    key: REDACTED")?;
+        if let Some(kms) = &self.kms {
+            write!(f, "    kms: {kms}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum KmsSettings {
+    /// AWS KMS key, used with the credentials from the `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables
+    AwsKms {
+        /// key id or arn
+        key_id: String,
+
+        /// region of the key
+        region: String,
+    },
+
+    /// GCP KMS key, used with the service account key file from the
+    /// `GOOGLE_APPLICATION_CREDENTIALS` environment variable or else the
+    /// instance's service account
+    GcpKms {
+        /// key name in the projects/*/locations/*/keyRings/*/cryptoKeys/* form
+        key_name: String,
+    },
+}
+
+impl Display for KmsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KmsSettings::AwsKms { key_id, region } => {
+                writeln!(f, "aws_kms {{ key_id: {key_id}, region: {region} }}")
+            }
+            KmsSettings::GcpKms { key_name } => {
+                writeln!(f, "gcp_kms {{ key_name: {key_name} }}")
+            }
+        }
     }
 }
 
@@ -110,8 +156,14 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub worker: WorkerSettings,
+    #[serde(default)]
+    pub kubernetes: KubernetesSettings,
     pub encryption_key: EncryptionKey,
     pub api_key: String,
+    #[serde(default)]
+    pub oidc: Option<OidcSettings>,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
 }
 
 impl Display for Settings {
@@ -120,8 +172,14 @@ impl Display for Settings {
         writeln!(f, "  database:\n{}", self.database)?;
         writeln!(f, "  application:\n{}", self.application)?;
         writeln!(f, "  worker:\n{}", self.worker)?;
+        writeln!(f, "  kubernetes:\n{}", self.kubernetes)?;
         writeln!(f, "  encryption_key:\n{}", self.encryption_key)?;
-        writeln!(f, "  api_key: REDACTED")
+        writeln!(f, "  api_key: REDACTED")?;
+        if let Some(oidc) = &self.oidc {
+            writeln!(f, "  oidc:\n{oidc}")?;
+        }
+        writeln!(f, "  metrics:\n{}", self.metrics)?;
+        Ok(())
     }
 }
 
@@ -201,11 +259,279 @@ impl Display for ApplicationSettings {
 pub struct WorkerSettings {
     /// interval after which the worker looks in the queue for tasks
     pub poll_interval_secs: u64,
+
+    /// interval after which deployed replicators are reconciled with the
+    /// pipelines which should be running
+    #[serde(default = "default_reconcile_interval_secs")]
+    pub reconcile_interval_secs: u64,
+
+    /// time after which jobs following a pipeline's copy fail
+    #[serde(default = "default_job_timeout_secs")]
+    pub job_timeout_secs: u64,
+
+    /// time for which deleted sources, sinks and pipelines can be restored
+    /// before they are purged
+    #[serde(default = "default_deleted_retention_secs")]
+    pub deleted_retention_secs: u64,
+
+    /// when pipelines are considered stalled or crashed and restarted
+    #[serde(default)]
+    pub health: HealthSettings,
 }
 
 impl Display for WorkerSettings {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "    poll_interval_secs: {}", self.poll_interval_secs)
+        writeln!(f, "    poll_interval_secs: {}", self.poll_interval_secs)?;
+        writeln!(
+            f,
+            "    reconcile_interval_secs: {}",
+            self.reconcile_interval_secs
+        )?;
+        writeln!(f, "    job_timeout_secs: {}", self.job_timeout_secs)?;
+        writeln!(
+            f,
+            "    deleted_retention_secs: {}",
+            self.deleted_retention_secs
+        )?;
+        write!(f, "    health:\n{}", self.health)
+    }
+}
+
+fn default_reconcile_interval_secs() -> u64 {
+    30
+}
+
+fn default_job_timeout_secs() -> u64 {
+    86_400
+}
+
+fn default_deleted_retention_secs() -> u64 {
+    604_800
+}
+
+/// Thresholds on the time since a started pipeline's last status report
+#[derive(serde::Deserialize, Clone)]
+pub struct HealthSettings {
+    /// seconds without a report after which a pipeline is stalled
+    #[serde(default = "default_stalled_after_secs")]
+    pub stalled_after_secs: i64,
+
+    /// seconds without a report after which a pipeline is crashed
+    #[serde(default = "default_crashed_after_secs")]
+    pub crashed_after_secs: i64,
+
+    /// whether crashed pipelines are restarted
+    #[serde(default = "default_auto_restart")]
+    pub auto_restart: bool,
+
+    /// restarts after which a crashed pipeline is left alone until it
+    /// reaches cdc again
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: i32,
+}
+
+fn default_stalled_after_secs() -> i64 {
+    60
+}
+
+fn default_crashed_after_secs() -> i64 {
+    300
+}
+
+fn default_auto_restart() -> bool {
+    true
+}
+
+fn default_max_restarts() -> i32 {
+    3
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            stalled_after_secs: default_stalled_after_secs(),
+            crashed_after_secs: default_crashed_after_secs(),
+            auto_restart: default_auto_restart(),
+            max_restarts: default_max_restarts(),
+        }
+    }
+}
+
+impl Display for HealthSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "      stalled_after_secs: {}", self.stalled_after_secs)?;
+        writeln!(f, "      crashed_after_secs: {}", self.crashed_after_secs)?;
+        writeln!(f, "      auto_restart: {}", self.auto_restart)?;
+        writeln!(f, "      max_restarts: {}", self.max_restarts)
+    }
+}
+
+/// Limits on the series of the per table metrics of pipelines. Tables left
+/// out are added up in the series of a `_other` table, so that databases with
+/// thousands of tables don't make as many series.
+#[derive(serde::Deserialize, Clone)]
+pub struct MetricsSettings {
+    /// tables with the most changes which get their own series, per pipeline
+    #[serde(default = "default_max_tables_per_pipeline")]
+    pub max_tables_per_pipeline: usize,
+
+    /// changes below which a table has no series of its own
+    #[serde(default)]
+    pub min_table_changes: u64,
+}
+
+fn default_max_tables_per_pipeline() -> usize {
+    100
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            max_tables_per_pipeline: default_max_tables_per_pipeline(),
+            min_table_changes: 0,
+        }
+    }
+}
+
+impl Display for MetricsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "    max_tables_per_pipeline: {}",
+            self.max_tables_per_pipeline
+        )?;
+        writeln!(f, "    min_table_changes: {}", self.min_table_changes)
+    }
+}
+
+/// Where and how the replicators of started pipelines are deployed
+#[derive(serde::Deserialize, Clone)]
+pub struct KubernetesSettings {
+    /// namespace of the replicators' stateful sets, config maps and secrets
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+
+    /// resources requested by and limiting each replicator container
+    #[serde(default)]
+    pub resources: Option<ReplicatorResources>,
+
+    /// secrets used to pull replicator images from private registries
+    #[serde(default)]
+    pub image_pull_secrets: Vec<String>,
+
+    /// url of the api as reached from the replicators, which report the
+    /// status of their pipelines to it
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+}
+
+fn default_namespace() -> String {
+    "replicator-data-plane".to_string()
+}
+
+fn default_api_url() -> String {
+    "http://api:8000".to_string()
+}
+
+impl Default for KubernetesSettings {
+    fn default() -> Self {
+        KubernetesSettings {
+            namespace: default_namespace(),
+            resources: None,
+            image_pull_secrets: vec![],
+            api_url: default_api_url(),
+        }
+    }
+}
+
+impl Display for KubernetesSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    namespace: {}", self.namespace)?;
+        if let Some(resources) = &self.resources {
+            write!(f, "    resources:\n{resources}")?;
+        }
+        writeln!(
+            f,
+            "    image_pull_secrets: {}",
+            self.image_pull_secrets.join(", ")
+        )?;
+        writeln!(f, "    api_url: {}", self.api_url)
+    }
+}
+
+/// Kubernetes resource quantities, e.g. `500m` cpu or `512Mi` memory
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct ResourceQuantities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+}
+
+impl Display for ResourceQuantities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cpu = self.cpu.as_deref().unwrap_or("-");
+        let memory = self.memory.as_deref().unwrap_or("-");
+        write!(f, "cpu: {cpu}, memory: {memory}")
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct ReplicatorResources {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests: Option<ResourceQuantities>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<ResourceQuantities>,
+}
+
+impl Display for ReplicatorResources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(requests) = &self.requests {
+            writeln!(f, "      requests: {requests}")?;
+        }
+        if let Some(limits) = &self.limits {
+            writeln!(f, "      limits: {limits}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lets users authenticate with JWTs from an OpenID Connect issuer, in
+/// addition to api keys
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcSettings {
+    /// issuer url, whose discovery document points to the keys signing tokens
+    pub issuer: String,
+
+    /// audience tokens must be issued for
+    pub audience: String,
+
+    /// claim holding the user's roles: admin, editor or viewer
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+
+    /// claim holding the tenant the user is restricted to, users without it
+    /// can access every tenant
+    #[serde(default = "default_tenant_claim")]
+    pub tenant_claim: String,
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+fn default_tenant_claim() -> String {
+    "tenant_id".to_string()
+}
+
+impl Display for OidcSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    issuer: {}", self.issuer)?;
+        writeln!(f, "    audience: {}", self.audience)?;
+        writeln!(f, "    roles_claim: {}", self.roles_claim)?;
+        writeln!(f, "    tenant_claim: {}", self.tenant_claim)
     }
 }
 
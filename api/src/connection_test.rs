@@ -0,0 +1,325 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgConnectOptions, Connection, PgConnection, Row};
+use utoipa::ToSchema;
+
+use crate::{
+    db::sinks::SinkConfig,
+    google_auth::{access_token, ServiceAccountKey},
+};
+
+pub(crate) const GOOGLE_SCOPES: &str =
+    "https://www.googleapis.com/auth/bigquery https://www.googleapis.com/auth/cloud-platform.read-only";
+pub(crate) const BIGQUERY_API_URL: &str = "https://bigquery.googleapis.com/bigquery/v2";
+const RESOURCE_MANAGER_API_URL: &str = "https://cloudresourcemanager.googleapis.com/v1";
+
+/// Dataset access roles which allow creating and writing to tables
+const DATASET_WRITER_ROLES: [&str; 5] = [
+    "WRITER",
+    "OWNER",
+    "roles/bigquery.dataEditor",
+    "roles/bigquery.dataOwner",
+    "roles/bigquery.admin",
+];
+
+/// Permissions a replicator needs in the sink's dataset
+const REQUIRED_BIGQUERY_PERMISSIONS: [&str; 5] = [
+    "bigquery.tables.create",
+    "bigquery.tables.get",
+    "bigquery.tables.update",
+    "bigquery.tables.updateData",
+    "bigquery.tables.getData",
+];
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConnectionCheck {
+    #[schema(example = "wal_level")]
+    pub name: String,
+    pub passed: bool,
+    #[schema(example = "wal_level is logical")]
+    pub message: String,
+}
+
+/// Outcome of a connection test. Checks which depend on an earlier failed
+/// check are not run.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConnectionTestResult {
+    pub passed: bool,
+    pub checks: Vec<ConnectionCheck>,
+}
+
+impl ConnectionTestResult {
+    fn new() -> ConnectionTestResult {
+        ConnectionTestResult {
+            passed: true,
+            checks: vec![],
+        }
+    }
+
+    fn check(&mut self, name: &str, passed: bool, message: impl Into<String>) -> bool {
+        self.passed &= passed;
+        self.checks.push(ConnectionCheck {
+            name: name.to_string(),
+            passed,
+            message: message.into(),
+        });
+        passed
+    }
+}
+
+/// Connects to a Postgres source and checks that it can be replicated from:
+/// logical wal_level, replication privilege, an existing or creatable slot
+/// and, if given, an existing publication.
+pub async fn test_source(
+    options: &PgConnectOptions,
+    slot_name: &str,
+    publication_name: Option<&str>,
+) -> ConnectionTestResult {
+    let mut result = ConnectionTestResult::new();
+
+    let mut connection = match PgConnection::connect_with(options).await {
+        Ok(connection) => {
+            result.check("connection", true, "connected to the source");
+            connection
+        }
+        Err(e) => {
+            result.check("connection", false, format!("failed to connect: {e}"));
+            return result;
+        }
+    };
+
+    if let Err(e) =
+        run_source_checks(&mut connection, slot_name, publication_name, &mut result).await
+    {
+        result.check("query", false, format!("failed to query the source: {e}"));
+    }
+
+    result
+}
+
+async fn run_source_checks(
+    connection: &mut PgConnection,
+    slot_name: &str,
+    publication_name: Option<&str>,
+    result: &mut ConnectionTestResult,
+) -> Result<(), sqlx::Error> {
+    let wal_level: String = sqlx::query("show wal_level")
+        .fetch_one(&mut *connection)
+        .await?
+        .get(0);
+    let message = if wal_level == "logical" {
+        "wal_level is logical".to_string()
+    } else {
+        format!("wal_level is {wal_level}, it must be logical")
+    };
+    result.check("wal_level", wal_level == "logical", message);
+
+    let can_replicate: bool =
+        sqlx::query("select rolreplication or rolsuper from pg_roles where rolname = current_user")
+            .fetch_one(&mut *connection)
+            .await?
+            .get(0);
+    result.check(
+        "replication_privilege",
+        can_replicate,
+        if can_replicate {
+            "the user has the replication privilege"
+        } else {
+            "the user needs the replication privilege"
+        },
+    );
+
+    let row = sqlx::query(
+        r#"
+        select
+            exists(select 1 from pg_replication_slots where slot_name = $1) as slot_exists,
+            (select count(*) from pg_replication_slots)
+                < current_setting('max_replication_slots')::bigint as slot_available
+        "#,
+    )
+    .bind(slot_name)
+    .fetch_one(&mut *connection)
+    .await?;
+    let slot_exists: bool = row.get("slot_exists");
+    let slot_available: bool = row.get("slot_available");
+    let message = if slot_exists {
+        format!("replication slot {slot_name} exists")
+    } else if slot_available {
+        format!("replication slot {slot_name} will be created")
+    } else {
+        format!("replication slot {slot_name} can't be created, max_replication_slots is reached")
+    };
+    result.check("replication_slot", slot_exists || slot_available, message);
+
+    if let Some(publication_name) = publication_name {
+        let exists: bool =
+            sqlx::query("select exists(select 1 from pg_publication where pubname = $1)")
+                .bind(publication_name)
+                .fetch_one(&mut *connection)
+                .await?
+                .get(0);
+        let message = if exists {
+            format!("publication {publication_name} exists")
+        } else {
+            format!("publication {publication_name} does not exist")
+        };
+        result.check("publication", exists, message);
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Dataset {
+    #[serde(default)]
+    access: Vec<DatasetAccess>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatasetAccess {
+    #[serde(default)]
+    role: String,
+    user_by_email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TestPermissionsResponse {
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+/// Authenticates against a BigQuery sink and checks that the dataset exists
+/// and that the service account can create and write to tables in it.
+pub async fn test_sink(client: &reqwest::Client, config: &SinkConfig) -> ConnectionTestResult {
+    let SinkConfig::BigQuery {
+        project_id,
+        dataset_id,
+        service_account_key,
+    } = config;
+    let mut result = ConnectionTestResult::new();
+
+    let key: ServiceAccountKey = match serde_json::from_str(service_account_key) {
+        Ok(key) => {
+            result.check("service_account_key", true, "service account key is valid");
+            key
+        }
+        Err(e) => {
+            result.check(
+                "service_account_key",
+                false,
+                format!("invalid service account key: {e}"),
+            );
+            return result;
+        }
+    };
+
+    let access_token = match access_token(client, &key, GOOGLE_SCOPES).await {
+        Ok(access_token) => {
+            result.check(
+                "authentication",
+                true,
+                format!("authenticated as {}", key.client_email),
+            );
+            access_token
+        }
+        Err(message) => {
+            result.check("authentication", false, message);
+            return result;
+        }
+    };
+
+    let dataset_url = format!("{BIGQUERY_API_URL}/projects/{project_id}/datasets/{dataset_id}");
+    let response = client
+        .get(&dataset_url)
+        .bearer_auth(&access_token)
+        .send()
+        .await;
+    let dataset: Dataset = match response {
+        Ok(response) if response.status().is_success() => match response.json().await {
+            Ok(dataset) => {
+                result.check("dataset", true, format!("dataset {dataset_id} exists"));
+                dataset
+            }
+            Err(e) => {
+                result.check("dataset", false, format!("invalid dataset response: {e}"));
+                return result;
+            }
+        },
+        Ok(response) => {
+            result.check(
+                "dataset",
+                false,
+                format!(
+                    "dataset {project_id}.{dataset_id} can't be read, status {}",
+                    response.status()
+                ),
+            );
+            return result;
+        }
+        Err(e) => {
+            result.check("dataset", false, format!("failed to read dataset: {e}"));
+            return result;
+        }
+    };
+
+    let granted_on_dataset = dataset.access.iter().any(|entry| {
+        entry.user_by_email.as_deref() == Some(key.client_email.as_str())
+            && DATASET_WRITER_ROLES.contains(&entry.role.as_str())
+    });
+    if granted_on_dataset {
+        result.check(
+            "permissions",
+            true,
+            format!("{} can write to the dataset", key.client_email),
+        );
+        return result;
+    }
+
+    // Without a role on the dataset itself the permissions must be granted
+    // on the project
+    let response = client
+        .post(format!(
+            "{RESOURCE_MANAGER_API_URL}/projects/{project_id}:testIamPermissions"
+        ))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({ "permissions": REQUIRED_BIGQUERY_PERMISSIONS }))
+        .send()
+        .await;
+    let granted = match response {
+        Ok(response) if response.status().is_success() => {
+            response.json::<TestPermissionsResponse>().await
+        }
+        Ok(response) => {
+            result.check(
+                "permissions",
+                false,
+                format!("failed to test permissions, status {}", response.status()),
+            );
+            return result;
+        }
+        Err(e) => Err(e),
+    };
+    match granted {
+        Ok(granted) => {
+            let missing: Vec<&str> = REQUIRED_BIGQUERY_PERMISSIONS
+                .into_iter()
+                .filter(|p| !granted.permissions.iter().any(|g| g == p))
+                .collect();
+            let message = if missing.is_empty() {
+                "all required permissions are granted".to_string()
+            } else {
+                format!("missing permissions: {}", missing.join(", "))
+            };
+            result.check("permissions", missing.is_empty(), message);
+        }
+        Err(e) => {
+            result.check(
+                "permissions",
+                false,
+                format!("failed to test permissions: {e}"),
+            );
+        }
+    }
+
+    result
+}
@@ -0,0 +1,168 @@
+use sqlx::{PgPool, QueryBuilder, Row};
+
+use super::pagination::{ListParams, Page};
+
+pub struct ApiKey {
+    pub id: i64,
+    pub tenant_id: String,
+    pub name: String,
+    /// Pipeline whose replicator reports its status with the key, which
+    /// can't make any other request
+    pub pipeline_id: Option<i64>,
+}
+
+pub async fn create_api_key(
+    pool: &PgPool,
+    tenant_id: &str,
+    name: &str,
+    key_hash: &[u8],
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        insert into app.api_keys (tenant_id, name, key_hash)
+        values ($1, $2, $3)
+        returning id
+        "#,
+        tenant_id,
+        name,
+        key_hash,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.id)
+}
+
+/// Replaces the key of an api key, which invalidates the old key
+pub async fn rotate_api_key(
+    pool: &PgPool,
+    tenant_id: &str,
+    api_key_id: i64,
+    key_hash: &[u8],
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.api_keys
+        set key_hash = $1
+        where tenant_id = $2 and id = $3
+        returning id
+        "#,
+        key_hash,
+        tenant_id,
+        api_key_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn delete_api_key(
+    pool: &PgPool,
+    tenant_id: &str,
+    api_key_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        delete from app.api_keys
+        where tenant_id = $1 and id = $2
+        returning id
+        "#,
+        tenant_id,
+        api_key_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn read_all_api_keys(
+    pool: &PgPool,
+    tenant_id: &str,
+    params: &ListParams<i64>,
+) -> Result<Page<ApiKey>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "select id, tenant_id, name, pipeline_id from app.api_keys where tenant_id = ",
+    );
+    query.push_bind(tenant_id);
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
+
+    let api_keys: Vec<ApiKey> = records
+        .into_iter()
+        .map(|r| {
+            Ok(ApiKey {
+                id: r.try_get("id")?,
+                tenant_id: r.try_get("tenant_id")?,
+                name: r.try_get("name")?,
+                pipeline_id: r.try_get("pipeline_id")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(api_keys, |api_key| (api_key.id, api_key.name.clone())))
+}
+
+/// Reads the api key with hash `key_hash`
+pub async fn read_api_key_by_hash(
+    pool: &PgPool,
+    key_hash: &[u8],
+) -> Result<Option<ApiKey>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id, tenant_id, name, pipeline_id
+        from app.api_keys
+        where key_hash = $1
+        "#,
+        key_hash,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| ApiKey {
+        id: r.id,
+        tenant_id: r.tenant_id,
+        name: r.name,
+        pipeline_id: r.pipeline_id,
+    }))
+}
+
+/// Replaces the key the replicator of a pipeline reports its status with,
+/// which invalidates the key of the replicator's previous deployment
+pub async fn replace_pipeline_api_key(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    key_hash: &[u8],
+) -> Result<i64, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        delete from app.api_keys
+        where tenant_id = $1 and pipeline_id = $2
+        "#,
+        tenant_id,
+        pipeline_id,
+    )
+    .execute(&mut *txn)
+    .await?;
+
+    let name = format!("replicator of pipeline {pipeline_id}");
+    let record = sqlx::query!(
+        r#"
+        insert into app.api_keys (tenant_id, name, key_hash, pipeline_id)
+        values ($1, $2, $3, $4)
+        returning id
+        "#,
+        tenant_id,
+        name,
+        key_hash,
+        pipeline_id,
+    )
+    .fetch_one(&mut *txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(record.id)
+}
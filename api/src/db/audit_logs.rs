@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, QueryBuilder, Row};
+use utoipa::ToSchema;
+
+use super::pagination::{ListParams, Page};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<AuditAction> {
+        match action {
+            "create" => Some(AuditAction::Create),
+            "update" => Some(AuditAction::Update),
+            "delete" => Some(AuditAction::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// Kinds of resources whose changes are audited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditResource {
+    Tenant,
+    Source,
+    Sink,
+    Pipeline,
+    Quotas,
+}
+
+impl AuditResource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditResource::Tenant => "tenant",
+            AuditResource::Source => "source",
+            AuditResource::Sink => "sink",
+            AuditResource::Pipeline => "pipeline",
+            AuditResource::Quotas => "quotas",
+        }
+    }
+
+    pub fn parse(resource: &str) -> Option<AuditResource> {
+        match resource {
+            "tenant" => Some(AuditResource::Tenant),
+            "source" => Some(AuditResource::Source),
+            "sink" => Some(AuditResource::Sink),
+            "pipeline" => Some(AuditResource::Pipeline),
+            "quotas" => Some(AuditResource::Quotas),
+            _ => None,
+        }
+    }
+}
+
+/// A change of a resource. `before` is not set for creates and `after` is
+/// not set for deletes.
+pub struct AuditLog {
+    pub id: i64,
+    pub tenant_id: String,
+    pub actor: String,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    /// Seconds since the unix epoch
+    pub created_at: i64,
+}
+
+#[derive(Default)]
+pub struct AuditLogFilter {
+    pub resource_type: Option<AuditResource>,
+    pub resource_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub actor: Option<String>,
+    /// Only changes made at or after this many seconds since the unix epoch
+    pub since: Option<i64>,
+    /// Only changes made before this many seconds since the unix epoch
+    pub until: Option<i64>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_audit_log(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    actor: &str,
+    action: AuditAction,
+    resource_type: AuditResource,
+    resource_id: &str,
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        insert into app.audit_logs (tenant_id, actor, action, resource_type, resource_id, before, after)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        returning id
+        "#,
+        tenant_id,
+        actor,
+        action.as_str(),
+        resource_type.as_str(),
+        resource_id,
+        before,
+        after
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.id)
+}
+
+/// Reads a page of the tenant's audit log matching `filter`
+pub async fn read_audit_logs(
+    pool: &PgPool,
+    tenant_id: &str,
+    filter: &AuditLogFilter,
+    params: &ListParams<i64>,
+) -> Result<Page<AuditLog>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        r#"
+        select id,
+            tenant_id,
+            actor,
+            action,
+            resource_type,
+            resource_id,
+            before,
+            after,
+            extract(epoch from created_at)::bigint as created_at
+        from app.audit_logs
+        where tenant_id = "#,
+    );
+    query.push_bind(tenant_id);
+    if let Some(resource_type) = filter.resource_type {
+        query.push(" and resource_type = ");
+        query.push_bind(resource_type.as_str());
+    }
+    if let Some(resource_id) = &filter.resource_id {
+        query.push(" and resource_id = ");
+        query.push_bind(resource_id.clone());
+    }
+    if let Some(action) = filter.action {
+        query.push(" and action = ");
+        query.push_bind(action.as_str());
+    }
+    if let Some(actor) = &filter.actor {
+        query.push(" and actor = ");
+        query.push_bind(actor.clone());
+    }
+    if let Some(since) = filter.since {
+        query.push(" and created_at >= to_timestamp(");
+        query.push_bind(since as f64);
+        query.push(")");
+    }
+    if let Some(until) = filter.until {
+        query.push(" and created_at < to_timestamp(");
+        query.push_bind(until as f64);
+        query.push(")");
+    }
+    params.push_to(&mut query, "id", "actor");
+    let records = query.build().fetch_all(pool).await?;
+
+    let audit_logs: Vec<AuditLog> = records
+        .into_iter()
+        .map(|r| {
+            Ok(AuditLog {
+                id: r.try_get("id")?,
+                tenant_id: r.try_get("tenant_id")?,
+                actor: r.try_get("actor")?,
+                action: r.try_get("action")?,
+                resource_type: r.try_get("resource_type")?,
+                resource_id: r.try_get("resource_id")?,
+                before: r.try_get("before")?,
+                after: r.try_get("after")?,
+                created_at: r.try_get("created_at")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(audit_logs, |audit_log| {
+        (audit_log.id, audit_log.actor.clone())
+    }))
+}
@@ -1,4 +1,6 @@
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgExecutor, PgPool, Postgres, QueryBuilder, Row, Transaction};
+
+use super::pagination::{ListParams, Page};
 
 pub struct Image {
     pub id: i64,
@@ -18,6 +20,9 @@ pub async fn create_image_txn(
     name: &str,
     is_default: bool,
 ) -> Result<i64, sqlx::Error> {
+    if is_default {
+        clear_default_image_txn(txn).await?;
+    }
     let record = sqlx::query!(
         r#"
         insert into app.images (name, is_default)
@@ -33,7 +38,24 @@ pub async fn create_image_txn(
     Ok(record.id)
 }
 
-pub async fn read_default_image(pool: &PgPool) -> Result<Option<Image>, sqlx::Error> {
+/// Makes no image the default one, before another one is made the default
+async fn clear_default_image_txn(txn: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update app.images
+        set is_default = false
+        where is_default = true
+        "#,
+    )
+    .execute(&mut **txn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn read_default_image(
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<Image>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
         select id, name, is_default
@@ -41,7 +63,7 @@ pub async fn read_default_image(pool: &PgPool) -> Result<Option<Image>, sqlx::Er
         where is_default = true
         "#,
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     Ok(record.map(|r| Image {
@@ -76,6 +98,10 @@ pub async fn update_image(
     name: &str,
     is_default: bool,
 ) -> Result<Option<i64>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    if is_default {
+        clear_default_image_txn(&mut txn).await?;
+    }
     let record = sqlx::query!(
         r#"
         update app.images
@@ -87,10 +113,16 @@ pub async fn update_image(
         is_default,
         image_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *txn)
     .await?;
+    // an image which doesn't exist doesn't take the default from another one,
+    // as the transaction is rolled back when dropped
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    txn.commit().await?;
 
-    Ok(record.map(|r| r.id))
+    Ok(Some(record.id))
 }
 
 pub async fn delete_image(pool: &PgPool, image_id: i64) -> Result<Option<i64>, sqlx::Error> {
@@ -108,24 +140,26 @@ pub async fn delete_image(pool: &PgPool, image_id: i64) -> Result<Option<i64>, s
     Ok(record.map(|r| r.id))
 }
 
-pub async fn read_all_images(pool: &PgPool) -> Result<Vec<Image>, sqlx::Error> {
-    let mut record = sqlx::query!(
-        r#"
-        select id, name, is_default
-        from app.images
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
-
-    Ok(record
-        .drain(..)
-        .map(|r| Image {
-            id: r.id,
-            name: r.name,
-            is_default: r.is_default,
+pub async fn read_all_images(
+    pool: &PgPool,
+    params: &ListParams<i64>,
+) -> Result<Page<Image>, sqlx::Error> {
+    let mut query = QueryBuilder::new("select id, name, is_default from app.images where true");
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
+
+    let images: Vec<Image> = records
+        .into_iter()
+        .map(|r| {
+            Ok(Image {
+                id: r.try_get("id")?,
+                name: r.try_get("name")?,
+                is_default: r.try_get("is_default")?,
+            })
         })
-        .collect())
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(images, |image| (image.id, image.name.clone())))
 }
 
 pub async fn read_image_by_replicator_id(
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<JobStatus> {
+        match status {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "succeeded" => Some(JobStatus::Succeeded),
+            "failed" => Some(JobStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// What a job does, with the resources it does it to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobParams {
+    /// Runs the connection test of a sink
+    SinkValidation { sink_id: i64 },
+    /// Starts a pipeline and follows it until it copied its tables
+    InitialCopy { pipeline_id: i64 },
+    /// Copies tables of a pipeline again, then follows it until it copied
+    /// them
+    TableResync {
+        pipeline_id: i64,
+        /// Tables to copy again, as `schema.table`
+        tables: Vec<String>,
+    },
+}
+
+impl JobParams {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobParams::SinkValidation { .. } => "sink_validation",
+            JobParams::InitialCopy { .. } => "initial_copy",
+            JobParams::TableResync { .. } => "table_resync",
+        }
+    }
+}
+
+pub struct Job {
+    pub id: i64,
+    pub tenant_id: String,
+    pub kind: String,
+    pub params: serde_json::Value,
+    pub status: String,
+    pub progress: Option<serde_json::Value>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    /// Seconds since the unix epoch
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+pub async fn create_job(
+    pool: &PgPool,
+    tenant_id: &str,
+    params: &JobParams,
+) -> Result<i64, sqlx::Error> {
+    let params_json = serde_json::to_value(params).expect("failed to serialize job params");
+    let record = sqlx::query!(
+        r#"
+        insert into app.jobs (tenant_id, kind, params)
+        values ($1, $2, $3)
+        returning id
+        "#,
+        tenant_id,
+        params.kind(),
+        params_json
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.id)
+}
+
+pub async fn read_job(
+    pool: &PgPool,
+    tenant_id: &str,
+    job_id: i64,
+) -> Result<Option<Job>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id,
+            tenant_id,
+            kind,
+            params,
+            status,
+            progress,
+            result,
+            error,
+            extract(epoch from created_at)::bigint as "created_at!",
+            extract(epoch from started_at)::bigint as started_at,
+            extract(epoch from finished_at)::bigint as finished_at
+        from app.jobs
+        where tenant_id = $1 and id = $2
+        "#,
+        tenant_id,
+        job_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| Job {
+        id: r.id,
+        tenant_id: r.tenant_id,
+        kind: r.kind,
+        params: r.params,
+        status: r.status,
+        progress: r.progress,
+        result: r.result,
+        error: r.error,
+        created_at: r.created_at,
+        started_at: r.started_at,
+        finished_at: r.finished_at,
+    }))
+}
+
+/// A job claimed by an api instance to run it
+pub struct ClaimedJob {
+    pub id: i64,
+    pub tenant_id: String,
+    pub params: serde_json::Value,
+}
+
+/// Claims the oldest queued job, or running job whose lease expired, and
+/// leases it for `lease_secs`. The lease has to be renewed while the job
+/// runs, else another api instance runs the job again.
+pub async fn claim_job(pool: &PgPool, lease_secs: i64) -> Result<Option<ClaimedJob>, sqlx::Error> {
+    let lease_secs = lease_secs as f64;
+    let record = sqlx::query!(
+        r#"
+        update app.jobs
+        set status = 'running',
+            started_at = coalesce(started_at, now()),
+            lease_expires_at = now() + make_interval(secs => $1)
+        where id = (
+            select id
+            from app.jobs
+            where status = 'queued'
+                or (status = 'running' and lease_expires_at <= now())
+            order by id
+            limit 1
+            for update skip locked
+        )
+        returning id, tenant_id, params
+        "#,
+        lease_secs
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| ClaimedJob {
+        id: r.id,
+        tenant_id: r.tenant_id,
+        params: r.params,
+    }))
+}
+
+/// Records the progress of a running job and renews its lease
+pub async fn update_job_progress(
+    pool: &PgPool,
+    job_id: i64,
+    progress: &serde_json::Value,
+    lease_secs: i64,
+) -> Result<(), sqlx::Error> {
+    let lease_secs = lease_secs as f64;
+    sqlx::query!(
+        r#"
+        update app.jobs
+        set progress = $2,
+            lease_expires_at = now() + make_interval(secs => $3)
+        where id = $1 and status = 'running'
+        "#,
+        job_id,
+        progress,
+        lease_secs
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn finish_job(
+    pool: &PgPool,
+    job_id: i64,
+    status: JobStatus,
+    result: Option<&serde_json::Value>,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update app.jobs
+        set status = $2,
+            result = $3,
+            error = $4,
+            lease_expires_at = null,
+            finished_at = now()
+        where id = $1
+        "#,
+        job_id,
+        status.as_str(),
+        result,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,8 +1,15 @@
+pub mod api_keys;
+pub mod audit_logs;
 pub mod images;
+pub mod jobs;
+pub mod pagination;
+pub mod pipeline_statuses;
 pub mod pipelines;
 pub mod publications;
+pub mod quotas;
 pub mod replicators;
 pub mod sinks;
 pub mod sources;
 pub mod tables;
 pub mod tenants;
+pub mod webhooks;
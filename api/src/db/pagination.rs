@@ -0,0 +1,173 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{Encode, Postgres, QueryBuilder, Type};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 50;
+pub const MAX_PAGE_LIMIT: i64 = 200;
+
+#[derive(Debug, Error)]
+pub enum PaginationError {
+    #[error("invalid cursor")]
+    InvalidCursor,
+
+    #[error("limit must be between 1 and {MAX_PAGE_LIMIT}")]
+    InvalidLimit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Id,
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Position after the last item of a page. The name is only set when
+/// sorting by name, the id breaks ties between equal names.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor<Id> {
+    id: Id,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl<Id: Serialize + DeserializeOwned> Cursor<Id> {
+    fn encode(&self) -> String {
+        let cursor = serde_json::to_vec(self).expect("failed to serialize cursor");
+        BASE64_URL_SAFE_NO_PAD.encode(cursor)
+    }
+
+    fn decode(cursor: &str) -> Result<Cursor<Id>, PaginationError> {
+        let cursor = BASE64_URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| PaginationError::InvalidCursor)?;
+        serde_json::from_slice(&cursor).map_err(|_| PaginationError::InvalidCursor)
+    }
+}
+
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor to request the next page with, not set on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Filter, sort order and position of a page of a list. Items are always
+/// ordered by id after the sort field so that pages are stable.
+pub struct ListParams<Id> {
+    name: Option<String>,
+    sort: SortField,
+    order: SortOrder,
+    after: Option<Cursor<Id>>,
+    limit: i64,
+}
+
+impl<Id> ListParams<Id>
+where
+    Id: Serialize + DeserializeOwned + Clone + Send + for<'q> Encode<'q, Postgres> + Type<Postgres>,
+    Id: 'static,
+{
+    pub fn new(
+        name: Option<String>,
+        sort: Option<SortField>,
+        order: Option<SortOrder>,
+        cursor: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<ListParams<Id>, PaginationError> {
+        let sort = sort.unwrap_or_default();
+        let after = cursor.map(Cursor::decode).transpose()?;
+        if let Some(after) = &after {
+            if (sort == SortField::Name) != after.name.is_some() {
+                return Err(PaginationError::InvalidCursor);
+            }
+        }
+
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(PaginationError::InvalidLimit);
+        }
+
+        Ok(ListParams {
+            name,
+            sort,
+            order: order.unwrap_or_default(),
+            after,
+            limit,
+        })
+    }
+
+    /// Appends the name filter, the cursor condition, the order by and the
+    /// limit to a query whose where clause is already started
+    pub fn push_to(
+        &self,
+        query: &mut QueryBuilder<'_, Postgres>,
+        id_column: &str,
+        name_column: &str,
+    ) {
+        if let Some(name) = &self.name {
+            let pattern = name
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            query.push(format!(" and {name_column} ilike "));
+            query.push_bind(format!("%{pattern}%"));
+        }
+
+        let (operator, direction) = match self.order {
+            SortOrder::Asc => (">", "asc"),
+            SortOrder::Desc => ("<", "desc"),
+        };
+        if let Some(after) = &self.after {
+            match &after.name {
+                Some(name) => {
+                    query.push(format!(" and ({name_column}, {id_column}) {operator} ("));
+                    query.push_bind(name.clone());
+                    query.push(", ");
+                    query.push_bind(after.id.clone());
+                    query.push(")");
+                }
+                None => {
+                    query.push(format!(" and {id_column} {operator} "));
+                    query.push_bind(after.id.clone());
+                }
+            }
+        }
+
+        match self.sort {
+            SortField::Id => query.push(format!(" order by {id_column} {direction}")),
+            SortField::Name => query.push(format!(
+                " order by {name_column} {direction}, {id_column} {direction}"
+            )),
+        };
+
+        // One more item than the limit tells whether there's a next page
+        query.push(" limit ");
+        query.push_bind(self.limit + 1);
+    }
+
+    /// Turns the items read with a query built by `push_to` into a page.
+    /// `key` returns the id and name of an item.
+    pub fn page<T>(&self, mut items: Vec<T>, key: impl Fn(&T) -> (Id, String)) -> Page<T> {
+        let has_next_page = items.len() as i64 > self.limit;
+        items.truncate(self.limit as usize);
+        let next_cursor = match items.last() {
+            Some(last) if has_next_page => {
+                let (id, name) = key(last);
+                let name = (self.sort == SortField::Name).then_some(name);
+                Some(Cursor { id, name }.encode())
+            }
+            _ => None,
+        };
+        Page { items, next_cursor }
+    }
+}
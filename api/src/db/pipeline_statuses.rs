@@ -0,0 +1,270 @@
+use sqlx::PgPool;
+
+/// Status of a pipeline as last reported by its replicator
+pub struct PipelineStatusReport {
+    pub phase: String,
+    pub last_flushed_lsn: Option<String>,
+    pub lag_bytes: Option<i64>,
+    pub lag_secs: Option<i64>,
+    /// Position of the source's WAL when the replicator last queried it
+    pub source_wal_lsn: Option<String>,
+    /// Time the replicator needs to catch up at the rate the source writes
+    /// WAL
+    pub estimated_lag_secs: Option<i64>,
+    pub tables: serde_json::Value,
+    pub last_error: Option<String>,
+    /// Process id of the replicator which sent the report
+    pub pid: Option<i32>,
+    /// Host the replicator which sent the report runs on
+    pub host: Option<String>,
+    /// Counters of the tables with changes since the replicator started
+    pub table_metrics: serde_json::Value,
+}
+
+pub struct ReportedPipelineStatus {
+    pub report: PipelineStatusReport,
+    pub secs_since_report: i64,
+    pub health: String,
+    pub restart_count: i32,
+}
+
+/// Lag and table metrics last reported by the replicator of a pipeline
+pub struct PipelineMetrics {
+    pub tenant_id: String,
+    pub pipeline_id: i64,
+    pub lag_bytes: Option<i64>,
+    pub lag_secs: Option<i64>,
+    pub estimated_lag_secs: Option<i64>,
+    pub table_metrics: serde_json::Value,
+}
+
+/// Time since the last status report, or restart, of a pipeline whose
+/// replicator should be running, or since its creation if it never reported
+pub struct PipelineHeartbeat {
+    pub tenant_id: String,
+    pub pipeline_id: i64,
+    pub health: String,
+    pub secs_since_report: i64,
+    pub restart_count: i32,
+}
+
+pub async fn upsert_pipeline_status(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    report: &PipelineStatusReport,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        insert into app.pipeline_statuses (pipeline_id, tenant_id, phase, last_flushed_lsn, lag_bytes, lag_secs, tables, last_error, pid, host, table_metrics, source_wal_lsn, estimated_lag_secs)
+        select id, tenant_id, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13
+        from app.pipelines
+        where tenant_id = $1 and id = $2 and deleted_at is null
+        on conflict (pipeline_id) do update
+        set phase = excluded.phase,
+            last_flushed_lsn = excluded.last_flushed_lsn,
+            lag_bytes = excluded.lag_bytes,
+            lag_secs = excluded.lag_secs,
+            tables = excluded.tables,
+            last_error = excluded.last_error,
+            pid = excluded.pid,
+            host = excluded.host,
+            table_metrics = excluded.table_metrics,
+            source_wal_lsn = excluded.source_wal_lsn,
+            estimated_lag_secs = excluded.estimated_lag_secs,
+            health = 'healthy',
+            restart_count = case
+                when excluded.phase = 'cdc' then 0
+                else app.pipeline_statuses.restart_count
+            end,
+            reported_at = now()
+        returning pipeline_id
+        "#,
+        tenant_id,
+        pipeline_id,
+        report.phase,
+        report.last_flushed_lsn,
+        report.lag_bytes,
+        report.lag_secs,
+        report.tables,
+        report.last_error,
+        report.pid,
+        report.host,
+        report.table_metrics,
+        report.source_wal_lsn,
+        report.estimated_lag_secs,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.pipeline_id))
+}
+
+pub async fn read_pipeline_status(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<ReportedPipelineStatus>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select phase,
+            last_flushed_lsn,
+            lag_bytes,
+            lag_secs,
+            source_wal_lsn,
+            estimated_lag_secs,
+            tables,
+            last_error,
+            pid,
+            host,
+            table_metrics,
+            health,
+            restart_count,
+            extract(epoch from now() - reported_at)::bigint as "secs_since_report!"
+        from app.pipeline_statuses
+        where tenant_id = $1 and pipeline_id = $2
+        "#,
+        tenant_id,
+        pipeline_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| ReportedPipelineStatus {
+        report: PipelineStatusReport {
+            phase: r.phase,
+            last_flushed_lsn: r.last_flushed_lsn,
+            lag_bytes: r.lag_bytes,
+            lag_secs: r.lag_secs,
+            source_wal_lsn: r.source_wal_lsn,
+            estimated_lag_secs: r.estimated_lag_secs,
+            tables: r.tables,
+            last_error: r.last_error,
+            pid: r.pid,
+            host: r.host,
+            table_metrics: r.table_metrics,
+        },
+        secs_since_report: r.secs_since_report,
+        health: r.health,
+        restart_count: r.restart_count,
+    }))
+}
+
+/// Reads the heartbeats of the pipelines whose replicators should be running
+pub async fn read_heartbeats(pool: &PgPool) -> Result<Vec<PipelineHeartbeat>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select p.tenant_id,
+            p.id as pipeline_id,
+            coalesce(s.health, 'healthy') as "health!",
+            coalesce(s.restart_count, 0) as "restart_count!",
+            extract(epoch from now() - coalesce(greatest(s.reported_at, s.restarted_at), p.created_at))::bigint as "secs_since_report!"
+        from app.pipelines p
+        join app.replicators r on r.id = p.replicator_id
+        left join app.pipeline_statuses s on s.pipeline_id = p.id
+        where r.desired_status = 'started' and p.deleted_at is null
+        order by p.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| PipelineHeartbeat {
+            tenant_id: r.tenant_id,
+            pipeline_id: r.pipeline_id,
+            health: r.health,
+            secs_since_report: r.secs_since_report,
+            restart_count: r.restart_count,
+        })
+        .collect())
+}
+
+/// Reads the lag and table metrics last reported by the pipelines which
+/// aren't deleted
+pub async fn read_pipeline_metrics(pool: &PgPool) -> Result<Vec<PipelineMetrics>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select s.tenant_id,
+            s.pipeline_id,
+            s.lag_bytes,
+            s.lag_secs,
+            s.estimated_lag_secs,
+            s.table_metrics
+        from app.pipeline_statuses s
+        join app.pipelines p on p.id = s.pipeline_id
+        where p.deleted_at is null
+        order by s.pipeline_id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| PipelineMetrics {
+            tenant_id: r.tenant_id,
+            pipeline_id: r.pipeline_id,
+            lag_bytes: r.lag_bytes,
+            lag_secs: r.lag_secs,
+            estimated_lag_secs: r.estimated_lag_secs,
+            table_metrics: r.table_metrics,
+        })
+        .collect())
+}
+
+/// Changes the health of a pipeline from `from` to `to`. Returns false if
+/// its health was no longer `from`, e.g. because it reported meanwhile. A
+/// pipeline which never reported gets a status without a report, reported
+/// when the pipeline was created, to record its health in.
+pub async fn update_pipeline_health(
+    pool: &PgPool,
+    pipeline_id: i64,
+    from: &str,
+    to: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        insert into app.pipeline_statuses (pipeline_id, tenant_id, phase, tables, health, reported_at)
+        select id, tenant_id, 'starting', '[]', $3, created_at
+        from app.pipelines
+        where id = $1
+        on conflict (pipeline_id) do update
+        set health = excluded.health
+        where app.pipeline_statuses.health = $2
+        "#,
+        pipeline_id,
+        from,
+        to,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Counts a restart of a crashed pipeline and marks it healthy again, unless
+/// another api instance restarted it since `restart_count` was read. Returns
+/// whether this call claimed the restart.
+pub async fn claim_restart(
+    pool: &PgPool,
+    pipeline_id: i64,
+    restart_count: i32,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        update app.pipeline_statuses
+        set restart_count = restart_count + 1,
+            restarted_at = now(),
+            health = 'healthy'
+        where pipeline_id = $1 and restart_count = $2 and health = 'crashed'
+        "#,
+        pipeline_id,
+        restart_count,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
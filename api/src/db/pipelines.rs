@@ -1,19 +1,214 @@
-use sqlx::PgPool;
+use std::collections::BTreeMap;
 
-use super::replicators::create_replicator_txn;
+use sqlx::{Acquire, PgExecutor, PgPool, Postgres, QueryBuilder, Row, Transaction};
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+use super::{
+    pagination::{ListParams, Page},
+    replicators::{create_replicator_txn, ReplicatorStatus},
+};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
 pub struct PipelineConfig {
     pub config: BatchConfig,
+
+    /// Tables of the publication to replicate. All of them are replicated,
+    /// to tables with the default names, if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tables: Option<TableSelection>,
+
+    /// Settings of source tables in `schema.name` form replacing the
+    /// pipeline's
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, TableOverrides>,
+}
+
+/// Settings of a table replacing the pipeline's. Partitioning and
+/// clustering are only used when the destination table is created
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub struct TableOverrides {
+    /// Name of the destination table, instead of the default or mapped
+    /// name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+
+    /// Whether the table's changes are applied to it or appended to its
+    /// change log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_mode: Option<WriteMode>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<TablePartitioning>,
+
+    /// Columns to cluster the table by, at most four
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clustering: Vec<String>,
+
+    /// Columns of the table encrypted before they are written, with the
+    /// replicator's encryption key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked_columns: Vec<String>,
+
+    /// Batches of the table's copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<TableBatchConfig>,
+}
+
+impl TableOverrides {
+    /// Returns a description of the first invalid setting
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .destination
+            .as_ref()
+            .is_some_and(|destination| destination.is_empty())
+        {
+            return Err("destination is empty".to_string());
+        }
+        if self.clustering.len() > 4 {
+            return Err("a table can be clustered by at most four columns".to_string());
+        }
+        if self.batch.as_ref().is_some_and(|batch| batch.max_size == 0) {
+            return Err("batch max_size must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema,
+)]
+pub enum WriteMode {
+    /// Apply changes so that the table holds the current state of the
+    /// source table
+    Upsert,
+
+    /// Append every change to the table's change log
+    AppendOnly,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub enum TablePartitioning {
+    /// Partition by a date or timestamp column
+    Column {
+        column: String,
+        granularity: PartitionGranularity,
+    },
+
+    /// Partition by the time rows were written
+    IngestionTime { granularity: PartitionGranularity },
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionGranularity {
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub struct TableBatchConfig {
+    /// maximum batch size in number of rows
+    pub max_size: usize,
+}
+
+/// Selects tables by their name in `schema.name` form, with patterns which
+/// may contain `*` wildcards. A table is replicated if it matches any
+/// include pattern, or there are none, and no exclude pattern.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub struct TableSelection {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    /// Destination table names of source tables in `schema.name` form,
+    /// instead of the default names
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mappings: BTreeMap<String, String>,
+}
+
+impl TableSelection {
+    /// Returns a description of the first invalid pattern or mapping
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(pattern) = self
+            .include
+            .iter()
+            .chain(&self.exclude)
+            .find(|pattern| pattern.is_empty())
+        {
+            return Err(format!("invalid table pattern \"{pattern}\""));
+        }
+
+        for (source_table, destination_table) in &self.mappings {
+            if source_table.split_once('.').is_none() {
+                return Err(format!(
+                    "source table \"{source_table}\" is not in schema.name form"
+                ));
+            }
+            if destination_table.is_empty() {
+                return Err(format!("destination table of \"{source_table}\" is empty"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the table, in `schema.name` form, is replicated
+    pub fn selects(&self, table_name: &str) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| glob_matches(pattern, table_name));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| glob_matches(pattern, table_name))
+    }
+}
+
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it matched up to, to
+    // backtrack to when the rest of the pattern doesn't match
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
 pub struct BatchConfig {
     /// maximum batch size in number of events
     pub max_size: usize,
 
     /// maximum duration, in seconds, to wait for a batch to fill
     pub max_fill_secs: u64,
+
+    /// whether batches end only on the commits of transactions, so that a
+    /// transaction is never split across batches
+    #[serde(default)]
+    pub transactional: bool,
 }
 
 pub struct Pipeline {
@@ -28,40 +223,203 @@ pub struct Pipeline {
     pub config: serde_json::Value,
 }
 
-pub async fn create_pipeline(
-    pool: &PgPool,
+/// A past or the current config of a pipeline
+pub struct PipelineVersion {
+    pub version: i32,
+    pub source_id: i64,
+    pub sink_id: i64,
+    pub publication_name: String,
+    pub config: serde_json::Value,
+    /// Seconds since the unix epoch
+    pub created_at: i64,
+}
+
+/// Whether a pipeline was created or an equal one already existed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineCreation {
+    Created(i64),
+    Existing(i64),
+}
+
+/// Reads the id of the tenant's pipeline created with the idempotency key
+/// or, if there's none, of its pipeline replicating the source to the sink
+pub async fn read_existing_pipeline_id(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
+    sink_id: i64,
+    idempotency_key: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id
+        from app.pipelines
+        where tenant_id = $1
+            and deleted_at is null
+            and (idempotency_key = $2 or (source_id = $3 and sink_id = $4))
+        order by idempotency_key = $2 desc nulls last
+        limit 1
+        "#,
+        tenant_id,
+        idempotency_key,
+        source_id,
+        sink_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Creates a pipeline unless the tenant already has one with the same
+/// idempotency key, or replicating the same source to the same sink, in
+/// which case the existing one is returned
+#[allow(clippy::too_many_arguments)]
+pub async fn create_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
     tenant_id: &str,
     source_id: i64,
     sink_id: i64,
     image_id: i64,
     publication_name: String,
     config: &PipelineConfig,
-) -> Result<i64, sqlx::Error> {
+    idempotency_key: Option<&str>,
+) -> Result<PipelineCreation, sqlx::Error> {
     let config = serde_json::to_value(config).expect("failed to serialize config");
-    let mut txn = pool.begin().await?;
-    let replicator_id = create_replicator_txn(&mut txn, tenant_id, image_id).await?;
+    // the replicator is created in a savepoint, so that it can be rolled back
+    // without the rest of the transaction
+    let mut savepoint = txn.begin().await?;
+    let replicator_id = create_replicator_txn(&mut savepoint, tenant_id, image_id).await?;
     let record = sqlx::query!(
         r#"
-        insert into app.pipelines (tenant_id, source_id, sink_id, replicator_id, publication_name, config)
-        values ($1, $2, $3, $4, $5, $6)
-        returning id
+        insert into app.pipelines (tenant_id, source_id, sink_id, replicator_id, publication_name, config, idempotency_key)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        on conflict do nothing
+        returning id, version
         "#,
         tenant_id,
         source_id,
         sink_id,
         replicator_id,
         publication_name,
+        config,
+        idempotency_key
+    )
+    .fetch_optional(&mut *savepoint)
+    .await?;
+
+    let Some(record) = record else {
+        // the pipeline exists, and the replicator created for this one is
+        // rolled back
+        savepoint.rollback().await?;
+        let id =
+            read_existing_pipeline_id(&mut **txn, tenant_id, source_id, sink_id, idempotency_key)
+                .await?
+                .ok_or(sqlx::Error::RowNotFound)?;
+        return Ok(PipelineCreation::Existing(id));
+    };
+    savepoint.commit().await?;
+    create_pipeline_version_txn(
+        txn,
+        record.id,
+        record.version,
+        source_id,
+        sink_id,
+        &publication_name,
+        &config,
+    )
+    .await?;
+
+    Ok(PipelineCreation::Created(record.id))
+}
+
+async fn create_pipeline_version_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    pipeline_id: i64,
+    version: i32,
+    source_id: i64,
+    sink_id: i64,
+    publication_name: &str,
+    config: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.pipeline_versions (pipeline_id, version, source_id, sink_id, publication_name, config)
+        values ($1, $2, $3, $4, $5, $6)
+        "#,
+        pipeline_id,
+        version,
+        source_id,
+        sink_id,
+        publication_name,
         config
     )
-    .fetch_one(&mut *txn)
+    .execute(&mut **txn)
+    .await?;
+
+    Ok(())
+}
+
+/// A started pipeline replicating from the same database as another source
+pub struct ConflictingPipeline {
+    pub id: i64,
+    pub publication_name: String,
+    /// Whether its source has the same replication slot
+    pub shares_slot: bool,
+}
+
+/// Reads the tenant's started pipelines, other than `pipeline_id`, which
+/// replicate from the database of the source and use its replication slot or
+/// the publication
+pub async fn read_conflicting_pipelines(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    pipeline_id: Option<i64>,
+    source_id: i64,
+    publication_name: &str,
+) -> Result<Vec<ConflictingPipeline>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select p.id,
+            p.publication_name,
+            s.config->'Postgres'->'slot_name' = source.config->'Postgres'->'slot_name' as "shares_slot!"
+        from app.pipelines p
+        join app.replicators r on r.id = p.replicator_id
+        join app.sources s on s.id = p.source_id
+        join app.sources source on source.tenant_id = p.tenant_id and source.id = $3
+        where p.tenant_id = $1
+            and p.id is distinct from $2
+            and p.deleted_at is null
+            and r.desired_status = 'started'
+            and s.config->'Postgres'->'host' = source.config->'Postgres'->'host'
+            and s.config->'Postgres'->'port' = source.config->'Postgres'->'port'
+            and s.config->'Postgres'->'name' = source.config->'Postgres'->'name'
+            and (
+                s.config->'Postgres'->'slot_name' = source.config->'Postgres'->'slot_name'
+                or p.publication_name = $4
+            )
+        order by p.id
+        "#,
+        tenant_id,
+        pipeline_id,
+        source_id,
+        publication_name
+    )
+    .fetch_all(executor)
     .await?;
-    txn.commit().await?;
 
-    Ok(record.id)
+    Ok(records
+        .into_iter()
+        .map(|r| ConflictingPipeline {
+            id: r.id,
+            publication_name: r.publication_name,
+            shares_slot: r.shares_slot,
+        })
+        .collect())
 }
 
 pub async fn read_pipeline(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     tenant_id: &str,
     pipeline_id: i64,
 ) -> Result<Option<Pipeline>, sqlx::Error> {
@@ -79,12 +437,12 @@ pub async fn read_pipeline(
         from app.pipelines p
         join app.sources sr on p.source_id = sr.id
         join app.sinks sn on p.sink_id = sn.id
-        where p.tenant_id = $1 and p.id = $2
+        where p.tenant_id = $1 and p.id = $2 and p.deleted_at is null
         "#,
         tenant_id,
         pipeline_id,
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     Ok(record.map(|r| Pipeline {
@@ -100,8 +458,8 @@ pub async fn read_pipeline(
     }))
 }
 
-pub async fn update_pipeline(
-    pool: &PgPool,
+pub async fn update_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
     tenant_id: &str,
     pipeline_id: i64,
     source_id: i64,
@@ -110,12 +468,37 @@ pub async fn update_pipeline(
     config: &PipelineConfig,
 ) -> Result<Option<i64>, sqlx::Error> {
     let config = serde_json::to_value(config).expect("failed to serialize config");
+    let version = update_pipeline_config_txn(
+        txn,
+        tenant_id,
+        pipeline_id,
+        source_id,
+        sink_id,
+        &publication_name,
+        &config,
+    )
+    .await?;
+
+    Ok(version.map(|_| pipeline_id))
+}
+
+/// Updates a pipeline and records its config as a new version. Returns the
+/// new version, or `None` if the pipeline doesn't exist.
+async fn update_pipeline_config_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    pipeline_id: i64,
+    source_id: i64,
+    sink_id: i64,
+    publication_name: &str,
+    config: &serde_json::Value,
+) -> Result<Option<i32>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
         update app.pipelines
-        set source_id = $1, sink_id = $2, publication_name = $3, config = $4
-        where tenant_id = $5 and id = $6
-        returning id
+        set source_id = $1, sink_id = $2, publication_name = $3, config = $4, version = version + 1
+        where tenant_id = $5 and id = $6 and deleted_at is null
+        returning version
         "#,
         source_id,
         sink_id,
@@ -124,37 +507,319 @@ pub async fn update_pipeline(
         tenant_id,
         pipeline_id
     )
+    .fetch_optional(&mut **txn)
+    .await?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    create_pipeline_version_txn(
+        txn,
+        pipeline_id,
+        record.version,
+        source_id,
+        sink_id,
+        publication_name,
+        config,
+    )
+    .await?;
+
+    Ok(Some(record.version))
+}
+
+/// Reads the versions of a pipeline's config, newest first
+pub async fn read_pipeline_versions(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Vec<PipelineVersion>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select v.version,
+            v.source_id,
+            v.sink_id,
+            v.publication_name,
+            v.config,
+            extract(epoch from v.created_at)::bigint as "created_at!"
+        from app.pipeline_versions v
+        join app.pipelines p on p.id = v.pipeline_id
+        where p.tenant_id = $1 and v.pipeline_id = $2 and p.deleted_at is null
+        order by v.version desc
+        "#,
+        tenant_id,
+        pipeline_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| PipelineVersion {
+            version: r.version,
+            source_id: r.source_id,
+            sink_id: r.sink_id,
+            publication_name: r.publication_name,
+            config: r.config,
+            created_at: r.created_at,
+        })
+        .collect())
+}
+
+/// Reads a version of a pipeline's config
+pub async fn read_pipeline_version(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    version: i32,
+) -> Result<Option<PipelineVersion>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select v.version,
+            v.source_id,
+            v.sink_id,
+            v.publication_name,
+            v.config,
+            extract(epoch from v.created_at)::bigint as "created_at!"
+        from app.pipeline_versions v
+        join app.pipelines p on p.id = v.pipeline_id
+        where p.tenant_id = $1
+            and v.pipeline_id = $2
+            and v.version = $3
+            and p.deleted_at is null
+        "#,
+        tenant_id,
+        pipeline_id,
+        version
+    )
     .fetch_optional(pool)
     .await?;
 
-    Ok(record.map(|r| r.id))
+    Ok(record.map(|r| PipelineVersion {
+        version: r.version,
+        source_id: r.source_id,
+        sink_id: r.sink_id,
+        publication_name: r.publication_name,
+        config: r.config,
+        created_at: r.created_at,
+    }))
 }
 
-pub async fn delete_pipeline(
+/// Restores the config of a version of a pipeline, recording it as a new
+/// version. Returns the new version, or `None` if the pipeline or version
+/// don't exist.
+pub async fn rollback_pipeline(
     pool: &PgPool,
     tenant_id: &str,
     pipeline_id: i64,
+    version: i32,
+) -> Result<Option<i32>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let record = sqlx::query!(
+        r#"
+        select v.source_id, v.sink_id, v.publication_name, v.config
+        from app.pipeline_versions v
+        join app.pipelines p on p.id = v.pipeline_id
+        where p.tenant_id = $1
+            and v.pipeline_id = $2
+            and v.version = $3
+            and p.deleted_at is null
+        for update of p
+        "#,
+        tenant_id,
+        pipeline_id,
+        version
+    )
+    .fetch_optional(&mut *txn)
+    .await?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    let new_version = update_pipeline_config_txn(
+        &mut txn,
+        tenant_id,
+        pipeline_id,
+        record.source_id,
+        record.sink_id,
+        &record.publication_name,
+        &record.config,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(new_version)
+}
+
+/// Marks a pipeline as deleted, after which it's kept for the retention
+/// window before being purged. Its replicator is torn down while it's
+/// deleted, but its replication slot is kept, so a restored pipeline resumes
+/// where it stopped.
+pub async fn delete_pipeline(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    pipeline_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.pipelines
-        where tenant_id = $1 and id = $2
+        update app.pipelines
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
         returning id
         "#,
         tenant_id,
         pipeline_id
     )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// A deleted pipeline which can still be restored
+pub struct DeletedPipeline {
+    pub id: i64,
+    pub source_id: i64,
+    pub sink_id: i64,
+}
+
+pub async fn read_deleted_pipeline(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<DeletedPipeline>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id, source_id, sink_id
+        from app.pipelines
+        where tenant_id = $1 and id = $2 and deleted_at is not null
+        "#,
+        tenant_id,
+        pipeline_id
+    )
     .fetch_optional(pool)
     .await?;
 
+    Ok(record.map(|r| DeletedPipeline {
+        id: r.id,
+        source_id: r.source_id,
+        sink_id: r.sink_id,
+    }))
+}
+
+/// Restores a deleted pipeline which hasn't been purged yet. Fails with a
+/// unique violation if another pipeline was since created with its source
+/// and sink or idempotency key.
+pub async fn restore_pipeline(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.pipelines
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
+        returning id
+        "#,
+        tenant_id,
+        pipeline_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
     Ok(record.map(|r| r.id))
 }
 
+/// Deletes the pipelines deleted more than `retention_secs` ago. Returns the
+/// number of pipelines purged.
+pub async fn purge_deleted_pipelines(
+    pool: &PgPool,
+    retention_secs: i64,
+) -> Result<u64, sqlx::Error> {
+    let retention_secs = retention_secs as f64;
+    let result = sqlx::query!(
+        r#"
+        delete from app.pipelines
+        where deleted_at < now() - make_interval(secs => $1)
+        "#,
+        retention_secs
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Reads the ids of all pipelines of a tenant
+pub async fn read_pipeline_ids(pool: &PgPool, tenant_id: &str) -> Result<Vec<i64>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id
+        from app.pipelines
+        where tenant_id = $1 and deleted_at is null
+        order by id
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records.into_iter().map(|r| r.id).collect())
+}
+
+/// Reads the ids of the tenant's pipelines which use the source
+pub async fn read_pipeline_ids_by_source(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id
+        from app.pipelines
+        where tenant_id = $1 and source_id = $2 and deleted_at is null
+        order by id
+        "#,
+        tenant_id,
+        source_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(records.into_iter().map(|r| r.id).collect())
+}
+
+/// Reads the ids of the tenant's pipelines which use the sink
+pub async fn read_pipeline_ids_by_sink(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id
+        from app.pipelines
+        where tenant_id = $1 and sink_id = $2 and deleted_at is null
+        order by id
+        "#,
+        tenant_id,
+        sink_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(records.into_iter().map(|r| r.id).collect())
+}
+
+/// Reads a page of the tenant's pipelines, optionally only those whose
+/// replicator has the given status. Pipelines are named by their publication.
 pub async fn read_all_pipelines(
     pool: &PgPool,
     tenant_id: &str,
-) -> Result<Vec<Pipeline>, sqlx::Error> {
-    let mut record = sqlx::query!(
+    status: Option<ReplicatorStatus>,
+    params: &ListParams<i64>,
+) -> Result<Page<Pipeline>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
         r#"
         select p.id,
             p.tenant_id,
@@ -168,25 +833,35 @@ pub async fn read_all_pipelines(
         from app.pipelines p
         join app.sources sr on p.source_id = sr.id
         join app.sinks sn on p.sink_id = sn.id
-        where p.tenant_id = $1
-        "#,
-        tenant_id,
-    )
-    .fetch_all(pool)
-    .await?;
+        join app.replicators r on p.replicator_id = r.id
+        where p.deleted_at is null and p.tenant_id = "#,
+    );
+    query.push_bind(tenant_id);
+    if let Some(status) = status {
+        query.push(" and r.status = ");
+        query.push_bind(status);
+    }
+    params.push_to(&mut query, "p.id", "p.publication_name");
+    let records = query.build().fetch_all(pool).await?;
 
-    Ok(record
-        .drain(..)
-        .map(|r| Pipeline {
-            id: r.id,
-            tenant_id: r.tenant_id,
-            source_id: r.source_id,
-            source_name: r.source_name,
-            sink_id: r.sink_id,
-            sink_name: r.sink_name,
-            replicator_id: r.replicator_id,
-            publication_name: r.publication_name,
-            config: r.config,
+    let pipelines: Vec<Pipeline> = records
+        .into_iter()
+        .map(|r| {
+            Ok(Pipeline {
+                id: r.try_get("id")?,
+                tenant_id: r.try_get("tenant_id")?,
+                source_id: r.try_get("source_id")?,
+                source_name: r.try_get("source_name")?,
+                sink_id: r.try_get("sink_id")?,
+                sink_name: r.try_get("sink_name")?,
+                replicator_id: r.try_get("replicator_id")?,
+                publication_name: r.try_get("publication_name")?,
+                config: r.try_get("config")?,
+            })
         })
-        .collect())
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(pipelines, |pipeline| {
+        (pipeline.id, pipeline.publication_name.clone())
+    }))
 }
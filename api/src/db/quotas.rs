@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+
+/// Limits of a tenant's resources, set by admins. Limits which are not set
+/// are not enforced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TenantQuotas {
+    /// Maximum number of pipelines
+    #[schema(example = 10)]
+    pub max_pipelines: Option<i32>,
+
+    /// Maximum number of tables a pipeline replicates, counted in its
+    /// publication when it's created or updated
+    #[schema(example = 100)]
+    pub max_tables_per_pipeline: Option<i32>,
+
+    /// Maximum number of sinks
+    #[schema(example = 5)]
+    pub max_sinks: Option<i32>,
+}
+
+/// Reads the quotas of a tenant, which are all unset if an admin never set
+/// them
+pub async fn read_quotas(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+) -> Result<TenantQuotas, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select max_pipelines, max_tables_per_pipeline, max_sinks
+        from app.tenant_quotas
+        where tenant_id = $1
+        "#,
+        tenant_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record
+        .map(|r| TenantQuotas {
+            max_pipelines: r.max_pipelines,
+            max_tables_per_pipeline: r.max_tables_per_pipeline,
+            max_sinks: r.max_sinks,
+        })
+        .unwrap_or_default())
+}
+
+/// Reads the quotas of a tenant like [read_quotas] and holds a lock on them
+/// until the transaction ends, so that concurrent creations of the tenant's
+/// pipelines and sinks are counted one after another
+pub async fn lock_quotas(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+) -> Result<TenantQuotas, sqlx::Error> {
+    // an advisory lock, as tenants whose quotas were never set have no row
+    // to lock
+    sqlx::query("select pg_advisory_xact_lock(hashtext($1))")
+        .bind(tenant_id)
+        .execute(&mut **txn)
+        .await?;
+    read_quotas(&mut **txn, tenant_id).await
+}
+
+pub async fn set_quotas(
+    pool: &PgPool,
+    tenant_id: &str,
+    quotas: &TenantQuotas,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.tenant_quotas (tenant_id, max_pipelines, max_tables_per_pipeline, max_sinks)
+        values ($1, $2, $3, $4)
+        on conflict (tenant_id) do update
+        set max_pipelines = $2,
+            max_tables_per_pipeline = $3,
+            max_sinks = $4,
+            updated_at = now()
+        "#,
+        tenant_id,
+        quotas.max_pipelines,
+        quotas.max_tables_per_pipeline,
+        quotas.max_sinks
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn count_pipelines(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select count(*) as "count!"
+        from app.pipelines
+        where tenant_id = $1 and deleted_at is null
+        "#,
+        tenant_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.count)
+}
+
+pub async fn count_sinks(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select count(*) as "count!"
+        from app.sinks
+        where tenant_id = $1 and deleted_at is null
+        "#,
+        tenant_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.count)
+}
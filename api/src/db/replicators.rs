@@ -1,9 +1,41 @@
 use sqlx::{PgPool, Postgres, Transaction};
 
+/// Status of a replicator. A replicator's desired status is the one it was
+/// last asked to be in, either `Started` or `Stopped`, its status the one
+/// it was last seen in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, serde::Deserialize, utoipa::ToSchema)]
+#[sqlx(type_name = "app.replicator_status", rename_all = "lowercase")]
+pub enum ReplicatorStatus {
+    Stopped,
+    Starting,
+    Started,
+    Stopping,
+}
+
+impl ReplicatorStatus {
+    pub const ALL: [ReplicatorStatus; 4] = [
+        ReplicatorStatus::Stopped,
+        ReplicatorStatus::Starting,
+        ReplicatorStatus::Started,
+        ReplicatorStatus::Stopping,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplicatorStatus::Stopped => "stopped",
+            ReplicatorStatus::Starting => "starting",
+            ReplicatorStatus::Started => "started",
+            ReplicatorStatus::Stopping => "stopping",
+        }
+    }
+}
+
 pub struct Replicator {
     pub id: i64,
     pub tenant_id: String,
     pub image_id: i64,
+    pub desired_status: ReplicatorStatus,
+    pub status: ReplicatorStatus,
 }
 
 pub async fn create_replicator(
@@ -44,10 +76,14 @@ pub async fn read_replicator_by_pipeline_id(
 ) -> Result<Option<Replicator>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        select r.id, r.tenant_id, r.image_id
+        select r.id,
+            r.tenant_id,
+            r.image_id,
+            r.desired_status as "desired_status: ReplicatorStatus",
+            r.status as "status: ReplicatorStatus"
         from app.replicators r
         join app.pipelines p on r.id = p.replicator_id
-        where r.tenant_id = $1 and p.tenant_id = $1 and p.id = $2
+        where r.tenant_id = $1 and p.tenant_id = $1 and p.id = $2 and p.deleted_at is null
         "#,
         tenant_id,
         pipeline_id,
@@ -59,5 +95,214 @@ pub async fn read_replicator_by_pipeline_id(
         id: r.id,
         tenant_id: r.tenant_id,
         image_id: r.image_id,
+        desired_status: r.desired_status,
+        status: r.status,
+    }))
+}
+
+pub async fn update_replicator_desired_status(
+    pool: &PgPool,
+    tenant_id: &str,
+    replicator_id: i64,
+    desired_status: ReplicatorStatus,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.replicators
+        set desired_status = $1
+        where tenant_id = $2 and id = $3
+        returning id
+        "#,
+        desired_status as ReplicatorStatus,
+        tenant_id,
+        replicator_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn update_replicator_status(
+    pool: &PgPool,
+    tenant_id: &str,
+    replicator_id: i64,
+    status: ReplicatorStatus,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.replicators
+        set status = $1
+        where tenant_id = $2 and id = $3
+        returning id
+        "#,
+        status as ReplicatorStatus,
+        tenant_id,
+        replicator_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// A replicator which should be running, with its pipeline
+pub struct StartedReplicator {
+    pub id: i64,
+    pub tenant_id: String,
+    pub pipeline_id: i64,
+}
+
+pub async fn read_started_replicators(
+    pool: &PgPool,
+) -> Result<Vec<StartedReplicator>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select r.id, r.tenant_id, p.id as pipeline_id
+        from app.replicators r
+        join app.pipelines p on r.id = p.replicator_id
+        where r.desired_status = 'started' and p.deleted_at is null
+        order by r.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| StartedReplicator {
+            id: r.id,
+            tenant_id: r.tenant_id,
+            pipeline_id: r.pipeline_id,
+        })
+        .collect())
+}
+
+pub struct PipelineCount {
+    pub status: ReplicatorStatus,
+    pub count: i64,
+}
+
+/// Counts the pipelines of all tenants by the status of their replicator
+pub async fn count_pipelines_by_status(pool: &PgPool) -> Result<Vec<PipelineCount>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select r.status as "status: ReplicatorStatus", count(*) as "count!"
+        from app.replicators r
+        join app.pipelines p on r.id = p.replicator_id
+        where p.deleted_at is null
+        group by r.status
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| PipelineCount {
+            status: r.status,
+            count: r.count,
+        })
+        .collect())
+}
+
+/// The image a pipeline's replicator runs, which it keeps when the tenant's
+/// pipelines are upgraded if it's pinned
+pub struct PipelineImage {
+    pub image_id: i64,
+    pub image_name: String,
+    pub pinned: bool,
+}
+
+pub async fn read_pipeline_image(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<PipelineImage>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select i.id, i.name, r.image_pinned
+        from app.replicators r
+        join app.pipelines p on r.id = p.replicator_id
+        join app.images i on i.id = r.image_id
+        where r.tenant_id = $1 and p.tenant_id = $1 and p.id = $2 and p.deleted_at is null
+        "#,
+        tenant_id,
+        pipeline_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| PipelineImage {
+        image_id: r.id,
+        image_name: r.name,
+        pinned: r.image_pinned,
     }))
 }
+
+pub async fn update_pipeline_image(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    image_id: i64,
+    pinned: bool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.replicators r
+        set image_id = $3, image_pinned = $4
+        from app.pipelines p
+        where r.id = p.replicator_id
+            and r.tenant_id = $1 and p.tenant_id = $1 and p.id = $2 and p.deleted_at is null
+        returning r.id
+        "#,
+        tenant_id,
+        pipeline_id,
+        image_id,
+        pinned,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub struct UpgradedPipeline {
+    pub pipeline_id: i64,
+    pub previous_image_id: i64,
+}
+
+/// Moves the tenant's pipelines which aren't pinned to another image to the
+/// image, returning the pipelines moved with the image they ran before
+pub async fn upgrade_unpinned_pipelines(
+    pool: &PgPool,
+    tenant_id: &str,
+    image_id: i64,
+) -> Result<Vec<UpgradedPipeline>, sqlx::Error> {
+    // the self join reads the replicators as they were before the update
+    let records = sqlx::query!(
+        r#"
+        update app.replicators r
+        set image_id = $2
+        from app.pipelines p, app.replicators previous
+        where r.id = p.replicator_id and previous.id = r.id
+            and r.tenant_id = $1 and p.tenant_id = $1 and p.deleted_at is null
+            and r.image_pinned = false and r.image_id <> $2
+        returning p.id, previous.image_id as previous_image_id
+        "#,
+        tenant_id,
+        image_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut upgraded: Vec<UpgradedPipeline> = records
+        .into_iter()
+        .map(|r| UpgradedPipeline {
+            pipeline_id: r.id,
+            previous_image_id: r.previous_image_id,
+        })
+        .collect();
+    upgraded.sort_unstable_by_key(|u| u.pipeline_id);
+    Ok(upgraded)
+}
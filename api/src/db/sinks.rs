@@ -1,15 +1,17 @@
 use aws_lc_rs::{aead::Nonce, error::Unspecified};
 use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, QueryBuilder, Row, Transaction};
 use std::{
     fmt::{Debug, Formatter},
     str::{from_utf8, Utf8Error},
 };
 use thiserror::Error;
 
-use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey};
+use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey, REDACTED_SECRET};
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+use super::pagination::{ListParams, Page};
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
 pub enum SinkConfig {
     BigQuery {
         /// BigQuery project id
@@ -24,13 +26,41 @@ pub enum SinkConfig {
 }
 
 impl SinkConfig {
-    fn into_db_config(self, encryption_key: &EncryptionKey) -> Result<SinkConfigInDb, Unspecified> {
+    fn has_redacted_service_account_key(&self) -> bool {
+        let SinkConfig::BigQuery {
+            service_account_key,
+            ..
+        } = self;
+        service_account_key == REDACTED_SECRET
+    }
+
+    /// Encrypts the service account key, unless it is redacted, in which case
+    /// the service account key of `stored_config` is kept
+    fn into_db_config(
+        self,
+        encryption_key: &EncryptionKey,
+        stored_config: Option<SinkConfigInDb>,
+    ) -> Result<SinkConfigInDb, Unspecified> {
         let SinkConfig::BigQuery {
             project_id,
             dataset_id,
             service_account_key,
         } = self;
 
+        if let Some(SinkConfigInDb::BigQuery {
+            service_account_key: stored_sa_key,
+            ..
+        }) = stored_config
+        {
+            if service_account_key == REDACTED_SECRET {
+                return Ok(SinkConfigInDb::BigQuery {
+                    project_id,
+                    dataset_id,
+                    service_account_key: stored_sa_key,
+                });
+            }
+        }
+
         let (encrypted_sa_key, nonce) =
             encrypt(service_account_key.as_bytes(), &encryption_key.key)?;
         let encrypted_encoded_sa_key = BASE64_STANDARD.encode(encrypted_sa_key);
@@ -111,6 +141,22 @@ impl SinkConfigInDb {
             service_account_key: decrypted_sa_key,
         })
     }
+
+    /// Returns the config with the service account key redacted instead of
+    /// decrypted
+    fn into_redacted_config(self) -> SinkConfig {
+        let SinkConfigInDb::BigQuery {
+            project_id,
+            dataset_id,
+            service_account_key: _,
+        } = self;
+
+        SinkConfig::BigQuery {
+            project_id,
+            dataset_id,
+            service_account_key: REDACTED_SECRET.to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -141,147 +187,290 @@ pub struct Sink {
     pub config: SinkConfig,
 }
 
-pub async fn create_sink(
-    pool: &PgPool,
+/// A past or the current config of a sink, with its service account key redacted
+pub struct SinkVersion {
+    pub version: i32,
+    pub name: String,
+    pub config: SinkConfig,
+    /// Seconds since the unix epoch
+    pub created_at: i64,
+}
+
+pub async fn create_sink_txn(
+    txn: &mut Transaction<'_, Postgres>,
     tenant_id: &str,
     name: &str,
     config: SinkConfig,
     encryption_key: &EncryptionKey,
 ) -> Result<i64, SinksDbError> {
-    let db_config = config.into_db_config(encryption_key)?;
+    let db_config = config.into_db_config(encryption_key, None)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
     let record = sqlx::query!(
         r#"
         insert into app.sinks (tenant_id, name, config)
         values ($1, $2, $3)
-        returning id
+        returning id, version
         "#,
         tenant_id,
         name,
         db_config
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **txn)
     .await?;
+    create_sink_version_txn(txn, record.id, record.version, name, &db_config).await?;
 
     Ok(record.id)
 }
 
-pub async fn read_sink(
-    pool: &PgPool,
+async fn create_sink_version_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    sink_id: i64,
+    version: i32,
+    name: &str,
+    db_config: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.sink_versions (sink_id, version, name, config)
+        values ($1, $2, $3, $4)
+        "#,
+        sink_id,
+        version,
+        name,
+        db_config
+    )
+    .execute(&mut **txn)
+    .await?;
+
+    Ok(())
+}
+
+struct SinkInDb {
+    id: i64,
+    tenant_id: String,
+    name: String,
+    config: SinkConfigInDb,
+}
+
+async fn read_sink_in_db(
+    executor: impl PgExecutor<'_>,
     tenant_id: &str,
     sink_id: i64,
-    encryption_key: &EncryptionKey,
-) -> Result<Option<Sink>, SinksDbError> {
+) -> Result<Option<SinkInDb>, SinksDbError> {
     let record = sqlx::query!(
         r#"
         select id, tenant_id, name, config
         from app.sinks
-        where tenant_id = $1 and id = $2
+        where tenant_id = $1 and id = $2 and deleted_at is null
         "#,
         tenant_id,
         sink_id,
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     let sink = record
         .map(|r| {
             let config: SinkConfigInDb = serde_json::from_value(r.config)?;
-            let config = config.into_config(encryption_key)?;
-            let source = Sink {
+            let sink = SinkInDb {
                 id: r.id,
                 tenant_id: r.tenant_id,
                 name: r.name,
                 config,
             };
-            Ok::<Sink, SinksDbError>(source)
+            Ok::<SinkInDb, SinksDbError>(sink)
         })
         .transpose()?;
     Ok(sink)
 }
 
-pub async fn update_sink(
+/// Reads a sink with its service account key decrypted, only for the code
+/// connecting to the sink
+pub async fn read_sink(
     pool: &PgPool,
     tenant_id: &str,
+    sink_id: i64,
+    encryption_key: &EncryptionKey,
+) -> Result<Option<Sink>, SinksDbError> {
+    let sink = read_sink_in_db(pool, tenant_id, sink_id)
+        .await?
+        .map(|sink| {
+            let sink = Sink {
+                id: sink.id,
+                tenant_id: sink.tenant_id,
+                name: sink.name,
+                config: sink.config.into_config(encryption_key)?,
+            };
+            Ok::<Sink, SinksDbError>(sink)
+        })
+        .transpose()?;
+    Ok(sink)
+}
+
+/// Reads a sink with its service account key redacted
+pub async fn read_redacted_sink(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<Option<Sink>, SinksDbError> {
+    let sink = read_sink_in_db(executor, tenant_id, sink_id)
+        .await?
+        .map(|sink| Sink {
+            id: sink.id,
+            tenant_id: sink.tenant_id,
+            name: sink.name,
+            config: sink.config.into_redacted_config(),
+        });
+    Ok(sink)
+}
+
+pub async fn update_sink_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
     name: &str,
     sink_id: i64,
     config: SinkConfig,
     encryption_key: &EncryptionKey,
 ) -> Result<Option<i64>, SinksDbError> {
-    let db_config = config.into_db_config(encryption_key)?;
+    let stored_config = if config.has_redacted_service_account_key() {
+        read_sink_in_db(&mut **txn, tenant_id, sink_id)
+            .await?
+            .map(|sink| sink.config)
+    } else {
+        None
+    };
+    let db_config = config.into_db_config(encryption_key, stored_config)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
     let record = sqlx::query!(
         r#"
         update app.sinks
-        set config = $1, name = $2
-        where tenant_id = $3 and id = $4
-        returning id
+        set config = $1, name = $2, version = version + 1
+        where tenant_id = $3 and id = $4 and deleted_at is null
+        returning id, version
         "#,
         db_config,
         name,
         tenant_id,
         sink_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **txn)
     .await?;
+    if let Some(record) = &record {
+        create_sink_version_txn(txn, record.id, record.version, name, &db_config).await?;
+    }
 
     Ok(record.map(|r| r.id))
 }
 
-pub async fn delete_sink(
+/// Reads the versions of a sink's config, newest first
+pub async fn read_sink_versions(
     pool: &PgPool,
     tenant_id: &str,
     sink_id: i64,
+) -> Result<Vec<SinkVersion>, SinksDbError> {
+    let records = sqlx::query!(
+        r#"
+        select v.version,
+            v.name,
+            v.config,
+            extract(epoch from v.created_at)::bigint as "created_at!"
+        from app.sink_versions v
+        join app.sinks s on s.id = v.sink_id
+        where s.tenant_id = $1 and v.sink_id = $2 and s.deleted_at is null
+        order by v.version desc
+        "#,
+        tenant_id,
+        sink_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut versions = Vec::with_capacity(records.len());
+    for record in records {
+        let config: SinkConfigInDb = serde_json::from_value(record.config)?;
+        versions.push(SinkVersion {
+            version: record.version,
+            name: record.name,
+            config: config.into_redacted_config(),
+            created_at: record.created_at,
+        });
+    }
+
+    Ok(versions)
+}
+
+/// Marks a sink as deleted, after which it's kept for the retention window
+/// before being purged
+pub async fn delete_sink(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    sink_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.sinks
-        where tenant_id = $1 and id = $2
+        update app.sinks
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
         returning id
         "#,
         tenant_id,
         sink_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     Ok(record.map(|r| r.id))
 }
 
+/// Reads all sinks of a tenant with their service account keys redacted
 pub async fn read_all_sinks(
     pool: &PgPool,
     tenant_id: &str,
-    encryption_key: &EncryptionKey,
-) -> Result<Vec<Sink>, SinksDbError> {
+    params: &ListParams<i64>,
+) -> Result<Page<Sink>, SinksDbError> {
+    let mut query =
+        QueryBuilder::new(
+        "select id, tenant_id, name, config from app.sinks where deleted_at is null and tenant_id = ",
+    );
+    query.push_bind(tenant_id);
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
+
+    let mut sinks = Vec::with_capacity(records.len());
+    for record in records {
+        let config: SinkConfigInDb = serde_json::from_value(record.try_get("config")?)?;
+        let config = config.into_redacted_config();
+        let sink = Sink {
+            id: record.try_get("id")?,
+            tenant_id: record.try_get("tenant_id")?,
+            name: record.try_get("name")?,
+            config,
+        };
+        sinks.push(sink);
+    }
+
+    Ok(params.page(sinks, |sink| (sink.id, sink.name.clone())))
+}
+
+/// Reads the ids of all sinks of a tenant
+pub async fn read_sink_ids(pool: &PgPool, tenant_id: &str) -> Result<Vec<i64>, sqlx::Error> {
     let records = sqlx::query!(
         r#"
-        select id, tenant_id, name, config
+        select id
         from app.sinks
-        where tenant_id = $1
+        where tenant_id = $1 and deleted_at is null
+        order by id
         "#,
-        tenant_id,
+        tenant_id
     )
     .fetch_all(pool)
     .await?;
 
-    let mut sinks = Vec::with_capacity(records.len());
-    for record in records {
-        let config: SinkConfigInDb = serde_json::from_value(record.config)?;
-        let config = config.into_config(encryption_key)?;
-        let source = Sink {
-            id: record.id,
-            tenant_id: record.tenant_id,
-            name: record.name,
-            config,
-        };
-        sinks.push(source);
-    }
-
-    Ok(sinks)
+    Ok(records.into_iter().map(|r| r.id).collect())
 }
 
 pub async fn sink_exists(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     tenant_id: &str,
     sink_id: i64,
 ) -> Result<bool, sqlx::Error> {
@@ -289,13 +478,53 @@ pub async fn sink_exists(
         r#"
         select exists (select id
         from app.sinks
-        where tenant_id = $1 and id = $2) as "exists!"
+        where tenant_id = $1 and id = $2 and deleted_at is null) as "exists!"
         "#,
         tenant_id,
         sink_id,
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     Ok(record.exists)
 }
+
+/// Restores a deleted sink which hasn't been purged yet
+pub async fn restore_sink(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.sinks
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
+        returning id
+        "#,
+        tenant_id,
+        sink_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Deletes the sinks deleted more than `retention_secs` ago which no
+/// pipeline uses anymore. Returns the number of sinks purged.
+pub async fn purge_deleted_sinks(pool: &PgPool, retention_secs: i64) -> Result<u64, sqlx::Error> {
+    let retention_secs = retention_secs as f64;
+    let result = sqlx::query!(
+        r#"
+        delete from app.sinks s
+        where s.deleted_at < now() - make_interval(secs => $1)
+            and not exists (select 1 from app.pipelines p where p.sink_id = s.id)
+        "#,
+        retention_secs
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -2,7 +2,7 @@ use aws_lc_rs::{aead::Nonce, error::Unspecified};
 use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
-    PgPool,
+    PgExecutor, PgPool, Postgres, QueryBuilder, Row, Transaction,
 };
 use std::{
     fmt::{Debug, Formatter},
@@ -10,7 +10,9 @@ use std::{
 };
 use thiserror::Error;
 
-use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey};
+use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey, REDACTED_SECRET};
+
+use super::pagination::{ListParams, Page};
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 enum SourceConfigInDb {
@@ -77,9 +79,30 @@ impl SourceConfigInDb {
             slot_name,
         })
     }
+
+    /// Returns the config with the password redacted instead of decrypted
+    fn into_redacted_config(self) -> SourceConfig {
+        let SourceConfigInDb::Postgres {
+            host,
+            port,
+            name,
+            username,
+            password,
+            slot_name,
+        } = self;
+
+        SourceConfig::Postgres {
+            host,
+            port,
+            name,
+            username,
+            password: password.map(|_| REDACTED_SECRET.to_string()),
+            slot_name,
+        }
+    }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
 pub enum SourceConfig {
     Postgres {
         /// Host on which Postgres is running
@@ -130,9 +153,17 @@ impl SourceConfig {
         }
     }
 
+    fn has_redacted_password(&self) -> bool {
+        let SourceConfig::Postgres { password, .. } = self;
+        password.as_deref() == Some(REDACTED_SECRET)
+    }
+
+    /// Encrypts the password, unless it is redacted, in which case the
+    /// password of `stored_config` is kept
     fn into_db_config(
         self,
         encryption_key: &EncryptionKey,
+        stored_config: Option<SourceConfigInDb>,
     ) -> Result<SourceConfigInDb, Unspecified> {
         let SourceConfig::Postgres {
             host,
@@ -143,6 +174,21 @@ impl SourceConfig {
             slot_name,
         } = self;
 
+        let stored_password = stored_config.and_then(|config| {
+            let SourceConfigInDb::Postgres { password, .. } = config;
+            password
+        });
+        if password.as_deref() == Some(REDACTED_SECRET) && stored_password.is_some() {
+            return Ok(SourceConfigInDb::Postgres {
+                host,
+                port,
+                name,
+                username,
+                password: stored_password,
+                slot_name,
+            });
+        }
+
         let encrypted_password = password
             .map(|password| {
                 let (encrypted_password, nonce) =
@@ -219,147 +265,290 @@ pub enum SourcesDbError {
     Utf8(#[from] Utf8Error),
 }
 
-pub async fn create_source(
-    pool: &PgPool,
+/// A past or the current config of a source, with its password redacted
+pub struct SourceVersion {
+    pub version: i32,
+    pub name: String,
+    pub config: SourceConfig,
+    /// Seconds since the unix epoch
+    pub created_at: i64,
+}
+
+pub async fn create_source_txn(
+    txn: &mut Transaction<'_, Postgres>,
     tenant_id: &str,
     name: &str,
     config: SourceConfig,
     encryption_key: &EncryptionKey,
 ) -> Result<i64, SourcesDbError> {
-    let db_config = config.into_db_config(encryption_key)?;
+    let db_config = config.into_db_config(encryption_key, None)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
     let record = sqlx::query!(
         r#"
         insert into app.sources (tenant_id, name, config)
         values ($1, $2, $3)
-        returning id
+        returning id, version
         "#,
         tenant_id,
         name,
         db_config
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **txn)
     .await?;
+    create_source_version_txn(txn, record.id, record.version, name, &db_config).await?;
 
     Ok(record.id)
 }
 
-pub async fn read_source(
-    pool: &PgPool,
+async fn create_source_version_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    source_id: i64,
+    version: i32,
+    name: &str,
+    db_config: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.source_versions (source_id, version, name, config)
+        values ($1, $2, $3, $4)
+        "#,
+        source_id,
+        version,
+        name,
+        db_config
+    )
+    .execute(&mut **txn)
+    .await?;
+
+    Ok(())
+}
+
+struct SourceInDb {
+    id: i64,
+    tenant_id: String,
+    name: String,
+    config: SourceConfigInDb,
+}
+
+async fn read_source_in_db(
+    executor: impl PgExecutor<'_>,
     tenant_id: &str,
     source_id: i64,
-    encryption_key: &EncryptionKey,
-) -> Result<Option<Source>, SourcesDbError> {
+) -> Result<Option<SourceInDb>, SourcesDbError> {
     let record = sqlx::query!(
         r#"
         select id, tenant_id, name, config
         from app.sources
-        where tenant_id = $1 and id = $2
+        where tenant_id = $1 and id = $2 and deleted_at is null
         "#,
         tenant_id,
         source_id,
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     let source = record
         .map(|r| {
             let config: SourceConfigInDb = serde_json::from_value(r.config)?;
-            let config = config.into_config(encryption_key)?;
-            let source = Source {
+            let source = SourceInDb {
                 id: r.id,
                 tenant_id: r.tenant_id,
                 name: r.name,
                 config,
             };
+            Ok::<SourceInDb, SourcesDbError>(source)
+        })
+        .transpose()?;
+    Ok(source)
+}
+
+/// Reads a source with its password decrypted, only for the code connecting
+/// to the source
+pub async fn read_source(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
+    encryption_key: &EncryptionKey,
+) -> Result<Option<Source>, SourcesDbError> {
+    let source = read_source_in_db(executor, tenant_id, source_id)
+        .await?
+        .map(|source| {
+            let source = Source {
+                id: source.id,
+                tenant_id: source.tenant_id,
+                name: source.name,
+                config: source.config.into_config(encryption_key)?,
+            };
             Ok::<Source, SourcesDbError>(source)
         })
         .transpose()?;
     Ok(source)
 }
 
-pub async fn update_source(
-    pool: &PgPool,
+/// Reads a source with its password redacted
+pub async fn read_redacted_source(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<Option<Source>, SourcesDbError> {
+    let source = read_source_in_db(executor, tenant_id, source_id)
+        .await?
+        .map(|source| Source {
+            id: source.id,
+            tenant_id: source.tenant_id,
+            name: source.name,
+            config: source.config.into_redacted_config(),
+        });
+    Ok(source)
+}
+
+pub async fn update_source_txn(
+    txn: &mut Transaction<'_, Postgres>,
     tenant_id: &str,
     name: &str,
     source_id: i64,
     config: SourceConfig,
     encryption_key: &EncryptionKey,
 ) -> Result<Option<i64>, SourcesDbError> {
-    let db_config = config.into_db_config(encryption_key)?;
+    let stored_config = if config.has_redacted_password() {
+        read_source_in_db(&mut **txn, tenant_id, source_id)
+            .await?
+            .map(|source| source.config)
+    } else {
+        None
+    };
+    let db_config = config.into_db_config(encryption_key, stored_config)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
     let record = sqlx::query!(
         r#"
         update app.sources
-        set config = $1, name = $2
-        where tenant_id = $3 and id = $4
-        returning id
+        set config = $1, name = $2, version = version + 1
+        where tenant_id = $3 and id = $4 and deleted_at is null
+        returning id, version
         "#,
         db_config,
         name,
         tenant_id,
         source_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **txn)
     .await?;
+    if let Some(record) = &record {
+        create_source_version_txn(txn, record.id, record.version, name, &db_config).await?;
+    }
 
     Ok(record.map(|r| r.id))
 }
 
-pub async fn delete_source(
+/// Reads the versions of a source's config, newest first
+pub async fn read_source_versions(
     pool: &PgPool,
     tenant_id: &str,
     source_id: i64,
+) -> Result<Vec<SourceVersion>, SourcesDbError> {
+    let records = sqlx::query!(
+        r#"
+        select v.version,
+            v.name,
+            v.config,
+            extract(epoch from v.created_at)::bigint as "created_at!"
+        from app.source_versions v
+        join app.sources s on s.id = v.source_id
+        where s.tenant_id = $1 and v.source_id = $2 and s.deleted_at is null
+        order by v.version desc
+        "#,
+        tenant_id,
+        source_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut versions = Vec::with_capacity(records.len());
+    for record in records {
+        let config: SourceConfigInDb = serde_json::from_value(record.config)?;
+        versions.push(SourceVersion {
+            version: record.version,
+            name: record.name,
+            config: config.into_redacted_config(),
+            created_at: record.created_at,
+        });
+    }
+
+    Ok(versions)
+}
+
+/// Marks a source as deleted, after which it's kept for the retention window
+/// before being purged
+pub async fn delete_source(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.sources
-        where tenant_id = $1 and id = $2
+        update app.sources
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
         returning id
         "#,
         tenant_id,
         source_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     Ok(record.map(|r| r.id))
 }
 
+/// Reads all sources of a tenant with their passwords redacted
 pub async fn read_all_sources(
     pool: &PgPool,
     tenant_id: &str,
-    encryption_key: &EncryptionKey,
-) -> Result<Vec<Source>, SourcesDbError> {
-    let records = sqlx::query!(
-        r#"
-        select id, tenant_id, name, config
-        from app.sources
-        where tenant_id = $1
-        "#,
-        tenant_id,
-    )
-    .fetch_all(pool)
-    .await?;
+    params: &ListParams<i64>,
+) -> Result<Page<Source>, SourcesDbError> {
+    let mut query =
+        QueryBuilder::new(
+        "select id, tenant_id, name, config from app.sources where deleted_at is null and tenant_id = ",
+    );
+    query.push_bind(tenant_id);
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
 
     let mut sources = Vec::with_capacity(records.len());
     for record in records {
-        let config: SourceConfigInDb = serde_json::from_value(record.config)?;
-        let config = config.into_config(encryption_key)?;
+        let config: SourceConfigInDb = serde_json::from_value(record.try_get("config")?)?;
+        let config = config.into_redacted_config();
         let source = Source {
-            id: record.id,
-            tenant_id: record.tenant_id,
-            name: record.name,
+            id: record.try_get("id")?,
+            tenant_id: record.try_get("tenant_id")?,
+            name: record.try_get("name")?,
             config,
         };
         sources.push(source);
     }
 
-    Ok(sources)
+    Ok(params.page(sources, |source| (source.id, source.name.clone())))
+}
+
+/// Reads the ids of all sources of a tenant
+pub async fn read_source_ids(pool: &PgPool, tenant_id: &str) -> Result<Vec<i64>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id
+        from app.sources
+        where tenant_id = $1 and deleted_at is null
+        order by id
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records.into_iter().map(|r| r.id).collect())
 }
 
 pub async fn source_exists(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     tenant_id: &str,
     source_id: i64,
 ) -> Result<bool, sqlx::Error> {
@@ -367,13 +556,53 @@ pub async fn source_exists(
         r#"
         select exists (select id
         from app.sources
-        where tenant_id = $1 and id = $2) as "exists!"
+        where tenant_id = $1 and id = $2 and deleted_at is null) as "exists!"
         "#,
         tenant_id,
         source_id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     Ok(record.exists)
 }
+
+/// Restores a deleted source which hasn't been purged yet
+pub async fn restore_source(
+    pool: &PgPool,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.sources
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
+        returning id
+        "#,
+        tenant_id,
+        source_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Deletes the sources deleted more than `retention_secs` ago which no
+/// pipeline uses anymore. Returns the number of sources purged.
+pub async fn purge_deleted_sources(pool: &PgPool, retention_secs: i64) -> Result<u64, sqlx::Error> {
+    let retention_secs = retention_secs as f64;
+    let result = sqlx::query!(
+        r#"
+        delete from app.sources s
+        where s.deleted_at < now() - make_interval(secs => $1)
+            and not exists (select 1 from app.pipelines p where p.source_id = s.id)
+        "#,
+        retention_secs
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, Row};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Table {
     pub schema: String,
     pub name: String,
@@ -35,3 +36,29 @@ pub async fn get_tables(options: &PgConnectOptions) -> Result<Vec<Table>, sqlx::
         .collect();
     Ok(tables)
 }
+
+/// Looks up the oids of `tables`, each a `(schema, name)` pair. Tables which
+/// don't exist have no oid.
+pub async fn get_table_ids(
+    options: &PgConnectOptions,
+    tables: &[(String, String)],
+) -> Result<Vec<Option<i64>>, sqlx::Error> {
+    let mut connection = PgConnection::connect_with(options).await?;
+    let query = r#"
+        select c.oid::int8 as id
+        from pg_catalog.pg_class c
+            join pg_catalog.pg_namespace n on n.oid = c.relnamespace
+        where n.nspname = $1 and c.relname = $2 and c.relkind in ('r', 'p');
+        "#;
+    let mut ids = Vec::with_capacity(tables.len());
+    for (schema, name) in tables {
+        let id = sqlx::query(query)
+            .bind(schema)
+            .bind(name)
+            .fetch_optional(&mut connection)
+            .await?
+            .map(|r| r.get("id"));
+        ids.push(id);
+    }
+    Ok(ids)
+}
@@ -1,4 +1,6 @@
-use sqlx::PgPool;
+use sqlx::{PgPool, QueryBuilder, Row};
+
+use super::pagination::{ListParams, Page};
 
 pub struct Tenant {
     pub id: String,
@@ -100,21 +102,23 @@ pub async fn delete_tenant(pool: &PgPool, tenant_id: &str) -> Result<Option<Stri
     Ok(record.map(|r| r.id))
 }
 
-pub async fn read_all_tenants(pool: &PgPool) -> Result<Vec<Tenant>, sqlx::Error> {
-    let mut record = sqlx::query!(
-        r#"
-        select id, name
-        from app.tenants
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
+pub async fn read_all_tenants(
+    pool: &PgPool,
+    params: &ListParams<String>,
+) -> Result<Page<Tenant>, sqlx::Error> {
+    let mut query = QueryBuilder::new("select id, name from app.tenants where true");
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
 
-    Ok(record
-        .drain(..)
-        .map(|r| Tenant {
-            id: r.id,
-            name: r.name,
+    let tenants: Vec<Tenant> = records
+        .into_iter()
+        .map(|r| {
+            Ok(Tenant {
+                id: r.try_get("id")?,
+                name: r.try_get("name")?,
+            })
         })
-        .collect())
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(tenants, |tenant| (tenant.id.clone(), tenant.name.clone())))
 }
@@ -0,0 +1,409 @@
+use aws_lc_rs::{aead::Nonce, error::Unspecified};
+use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
+use sqlx::{PgPool, QueryBuilder, Row};
+use thiserror::Error;
+
+use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey};
+
+use super::pagination::{ListParams, Page};
+
+pub struct Webhook {
+    pub id: i64,
+    pub tenant_id: String,
+    pub name: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub lag_threshold_secs: Option<i64>,
+}
+
+/// A delivery due to be sent, with the secret of its webhook decrypted to
+/// sign it
+pub struct PendingDelivery {
+    pub id: i64,
+    pub url: String,
+    pub secret: Vec<u8>,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub delivered: bool,
+    pub pending: bool,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum WebhooksDbError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("encryption error: {0}")]
+    Encryption(#[from] Unspecified),
+
+    #[error("invalid webhook secret in db")]
+    InvalidSecret(#[from] serde_json::Error),
+
+    #[error("mismatched key id. Expected: {0}, actual: {1}")]
+    MismatchedKeyId(u32, u32),
+
+    #[error("base64 decode error: {0}")]
+    Base64Decode(#[from] DecodeError),
+}
+
+fn encrypt_secret(
+    secret: &[u8],
+    encryption_key: &EncryptionKey,
+) -> Result<serde_json::Value, Unspecified> {
+    let (encrypted_secret, nonce) = encrypt(secret, &encryption_key.key)?;
+    let encrypted_secret = EncryptedValue {
+        id: encryption_key.id,
+        nonce: BASE64_STANDARD.encode(nonce.as_ref()),
+        value: BASE64_STANDARD.encode(encrypted_secret),
+    };
+    Ok(serde_json::to_value(encrypted_secret).expect("failed to serialize secret"))
+}
+
+fn decrypt_secret(
+    secret: serde_json::Value,
+    encryption_key: &EncryptionKey,
+) -> Result<Vec<u8>, WebhooksDbError> {
+    let encrypted_secret: EncryptedValue = serde_json::from_value(secret)?;
+    if encrypted_secret.id != encryption_key.id {
+        return Err(WebhooksDbError::MismatchedKeyId(
+            encrypted_secret.id,
+            encryption_key.id,
+        ));
+    }
+    let encrypted_secret_bytes = BASE64_STANDARD.decode(encrypted_secret.value)?;
+    let nonce = Nonce::try_assume_unique_for_key(&BASE64_STANDARD.decode(encrypted_secret.nonce)?)?;
+    Ok(decrypt(encrypted_secret_bytes, nonce, &encryption_key.key)?)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_webhook(
+    pool: &PgPool,
+    tenant_id: &str,
+    name: &str,
+    url: &str,
+    events: &[String],
+    lag_threshold_secs: Option<i64>,
+    secret: &[u8],
+    encryption_key: &EncryptionKey,
+) -> Result<i64, WebhooksDbError> {
+    let secret = encrypt_secret(secret, encryption_key)?;
+    let record = sqlx::query!(
+        r#"
+        insert into app.webhooks (tenant_id, name, url, events, lag_threshold_secs, secret)
+        values ($1, $2, $3, $4, $5, $6)
+        returning id
+        "#,
+        tenant_id,
+        name,
+        url,
+        events,
+        lag_threshold_secs,
+        secret
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.id)
+}
+
+pub async fn read_webhook(
+    pool: &PgPool,
+    tenant_id: &str,
+    webhook_id: i64,
+) -> Result<Option<Webhook>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id, tenant_id, name, url, events, lag_threshold_secs
+        from app.webhooks
+        where tenant_id = $1 and id = $2
+        "#,
+        tenant_id,
+        webhook_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| Webhook {
+        id: r.id,
+        tenant_id: r.tenant_id,
+        name: r.name,
+        url: r.url,
+        events: r.events,
+        lag_threshold_secs: r.lag_threshold_secs,
+    }))
+}
+
+/// Updates a webhook, its secret is kept
+pub async fn update_webhook(
+    pool: &PgPool,
+    tenant_id: &str,
+    webhook_id: i64,
+    name: &str,
+    url: &str,
+    events: &[String],
+    lag_threshold_secs: Option<i64>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.webhooks
+        set name = $1, url = $2, events = $3, lag_threshold_secs = $4
+        where tenant_id = $5 and id = $6
+        returning id
+        "#,
+        name,
+        url,
+        events,
+        lag_threshold_secs,
+        tenant_id,
+        webhook_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn delete_webhook(
+    pool: &PgPool,
+    tenant_id: &str,
+    webhook_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        delete from app.webhooks
+        where tenant_id = $1 and id = $2
+        returning id
+        "#,
+        tenant_id,
+        webhook_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn read_all_webhooks(
+    pool: &PgPool,
+    tenant_id: &str,
+    params: &ListParams<i64>,
+) -> Result<Page<Webhook>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "select id, tenant_id, name, url, events, lag_threshold_secs from app.webhooks where tenant_id = ",
+    );
+    query.push_bind(tenant_id);
+    params.push_to(&mut query, "id", "name");
+    let records = query.build().fetch_all(pool).await?;
+
+    let webhooks: Vec<Webhook> = records
+        .into_iter()
+        .map(|r| {
+            Ok(Webhook {
+                id: r.try_get("id")?,
+                tenant_id: r.try_get("tenant_id")?,
+                name: r.try_get("name")?,
+                url: r.try_get("url")?,
+                events: r.try_get("events")?,
+                lag_threshold_secs: r.try_get("lag_threshold_secs")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    Ok(params.page(webhooks, |webhook| (webhook.id, webhook.name.clone())))
+}
+
+/// Reads the tenant's webhooks subscribed to `event`
+pub async fn read_subscribed_webhooks(
+    pool: &PgPool,
+    tenant_id: &str,
+    event: &str,
+) -> Result<Vec<Webhook>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id, tenant_id, name, url, events, lag_threshold_secs
+        from app.webhooks
+        where tenant_id = $1 and $2 = any(events)
+        "#,
+        tenant_id,
+        event,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| Webhook {
+            id: r.id,
+            tenant_id: r.tenant_id,
+            name: r.name,
+            url: r.url,
+            events: r.events,
+            lag_threshold_secs: r.lag_threshold_secs,
+        })
+        .collect())
+}
+
+pub async fn create_delivery(
+    pool: &PgPool,
+    tenant_id: &str,
+    webhook_id: i64,
+    event: &str,
+    payload: &serde_json::Value,
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        insert into app.webhook_deliveries (tenant_id, webhook_id, event, payload)
+        values ($1, $2, $3, $4)
+        returning id
+        "#,
+        tenant_id,
+        webhook_id,
+        event,
+        payload
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.id)
+}
+
+/// Reads the latest deliveries of a webhook, newest first
+pub async fn read_deliveries(
+    pool: &PgPool,
+    tenant_id: &str,
+    webhook_id: i64,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id,
+            event,
+            payload,
+            attempts,
+            delivered_at is not null as "delivered!",
+            next_attempt_at is not null as "pending!",
+            last_error
+        from app.webhook_deliveries
+        where tenant_id = $1 and webhook_id = $2
+        order by id desc
+        limit $3
+        "#,
+        tenant_id,
+        webhook_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| WebhookDelivery {
+            id: r.id,
+            event: r.event,
+            payload: r.payload,
+            attempts: r.attempts,
+            delivered: r.delivered,
+            pending: r.pending,
+            last_error: r.last_error,
+        })
+        .collect())
+}
+
+/// Claims up to `limit` deliveries which are due. A claimed delivery is not
+/// due again for `lease_secs`, so other api instances don't send it at the
+/// same time, and it is retried if this instance stops before recording the
+/// outcome.
+pub async fn claim_due_deliveries(
+    pool: &PgPool,
+    limit: i64,
+    lease_secs: i64,
+    encryption_key: &EncryptionKey,
+) -> Result<Vec<PendingDelivery>, WebhooksDbError> {
+    let lease_secs = lease_secs as f64;
+    let records = sqlx::query!(
+        r#"
+        update app.webhook_deliveries d
+        set next_attempt_at = now() + make_interval(secs => $2)
+        from app.webhooks w
+        where w.id = d.webhook_id
+            and d.id in (
+                select id
+                from app.webhook_deliveries
+                where next_attempt_at <= now()
+                order by next_attempt_at
+                limit $1
+                for update skip locked
+            )
+        returning d.id, d.payload, d.attempts, w.url, w.secret
+        "#,
+        limit,
+        lease_secs
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut deliveries = Vec::with_capacity(records.len());
+    for r in records {
+        deliveries.push(PendingDelivery {
+            id: r.id,
+            url: r.url,
+            secret: decrypt_secret(r.secret, encryption_key)?,
+            payload: r.payload,
+            attempts: r.attempts,
+        });
+    }
+
+    Ok(deliveries)
+}
+
+pub async fn mark_delivery_delivered(pool: &PgPool, delivery_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update app.webhook_deliveries
+        set attempts = attempts + 1,
+            delivered_at = now(),
+            next_attempt_at = null,
+            last_error = null
+        where id = $1
+        "#,
+        delivery_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed attempt of a delivery, which is retried after
+/// `retry_after_secs` or, if it isn't set, given up on
+pub async fn mark_delivery_failed(
+    pool: &PgPool,
+    delivery_id: i64,
+    error: &str,
+    retry_after_secs: Option<i64>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update app.webhook_deliveries
+        set attempts = attempts + 1,
+            next_attempt_at = now() + make_interval(secs => $2),
+            last_error = $3
+        where id = $1
+        "#,
+        delivery_id,
+        retry_after_secs.map(|secs| secs as f64),
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
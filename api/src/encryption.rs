@@ -4,6 +4,10 @@ use aws_lc_rs::{
     rand::fill,
 };
 
+/// Replaces secrets in configs returned by the api. Sending it back in place
+/// of a secret keeps the stored secret.
+pub const REDACTED_SECRET: &str = "REDACTED";
+
 pub struct EncryptionKey {
     pub id: u32,
     pub key: RandomizedNonceKey,
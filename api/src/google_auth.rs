@@ -0,0 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_lc_rs::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use base64::{
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde::Deserialize;
+
+const METADATA_SERVER_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// The fields of a Google service account key needed to authenticate
+#[derive(Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    pub token_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchanges a JWT signed with the service account's key for an access token
+pub async fn access_token(
+    client: &reqwest::Client,
+    key: &ServiceAccountKey,
+    scopes: &str,
+) -> Result<String, String> {
+    let assertion = signed_jwt(key, scopes)?;
+    let response = client
+        .post(&key.token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &assertion),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("failed to request an access token: {e}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "access token request was rejected, status {}",
+            response.status()
+        ));
+    }
+    let token: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("invalid access token response: {e}"))?;
+    Ok(token.access_token)
+}
+
+fn signed_jwt(key: &ServiceAccountKey, scopes: &str) -> Result<String, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let header = serde_json::json!({ "alg": "RS256", "typ": "JWT" });
+    let claims = serde_json::json!({
+        "iss": key.client_email,
+        "scope": scopes,
+        "aud": key.token_uri,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    let message = format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+        BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+
+    let pem_body: String = key
+        .private_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = BASE64_STANDARD
+        .decode(pem_body)
+        .map_err(|e| format!("invalid private key encoding: {e}"))?;
+    let key_pair = RsaKeyPair::from_pkcs8(&der).map_err(|e| format!("invalid private key: {e}"))?;
+    let mut signature = vec![0; key_pair.public_modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|_| "failed to sign the access token request".to_string())?;
+
+    Ok(format!(
+        "{message}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
+
+/// Gets an access token for the service account attached to the instance
+/// the api runs on
+pub async fn metadata_server_access_token(client: &reqwest::Client) -> Result<String, String> {
+    let response = client
+        .get(METADATA_SERVER_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await
+        .map_err(|e| format!("failed to request an access token: {e}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "access token request was rejected, status {}",
+            response.status()
+        ));
+    }
+    let token: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("invalid access token response: {e}"))?;
+    Ok(token.access_token)
+}
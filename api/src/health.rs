@@ -0,0 +1,202 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::web::Data;
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::{HealthSettings, WorkerSettings},
+    db::{self, pipeline_statuses::PipelineHeartbeat},
+    encryption::EncryptionKey,
+    k8s_client::HttpK8sClient,
+    metrics::{ApiMetrics, Worker},
+    routes::pipelines::start_replicator,
+    webhooks::{self, WebhookEvent},
+};
+
+/// Health of a started pipeline, judged by how long ago its replicator last
+/// reported its status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineHealth {
+    Healthy,
+    Stalled,
+    Crashed,
+}
+
+impl PipelineHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PipelineHealth::Healthy => "healthy",
+            PipelineHealth::Stalled => "stalled",
+            PipelineHealth::Crashed => "crashed",
+        }
+    }
+
+    pub fn parse(health: &str) -> Option<PipelineHealth> {
+        match health {
+            "healthy" => Some(PipelineHealth::Healthy),
+            "stalled" => Some(PipelineHealth::Stalled),
+            "crashed" => Some(PipelineHealth::Crashed),
+            _ => None,
+        }
+    }
+}
+
+fn health_of(secs_since_report: i64, settings: &HealthSettings) -> PipelineHealth {
+    if secs_since_report >= settings.crashed_after_secs {
+        PipelineHealth::Crashed
+    } else if secs_since_report >= settings.stalled_after_secs {
+        PipelineHealth::Stalled
+    } else {
+        PipelineHealth::Healthy
+    }
+}
+
+fn should_restart(restart_count: i32, settings: &HealthSettings) -> bool {
+    settings.auto_restart && restart_count < settings.max_restarts
+}
+
+async fn check_heartbeat(
+    pool: &PgPool,
+    encryption_key: &EncryptionKey,
+    k8s_client: Option<&Arc<HttpK8sClient>>,
+    heartbeat: &PipelineHeartbeat,
+    settings: &HealthSettings,
+) -> Result<(), sqlx::Error> {
+    let PipelineHeartbeat {
+        tenant_id,
+        pipeline_id,
+        secs_since_report,
+        restart_count,
+        ..
+    } = heartbeat;
+    let pipeline_id = *pipeline_id;
+    let previous = PipelineHealth::parse(&heartbeat.health).unwrap_or(PipelineHealth::Healthy);
+    let health = health_of(*secs_since_report, settings);
+
+    // Only reports make a pipeline healthy again
+    if health != previous && health != PipelineHealth::Healthy {
+        let updated = db::pipeline_statuses::update_pipeline_health(
+            pool,
+            pipeline_id,
+            previous.as_str(),
+            health.as_str(),
+        )
+        .await?;
+        if !updated {
+            return Ok(());
+        }
+        warn!("pipeline {pipeline_id} is {}", health.as_str());
+        if health == PipelineHealth::Crashed {
+            let data = serde_json::json!({
+                "error": format!("no status report for {secs_since_report} secs"),
+            });
+            webhooks::notify(
+                pool,
+                tenant_id,
+                pipeline_id,
+                WebhookEvent::PipelineErrored,
+                data,
+            )
+            .await;
+        }
+    }
+
+    if health != PipelineHealth::Crashed || !should_restart(*restart_count, settings) {
+        return Ok(());
+    }
+    let Some(k8s_client) = k8s_client else {
+        return Ok(());
+    };
+    if !db::pipeline_statuses::claim_restart(pool, pipeline_id, *restart_count).await? {
+        return Ok(());
+    }
+    info!(
+        "restarting crashed pipeline {pipeline_id}, restart {}",
+        restart_count + 1
+    );
+    match start_replicator(pool, encryption_key, k8s_client, tenant_id, pipeline_id).await {
+        Ok(()) => {
+            webhooks::notify(
+                pool,
+                tenant_id,
+                pipeline_id,
+                WebhookEvent::PipelineStarted,
+                serde_json::json!({}),
+            )
+            .await
+        }
+        Err(e) => error!("failed to restart pipeline {pipeline_id}: {e}"),
+    }
+
+    Ok(())
+}
+
+/// Marks started pipelines which stopped reporting their status as stalled
+/// or crashed, restarting crashed ones, every `poll_interval_secs` until the
+/// process exits
+pub async fn run_health_monitor(
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    k8s_client: Option<Data<Arc<HttpK8sClient>>>,
+    metrics: Data<ApiMetrics>,
+    settings: WorkerSettings,
+) {
+    let poll_interval = Duration::from_secs(settings.poll_interval_secs);
+    loop {
+        metrics.record_heartbeat(Worker::HealthMonitor);
+        match db::pipeline_statuses::read_heartbeats(&pool).await {
+            Ok(heartbeats) => {
+                for heartbeat in &heartbeats {
+                    let result = check_heartbeat(
+                        &pool,
+                        &encryption_key,
+                        k8s_client.as_deref(),
+                        heartbeat,
+                        &settings.health,
+                    )
+                    .await;
+                    if let Err(e) = result {
+                        error!(
+                            "failed to check health of pipeline {}: {e}",
+                            heartbeat.pipeline_id
+                        );
+                    }
+                }
+            }
+            Err(e) => error!("failed to read pipeline heartbeats: {e}"),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::HealthSettings;
+
+    use super::{health_of, should_restart, PipelineHealth};
+
+    #[test]
+    fn health_follows_time_since_report() {
+        let settings = HealthSettings::default();
+        assert_eq!(health_of(0, &settings), PipelineHealth::Healthy);
+        assert_eq!(health_of(59, &settings), PipelineHealth::Healthy);
+        assert_eq!(health_of(60, &settings), PipelineHealth::Stalled);
+        assert_eq!(health_of(299, &settings), PipelineHealth::Stalled);
+        assert_eq!(health_of(300, &settings), PipelineHealth::Crashed);
+    }
+
+    #[test]
+    fn restarts_are_limited() {
+        let mut settings = HealthSettings::default();
+        assert!(should_restart(0, &settings));
+        assert!(should_restart(2, &settings));
+        assert!(!should_restart(3, &settings));
+
+        settings.auto_restart = false;
+        assert!(!should_restart(0, &settings));
+    }
+}
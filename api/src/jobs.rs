@@ -0,0 +1,443 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::web::Data;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{
+    configuration::WorkerSettings,
+    connection_test::{self, BIGQUERY_API_URL, GOOGLE_SCOPES},
+    db::{
+        self,
+        jobs::{ClaimedJob, JobParams, JobStatus},
+        sinks::{SinkConfig, SinksDbError},
+        sources::SourcesDbError,
+    },
+    encryption::EncryptionKey,
+    google_auth::{access_token, ServiceAccountKey},
+    k8s_client::HttpK8sClient,
+    metrics::{ApiMetrics, Worker},
+    routes::pipelines::{start_replicator, PipelineError},
+};
+
+/// A claimed job is run again by another api instance if its lease isn't
+/// renewed for this long
+const JOB_LEASE_SECS: i64 = 120;
+
+#[derive(Debug, Error)]
+enum JobError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("invalid job params: {0}")]
+    InvalidParams(#[from] serde_json::Error),
+
+    #[error("sink with id {0} not found")]
+    SinkNotFound(i64),
+
+    #[error("sinks db error: {0}")]
+    SinksDb(#[from] SinksDbError),
+
+    #[error("source with id {0} not found")]
+    SourceNotFound(i64),
+
+    #[error("sources db error: {0}")]
+    SourcesDb(#[from] SourcesDbError),
+
+    #[error("pipeline with id {0} not found")]
+    PipelineNotFound(i64),
+
+    #[error("table {0} not found in the source")]
+    TableNotFound(String),
+
+    #[error("tables not replicated by the pipeline: {0}")]
+    TablesNotReplicated(String),
+
+    #[error("kubernetes client not configured")]
+    K8sClientMissing,
+
+    #[error("failed to start the pipeline: {0}")]
+    Start(#[from] PipelineError),
+
+    #[error("bigquery error: {0}")]
+    BigQuery(String),
+
+    #[error("pipeline errored: {0}")]
+    PipelineErrored(String),
+
+    #[error("timed out after {0} secs")]
+    Timeout(u64),
+}
+
+impl JobError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in job errors
+            JobError::Database(_) | JobError::SinksDb(_) | JobError::SourcesDb(_) => {
+                "internal server error".to_string()
+            }
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+/// A table in a pipeline status report
+#[derive(Deserialize)]
+struct ReportedTable {
+    table_name: String,
+    rows_copied: u64,
+    copied: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct CopyProgress {
+    phase: String,
+    tables_copied: usize,
+    tables_total: usize,
+    rows_copied: u64,
+    /// Tables to copy which the pipeline doesn't replicate
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing_tables: Vec<String>,
+}
+
+/// Copy progress of `tables`, or of all the reported tables if not given
+fn copy_progress(
+    phase: &str,
+    reported_tables: &[ReportedTable],
+    tables: Option<&[String]>,
+) -> CopyProgress {
+    let selected: Vec<&ReportedTable> = reported_tables
+        .iter()
+        .filter(|table| tables.map_or(true, |tables| tables.contains(&table.table_name)))
+        .collect();
+    let missing_tables = tables
+        .unwrap_or_default()
+        .iter()
+        .filter(|name| {
+            !reported_tables
+                .iter()
+                .any(|table| &table.table_name == *name)
+        })
+        .cloned()
+        .collect();
+    CopyProgress {
+        phase: phase.to_string(),
+        tables_copied: selected.iter().filter(|table| table.copied).count(),
+        tables_total: selected.len(),
+        rows_copied: selected.iter().map(|table| table.rows_copied).sum(),
+        missing_tables,
+    }
+}
+
+/// Everything needed to run jobs, shared by the jobs an instance runs
+#[derive(Clone)]
+struct JobRunner {
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    client: reqwest::Client,
+    k8s_client: Option<Data<Arc<HttpK8sClient>>>,
+    settings: WorkerSettings,
+}
+
+impl JobRunner {
+    async fn run(&self, job: ClaimedJob) {
+        info!("running job {}", job.id);
+        let result = self.run_params(&job).await;
+        let finished = match &result {
+            Ok(result) => {
+                db::jobs::finish_job(&self.pool, job.id, JobStatus::Succeeded, Some(result), None)
+                    .await
+            }
+            Err(e) => {
+                error!("job {} failed: {e}", job.id);
+                let message = e.to_message();
+                db::jobs::finish_job(&self.pool, job.id, JobStatus::Failed, None, Some(&message))
+                    .await
+            }
+        };
+        if let Err(e) = finished {
+            error!("failed to record the outcome of job {}: {e}", job.id);
+        }
+    }
+
+    async fn run_params(&self, job: &ClaimedJob) -> Result<serde_json::Value, JobError> {
+        let tenant_id = &job.tenant_id;
+        match serde_json::from_value(job.params.clone())? {
+            JobParams::SinkValidation { sink_id } => {
+                let sink =
+                    db::sinks::read_sink(&self.pool, tenant_id, sink_id, &self.encryption_key)
+                        .await?
+                        .ok_or(JobError::SinkNotFound(sink_id))?;
+                let result = connection_test::test_sink(&self.client, &sink.config).await;
+                Ok(serde_json::to_value(result)?)
+            }
+            JobParams::InitialCopy { pipeline_id } => {
+                let started = Instant::now();
+                self.start(tenant_id, pipeline_id).await?;
+                self.follow_copy(job.id, tenant_id, pipeline_id, None, started)
+                    .await
+            }
+            JobParams::TableResync {
+                pipeline_id,
+                tables,
+            } => {
+                self.forget_copied_tables(tenant_id, pipeline_id, &tables)
+                    .await?;
+                let started = Instant::now();
+                self.start(tenant_id, pipeline_id).await?;
+                self.follow_copy(job.id, tenant_id, pipeline_id, Some(&tables), started)
+                    .await
+            }
+        }
+    }
+
+    async fn start(&self, tenant_id: &str, pipeline_id: i64) -> Result<(), JobError> {
+        let k8s_client = self.k8s_client.as_ref().ok_or(JobError::K8sClientMissing)?;
+        // restarting a running replicator makes it read which tables are
+        // copied again
+        start_replicator(
+            &self.pool,
+            &self.encryption_key,
+            k8s_client,
+            tenant_id,
+            pipeline_id,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Removes `tables` from the tables the pipeline's sink recorded as
+    /// copied, so that the replicator copies them again when it starts
+    async fn forget_copied_tables(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        tables: &[String],
+    ) -> Result<(), JobError> {
+        let pipeline = db::pipelines::read_pipeline(self.pool.get_ref(), tenant_id, pipeline_id)
+            .await?
+            .ok_or(JobError::PipelineNotFound(pipeline_id))?;
+        let source = db::sources::read_source(
+            self.pool.get_ref(),
+            tenant_id,
+            pipeline.source_id,
+            &self.encryption_key,
+        )
+        .await?
+        .ok_or(JobError::SourceNotFound(pipeline.source_id))?;
+        let sink = db::sinks::read_sink(
+            &self.pool,
+            tenant_id,
+            pipeline.sink_id,
+            &self.encryption_key,
+        )
+        .await?
+        .ok_or(JobError::SinkNotFound(pipeline.sink_id))?;
+
+        let names = tables
+            .iter()
+            .map(|table| {
+                table
+                    .split_once('.')
+                    .map(|(schema, name)| (schema.to_string(), name.to_string()))
+                    .ok_or_else(|| JobError::TableNotFound(table.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let ids = db::tables::get_table_ids(&source.config.connect_options(), &names).await?;
+        let ids = ids
+            .into_iter()
+            .zip(tables)
+            .map(|(id, table)| id.ok_or_else(|| JobError::TableNotFound(table.clone())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let SinkConfig::BigQuery {
+            project_id,
+            dataset_id,
+            ..
+        } = &sink.config;
+        let ids = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!(
+            "delete from `{project_id}.{dataset_id}.copied_tables` where table_id in ({ids})"
+        );
+        run_bigquery_query(&self.client, &sink.config, &query).await
+    }
+
+    /// Follows the status reports of the pipeline since `started` until it
+    /// copied `tables`, or all of its tables, and replicates changes
+    async fn follow_copy(
+        &self,
+        job_id: i64,
+        tenant_id: &str,
+        pipeline_id: i64,
+        tables: Option<&[String]>,
+        started: Instant,
+    ) -> Result<serde_json::Value, JobError> {
+        let poll_interval = Duration::from_secs(self.settings.poll_interval_secs);
+        loop {
+            let elapsed_secs = started.elapsed().as_secs();
+            if elapsed_secs > self.settings.job_timeout_secs {
+                return Err(JobError::Timeout(self.settings.job_timeout_secs));
+            }
+
+            let reported =
+                db::pipeline_statuses::read_pipeline_status(&self.pool, tenant_id, pipeline_id)
+                    .await?
+                    // reports sent before the replicator was started are stale
+                    .filter(|reported| reported.secs_since_report < elapsed_secs as i64);
+            let Some(reported) = reported else {
+                db::jobs::update_job_progress(
+                    &self.pool,
+                    job_id,
+                    &serde_json::json!({ "phase": "starting" }),
+                    JOB_LEASE_SECS,
+                )
+                .await?;
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            };
+
+            let report = reported.report;
+            let reported_tables: Vec<ReportedTable> = serde_json::from_value(report.tables)?;
+            let progress = copy_progress(&report.phase, &reported_tables, tables);
+            let progress_json = serde_json::to_value(&progress)?;
+            db::jobs::update_job_progress(&self.pool, job_id, &progress_json, JOB_LEASE_SECS)
+                .await?;
+
+            match report.phase.as_str() {
+                "errored" => {
+                    return Err(JobError::PipelineErrored(
+                        report.last_error.unwrap_or_default(),
+                    ))
+                }
+                "cdc" if !progress.missing_tables.is_empty() => {
+                    return Err(JobError::TablesNotReplicated(
+                        progress.missing_tables.join(", "),
+                    ))
+                }
+                "cdc" if progress.tables_copied == progress.tables_total => {
+                    return Ok(progress_json)
+                }
+                _ => {}
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
+
+async fn run_bigquery_query(
+    client: &reqwest::Client,
+    config: &SinkConfig,
+    query: &str,
+) -> Result<(), JobError> {
+    let SinkConfig::BigQuery {
+        project_id,
+        service_account_key,
+        ..
+    } = config;
+    let key: ServiceAccountKey = serde_json::from_str(service_account_key)
+        .map_err(|e| JobError::BigQuery(format!("invalid service account key: {e}")))?;
+    let access_token = access_token(client, &key, GOOGLE_SCOPES)
+        .await
+        .map_err(JobError::BigQuery)?;
+
+    let url = format!("{BIGQUERY_API_URL}/projects/{project_id}/queries");
+    let body = serde_json::json!({
+        "query": query,
+        "useLegacySql": false,
+    });
+    let response = client
+        .post(&url)
+        .bearer_auth(&access_token)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| JobError::BigQuery(e.to_string()))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(JobError::BigQuery(format!(
+            "query failed with status {status}: {body}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Runs queued jobs until the process exits, looking for them every
+/// `poll_interval_secs`. Each job runs in its own task, as jobs following a
+/// copy can run for hours.
+pub async fn run_job_worker(
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    client: reqwest::Client,
+    k8s_client: Option<Data<Arc<HttpK8sClient>>>,
+    metrics: Data<ApiMetrics>,
+    settings: WorkerSettings,
+) {
+    let poll_interval = Duration::from_secs(settings.poll_interval_secs);
+    let runner = JobRunner {
+        pool,
+        encryption_key,
+        client,
+        k8s_client,
+        settings,
+    };
+    loop {
+        metrics.record_heartbeat(Worker::Job);
+        match db::jobs::claim_job(&runner.pool, JOB_LEASE_SECS).await {
+            Ok(Some(job)) => {
+                let runner = runner.clone();
+                tokio::spawn(async move { runner.run(job).await });
+                // more jobs are probably queued
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => error!("failed to claim job: {e}"),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_progress, ReportedTable};
+
+    fn table(table_name: &str, rows_copied: u64, copied: bool) -> ReportedTable {
+        ReportedTable {
+            table_name: table_name.to_string(),
+            rows_copied,
+            copied,
+        }
+    }
+
+    #[test]
+    fn progress_covers_all_tables_by_default() {
+        let tables = [table("public.a", 10, true), table("public.b", 5, false)];
+        let progress = copy_progress("copying", &tables, None);
+        assert_eq!(progress.tables_copied, 1);
+        assert_eq!(progress.tables_total, 2);
+        assert_eq!(progress.rows_copied, 15);
+        assert!(progress.missing_tables.is_empty());
+    }
+
+    #[test]
+    fn progress_covers_selected_tables() {
+        let tables = [table("public.a", 10, true), table("public.b", 5, false)];
+        let selected = ["public.b".to_string(), "public.c".to_string()];
+        let progress = copy_progress("copying", &tables, Some(&selected));
+        assert_eq!(progress.tables_copied, 0);
+        assert_eq!(progress.tables_total, 1);
+        assert_eq!(progress.rows_copied, 5);
+        assert_eq!(progress.missing_tables, vec!["public.c".to_string()]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use k8s_openapi::api::{
@@ -9,10 +11,12 @@ use thiserror::Error;
 use tracing::*;
 
 use kube::{
-    api::{Api, DeleteParams, Patch, PatchParams},
+    api::{Api, DeleteParams, ListParams, Patch, PatchParams},
     Client,
 };
 
+use crate::configuration::{KubernetesSettings, ReplicatorResources};
+
 #[derive(Debug, Error)]
 pub enum K8sError {
     #[error["serde_json error: {0}"]]
@@ -56,10 +60,18 @@ pub trait K8sClient {
         bq_service_account_key: &str,
     ) -> Result<(), K8sError>;
 
+    async fn create_or_update_api_key_secret(
+        &self,
+        prefix: &str,
+        api_key: &str,
+    ) -> Result<(), K8sError>;
+
     async fn delete_postgres_secret(&self, prefix: &str) -> Result<(), K8sError>;
 
     async fn delete_bq_secret(&self, prefix: &str) -> Result<(), K8sError>;
 
+    async fn delete_api_key_secret(&self, prefix: &str) -> Result<(), K8sError>;
+
     async fn create_or_update_config_map(
         &self,
         prefix: &str,
@@ -77,12 +89,20 @@ pub trait K8sClient {
 
     async fn delete_stateful_set(&self, prefix: &str) -> Result<(), K8sError>;
 
+    /// Prefixes of the replicators whose stateful sets were created by the
+    /// api
+    async fn list_stateful_set_prefixes(&self) -> Result<HashSet<String>, K8sError>;
+
     async fn get_pod_phase(&self, prefix: &str) -> Result<PodPhase, K8sError>;
 
     async fn delete_pod(&self, prefix: &str) -> Result<(), K8sError>;
 }
 
 pub struct HttpK8sClient {
+    namespace: String,
+    api_url: String,
+    resources: Option<ReplicatorResources>,
+    image_pull_secrets: Vec<String>,
     secrets_api: Api<Secret>,
     config_maps_api: Api<ConfigMap>,
     stateful_sets_api: Api<StatefulSet>,
@@ -91,27 +111,40 @@ pub struct HttpK8sClient {
 
 const BQ_SECRET_NAME_SUFFIX: &str = "bq-service-account-key";
 const POSTGRES_SECRET_NAME_SUFFIX: &str = "postgres-password";
+const API_KEY_SECRET_NAME_SUFFIX: &str = "api-key";
 const CONFIG_MAP_NAME_SUFFIX: &str = "replicator-config";
 const STATEFUL_SET_NAME_SUFFIX: &str = "replicator";
 const CONTAINER_NAME_SUFFIX: &str = "replicator";
-const NAMESPACE_NAME: &str = "replicator-data-plane";
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+const MANAGED_BY: &str = "pg-replicate-api";
+const PREFIX_LABEL: &str = "pg-replicate/prefix";
 
 impl HttpK8sClient {
-    pub async fn new() -> Result<HttpK8sClient, K8sError> {
+    pub async fn new(settings: &KubernetesSettings) -> Result<HttpK8sClient, K8sError> {
         let client = Client::try_default().await?;
+        let namespace = settings.namespace.as_str();
 
-        let secrets_api: Api<Secret> = Api::namespaced(client.clone(), NAMESPACE_NAME);
-        let config_maps_api: Api<ConfigMap> = Api::namespaced(client.clone(), NAMESPACE_NAME);
-        let stateful_sets_api: Api<StatefulSet> = Api::namespaced(client.clone(), NAMESPACE_NAME);
-        let pods_api: Api<Pod> = Api::namespaced(client, NAMESPACE_NAME);
+        let secrets_api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+        let config_maps_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+        let stateful_sets_api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+        let pods_api: Api<Pod> = Api::namespaced(client, namespace);
 
         Ok(HttpK8sClient {
+            namespace: settings.namespace.clone(),
+            api_url: settings.api_url.clone(),
+            resources: settings.resources.clone(),
+            image_pull_secrets: settings.image_pull_secrets.clone(),
             secrets_api,
             config_maps_api,
             stateful_sets_api,
             pods_api,
         })
     }
+
+    /// Url of the api as reached from the replicators
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
 }
 
 #[async_trait]
@@ -130,7 +163,7 @@ impl K8sClient for HttpK8sClient {
           "kind": "Secret",
           "metadata": {
             "name": secret_name,
-            "namespace": NAMESPACE_NAME,
+            "namespace": self.namespace,
           },
           "type": "Opaque",
           "data": {
@@ -162,7 +195,7 @@ impl K8sClient for HttpK8sClient {
           "kind": "Secret",
           "metadata": {
             "name": secret_name,
-            "namespace": NAMESPACE_NAME,
+            "namespace": self.namespace,
           },
           "type": "Opaque",
           "data": {
@@ -180,6 +213,38 @@ impl K8sClient for HttpK8sClient {
         Ok(())
     }
 
+    async fn create_or_update_api_key_secret(
+        &self,
+        prefix: &str,
+        api_key: &str,
+    ) -> Result<(), K8sError> {
+        info!("patching api key secret");
+
+        let encoded_api_key = BASE64_STANDARD.encode(api_key);
+        let secret_name = format!("{prefix}-{API_KEY_SECRET_NAME_SUFFIX}");
+        let secret_json = json!({
+          "apiVersion": "v1",
+          "kind": "Secret",
+          "metadata": {
+            "name": secret_name,
+            "namespace": self.namespace,
+          },
+          "type": "Opaque",
+          "data": {
+            "api-key": encoded_api_key,
+          }
+        });
+        let secret: Secret = serde_json::from_value(secret_json)?;
+
+        let pp = PatchParams::apply(&secret_name);
+        self.secrets_api
+            .patch(&secret_name, &pp, &Patch::Apply(secret))
+            .await?;
+        info!("patched api key secret");
+
+        Ok(())
+    }
+
     async fn delete_postgres_secret(&self, prefix: &str) -> Result<(), K8sError> {
         info!("deleting postgres secret");
         let secret_name = format!("{prefix}-{POSTGRES_SECRET_NAME_SUFFIX}");
@@ -218,6 +283,25 @@ impl K8sClient for HttpK8sClient {
         Ok(())
     }
 
+    async fn delete_api_key_secret(&self, prefix: &str) -> Result<(), K8sError> {
+        info!("deleting api key secret");
+        let secret_name = format!("{prefix}-{API_KEY_SECRET_NAME_SUFFIX}");
+        let dp = DeleteParams::default();
+        match self.secrets_api.delete(&secret_name, &dp).await {
+            Ok(_) => {}
+            Err(e) => match e {
+                kube::Error::Api(ref er) => {
+                    if er.code != 404 {
+                        return Err(e.into());
+                    }
+                }
+                e => return Err(e.into()),
+            },
+        }
+        info!("deleted api key secret");
+        Ok(())
+    }
+
     async fn create_or_update_config_map(
         &self,
         prefix: &str,
@@ -232,7 +316,7 @@ impl K8sClient for HttpK8sClient {
           "apiVersion": "v1",
           "metadata": {
             "name": config_map_name,
-            "namespace": NAMESPACE_NAME,
+            "namespace": self.namespace,
           },
           "data": {
             "base.yaml": base_config,
@@ -279,14 +363,26 @@ impl K8sClient for HttpK8sClient {
         let container_name = format!("{prefix}-{CONTAINER_NAME_SUFFIX}");
         let postgres_secret_name = format!("{prefix}-{POSTGRES_SECRET_NAME_SUFFIX}");
         let bq_secret_name = format!("{prefix}-{BQ_SECRET_NAME_SUFFIX}");
+        let api_key_secret_name = format!("{prefix}-{API_KEY_SECRET_NAME_SUFFIX}");
         let config_map_name = format!("{prefix}-{CONFIG_MAP_NAME_SUFFIX}");
 
+        let resources = serde_json::to_value(&self.resources)?;
+        let image_pull_secrets: Vec<_> = self
+            .image_pull_secrets
+            .iter()
+            .map(|name| json!({ "name": name }))
+            .collect();
+
         let stateful_set_json = json!({
           "apiVersion": "apps/v1",
           "kind": "StatefulSet",
           "metadata": {
             "name": stateful_set_name,
-            "namespace": NAMESPACE_NAME,
+            "namespace": self.namespace,
+            "labels": {
+              MANAGED_BY_LABEL: MANAGED_BY,
+              PREFIX_LABEL: prefix,
+            }
           },
           "spec": {
             "replicas": 1,
@@ -302,6 +398,7 @@ impl K8sClient for HttpK8sClient {
                 }
               },
               "spec": {
+                "imagePullSecrets": image_pull_secrets,
                 "volumes": [
                   {
                     "name": "config-file",
@@ -314,6 +411,7 @@ impl K8sClient for HttpK8sClient {
                   {
                     "name": container_name,
                     "image": replicator_image,
+                    "resources": resources,
                     "env": [
                       {
                         "name": "APP_ENVIRONMENT",
@@ -336,6 +434,15 @@ impl K8sClient for HttpK8sClient {
                             "key": "service-account-key"
                           }
                         }
+                      },
+                      {
+                        "name": "APP_STATUS_REPORT__API_KEY",
+                        "valueFrom": {
+                          "secretKeyRef": {
+                            "name": api_key_secret_name,
+                            "key": "api-key"
+                          }
+                        }
                       }
                     ],
                     "volumeMounts": [{
@@ -383,6 +490,17 @@ impl K8sClient for HttpK8sClient {
         Ok(())
     }
 
+    async fn list_stateful_set_prefixes(&self) -> Result<HashSet<String>, K8sError> {
+        let lp = ListParams::default().labels(&format!("{MANAGED_BY_LABEL}={MANAGED_BY}"));
+        let stateful_sets = self.stateful_sets_api.list(&lp).await?;
+        let prefixes = stateful_sets
+            .items
+            .into_iter()
+            .filter_map(|stateful_set| stateful_set.metadata.labels?.remove(PREFIX_LABEL))
+            .collect();
+        Ok(prefixes)
+    }
+
     async fn get_pod_phase(&self, prefix: &str) -> Result<PodPhase, K8sError> {
         info!("getting pod status");
         let pod_name = format!("{prefix}-{STATEFUL_SET_NAME_SUFFIX}-0");
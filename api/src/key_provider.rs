@@ -0,0 +1,281 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_lc_rs::{
+    aead::{RandomizedNonceKey, AES_256_GCM},
+    digest::{digest, SHA256},
+    error::Unspecified,
+    hmac,
+};
+use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    configuration::{self, KmsSettings},
+    encryption::EncryptionKey,
+    google_auth::{access_token, metadata_server_access_token, ServiceAccountKey},
+};
+
+const GCP_KMS_SCOPE: &str = "https://www.googleapis.com/auth/cloudkms";
+
+#[derive(Debug, Error)]
+pub enum KeyProviderError {
+    #[error("encryption key is not base64 encoded: {0}")]
+    Base64Decode(#[from] DecodeError),
+
+    #[error("invalid encryption key")]
+    InvalidKey(#[from] Unspecified),
+
+    #[error("environment variable {0} is not set")]
+    MissingCredentials(&'static str),
+
+    #[error("failed to read the google credentials: {0}")]
+    GoogleCredentials(String),
+
+    #[error("google authentication failed: {0}")]
+    GoogleAuthentication(String),
+
+    #[error("kms request failed: {0}")]
+    Kms(#[from] reqwest::Error),
+}
+
+/// Loads the key encrypting the secrets in source and sink configs. The key
+/// is either in the configuration as is or, with a kms configured, wrapped
+/// by a kms key and unwrapped with a call to the kms at startup, so that the
+/// plaintext key is only ever held in memory.
+pub async fn load_encryption_key(
+    settings: &configuration::EncryptionKey,
+) -> Result<EncryptionKey, KeyProviderError> {
+    let key = BASE64_STANDARD.decode(&settings.key)?;
+    let key = match &settings.kms {
+        None => key,
+        Some(KmsSettings::AwsKms { key_id, region }) => {
+            aws_kms_decrypt(key_id, region, &key).await?
+        }
+        Some(KmsSettings::GcpKms { key_name }) => gcp_kms_decrypt(key_name, &key).await?,
+    };
+    let key = RandomizedNonceKey::new(&AES_256_GCM, &key)?;
+    Ok(EncryptionKey {
+        id: settings.id,
+        key,
+    })
+}
+
+#[derive(Deserialize)]
+struct AwsKmsDecryptResponse {
+    #[serde(rename = "Plaintext")]
+    plaintext: String,
+}
+
+async fn aws_kms_decrypt(
+    key_id: &str,
+    region: &str,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, KeyProviderError> {
+    let access_key_id = std::env::var("AWS_ACCESS_KEY_ID")
+        .map_err(|_| KeyProviderError::MissingCredentials("AWS_ACCESS_KEY_ID"))?;
+    let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+        .map_err(|_| KeyProviderError::MissingCredentials("AWS_SECRET_ACCESS_KEY"))?;
+    let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+
+    let host = format!("kms.{region}.amazonaws.com");
+    let body = serde_json::json!({
+        "KeyId": key_id,
+        "CiphertextBlob": BASE64_STANDARD.encode(ciphertext),
+    })
+    .to_string();
+    let amz_date = amz_date(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    );
+
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("host", host.clone()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(session_token) = session_token {
+        headers.push(("x-amz-security-token", session_token));
+    }
+    headers.push(("x-amz-target", "TrentService.Decrypt".to_string()));
+
+    let authorization = aws_sigv4_authorization(
+        &access_key_id,
+        &secret_access_key,
+        region,
+        "kms",
+        &amz_date,
+        &headers,
+        body.as_bytes(),
+    );
+
+    let client = reqwest::Client::new();
+    let mut request = client.post(format!("https://{host}/")).body(body);
+    for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+        request = request.header(name, value);
+    }
+    let response: AwsKmsDecryptResponse = request
+        .header("authorization", authorization)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(BASE64_STANDARD.decode(response.plaintext)?)
+}
+
+/// Signs a POST request to the root path with AWS Signature Version 4.
+/// `headers` must be sorted by name and include the host.
+fn aws_sigv4_authorization(
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> String {
+    let date = &amz_date[..8];
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex(digest(&SHA256, body).as_ref())
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(digest(&SHA256, canonical_request.as_bytes()).as_ref())
+    );
+
+    let hmac_sha256 = |key: &[u8], data: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+            .as_ref()
+            .to_vec()
+    };
+    let date_key = hmac_sha256(format!("AWS4{secret_access_key}").as_bytes(), date);
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, service);
+    let signing_key = hmac_sha256(&service_key, "aws4_request");
+    let signature = hex(&hmac_sha256(&signing_key, &string_to_sign));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={access_key_id}/{scope}, SignedHeaders={signed_headers}, Signature={signature}"
+    )
+}
+
+/// Formats seconds since the unix epoch as YYYYMMDDTHHMMSSZ
+fn amz_date(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[derive(Deserialize)]
+struct GcpKmsDecryptResponse {
+    plaintext: String,
+}
+
+async fn gcp_kms_decrypt(key_name: &str, ciphertext: &[u8]) -> Result<Vec<u8>, KeyProviderError> {
+    let client = reqwest::Client::new();
+    let access_token = match std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+        Ok(path) => {
+            let key = std::fs::read_to_string(&path)
+                .map_err(|e| KeyProviderError::GoogleCredentials(format!("{path}: {e}")))?;
+            let key: ServiceAccountKey = serde_json::from_str(&key)
+                .map_err(|e| KeyProviderError::GoogleCredentials(format!("{path}: {e}")))?;
+            access_token(&client, &key, GCP_KMS_SCOPE).await
+        }
+        Err(_) => metadata_server_access_token(&client).await,
+    }
+    .map_err(KeyProviderError::GoogleAuthentication)?;
+
+    let response: GcpKmsDecryptResponse = client
+        .post(format!(
+            "https://cloudkms.googleapis.com/v1/{key_name}:decrypt"
+        ))
+        .bearer_auth(access_token)
+        .json(&serde_json::json!({ "ciphertext": BASE64_STANDARD.encode(ciphertext) }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(BASE64_STANDARD.decode(response.plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{amz_date, aws_sigv4_authorization};
+
+    #[test]
+    fn amz_date_is_formatted_in_utc() {
+        assert_eq!(amz_date(0), "19700101T000000Z");
+        assert_eq!(amz_date(951_782_400), "20000229T000000Z");
+        assert_eq!(amz_date(1_729_083_845), "20241016T130405Z");
+    }
+
+    #[test]
+    fn request_is_signed_with_the_derived_key() {
+        // The example credentials and date from the AWS documentation
+        let headers = [
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", "kms.us-east-1.amazonaws.com".to_string()),
+            ("x-amz-date", "20150830T123600Z".to_string()),
+            ("x-amz-target", "TrentService.Decrypt".to_string()),
+        ];
+
+        let authorization = aws_sigv4_authorization(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+            "kms",
+            "20150830T123600Z",
+            &headers,
+            b"{}",
+        );
+
+        // Signed by botocore's SigV4Auth with the same request
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/kms/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-target, \
+             Signature=8a164eed79ee074124d2cc2a08ef2e71a1127fca419e26ba952221db2024db6a"
+        );
+    }
+}
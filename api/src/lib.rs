@@ -1,10 +1,21 @@
 pub mod authentication;
 pub mod configuration;
+pub mod connection_test;
 pub mod db;
 pub mod encryption;
+pub mod google_auth;
+pub mod health;
+pub mod jobs;
 pub mod k8s_client;
+pub mod key_provider;
+pub mod metrics;
+pub mod oidc;
+pub mod openapi;
+pub mod orchestrator;
 pub mod replicator_config;
+pub mod retention;
 pub mod routes;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
+pub mod webhooks;
@@ -0,0 +1,390 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use prometheus::{
+    proto::{Bucket, Counter, Gauge, Histogram, LabelPair, Metric, MetricFamily, MetricType},
+    GaugeVec, HistogramOpts, HistogramVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    configuration::MetricsSettings,
+    db::{self, replicators::ReplicatorStatus},
+    routes::pipelines::TableMetricsReport,
+};
+
+/// Table whose series add up the tables of a pipeline left out by the
+/// [`MetricsSettings`] limits
+const OTHER_TABLES: &str = "_other";
+
+/// A background worker of the api, which records a heartbeat on every
+/// iteration of its loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Worker {
+    Delivery,
+    Job,
+    HealthMonitor,
+    Purger,
+    Orchestrator,
+}
+
+impl Worker {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Worker::Delivery => "delivery",
+            Worker::Job => "job",
+            Worker::HealthMonitor => "health_monitor",
+            Worker::Purger => "purger",
+            Worker::Orchestrator => "orchestrator",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("prometheus error: {0}")]
+    Prometheus(#[from] prometheus::Error),
+}
+
+/// Metrics of the api service, scraped by prometheus from `/metrics`
+pub struct ApiMetrics {
+    registry: Registry,
+    request_duration: HistogramVec,
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+    pool_max_connections: IntGauge,
+    pipelines: IntGaugeVec,
+    worker_heartbeat_age: GaugeVec,
+    heartbeats: Mutex<BTreeMap<Worker, Instant>>,
+    settings: MetricsSettings,
+}
+
+impl ApiMetrics {
+    pub fn new(settings: MetricsSettings) -> Result<ApiMetrics, MetricsError> {
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "api_http_request_duration_seconds",
+                "Latency of the api's http requests",
+            ),
+            &["method", "path", "status"],
+        )?;
+        let pool_connections = IntGauge::new(
+            "api_db_pool_connections",
+            "Connections open in the database pool",
+        )?;
+        let pool_idle_connections = IntGauge::new(
+            "api_db_pool_idle_connections",
+            "Idle connections in the database pool",
+        )?;
+        let pool_max_connections = IntGauge::new(
+            "api_db_pool_max_connections",
+            "Maximum number of connections of the database pool",
+        )?;
+        let pipelines = IntGaugeVec::new(
+            Opts::new(
+                "api_pipelines",
+                "Pipelines of all tenants by the status of their replicator",
+            ),
+            &["status"],
+        )?;
+        let worker_heartbeat_age = GaugeVec::new(
+            Opts::new(
+                "api_worker_heartbeat_age_seconds",
+                "Time since a background worker of the api last ran its loop",
+            ),
+            &["worker"],
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(pool_connections.clone()))?;
+        registry.register(Box::new(pool_idle_connections.clone()))?;
+        registry.register(Box::new(pool_max_connections.clone()))?;
+        registry.register(Box::new(pipelines.clone()))?;
+        registry.register(Box::new(worker_heartbeat_age.clone()))?;
+
+        Ok(ApiMetrics {
+            registry,
+            request_duration,
+            pool_connections,
+            pool_idle_connections,
+            pool_max_connections,
+            pipelines,
+            worker_heartbeat_age,
+            heartbeats: Mutex::new(BTreeMap::new()),
+            settings,
+        })
+    }
+
+    /// Records the latency of a request. `path` is the pattern of the
+    /// matched route rather than the requested path, so that ids don't make
+    /// a series per resource.
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, duration: Duration) {
+        self.request_duration
+            .with_label_values(&[method, path, &status.to_string()])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn record_heartbeat(&self, worker: Worker) {
+        let mut heartbeats = self.heartbeats.lock().expect("heartbeats lock poisoned");
+        heartbeats.insert(worker, Instant::now());
+    }
+
+    /// Updates the gauges which are only read when scraped and encodes all
+    /// metrics in the prometheus text format
+    pub async fn render(&self, pool: &PgPool) -> Result<String, MetricsError> {
+        self.pool_connections.set(pool.size() as i64);
+        self.pool_idle_connections.set(pool.num_idle() as i64);
+        self.pool_max_connections
+            .set(pool.options().get_max_connections() as i64);
+
+        let counts = db::replicators::count_pipelines_by_status(pool).await?;
+        // Statuses without pipelines are reported as 0 rather than left out
+        for status in ReplicatorStatus::ALL {
+            let count = counts
+                .iter()
+                .find(|c| c.status == status)
+                .map(|c| c.count)
+                .unwrap_or(0);
+            self.pipelines
+                .with_label_values(&[status.as_str()])
+                .set(count);
+        }
+
+        {
+            let heartbeats = self.heartbeats.lock().expect("heartbeats lock poisoned");
+            for (worker, heartbeat) in heartbeats.iter() {
+                self.worker_heartbeat_age
+                    .with_label_values(&[worker.as_str()])
+                    .set(heartbeat.elapsed().as_secs_f64());
+            }
+        }
+
+        let mut families = self.registry.gather();
+        families.extend(self.pipeline_metric_families(pool).await?);
+
+        Ok(TextEncoder::new().encode_to_string(&families)?)
+    }
+
+    /// Builds the lag and per table metrics from the pipelines' last status
+    /// reports. The replicators report totals since they started, which are
+    /// exposed as they are rather than through the registry's counters.
+    async fn pipeline_metric_families(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Vec<MetricFamily>, MetricsError> {
+        let mut lag_bytes = vec![];
+        let mut lag_secs = vec![];
+        let mut estimated_lag_secs = vec![];
+        let mut changes = vec![];
+        let mut conversion_errors = vec![];
+        let mut column_conversion_errors = vec![];
+        let mut sink_latencies = vec![];
+        for pipeline in db::pipeline_statuses::read_pipeline_metrics(pool).await? {
+            let pipeline_id = pipeline.pipeline_id.to_string();
+            // one malformed report doesn't fail the scrape of every pipeline
+            let tables = serde_json::from_value::<Vec<TableMetricsReport>>(pipeline.table_metrics);
+            let tables = match tables {
+                Ok(tables) => tables,
+                Err(e) => {
+                    warn!("skipping pipeline {pipeline_id} with invalid table metrics: {e}");
+                    continue;
+                }
+            };
+            let pipeline_labels = || {
+                vec![
+                    label("pipeline_id", &pipeline_id),
+                    label("tenant_id", &pipeline.tenant_id),
+                ]
+            };
+            if let Some(value) = pipeline.lag_bytes {
+                lag_bytes.push(gauge(pipeline_labels(), value as f64));
+            }
+            if let Some(value) = pipeline.lag_secs {
+                lag_secs.push(gauge(pipeline_labels(), value as f64));
+            }
+            if let Some(value) = pipeline.estimated_lag_secs {
+                estimated_lag_secs.push(gauge(pipeline_labels(), value as f64));
+            }
+
+            for table in limit_tables(tables, &self.settings) {
+                let labels = [
+                    label("pipeline_id", &pipeline_id),
+                    label("table", &table.table_name),
+                    label("tenant_id", &pipeline.tenant_id),
+                ];
+                for (op, count) in [
+                    ("delete", table.deletes),
+                    ("insert", table.inserts),
+                    ("update", table.updates),
+                ] {
+                    let mut op_labels = vec![label("op", op)];
+                    op_labels.extend(labels.iter().cloned());
+                    changes.push(counter(op_labels, count));
+                }
+                conversion_errors.push(counter(labels.to_vec(), table.conversion_errors));
+                for (column, count) in &table.column_conversion_errors {
+                    let mut column_labels = vec![label("column", column)];
+                    column_labels.extend(labels.iter().cloned());
+                    column_conversion_errors.push(counter(column_labels, *count));
+                }
+                sink_latencies.push(histogram(labels.to_vec(), &table));
+            }
+        }
+
+        let families = [
+            family(
+                "replicator_lag_bytes",
+                "Bytes of WAL the source has written past the position flushed to the sink",
+                MetricType::GAUGE,
+                lag_bytes,
+            ),
+            family(
+                "replicator_lag_seconds",
+                "Age of the last flushed transaction when it was flushed",
+                MetricType::GAUGE,
+                lag_secs,
+            ),
+            family(
+                "replicator_estimated_lag_seconds",
+                "Time the replicator needs to catch up at the rate the source writes WAL",
+                MetricType::GAUGE,
+                estimated_lag_secs,
+            ),
+            family(
+                "replicator_table_changes_total",
+                "Changes of a table written to the sink by operation, since the replicator started",
+                MetricType::COUNTER,
+                changes,
+            ),
+            family(
+                "replicator_table_conversion_errors_total",
+                "Values of a table's rows and changes which couldn't be converted, since the replicator started",
+                MetricType::COUNTER,
+                conversion_errors,
+            ),
+            family(
+                "replicator_column_conversion_errors_total",
+                "Values of a table's column which couldn't be converted, since the replicator started",
+                MetricType::COUNTER,
+                column_conversion_errors,
+            ),
+            family(
+                "replicator_table_sink_latency_seconds",
+                "Time the sink took to write batches with rows of a table",
+                MetricType::HISTOGRAM,
+                sink_latencies,
+            ),
+        ];
+        // families without series can't be encoded
+        Ok(families
+            .into_iter()
+            .filter(|family| !family.get_metric().is_empty())
+            .collect())
+    }
+}
+
+fn table_changes(table: &TableMetricsReport) -> u64 {
+    table.inserts + table.updates + table.deletes + table.conversion_errors
+}
+
+/// Keeps the tables of a pipeline with the most changes, up to the limits of
+/// the settings, and adds up the others in a table named [`OTHER_TABLES`]
+fn limit_tables(
+    mut tables: Vec<TableMetricsReport>,
+    settings: &MetricsSettings,
+) -> Vec<TableMetricsReport> {
+    tables.sort_by_key(|table| Reverse(table_changes(table)));
+    let kept = tables
+        .iter()
+        .take(settings.max_tables_per_pipeline)
+        .take_while(|table| table_changes(table) >= settings.min_table_changes)
+        .count();
+    let others = tables.split_off(kept);
+    if let Some(mut other) = others.into_iter().reduce(|mut sum, table| {
+        sum.inserts += table.inserts;
+        sum.updates += table.updates;
+        sum.deletes += table.deletes;
+        sum.conversion_errors += table.conversion_errors;
+        for (bucket, table_bucket) in sum
+            .sink_latency_buckets
+            .iter_mut()
+            .zip(table.sink_latency_buckets)
+        {
+            bucket.count += table_bucket.count;
+        }
+        sum.sink_latency_sum_secs += table.sink_latency_sum_secs;
+        sum.sink_latency_count += table.sink_latency_count;
+        sum
+    }) {
+        other.table_name = OTHER_TABLES.to_string();
+        // columns of different tables aren't added up
+        other.column_conversion_errors.clear();
+        tables.push(other);
+    }
+    tables
+}
+
+fn label(name: &str, value: &str) -> LabelPair {
+    let mut label = LabelPair::default();
+    label.set_name(name.to_string());
+    label.set_value(value.to_string());
+    label
+}
+
+fn gauge(labels: Vec<LabelPair>, value: f64) -> Metric {
+    let mut gauge = Gauge::default();
+    gauge.set_value(value);
+    let mut metric = Metric::default();
+    metric.set_label(labels.into());
+    metric.set_gauge(gauge);
+    metric
+}
+
+fn counter(labels: Vec<LabelPair>, value: u64) -> Metric {
+    let mut counter = Counter::default();
+    counter.set_value(value as f64);
+    let mut metric = Metric::default();
+    metric.set_label(labels.into());
+    metric.set_counter(counter);
+    metric
+}
+
+fn histogram(labels: Vec<LabelPair>, table: &TableMetricsReport) -> Metric {
+    let buckets: Vec<Bucket> = table
+        .sink_latency_buckets
+        .iter()
+        .map(|latency_bucket| {
+            let mut bucket = Bucket::default();
+            bucket.set_upper_bound(latency_bucket.le_secs);
+            bucket.set_cumulative_count(latency_bucket.count);
+            bucket
+        })
+        .collect();
+    let mut histogram = Histogram::default();
+    histogram.set_bucket(buckets.into());
+    histogram.set_sample_sum(table.sink_latency_sum_secs);
+    histogram.set_sample_count(table.sink_latency_count);
+    let mut metric = Metric::default();
+    metric.set_label(labels.into());
+    metric.set_histogram(histogram);
+    metric
+}
+
+fn family(name: &str, help: &str, typ: MetricType, metrics: Vec<Metric>) -> MetricFamily {
+    let mut family = MetricFamily::default();
+    family.set_name(name.to_string());
+    family.set_help(help.to_string());
+    family.set_field_type(typ);
+    family.set_metric(metrics.into());
+    family
+}
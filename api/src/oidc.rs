@@ -0,0 +1,483 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aws_lc_rs::signature::{RsaPublicKeyComponents, RSA_PKCS1_2048_8192_SHA256};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+use crate::{authentication::Role, configuration::OidcSettings};
+
+/// Tolerated clock difference with the issuer when checking expiry
+const CLOCK_SKEW_LEEWAY_SECS: u64 = 60;
+
+/// Minimum time between two fetches of the issuer's keys, so that tokens
+/// with unknown key ids can't make the api hammer the issuer
+const MIN_KEYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("token is malformed")]
+    MalformedToken,
+
+    #[error("token algorithm {0} is not supported")]
+    UnsupportedAlgorithm(String),
+
+    #[error("no key with id {0} in the issuer's keys")]
+    UnknownKey(String),
+
+    #[error("token signature is invalid")]
+    InvalidSignature,
+
+    #[error("token issuer {0} is not trusted")]
+    InvalidIssuer(String),
+
+    #[error("token was not issued for audience {0}")]
+    InvalidAudience(String),
+
+    #[error("token is expired")]
+    Expired,
+
+    #[error("token is not valid yet")]
+    NotYetValid,
+
+    #[error("token has no known role")]
+    MissingRole,
+
+    #[error("failed to fetch the issuer's keys: {0}")]
+    FetchKeys(#[from] reqwest::Error),
+}
+
+/// What a validated token grants
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub role: Role,
+    pub tenant_id: Option<String>,
+    /// The sub claim, identifying the user
+    pub subject: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Clone)]
+struct RsaKey {
+    n: Vec<u8>,
+    e: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Keys {
+    keys: HashMap<String, RsaKey>,
+    fetched_at: Option<Instant>,
+}
+
+/// Validates RS256 signed JWTs issued by an OpenID Connect issuer. The
+/// issuer's keys are fetched on first use and again when a token is signed
+/// with a key not seen before.
+pub struct OidcValidator {
+    settings: OidcSettings,
+    client: reqwest::Client,
+    keys: RwLock<Keys>,
+}
+
+impl OidcValidator {
+    pub fn new(settings: OidcSettings) -> OidcValidator {
+        OidcValidator {
+            settings,
+            client: reqwest::Client::new(),
+            keys: RwLock::new(Keys::default()),
+        }
+    }
+
+    pub async fn validate(&self, token: &str) -> Result<TokenClaims, OidcError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(claims), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(OidcError::MalformedToken);
+        };
+
+        let header: Header = decode_json(header)?;
+        if header.alg != "RS256" {
+            return Err(OidcError::UnsupportedAlgorithm(header.alg));
+        }
+        let kid = header.kid.ok_or(OidcError::MalformedToken)?;
+        let key = self.key(&kid).await?;
+
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| OidcError::MalformedToken)?;
+        let message = &token[..header_and_claims_len(token)];
+        RsaPublicKeyComponents {
+            n: &key.n,
+            e: &key.e,
+        }
+        .verify(&RSA_PKCS1_2048_8192_SHA256, message.as_bytes(), &signature)
+        .map_err(|_| OidcError::InvalidSignature)?;
+
+        let claims: Value = decode_json(claims)?;
+        self.validate_claims(&claims)
+    }
+
+    fn validate_claims(&self, claims: &Value) -> Result<TokenClaims, OidcError> {
+        let issuer = claims["iss"].as_str().unwrap_or_default();
+        if issuer.trim_end_matches('/') != self.settings.issuer.trim_end_matches('/') {
+            return Err(OidcError::InvalidIssuer(issuer.to_string()));
+        }
+
+        let audience = &self.settings.audience;
+        let has_audience = match &claims["aud"] {
+            Value::String(aud) => aud == audience,
+            Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !has_audience {
+            return Err(OidcError::InvalidAudience(audience.clone()));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let expires_at = claims["exp"].as_u64().ok_or(OidcError::MalformedToken)?;
+        if expires_at.saturating_add(CLOCK_SKEW_LEEWAY_SECS) < now {
+            return Err(OidcError::Expired);
+        }
+        if let Some(not_before) = claims["nbf"].as_u64() {
+            if not_before > now + CLOCK_SKEW_LEEWAY_SECS {
+                return Err(OidcError::NotYetValid);
+            }
+        }
+
+        let role = match &claims[self.settings.roles_claim.as_str()] {
+            Value::String(role) => Role::parse(role),
+            Value::Array(roles) => roles
+                .iter()
+                .filter_map(|role| role.as_str().and_then(Role::parse))
+                .max(),
+            _ => None,
+        }
+        .ok_or(OidcError::MissingRole)?;
+
+        let tenant_id = claims[self.settings.tenant_claim.as_str()]
+            .as_str()
+            .map(|tenant_id| tenant_id.to_string());
+
+        let subject = claims["sub"].as_str().map(|subject| subject.to_string());
+
+        Ok(TokenClaims {
+            role,
+            tenant_id,
+            subject,
+        })
+    }
+
+    async fn key(&self, kid: &str) -> Result<RsaKey, OidcError> {
+        {
+            let keys = self.keys.read().await;
+            if let Some(key) = keys.keys.get(kid) {
+                return Ok(key.clone());
+            }
+            if keys
+                .fetched_at
+                .is_some_and(|fetched_at| fetched_at.elapsed() < MIN_KEYS_REFRESH_INTERVAL)
+            {
+                return Err(OidcError::UnknownKey(kid.to_string()));
+            }
+        }
+
+        let mut keys = self.keys.write().await;
+        // Another request might have refreshed the keys in the meantime
+        let refreshed = keys
+            .fetched_at
+            .is_some_and(|fetched_at| fetched_at.elapsed() < MIN_KEYS_REFRESH_INTERVAL);
+        if !refreshed {
+            keys.keys = self.fetch_keys().await?;
+            keys.fetched_at = Some(Instant::now());
+        }
+        keys.keys
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| OidcError::UnknownKey(kid.to_string()))
+    }
+
+    async fn fetch_keys(&self) -> Result<HashMap<String, RsaKey>, OidcError> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            self.settings.issuer.trim_end_matches('/')
+        );
+        let discovery: DiscoveryDocument = self
+            .client
+            .get(discovery_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk_set: JwkSet = self
+            .client
+            .get(discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let keys = jwk_set
+            .keys
+            .into_iter()
+            .filter(|jwk| jwk.kty == "RSA")
+            .filter_map(|jwk| {
+                let n = BASE64_URL_SAFE_NO_PAD.decode(jwk.n?).ok()?;
+                let e = BASE64_URL_SAFE_NO_PAD.decode(jwk.e?).ok()?;
+                Some((jwk.kid?, RsaKey { n, e }))
+            })
+            .collect();
+        Ok(keys)
+    }
+}
+
+fn header_and_claims_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(part: &str) -> Result<T, OidcError> {
+    let bytes = BASE64_URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| OidcError::MalformedToken)?;
+    serde_json::from_slice(&bytes).map_err(|_| OidcError::MalformedToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+    };
+    use base64::{
+        prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+        Engine,
+    };
+    use serde_json::json;
+
+    use crate::{authentication::Role, configuration::OidcSettings};
+
+    use super::{OidcError, OidcValidator, RsaKey, TokenClaims};
+
+    const PRIVATE_KEY: &str = "
+MIIEvgIBADANBgkqhkiG9w0BAQEFAASCBKgwggSkAgEAAoIBAQDwcUROt8zwCDfW
+OguImkavrR9ri6o5KhktKcR0TsxzZ1aLaOnLmImuAtu5CF2YCpImrSh3/Sn4/nvt
+trhSL2u7Af8EuanvGW2FFbC05sHSHVinHV9HNiKj7oGbkdYF5HR1FTzpvoydbZp+
+zPLamoNJwtn1U7Ck/51sj+x1PBzGglxOTDNs6kBzMfSm75vf+QWHJp09en6/pCpJ
+RXDYunuDuM27xV28LHkaD/dT/PUhjw1eqoYNuwlwW2CVMDD7LV16xiGehhUpyu/l
+Us6GouvPQt3hEdI2V5wd6oAtig8Q7JTKnO3kIwzwLicWSLZFMfMEW8YM6Un/uF/Y
+mUgrW+VdAgMBAAECggEAJA0vq0fm3BS3XpEseop7T8r6+5Kg7tpPlRR+ptvLzv/v
+G48bAxJ3jlZSYK+O7QWkvVTYTQlyGin6Jf3wLC59Djix5qD6CGY9JCfI647v/9Oz
+n7cJOrG0xvSzjUJ0H0UOayHIlNmj1tUAxcNwJHPnfI9RQ/YM9eh3z7fSwg9F10OZ
+MhA/7lcYujKFZJ5/px36C/r18ArOO1+CyU5HFjvnes+/eQ49f3guCyXmtojj82gG
+kfnYP0PKwx8m47ZDbLajl4NpVsG55jfKL1gRjzTgRjjYBrsPW4L8X1bFnm0xoooV
+v2yDSoGdc+zLykck/WM1/BpNFBJeH1Jd1zSpS6Xv5wKBgQD4Vu4QTpnw0EOtlLu4
+gn2FKGAkOMKOsMHwlkm7kKRJLASeVmXBu9L8p05FbI5CHl0e/heMwEL4BtSHOh19
+GovEgbzBdUFZSPA+X+l0GTRW7Nz8GE64v0gWWDp8CehUsKbV2LI3ozqQplAmM7Hu
+PxSWMvboKlkg0xpd1mMpozXyGwKBgQD32/m4SjKVgBEUq3uC05KhoHBs03X63XTr
+iO8CtTSsA0oYDvq/ZowDfkk77eCqBE2Iq5EoTC2tApJMcatPzJ8kBXnzPxXZaOSV
+LE6dBbHPLFSaX63aDXnYAoqRSKS8RZORmete4z2ZNImC3qF7U3Hg62LvJdSvgaoN
+e4/DoNo95wKBgQC5CgzYmbiadpP3ApvdH13nme4JD0HokEvxuAm4eE3xN5s5X6by
+c8ECAKeRkxFssrMwJKPnfFpp9KznFaZjUSRTh1fdzkCjBnX8A/svRph5LIR8UqBV
+iDFudM8fHUK/1+B39r7UNgutPD78OAicPORaBh9zXhqLGsDKqrO4kqPfgQKBgQCE
+EBIbTnVrUMRJFiGGEIL1WU3tvjIDi5Grmdd3wuMgr9P/w6N47gO2LMiKhPGKxSYm
+pvt91DEWGOOolXwo1acJg1157OgQYiJBFU2BvrIB5+XgJxCzuTBtltUMGf+kTRIW
+p0NQ2JVOlz6Zm1PBKTHpHKInOCn9pYHdEzQDkSYXiwKBgFiL9PomF4OW1d70C0AZ
+YRJCcst3TbOOAzmH3sa9B4wydn7oAJMN++DA97PxWJooDCYwHxg3d7t5CoiMJ8J3
+fn0Yy2cWQYTgAnrW6RbM6eZBCR9qcEShREOyb+XijZvwOw3BEYb8WkQ2+xSXgCHD
+TAyLguj4hJmwBXcSQwurGFk5
+";
+
+    const MODULUS: &str = "8HFETrfM8Ag31joLiJpGr60fa4uqOSoZLSnEdE7Mc2dWi2jpy5iJrgLbuQhdmAqSJq0od_0p-P577ba4Ui9ruwH_BLmp7xlthRWwtObB0h1Ypx1fRzYio-6Bm5HWBeR0dRU86b6MnW2afszy2pqDScLZ9VOwpP-dbI_sdTwcxoJcTkwzbOpAczH0pu-b3_kFhyadPXp-v6QqSUVw2Lp7g7jNu8VdvCx5Gg_3U_z1IY8NXqqGDbsJcFtglTAw-y1desYhnoYVKcrv5VLOhqLrz0Ld4RHSNlecHeqALYoPEOyUypzt5CMM8C4nFki2RTHzBFvGDOlJ_7hf2JlIK1vlXQ";
+
+    const EXPONENT: &str = "AQAB";
+
+    const KID: &str = "test-key";
+
+    async fn validator() -> OidcValidator {
+        let validator = OidcValidator::new(OidcSettings {
+            issuer: "https://issuer.example.com".to_string(),
+            audience: "pg_replicate".to_string(),
+            roles_claim: "roles".to_string(),
+            tenant_claim: "tenant_id".to_string(),
+        });
+        {
+            let mut keys = validator.keys.write().await;
+            keys.keys.insert(
+                KID.to_string(),
+                RsaKey {
+                    n: BASE64_URL_SAFE_NO_PAD.decode(MODULUS).unwrap(),
+                    e: BASE64_URL_SAFE_NO_PAD.decode(EXPONENT).unwrap(),
+                },
+            );
+            keys.fetched_at = Some(Instant::now());
+        }
+        validator
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn sign(header: serde_json::Value, claims: serde_json::Value) -> String {
+        let message = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let der = BASE64_STANDARD
+            .decode(PRIVATE_KEY.replace('\n', ""))
+            .unwrap();
+        let key_pair = RsaKeyPair::from_pkcs8(&der).unwrap();
+        let mut signature = vec![0; key_pair.public_modulus_len()];
+        key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .unwrap();
+        format!("{message}.{}", BASE64_URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn token(claims: serde_json::Value) -> String {
+        sign(json!({ "alg": "RS256", "typ": "JWT", "kid": KID }), claims)
+    }
+
+    #[tokio::test]
+    async fn valid_token_is_accepted() {
+        let token = token(json!({
+            "iss": "https://issuer.example.com",
+            "aud": ["pg_replicate", "other"],
+            "exp": now() + 300,
+            "roles": ["viewer", "editor"],
+            "tenant_id": "abcdefghijklmnopqrst",
+            "sub": "user-1",
+        }));
+
+        let claims = validator().await.validate(&token).await.unwrap();
+
+        assert_eq!(
+            claims,
+            TokenClaims {
+                role: Role::Editor,
+                tenant_id: Some("abcdefghijklmnopqrst".to_string()),
+                subject: Some("user-1".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn token_with_invalid_signature_is_rejected() {
+        let token = token(json!({
+            "iss": "https://issuer.example.com",
+            "aud": "pg_replicate",
+            "exp": now() + 300,
+            "roles": "viewer",
+        }));
+        let (message, _) = token.rsplit_once('.').unwrap();
+        let other_token = self::token(json!({ "roles": "admin" }));
+        let (_, other_signature) = other_token.rsplit_once('.').unwrap();
+        let tampered_token = format!("{message}.{other_signature}");
+
+        let result = validator().await.validate(&tampered_token).await;
+
+        assert!(matches!(result, Err(OidcError::InvalidSignature)));
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_rejected() {
+        let token = token(json!({
+            "iss": "https://issuer.example.com",
+            "aud": "pg_replicate",
+            "exp": now() - 300,
+            "roles": "admin",
+        }));
+
+        let result = validator().await.validate(&token).await;
+
+        assert!(matches!(result, Err(OidcError::Expired)));
+    }
+
+    #[tokio::test]
+    async fn token_for_another_audience_is_rejected() {
+        let token = token(json!({
+            "iss": "https://issuer.example.com",
+            "aud": "another_api",
+            "exp": now() + 300,
+            "roles": "admin",
+        }));
+
+        let result = validator().await.validate(&token).await;
+
+        assert!(matches!(result, Err(OidcError::InvalidAudience(_))));
+    }
+
+    #[tokio::test]
+    async fn token_without_known_role_is_rejected() {
+        let token = token(json!({
+            "iss": "https://issuer.example.com",
+            "aud": "pg_replicate",
+            "exp": now() + 300,
+            "roles": ["operator"],
+        }));
+
+        let result = validator().await.validate(&token).await;
+
+        assert!(matches!(result, Err(OidcError::MissingRole)));
+    }
+
+    #[tokio::test]
+    async fn token_with_unsupported_algorithm_is_rejected() {
+        let token = sign(
+            json!({ "alg": "none", "kid": KID }),
+            json!({
+                "iss": "https://issuer.example.com",
+                "aud": "pg_replicate",
+                "exp": now() + 300,
+                "roles": "admin",
+            }),
+        );
+
+        let result = validator().await.validate(&token).await;
+
+        assert!(matches!(result, Err(OidcError::UnsupportedAlgorithm(_))));
+    }
+}
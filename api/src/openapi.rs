@@ -0,0 +1,305 @@
+use utoipa::{
+    openapi::{
+        path::{ParameterBuilder, ParameterIn},
+        security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+        ObjectBuilder, Required, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    authentication::ADMIN_ONLY_PATHS,
+    connection_test::{ConnectionCheck, ConnectionTestResult},
+    db::{
+        audit_logs::{AuditAction, AuditResource},
+        jobs::{JobParams, JobStatus},
+        pagination::{SortField, SortOrder},
+        pipelines::{
+            BatchConfig, PartitionGranularity, PipelineConfig, TableBatchConfig, TableOverrides,
+            TablePartitioning, TableSelection, WriteMode,
+        },
+        publications::Publication,
+        quotas::TenantQuotas,
+        replicators::ReplicatorStatus,
+        sinks::SinkConfig,
+        sources::SourceConfig,
+        tables::Table,
+    },
+    health::PipelineHealth,
+    routes::{
+        api_keys::{GetApiKeyResponse, PostApiKeyRequest, PostApiKeyResponse},
+        audit::{AuditChange, GetAuditLogResponse},
+        images::{GetImageResponse, PostImageRequest, PostImageResponse},
+        jobs::{GetJobResponse, PostJobResponse, PostResyncRequest},
+        pipelines::{
+            images::{
+                GetPipelineImageResponse, PostUpgradePipelinesRequest,
+                PostUpgradePipelinesResponse, PutPipelineImageRequest,
+            },
+            GetPipelineResponse, GetPipelineStatusResponse, GetPipelineVersionResponse,
+            LatencyBucket, PipelineStatus, PostPipelineRequest, PostPipelineResponse,
+            PostPipelineStatusRequest, PostRollbackResponse, ReplicatorPhase,
+            ReplicatorStatusReport, TableCopyProgress, TableMetricsReport,
+        },
+        quotas::{Quota, QuotaExceededMessage},
+        sinks::{GetSinkResponse, GetSinkVersionResponse, PostSinkRequest, PostSinkResponse},
+        sources::{
+            publications::{CreatePublicationRequest, UpdatePublicationRequest},
+            GetSourceResponse, GetSourceVersionResponse, PostSourceRequest, PostSourceResponse,
+            TestSourceRequest,
+        },
+        tenants::{
+            config::{
+                ApplyPlan, DeclaredPipeline, DeclaredSink, DeclaredSource, PlannedChanges,
+                TenantConfig,
+            },
+            CreateTenantRequest, GetTenantResponse, PostTenantResponse, UpdateTenantRequest,
+        },
+        webhooks::{
+            DeliveryStatus, GetWebhookDeliveryResponse, GetWebhookResponse, PostWebhookRequest,
+            PostWebhookResponse,
+        },
+        ApiKeysPage, AuditLogsPage, BatchItemResult, BatchResponse, ErrorMessage, FieldError,
+        ImagesPage, PipelineBatchItem, PipelinesPage, SinkBatchItem, SinksPage, SourceBatchItem,
+        SourcesPage, TenantsPage, ValidationErrorMessage, WebhooksPage,
+    },
+    webhooks::WebhookEvent,
+};
+
+const BEARER_AUTH: &str = "bearer_auth";
+
+/// The OpenAPI document of the api, served at /api-docs/openapi.json
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::routes::health_check::health_check,
+        crate::routes::metrics::read_metrics,
+        crate::routes::api_keys::create_api_key,
+        crate::routes::api_keys::rotate_api_key,
+        crate::routes::api_keys::delete_api_key,
+        crate::routes::api_keys::read_all_api_keys,
+        crate::routes::webhooks::create_webhook,
+        crate::routes::webhooks::read_webhook,
+        crate::routes::webhooks::update_webhook,
+        crate::routes::webhooks::delete_webhook,
+        crate::routes::webhooks::read_all_webhooks,
+        crate::routes::webhooks::read_webhook_deliveries,
+        crate::routes::images::create_image,
+        crate::routes::images::read_image,
+        crate::routes::images::update_image,
+        crate::routes::images::delete_image,
+        crate::routes::images::read_all_images,
+        crate::routes::pipelines::create_pipeline,
+        crate::routes::pipelines::read_pipeline,
+        crate::routes::pipelines::update_pipeline,
+        crate::routes::pipelines::batch_pipelines,
+        crate::routes::pipelines::delete_pipeline,
+        crate::routes::pipelines::restore_pipeline,
+        crate::routes::pipelines::read_all_pipelines,
+        crate::routes::pipelines::start_pipeline,
+        crate::routes::pipelines::stop_pipeline,
+        crate::routes::pipelines::restart_pipeline,
+        crate::routes::pipelines::read_pipeline_versions,
+        crate::routes::pipelines::rollback_pipeline,
+        crate::routes::pipelines::get_pipeline_status,
+        crate::routes::pipelines::events::stream_pipeline_events,
+        crate::routes::pipelines::images::read_pipeline_image,
+        crate::routes::pipelines::images::update_pipeline_image,
+        crate::routes::pipelines::images::upgrade_pipelines,
+        crate::routes::pipelines::report_pipeline_status,
+        crate::routes::jobs::initial_copy,
+        crate::routes::jobs::resync_tables,
+        crate::routes::jobs::validate_sink,
+        crate::routes::jobs::read_job,
+        crate::routes::tenants::create_tenant,
+        crate::routes::tenants::create_or_update_tenant,
+        crate::routes::tenants::read_tenant,
+        crate::routes::tenants::update_tenant,
+        crate::routes::tenants::delete_tenant,
+        crate::routes::tenants::read_all_tenants,
+        crate::routes::tenants::config::apply_tenant_config,
+        crate::routes::quotas::read_tenant_quotas,
+        crate::routes::quotas::update_tenant_quotas,
+        crate::routes::audit::read_audit_logs,
+        crate::routes::sources::create_source,
+        crate::routes::sources::read_source,
+        crate::routes::sources::update_source,
+        crate::routes::sources::batch_sources,
+        crate::routes::sources::delete_source,
+        crate::routes::sources::restore_source,
+        crate::routes::sources::read_all_sources,
+        crate::routes::sources::test_source,
+        crate::routes::sources::read_source_versions,
+        crate::routes::sources::publications::create_publication,
+        crate::routes::sources::publications::read_publication,
+        crate::routes::sources::publications::update_publication,
+        crate::routes::sources::publications::delete_publication,
+        crate::routes::sources::publications::read_all_publications,
+        crate::routes::sources::tables::read_table_names,
+        crate::routes::sinks::create_sink,
+        crate::routes::sinks::read_sink,
+        crate::routes::sinks::update_sink,
+        crate::routes::sinks::batch_sinks,
+        crate::routes::sinks::delete_sink,
+        crate::routes::sinks::restore_sink,
+        crate::routes::sinks::read_all_sinks,
+        crate::routes::sinks::test_sink,
+        crate::routes::sinks::read_sink_versions,
+    ),
+    components(schemas(
+        ErrorMessage,
+        ValidationErrorMessage,
+        FieldError,
+        BatchResponse,
+        BatchItemResult,
+        SourceBatchItem,
+        SinkBatchItem,
+        PipelineBatchItem,
+        SortField,
+        SortOrder,
+        ApiKeysPage,
+        AuditLogsPage,
+        ImagesPage,
+        PipelinesPage,
+        SinksPage,
+        SourcesPage,
+        TenantsPage,
+        WebhooksPage,
+        PostApiKeyRequest,
+        PostApiKeyResponse,
+        GetApiKeyResponse,
+        PostWebhookRequest,
+        PostWebhookResponse,
+        GetWebhookResponse,
+        WebhookEvent,
+        GetWebhookDeliveryResponse,
+        DeliveryStatus,
+        PostImageRequest,
+        PostImageResponse,
+        GetImageResponse,
+        PostResyncRequest,
+        PostJobResponse,
+        GetJobResponse,
+        JobParams,
+        JobStatus,
+        PostPipelineRequest,
+        PostPipelineResponse,
+        GetPipelineResponse,
+        GetPipelineVersionResponse,
+        PostRollbackResponse,
+        GetPipelineImageResponse,
+        PutPipelineImageRequest,
+        PostUpgradePipelinesRequest,
+        PostUpgradePipelinesResponse,
+        PipelineConfig,
+        BatchConfig,
+        TableSelection,
+        TableOverrides,
+        WriteMode,
+        TablePartitioning,
+        PartitionGranularity,
+        TableBatchConfig,
+        GetPipelineStatusResponse,
+        PipelineStatus,
+        ReplicatorStatus,
+        PostPipelineStatusRequest,
+        ReplicatorPhase,
+        ReplicatorStatusReport,
+        PipelineHealth,
+        TableCopyProgress,
+        TableMetricsReport,
+        LatencyBucket,
+        CreateTenantRequest,
+        UpdateTenantRequest,
+        PostTenantResponse,
+        GetTenantResponse,
+        TenantConfig,
+        DeclaredSource,
+        DeclaredSink,
+        DeclaredPipeline,
+        ApplyPlan,
+        PlannedChanges,
+        TenantQuotas,
+        Quota,
+        QuotaExceededMessage,
+        GetAuditLogResponse,
+        AuditChange,
+        AuditAction,
+        AuditResource,
+        PostSourceRequest,
+        PostSourceResponse,
+        GetSourceResponse,
+        GetSourceVersionResponse,
+        SourceConfig,
+        TestSourceRequest,
+        CreatePublicationRequest,
+        UpdatePublicationRequest,
+        Publication,
+        Table,
+        PostSinkRequest,
+        PostSinkResponse,
+        GetSinkResponse,
+        GetSinkVersionResponse,
+        SinkConfig,
+        ConnectionTestResult,
+        ConnectionCheck,
+    )),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
+
+/// Documents the bearer authentication of the routes under /v1 and of
+/// /metrics and the tenant_id header of the routes scoped to a tenant, which
+/// would otherwise have to be repeated in every handler's annotation
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            BEARER_AUTH,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "The api key, a tenant's api key or a token of the OpenID Connect issuer",
+                    ))
+                    .build(),
+            ),
+        );
+
+        let tenant_id = ParameterBuilder::new()
+            .name("tenant_id")
+            .parameter_in(ParameterIn::Header)
+            .required(Required::False)
+            .description(Some(
+                "Id of the tenant, not needed when authenticating as a tenant",
+            ))
+            .schema(Some(ObjectBuilder::new().schema_type(SchemaType::String)))
+            .build();
+
+        for (path, path_item) in openapi.paths.paths.iter_mut() {
+            let v1_path = path.strip_prefix("/v1");
+            // the metrics are outside of /v1 but need the api key too
+            if v1_path.is_none() && path != "/metrics" {
+                continue;
+            }
+            let path = v1_path.unwrap_or(path);
+            let tenant_scoped = !ADMIN_ONLY_PATHS
+                .iter()
+                .any(|admin_path| path.starts_with(admin_path));
+            for operation in path_item.operations.values_mut() {
+                operation.security = Some(vec![SecurityRequirement::new(
+                    BEARER_AUTH,
+                    Vec::<String>::new(),
+                )]);
+                if tenant_scoped {
+                    operation
+                        .parameters
+                        .get_or_insert_with(Vec::new)
+                        .push(tenant_id.clone());
+                }
+            }
+        }
+    }
+}
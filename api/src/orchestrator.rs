@@ -0,0 +1,147 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use actix_web::web::Data;
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{
+    configuration::WorkerSettings,
+    db,
+    encryption::EncryptionKey,
+    k8s_client::{HttpK8sClient, K8sClient, K8sError},
+    metrics::{ApiMetrics, Worker},
+    replicator_config,
+    routes::pipelines::start_replicator,
+};
+
+#[derive(Debug, Error)]
+enum ReconcileError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("k8s error: {0}")]
+    K8s(#[from] K8sError),
+}
+
+/// Secrets of a replicator, stored in kubernetes secrets rather than in its
+/// config map
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Secrets {
+    pub postgres_password: String,
+    pub bigquery_service_account_key: String,
+    /// Key the replicator reports its pipeline's status with
+    pub api_key: String,
+}
+
+/// Prefix of the names of a replicator's kubernetes resources
+pub fn replicator_prefix(tenant_id: &str, replicator_id: i64) -> String {
+    format!("{tenant_id}-{replicator_id}")
+}
+
+/// Creates or updates a replicator's secrets, config map and stateful set,
+/// which replaces its running pod
+pub async fn deploy_replicator(
+    k8s_client: &Arc<HttpK8sClient>,
+    prefix: &str,
+    secrets: Secrets,
+    config: replicator_config::Config,
+    replicator_image: &str,
+) -> Result<(), K8sError> {
+    k8s_client
+        .create_or_update_postgres_secret(prefix, &secrets.postgres_password)
+        .await?;
+    k8s_client
+        .create_or_update_bq_secret(prefix, &secrets.bigquery_service_account_key)
+        .await?;
+    k8s_client
+        .create_or_update_api_key_secret(prefix, &secrets.api_key)
+        .await?;
+
+    let base_config = "";
+    let prod_config = serde_json::to_string(&config)?;
+    k8s_client
+        .create_or_update_config_map(prefix, base_config, &prod_config)
+        .await?;
+
+    k8s_client
+        .create_or_update_stateful_set(prefix, replicator_image)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a replicator's secrets, config map and stateful set, ignoring the
+/// ones which don't exist
+pub async fn teardown_replicator(
+    k8s_client: &Arc<HttpK8sClient>,
+    prefix: &str,
+) -> Result<(), K8sError> {
+    k8s_client.delete_postgres_secret(prefix).await?;
+    k8s_client.delete_bq_secret(prefix).await?;
+    k8s_client.delete_api_key_secret(prefix).await?;
+    k8s_client.delete_config_map(prefix).await?;
+    k8s_client.delete_stateful_set(prefix).await?;
+    Ok(())
+}
+
+/// Deploys the replicators of started pipelines which have no stateful set
+/// and tears down the stateful sets of replicators which shouldn't be
+/// running, e.g. because their pipeline was deleted
+async fn reconcile(
+    pool: &PgPool,
+    encryption_key: &EncryptionKey,
+    k8s_client: &Arc<HttpK8sClient>,
+) -> Result<(), ReconcileError> {
+    let started = db::replicators::read_started_replicators(pool).await?;
+    let deployed = k8s_client.list_stateful_set_prefixes().await?;
+
+    let mut expected = HashSet::new();
+    for replicator in started {
+        let prefix = replicator_prefix(&replicator.tenant_id, replicator.id);
+        if !deployed.contains(&prefix) {
+            info!("deploying missing replicator {prefix}");
+            let result = start_replicator(
+                pool,
+                encryption_key,
+                k8s_client,
+                &replicator.tenant_id,
+                replicator.pipeline_id,
+            )
+            .await;
+            if let Err(e) = result {
+                error!("failed to deploy replicator {prefix}: {e}");
+            }
+        }
+        expected.insert(prefix);
+    }
+
+    for prefix in deployed.difference(&expected) {
+        info!("tearing down replicator {prefix}");
+        if let Err(e) = teardown_replicator(k8s_client, prefix).await {
+            error!("failed to tear down replicator {prefix}: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Reconciles the deployed replicators with the pipelines which should be
+/// running every `reconcile_interval_secs` until the process exits, like a
+/// kubernetes operator whose desired state is kept in the api's database
+pub async fn run_orchestrator(
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    k8s_client: Data<Arc<HttpK8sClient>>,
+    metrics: Data<ApiMetrics>,
+    settings: WorkerSettings,
+) {
+    let reconcile_interval = Duration::from_secs(settings.reconcile_interval_secs);
+    loop {
+        metrics.record_heartbeat(Worker::Orchestrator);
+        if let Err(e) = reconcile(&pool, &encryption_key, &k8s_client).await {
+            error!("failed to reconcile replicators: {e}");
+        }
+        tokio::time::sleep(reconcile_interval).await;
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub enum SourceConfig {
@@ -20,6 +20,10 @@ pub enum SourceConfig {
 
         /// Postgres publication name
         publication: String,
+
+        /// Tables of the publication to replicate
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tables: Option<TableFilter>,
     },
 }
 
@@ -33,6 +37,7 @@ impl Debug for SourceConfig {
                 username,
                 slot_name,
                 publication,
+                tables,
             } => f
                 .debug_struct("Postgres")
                 .field("host", host)
@@ -41,6 +46,7 @@ impl Debug for SourceConfig {
                 .field("username", username)
                 .field("slot_name", slot_name)
                 .field("publication", publication)
+                .field("tables", tables)
                 .finish(),
         }
     }
@@ -54,6 +60,10 @@ pub enum SinkConfig {
 
         /// BigQuery dataset id
         dataset_id: String,
+
+        /// Names of the replicated tables
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        naming: Option<NamingConfig>,
     },
 }
 
@@ -63,15 +73,35 @@ impl Debug for SinkConfig {
             Self::BigQuery {
                 project_id,
                 dataset_id,
+                naming,
             } => f
                 .debug_struct("BigQuery")
                 .field("project_id", project_id)
                 .field("dataset_id", dataset_id)
+                .field("naming", naming)
                 .finish(),
         }
     }
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableFilter {
+    /// Patterns of table names in `schema.name` form to replicate
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    /// Patterns of table names in `schema.name` form not to replicate
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct NamingConfig {
+    /// BigQuery table names of Postgres tables in `schema.name` form
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub table_names: BTreeMap<String, String>,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct BatchConfig {
     /// maximum batch size in number of events
@@ -79,6 +109,76 @@ pub struct BatchConfig {
 
     /// maximum duration, in seconds, to wait for a batch to fill
     pub max_fill_secs: u64,
+
+    /// whether batches end only on the commits of transactions
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub transactional: bool,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableOverrides {
+    /// Name of the BigQuery table instead of the default or mapped name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_mode: Option<WriteMode>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<TablePartitioning>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clustering: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked_columns: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<TableBatchConfig>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum WriteMode {
+    Upsert,
+    AppendOnly,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum TablePartitioning {
+    Column {
+        column: String,
+        granularity: PartitionGranularity,
+    },
+    IngestionTime {
+        granularity: PartitionGranularity,
+    },
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionGranularity {
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableBatchConfig {
+    /// maximum batch size in number of rows
+    pub max_size: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct StatusReportConfig {
+    /// Base url of the api the pipeline's status is reported to
+    pub api_url: String,
+
+    /// Id of the tenant owning the pipeline
+    pub tenant_id: String,
+
+    /// Id of the pipeline in the api
+    pub pipeline_id: i64,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -86,11 +186,26 @@ pub struct Config {
     pub source: SourceConfig,
     pub sink: SinkConfig,
     pub batch: BatchConfig,
+
+    /// Settings of tables in `schema.name` form replacing the pipeline's
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub table_overrides: BTreeMap<String, TableOverrides>,
+
+    /// Where the replicator reports its pipeline's status. The api key it
+    /// reports with is a secret, so it's passed in the environment instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_report: Option<StatusReportConfig>,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::replicator_config::{BatchConfig, Config, SinkConfig, SourceConfig};
+    use std::collections::BTreeMap;
+
+    use crate::replicator_config::{
+        BatchConfig, Config, NamingConfig, PartitionGranularity, SinkConfig, SourceConfig,
+        StatusReportConfig, TableBatchConfig, TableFilter, TableOverrides, TablePartitioning,
+        WriteMode,
+    };
 
     #[test]
     pub fn deserialize_settings_test() {
@@ -125,15 +240,20 @@ mod tests {
                 username: "postgres".to_string(),
                 slot_name: "replicator_slot".to_string(),
                 publication: "replicator_publication".to_string(),
+                tables: None,
             },
             sink: SinkConfig::BigQuery {
                 project_id: "project-id".to_string(),
                 dataset_id: "dataset-id".to_string(),
+                naming: None,
             },
             batch: BatchConfig {
                 max_size: 1000,
                 max_fill_secs: 10,
+                transactional: false,
             },
+            table_overrides: BTreeMap::new(),
+            status_report: None,
         };
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
@@ -149,19 +269,141 @@ mod tests {
                 username: "postgres".to_string(),
                 slot_name: "replicator_slot".to_string(),
                 publication: "replicator_publication".to_string(),
+                tables: None,
             },
             sink: SinkConfig::BigQuery {
                 project_id: "project-id".to_string(),
                 dataset_id: "dataset-id".to_string(),
+                naming: None,
             },
             batch: BatchConfig {
                 max_size: 1000,
                 max_fill_secs: 10,
+                transactional: false,
             },
+            table_overrides: BTreeMap::new(),
+            status_report: None,
         };
         let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","publication":"replicator_publication"}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id"}},"batch":{"max_size":1000,"max_fill_secs":10}}"#;
         let actual = serde_json::to_string(&actual);
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
     }
+
+    #[test]
+    pub fn serialize_table_selection_test() {
+        let actual = Config {
+            source: SourceConfig::Postgres {
+                host: "localhost".to_string(),
+                port: 5432,
+                name: "postgres".to_string(),
+                username: "postgres".to_string(),
+                slot_name: "replicator_slot".to_string(),
+                publication: "replicator_publication".to_string(),
+                tables: Some(TableFilter {
+                    include: vec!["public.*".to_string()],
+                    exclude: vec![],
+                }),
+            },
+            sink: SinkConfig::BigQuery {
+                project_id: "project-id".to_string(),
+                dataset_id: "dataset-id".to_string(),
+                naming: Some(NamingConfig {
+                    table_names: BTreeMap::from([(
+                        "public.users".to_string(),
+                        "customers".to_string(),
+                    )]),
+                }),
+            },
+            batch: BatchConfig {
+                max_size: 1000,
+                max_fill_secs: 10,
+                transactional: false,
+            },
+            table_overrides: BTreeMap::new(),
+            status_report: None,
+        };
+        let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","publication":"replicator_publication","tables":{"include":["public.*"]}}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id","naming":{"table_names":{"public.users":"customers"}}}},"batch":{"max_size":1000,"max_fill_secs":10}}"#;
+        let actual = serde_json::to_string(&actual);
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn serialize_table_overrides_test() {
+        let actual = Config {
+            source: SourceConfig::Postgres {
+                host: "localhost".to_string(),
+                port: 5432,
+                name: "postgres".to_string(),
+                username: "postgres".to_string(),
+                slot_name: "replicator_slot".to_string(),
+                publication: "replicator_publication".to_string(),
+                tables: None,
+            },
+            sink: SinkConfig::BigQuery {
+                project_id: "project-id".to_string(),
+                dataset_id: "dataset-id".to_string(),
+                naming: None,
+            },
+            batch: BatchConfig {
+                max_size: 1000,
+                max_fill_secs: 10,
+                transactional: false,
+            },
+            table_overrides: BTreeMap::from([(
+                "public.events".to_string(),
+                TableOverrides {
+                    destination: Some("event_log".to_string()),
+                    write_mode: Some(WriteMode::AppendOnly),
+                    partitioning: Some(TablePartitioning::IngestionTime {
+                        granularity: PartitionGranularity::Day,
+                    }),
+                    clustering: vec!["user_id".to_string()],
+                    masked_columns: vec![],
+                    batch: Some(TableBatchConfig { max_size: 50000 }),
+                },
+            )]),
+            status_report: None,
+        };
+        let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","publication":"replicator_publication"}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id"}},"batch":{"max_size":1000,"max_fill_secs":10},"table_overrides":{"public.events":{"destination":"event_log","write_mode":"AppendOnly","partitioning":{"IngestionTime":{"granularity":"day"}},"clustering":["user_id"],"batch":{"max_size":50000}}}}"#;
+        let actual = serde_json::to_string(&actual);
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn serialize_status_report_test() {
+        let actual = Config {
+            source: SourceConfig::Postgres {
+                host: "localhost".to_string(),
+                port: 5432,
+                name: "postgres".to_string(),
+                username: "postgres".to_string(),
+                slot_name: "replicator_slot".to_string(),
+                publication: "replicator_publication".to_string(),
+                tables: None,
+            },
+            sink: SinkConfig::BigQuery {
+                project_id: "project-id".to_string(),
+                dataset_id: "dataset-id".to_string(),
+                naming: None,
+            },
+            batch: BatchConfig {
+                max_size: 1000,
+                max_fill_secs: 10,
+                transactional: false,
+            },
+            table_overrides: BTreeMap::new(),
+            status_report: Some(StatusReportConfig {
+                api_url: "http://api:8000".to_string(),
+                tenant_id: "abcdefghijklmnopqrst".to_string(),
+                pipeline_id: 1,
+            }),
+        };
+        let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","publication":"replicator_publication"}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id"}},"batch":{"max_size":1000,"max_fill_secs":10},"status_report":{"api_url":"http://api:8000","tenant_id":"abcdefghijklmnopqrst","pipeline_id":1}}"#;
+        let actual = serde_json::to_string(&actual);
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
 }
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{
+    configuration::WorkerSettings,
+    db,
+    metrics::{ApiMetrics, Worker},
+};
+
+/// Purges the sources, sinks and pipelines deleted longer ago than the
+/// retention window. Pipelines are purged first, as a source or sink is only
+/// purged once no pipeline uses it.
+async fn purge(pool: &PgPool, retention_secs: i64) -> Result<(), sqlx::Error> {
+    let pipelines = db::pipelines::purge_deleted_pipelines(pool, retention_secs).await?;
+    let sources = db::sources::purge_deleted_sources(pool, retention_secs).await?;
+    let sinks = db::sinks::purge_deleted_sinks(pool, retention_secs).await?;
+    if pipelines + sources + sinks > 0 {
+        info!("purged {pipelines} pipelines, {sources} sources and {sinks} sinks");
+    }
+    Ok(())
+}
+
+/// Purges deleted resources past the retention window every
+/// `poll_interval_secs` until the process exits
+pub async fn run_purger(pool: Data<PgPool>, metrics: Data<ApiMetrics>, settings: WorkerSettings) {
+    let poll_interval = Duration::from_secs(settings.poll_interval_secs);
+    let retention_secs = settings.deleted_retention_secs as i64;
+    loop {
+        metrics.record_heartbeat(Worker::Purger);
+        if let Err(e) = purge(&pool, retention_secs).await {
+            error!("failed to purge deleted resources: {e}");
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
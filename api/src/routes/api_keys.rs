@@ -0,0 +1,203 @@
+use actix_web::{
+    delete, get,
+    http::{header::ContentType, StatusCode},
+    post,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use aws_lc_rs::error::Unspecified;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+    authentication::generate_api_key,
+    db::{self, pagination::PaginationError},
+    routes::extract_tenant_id,
+};
+
+use super::{ErrorMessage, ListQuery, ListResponse, TenantIdError};
+
+#[derive(Debug, Error)]
+enum ApiKeyError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("api key with id {0} not found")]
+    ApiKeyNotFound(i64),
+
+    #[error("tenant id error: {0}")]
+    TenantId(#[from] TenantIdError),
+
+    #[error("failed to generate api key")]
+    KeyGeneration(#[from] Unspecified),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
+}
+
+impl ApiKeyError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            ApiKeyError::DatabaseError(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::DatabaseError(_) | ApiKeyError::KeyGeneration(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiKeyError::ApiKeyNotFound(_) => StatusCode::NOT_FOUND,
+            ApiKeyError::TenantId(_) | ApiKeyError::Pagination(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostApiKeyRequest {
+    #[schema(example = "CI pipeline key", required = true)]
+    pub name: String,
+}
+
+/// The key is only returned when it is issued or rotated, only its hash is
+/// stored
+#[derive(Serialize, ToSchema)]
+pub struct PostApiKeyResponse {
+    id: i64,
+    #[schema(example = "XOUbHmWbt9h7nWl15wWwyWQnctmFGNjpawMc3lT5CFs=")]
+    key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetApiKeyResponse {
+    #[schema(example = 1)]
+    id: i64,
+    #[schema(example = "abcdefghijklmnopqrst")]
+    tenant_id: String,
+    #[schema(example = "CI pipeline key")]
+    name: String,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostApiKeyRequest,
+    responses(
+        (status = 200, description = "Issue a new api key for the tenant", body = PostApiKeyResponse),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/api-keys")]
+pub async fn create_api_key(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    api_key: Json<PostApiKeyRequest>,
+) -> Result<impl Responder, ApiKeyError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let (key, key_hash) = generate_api_key()?;
+    let id = db::api_keys::create_api_key(&pool, tenant_id, &api_key.name, &key_hash).await?;
+    let response = PostApiKeyResponse { id, key };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("api_key_id" = i64, Path, description = "Id of the api key"),
+    ),
+    responses(
+        (status = 200, description = "Replace the key of api key with id = api_key_id", body = PostApiKeyResponse),
+        (status = 404, description = "Api key not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/api-keys/{api_key_id}/rotate")]
+pub async fn rotate_api_key(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    api_key_id: Path<i64>,
+) -> Result<impl Responder, ApiKeyError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let api_key_id = api_key_id.into_inner();
+    let (key, key_hash) = generate_api_key()?;
+    let id = db::api_keys::rotate_api_key(&pool, tenant_id, api_key_id, &key_hash)
+        .await?
+        .ok_or(ApiKeyError::ApiKeyNotFound(api_key_id))?;
+    let response = PostApiKeyResponse { id, key };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("api_key_id" = i64, Path, description = "Id of the api key"),
+    ),
+    responses(
+        (status = 200, description = "Revoke api key with id = api_key_id"),
+        (status = 404, description = "Api key not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[delete("/api-keys/{api_key_id}")]
+pub async fn delete_api_key(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    api_key_id: Path<i64>,
+) -> Result<impl Responder, ApiKeyError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let api_key_id = api_key_id.into_inner();
+    db::api_keys::delete_api_key(&pool, tenant_id, api_key_id)
+        .await?
+        .ok_or(ApiKeyError::ApiKeyNotFound(api_key_id))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "Return a page of the tenant's api keys", body = ApiKeysPage),
+        (status = 400, description = "Invalid list parameters"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/api-keys")]
+pub async fn read_all_api_keys(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    query: Query<ListQuery>,
+) -> Result<impl Responder, ApiKeyError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let params = query.into_inner().into_params()?;
+    let page = db::api_keys::read_all_api_keys(&pool, tenant_id, &params).await?;
+    let mut api_keys = vec![];
+    for api_key in page.items {
+        let api_key = GetApiKeyResponse {
+            id: api_key.id,
+            tenant_id: api_key.tenant_id,
+            name: api_key.name,
+        };
+        api_keys.push(api_key);
+    }
+    Ok(Json(ListResponse {
+        items: api_keys,
+        next_cursor: page.next_cursor,
+    }))
+}
@@ -0,0 +1,310 @@
+use actix_web::{
+    get,
+    http::{header::ContentType, StatusCode},
+    web::{Data, Json, Path, Query},
+    HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    authentication::Actor,
+    db::{
+        self,
+        audit_logs::{AuditAction, AuditLog, AuditLogFilter, AuditResource},
+        pagination::{ListParams, PaginationError, SortField, SortOrder},
+    },
+};
+
+use super::{ErrorMessage, ListResponse};
+
+#[derive(Debug, Error)]
+enum AuditError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("invalid audit log with id {0} in db")]
+    InvalidAuditLog(i64),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
+}
+
+impl AuditError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            AuditError::DatabaseError(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for AuditError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuditError::DatabaseError(_) | AuditError::InvalidAuditLog(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AuditError::Pagination(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+/// Query parameters of the audit log endpoint
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// `next_cursor` of the previous page, to read the page after it
+    cursor: Option<String>,
+    /// Maximum number of changes in the page, 50 by default and at most 200
+    limit: Option<i64>,
+    /// Only changes of this kind of resource
+    resource_type: Option<AuditResource>,
+    /// Only changes of the resource with this id
+    resource_id: Option<String>,
+    /// Only changes of this kind
+    action: Option<AuditAction>,
+    /// Only changes made by this actor, e.g. `admin`, `api_key:1` or `user:<subject>`
+    actor: Option<String>,
+    /// Only changes made at or after this time, in seconds since the unix epoch
+    since: Option<i64>,
+    /// Only changes made before this time, in seconds since the unix epoch
+    until: Option<i64>,
+}
+
+/// A changed field of a resource, `before` is null for added fields and
+/// `after` is null for removed ones
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct AuditChange {
+    /// Dot separated path of the field
+    #[schema(example = "config.postgres.host")]
+    path: String,
+    before: Value,
+    after: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetAuditLogResponse {
+    #[schema(example = 1)]
+    id: i64,
+    #[schema(example = "abcdefghijklmnopqrst")]
+    tenant_id: String,
+    /// Who made the change: `admin`, `api_key:<id>` or `user:<subject>`
+    #[schema(example = "api_key:1")]
+    actor: String,
+    action: AuditAction,
+    resource_type: AuditResource,
+    #[schema(example = "1")]
+    resource_id: String,
+    /// The resource before the change, not set for creates
+    before: Option<Value>,
+    /// The resource after the change, not set for deletes
+    after: Option<Value>,
+    /// Fields which differ between `before` and `after`
+    changes: Vec<AuditChange>,
+    /// Time of the change, in seconds since the unix epoch
+    #[schema(example = 1_729_328_400)]
+    created_at: i64,
+}
+
+/// Collects the leaf fields which differ between `before` and `after` into
+/// `changes`. Objects are compared field by field, any other values as a
+/// whole.
+fn diff(path: &str, before: &Value, after: &Value, changes: &mut Vec<AuditChange>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff(
+                    &path,
+                    before.get(key).unwrap_or(&Value::Null),
+                    after.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        // Treat a missing resource as an empty one so that each of its
+        // fields is listed as added or removed
+        (Value::Null, Value::Object(_)) if path.is_empty() => {
+            diff(path, &Value::Object(Default::default()), after, changes)
+        }
+        (Value::Object(_), Value::Null) if path.is_empty() => {
+            diff(path, before, &Value::Object(Default::default()), changes)
+        }
+        (before, after) if before != after => changes.push(AuditChange {
+            path: path.to_string(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn audit_log_response(audit_log: AuditLog) -> Result<GetAuditLogResponse, AuditError> {
+    let action =
+        AuditAction::parse(&audit_log.action).ok_or(AuditError::InvalidAuditLog(audit_log.id))?;
+    let resource_type = AuditResource::parse(&audit_log.resource_type)
+        .ok_or(AuditError::InvalidAuditLog(audit_log.id))?;
+    let mut changes = vec![];
+    diff(
+        "",
+        audit_log.before.as_ref().unwrap_or(&Value::Null),
+        audit_log.after.as_ref().unwrap_or(&Value::Null),
+        &mut changes,
+    );
+    Ok(GetAuditLogResponse {
+        id: audit_log.id,
+        tenant_id: audit_log.tenant_id,
+        actor: audit_log.actor,
+        action,
+        resource_type,
+        resource_id: audit_log.resource_id,
+        before: audit_log.before,
+        after: audit_log.after,
+        changes,
+        created_at: audit_log.created_at,
+    })
+}
+
+/// Records a change of a resource made by the request's actor. The action
+/// follows from the snapshots: no `before` is a create and no `after` a
+/// delete.
+pub(super) async fn record_change(
+    req: &HttpRequest,
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    resource_type: AuditResource,
+    resource_id: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), sqlx::Error> {
+    let action = match (&before, &after) {
+        (None, _) => AuditAction::Create,
+        (_, None) => AuditAction::Delete,
+        _ => AuditAction::Update,
+    };
+    let actor = req
+        .extensions()
+        .get::<Actor>()
+        .map(|actor| actor.0.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+    db::audit_logs::create_audit_log(
+        executor,
+        tenant_id,
+        &actor,
+        action,
+        resource_type,
+        resource_id,
+        before.as_ref(),
+        after.as_ref(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("tenant_id" = String, Path, description = "Id of the tenant"),
+        AuditQuery,
+    ),
+    responses(
+        (status = 200, description = "Return a page of the tenant's configuration changes, newest first", body = AuditLogsPage),
+        (status = 400, description = "Invalid list parameters"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/tenants/{tenant_id}/audit")]
+pub async fn read_audit_logs(
+    pool: Data<PgPool>,
+    tenant_id: Path<String>,
+    query: Query<AuditQuery>,
+) -> Result<impl Responder, AuditError> {
+    let tenant_id = tenant_id.into_inner();
+    let query = query.into_inner();
+    let params: ListParams<i64> = ListParams::new(
+        None,
+        Some(SortField::Id),
+        Some(SortOrder::Desc),
+        query.cursor.as_deref(),
+        query.limit,
+    )?;
+    let filter = AuditLogFilter {
+        resource_type: query.resource_type,
+        resource_id: query.resource_id,
+        action: query.action,
+        actor: query.actor,
+        since: query.since,
+        until: query.until,
+    };
+    let page = db::audit_logs::read_audit_logs(&pool, &tenant_id, &filter, &params).await?;
+    let items = page
+        .items
+        .into_iter()
+        .map(audit_log_response)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Json(ListResponse {
+        items,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{diff, AuditChange};
+
+    fn changes(before: Value, after: Value) -> Vec<AuditChange> {
+        let mut changes = vec![];
+        diff("", &before, &after, &mut changes);
+        changes
+    }
+
+    #[test]
+    fn diff_lists_changed_nested_fields() {
+        let before = json!({"name": "a", "config": {"host": "h1", "port": 5432}});
+        let after = json!({"name": "a", "config": {"host": "h2", "port": 5432}});
+        assert_eq!(
+            changes(before, after),
+            vec![AuditChange {
+                path: "config.host".to_string(),
+                before: json!("h1"),
+                after: json!("h2"),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_of_a_created_resource_lists_all_fields() {
+        let after = json!({"name": "a", "config": {"port": 5432}});
+        let paths: Vec<String> = changes(Value::Null, after)
+            .into_iter()
+            .map(|change| change.path)
+            .collect();
+        assert_eq!(paths, vec!["config.port", "name"]);
+    }
+}
@@ -2,7 +2,7 @@ use actix_web::{
     delete, get,
     http::{header::ContentType, StatusCode},
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
@@ -10,9 +10,9 @@ use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use crate::db;
+use crate::db::{self, pagination::PaginationError};
 
-use super::ErrorMessage;
+use super::{ErrorMessage, ListQuery, ListResponse};
 
 #[derive(Debug, Error)]
 enum ImageError {
@@ -21,6 +21,9 @@ enum ImageError {
 
     #[error("source with id {0} not found")]
     ImageNotFound(i64),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
 }
 
 impl ImageError {
@@ -39,6 +42,7 @@ impl ResponseError for ImageError {
         match self {
             ImageError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ImageError::ImageNotFound(_) => StatusCode::NOT_FOUND,
+            ImageError::Pagination(_) => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -171,21 +175,31 @@ pub async fn delete_image(
 
 #[utoipa::path(
     context_path = "/v1",
+    params(ListQuery),
     responses(
-        (status = 200, description = "Return all images"),
+        (status = 200, description = "Return a page of images", body = ImagesPage),
+        (status = 400, description = "Invalid list parameters"),
         (status = 500, description = "Internal server error")
     )
 )]
 #[get("/images")]
-pub async fn read_all_images(pool: Data<PgPool>) -> Result<impl Responder, ImageError> {
-    let mut sources = vec![];
-    for image in db::images::read_all_images(&pool).await? {
+pub async fn read_all_images(
+    pool: Data<PgPool>,
+    query: Query<ListQuery>,
+) -> Result<impl Responder, ImageError> {
+    let params = query.into_inner().into_params()?;
+    let page = db::images::read_all_images(&pool, &params).await?;
+    let mut images = vec![];
+    for image in page.items {
         let image = GetImageResponse {
             id: image.id,
             name: image.name,
             is_default: image.is_default,
         };
-        sources.push(image);
+        images.push(image);
     }
-    Ok(Json(sources))
+    Ok(Json(ListResponse {
+        items: images,
+        next_cursor: page.next_cursor,
+    }))
 }
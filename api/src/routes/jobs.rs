@@ -0,0 +1,281 @@
+use actix_web::{
+    get,
+    http::{header::ContentType, StatusCode},
+    post,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{
+        self,
+        jobs::{Job, JobParams, JobStatus},
+        sinks::sink_exists,
+    },
+    routes::extract_tenant_id,
+};
+
+use super::{ErrorMessage, TenantIdError};
+
+#[derive(Debug, Error)]
+enum JobError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("job with id {0} not found")]
+    JobNotFound(i64),
+
+    #[error("sink with id {0} not found")]
+    SinkNotFound(i64),
+
+    #[error("pipeline with id {0} not found")]
+    PipelineNotFound(i64),
+
+    #[error("invalid job with id {0} in db")]
+    InvalidJob(i64),
+
+    #[error("invalid resync request: {0}")]
+    InvalidResync(String),
+
+    #[error("tenant id error: {0}")]
+    TenantId(#[from] TenantIdError),
+}
+
+impl JobError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            JobError::DatabaseError(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for JobError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            JobError::DatabaseError(_) | JobError::InvalidJob(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            JobError::JobNotFound(_)
+            | JobError::SinkNotFound(_)
+            | JobError::PipelineNotFound(_) => StatusCode::NOT_FOUND,
+            JobError::InvalidResync(_) | JobError::TenantId(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostResyncRequest {
+    /// Tables to copy again, as `schema.table`
+    #[schema(example = json!(["public.users"]))]
+    tables: Vec<String>,
+}
+
+/// The queued job, whose progress is polled with `GET /v1/jobs/{id}`
+#[derive(Serialize, ToSchema)]
+pub struct PostJobResponse {
+    #[schema(example = 1)]
+    id: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetJobResponse {
+    #[schema(example = 1)]
+    id: i64,
+    #[schema(example = "abcdefghijklmnopqrst")]
+    tenant_id: String,
+    params: JobParams,
+    status: JobStatus,
+    /// Progress of a running job, e.g. the tables copied so far
+    progress: Option<Value>,
+    /// Result of a succeeded job, e.g. the checks of a sink validation
+    result: Option<Value>,
+    /// Why a failed job failed
+    error: Option<String>,
+    /// Time the job was queued, in seconds since the unix epoch
+    #[schema(example = 1_729_328_400)]
+    created_at: i64,
+    /// Time the job started running, in seconds since the unix epoch
+    started_at: Option<i64>,
+    /// Time the job succeeded or failed, in seconds since the unix epoch
+    finished_at: Option<i64>,
+}
+
+fn job_response(job: Job) -> Result<GetJobResponse, JobError> {
+    let params = serde_json::from_value(job.params).map_err(|_| JobError::InvalidJob(job.id))?;
+    let status = JobStatus::parse(&job.status).ok_or(JobError::InvalidJob(job.id))?;
+    Ok(GetJobResponse {
+        id: job.id,
+        tenant_id: job.tenant_id,
+        params,
+        status,
+        progress: job.progress,
+        result: job.result,
+        error: job.error,
+        created_at: job.created_at,
+        started_at: job.started_at,
+        finished_at: job.finished_at,
+    })
+}
+
+async fn queue_job(
+    pool: &PgPool,
+    tenant_id: &str,
+    params: JobParams,
+) -> Result<HttpResponse, JobError> {
+    let id = db::jobs::create_job(pool, tenant_id, &params).await?;
+    Ok(HttpResponse::Accepted().json(PostJobResponse { id }))
+}
+
+async fn check_pipeline_exists(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<(), JobError> {
+    db::pipelines::read_pipeline(pool, tenant_id, pipeline_id)
+        .await?
+        .ok_or(JobError::PipelineNotFound(pipeline_id))?;
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("sink_id" = i64, Path, description = "Id of the sink"),
+    ),
+    responses(
+        (status = 202, description = "Queue a job testing the connection to the sink", body = PostJobResponse),
+        (status = 404, description = "Sink not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sinks/{sink_id}/validate")]
+pub async fn validate_sink(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    sink_id: Path<i64>,
+) -> Result<impl Responder, JobError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let sink_id = sink_id.into_inner();
+
+    if !sink_exists(pool.get_ref(), tenant_id, sink_id).await? {
+        return Err(JobError::SinkNotFound(sink_id));
+    }
+
+    queue_job(&pool, tenant_id, JobParams::SinkValidation { sink_id }).await
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 202, description = "Queue a job starting the pipeline and following it until it copied its tables", body = PostJobResponse),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/initial-copy")]
+pub async fn initial_copy(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, JobError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    check_pipeline_exists(&pool, tenant_id, pipeline_id).await?;
+
+    queue_job(&pool, tenant_id, JobParams::InitialCopy { pipeline_id }).await
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostResyncRequest,
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 202, description = "Queue a job copying tables of the pipeline again", body = PostJobResponse),
+        (status = 400, description = "No or invalid tables"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/resync")]
+pub async fn resync_tables(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+    resync: Json<PostResyncRequest>,
+) -> Result<impl Responder, JobError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+    let tables = resync.into_inner().tables;
+
+    if tables.is_empty() {
+        return Err(JobError::InvalidResync("no tables given".to_string()));
+    }
+    if let Some(table) = tables.iter().find(|table| !table.contains('.')) {
+        return Err(JobError::InvalidResync(format!(
+            "table {table} is not in the schema.table form"
+        )));
+    }
+    check_pipeline_exists(&pool, tenant_id, pipeline_id).await?;
+
+    queue_job(
+        &pool,
+        tenant_id,
+        JobParams::TableResync {
+            pipeline_id,
+            tables,
+        },
+    )
+    .await
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("job_id" = i64, Path, description = "Id of the job"),
+    ),
+    responses(
+        (status = 200, description = "Return job with id = job_id", body = GetJobResponse),
+        (status = 404, description = "Job not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/jobs/{job_id}")]
+pub async fn read_job(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    job_id: Path<i64>,
+) -> Result<impl Responder, JobError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let job_id = job_id.into_inner();
+
+    let job = db::jobs::read_job(&pool, tenant_id, job_id)
+        .await?
+        .ok_or(JobError::JobNotFound(job_id))?;
+
+    Ok(Json(job_response(job)?))
+}
@@ -0,0 +1,53 @@
+use actix_web::{
+    get,
+    http::{header::ContentType, StatusCode},
+    web::Data,
+    HttpResponse, Responder, ResponseError,
+};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use prometheus::TEXT_FORMAT;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::auth_validator,
+    metrics::{ApiMetrics, MetricsError},
+};
+
+use super::ErrorMessage;
+
+impl ResponseError for MetricsError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Do not expose internal database details in error messages
+        let error_message = ErrorMessage {
+            error: "internal server error".to_string(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Return the metrics of the api in the prometheus text format: request latencies, database pool connections, pipelines by status, the time since each background worker last ran, the lag of each pipeline and the changes, conversion errors and sink latencies of the tables of each pipeline", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or invalid api key"),
+        (status = 403, description = "Only admins not restricted to a tenant can read the metrics"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+// The series are labelled with the pipelines and tables of every tenant, so
+// only admins not restricted to a tenant can read them
+#[get("/metrics", wrap = "HttpAuthentication::bearer(auth_validator)")]
+pub async fn read_metrics(
+    pool: Data<PgPool>,
+    metrics: Data<ApiMetrics>,
+) -> Result<impl Responder, MetricsError> {
+    let body = metrics.render(&pool).await?;
+    Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body))
+}
@@ -1,19 +1,66 @@
-use actix_web::HttpRequest;
-use serde::Serialize;
+use actix_web::{http::StatusCode, HttpRequest};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{Encode, Postgres, Type};
 use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
 
+use crate::db::pagination::{ListParams, PaginationError, SortField, SortOrder};
+
+use self::{
+    api_keys::GetApiKeyResponse,
+    audit::GetAuditLogResponse,
+    images::GetImageResponse,
+    pipelines::{GetPipelineResponse, PostPipelineRequest},
+    sinks::{GetSinkResponse, PostSinkRequest},
+    sources::{GetSourceResponse, PostSourceRequest},
+    tenants::GetTenantResponse,
+    webhooks::GetWebhookResponse,
+};
+
+pub mod api_keys;
+pub mod audit;
 pub mod health_check;
 pub mod images;
+pub mod jobs;
+pub mod metrics;
 pub mod pipelines;
+pub mod quotas;
 pub mod sinks;
 pub mod sources;
 pub mod tenants;
+pub mod webhooks;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ErrorMessage {
     pub error: String,
 }
 
+/// An invalid field of a request body
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Path of the field in the body
+    #[schema(example = "config.config.max_size")]
+    pub field: String,
+    #[schema(example = "must be greater than 0")]
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Error returned when fields of a request body are invalid
+#[derive(Serialize, ToSchema)]
+pub struct ValidationErrorMessage {
+    pub error: String,
+    pub fields: Vec<FieldError>,
+}
+
 #[derive(Debug, Error)]
 enum TenantIdError {
     #[error("tenant id missing in request")]
@@ -33,3 +80,129 @@ fn extract_tenant_id(req: &HttpRequest) -> Result<&str, TenantIdError> {
         .map_err(|_| TenantIdError::TenantIdIllFormed)?;
     Ok(tenant_id)
 }
+
+/// A page of the items of a list endpoint
+#[derive(Serialize, ToSchema)]
+#[aliases(
+    ApiKeysPage = ListResponse<GetApiKeyResponse>,
+    AuditLogsPage = ListResponse<GetAuditLogResponse>,
+    ImagesPage = ListResponse<GetImageResponse>,
+    PipelinesPage = ListResponse<GetPipelineResponse>,
+    SinksPage = ListResponse<GetSinkResponse>,
+    SourcesPage = ListResponse<GetSourceResponse>,
+    TenantsPage = ListResponse<GetTenantResponse>,
+    WebhooksPage = ListResponse<GetWebhookResponse>,
+)]
+pub struct ListResponse<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, not set on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Maximum number of items of a batch request
+const MAX_BATCH_ITEMS: usize = 100;
+
+/// An item of a batch request, which updates the resource with `id` or,
+/// without one, creates a new resource
+#[derive(Deserialize, ToSchema)]
+#[aliases(
+    PipelineBatchItem = BatchItem<PostPipelineRequest>,
+    SinkBatchItem = BatchItem<PostSinkRequest>,
+    SourceBatchItem = BatchItem<PostSourceRequest>,
+)]
+pub struct BatchItem<T> {
+    /// Id of the resource to update, unset to create a new one
+    pub id: Option<i64>,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// Result of an item of a batch request
+#[derive(Serialize, ToSchema)]
+pub struct BatchItemResult {
+    /// Position of the item in the request
+    #[schema(example = 0)]
+    pub index: usize,
+    /// Id of the created or updated resource, unset if the item failed
+    #[schema(example = 1)]
+    pub id: Option<i64>,
+    /// Status code of the item, as if it had been sent on its own
+    #[schema(example = 200)]
+    pub status: u16,
+    /// Why the item failed
+    pub error: Option<String>,
+}
+
+impl BatchItemResult {
+    fn succeeded(index: usize, id: i64) -> BatchItemResult {
+        BatchItemResult {
+            index,
+            id: Some(id),
+            status: StatusCode::OK.as_u16(),
+            error: None,
+        }
+    }
+
+    fn failed(index: usize, status: StatusCode, error: String) -> BatchItemResult {
+        BatchItemResult {
+            index,
+            id: None,
+            status: status.as_u16(),
+            error: Some(error),
+        }
+    }
+}
+
+/// Results of the items of a batch request, in the order of the items
+#[derive(Serialize, ToSchema)]
+pub struct BatchResponse {
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Error)]
+#[error("batch of {0} items exceeds the maximum of {} items", MAX_BATCH_ITEMS)]
+pub struct BatchTooLarge(usize);
+
+fn check_batch_size(items: usize) -> Result<(), BatchTooLarge> {
+    if items > MAX_BATCH_ITEMS {
+        return Err(BatchTooLarge(items));
+    }
+    Ok(())
+}
+
+/// Query parameters of the list endpoints
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    /// `next_cursor` of the previous page, to read the page after it
+    cursor: Option<String>,
+    /// Maximum number of items in the page, 50 by default and at most 200
+    limit: Option<i64>,
+    /// Only items whose name contains this, ignoring case
+    name: Option<String>,
+    /// Field to sort by, id by default
+    sort: Option<SortField>,
+    /// Sort order, ascending by default
+    order: Option<SortOrder>,
+}
+
+impl ListQuery {
+    fn into_params<Id>(self) -> Result<ListParams<Id>, PaginationError>
+    where
+        Id: Serialize
+            + DeserializeOwned
+            + Clone
+            + Send
+            + for<'q> Encode<'q, Postgres>
+            + Type<Postgres>
+            + 'static,
+    {
+        ListParams::new(
+            self.name,
+            self.sort,
+            self.order,
+            self.cursor.as_deref(),
+            self.limit,
+        )
+    }
+}
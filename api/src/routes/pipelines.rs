@@ -1,48 +1,78 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use actix_web::{
     delete, get,
     http::{header::ContentType, StatusCode},
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
+use aws_lc_rs::error::Unspecified;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use thiserror::Error;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
+    authentication::generate_api_key,
     db::{
         self,
+        audit_logs::AuditResource,
         images::Image,
-        pipelines::{Pipeline, PipelineConfig},
-        replicators::Replicator,
+        pagination::PaginationError,
+        pipeline_statuses::PipelineStatusReport,
+        pipelines::{
+            PartitionGranularity, Pipeline, PipelineConfig, PipelineCreation, TableOverrides,
+            TablePartitioning, WriteMode,
+        },
+        quotas::TenantQuotas,
+        replicators::{Replicator, ReplicatorStatus},
         sinks::{sink_exists, Sink, SinkConfig, SinksDbError},
         sources::{source_exists, Source, SourceConfig, SourcesDbError},
     },
     encryption::EncryptionKey,
+    health::PipelineHealth,
     k8s_client::{HttpK8sClient, K8sClient, K8sError, PodPhase},
+    orchestrator::{deploy_replicator, replicator_prefix, teardown_replicator, Secrets},
     replicator_config,
     routes::extract_tenant_id,
+    webhooks::{self, WebhookEvent},
 };
 
-use super::{ErrorMessage, TenantIdError};
+use super::{
+    audit::record_change,
+    check_batch_size,
+    quotas::{check_quota, Quota, QuotaExceeded},
+    BatchItem, BatchItemResult, BatchResponse, BatchTooLarge, ErrorMessage, FieldError, ListQuery,
+    ListResponse, TenantIdError, ValidationErrorMessage,
+};
 
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct Secrets {
-    pub postgres_password: String,
-    pub bigquery_service_account_key: String,
-}
+pub mod events;
+pub mod images;
 
 #[derive(Debug, Error)]
-enum PipelineError {
+pub(crate) enum PipelineError {
     #[error("database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
     #[error("pipeline with id {0} not found")]
     PipelineNotFound(i64),
 
+    #[error("version {0} of the pipeline not found")]
+    VersionNotFound(i32),
+
+    #[error("deleted pipeline with id {0} not found")]
+    DeletedPipelineNotFound(i64),
+
+    #[error("source with id {0} of the pipeline is deleted")]
+    SourceDeleted(i64),
+
+    #[error("sink with id {0} of the pipeline is deleted")]
+    SinkDeleted(i64),
+
+    #[error("kubernetes client not configured")]
+    K8sClientMissing,
+
     #[error("source with id {0} not found")]
     SourceNotFound(i64),
 
@@ -58,6 +88,9 @@ enum PipelineError {
     #[error("no default image found")]
     NoDefaultImageFound,
 
+    #[error("image with id {0} not found")]
+    RequestedImageNotFound(i64),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
@@ -67,11 +100,41 @@ enum PipelineError {
     #[error("k8s error: {0}")]
     K8sError(#[from] K8sError),
 
+    #[error("failed to generate api key")]
+    KeyGeneration(#[from] Unspecified),
+
     #[error("sources db error: {0}")]
     SourcesDb(#[from] SourcesDbError),
 
     #[error("sinks db error: {0}")]
     SinksDb(#[from] SinksDbError),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
+
+    #[error("publication {0} not found in the source")]
+    PublicationNotFound(String),
+
+    #[error("a pipeline from source {0} to sink {1} already exists")]
+    DuplicatePipeline(i64, i64),
+
+    #[error(
+        "idempotency key must be a non-empty string of at most {} characters",
+        MAX_IDEMPOTENCY_KEY_LEN
+    )]
+    InvalidIdempotencyKey,
+
+    #[error(transparent)]
+    QuotaExceeded(#[from] QuotaExceeded),
+
+    #[error(transparent)]
+    BatchTooLarge(#[from] BatchTooLarge),
+
+    #[error(
+        "invalid pipeline: {}",
+        .0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join(", ")
+    )]
+    Validation(Vec<FieldError>),
 }
 
 impl PipelineError {
@@ -95,20 +158,40 @@ impl ResponseError for PipelineError {
             | PipelineError::NoDefaultImageFound
             | PipelineError::SourcesDb(_)
             | PipelineError::SinksDb(_)
-            | PipelineError::K8sError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            PipelineError::PipelineNotFound(_) => StatusCode::NOT_FOUND,
+            | PipelineError::K8sClientMissing
+            | PipelineError::K8sError(_)
+            | PipelineError::KeyGeneration(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PipelineError::PipelineNotFound(_)
+            | PipelineError::VersionNotFound(_)
+            | PipelineError::DeletedPipelineNotFound(_) => StatusCode::NOT_FOUND,
             PipelineError::TenantId(_)
+            | PipelineError::Pagination(_)
             | PipelineError::SourceNotFound(_)
-            | PipelineError::SinkNotFound(_) => StatusCode::BAD_REQUEST,
+            | PipelineError::SinkNotFound(_)
+            | PipelineError::PublicationNotFound(_)
+            | PipelineError::RequestedImageNotFound(_)
+            | PipelineError::InvalidIdempotencyKey
+            | PipelineError::BatchTooLarge(_) => StatusCode::BAD_REQUEST,
+            PipelineError::DuplicatePipeline(_, _)
+            | PipelineError::SourceDeleted(_)
+            | PipelineError::SinkDeleted(_) => StatusCode::CONFLICT,
+            PipelineError::QuotaExceeded(e) => e.status_code(),
+            PipelineError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
+        let body = match self {
+            PipelineError::QuotaExceeded(e) => return e.error_response(),
+            PipelineError::Validation(fields) => serde_json::to_string(&ValidationErrorMessage {
+                error: self.to_message(),
+                fields: fields.clone(),
+            }),
+            _ => serde_json::to_string(&ErrorMessage {
+                error: self.to_message(),
+            }),
         };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        let body = body.expect("failed to serialize error message");
         HttpResponse::build(self.status_code())
             .insert_header(ContentType::json())
             .body(body)
@@ -126,6 +209,9 @@ pub struct PostPipelineRequest {
 #[derive(Serialize, ToSchema)]
 pub struct PostPipelineResponse {
     id: i64,
+    /// False if an existing pipeline was returned, because it was created
+    /// with the same idempotency key or has the same source and sink
+    created: bool,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -141,48 +227,402 @@ pub struct GetPipelineResponse {
     config: PipelineConfig,
 }
 
-#[utoipa::path(
-    context_path = "/v1",
-    request_body = PostPipelineRequest,
-    responses(
-        (status = 200, description = "Create new pipeline", body = PostPipelineResponse),
-        (status = 500, description = "Internal server error")
-    )
-)]
-#[post("/pipelines")]
-pub async fn create_pipeline(
-    req: HttpRequest,
-    pool: Data<PgPool>,
-    pipeline: Json<PostPipelineRequest>,
-) -> Result<impl Responder, PipelineError> {
-    let pipeline = pipeline.0;
-    let tenant_id = extract_tenant_id(&req)?;
-    let config = pipeline.config;
+/// The pipeline as recorded in the audit log
+async fn read_pipeline_snapshot(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    let snapshot = db::pipelines::read_pipeline(executor, tenant_id, pipeline_id)
+        .await?
+        .map(|p| {
+            serde_json::json!({
+                "source_id": p.source_id,
+                "sink_id": p.sink_id,
+                "publication_name": p.publication_name,
+                "config": p.config,
+            })
+        });
+    Ok(snapshot)
+}
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+fn extract_idempotency_key(req: &HttpRequest) -> Result<Option<&str>, PipelineError> {
+    let Some(key) = req.headers().get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => Ok(Some(key)),
+        _ => Err(PipelineError::InvalidIdempotencyKey),
+    }
+}
+
+/// Maps the unique violation of updating a pipeline to the source and sink
+/// of another one
+fn duplicate_pipeline_error(e: sqlx::Error, source_id: i64, sink_id: i64) -> PipelineError {
+    match e {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+            PipelineError::DuplicatePipeline(source_id, sink_id)
+        }
+        e => e.into(),
+    }
+}
+
+/// Validates a created or updated pipeline, collecting every invalid field
+/// rather than stopping at the first. Its source's replication slot and its
+/// publication mustn't be used by another started pipeline replicating from
+/// the same database.
+async fn validate_pipeline(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    pipeline_id: Option<i64>,
+    pipeline: &PostPipelineRequest,
+) -> Result<(), PipelineError> {
+    let mut errors = vec![];
+
+    let source_found = source_exists(&mut **txn, tenant_id, pipeline.source_id).await?;
+    if !source_found {
+        errors.push(FieldError::new(
+            "source_id",
+            format!("source with id {} not found", pipeline.source_id),
+        ));
+    }
+    if !sink_exists(&mut **txn, tenant_id, pipeline.sink_id).await? {
+        errors.push(FieldError::new(
+            "sink_id",
+            format!("sink with id {} not found", pipeline.sink_id),
+        ));
+    }
+    if pipeline.publication_name.is_empty() {
+        errors.push(FieldError::new("publication_name", "must not be empty"));
+    }
+
+    let batch_config = &pipeline.config.config;
+    if batch_config.max_size == 0 {
+        errors.push(FieldError::new(
+            "config.config.max_size",
+            "must be greater than 0",
+        ));
+    }
+    if batch_config.max_fill_secs == 0 {
+        errors.push(FieldError::new(
+            "config.config.max_fill_secs",
+            "must be greater than 0",
+        ));
+    }
+    if let Some(Err(e)) = pipeline
+        .config
+        .tables
+        .as_ref()
+        .map(|tables| tables.validate())
+    {
+        errors.push(FieldError::new("config.tables", e));
+    }
+    for (source_table, overrides) in &pipeline.config.overrides {
+        let field = format!("config.overrides.{source_table}");
+        if source_table.split_once('.').is_none() {
+            errors.push(FieldError::new(&field, "is not in schema.name form"));
+            continue;
+        }
+        if let Err(e) = overrides.validate() {
+            errors.push(FieldError::new(&field, e));
+            continue;
+        }
+        let mapped = pipeline
+            .config
+            .tables
+            .as_ref()
+            .is_some_and(|tables| tables.mappings.contains_key(source_table));
+        if overrides.destination.is_some() && mapped {
+            errors.push(FieldError::new(
+                &field,
+                "destination conflicts with the table's mapping in config.tables",
+            ));
+        }
+    }
+
+    if source_found {
+        let conflicting_pipelines = db::pipelines::read_conflicting_pipelines(
+            &mut **txn,
+            tenant_id,
+            pipeline_id,
+            pipeline.source_id,
+            &pipeline.publication_name,
+        )
+        .await?;
+        for conflicting_pipeline in conflicting_pipelines {
+            if conflicting_pipeline.shares_slot {
+                errors.push(FieldError::new(
+                    "source_id",
+                    format!(
+                        "replication slot of the source is used by started pipeline {}",
+                        conflicting_pipeline.id
+                    ),
+                ));
+            }
+            if conflicting_pipeline.publication_name == pipeline.publication_name {
+                errors.push(FieldError::new(
+                    "publication_name",
+                    format!(
+                        "publication is used by started pipeline {} of the same database",
+                        conflicting_pipeline.id
+                    ),
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PipelineError::Validation(errors))
+    }
+}
 
-    if !source_exists(&pool, tenant_id, pipeline.source_id).await? {
-        return Err(PipelineError::SourceNotFound(pipeline.source_id));
+/// Checks that the tables of the publication selected by the config don't
+/// exceed the tenant's tables quota. They are only read from the source if
+/// the include patterns alone could select too many.
+async fn check_tables_quota(
+    executor: impl PgExecutor<'_>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    quotas: &TenantQuotas,
+    source_id: i64,
+    publication_name: &str,
+    config: &PipelineConfig,
+) -> Result<(), PipelineError> {
+    let Some(max_tables) = quotas.max_tables_per_pipeline else {
+        return Ok(());
+    };
+    let selection = config.tables.as_ref();
+    if let Some(selection) = selection {
+        let only_table_names = !selection.include.is_empty()
+            && selection
+                .include
+                .iter()
+                .all(|pattern| !pattern.contains('*'));
+        if only_table_names && selection.include.len() as i64 <= max_tables as i64 {
+            return Ok(());
+        }
     }
 
-    if !sink_exists(&pool, tenant_id, pipeline.sink_id).await? {
-        return Err(PipelineError::SinkNotFound(pipeline.sink_id));
+    let source = db::sources::read_source(executor, tenant_id, source_id, encryption_key)
+        .await?
+        .ok_or(PipelineError::SourceNotFound(source_id))?;
+    let options = source.config.connect_options();
+    let publication = db::publications::read_publication(publication_name, &options)
+        .await?
+        .ok_or(PipelineError::PublicationNotFound(
+            publication_name.to_string(),
+        ))?;
+    let tables = publication
+        .tables
+        .iter()
+        .filter(|table| {
+            selection.map_or(true, |selection| {
+                selection.selects(&format!("{}.{}", table.schema, table.name))
+            })
+        })
+        .count();
+
+    check_quota(quotas, Quota::MaxTablesPerPipeline, tables as i64)?;
+    Ok(())
+}
+
+/// Creates a pipeline in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config. Returns its id and
+/// whether it was created rather than an existing one returned.
+pub(super) async fn create_pipeline_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    idempotency_key: Option<&str>,
+    pipeline: PostPipelineRequest,
+) -> Result<(i64, bool), PipelineError> {
+    // a retried request returns the pipeline before it's validated, as it
+    // would conflict with itself, and before the quotas it counts towards
+    // are checked
+    if let Some(id) = db::pipelines::read_existing_pipeline_id(
+        &mut **txn,
+        tenant_id,
+        pipeline.source_id,
+        pipeline.sink_id,
+        idempotency_key,
+    )
+    .await?
+    {
+        return Ok((id, false));
     }
 
-    let image = db::images::read_default_image(&pool)
+    validate_pipeline(txn, tenant_id, None, &pipeline).await?;
+    let config = pipeline.config;
+
+    let quotas = db::quotas::lock_quotas(txn, tenant_id).await?;
+    let pipelines = db::quotas::count_pipelines(&mut **txn, tenant_id).await?;
+    check_quota(&quotas, Quota::MaxPipelines, pipelines + 1)?;
+    check_tables_quota(
+        &mut **txn,
+        encryption_key,
+        tenant_id,
+        &quotas,
+        pipeline.source_id,
+        &pipeline.publication_name,
+        &config,
+    )
+    .await?;
+
+    let image = db::images::read_default_image(&mut **txn)
         .await?
         .ok_or(PipelineError::NoDefaultImageFound)?;
 
-    let id = db::pipelines::create_pipeline(
-        &pool,
+    let creation = db::pipelines::create_pipeline_txn(
+        txn,
         tenant_id,
         pipeline.source_id,
         pipeline.sink_id,
         image.id,
         pipeline.publication_name,
         &config,
+        idempotency_key,
+    )
+    .await?;
+    let id = match creation {
+        PipelineCreation::Created(id) => id,
+        PipelineCreation::Existing(id) => return Ok((id, false)),
+    };
+    let after = read_pipeline_snapshot(&mut **txn, tenant_id, id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Pipeline,
+        &id.to_string(),
+        None,
+        after,
+    )
+    .await?;
+
+    Ok((id, true))
+}
+
+/// Updates a pipeline in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config
+pub(super) async fn update_pipeline_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    pipeline_id: i64,
+    pipeline: PostPipelineRequest,
+) -> Result<(), PipelineError> {
+    validate_pipeline(txn, tenant_id, Some(pipeline_id), &pipeline).await?;
+    let config = &pipeline.config;
+    let source_id = pipeline.source_id;
+    let sink_id = pipeline.sink_id;
+    let publication_name = pipeline.publication_name;
+
+    let quotas = db::quotas::read_quotas(&mut **txn, tenant_id).await?;
+    check_tables_quota(
+        &mut **txn,
+        encryption_key,
+        tenant_id,
+        &quotas,
+        source_id,
+        &publication_name,
+        config,
+    )
+    .await?;
+
+    let before = read_pipeline_snapshot(&mut **txn, tenant_id, pipeline_id).await?;
+    db::pipelines::update_pipeline_txn(
+        txn,
+        tenant_id,
+        pipeline_id,
+        source_id,
+        sink_id,
+        publication_name,
+        config,
+    )
+    .await
+    .map_err(|e| duplicate_pipeline_error(e, source_id, sink_id))?
+    .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let after = read_pipeline_snapshot(&mut **txn, tenant_id, pipeline_id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Pipeline,
+        &pipeline_id.to_string(),
+        before,
+        after,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a pipeline in the transaction, as a request or a change of an
+/// applied tenant config
+pub(super) async fn delete_pipeline_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<(), PipelineError> {
+    let before = read_pipeline_snapshot(&mut **txn, tenant_id, pipeline_id).await?;
+    db::pipelines::delete_pipeline(&mut **txn, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Pipeline,
+        &pipeline_id.to_string(),
+        before,
+        None,
     )
     .await?;
+    Ok(())
+}
 
-    let response = PostPipelineResponse { id };
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostPipelineRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Key to retry the request with, which returns the pipeline created by the first request"),
+    ),
+    responses(
+        (status = 200, description = "Create new pipeline, or return the existing one with the same idempotency key or source and sink", body = PostPipelineResponse),
+        (status = 400, description = "Invalid idempotency key, or publication not found in the source"),
+        (status = 403, description = "Pipelines or tables quota of the tenant exceeded", body = QuotaExceededMessage),
+        (status = 422, description = "Invalid fields", body = ValidationErrorMessage),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines")]
+pub async fn create_pipeline(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    pipeline: Json<PostPipelineRequest>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let idempotency_key = extract_idempotency_key(&req)?;
+    let mut txn = pool.begin().await?;
+    let (id, created) = create_pipeline_item(
+        &req,
+        &mut txn,
+        &encryption_key,
+        tenant_id,
+        idempotency_key,
+        pipeline.0,
+    )
+    .await?;
+    txn.commit().await?;
+    let response = PostPipelineResponse { id, created };
     Ok(Json(response))
 }
 
@@ -206,7 +646,7 @@ pub async fn read_pipeline(
     let tenant_id = extract_tenant_id(&req)?;
     let pipeline_id = pipeline_id.into_inner();
 
-    let response = db::pipelines::read_pipeline(&pool, tenant_id, pipeline_id)
+    let response = db::pipelines::read_pipeline(pool.get_ref(), tenant_id, pipeline_id)
         .await?
         .map(|s| {
             let config: PipelineConfig = serde_json::from_value(s.config)?;
@@ -230,13 +670,17 @@ pub async fn read_pipeline(
 
 #[utoipa::path(
     context_path = "/v1",
-    request_body = PostSinkRequest,
+    request_body = PostPipelineRequest,
     params(
         ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
     ),
     responses(
         (status = 200, description = "Update pipeline with id = pipeline_id"),
+        (status = 400, description = "Publication not found in the source"),
+        (status = 403, description = "Tables quota of the tenant exceeded", body = QuotaExceededMessage),
         (status = 404, description = "Pipeline not found"),
+        (status = 409, description = "Another pipeline has the same source and sink"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorMessage),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -244,47 +688,82 @@ pub async fn read_pipeline(
 pub async fn update_pipeline(
     req: HttpRequest,
     pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
     pipeline_id: Path<i64>,
     pipeline: Json<PostPipelineRequest>,
 ) -> Result<impl Responder, PipelineError> {
-    let pipeline = pipeline.0;
     let tenant_id = extract_tenant_id(&req)?;
     let pipeline_id = pipeline_id.into_inner();
-    let config = &pipeline.config;
-    let source_id = pipeline.source_id;
-    let sink_id = pipeline.sink_id;
-    let publication_name = pipeline.publication_name;
-
-    if !source_exists(&pool, tenant_id, source_id).await? {
-        return Err(PipelineError::SourceNotFound(source_id));
-    }
-
-    if !sink_exists(&pool, tenant_id, sink_id).await? {
-        return Err(PipelineError::SinkNotFound(sink_id));
-    }
-
-    db::pipelines::update_pipeline(
-        &pool,
+    let mut txn = pool.begin().await?;
+    update_pipeline_item(
+        &req,
+        &mut txn,
+        &encryption_key,
         tenant_id,
         pipeline_id,
-        source_id,
-        sink_id,
-        publication_name,
-        config,
+        pipeline.0,
     )
-    .await?
-    .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
-
+    .await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = Vec<PipelineBatchItem>,
+    responses(
+        (status = 200, description = "Create the items without an id, or return the existing pipeline with the same source and sink, and update those with one, one after another, returning the result of each item", body = BatchResponse),
+        (status = 400, description = "Too many items"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/batch")]
+pub async fn batch_pipelines(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    items: Json<Vec<BatchItem<PostPipelineRequest>>>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let items = items.into_inner();
+    check_batch_size(items.len())?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for (index, item) in items.into_iter().enumerate() {
+        // each item is committed on its own, a failed item is rolled back
+        let mut txn = pool.begin().await?;
+        let result = match item.id {
+            Some(id) => {
+                update_pipeline_item(&req, &mut txn, &encryption_key, tenant_id, id, item.item)
+                    .await
+                    .map(|()| id)
+            }
+            None => {
+                create_pipeline_item(&req, &mut txn, &encryption_key, tenant_id, None, item.item)
+                    .await
+                    .map(|(id, _)| id)
+            }
+        };
+        let result = match result {
+            Ok(id) => txn.commit().await.map(|()| id).map_err(PipelineError::from),
+            Err(e) => Err(e),
+        };
+        results.push(match result {
+            Ok(id) => BatchItemResult::succeeded(index, id),
+            Err(e) => BatchItemResult::failed(index, e.status_code(), e.to_message()),
+        });
+    }
+
+    Ok(Json(BatchResponse { results }))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
         ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
     ),
     responses(
-        (status = 200, description = "Delete pipeline with id = pipeline_id"),
+        (status = 200, description = "Delete pipeline with id = pipeline_id and tear down its replicator, keeping its replication slot until it's purged after the retention window"),
         (status = 404, description = "Pipeline not found"),
         (status = 500, description = "Internal server error")
     )
@@ -297,16 +776,81 @@ pub async fn delete_pipeline(
 ) -> Result<impl Responder, PipelineError> {
     let tenant_id = extract_tenant_id(&req)?;
     let pipeline_id = pipeline_id.into_inner();
-    db::pipelines::delete_pipeline(&pool, tenant_id, pipeline_id)
+    let mut txn = pool.begin().await?;
+    delete_pipeline_item(&req, &mut txn, tenant_id, pipeline_id).await?;
+    txn.commit().await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted pipeline with id = pipeline_id, whose replicator is deployed again if it was started"),
+        (status = 403, description = "Pipelines quota of the tenant exceeded", body = QuotaExceededMessage),
+        (status = 404, description = "Deleted pipeline not found, or already purged"),
+        (status = 409, description = "Source or sink of the pipeline deleted, or another pipeline has the same source and sink"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/restore")]
+pub async fn restore_pipeline(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+    let pipeline = db::pipelines::read_deleted_pipeline(&pool, tenant_id, pipeline_id)
         .await?
-        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+        .ok_or(PipelineError::DeletedPipelineNotFound(pipeline_id))?;
+
+    let mut txn = pool.begin().await?;
+    if !source_exists(&mut *txn, tenant_id, pipeline.source_id).await? {
+        return Err(PipelineError::SourceDeleted(pipeline.source_id));
+    }
+    if !sink_exists(&mut *txn, tenant_id, pipeline.sink_id).await? {
+        return Err(PipelineError::SinkDeleted(pipeline.sink_id));
+    }
+
+    let quotas = db::quotas::lock_quotas(&mut txn, tenant_id).await?;
+    let pipelines = db::quotas::count_pipelines(&mut *txn, tenant_id).await?;
+    check_quota(&quotas, Quota::MaxPipelines, pipelines + 1)?;
+
+    db::pipelines::restore_pipeline(&mut *txn, tenant_id, pipeline.id)
+        .await
+        .map_err(|e| duplicate_pipeline_error(e, pipeline.source_id, pipeline.sink_id))?
+        .ok_or(PipelineError::DeletedPipelineNotFound(pipeline_id))?;
+    let after = read_pipeline_snapshot(&mut *txn, tenant_id, pipeline_id).await?;
+    record_change(
+        &req,
+        &mut *txn,
+        tenant_id,
+        AuditResource::Pipeline,
+        &pipeline_id.to_string(),
+        None,
+        after,
+    )
+    .await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PipelineFilter {
+    /// Only pipelines whose replicator has this status
+    status: Option<ReplicatorStatus>,
+}
+
 #[utoipa::path(
     context_path = "/v1",
+    params(ListQuery, PipelineFilter),
     responses(
-        (status = 200, description = "Return all pipelines"),
+        (status = 200, description = "Return a page of pipelines, named by their publication", body = PipelinesPage),
+        (status = 400, description = "Invalid list parameters"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -314,10 +858,14 @@ pub async fn delete_pipeline(
 pub async fn read_all_pipelines(
     req: HttpRequest,
     pool: Data<PgPool>,
+    query: Query<ListQuery>,
+    filter: Query<PipelineFilter>,
 ) -> Result<impl Responder, PipelineError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let params = query.into_inner().into_params()?;
+    let page = db::pipelines::read_all_pipelines(&pool, tenant_id, filter.status, &params).await?;
     let mut pipelines = vec![];
-    for pipeline in db::pipelines::read_all_pipelines(&pool, tenant_id).await? {
+    for pipeline in page.items {
         let config: PipelineConfig = serde_json::from_value(pipeline.config)?;
         let sink = GetPipelineResponse {
             id: pipeline.id,
@@ -332,13 +880,20 @@ pub async fn read_all_pipelines(
         };
         pipelines.push(sink);
     }
-    Ok(Json(pipelines))
+    Ok(Json(ListResponse {
+        items: pipelines,
+        next_cursor: page.next_cursor,
+    }))
 }
 
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
     responses(
         (status = 200, description = "Start a pipeline"),
+        (status = 404, description = "Pipeline not found"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -353,23 +908,27 @@ pub async fn start_pipeline(
     let tenant_id = extract_tenant_id(&req)?;
     let pipeline_id = pipeline_id.into_inner();
 
-    let (pipeline, replicator, image, source, sink) =
-        read_data(&pool, tenant_id, pipeline_id, &encryption_key).await?;
-
-    let (secrets, config) = create_configs(source.config, sink.config, pipeline)?;
-    let prefix = create_prefix(tenant_id, replicator.id);
-
-    create_or_update_secrets(&k8s_client, &prefix, secrets).await?;
-    create_or_update_config(&k8s_client, &prefix, config).await?;
-    create_or_update_replicator(&k8s_client, &prefix, image.name).await?;
+    start_replicator(&pool, &encryption_key, &k8s_client, tenant_id, pipeline_id).await?;
+    webhooks::notify(
+        &pool,
+        tenant_id,
+        pipeline_id,
+        WebhookEvent::PipelineStarted,
+        serde_json::json!({}),
+    )
+    .await;
 
     Ok(HttpResponse::Ok().finish())
 }
 
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
     responses(
         (status = 200, description = "Stop a pipeline"),
+        (status = 404, description = "Pipeline not found"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -387,15 +946,233 @@ pub async fn stop_pipeline(
         .await?
         .ok_or(PipelineError::ReplicatorNotFound(pipeline_id))?;
 
-    let prefix = create_prefix(tenant_id, replicator.id);
-    delete_secrets(&k8s_client, &prefix).await?;
-    delete_config(&k8s_client, &prefix).await?;
-    delete_replicator(&k8s_client, &prefix).await?;
+    db::replicators::update_replicator_desired_status(
+        &pool,
+        tenant_id,
+        replicator.id,
+        ReplicatorStatus::Stopped,
+    )
+    .await?;
+
+    let prefix = replicator_prefix(tenant_id, replicator.id);
+    teardown_replicator(&k8s_client, &prefix).await?;
+
+    db::replicators::update_replicator_status(
+        &pool,
+        tenant_id,
+        replicator.id,
+        ReplicatorStatus::Stopping,
+    )
+    .await?;
+    webhooks::notify(
+        &pool,
+        tenant_id,
+        pipeline_id,
+        WebhookEvent::PipelineStopped,
+        serde_json::json!({}),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Restart a pipeline with its current config"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/restart")]
+pub async fn restart_pipeline(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    k8s_client: Data<Arc<HttpK8sClient>>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    // updating the replicator's stateful set replaces its running pod
+    start_replicator(&pool, &encryption_key, &k8s_client, tenant_id, pipeline_id).await?;
+    webhooks::notify(
+        &pool,
+        tenant_id,
+        pipeline_id,
+        WebhookEvent::PipelineStarted,
+        serde_json::json!({}),
+    )
+    .await;
 
     Ok(HttpResponse::Ok().finish())
 }
 
 #[derive(Serialize, ToSchema)]
+pub struct GetPipelineVersionResponse {
+    #[schema(example = 2)]
+    version: i32,
+    source_id: i64,
+    sink_id: i64,
+    publication_name: String,
+    config: PipelineConfig,
+    /// Time the version was created, in seconds since the unix epoch
+    #[schema(example = 1_729_328_400)]
+    created_at: i64,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Return the versions of the config of pipeline with id = pipeline_id, newest first", body = Vec<GetPipelineVersionResponse>),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/pipelines/{pipeline_id}/versions")]
+pub async fn read_pipeline_versions(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+    let versions = db::pipelines::read_pipeline_versions(&pool, tenant_id, pipeline_id).await?;
+    // Every pipeline has at least the version it was created with
+    if versions.is_empty() {
+        return Err(PipelineError::PipelineNotFound(pipeline_id));
+    }
+    let mut response = Vec::with_capacity(versions.len());
+    for version in versions {
+        response.push(GetPipelineVersionResponse {
+            version: version.version,
+            source_id: version.source_id,
+            sink_id: version.sink_id,
+            publication_name: version.publication_name,
+            config: serde_json::from_value(version.config)?,
+            created_at: version.created_at,
+        });
+    }
+    Ok(Json(response))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RollbackQuery {
+    /// Version of the config to restore
+    version: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PostRollbackResponse {
+    /// The new version, with the restored config
+    #[schema(example = 3)]
+    version: i32,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+        RollbackQuery,
+    ),
+    responses(
+        (status = 200, description = "Restore a previous config of pipeline with id = pipeline_id as a new version, redeploying its replicator if it is started", body = PostRollbackResponse),
+        (status = 400, description = "Source, sink or publication of the version no longer exists"),
+        (status = 403, description = "Tables quota of the tenant exceeded", body = QuotaExceededMessage),
+        (status = 404, description = "Pipeline or version not found"),
+        (status = 409, description = "Another pipeline has the same source and sink as the version"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/rollback")]
+pub async fn rollback_pipeline(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    k8s_client: Option<Data<Arc<HttpK8sClient>>>,
+    pipeline_id: Path<i64>,
+    query: Query<RollbackQuery>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+    let version = query.version;
+
+    let before = read_pipeline_snapshot(pool.get_ref(), tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let target = db::pipelines::read_pipeline_version(&pool, tenant_id, pipeline_id, version)
+        .await?
+        .ok_or(PipelineError::VersionNotFound(version))?;
+
+    if !source_exists(pool.get_ref(), tenant_id, target.source_id).await? {
+        return Err(PipelineError::SourceNotFound(target.source_id));
+    }
+
+    if !sink_exists(pool.get_ref(), tenant_id, target.sink_id).await? {
+        return Err(PipelineError::SinkNotFound(target.sink_id));
+    }
+
+    let quotas = db::quotas::read_quotas(pool.get_ref(), tenant_id).await?;
+    let config: PipelineConfig = serde_json::from_value(target.config)?;
+    check_tables_quota(
+        pool.get_ref(),
+        &encryption_key,
+        tenant_id,
+        &quotas,
+        target.source_id,
+        &target.publication_name,
+        &config,
+    )
+    .await?;
+
+    let new_version = db::pipelines::rollback_pipeline(&pool, tenant_id, pipeline_id, version)
+        .await
+        .map_err(|e| duplicate_pipeline_error(e, target.source_id, target.sink_id))?
+        .ok_or(PipelineError::VersionNotFound(version))?;
+    let after = read_pipeline_snapshot(pool.get_ref(), tenant_id, pipeline_id).await?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        tenant_id,
+        AuditResource::Pipeline,
+        &pipeline_id.to_string(),
+        Some(before),
+        after,
+    )
+    .await?;
+
+    let replicator = db::replicators::read_replicator_by_pipeline_id(&pool, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::ReplicatorNotFound(pipeline_id))?;
+    if replicator.desired_status == ReplicatorStatus::Started {
+        let k8s_client = k8s_client.ok_or(PipelineError::K8sClientMissing)?;
+        // updating the replicator's config and stateful set replaces its
+        // running pod with one using the restored config
+        start_replicator(&pool, &encryption_key, &k8s_client, tenant_id, pipeline_id).await?;
+        webhooks::notify(
+            &pool,
+            tenant_id,
+            pipeline_id,
+            WebhookEvent::PipelineStarted,
+            serde_json::json!({}),
+        )
+        .await;
+    }
+
+    Ok(Json(PostRollbackResponse {
+        version: new_version,
+    }))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
 pub enum PipelineStatus {
     Stopped,
     Starting,
@@ -404,10 +1181,189 @@ pub enum PipelineStatus {
     Unknown,
 }
 
+impl From<ReplicatorStatus> for PipelineStatus {
+    fn from(value: ReplicatorStatus) -> Self {
+        match value {
+            ReplicatorStatus::Stopped => PipelineStatus::Stopped,
+            ReplicatorStatus::Starting => PipelineStatus::Starting,
+            ReplicatorStatus::Started => PipelineStatus::Started,
+            ReplicatorStatus::Stopping => PipelineStatus::Stopping,
+        }
+    }
+}
+
+/// Phase of a running replicator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicatorPhase {
+    Starting,
+    Copying,
+    Cdc,
+    Errored,
+}
+
+impl ReplicatorPhase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReplicatorPhase::Starting => "starting",
+            ReplicatorPhase::Copying => "copying",
+            ReplicatorPhase::Cdc => "cdc",
+            ReplicatorPhase::Errored => "errored",
+        }
+    }
+
+    fn parse(phase: &str) -> Option<ReplicatorPhase> {
+        match phase {
+            "starting" => Some(ReplicatorPhase::Starting),
+            "copying" => Some(ReplicatorPhase::Copying),
+            "cdc" => Some(ReplicatorPhase::Cdc),
+            "errored" => Some(ReplicatorPhase::Errored),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TableCopyProgress {
+    table_name: String,
+    rows_copied: u64,
+    copied: bool,
+    /// Rows of the table estimated from its statistics before its copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimated_rows: Option<u64>,
+    /// Bytes the table takes on disk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimated_bytes: Option<u64>,
+    /// Bytes copied, estimated from the table's average row size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimated_bytes_copied: Option<u64>,
+    /// Time spent copying the table so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    copy_secs: Option<f64>,
+    /// Time left to copy the table at the rate it was copied so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    eta_secs: Option<u64>,
+}
+
+/// Time left to copy the tables which aren't copied yet at the rate rows
+/// were copied so far, as the tables are copied one after another. None if
+/// some table's rows aren't estimated or no rows were copied yet
+fn copy_eta_secs(tables: &[TableCopyProgress]) -> Option<u64> {
+    let mut remaining_rows = 0;
+    for table in tables.iter().filter(|table| !table.copied) {
+        remaining_rows += table.estimated_rows?.saturating_sub(table.rows_copied);
+    }
+    if remaining_rows == 0 {
+        return Some(0);
+    }
+    let rows_copied: u64 = tables.iter().map(|table| table.rows_copied).sum();
+    let copy_secs: f64 = tables.iter().filter_map(|table| table.copy_secs).sum();
+    if rows_copied == 0 || copy_secs <= 0.0 {
+        return None;
+    }
+    Some((remaining_rows as f64 * copy_secs / rows_copied as f64).round() as u64)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LatencyBucket {
+    /// Upper bound of the bucket
+    pub le_secs: f64,
+    /// Cumulative count of writes at most as slow
+    pub count: u64,
+}
+
+/// Counters of the changes of a table since the replicator started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TableMetricsReport {
+    pub table_name: String,
+    pub inserts: u64,
+    pub updates: u64,
+    pub deletes: u64,
+    /// Values of rows and changes which couldn't be converted
+    pub conversion_errors: u64,
+    /// Values which couldn't be converted by column
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub column_conversion_errors: BTreeMap<String, u64>,
+    /// Histogram of the time the sink took to write batches with rows of
+    /// the table
+    pub sink_latency_buckets: Vec<LatencyBucket>,
+    pub sink_latency_sum_secs: f64,
+    pub sink_latency_count: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostPipelineStatusRequest {
+    phase: ReplicatorPhase,
+    last_flushed_lsn: Option<String>,
+    lag_bytes: Option<u64>,
+    lag_secs: Option<u64>,
+    /// Position of the source's WAL when the replicator last queried it
+    #[serde(default)]
+    source_wal_lsn: Option<String>,
+    /// Time the replicator needs to catch up at the rate the source writes
+    /// WAL
+    #[serde(default)]
+    estimated_lag_secs: Option<u64>,
+    tables: Vec<TableCopyProgress>,
+    /// Counters of the tables with changes, exposed on `/metrics`
+    #[serde(default)]
+    table_metrics: Vec<TableMetricsReport>,
+    last_error: Option<String>,
+    /// Process id of the replicator
+    pid: Option<u32>,
+    /// Host the replicator runs on
+    host: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReplicatorStatusReport {
+    phase: ReplicatorPhase,
+    /// Lsn up to which changes have been written to the sink
+    last_flushed_lsn: Option<String>,
+    /// Bytes of WAL the source has written past `last_flushed_lsn`
+    lag_bytes: Option<u64>,
+    /// Age of the last flushed transaction when it was flushed
+    lag_secs: Option<u64>,
+    /// Position of the source's WAL when the replicator last queried it
+    source_wal_lsn: Option<String>,
+    /// Time the replicator needs to catch up at the rate the source writes
+    /// WAL
+    estimated_lag_secs: Option<u64>,
+    tables: Vec<TableCopyProgress>,
+    /// Time left to copy the tables at the rate rows were copied so far,
+    /// none if it can't be estimated
+    copy_eta_secs: Option<u64>,
+    last_error: Option<String>,
+    /// Process id of the replicator which sent this report
+    pid: Option<u32>,
+    /// Host the replicator which sent this report runs on
+    host: Option<String>,
+    /// Seconds since the replicator sent this report
+    secs_since_report: u64,
+    /// Whether the replicator still reports its status in time
+    health: PipelineHealth,
+    /// Times the replicator was restarted after crashing since it last
+    /// reached cdc
+    restart_count: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetPipelineStatusResponse {
+    /// Status the pipeline was last asked to be in
+    desired_status: PipelineStatus,
+    /// Status of the pipeline's replicator
+    status: PipelineStatus,
+    /// Last status reported by the replicator, if it reported any
+    report: Option<ReplicatorStatusReport>,
+}
+
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
     responses(
-        (status = 200, description = "Get pipeline status"),
+        (status = 200, description = "Get pipeline status", body = GetPipelineStatusResponse),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -425,19 +1381,226 @@ pub async fn get_pipeline_status(
         .await?
         .ok_or(PipelineError::ReplicatorNotFound(pipeline_id))?;
 
-    let prefix = create_prefix(tenant_id, replicator.id);
+    let prefix = replicator_prefix(tenant_id, replicator.id);
 
     let pod_phase = k8s_client.get_pod_phase(&prefix).await?;
 
-    let status = match pod_phase {
-        PodPhase::Pending => PipelineStatus::Starting,
-        PodPhase::Running => PipelineStatus::Started,
-        PodPhase::Succeeded => PipelineStatus::Stopped,
-        PodPhase::Failed => PipelineStatus::Stopped,
-        PodPhase::Unknown => PipelineStatus::Unknown,
+    let status = match (replicator.desired_status, pod_phase) {
+        (ReplicatorStatus::Stopped, PodPhase::Pending | PodPhase::Running) => {
+            Some(ReplicatorStatus::Stopping)
+        }
+        (_, PodPhase::Pending) => Some(ReplicatorStatus::Starting),
+        (_, PodPhase::Running) => Some(ReplicatorStatus::Started),
+        (_, PodPhase::Succeeded | PodPhase::Failed) => Some(ReplicatorStatus::Stopped),
+        (_, PodPhase::Unknown) => None,
+    };
+
+    if let Some(status) = status {
+        if status != replicator.status {
+            db::replicators::update_replicator_status(&pool, tenant_id, replicator.id, status)
+                .await?;
+        }
+    }
+
+    let report = db::pipeline_statuses::read_pipeline_status(&pool, tenant_id, pipeline_id)
+        .await?
+        .map(|reported| {
+            let report = reported.report;
+            let tables: Vec<TableCopyProgress> = serde_json::from_value(report.tables)?;
+            Ok::<ReplicatorStatusReport, serde_json::Error>(ReplicatorStatusReport {
+                phase: ReplicatorPhase::parse(&report.phase).unwrap_or(ReplicatorPhase::Errored),
+                last_flushed_lsn: report.last_flushed_lsn,
+                lag_bytes: report.lag_bytes.map(|lag_bytes| lag_bytes as u64),
+                lag_secs: report.lag_secs.map(|lag_secs| lag_secs as u64),
+                source_wal_lsn: report.source_wal_lsn,
+                estimated_lag_secs: report
+                    .estimated_lag_secs
+                    .map(|estimated_lag_secs| estimated_lag_secs as u64),
+                copy_eta_secs: copy_eta_secs(&tables),
+                tables,
+                last_error: report.last_error,
+                pid: report.pid.map(|pid| pid as u32),
+                host: report.host,
+                secs_since_report: reported.secs_since_report.max(0) as u64,
+                health: PipelineHealth::parse(&reported.health).unwrap_or(PipelineHealth::Healthy),
+                restart_count: reported.restart_count.max(0) as u32,
+            })
+        })
+        .transpose()?;
+
+    let response = GetPipelineStatusResponse {
+        desired_status: replicator.desired_status.into(),
+        status: status.map_or(PipelineStatus::Unknown, Into::into),
+        report,
+    };
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostPipelineStatusRequest,
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Record the status reported by a pipeline's replicator"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/status")]
+pub async fn report_pipeline_status(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+    status: Json<PostPipelineStatusRequest>,
+) -> Result<impl Responder, PipelineError> {
+    let status = status.0;
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    let previous_report =
+        db::pipeline_statuses::read_pipeline_status(&pool, tenant_id, pipeline_id)
+            .await?
+            .map(|reported| reported.report);
+
+    let report = PipelineStatusReport {
+        phase: status.phase.as_str().to_string(),
+        last_flushed_lsn: status.last_flushed_lsn.clone(),
+        lag_bytes: status.lag_bytes.map(|lag_bytes| lag_bytes as i64),
+        lag_secs: status.lag_secs.map(|lag_secs| lag_secs as i64),
+        source_wal_lsn: status.source_wal_lsn.clone(),
+        estimated_lag_secs: status
+            .estimated_lag_secs
+            .map(|estimated_lag_secs| estimated_lag_secs as i64),
+        tables: serde_json::to_value(&status.tables)?,
+        last_error: status.last_error.clone(),
+        pid: status.pid.map(|pid| pid as i32),
+        host: status.host.clone(),
+        table_metrics: serde_json::to_value(&status.table_metrics)?,
+    };
+
+    db::pipeline_statuses::upsert_pipeline_status(&pool, tenant_id, pipeline_id, &report)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+
+    notify_status_changes(&pool, tenant_id, pipeline_id, previous_report, &status).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Notifies webhooks of the changes between the previous status report of a
+/// pipeline and the new one
+async fn notify_status_changes(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    previous_report: Option<PipelineStatusReport>,
+    status: &PostPipelineStatusRequest,
+) {
+    let (previous_phase, previous_lag_secs, previous_tables) = match previous_report {
+        Some(report) => {
+            // A report which can't be read only causes extra notifications
+            let tables: Vec<TableCopyProgress> =
+                serde_json::from_value(report.tables).unwrap_or_default();
+            (
+                ReplicatorPhase::parse(&report.phase),
+                report.lag_secs,
+                tables,
+            )
+        }
+        None => (None, None, vec![]),
     };
 
-    Ok(Json(status))
+    if status.phase == ReplicatorPhase::Errored && previous_phase != Some(ReplicatorPhase::Errored)
+    {
+        let data = serde_json::json!({ "error": status.last_error });
+        webhooks::notify(
+            pool,
+            tenant_id,
+            pipeline_id,
+            WebhookEvent::PipelineErrored,
+            data,
+        )
+        .await;
+    }
+
+    if let Some(lag_secs) = status.lag_secs {
+        webhooks::notify_lag(
+            pool,
+            tenant_id,
+            pipeline_id,
+            previous_lag_secs,
+            lag_secs as i64,
+        )
+        .await;
+    }
+
+    for table in status.tables.iter().filter(|table| table.copied) {
+        let was_copied = previous_tables
+            .iter()
+            .any(|previous| previous.table_name == table.table_name && previous.copied);
+        if !was_copied {
+            let data = serde_json::json!({
+                "table_name": table.table_name,
+                "rows_copied": table.rows_copied,
+            });
+            webhooks::notify(
+                pool,
+                tenant_id,
+                pipeline_id,
+                WebhookEvent::TableSyncCompleted,
+                data,
+            )
+            .await;
+        }
+    }
+}
+
+/// Deploys the pipeline's replicator with the pipeline's current config and
+/// records that it should be running
+pub(crate) async fn start_replicator(
+    pool: &PgPool,
+    encryption_key: &EncryptionKey,
+    k8s_client: &Arc<HttpK8sClient>,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<(), PipelineError> {
+    let (pipeline, replicator, image, source, sink) =
+        read_data(pool, tenant_id, pipeline_id, encryption_key).await?;
+
+    db::replicators::update_replicator_desired_status(
+        pool,
+        tenant_id,
+        replicator.id,
+        ReplicatorStatus::Started,
+    )
+    .await?;
+
+    // every deployment gets a new key, which invalidates the previous one's
+    let (api_key, api_key_hash) = generate_api_key()?;
+    db::api_keys::replace_pipeline_api_key(pool, tenant_id, pipeline_id, &api_key_hash).await?;
+    let status_report = replicator_config::StatusReportConfig {
+        api_url: k8s_client.api_url().to_string(),
+        tenant_id: tenant_id.to_string(),
+        pipeline_id,
+    };
+
+    let (secrets, config) =
+        create_configs(source.config, sink.config, pipeline, status_report, api_key)?;
+    let prefix = replicator_prefix(tenant_id, replicator.id);
+    deploy_replicator(k8s_client, &prefix, secrets, config, &image.name).await?;
+
+    db::replicators::update_replicator_status(
+        pool,
+        tenant_id,
+        replicator.id,
+        ReplicatorStatus::Starting,
+    )
+    .await?;
+
+    Ok(())
 }
 
 async fn read_data(
@@ -471,6 +1634,8 @@ fn create_configs(
     source_config: SourceConfig,
     sink_config: SinkConfig,
     pipeline: Pipeline,
+    status_report: replicator_config::StatusReportConfig,
+    api_key: String,
 ) -> Result<(Secrets, replicator_config::Config), PipelineError> {
     let SourceConfig::Postgres {
         host,
@@ -490,6 +1655,26 @@ fn create_configs(
     let secrets = Secrets {
         postgres_password: postgres_password.unwrap_or_default(),
         bigquery_service_account_key,
+        api_key,
+    };
+
+    let pipeline_config: PipelineConfig = serde_json::from_value(pipeline.config)?;
+    let (tables, naming) = match pipeline_config.tables {
+        Some(tables) => {
+            let table_filter = replicator_config::TableFilter {
+                include: tables.include,
+                exclude: tables.exclude,
+            };
+            let naming = if tables.mappings.is_empty() {
+                None
+            } else {
+                Some(replicator_config::NamingConfig {
+                    table_names: tables.mappings,
+                })
+            };
+            (Some(table_filter), naming)
+        }
+        None => (None, None),
     };
 
     let publication = pipeline.publication_name;
@@ -500,90 +1685,74 @@ fn create_configs(
         username,
         slot_name,
         publication,
+        tables,
     };
 
     let sink_config = replicator_config::SinkConfig::BigQuery {
         project_id,
         dataset_id,
+        naming,
     };
 
-    let pipeline_config: PipelineConfig = serde_json::from_value(pipeline.config)?;
     let batch_config = pipeline_config.config;
     let batch_config = replicator_config::BatchConfig {
         max_size: batch_config.max_size,
         max_fill_secs: batch_config.max_fill_secs,
+        transactional: batch_config.transactional,
     };
 
+    let table_overrides = pipeline_config
+        .overrides
+        .into_iter()
+        .map(|(source_table, overrides)| (source_table, table_overrides(overrides)))
+        .collect();
+
     let config = replicator_config::Config {
         source: source_config,
         sink: sink_config,
         batch: batch_config,
+        table_overrides,
+        status_report: Some(status_report),
     };
 
     Ok((secrets, config))
 }
 
-fn create_prefix(tenant_id: &str, replicator_id: i64) -> String {
-    format!("{tenant_id}-{replicator_id}")
-}
-
-async fn create_or_update_secrets(
-    k8s_client: &Arc<HttpK8sClient>,
-    prefix: &str,
-    secrets: Secrets,
-) -> Result<(), PipelineError> {
-    k8s_client
-        .create_or_update_postgres_secret(prefix, &secrets.postgres_password)
-        .await?;
-    k8s_client
-        .create_or_update_bq_secret(prefix, &secrets.bigquery_service_account_key)
-        .await?;
-    Ok(())
-}
-
-async fn create_or_update_config(
-    k8s_client: &Arc<HttpK8sClient>,
-    prefix: &str,
-    config: replicator_config::Config,
-) -> Result<(), PipelineError> {
-    let base_config = "";
-    let prod_config = serde_json::to_string(&config)?;
-    k8s_client
-        .create_or_update_config_map(prefix, base_config, &prod_config)
-        .await?;
-    Ok(())
-}
-
-async fn create_or_update_replicator(
-    k8s_client: &Arc<HttpK8sClient>,
-    prefix: &str,
-    replicator_image: String,
-) -> Result<(), PipelineError> {
-    k8s_client
-        .create_or_update_stateful_set(prefix, &replicator_image)
-        .await?;
-
-    Ok(())
-}
-
-async fn delete_secrets(
-    k8s_client: &Arc<HttpK8sClient>,
-    prefix: &str,
-) -> Result<(), PipelineError> {
-    k8s_client.delete_postgres_secret(prefix).await?;
-    k8s_client.delete_bq_secret(prefix).await?;
-    Ok(())
-}
-
-async fn delete_config(k8s_client: &Arc<HttpK8sClient>, prefix: &str) -> Result<(), PipelineError> {
-    k8s_client.delete_config_map(prefix).await?;
-    Ok(())
-}
-
-async fn delete_replicator(
-    k8s_client: &Arc<HttpK8sClient>,
-    prefix: &str,
-) -> Result<(), PipelineError> {
-    k8s_client.delete_stateful_set(prefix).await?;
-    Ok(())
+fn table_overrides(overrides: TableOverrides) -> replicator_config::TableOverrides {
+    let granularity = |granularity| match granularity {
+        PartitionGranularity::Hour => replicator_config::PartitionGranularity::Hour,
+        PartitionGranularity::Day => replicator_config::PartitionGranularity::Day,
+        PartitionGranularity::Month => replicator_config::PartitionGranularity::Month,
+        PartitionGranularity::Year => replicator_config::PartitionGranularity::Year,
+    };
+    replicator_config::TableOverrides {
+        destination: overrides.destination,
+        write_mode: overrides.write_mode.map(|write_mode| match write_mode {
+            WriteMode::Upsert => replicator_config::WriteMode::Upsert,
+            WriteMode::AppendOnly => replicator_config::WriteMode::AppendOnly,
+        }),
+        partitioning: overrides
+            .partitioning
+            .map(|partitioning| match partitioning {
+                TablePartitioning::Column {
+                    column,
+                    granularity: g,
+                } => replicator_config::TablePartitioning::Column {
+                    column,
+                    granularity: granularity(g),
+                },
+                TablePartitioning::IngestionTime { granularity: g } => {
+                    replicator_config::TablePartitioning::IngestionTime {
+                        granularity: granularity(g),
+                    }
+                }
+            }),
+        clustering: overrides.clustering,
+        masked_columns: overrides.masked_columns,
+        batch: overrides
+            .batch
+            .map(|batch| replicator_config::TableBatchConfig {
+                max_size: batch.max_size,
+            }),
+    }
 }
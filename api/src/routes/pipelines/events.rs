@@ -0,0 +1,344 @@
+use std::{collections::VecDeque, convert::Infallible, time::Duration};
+
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective},
+    web::{Bytes, Data, Path},
+    HttpRequest, HttpResponse, Responder,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::{
+    db::{self, pipeline_statuses::ReportedPipelineStatus},
+    health::PipelineHealth,
+    routes::extract_tenant_id,
+};
+
+use super::{PipelineError, ReplicatorPhase, TableCopyProgress};
+
+/// Interval after which the stream reads the pipeline's status again
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls without events after which a comment is sent, so that proxies
+/// don't close an idle stream
+const KEEP_ALIVE_POLLS: u32 = 15;
+
+/// A change of a pipeline's status, sent as a server-sent event
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+enum PipelineEvent {
+    Phase {
+        phase: ReplicatorPhase,
+    },
+    TableProgress(TableCopyProgress),
+    Lag {
+        last_flushed_lsn: Option<String>,
+        lag_bytes: Option<u64>,
+        lag_secs: Option<u64>,
+    },
+    Error {
+        error: String,
+    },
+    Health {
+        health: PipelineHealth,
+    },
+}
+
+impl PipelineEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            PipelineEvent::Phase { .. } => "phase",
+            PipelineEvent::TableProgress(_) => "table_progress",
+            PipelineEvent::Lag { .. } => "lag",
+            PipelineEvent::Error { .. } => "error",
+            PipelineEvent::Health { .. } => "health",
+        }
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        let data = serde_json::to_string(self).expect("failed to serialize event");
+        Bytes::from(format!("event: {}\ndata: {data}\n\n", self.name()))
+    }
+}
+
+/// The parts of a pipeline's status whose changes are streamed
+#[derive(Debug, Clone, PartialEq)]
+struct StatusSnapshot {
+    phase: ReplicatorPhase,
+    tables: Vec<TableCopyProgress>,
+    last_flushed_lsn: Option<String>,
+    lag_bytes: Option<u64>,
+    lag_secs: Option<u64>,
+    last_error: Option<String>,
+    health: PipelineHealth,
+}
+
+impl From<ReportedPipelineStatus> for StatusSnapshot {
+    fn from(reported: ReportedPipelineStatus) -> Self {
+        let report = reported.report;
+        StatusSnapshot {
+            phase: ReplicatorPhase::parse(&report.phase).unwrap_or(ReplicatorPhase::Errored),
+            // A report which can't be read only streams no table progress
+            tables: serde_json::from_value(report.tables).unwrap_or_default(),
+            last_flushed_lsn: report.last_flushed_lsn,
+            lag_bytes: report.lag_bytes.map(|lag_bytes| lag_bytes as u64),
+            lag_secs: report.lag_secs.map(|lag_secs| lag_secs as u64),
+            last_error: report.last_error,
+            health: PipelineHealth::parse(&reported.health).unwrap_or(PipelineHealth::Healthy),
+        }
+    }
+}
+
+/// Events for the changes between the previous status of a pipeline and the
+/// current one. Without a previous status every part of the current one is
+/// an event, so that a new stream starts with the whole status.
+fn status_events(
+    previous: Option<&StatusSnapshot>,
+    current: &StatusSnapshot,
+) -> Vec<PipelineEvent> {
+    let mut events = vec![];
+
+    if previous.map(|p| p.phase) != Some(current.phase) {
+        events.push(PipelineEvent::Phase {
+            phase: current.phase,
+        });
+    }
+
+    for table in &current.tables {
+        let unchanged = previous.is_some_and(|p| p.tables.contains(table));
+        if !unchanged {
+            events.push(PipelineEvent::TableProgress(table.clone()));
+        }
+    }
+
+    let lag_changed = match previous {
+        Some(p) => {
+            p.last_flushed_lsn != current.last_flushed_lsn
+                || p.lag_bytes != current.lag_bytes
+                || p.lag_secs != current.lag_secs
+        }
+        None => true,
+    };
+    let has_lag = current.last_flushed_lsn.is_some()
+        || current.lag_bytes.is_some()
+        || current.lag_secs.is_some();
+    if lag_changed && has_lag {
+        events.push(PipelineEvent::Lag {
+            last_flushed_lsn: current.last_flushed_lsn.clone(),
+            lag_bytes: current.lag_bytes,
+            lag_secs: current.lag_secs,
+        });
+    }
+
+    if let Some(error) = &current.last_error {
+        if previous.and_then(|p| p.last_error.as_ref()) != Some(error) {
+            events.push(PipelineEvent::Error {
+                error: error.clone(),
+            });
+        }
+    }
+
+    if previous.map(|p| p.health) != Some(current.health) {
+        events.push(PipelineEvent::Health {
+            health: current.health,
+        });
+    }
+
+    events
+}
+
+struct EventStream {
+    pool: PgPool,
+    tenant_id: String,
+    pipeline_id: i64,
+    previous: Option<StatusSnapshot>,
+    pending: VecDeque<Bytes>,
+    polled: bool,
+    idle_polls: u32,
+}
+
+impl EventStream {
+    /// Reads the pipeline's status and queues the events of its changes
+    async fn poll(&mut self) {
+        let status = db::pipeline_statuses::read_pipeline_status(
+            &self.pool,
+            &self.tenant_id,
+            self.pipeline_id,
+        )
+        .await;
+        let current = match status {
+            Ok(Some(reported)) => StatusSnapshot::from(reported),
+            // The replicator hasn't reported its status yet
+            Ok(None) => return self.idle(),
+            Err(e) => {
+                error!(
+                    "failed to read status of pipeline {}: {e}",
+                    self.pipeline_id
+                );
+                return self.idle();
+            }
+        };
+
+        let events = status_events(self.previous.as_ref(), &current);
+        self.previous = Some(current);
+        if events.is_empty() {
+            return self.idle();
+        }
+        self.idle_polls = 0;
+        self.pending
+            .extend(events.iter().map(PipelineEvent::to_bytes));
+    }
+
+    fn idle(&mut self) {
+        self.idle_polls += 1;
+        if self.idle_polls >= KEEP_ALIVE_POLLS {
+            self.idle_polls = 0;
+            self.pending
+                .push_back(Bytes::from_static(b": keep-alive\n\n"));
+        }
+    }
+
+    async fn next(&mut self) -> Bytes {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+            if self.polled {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            self.polled = true;
+            self.poll().await;
+        }
+    }
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Stream the changes of the pipeline's status as server-sent events: phase, table_progress, lag, error and health. The stream starts with the current status.", body = String, content_type = "text/event-stream"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/pipelines/{pipeline_id}/events")]
+pub async fn stream_pipeline_events(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    db::pipelines::read_pipeline(pool.get_ref(), tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+
+    let stream = EventStream {
+        pool: pool.get_ref().clone(),
+        tenant_id: tenant_id.to_string(),
+        pipeline_id,
+        previous: None,
+        pending: VecDeque::new(),
+        polled: false,
+        idle_polls: 0,
+    };
+    // The stream ends when the client disconnects
+    let events = futures::stream::unfold(stream, |mut stream| async move {
+        let event = stream.next().await;
+        Some((Ok::<Bytes, Infallible>(event), stream))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        health::PipelineHealth,
+        routes::pipelines::{ReplicatorPhase, TableCopyProgress},
+    };
+
+    use super::{status_events, PipelineEvent, StatusSnapshot};
+
+    fn table(rows_copied: u64, copied: bool) -> TableCopyProgress {
+        TableCopyProgress {
+            table_name: "public.users".to_string(),
+            rows_copied,
+            copied,
+            estimated_rows: None,
+            estimated_bytes: None,
+            estimated_bytes_copied: None,
+            copy_secs: None,
+            eta_secs: None,
+        }
+    }
+
+    fn snapshot() -> StatusSnapshot {
+        StatusSnapshot {
+            phase: ReplicatorPhase::Copying,
+            tables: vec![table(10, false)],
+            last_flushed_lsn: None,
+            lag_bytes: None,
+            lag_secs: None,
+            last_error: None,
+            health: PipelineHealth::Healthy,
+        }
+    }
+
+    #[test]
+    fn first_status_is_streamed_whole() {
+        let events = status_events(None, &snapshot());
+        assert_eq!(
+            events,
+            vec![
+                PipelineEvent::Phase {
+                    phase: ReplicatorPhase::Copying
+                },
+                PipelineEvent::TableProgress(table(10, false)),
+                PipelineEvent::Health {
+                    health: PipelineHealth::Healthy
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changes_are_streamed() {
+        let previous = snapshot();
+        assert!(status_events(Some(&previous), &previous).is_empty());
+
+        let current = StatusSnapshot {
+            phase: ReplicatorPhase::Cdc,
+            tables: vec![table(20, true)],
+            lag_secs: Some(3),
+            last_error: Some("connection reset".to_string()),
+            ..snapshot()
+        };
+        let events = status_events(Some(&previous), &current);
+        assert_eq!(
+            events,
+            vec![
+                PipelineEvent::Phase {
+                    phase: ReplicatorPhase::Cdc
+                },
+                PipelineEvent::TableProgress(table(20, true)),
+                PipelineEvent::Lag {
+                    last_flushed_lsn: None,
+                    lag_bytes: None,
+                    lag_secs: Some(3),
+                },
+                PipelineEvent::Error {
+                    error: "connection reset".to_string()
+                },
+            ]
+        );
+    }
+}
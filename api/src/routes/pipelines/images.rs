@@ -0,0 +1,190 @@
+use actix_web::{
+    get, post, put,
+    web::{Data, Json, Path},
+    HttpRequest, Responder,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{self, audit_logs::AuditResource, images::Image},
+    routes::{audit::record_change, extract_tenant_id},
+};
+
+use super::PipelineError;
+
+#[derive(Deserialize, ToSchema)]
+pub struct PutPipelineImageRequest {
+    /// Image to pin the pipeline to. Without one the pipeline is moved to
+    /// the default image and follows it when the tenant's pipelines are
+    /// upgraded.
+    #[schema(example = 1)]
+    image_id: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetPipelineImageResponse {
+    #[schema(example = 1)]
+    image_id: i64,
+    #[schema(example = "supabase/replicator:1.2.3")]
+    image_name: String,
+    /// True if the pipeline keeps its image when the tenant's pipelines are
+    /// upgraded
+    pinned: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostUpgradePipelinesRequest {
+    /// Image to move the pipelines to, the default image if none is given
+    #[schema(example = 2)]
+    image_id: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PostUpgradePipelinesResponse {
+    #[schema(example = 2)]
+    image_id: i64,
+    /// Pipelines moved to the image
+    #[schema(example = json!([1, 3]))]
+    pipeline_ids: Vec<i64>,
+}
+
+/// Reads the image with `image_id`, or the default image without one
+async fn read_target_image(pool: &PgPool, image_id: Option<i64>) -> Result<Image, PipelineError> {
+    match image_id {
+        Some(image_id) => db::images::read_image(pool, image_id)
+            .await?
+            .ok_or(PipelineError::RequestedImageNotFound(image_id)),
+        None => db::images::read_default_image(pool)
+            .await?
+            .ok_or(PipelineError::NoDefaultImageFound),
+    }
+}
+
+fn image_snapshot(image_id: i64, pinned: bool) -> Option<serde_json::Value> {
+    Some(serde_json::json!({
+        "image_id": image_id,
+        "image_pinned": pinned,
+    }))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Return the image of pipeline with id = pipeline_id", body = GetPipelineImageResponse),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/pipelines/{pipeline_id}/image")]
+pub async fn read_pipeline_image(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    let image = db::replicators::read_pipeline_image(&pool, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+
+    Ok(Json(GetPipelineImageResponse {
+        image_id: image.image_id,
+        image_name: image.image_name,
+        pinned: image.pinned,
+    }))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PutPipelineImageRequest,
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Pin pipeline with id = pipeline_id to an image, or unpin it. The image is used the next time the pipeline is started or restarted.", body = GetPipelineImageResponse),
+        (status = 400, description = "Image not found"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[put("/pipelines/{pipeline_id}/image")]
+pub async fn update_pipeline_image(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+    image: Json<PutPipelineImageRequest>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+    let image_id = image.into_inner().image_id;
+
+    let before = db::replicators::read_pipeline_image(&pool, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let image = read_target_image(&pool, image_id).await?;
+    let pinned = image_id.is_some();
+
+    db::replicators::update_pipeline_image(&pool, tenant_id, pipeline_id, image.id, pinned)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        tenant_id,
+        AuditResource::Pipeline,
+        &pipeline_id.to_string(),
+        image_snapshot(before.image_id, before.pinned),
+        image_snapshot(image.id, pinned),
+    )
+    .await?;
+
+    Ok(Json(GetPipelineImageResponse {
+        image_id: image.id,
+        image_name: image.name,
+        pinned,
+    }))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostUpgradePipelinesRequest,
+    responses(
+        (status = 200, description = "Move the tenant's pipelines which aren't pinned to an image to the given or default image. The image is used the next time a pipeline is started or restarted.", body = PostUpgradePipelinesResponse),
+        (status = 400, description = "Image not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/upgrade")]
+pub async fn upgrade_pipelines(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    upgrade: Json<PostUpgradePipelinesRequest>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let image = read_target_image(&pool, upgrade.into_inner().image_id).await?;
+
+    let upgraded = db::replicators::upgrade_unpinned_pipelines(&pool, tenant_id, image.id).await?;
+    for pipeline in &upgraded {
+        record_change(
+            &req,
+            pool.get_ref(),
+            tenant_id,
+            AuditResource::Pipeline,
+            &pipeline.pipeline_id.to_string(),
+            image_snapshot(pipeline.previous_image_id, false),
+            image_snapshot(image.id, false),
+        )
+        .await?;
+    }
+
+    Ok(Json(PostUpgradePipelinesResponse {
+        image_id: image.id,
+        pipeline_ids: upgraded.iter().map(|p| p.pipeline_id).collect(),
+    }))
+}
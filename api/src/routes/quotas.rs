@@ -0,0 +1,222 @@
+use actix_web::{
+    get,
+    http::{header::ContentType, StatusCode},
+    put,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::db::{self, audit_logs::AuditResource, quotas::TenantQuotas};
+
+use super::{audit::record_change, ErrorMessage};
+
+/// A limit of a tenant's resources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Quota {
+    MaxPipelines,
+    MaxTablesPerPipeline,
+    MaxSinks,
+}
+
+impl Quota {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quota::MaxPipelines => "max_pipelines",
+            Quota::MaxTablesPerPipeline => "max_tables_per_pipeline",
+            Quota::MaxSinks => "max_sinks",
+        }
+    }
+
+    fn limit(&self, quotas: &TenantQuotas) -> Option<i32> {
+        match self {
+            Quota::MaxPipelines => quotas.max_pipelines,
+            Quota::MaxTablesPerPipeline => quotas.max_tables_per_pipeline,
+            Quota::MaxSinks => quotas.max_sinks,
+        }
+    }
+}
+
+/// A request which would take a tenant over one of its quotas
+#[derive(Debug, Error)]
+#[error("quota {} of {limit} exceeded, {requested} requested", quota.as_str())]
+pub struct QuotaExceeded {
+    quota: Quota,
+    limit: i32,
+    requested: i64,
+}
+
+/// Error returned when a request would exceed a quota of the tenant
+#[derive(Serialize, ToSchema)]
+pub struct QuotaExceededMessage {
+    #[schema(example = "quota max_pipelines of 10 exceeded, 11 requested")]
+    error: String,
+    quota: Quota,
+    #[schema(example = 10)]
+    limit: i32,
+    /// Number of resources the tenant would have after the request
+    #[schema(example = 11)]
+    requested: i64,
+}
+
+impl ResponseError for QuotaExceeded {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = QuotaExceededMessage {
+            error: self.to_string(),
+            quota: self.quota,
+            limit: self.limit,
+            requested: self.requested,
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+/// Checks that a tenant with `quotas` may have `requested` resources limited
+/// by `quota`
+pub(super) fn check_quota(
+    quotas: &TenantQuotas,
+    quota: Quota,
+    requested: i64,
+) -> Result<(), QuotaExceeded> {
+    match quota.limit(quotas) {
+        Some(limit) if requested > limit as i64 => Err(QuotaExceeded {
+            quota,
+            limit,
+            requested,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Error)]
+enum QuotaError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("tenant with id {0} not found")]
+    TenantNotFound(String),
+
+    #[error("invalid quota: {0} can't be negative")]
+    NegativeQuota(&'static str),
+}
+
+impl QuotaError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            QuotaError::DatabaseError(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for QuotaError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            QuotaError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            QuotaError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            QuotaError::NegativeQuota(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+async fn check_tenant_exists(pool: &PgPool, tenant_id: &str) -> Result<(), QuotaError> {
+    db::tenants::read_tenant(pool, tenant_id)
+        .await?
+        .ok_or(QuotaError::TenantNotFound(tenant_id.to_string()))?;
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("tenant_id" = String, Path, description = "Id of the tenant"),
+    ),
+    responses(
+        (status = 200, description = "Return the quotas of tenant with id = tenant_id", body = TenantQuotas),
+        (status = 404, description = "Tenant not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/tenants/{tenant_id}/quotas")]
+pub async fn read_tenant_quotas(
+    pool: Data<PgPool>,
+    tenant_id: Path<String>,
+) -> Result<impl Responder, QuotaError> {
+    let tenant_id = tenant_id.into_inner();
+    check_tenant_exists(&pool, &tenant_id).await?;
+    let quotas = db::quotas::read_quotas(pool.get_ref(), &tenant_id).await?;
+    Ok(Json(quotas))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = TenantQuotas,
+    params(
+        ("tenant_id" = String, Path, description = "Id of the tenant"),
+    ),
+    responses(
+        (status = 200, description = "Set the quotas of tenant with id = tenant_id, which apply to later creates and updates"),
+        (status = 400, description = "Negative quota"),
+        (status = 404, description = "Tenant not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[put("/tenants/{tenant_id}/quotas")]
+pub async fn update_tenant_quotas(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    tenant_id: Path<String>,
+    quotas: Json<TenantQuotas>,
+) -> Result<impl Responder, QuotaError> {
+    let tenant_id = tenant_id.into_inner();
+    let quotas = quotas.into_inner();
+    for quota in [
+        Quota::MaxPipelines,
+        Quota::MaxTablesPerPipeline,
+        Quota::MaxSinks,
+    ] {
+        if quota.limit(&quotas).is_some_and(|limit| limit < 0) {
+            return Err(QuotaError::NegativeQuota(quota.as_str()));
+        }
+    }
+    check_tenant_exists(&pool, &tenant_id).await?;
+
+    let before = db::quotas::read_quotas(pool.get_ref(), &tenant_id).await?;
+    db::quotas::set_quotas(&pool, &tenant_id, &quotas).await?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        &tenant_id,
+        AuditResource::Quotas,
+        &tenant_id,
+        Some(serde_json::json!(before)),
+        Some(serde_json::json!(quotas)),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
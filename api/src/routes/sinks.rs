@@ -2,38 +2,63 @@ use actix_web::{
     delete, get,
     http::{header::ContentType, StatusCode},
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use crate::{
+    connection_test::{self, ConnectionTestResult},
     db::{
         self,
+        audit_logs::AuditResource,
+        pagination::PaginationError,
         sinks::{SinkConfig, SinksDbError},
     },
     encryption::EncryptionKey,
     routes::extract_tenant_id,
 };
 
-use super::{ErrorMessage, TenantIdError};
+use super::{
+    audit::record_change,
+    check_batch_size,
+    quotas::{check_quota, Quota, QuotaExceeded},
+    BatchItem, BatchItemResult, BatchResponse, BatchTooLarge, ErrorMessage, ListQuery,
+    ListResponse, TenantIdError,
+};
 
 #[derive(Debug, Error)]
-enum SinkError {
+pub(super) enum SinkError {
     #[error("database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
     #[error("sink with id {0} not found")]
     SinkNotFound(i64),
 
+    #[error("deleted sink with id {0} not found")]
+    DeletedSinkNotFound(i64),
+
+    #[error("sink with id {0} is used by pipelines {1:?}")]
+    SinkInUse(i64, Vec<i64>),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
+    #[error(transparent)]
+    BatchTooLarge(#[from] BatchTooLarge),
+
     #[error("sinks db error: {0}")]
     SinksDb(#[from] SinksDbError),
+
+    #[error(transparent)]
+    QuotaExceeded(#[from] QuotaExceeded),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
 }
 
 impl SinkError {
@@ -53,12 +78,19 @@ impl ResponseError for SinkError {
             SinkError::DatabaseError(_) | SinkError::SinksDb(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
-            SinkError::SinkNotFound(_) => StatusCode::NOT_FOUND,
-            SinkError::TenantId(_) => StatusCode::BAD_REQUEST,
+            SinkError::SinkNotFound(_) | SinkError::DeletedSinkNotFound(_) => StatusCode::NOT_FOUND,
+            SinkError::SinkInUse(_, _) => StatusCode::CONFLICT,
+            SinkError::TenantId(_) | SinkError::Pagination(_) | SinkError::BatchTooLarge(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SinkError::QuotaExceeded(e) => e.status_code(),
         }
     }
 
     fn error_response(&self) -> HttpResponse {
+        if let SinkError::QuotaExceeded(e) = self {
+            return e.error_response();
+        }
         let error_message = ErrorMessage {
             error: self.to_message(),
         };
@@ -93,11 +125,124 @@ pub struct GetSinkResponse {
     config: SinkConfig,
 }
 
+/// The sink as recorded in the audit log, with its service account key
+/// redacted
+async fn read_sink_snapshot(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<Option<Value>, SinksDbError> {
+    let snapshot = db::sinks::read_redacted_sink(executor, tenant_id, sink_id)
+        .await?
+        .map(|s| {
+            serde_json::json!({
+                "name": s.name,
+                "config": s.config,
+            })
+        });
+    Ok(snapshot)
+}
+
+/// Creates a sink in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config
+pub(super) async fn create_sink_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    sink: PostSinkRequest,
+) -> Result<i64, SinkError> {
+    let quotas = db::quotas::lock_quotas(txn, tenant_id).await?;
+    let sinks = db::quotas::count_sinks(&mut **txn, tenant_id).await?;
+    check_quota(&quotas, Quota::MaxSinks, sinks + 1)?;
+
+    let id =
+        db::sinks::create_sink_txn(txn, tenant_id, &sink.name, sink.config, encryption_key).await?;
+    let after = read_sink_snapshot(&mut **txn, tenant_id, id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Sink,
+        &id.to_string(),
+        None,
+        after,
+    )
+    .await?;
+    Ok(id)
+}
+
+/// Updates a sink in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config
+pub(super) async fn update_sink_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    sink_id: i64,
+    sink: PostSinkRequest,
+) -> Result<(), SinkError> {
+    let before = read_sink_snapshot(&mut **txn, tenant_id, sink_id).await?;
+    db::sinks::update_sink_txn(
+        txn,
+        tenant_id,
+        &sink.name,
+        sink_id,
+        sink.config,
+        encryption_key,
+    )
+    .await?
+    .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let after = read_sink_snapshot(&mut **txn, tenant_id, sink_id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Sink,
+        &sink_id.to_string(),
+        before,
+        after,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes a sink in the transaction, as a request or a change of an applied
+/// tenant config
+pub(super) async fn delete_sink_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<(), SinkError> {
+    let pipeline_ids =
+        db::pipelines::read_pipeline_ids_by_sink(&mut **txn, tenant_id, sink_id).await?;
+    if !pipeline_ids.is_empty() {
+        return Err(SinkError::SinkInUse(sink_id, pipeline_ids));
+    }
+    let before = read_sink_snapshot(&mut **txn, tenant_id, sink_id).await?;
+    db::sinks::delete_sink(&mut **txn, tenant_id, sink_id)
+        .await?
+        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Sink,
+        &sink_id.to_string(),
+        before,
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
 #[utoipa::path(
     context_path = "/v1",
     request_body = PostSinkRequest,
     responses(
         (status = 200, description = "Create new sink", body = PostSinkResponse),
+        (status = 403, description = "Sinks quota of the tenant exceeded", body = QuotaExceededMessage),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -108,11 +253,10 @@ pub async fn create_sink(
     encryption_key: Data<EncryptionKey>,
     sink: Json<PostSinkRequest>,
 ) -> Result<impl Responder, SinkError> {
-    let sink = sink.0;
     let tenant_id = extract_tenant_id(&req)?;
-    let name = sink.name;
-    let config = sink.config;
-    let id = db::sinks::create_sink(&pool, tenant_id, &name, config, &encryption_key).await?;
+    let mut txn = pool.begin().await?;
+    let id = create_sink_item(&req, &mut txn, &encryption_key, tenant_id, sink.0).await?;
+    txn.commit().await?;
     let response = PostSinkResponse { id };
     Ok(Json(response))
 }
@@ -132,12 +276,11 @@ pub async fn create_sink(
 pub async fn read_sink(
     req: HttpRequest,
     pool: Data<PgPool>,
-    encryption_key: Data<EncryptionKey>,
     sink_id: Path<i64>,
 ) -> Result<impl Responder, SinkError> {
     let tenant_id = extract_tenant_id(&req)?;
     let sink_id = sink_id.into_inner();
-    let response = db::sinks::read_sink(&pool, tenant_id, sink_id, &encryption_key)
+    let response = db::sinks::read_redacted_sink(pool.get_ref(), tenant_id, sink_id)
         .await?
         .map(|s| GetSinkResponse {
             id: s.id,
@@ -169,25 +312,66 @@ pub async fn update_sink(
     encryption_key: Data<EncryptionKey>,
     sink: Json<PostSinkRequest>,
 ) -> Result<impl Responder, SinkError> {
-    let sink = sink.0;
     let tenant_id = extract_tenant_id(&req)?;
     let sink_id = sink_id.into_inner();
-    let name = sink.name;
-    let config = sink.config;
-    db::sinks::update_sink(&pool, tenant_id, &name, sink_id, config, &encryption_key)
-        .await?
-        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let mut txn = pool.begin().await?;
+    update_sink_item(&req, &mut txn, &encryption_key, tenant_id, sink_id, sink.0).await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = Vec<SinkBatchItem>,
+    responses(
+        (status = 200, description = "Create the items without an id and update those with one, one after another, returning the result of each item", body = BatchResponse),
+        (status = 400, description = "Too many items"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sinks/batch")]
+pub async fn batch_sinks(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    items: Json<Vec<BatchItem<PostSinkRequest>>>,
+) -> Result<impl Responder, SinkError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let items = items.into_inner();
+    check_batch_size(items.len())?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for (index, item) in items.into_iter().enumerate() {
+        // each item is committed on its own, a failed item is rolled back
+        let mut txn = pool.begin().await?;
+        let result = match item.id {
+            Some(id) => update_sink_item(&req, &mut txn, &encryption_key, tenant_id, id, item.item)
+                .await
+                .map(|()| id),
+            None => create_sink_item(&req, &mut txn, &encryption_key, tenant_id, item.item).await,
+        };
+        let result = match result {
+            Ok(id) => txn.commit().await.map(|()| id).map_err(SinkError::from),
+            Err(e) => Err(e),
+        };
+        results.push(match result {
+            Ok(id) => BatchItemResult::succeeded(index, id),
+            Err(e) => BatchItemResult::failed(index, e.status_code(), e.to_message()),
+        });
+    }
+
+    Ok(Json(BatchResponse { results }))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
         ("sink_id" = i64, Path, description = "Id of the sink"),
     ),
     responses(
-        (status = 200, description = "Delete sink with id = sink_id"),
+        (status = 200, description = "Delete sink with id = sink_id, which can be restored until it's purged after the retention window"),
         (status = 404, description = "Sink not found"),
+        (status = 409, description = "Sink used by pipelines"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -199,16 +383,109 @@ pub async fn delete_sink(
 ) -> Result<impl Responder, SinkError> {
     let tenant_id = extract_tenant_id(&req)?;
     let sink_id = sink_id.into_inner();
-    db::sinks::delete_sink(&pool, tenant_id, sink_id)
+    let mut txn = pool.begin().await?;
+    delete_sink_item(&req, &mut txn, tenant_id, sink_id).await?;
+    txn.commit().await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("sink_id" = i64, Path, description = "Id of the sink"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted sink with id = sink_id"),
+        (status = 403, description = "Sinks quota of the tenant exceeded", body = QuotaExceededMessage),
+        (status = 404, description = "Deleted sink not found, or already purged"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sinks/{sink_id}/restore")]
+pub async fn restore_sink(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    sink_id: Path<i64>,
+) -> Result<impl Responder, SinkError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let sink_id = sink_id.into_inner();
+
+    let mut txn = pool.begin().await?;
+    let quotas = db::quotas::lock_quotas(&mut txn, tenant_id).await?;
+    let sinks = db::quotas::count_sinks(&mut *txn, tenant_id).await?;
+    check_quota(&quotas, Quota::MaxSinks, sinks + 1)?;
+    db::sinks::restore_sink(&mut *txn, tenant_id, sink_id)
         .await?
-        .ok_or(SinkError::SinkNotFound(sink_id))?;
+        .ok_or(SinkError::DeletedSinkNotFound(sink_id))?;
+    let after = read_sink_snapshot(&mut *txn, tenant_id, sink_id).await?;
+    record_change(
+        &req,
+        &mut *txn,
+        tenant_id,
+        AuditResource::Sink,
+        &sink_id.to_string(),
+        None,
+        after,
+    )
+    .await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct GetSinkVersionResponse {
+    #[schema(example = 2)]
+    version: i32,
+    #[schema(example = "BigQuery Sink")]
+    name: String,
+    config: SinkConfig,
+    /// Time the version was created, in seconds since the unix epoch
+    #[schema(example = 1_729_328_400)]
+    created_at: i64,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("sink_id" = i64, Path, description = "Id of the sink"),
+    ),
+    responses(
+        (status = 200, description = "Return the versions of the config of sink with id = sink_id, newest first, with their service account keys redacted", body = Vec<GetSinkVersionResponse>),
+        (status = 404, description = "Sink not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/sinks/{sink_id}/versions")]
+pub async fn read_sink_versions(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    sink_id: Path<i64>,
+) -> Result<impl Responder, SinkError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let sink_id = sink_id.into_inner();
+    let versions = db::sinks::read_sink_versions(&pool, tenant_id, sink_id).await?;
+    // Every sink has at least the version it was created with
+    if versions.is_empty() {
+        return Err(SinkError::SinkNotFound(sink_id));
+    }
+    let response: Vec<GetSinkVersionResponse> = versions
+        .into_iter()
+        .map(|v| GetSinkVersionResponse {
+            version: v.version,
+            name: v.name,
+            config: v.config,
+            created_at: v.created_at,
+        })
+        .collect();
+    Ok(Json(response))
+}
+
 #[utoipa::path(
     context_path = "/v1",
+    params(ListQuery),
     responses(
-        (status = 200, description = "Return all sinks"),
+        (status = 200, description = "Return a page of sinks", body = SinksPage),
+        (status = 400, description = "Invalid list parameters"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -216,11 +493,13 @@ pub async fn delete_sink(
 pub async fn read_all_sinks(
     req: HttpRequest,
     pool: Data<PgPool>,
-    encryption_key: Data<EncryptionKey>,
+    query: Query<ListQuery>,
 ) -> Result<impl Responder, SinkError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let params = query.into_inner().into_params()?;
+    let page = db::sinks::read_all_sinks(&pool, tenant_id, &params).await?;
     let mut sinks = vec![];
-    for sink in db::sinks::read_all_sinks(&pool, tenant_id, &encryption_key).await? {
+    for sink in page.items {
         let sink = GetSinkResponse {
             id: sink.id,
             tenant_id: sink.tenant_id,
@@ -229,5 +508,37 @@ pub async fn read_all_sinks(
         };
         sinks.push(sink);
     }
-    Ok(Json(sinks))
+    Ok(Json(ListResponse {
+        items: sinks,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("sink_id" = i64, Path, description = "Id of the sink"),
+    ),
+    responses(
+        (status = 200, description = "Test the connection to sink with id = sink_id", body = ConnectionTestResult),
+        (status = 404, description = "Sink not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sinks/{sink_id}/test")]
+pub async fn test_sink(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    http_client: Data<reqwest::Client>,
+    sink_id: Path<i64>,
+) -> Result<impl Responder, SinkError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let sink_id = sink_id.into_inner();
+    let config = db::sinks::read_sink(&pool, tenant_id, sink_id, &encryption_key)
+        .await?
+        .map(|s| s.config)
+        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let result = connection_test::test_sink(&http_client, &config).await;
+    Ok(Json(result))
 }
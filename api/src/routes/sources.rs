@@ -2,18 +2,25 @@ use actix_web::{
     delete, get,
     http::{header::ContentType, StatusCode},
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use super::{ErrorMessage, TenantIdError};
+use super::{
+    audit::record_change, check_batch_size, BatchItem, BatchItemResult, BatchResponse,
+    BatchTooLarge, ErrorMessage, ListQuery, ListResponse, TenantIdError,
+};
 use crate::{
+    connection_test::{self, ConnectionTestResult},
     db::{
         self,
+        audit_logs::AuditResource,
+        pagination::PaginationError,
         sources::{SourceConfig, SourcesDbError},
     },
     encryption::EncryptionKey,
@@ -24,18 +31,29 @@ pub mod publications;
 pub mod tables;
 
 #[derive(Debug, Error)]
-enum SourceError {
+pub(super) enum SourceError {
     #[error("database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
     #[error("source with id {0} not found")]
     SourceNotFound(i64),
 
+    #[error("deleted source with id {0} not found")]
+    DeletedSourceNotFound(i64),
+
+    #[error("source with id {0} is used by pipelines {1:?}")]
+    SourceInUse(i64, Vec<i64>),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
+    #[error(transparent)]
+    BatchTooLarge(#[from] BatchTooLarge),
+
     #[error("sources db error: {0}")]
     SourcesDb(#[from] SourcesDbError),
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
 }
 
 impl SourceError {
@@ -55,8 +73,13 @@ impl ResponseError for SourceError {
             SourceError::DatabaseError(_) | SourceError::SourcesDb(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
-            SourceError::SourceNotFound(_) => StatusCode::NOT_FOUND,
-            SourceError::TenantId(_) => StatusCode::BAD_REQUEST,
+            SourceError::SourceNotFound(_) | SourceError::DeletedSourceNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            SourceError::SourceInUse(_, _) => StatusCode::CONFLICT,
+            SourceError::TenantId(_)
+            | SourceError::Pagination(_)
+            | SourceError::BatchTooLarge(_) => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -95,6 +118,114 @@ pub struct GetSourceResponse {
     config: SourceConfig,
 }
 
+/// The source as recorded in the audit log, with its password redacted
+async fn read_source_snapshot(
+    executor: impl PgExecutor<'_>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<Option<Value>, SourcesDbError> {
+    let snapshot = db::sources::read_redacted_source(executor, tenant_id, source_id)
+        .await?
+        .map(|s| {
+            serde_json::json!({
+                "name": s.name,
+                "config": s.config,
+            })
+        });
+    Ok(snapshot)
+}
+
+/// Creates a source in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config
+pub(super) async fn create_source_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    source: PostSourceRequest,
+) -> Result<i64, SourceError> {
+    let id =
+        db::sources::create_source_txn(txn, tenant_id, &source.name, source.config, encryption_key)
+            .await?;
+    let after = read_source_snapshot(&mut **txn, tenant_id, id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Source,
+        &id.to_string(),
+        None,
+        after,
+    )
+    .await?;
+    Ok(id)
+}
+
+/// Updates a source in the transaction, as a request, an item of a batch
+/// request or a change of an applied tenant config
+pub(super) async fn update_source_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    source_id: i64,
+    source: PostSourceRequest,
+) -> Result<(), SourceError> {
+    let before = read_source_snapshot(&mut **txn, tenant_id, source_id).await?;
+    db::sources::update_source_txn(
+        txn,
+        tenant_id,
+        &source.name,
+        source_id,
+        source.config,
+        encryption_key,
+    )
+    .await?
+    .ok_or(SourceError::SourceNotFound(source_id))?;
+    let after = read_source_snapshot(&mut **txn, tenant_id, source_id).await?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Source,
+        &source_id.to_string(),
+        before,
+        after,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes a source in the transaction, as a request or a change of an
+/// applied tenant config
+pub(super) async fn delete_source_item(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<(), SourceError> {
+    let pipeline_ids =
+        db::pipelines::read_pipeline_ids_by_source(&mut **txn, tenant_id, source_id).await?;
+    if !pipeline_ids.is_empty() {
+        return Err(SourceError::SourceInUse(source_id, pipeline_ids));
+    }
+    let before = read_source_snapshot(&mut **txn, tenant_id, source_id).await?;
+    db::sources::delete_source(&mut **txn, tenant_id, source_id)
+        .await?
+        .ok_or(SourceError::SourceNotFound(source_id))?;
+    record_change(
+        req,
+        &mut **txn,
+        tenant_id,
+        AuditResource::Source,
+        &source_id.to_string(),
+        before,
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
 #[utoipa::path(
     context_path = "/v1",
     request_body = PostSourceRequest,
@@ -110,11 +241,10 @@ pub async fn create_source(
     encryption_key: Data<EncryptionKey>,
     source: Json<PostSourceRequest>,
 ) -> Result<impl Responder, SourceError> {
-    let source = source.0;
     let tenant_id = extract_tenant_id(&req)?;
-    let name = source.name;
-    let config = source.config;
-    let id = db::sources::create_source(&pool, tenant_id, &name, config, &encryption_key).await?;
+    let mut txn = pool.begin().await?;
+    let id = create_source_item(&req, &mut txn, &encryption_key, tenant_id, source.0).await?;
+    txn.commit().await?;
     let response = PostSourceResponse { id };
     Ok(Json(response))
 }
@@ -134,12 +264,11 @@ pub async fn create_source(
 pub async fn read_source(
     req: HttpRequest,
     pool: Data<PgPool>,
-    encryption_key: Data<EncryptionKey>,
     source_id: Path<i64>,
 ) -> Result<impl Responder, SourceError> {
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
-    let response = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let response = db::sources::read_redacted_source(pool.get_ref(), tenant_id, source_id)
         .await?
         .map(|s| GetSourceResponse {
             id: s.id,
@@ -171,25 +300,76 @@ pub async fn update_source(
     encryption_key: Data<EncryptionKey>,
     source: Json<PostSourceRequest>,
 ) -> Result<impl Responder, SourceError> {
-    let source = source.0;
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
-    let name = source.name;
-    let config = source.config;
-    db::sources::update_source(&pool, tenant_id, &name, source_id, config, &encryption_key)
-        .await?
-        .ok_or(SourceError::SourceNotFound(source_id))?;
+    let mut txn = pool.begin().await?;
+    update_source_item(
+        &req,
+        &mut txn,
+        &encryption_key,
+        tenant_id,
+        source_id,
+        source.0,
+    )
+    .await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = Vec<SourceBatchItem>,
+    responses(
+        (status = 200, description = "Create the items without an id and update those with one, one after another, returning the result of each item", body = BatchResponse),
+        (status = 400, description = "Too many items"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sources/batch")]
+pub async fn batch_sources(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    items: Json<Vec<BatchItem<PostSourceRequest>>>,
+) -> Result<impl Responder, SourceError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let items = items.into_inner();
+    check_batch_size(items.len())?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for (index, item) in items.into_iter().enumerate() {
+        // each item is committed on its own, a failed item is rolled back
+        let mut txn = pool.begin().await?;
+        let result = match item.id {
+            Some(id) => {
+                update_source_item(&req, &mut txn, &encryption_key, tenant_id, id, item.item)
+                    .await
+                    .map(|()| id)
+            }
+            None => create_source_item(&req, &mut txn, &encryption_key, tenant_id, item.item).await,
+        };
+        let result = match result {
+            Ok(id) => txn.commit().await.map(|()| id).map_err(SourceError::from),
+            Err(e) => Err(e),
+        };
+        results.push(match result {
+            Ok(id) => BatchItemResult::succeeded(index, id),
+            Err(e) => BatchItemResult::failed(index, e.status_code(), e.to_message()),
+        });
+    }
+
+    Ok(Json(BatchResponse { results }))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
         ("source_id" = i64, Path, description = "Id of the source"),
     ),
     responses(
-        (status = 200, description = "Delete source with id = source_id"),
+        (status = 200, description = "Delete source with id = source_id, which can be restored until it's purged after the retention window"),
         (status = 404, description = "Source not found"),
+        (status = 409, description = "Source used by pipelines"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -201,16 +381,102 @@ pub async fn delete_source(
 ) -> Result<impl Responder, SourceError> {
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
-    db::sources::delete_source(&pool, tenant_id, source_id)
+    let mut txn = pool.begin().await?;
+    delete_source_item(&req, &mut txn, tenant_id, source_id).await?;
+    txn.commit().await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted source with id = source_id"),
+        (status = 404, description = "Deleted source not found, or already purged"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sources/{source_id}/restore")]
+pub async fn restore_source(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    source_id: Path<i64>,
+) -> Result<impl Responder, SourceError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let source_id = source_id.into_inner();
+    db::sources::restore_source(&pool, tenant_id, source_id)
         .await?
-        .ok_or(SourceError::SourceNotFound(source_id))?;
+        .ok_or(SourceError::DeletedSourceNotFound(source_id))?;
+    let after = read_source_snapshot(pool.get_ref(), tenant_id, source_id).await?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        tenant_id,
+        AuditResource::Source,
+        &source_id.to_string(),
+        None,
+        after,
+    )
+    .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct GetSourceVersionResponse {
+    #[schema(example = 2)]
+    version: i32,
+    #[schema(example = "Postgres Source")]
+    name: String,
+    config: SourceConfig,
+    /// Time the version was created, in seconds since the unix epoch
+    #[schema(example = 1_729_328_400)]
+    created_at: i64,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Return the versions of the config of source with id = source_id, newest first, with their passwords redacted", body = Vec<GetSourceVersionResponse>),
+        (status = 404, description = "Source not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/sources/{source_id}/versions")]
+pub async fn read_source_versions(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    source_id: Path<i64>,
+) -> Result<impl Responder, SourceError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let source_id = source_id.into_inner();
+    let versions = db::sources::read_source_versions(&pool, tenant_id, source_id).await?;
+    // Every source has at least the version it was created with
+    if versions.is_empty() {
+        return Err(SourceError::SourceNotFound(source_id));
+    }
+    let response: Vec<GetSourceVersionResponse> = versions
+        .into_iter()
+        .map(|v| GetSourceVersionResponse {
+            version: v.version,
+            name: v.name,
+            config: v.config,
+            created_at: v.created_at,
+        })
+        .collect();
+    Ok(Json(response))
+}
+
 #[utoipa::path(
     context_path = "/v1",
+    params(ListQuery),
     responses(
-        (status = 200, description = "Return all sources"),
+        (status = 200, description = "Return a page of sources", body = SourcesPage),
+        (status = 400, description = "Invalid list parameters"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -218,11 +484,13 @@ pub async fn delete_source(
 pub async fn read_all_sources(
     req: HttpRequest,
     pool: Data<PgPool>,
-    encryption_key: Data<EncryptionKey>,
+    query: Query<ListQuery>,
 ) -> Result<impl Responder, SourceError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let params = query.into_inner().into_params()?;
+    let page = db::sources::read_all_sources(&pool, tenant_id, &params).await?;
     let mut sources = vec![];
-    for source in db::sources::read_all_sources(&pool, tenant_id, &encryption_key).await? {
+    for source in page.items {
         let source = GetSourceResponse {
             id: source.id,
             tenant_id: source.tenant_id,
@@ -231,5 +499,51 @@ pub async fn read_all_sources(
         };
         sources.push(source);
     }
-    Ok(Json(sources))
+    Ok(Json(ListResponse {
+        items: sources,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TestSourceRequest {
+    /// Also checks that this publication exists
+    #[schema(example = "my_publication")]
+    pub publication_name: Option<String>,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = TestSourceRequest,
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Test the connection to source with id = source_id", body = ConnectionTestResult),
+        (status = 404, description = "Source not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sources/{source_id}/test")]
+pub async fn test_source(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    source_id: Path<i64>,
+    test: Json<TestSourceRequest>,
+) -> Result<impl Responder, SourceError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let source_id = source_id.into_inner();
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
+        .await?
+        .map(|s| s.config)
+        .ok_or(SourceError::SourceNotFound(source_id))?;
+    let SourceConfig::Postgres { slot_name, .. } = &config;
+    let result = connection_test::test_source(
+        &config.connect_options(),
+        slot_name,
+        test.publication_name.as_deref(),
+    )
+    .await;
+    Ok(Json(result))
 }
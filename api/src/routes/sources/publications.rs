@@ -100,7 +100,7 @@ pub async fn create_publication(
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
 
-    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
         .await?
         .map(|s| s.config)
         .ok_or(PublicationError::SourceNotFound(source_id))?;
@@ -138,7 +138,7 @@ pub async fn read_publication(
     let tenant_id = extract_tenant_id(&req)?;
     let (source_id, publication_name) = source_id_and_pub_name.into_inner();
 
-    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
         .await?
         .map(|s| s.config)
         .ok_or(PublicationError::SourceNotFound(source_id))?;
@@ -175,7 +175,7 @@ pub async fn update_publication(
     let tenant_id = extract_tenant_id(&req)?;
     let (source_id, publication_name) = source_id_and_pub_name.into_inner();
 
-    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
         .await?
         .map(|s| s.config)
         .ok_or(PublicationError::SourceNotFound(source_id))?;
@@ -213,7 +213,7 @@ pub async fn delete_publication(
     let tenant_id = extract_tenant_id(&req)?;
     let (source_id, publication_name) = source_id_and_pub_name.into_inner();
 
-    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
         .await?
         .map(|s| s.config)
         .ok_or(PublicationError::SourceNotFound(source_id))?;
@@ -230,7 +230,7 @@ pub async fn delete_publication(
         ("source_id" = i64, Path, description = "Id of the source"),
     ),
     responses(
-        (status = 200, description = "Return all publications"),
+        (status = 200, description = "Return all publications", body = Vec<Publication>),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -244,7 +244,7 @@ pub async fn read_all_publications(
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
 
-    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+    let config = db::sources::read_source(pool.get_ref(), tenant_id, source_id, &encryption_key)
         .await?
         .map(|s| s.config)
         .ok_or(PublicationError::SourceNotFound(source_id))?;
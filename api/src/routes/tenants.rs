@@ -2,17 +2,20 @@ use actix_web::{
     delete, get,
     http::{header::ContentType, StatusCode},
     post, put,
-    web::{Data, Json, Path},
-    HttpResponse, Responder, ResponseError,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use crate::db;
+use crate::db::{self, audit_logs::AuditResource, pagination::PaginationError, tenants::Tenant};
 
-use super::ErrorMessage;
+use super::{audit::record_change, ErrorMessage, ListQuery, ListResponse};
+
+pub mod config;
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateTenantRequest {
@@ -40,6 +43,9 @@ enum TenantError {
 
     #[error("tenant with id {0} not found")]
     TenantNotFound(String),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
 }
 
 impl TenantError {
@@ -58,6 +64,7 @@ impl ResponseError for TenantError {
         match self {
             TenantError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TenantError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            TenantError::Pagination(_) => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -81,6 +88,24 @@ pub struct GetTenantResponse {
     name: String,
 }
 
+/// The tenant as recorded in the audit log
+fn tenant_snapshot(tenant: &Tenant) -> Value {
+    serde_json::json!({
+        "id": tenant.id,
+        "name": tenant.name,
+    })
+}
+
+async fn read_tenant_snapshot(
+    pool: &PgPool,
+    tenant_id: &str,
+) -> Result<Option<Value>, sqlx::Error> {
+    Ok(db::tenants::read_tenant(pool, tenant_id)
+        .await?
+        .as_ref()
+        .map(tenant_snapshot))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     request_body = CreateTenantRequest,
@@ -91,6 +116,7 @@ pub struct GetTenantResponse {
 )]
 #[post("/tenants")]
 pub async fn create_tenant(
+    req: HttpRequest,
     pool: Data<PgPool>,
     tenant: Json<CreateTenantRequest>,
 ) -> Result<impl Responder, TenantError> {
@@ -98,6 +124,20 @@ pub async fn create_tenant(
     let id = tenant.id;
     let name = tenant.name;
     let id = db::tenants::create_tenant(&pool, &id, &name).await?;
+    let after = tenant_snapshot(&Tenant {
+        id: id.clone(),
+        name,
+    });
+    record_change(
+        &req,
+        pool.get_ref(),
+        &id,
+        AuditResource::Tenant,
+        &id,
+        None,
+        Some(after),
+    )
+    .await?;
     let response = PostTenantResponse { id };
     Ok(Json(response))
 }
@@ -112,6 +152,7 @@ pub async fn create_tenant(
 )]
 #[put("/tenants/{tenant_id}")]
 pub async fn create_or_update_tenant(
+    req: HttpRequest,
     pool: Data<PgPool>,
     tenant_id: Path<String>,
     tenant: Json<UpdateTenantRequest>,
@@ -119,7 +160,22 @@ pub async fn create_or_update_tenant(
     let tenant = tenant.0;
     let tenant_id = tenant_id.into_inner();
     let name = tenant.name;
+    let before = read_tenant_snapshot(&pool, &tenant_id).await?;
     let id = db::tenants::create_or_update_tenant(&pool, &tenant_id, &name).await?;
+    let after = tenant_snapshot(&Tenant {
+        id: id.clone(),
+        name,
+    });
+    record_change(
+        &req,
+        pool.get_ref(),
+        &id,
+        AuditResource::Tenant,
+        &id,
+        before,
+        Some(after),
+    )
+    .await?;
     let response = PostTenantResponse { id };
     Ok(Json(response))
 }
@@ -165,14 +221,27 @@ pub async fn read_tenant(
 )]
 #[post("/tenants/{tenant_id}")]
 pub async fn update_tenant(
+    req: HttpRequest,
     pool: Data<PgPool>,
     tenant_id: Path<String>,
     tenant: Json<UpdateTenantRequest>,
 ) -> Result<impl Responder, TenantError> {
     let tenant_id = tenant_id.into_inner();
+    let before = read_tenant_snapshot(&pool, &tenant_id).await?;
     db::tenants::update_tenant(&pool, &tenant_id, &tenant.0.name)
         .await?
-        .ok_or(TenantError::TenantNotFound(tenant_id))?;
+        .ok_or(TenantError::TenantNotFound(tenant_id.clone()))?;
+    let after = read_tenant_snapshot(&pool, &tenant_id).await?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        &tenant_id,
+        AuditResource::Tenant,
+        &tenant_id,
+        before,
+        after,
+    )
+    .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -189,32 +258,54 @@ pub async fn update_tenant(
 )]
 #[delete("/tenants/{tenant_id}")]
 pub async fn delete_tenant(
+    req: HttpRequest,
     pool: Data<PgPool>,
     tenant_id: Path<String>,
 ) -> Result<impl Responder, TenantError> {
     let tenant_id = tenant_id.into_inner();
+    let before = read_tenant_snapshot(&pool, &tenant_id).await?;
     db::tenants::delete_tenant(&pool, &tenant_id)
         .await?
-        .ok_or(TenantError::TenantNotFound(tenant_id))?;
+        .ok_or(TenantError::TenantNotFound(tenant_id.clone()))?;
+    record_change(
+        &req,
+        pool.get_ref(),
+        &tenant_id,
+        AuditResource::Tenant,
+        &tenant_id,
+        before,
+        None,
+    )
+    .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
 #[utoipa::path(
     context_path = "/v1",
+    params(ListQuery),
     responses(
-        (status = 200, description = "Return all tenants"),
+        (status = 200, description = "Return a page of tenants", body = TenantsPage),
+        (status = 400, description = "Invalid list parameters"),
         (status = 500, description = "Internal server error")
     )
 )]
 #[get("/tenants")]
-pub async fn read_all_tenants(pool: Data<PgPool>) -> Result<impl Responder, TenantError> {
-    let response: Vec<GetTenantResponse> = db::tenants::read_all_tenants(&pool)
-        .await?
-        .drain(..)
+pub async fn read_all_tenants(
+    pool: Data<PgPool>,
+    query: Query<ListQuery>,
+) -> Result<impl Responder, TenantError> {
+    let params = query.into_inner().into_params()?;
+    let page = db::tenants::read_all_tenants(&pool, &params).await?;
+    let items: Vec<GetTenantResponse> = page
+        .items
+        .into_iter()
         .map(|t| GetTenantResponse {
             id: t.id,
             name: t.name,
         })
         .collect();
-    Ok(Json(response))
+    Ok(Json(ListResponse {
+        items,
+        next_cursor: page.next_cursor,
+    }))
 }
@@ -0,0 +1,538 @@
+use std::collections::{BTreeMap, HashSet};
+
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    put,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    db::{
+        self,
+        pipelines::PipelineConfig,
+        sinks::{SinkConfig, SinksDbError},
+        sources::{SourceConfig, SourcesDbError},
+    },
+    encryption::EncryptionKey,
+    routes::{
+        pipelines::{
+            create_pipeline_item, delete_pipeline_item, update_pipeline_item, PipelineError,
+            PostPipelineRequest,
+        },
+        sinks::{create_sink_item, delete_sink_item, update_sink_item, PostSinkRequest, SinkError},
+        sources::{
+            create_source_item, delete_source_item, update_source_item, PostSourceRequest,
+            SourceError,
+        },
+        ErrorMessage,
+    },
+};
+
+#[derive(Debug, Error)]
+enum ConfigError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("sources db error: {0}")]
+    SourcesDb(#[from] SourcesDbError),
+
+    #[error("sinks db error: {0}")]
+    SinksDb(#[from] SinksDbError),
+
+    #[error("invalid pipeline config in db")]
+    InvalidPipelineConfig(#[from] serde_json::Error),
+
+    #[error("tenant with id {0} not found")]
+    TenantNotFound(String),
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("the tenant has several {0} named {1}, which a config can't tell apart")]
+    AmbiguousName(&'static str, String),
+
+    #[error(transparent)]
+    Source(#[from] SourceError),
+
+    #[error(transparent)]
+    Sink(#[from] SinkError),
+
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+}
+
+impl ConfigError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            ConfigError::DatabaseError(_)
+            | ConfigError::SourcesDb(_)
+            | ConfigError::SinksDb(_)
+            | ConfigError::InvalidPipelineConfig(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for ConfigError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfigError::DatabaseError(_)
+            | ConfigError::SourcesDb(_)
+            | ConfigError::SinksDb(_)
+            | ConfigError::InvalidPipelineConfig(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ConfigError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            ConfigError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            ConfigError::AmbiguousName(_, _) => StatusCode::CONFLICT,
+            ConfigError::Source(e) => e.status_code(),
+            ConfigError::Sink(e) => e.status_code(),
+            ConfigError::Pipeline(e) => e.status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfigError::Source(e) => return e.error_response(),
+            ConfigError::Sink(e) => return e.error_response(),
+            ConfigError::Pipeline(e) => return e.error_response(),
+            _ => {}
+        }
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+/// The sources, sinks and pipelines a tenant should have. Sources and sinks
+/// are identified by their names, pipelines by the names of their source and
+/// sink.
+#[derive(Deserialize, ToSchema)]
+pub struct TenantConfig {
+    #[serde(default)]
+    sources: Vec<DeclaredSource>,
+    #[serde(default)]
+    sinks: Vec<DeclaredSink>,
+    #[serde(default)]
+    pipelines: Vec<DeclaredPipeline>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeclaredSource {
+    #[schema(example = "Postgres Source")]
+    name: String,
+    #[schema(required = true)]
+    config: SourceConfig,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeclaredSink {
+    #[schema(example = "BigQuery Sink")]
+    name: String,
+    #[schema(required = true)]
+    config: SinkConfig,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeclaredPipeline {
+    /// Name of a source of the config
+    #[schema(example = "Postgres Source")]
+    source: String,
+    /// Name of a sink of the config
+    #[schema(example = "BigQuery Sink")]
+    sink: String,
+    publication_name: String,
+    config: PipelineConfig,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApplyQuery {
+    /// Only return the changes applying the config would make
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Names of the resources of one kind which applying a config creates,
+/// updates, deletes or leaves unchanged. Pipelines are named
+/// `source -> sink`.
+#[derive(Default, Serialize, ToSchema)]
+pub struct PlannedChanges {
+    create: Vec<String>,
+    update: Vec<String>,
+    delete: Vec<String>,
+    unchanged: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApplyPlan {
+    /// False if the changes were only planned, because of `dry_run`
+    applied: bool,
+    sources: PlannedChanges,
+    sinks: PlannedChanges,
+    pipelines: PlannedChanges,
+}
+
+/// The change bringing a current resource, with its id, to the declared one
+enum Change<T> {
+    Create(T),
+    Update(i64, T),
+    Delete(i64),
+    Unchanged(i64),
+}
+
+/// Diffs the declared resources against the current ones, both keyed by
+/// what identifies them in a config. Current resources which aren't
+/// declared are deleted.
+fn diff<K: Ord, T: PartialEq>(
+    mut current: BTreeMap<K, (i64, T)>,
+    declared: Vec<(K, T)>,
+) -> Vec<(K, Change<T>)> {
+    let mut changes = Vec::with_capacity(declared.len());
+    for (key, spec) in declared {
+        let change = match current.remove(&key) {
+            None => Change::Create(spec),
+            Some((id, current_spec)) if current_spec == spec => Change::Unchanged(id),
+            Some((id, _)) => Change::Update(id, spec),
+        };
+        changes.push((key, change));
+    }
+    changes.extend(
+        current
+            .into_iter()
+            .map(|(key, (id, _))| (key, Change::Delete(id))),
+    );
+    changes
+}
+
+fn planned_changes<K, T>(
+    changes: &[(K, Change<T>)],
+    name: impl Fn(&K) -> String,
+) -> PlannedChanges {
+    let mut planned = PlannedChanges::default();
+    for (key, change) in changes {
+        let names = match change {
+            Change::Create(_) => &mut planned.create,
+            Change::Update(_, _) => &mut planned.update,
+            Change::Delete(_) => &mut planned.delete,
+            Change::Unchanged(_) => &mut planned.unchanged,
+        };
+        names.push(name(key));
+    }
+    planned
+}
+
+fn pipeline_name((source, sink): &(String, String)) -> String {
+    format!("{source} -> {sink}")
+}
+
+/// What a pipeline is compared by, besides its source and sink
+#[derive(PartialEq)]
+struct PipelineSpec {
+    publication_name: String,
+    config: PipelineConfig,
+}
+
+struct Plan {
+    sources: Vec<(String, Change<SourceConfig>)>,
+    sinks: Vec<(String, Change<SinkConfig>)>,
+    pipelines: Vec<((String, String), Change<PipelineSpec>)>,
+}
+
+impl Plan {
+    fn summary(&self, applied: bool) -> ApplyPlan {
+        ApplyPlan {
+            applied,
+            sources: planned_changes(&self.sources, String::clone),
+            sinks: planned_changes(&self.sinks, String::clone),
+            pipelines: planned_changes(&self.pipelines, pipeline_name),
+        }
+    }
+}
+
+fn check_unique(kind: &str, names: impl Iterator<Item = String>) -> Result<(), ConfigError> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name.clone()) {
+            return Err(ConfigError::InvalidConfig(format!(
+                "{kind} {name} is declared more than once"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the config names each resource once and that its pipelines
+/// only use its sources and sinks
+fn validate_config(config: &TenantConfig) -> Result<(), ConfigError> {
+    check_unique("source", config.sources.iter().map(|s| s.name.clone()))?;
+    check_unique("sink", config.sinks.iter().map(|s| s.name.clone()))?;
+    check_unique(
+        "pipeline",
+        config
+            .pipelines
+            .iter()
+            .map(|p| pipeline_name(&(p.source.clone(), p.sink.clone()))),
+    )?;
+
+    for pipeline in &config.pipelines {
+        if !config.sources.iter().any(|s| s.name == pipeline.source) {
+            return Err(ConfigError::InvalidConfig(format!(
+                "pipeline uses source {}, which isn't declared",
+                pipeline.source
+            )));
+        }
+        if !config.sinks.iter().any(|s| s.name == pipeline.sink) {
+            return Err(ConfigError::InvalidConfig(format!(
+                "pipeline uses sink {}, which isn't declared",
+                pipeline.sink
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Plans the changes bringing the tenant's current sources, sinks and
+/// pipelines to the config. Configs are compared with their secrets
+/// decrypted, so that a secret which changed updates its resource.
+async fn plan(
+    pool: &PgPool,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    config: TenantConfig,
+) -> Result<Plan, ConfigError> {
+    let mut sources = BTreeMap::new();
+    for id in db::sources::read_source_ids(pool, tenant_id).await? {
+        // a source deleted since its id was read is left out
+        let Some(source) = db::sources::read_source(pool, tenant_id, id, encryption_key).await?
+        else {
+            continue;
+        };
+        if sources.contains_key(&source.name) {
+            return Err(ConfigError::AmbiguousName("sources", source.name));
+        }
+        sources.insert(source.name, (source.id, source.config));
+    }
+
+    let mut sinks = BTreeMap::new();
+    for id in db::sinks::read_sink_ids(pool, tenant_id).await? {
+        let Some(sink) = db::sinks::read_sink(pool, tenant_id, id, encryption_key).await? else {
+            continue;
+        };
+        if sinks.contains_key(&sink.name) {
+            return Err(ConfigError::AmbiguousName("sinks", sink.name));
+        }
+        sinks.insert(sink.name, (sink.id, sink.config));
+    }
+
+    // pipelines are unique by source and sink, whose names are unique
+    let mut pipelines = BTreeMap::new();
+    for id in db::pipelines::read_pipeline_ids(pool, tenant_id).await? {
+        let Some(pipeline) = db::pipelines::read_pipeline(pool, tenant_id, id).await? else {
+            continue;
+        };
+        let spec = PipelineSpec {
+            publication_name: pipeline.publication_name,
+            config: serde_json::from_value(pipeline.config)?,
+        };
+        pipelines.insert(
+            (pipeline.source_name, pipeline.sink_name),
+            (pipeline.id, spec),
+        );
+    }
+
+    Ok(Plan {
+        sources: diff(
+            sources,
+            config
+                .sources
+                .into_iter()
+                .map(|s| (s.name, s.config))
+                .collect(),
+        ),
+        sinks: diff(
+            sinks,
+            config
+                .sinks
+                .into_iter()
+                .map(|s| (s.name, s.config))
+                .collect(),
+        ),
+        pipelines: diff(
+            pipelines,
+            config
+                .pipelines
+                .into_iter()
+                .map(|p| {
+                    let spec = PipelineSpec {
+                        publication_name: p.publication_name,
+                        config: p.config,
+                    };
+                    ((p.source, p.sink), spec)
+                })
+                .collect(),
+        ),
+    })
+}
+
+/// Applies the planned changes one after another in the transaction, so that
+/// an apply which fails part way changes nothing once it's rolled back.
+/// Pipelines are deleted before others are created, so that they don't count
+/// against the tenant's quota, and sources and sinks are deleted last, once
+/// no pipeline uses them.
+async fn apply(
+    req: &HttpRequest,
+    txn: &mut Transaction<'_, Postgres>,
+    encryption_key: &EncryptionKey,
+    tenant_id: &str,
+    plan: Plan,
+) -> Result<(), ConfigError> {
+    let mut source_ids = BTreeMap::new();
+    let mut deleted_source_ids = vec![];
+    for (name, change) in plan.sources {
+        let id = match change {
+            Change::Create(config) => {
+                let source = PostSourceRequest {
+                    name: name.clone(),
+                    config,
+                };
+                create_source_item(req, txn, encryption_key, tenant_id, source).await?
+            }
+            Change::Update(id, config) => {
+                let source = PostSourceRequest {
+                    name: name.clone(),
+                    config,
+                };
+                update_source_item(req, txn, encryption_key, tenant_id, id, source).await?;
+                id
+            }
+            Change::Unchanged(id) => id,
+            Change::Delete(id) => {
+                deleted_source_ids.push(id);
+                continue;
+            }
+        };
+        source_ids.insert(name, id);
+    }
+
+    let mut sink_ids = BTreeMap::new();
+    let mut deleted_sink_ids = vec![];
+    for (name, change) in plan.sinks {
+        let id = match change {
+            Change::Create(config) => {
+                let sink = PostSinkRequest {
+                    name: name.clone(),
+                    config,
+                };
+                create_sink_item(req, txn, encryption_key, tenant_id, sink).await?
+            }
+            Change::Update(id, config) => {
+                let sink = PostSinkRequest {
+                    name: name.clone(),
+                    config,
+                };
+                update_sink_item(req, txn, encryption_key, tenant_id, id, sink).await?;
+                id
+            }
+            Change::Unchanged(id) => id,
+            Change::Delete(id) => {
+                deleted_sink_ids.push(id);
+                continue;
+            }
+        };
+        sink_ids.insert(name, id);
+    }
+
+    for (_, change) in &plan.pipelines {
+        if let Change::Delete(id) = change {
+            delete_pipeline_item(req, txn, tenant_id, *id).await?;
+        }
+    }
+    for ((source, sink), change) in plan.pipelines {
+        let (id, spec) = match change {
+            Change::Create(spec) => (None, spec),
+            Change::Update(id, spec) => (Some(id), spec),
+            Change::Delete(_) | Change::Unchanged(_) => continue,
+        };
+        // the config was validated to only use its own sources and sinks
+        let pipeline = PostPipelineRequest {
+            source_id: source_ids[&source],
+            sink_id: sink_ids[&sink],
+            publication_name: spec.publication_name,
+            config: spec.config,
+        };
+        match id {
+            Some(id) => {
+                update_pipeline_item(req, txn, encryption_key, tenant_id, id, pipeline).await?
+            }
+            None => {
+                create_pipeline_item(req, txn, encryption_key, tenant_id, None, pipeline).await?;
+            }
+        }
+    }
+
+    for id in deleted_sink_ids {
+        delete_sink_item(req, txn, tenant_id, id).await?;
+    }
+    for id in deleted_source_ids {
+        delete_source_item(req, txn, tenant_id, id).await?;
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = TenantConfig,
+    params(
+        ("tenant_id" = String, Path, description = "Id of the tenant"),
+        ApplyQuery,
+    ),
+    responses(
+        (status = 200, description = "Create, update and delete the sources, sinks and pipelines of tenant with id = tenant_id so that they match the config, returning the changes. The changes are made in one transaction, so an apply which fails changes nothing.", body = ApplyPlan),
+        (status = 400, description = "Invalid config"),
+        (status = 403, description = "Quota of the tenant exceeded", body = QuotaExceededMessage),
+        (status = 404, description = "Tenant not found"),
+        (status = 409, description = "Several sources or sinks of the tenant have the same name"),
+        (status = 422, description = "Invalid pipeline", body = ValidationErrorMessage),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[put("/tenants/{tenant_id}/config")]
+pub async fn apply_tenant_config(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    tenant_id: Path<String>,
+    query: Query<ApplyQuery>,
+    config: Json<TenantConfig>,
+) -> Result<impl Responder, ConfigError> {
+    let tenant_id = tenant_id.into_inner();
+    let config = config.into_inner();
+
+    validate_config(&config)?;
+    db::tenants::read_tenant(&pool, &tenant_id)
+        .await?
+        .ok_or(ConfigError::TenantNotFound(tenant_id.clone()))?;
+
+    let plan = plan(&pool, &encryption_key, &tenant_id, config).await?;
+    if query.dry_run {
+        return Ok(Json(plan.summary(false)));
+    }
+    let summary = plan.summary(true);
+    let mut txn = pool.begin().await?;
+    apply(&req, &mut txn, &encryption_key, &tenant_id, plan).await?;
+    txn.commit().await?;
+
+    Ok(Json(summary))
+}
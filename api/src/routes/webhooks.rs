@@ -0,0 +1,397 @@
+use actix_web::{
+    delete, get,
+    http::{header::ContentType, StatusCode},
+    post,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use aws_lc_rs::error::Unspecified;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{
+        self,
+        pagination::PaginationError,
+        webhooks::{Webhook, WebhookDelivery, WebhooksDbError},
+    },
+    encryption::EncryptionKey,
+    routes::extract_tenant_id,
+    webhooks::{generate_webhook_secret, WebhookEvent},
+};
+
+use super::{ErrorMessage, ListQuery, ListResponse, TenantIdError};
+
+/// Number of deliveries returned by the deliveries endpoint
+const RECENT_DELIVERIES_LIMIT: i64 = 50;
+
+#[derive(Debug, Error)]
+enum WebhookError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("webhook with id {0} not found")]
+    WebhookNotFound(i64),
+
+    #[error("tenant id error: {0}")]
+    TenantId(#[from] TenantIdError),
+
+    #[error("invalid webhook: {0}")]
+    InvalidWebhook(String),
+
+    #[error("failed to generate webhook secret")]
+    SecretGeneration(#[from] Unspecified),
+
+    #[error("webhooks db error: {0}")]
+    WebhooksDb(#[from] WebhooksDbError),
+
+    #[error("pagination error: {0}")]
+    Pagination(#[from] PaginationError),
+}
+
+impl WebhookError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            WebhookError::DatabaseError(_) | WebhookError::WebhooksDb(_) => {
+                "internal server error".to_string()
+            }
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::DatabaseError(_)
+            | WebhookError::SecretGeneration(_)
+            | WebhookError::WebhooksDb(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            WebhookError::WebhookNotFound(_) => StatusCode::NOT_FOUND,
+            WebhookError::TenantId(_)
+            | WebhookError::InvalidWebhook(_)
+            | WebhookError::Pagination(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error_message = ErrorMessage {
+            error: self.to_message(),
+        };
+        let body =
+            serde_json::to_string(&error_message).expect("failed to serialize error message");
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PostWebhookRequest {
+    #[schema(example = "On-call alerts", required = true)]
+    pub name: String,
+    #[schema(example = "https://example.com/hooks/pg-replicate", required = true)]
+    pub url: String,
+    /// Events deliveries are sent for
+    pub events: Vec<WebhookEvent>,
+    /// Lag, in seconds, over which a `lag_threshold_exceeded` delivery is
+    /// sent. Required with that event.
+    #[schema(example = 300)]
+    pub lag_threshold_secs: Option<u64>,
+}
+
+/// The secret is only returned when the webhook is created. Deliveries are
+/// signed with it as in the Standard Webhooks specification.
+#[derive(Serialize, ToSchema)]
+pub struct PostWebhookResponse {
+    id: i64,
+    #[schema(example = "whsec_C2FVsBQIhrscChlQIMV+b5sSYspob7oD")]
+    secret: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetWebhookResponse {
+    #[schema(example = 1)]
+    id: i64,
+    #[schema(example = "abcdefghijklmnopqrst")]
+    tenant_id: String,
+    #[schema(example = "On-call alerts")]
+    name: String,
+    #[schema(example = "https://example.com/hooks/pg-replicate")]
+    url: String,
+    events: Vec<WebhookEvent>,
+    #[schema(example = 300)]
+    lag_threshold_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not sent yet or to be retried
+    Pending,
+    Delivered,
+    /// Given up on after too many failed attempts
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetWebhookDeliveryResponse {
+    id: i64,
+    event: String,
+    /// Body of the delivery
+    payload: serde_json::Value,
+    status: DeliveryStatus,
+    attempts: i32,
+    /// Error of the last failed attempt
+    last_error: Option<String>,
+}
+
+fn validate_webhook(webhook: &PostWebhookRequest) -> Result<(), WebhookError> {
+    let url = Url::parse(&webhook.url)
+        .map_err(|e| WebhookError::InvalidWebhook(format!("invalid url: {e}")))?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(WebhookError::InvalidWebhook(
+            "url must be an http or https url".to_string(),
+        ));
+    }
+
+    if webhook.events.is_empty() {
+        return Err(WebhookError::InvalidWebhook(
+            "at least one event is required".to_string(),
+        ));
+    }
+
+    if webhook.events.contains(&WebhookEvent::LagThresholdExceeded)
+        && webhook.lag_threshold_secs.is_none()
+    {
+        return Err(WebhookError::InvalidWebhook(
+            "lag_threshold_secs is required with the lag_threshold_exceeded event".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn event_names(events: &[WebhookEvent]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| event.as_str().to_string())
+        .collect()
+}
+
+fn webhook_response(webhook: Webhook) -> GetWebhookResponse {
+    GetWebhookResponse {
+        id: webhook.id,
+        tenant_id: webhook.tenant_id,
+        name: webhook.name,
+        url: webhook.url,
+        events: webhook
+            .events
+            .iter()
+            .filter_map(|event| WebhookEvent::parse(event))
+            .collect(),
+        lag_threshold_secs: webhook.lag_threshold_secs.map(|secs| secs as u64),
+    }
+}
+
+fn delivery_response(delivery: WebhookDelivery) -> GetWebhookDeliveryResponse {
+    let status = if delivery.delivered {
+        DeliveryStatus::Delivered
+    } else if delivery.pending {
+        DeliveryStatus::Pending
+    } else {
+        DeliveryStatus::Failed
+    };
+    GetWebhookDeliveryResponse {
+        id: delivery.id,
+        event: delivery.event,
+        payload: delivery.payload,
+        status,
+        attempts: delivery.attempts,
+        last_error: delivery.last_error,
+    }
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostWebhookRequest,
+    responses(
+        (status = 200, description = "Create new webhook", body = PostWebhookResponse),
+        (status = 400, description = "Invalid webhook"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/webhooks")]
+pub async fn create_webhook(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    webhook: Json<PostWebhookRequest>,
+) -> Result<impl Responder, WebhookError> {
+    let webhook = webhook.0;
+    let tenant_id = extract_tenant_id(&req)?;
+    validate_webhook(&webhook)?;
+    let (secret, key) = generate_webhook_secret()?;
+    let id = db::webhooks::create_webhook(
+        &pool,
+        tenant_id,
+        &webhook.name,
+        &webhook.url,
+        &event_names(&webhook.events),
+        webhook.lag_threshold_secs.map(|secs| secs as i64),
+        &key,
+        &encryption_key,
+    )
+    .await?;
+    let response = PostWebhookResponse { id, secret };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("webhook_id" = i64, Path, description = "Id of the webhook"),
+    ),
+    responses(
+        (status = 200, description = "Return webhook with id = webhook_id", body = GetWebhookResponse),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/webhooks/{webhook_id}")]
+pub async fn read_webhook(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    webhook_id: Path<i64>,
+) -> Result<impl Responder, WebhookError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let webhook_id = webhook_id.into_inner();
+    let response = db::webhooks::read_webhook(&pool, tenant_id, webhook_id)
+        .await?
+        .map(webhook_response)
+        .ok_or(WebhookError::WebhookNotFound(webhook_id))?;
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = PostWebhookRequest,
+    params(
+        ("webhook_id" = i64, Path, description = "Id of the webhook"),
+    ),
+    responses(
+        (status = 200, description = "Update webhook with id = webhook_id, its secret is kept"),
+        (status = 400, description = "Invalid webhook"),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/webhooks/{webhook_id}")]
+pub async fn update_webhook(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    webhook_id: Path<i64>,
+    webhook: Json<PostWebhookRequest>,
+) -> Result<impl Responder, WebhookError> {
+    let webhook = webhook.0;
+    let tenant_id = extract_tenant_id(&req)?;
+    let webhook_id = webhook_id.into_inner();
+    validate_webhook(&webhook)?;
+    db::webhooks::update_webhook(
+        &pool,
+        tenant_id,
+        webhook_id,
+        &webhook.name,
+        &webhook.url,
+        &event_names(&webhook.events),
+        webhook.lag_threshold_secs.map(|secs| secs as i64),
+    )
+    .await?
+    .ok_or(WebhookError::WebhookNotFound(webhook_id))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("webhook_id" = i64, Path, description = "Id of the webhook"),
+    ),
+    responses(
+        (status = 200, description = "Delete webhook with id = webhook_id"),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[delete("/webhooks/{webhook_id}")]
+pub async fn delete_webhook(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    webhook_id: Path<i64>,
+) -> Result<impl Responder, WebhookError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let webhook_id = webhook_id.into_inner();
+    db::webhooks::delete_webhook(&pool, tenant_id, webhook_id)
+        .await?
+        .ok_or(WebhookError::WebhookNotFound(webhook_id))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "Return a page of the tenant's webhooks", body = WebhooksPage),
+        (status = 400, description = "Invalid list parameters"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/webhooks")]
+pub async fn read_all_webhooks(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    query: Query<ListQuery>,
+) -> Result<impl Responder, WebhookError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let params = query.into_inner().into_params()?;
+    let page = db::webhooks::read_all_webhooks(&pool, tenant_id, &params).await?;
+    let webhooks: Vec<GetWebhookResponse> = page.items.into_iter().map(webhook_response).collect();
+    Ok(Json(ListResponse {
+        items: webhooks,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("webhook_id" = i64, Path, description = "Id of the webhook"),
+    ),
+    responses(
+        (status = 200, description = "Return the latest deliveries of webhook with id = webhook_id, newest first", body = Vec<GetWebhookDeliveryResponse>),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/webhooks/{webhook_id}/deliveries")]
+pub async fn read_webhook_deliveries(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    webhook_id: Path<i64>,
+) -> Result<impl Responder, WebhookError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let webhook_id = webhook_id.into_inner();
+    db::webhooks::read_webhook(&pool, tenant_id, webhook_id)
+        .await?
+        .ok_or(WebhookError::WebhookNotFound(webhook_id))?;
+    let deliveries: Vec<GetWebhookDeliveryResponse> =
+        db::webhooks::read_deliveries(&pool, tenant_id, webhook_id, RECENT_DELIVERIES_LIMIT)
+            .await?
+            .into_iter()
+            .map(delivery_response)
+            .collect();
+    Ok(Json(deliveries))
+}
@@ -1,9 +1,10 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{net::TcpListener, sync::Arc, time::Instant};
 
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{
+    dev::{Server, Service},
+    web, App, HttpServer, ResponseError,
+};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use aws_lc_rs::aead::{RandomizedNonceKey, AES_256_GCM};
-use base64::{prelude::BASE64_STANDARD, Engine};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing_actix_web::TracingLogger;
 use utoipa::OpenApi;
@@ -11,40 +12,58 @@ use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
     authentication::auth_validator,
-    configuration::{DatabaseSettings, Settings},
-    db::publications::Publication,
+    configuration::{DatabaseSettings, MetricsSettings, Settings, WorkerSettings},
     encryption,
+    health::run_health_monitor,
+    jobs::run_job_worker,
     k8s_client::HttpK8sClient,
+    key_provider::load_encryption_key,
+    metrics::ApiMetrics,
+    oidc::OidcValidator,
+    openapi::ApiDoc,
+    orchestrator::run_orchestrator,
+    retention::run_purger,
     routes::{
+        api_keys::{create_api_key, delete_api_key, read_all_api_keys, rotate_api_key},
+        audit::read_audit_logs,
         health_check::health_check,
-        images::{
-            create_image, delete_image, read_all_images, read_image, update_image,
-            GetImageResponse, PostImageRequest, PostImageResponse,
-        },
+        images::{create_image, delete_image, read_all_images, read_image, update_image},
+        jobs::{initial_copy, read_job, resync_tables, validate_sink},
+        metrics::read_metrics,
         pipelines::{
-            create_pipeline, delete_pipeline, get_pipeline_status, read_all_pipelines,
-            read_pipeline, start_pipeline, stop_pipeline, update_pipeline, GetPipelineResponse,
-            PostPipelineRequest, PostPipelineResponse,
+            batch_pipelines, create_pipeline, delete_pipeline,
+            events::stream_pipeline_events,
+            get_pipeline_status,
+            images::{read_pipeline_image, update_pipeline_image, upgrade_pipelines},
+            read_all_pipelines, read_pipeline, read_pipeline_versions, report_pipeline_status,
+            restart_pipeline, restore_pipeline, rollback_pipeline, start_pipeline, stop_pipeline,
+            update_pipeline,
         },
+        quotas::{read_tenant_quotas, update_tenant_quotas},
         sinks::{
-            create_sink, delete_sink, read_all_sinks, read_sink, update_sink, GetSinkResponse,
-            PostSinkRequest, PostSinkResponse,
+            batch_sinks, create_sink, delete_sink, read_all_sinks, read_sink, read_sink_versions,
+            restore_sink, test_sink, update_sink,
         },
         sources::{
-            create_source, delete_source,
+            batch_sources, create_source, delete_source,
             publications::{
                 create_publication, delete_publication, read_all_publications, read_publication,
-                update_publication, CreatePublicationRequest, UpdatePublicationRequest,
+                update_publication,
             },
-            read_all_sources, read_source,
+            read_all_sources, read_source, read_source_versions, restore_source,
             tables::read_table_names,
-            update_source, GetSourceResponse, PostSourceRequest, PostSourceResponse,
+            test_source, update_source,
         },
         tenants::{
-            create_or_update_tenant, create_tenant, delete_tenant, read_all_tenants, read_tenant,
-            update_tenant, CreateTenantRequest, GetTenantResponse, PostTenantResponse,
+            config::apply_tenant_config, create_or_update_tenant, create_tenant, delete_tenant,
+            read_all_tenants, read_tenant, update_tenant,
+        },
+        webhooks::{
+            create_webhook, delete_webhook, read_all_webhooks, read_webhook,
+            read_webhook_deliveries, update_webhook,
         },
     },
+    webhooks::run_delivery_worker,
 };
 
 pub struct Application {
@@ -62,20 +81,19 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
-        let key_bytes = BASE64_STANDARD.decode(&configuration.encryption_key.key)?;
-        let key = RandomizedNonceKey::new(&AES_256_GCM, &key_bytes)?;
-        let encryption_key = encryption::EncryptionKey {
-            id: configuration.encryption_key.id,
-            key,
-        };
+        let encryption_key = load_encryption_key(&configuration.encryption_key).await?;
         let api_key = configuration.api_key;
-        let k8s_client = HttpK8sClient::new().await?;
+        let k8s_client = HttpK8sClient::new(&configuration.kubernetes).await?;
+        let oidc_validator = configuration.oidc.map(OidcValidator::new);
         let server = run(
             listener,
             connection_pool,
             encryption_key,
             api_key,
             Some(k8s_client),
+            oidc_validator,
+            Some(configuration.worker),
+            configuration.metrics,
         )
         .await?;
 
@@ -106,78 +124,65 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 // HttpK8sClient is wrapped in an option because creating it
 // in tests involves setting a default CryptoProvider and it
 // interferes with parallel tasks because only one can be set.
+// The webhook delivery worker, job worker, pipeline health monitor, purger of
+// deleted resources and orchestrator only run with worker settings, the
+// orchestrator also needs a k8s client.
 pub async fn run(
     listener: TcpListener,
     connection_pool: PgPool,
     encryption_key: encryption::EncryptionKey,
     api_key: String,
     http_k8s_client: Option<HttpK8sClient>,
+    oidc_validator: Option<OidcValidator>,
+    worker: Option<WorkerSettings>,
+    metrics_settings: MetricsSettings,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let encryption_key = web::Data::new(encryption_key);
     let api_key = web::Data::new(api_key);
+    let http_client = web::Data::new(reqwest::Client::new());
     let k8s_client = http_k8s_client.map(|client| web::Data::new(Arc::new(client)));
+    let oidc_validator = oidc_validator.map(web::Data::new);
+    let metrics = web::Data::new(ApiMetrics::new(metrics_settings)?);
 
-    #[derive(OpenApi)]
-    #[openapi(
-        paths(
-            crate::routes::health_check::health_check,
-            crate::routes::images::create_image,
-            crate::routes::images::read_image,
-            crate::routes::images::update_image,
-            crate::routes::images::delete_image,
-            crate::routes::images::read_all_images,
-            crate::routes::pipelines::create_pipeline,
-            crate::routes::pipelines::read_pipeline,
-            crate::routes::pipelines::update_pipeline,
-            crate::routes::pipelines::delete_pipeline,
-            crate::routes::pipelines::read_all_pipelines,
-            crate::routes::pipelines::get_pipeline_status,
-            crate::routes::tenants::create_tenant,
-            crate::routes::tenants::create_or_update_tenant,
-            crate::routes::tenants::read_tenant,
-            crate::routes::tenants::update_tenant,
-            crate::routes::tenants::delete_tenant,
-            crate::routes::tenants::read_all_tenants,
-            crate::routes::sources::create_source,
-            crate::routes::sources::read_source,
-            crate::routes::sources::update_source,
-            crate::routes::sources::delete_source,
-            crate::routes::sources::read_all_sources,
-            crate::routes::sources::publications::create_publication,
-            crate::routes::sources::publications::read_publication,
-            crate::routes::sources::publications::update_publication,
-            crate::routes::sources::publications::delete_publication,
-            crate::routes::sources::publications::read_all_publications,
-            crate::routes::sources::tables::read_table_names,
-            crate::routes::sinks::create_sink,
-            crate::routes::sinks::read_sink,
-            crate::routes::sinks::update_sink,
-            crate::routes::sinks::delete_sink,
-            crate::routes::sinks::read_all_sinks,
-        ),
-        components(schemas(
-            PostImageRequest,
-            PostImageResponse,
-            GetImageResponse,
-            PostPipelineRequest,
-            PostPipelineResponse,
-            GetPipelineResponse,
-            CreateTenantRequest,
-            PostTenantResponse,
-            GetTenantResponse,
-            PostSourceRequest,
-            PostSourceResponse,
-            GetSourceResponse,
-            CreatePublicationRequest,
-            UpdatePublicationRequest,
-            Publication,
-            PostSinkRequest,
-            PostSinkResponse,
-            GetSinkResponse,
-        ))
-    )]
-    struct ApiDoc;
+    if let Some(worker) = worker {
+        tokio::spawn(run_delivery_worker(
+            connection_pool.clone(),
+            http_client.get_ref().clone(),
+            encryption_key.clone(),
+            metrics.clone(),
+            worker.clone(),
+        ));
+        tokio::spawn(run_job_worker(
+            connection_pool.clone(),
+            encryption_key.clone(),
+            http_client.get_ref().clone(),
+            k8s_client.clone(),
+            metrics.clone(),
+            worker.clone(),
+        ));
+        tokio::spawn(run_health_monitor(
+            connection_pool.clone(),
+            encryption_key.clone(),
+            k8s_client.clone(),
+            metrics.clone(),
+            worker.clone(),
+        ));
+        tokio::spawn(run_purger(
+            connection_pool.clone(),
+            metrics.clone(),
+            worker.clone(),
+        ));
+        if let Some(k8s_client) = k8s_client.clone() {
+            tokio::spawn(run_orchestrator(
+                connection_pool.clone(),
+                encryption_key.clone(),
+                k8s_client,
+                metrics.clone(),
+                worker,
+            ));
+        }
+    }
 
     //TODO: replace all the context_path = v1 in route modules with the nest attribute
     //when it is available in utoipa 5.0.0: https://github.com/juhaku/utoipa/pull/930
@@ -185,9 +190,30 @@ pub async fn run(
 
     let server = HttpServer::new(move || {
         let authentication = HttpAuthentication::bearer(auth_validator);
+        let request_metrics = metrics.clone();
         let app = App::new()
             .wrap(TracingLogger::default())
+            .wrap_fn(move |req, srv| {
+                let metrics = request_metrics.clone();
+                let method = req.method().to_string();
+                // Requests not matching a route are grouped in one series
+                let path = req
+                    .match_pattern()
+                    .unwrap_or_else(|| "unmatched".to_string());
+                let start = Instant::now();
+                let res = srv.call(req);
+                async move {
+                    let res = res.await;
+                    let status = match &res {
+                        Ok(res) => res.status(),
+                        Err(e) => e.as_response_error().status_code(),
+                    };
+                    metrics.observe_request(&method, &path, status.as_u16(), start.elapsed());
+                    res
+                }
+            })
             .service(health_check)
+            .service(read_metrics)
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
             )
@@ -201,27 +227,68 @@ pub async fn run(
                     .service(update_tenant)
                     .service(delete_tenant)
                     .service(read_all_tenants)
+                    .service(apply_tenant_config)
+                    .service(read_tenant_quotas)
+                    .service(update_tenant_quotas)
+                    .service(read_audit_logs)
+                    //api keys
+                    .service(create_api_key)
+                    .service(rotate_api_key)
+                    .service(delete_api_key)
+                    .service(read_all_api_keys)
+                    //webhooks
+                    .service(create_webhook)
+                    .service(read_webhook)
+                    .service(update_webhook)
+                    .service(delete_webhook)
+                    .service(read_all_webhooks)
+                    .service(read_webhook_deliveries)
                     //sources
                     .service(create_source)
+                    // registered before the routes of a source, whose path
+                    // would match it
+                    .service(batch_sources)
                     .service(read_source)
                     .service(update_source)
                     .service(delete_source)
+                    .service(restore_source)
                     .service(read_all_sources)
+                    .service(test_source)
+                    .service(read_source_versions)
                     //sinks
                     .service(create_sink)
+                    .service(batch_sinks)
                     .service(read_sink)
                     .service(update_sink)
                     .service(delete_sink)
+                    .service(restore_sink)
                     .service(read_all_sinks)
+                    .service(test_sink)
+                    .service(read_sink_versions)
+                    .service(validate_sink)
                     //pipelines
                     .service(create_pipeline)
+                    .service(batch_pipelines)
+                    .service(upgrade_pipelines)
                     .service(read_pipeline)
                     .service(update_pipeline)
                     .service(delete_pipeline)
+                    .service(restore_pipeline)
                     .service(read_all_pipelines)
                     .service(start_pipeline)
                     .service(stop_pipeline)
+                    .service(restart_pipeline)
+                    .service(read_pipeline_versions)
+                    .service(rollback_pipeline)
                     .service(get_pipeline_status)
+                    .service(stream_pipeline_events)
+                    .service(read_pipeline_image)
+                    .service(update_pipeline_image)
+                    .service(report_pipeline_status)
+                    .service(initial_copy)
+                    .service(resync_tables)
+                    //jobs
+                    .service(read_job)
                     //tables
                     .service(read_table_names)
                     //publications
@@ -239,11 +306,18 @@ pub async fn run(
             )
             .app_data(connection_pool.clone())
             .app_data(encryption_key.clone())
-            .app_data(api_key.clone());
-        if let Some(k8s_client) = k8s_client.clone() {
+            .app_data(api_key.clone())
+            .app_data(http_client.clone())
+            .app_data(metrics.clone());
+        let app = if let Some(k8s_client) = k8s_client.clone() {
             app.app_data(k8s_client.clone())
         } else {
             app
+        };
+        if let Some(oidc_validator) = oidc_validator.clone() {
+            app.app_data(oidc_validator)
+        } else {
+            app
         }
     })
     .listen(listener)?
@@ -0,0 +1,302 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::web::Data;
+use aws_lc_rs::{error::Unspecified, hmac, rand::fill};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::WorkerSettings,
+    db::{
+        self,
+        webhooks::{PendingDelivery, Webhook},
+    },
+    encryption::EncryptionKey,
+    metrics::{ApiMetrics, Worker},
+};
+
+/// Prefix of the secrets shown to users, the rest is the base64 encoded key
+pub const WEBHOOK_SECRET_PREFIX: &str = "whsec_";
+
+/// Deliveries are given up on after this many failed attempts
+const MAX_DELIVERY_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry of a failed delivery, doubled on each
+/// further retry
+const INITIAL_RETRY_DELAY_SECS: i64 = 30;
+
+const MAX_RETRY_DELAY_SECS: i64 = 3_600;
+
+/// Time a claimed delivery has to be sent in before it is claimed again
+const DELIVERY_LEASE_SECS: i64 = 120;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DELIVERY_BATCH_SIZE: i64 = 50;
+
+/// Pipeline lifecycle events webhooks can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The pipeline was started or restarted
+    PipelineStarted,
+    /// The pipeline was stopped
+    PipelineStopped,
+    /// The pipeline's replicator reported an error
+    PipelineErrored,
+    /// The pipeline's replication lag went over the webhook's threshold
+    LagThresholdExceeded,
+    /// The pipeline finished copying a table
+    TableSyncCompleted,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::PipelineStarted => "pipeline_started",
+            WebhookEvent::PipelineStopped => "pipeline_stopped",
+            WebhookEvent::PipelineErrored => "pipeline_errored",
+            WebhookEvent::LagThresholdExceeded => "lag_threshold_exceeded",
+            WebhookEvent::TableSyncCompleted => "table_sync_completed",
+        }
+    }
+
+    pub fn parse(event: &str) -> Option<WebhookEvent> {
+        match event {
+            "pipeline_started" => Some(WebhookEvent::PipelineStarted),
+            "pipeline_stopped" => Some(WebhookEvent::PipelineStopped),
+            "pipeline_errored" => Some(WebhookEvent::PipelineErrored),
+            "lag_threshold_exceeded" => Some(WebhookEvent::LagThresholdExceeded),
+            "table_sync_completed" => Some(WebhookEvent::TableSyncCompleted),
+            _ => None,
+        }
+    }
+}
+
+/// Generates a new webhook secret. Returns the secret as shown to the user
+/// and the key signing the deliveries.
+pub fn generate_webhook_secret() -> Result<(String, Vec<u8>), Unspecified> {
+    let mut key = [0u8; 32];
+    fill(&mut key)?;
+    let secret = format!("{WEBHOOK_SECRET_PREFIX}{}", BASE64_STANDARD.encode(key));
+    Ok((secret, key.to_vec()))
+}
+
+/// Signs a delivery as in the Standard Webhooks specification: a base64
+/// encoded HMAC-SHA256 of the delivery id, the timestamp and the body,
+/// separated by dots and prefixed with the signature version
+pub fn sign_delivery(key: &[u8], delivery_id: i64, timestamp: u64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let signature = hmac::sign(&key, format!("{delivery_id}.{timestamp}.{body}").as_bytes());
+    format!("v1,{}", BASE64_STANDARD.encode(signature.as_ref()))
+}
+
+/// Returns the delay before retrying a delivery which failed `attempts`
+/// times, or `None` if it should be given up on
+fn retry_delay_secs(attempts: i32) -> Option<i64> {
+    if attempts >= MAX_DELIVERY_ATTEMPTS {
+        return None;
+    }
+    let delay = INITIAL_RETRY_DELAY_SECS << (attempts - 1).clamp(0, 16);
+    Some(delay.min(MAX_RETRY_DELAY_SECS))
+}
+
+async fn create_delivery(
+    pool: &PgPool,
+    webhook: &Webhook,
+    pipeline_id: i64,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let payload = serde_json::json!({
+        "event": event,
+        "tenant_id": webhook.tenant_id,
+        "pipeline_id": pipeline_id,
+        "data": data,
+    });
+    db::webhooks::create_delivery(
+        pool,
+        &webhook.tenant_id,
+        webhook.id,
+        event.as_str(),
+        &payload,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Queues a delivery of `event` to each of the tenant's webhooks subscribed
+/// to it. Failures are logged rather than returned so that they don't fail
+/// the request which caused the event.
+pub async fn notify(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) {
+    let result = async {
+        let webhooks =
+            db::webhooks::read_subscribed_webhooks(pool, tenant_id, event.as_str()).await?;
+        for webhook in &webhooks {
+            create_delivery(pool, webhook, pipeline_id, event, data.clone()).await?;
+        }
+        Ok::<(), sqlx::Error>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        error!("failed to queue {} webhook deliveries: {e}", event.as_str());
+    }
+}
+
+/// Queues a lag threshold exceeded delivery to each of the tenant's webhooks
+/// whose threshold the lag went over since the previous report
+pub async fn notify_lag(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    previous_lag_secs: Option<i64>,
+    lag_secs: i64,
+) {
+    let event = WebhookEvent::LagThresholdExceeded;
+    let result = async {
+        let webhooks =
+            db::webhooks::read_subscribed_webhooks(pool, tenant_id, event.as_str()).await?;
+        for webhook in &webhooks {
+            let Some(threshold_secs) = webhook.lag_threshold_secs else {
+                continue;
+            };
+            let exceeded = lag_secs > threshold_secs
+                && previous_lag_secs.map_or(true, |previous| previous <= threshold_secs);
+            if exceeded {
+                let data = serde_json::json!({
+                    "lag_secs": lag_secs,
+                    "lag_threshold_secs": threshold_secs,
+                });
+                create_delivery(pool, webhook, pipeline_id, event, data).await?;
+            }
+        }
+        Ok::<(), sqlx::Error>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        error!("failed to queue {} webhook deliveries: {e}", event.as_str());
+    }
+}
+
+async fn send_delivery(client: &reqwest::Client, delivery: &PendingDelivery) -> Result<(), String> {
+    let body = delivery.payload.to_string();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let signature = sign_delivery(&delivery.secret, delivery.id, timestamp, &body);
+
+    let response = client
+        .post(&delivery.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header("content-type", "application/json")
+        .header("webhook-id", delivery.id.to_string())
+        .header("webhook-timestamp", timestamp.to_string())
+        .header("webhook-signature", signature)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {e}"))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "webhook responded with status {}",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sends the due deliveries and records their outcome. Returns the number
+/// of deliveries claimed.
+async fn send_due_deliveries(
+    pool: &PgPool,
+    client: &reqwest::Client,
+    encryption_key: &EncryptionKey,
+) -> Result<usize, db::webhooks::WebhooksDbError> {
+    let deliveries = db::webhooks::claim_due_deliveries(
+        pool,
+        DELIVERY_BATCH_SIZE,
+        DELIVERY_LEASE_SECS,
+        encryption_key,
+    )
+    .await?;
+
+    for delivery in &deliveries {
+        match send_delivery(client, delivery).await {
+            Ok(()) => db::webhooks::mark_delivery_delivered(pool, delivery.id).await?,
+            Err(message) => {
+                let retry_after_secs = retry_delay_secs(delivery.attempts + 1);
+                if retry_after_secs.is_none() {
+                    info!(
+                        "giving up on webhook delivery {} after {} attempts: {message}",
+                        delivery.id,
+                        delivery.attempts + 1
+                    );
+                }
+                db::webhooks::mark_delivery_failed(pool, delivery.id, &message, retry_after_secs)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(deliveries.len())
+}
+
+/// Sends queued webhook deliveries until the process exits, looking for
+/// due deliveries every `poll_interval_secs`
+pub async fn run_delivery_worker(
+    pool: Data<PgPool>,
+    client: reqwest::Client,
+    encryption_key: Data<EncryptionKey>,
+    metrics: Data<ApiMetrics>,
+    settings: WorkerSettings,
+) {
+    let poll_interval = Duration::from_secs(settings.poll_interval_secs);
+    loop {
+        metrics.record_heartbeat(Worker::Delivery);
+        match send_due_deliveries(&pool, &client, &encryption_key).await {
+            // A full batch means more deliveries are probably due
+            Ok(claimed) if claimed as i64 == DELIVERY_BATCH_SIZE => continue,
+            Ok(_) => {}
+            Err(e) => error!("failed to send webhook deliveries: {e}"),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay_secs, sign_delivery, MAX_DELIVERY_ATTEMPTS, MAX_RETRY_DELAY_SECS};
+
+    #[test]
+    fn retries_back_off_exponentially_until_given_up() {
+        assert_eq!(retry_delay_secs(1), Some(30));
+        assert_eq!(retry_delay_secs(2), Some(60));
+        assert_eq!(retry_delay_secs(3), Some(120));
+        assert_eq!(
+            retry_delay_secs(MAX_DELIVERY_ATTEMPTS - 1),
+            Some(MAX_RETRY_DELAY_SECS)
+        );
+        assert_eq!(retry_delay_secs(MAX_DELIVERY_ATTEMPTS), None);
+    }
+
+    #[test]
+    fn deliveries_are_signed_with_the_standard_webhooks_scheme() {
+        let key = [7u8; 32];
+        let signature = sign_delivery(&key, 1, 1_614_265_330, r#"{"test": 2432232314}"#);
+        assert_eq!(signature, "v1,1hQVdU8EsWJBIxhYu1CiRIGUSCYDtv7LsAtaucQWkZ4=");
+    }
+}
@@ -0,0 +1,155 @@
+use reqwest::StatusCode;
+
+use crate::{
+    sources::create_source,
+    tenants::{create_tenant, create_tenant_with_id_and_name},
+    test_app::{
+        spawn_app, ApiKeyResponse, CreateApiKeyRequest, CreateApiKeyResponse, ListResponse,
+        SourceResponse, TestApp,
+    },
+};
+
+pub async fn create_api_key(app: &TestApp, tenant_id: &str) -> CreateApiKeyResponse {
+    let api_key = CreateApiKeyRequest {
+        name: "CI key".to_string(),
+    };
+    let response = app.create_api_key(tenant_id, &api_key).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn api_key_can_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Assert
+    let response = app.read_all_api_keys(tenant_id).await;
+    assert!(response.status().is_success());
+    let response: ListResponse<ApiKeyResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.items.len(), 1);
+    assert_eq!(response.items[0].id, api_key.id);
+    assert_eq!(&response.items[0].tenant_id, tenant_id);
+    assert_eq!(response.items[0].name, "CI key");
+}
+
+#[tokio::test]
+async fn api_key_is_scoped_to_its_tenant() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app.read_all_sources_with_api_key(&api_key.key, None).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.items.len(), 1);
+    assert_eq!(response.items[0].id, source_id);
+}
+
+#[tokio::test]
+async fn api_key_cant_access_another_tenant() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let other_tenant_id = &create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "Other Tenant".to_string(),
+    )
+    .await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app
+        .read_all_sources_with_api_key(&api_key.key, Some(other_tenant_id))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn api_key_cant_access_tenants() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app.read_all_tenants_with_api_key(&api_key.key).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn rotated_api_key_replaces_the_old_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app.rotate_api_key(tenant_id, api_key.id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let rotated_api_key: CreateApiKeyResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(rotated_api_key.id, api_key.id);
+    let response = app.read_all_sources_with_api_key(&api_key.key, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app
+        .read_all_sources_with_api_key(&rotated_api_key.key, None)
+        .await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn deleted_api_key_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app.delete_api_key(tenant_id, api_key.id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_all_sources_with_api_key(&api_key.key, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn a_non_existing_api_key_cant_be_rotated() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.rotate_api_key(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,242 @@
+use api::db::sources::SourceConfig;
+use reqwest::StatusCode;
+
+use crate::{
+    sources::{create_source, create_source_with_config},
+    tenants::{create_tenant, create_tenant_with_id_and_name},
+    test_app::{
+        spawn_app, spawn_app_with_oidc, AuditLogResponse, ListResponse, TestApp,
+        UpdateSourceRequest,
+    },
+};
+
+fn source_config(host: &str, password: &str) -> SourceConfig {
+    SourceConfig::Postgres {
+        host: host.to_string(),
+        port: 5432,
+        name: "postgres".to_string(),
+        username: "postgres".to_string(),
+        password: Some(password.to_string()),
+        slot_name: "slot".to_string(),
+    }
+}
+
+async fn read_audit_logs(
+    app: &TestApp,
+    tenant_id: &str,
+    query: &[(&str, &str)],
+) -> ListResponse<AuditLogResponse> {
+    let response = app.read_audit_logs(tenant_id, query).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn source_changes_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source_with_config(
+        &app,
+        tenant_id,
+        "Postgres Source".to_string(),
+        source_config("localhost", "first-secret"),
+    )
+    .await;
+    let source = UpdateSourceRequest {
+        name: "Postgres Source (Updated)".to_string(),
+        config: source_config("example.com", "second-secret"),
+    };
+    let response = app.update_source(tenant_id, source_id, &source).await;
+    assert!(response.status().is_success());
+    let response = app.delete_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let audit_logs = read_audit_logs(&app, tenant_id, &[("resource_type", "source")]).await;
+
+    // Assert
+    let actions: Vec<&str> = audit_logs
+        .items
+        .iter()
+        .map(|log| log.action.as_str())
+        .collect();
+    assert_eq!(actions, vec!["delete", "update", "create"]);
+    for log in &audit_logs.items {
+        assert_eq!(log.actor, "admin");
+        assert_eq!(log.resource_type, "source");
+        assert_eq!(log.resource_id, source_id.to_string());
+    }
+
+    let [deleted, updated, created] = &audit_logs.items[..] else {
+        panic!("expected three audit logs");
+    };
+    assert!(created.before.is_none());
+    assert!(created.after.is_some());
+    assert!(deleted.before.is_some());
+    assert!(deleted.after.is_none());
+
+    let mut paths: Vec<&str> = updated
+        .changes
+        .iter()
+        .map(|change| change.path.as_str())
+        .collect();
+    paths.sort();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[0].starts_with("config.") && paths[0].ends_with(".host"));
+    assert_eq!(paths[1], "name");
+    let name_change = updated.changes.iter().find(|c| c.path == "name").unwrap();
+    assert_eq!(name_change.before, "Postgres Source");
+    assert_eq!(name_change.after, "Postgres Source (Updated)");
+
+    // Passwords are redacted in the audit log
+    for log in &audit_logs.items {
+        let snapshots = format!("{:?} {:?}", log.before, log.after);
+        assert!(!snapshots.contains("first-secret"));
+        assert!(!snapshots.contains("second-secret"));
+    }
+}
+
+#[tokio::test]
+async fn tenant_changes_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let tenant_id = &create_tenant(&app).await;
+
+    // Assert
+    let audit_logs = read_audit_logs(&app, tenant_id, &[]).await;
+    assert_eq!(audit_logs.items.len(), 1);
+    let log = &audit_logs.items[0];
+    assert_eq!(log.action, "create");
+    assert_eq!(log.resource_type, "tenant");
+    assert_eq!(&log.resource_id, tenant_id);
+}
+
+#[tokio::test]
+async fn audit_log_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let first_source_id = create_source(&app, tenant_id).await;
+    let second_source_id = create_source(&app, tenant_id).await;
+    let response = app.delete_source(tenant_id, second_source_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let deletes = read_audit_logs(&app, tenant_id, &[("action", "delete")]).await;
+    let first_source_id = first_source_id.to_string();
+    let first_source_changes = read_audit_logs(
+        &app,
+        tenant_id,
+        &[
+            ("resource_type", "source"),
+            ("resource_id", &first_source_id),
+        ],
+    )
+    .await;
+    let other_actor = read_audit_logs(&app, tenant_id, &[("actor", "api_key:1")]).await;
+
+    // Assert
+    assert_eq!(deletes.items.len(), 1);
+    assert_eq!(deletes.items[0].resource_id, second_source_id.to_string());
+    assert_eq!(first_source_changes.items.len(), 1);
+    assert_eq!(first_source_changes.items[0].action, "create");
+    assert!(other_actor.items.is_empty());
+}
+
+#[tokio::test]
+async fn audit_log_is_paginated_newest_first() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+
+    // Act
+    let first_page = read_audit_logs(&app, tenant_id, &[("limit", "1")]).await;
+    let cursor = first_page.next_cursor.clone().expect("missing next cursor");
+    let second_page =
+        read_audit_logs(&app, tenant_id, &[("limit", "1"), ("cursor", &cursor)]).await;
+
+    // Assert
+    assert_eq!(first_page.items.len(), 1);
+    assert_eq!(first_page.items[0].resource_id, source_id.to_string());
+    assert_eq!(second_page.items.len(), 1);
+    assert_eq!(second_page.items[0].resource_type, "tenant");
+    assert!(second_page.next_cursor.is_none());
+}
+
+#[tokio::test]
+async fn invalid_audit_log_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app
+        .read_audit_logs(tenant_id, &[("action", "rename")])
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn a_tenant_admin_can_read_the_tenants_audit_log() {
+    // Arrange
+    let app = spawn_app_with_oidc().await;
+    let tenant_id = &create_tenant(&app).await;
+    let token = app.oidc_token("admin", tenant_id);
+
+    // Act
+    let response = app.read_audit_logs_with_token(&token, tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let audit_logs: ListResponse<AuditLogResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(audit_logs.items.len(), 1);
+    assert_eq!(&audit_logs.items[0].resource_id, tenant_id);
+}
+
+#[tokio::test]
+async fn a_tenant_admin_cant_read_another_tenants_audit_log() {
+    // Arrange
+    let app = spawn_app_with_oidc().await;
+    let tenant_id = &create_tenant(&app).await;
+    let other_tenant_id = &create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "OtherTenant".to_string(),
+    )
+    .await;
+    let token = app.oidc_token("admin", tenant_id);
+
+    // Act
+    let response = app
+        .read_audit_logs_with_token(&token, other_tenant_id)
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn a_tenant_editor_cant_read_the_tenants_audit_log() {
+    // Arrange
+    let app = spawn_app_with_oidc().await;
+    let tenant_id = &create_tenant(&app).await;
+    let token = app.oidc_token("editor", tenant_id);
+
+    // Act
+    let response = app.read_audit_logs_with_token(&token, tenant_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
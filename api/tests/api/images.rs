@@ -1,7 +1,8 @@
 use reqwest::StatusCode;
 
 use crate::test_app::{
-    spawn_app, CreateImageRequest, CreateImageResponse, ImageResponse, TestApp, UpdateImageRequest,
+    spawn_app, CreateImageRequest, CreateImageResponse, ImageResponse, ListResponse, TestApp,
+    UpdateImageRequest,
 };
 
 pub async fn create_default_image(app: &TestApp) -> i64 {
@@ -191,11 +192,11 @@ async fn all_images_can_be_read() {
 
     // Assert
     assert!(response.status().is_success());
-    let response: Vec<ImageResponse> = response
+    let response: ListResponse<ImageResponse> = response
         .json()
         .await
         .expect("failed to deserialize response");
-    for image in response {
+    for image in response.items {
         if image.id == image1_id {
             assert_eq!(image.name, "some/image");
             assert!(image.is_default);
@@ -205,3 +206,29 @@ async fn all_images_can_be_read() {
         }
     }
 }
+
+#[tokio::test]
+async fn a_new_default_image_replaces_the_previous_one() {
+    // Arrange
+    let app = spawn_app().await;
+    let image1_id = create_image_with_name(&app, "some/image".to_string(), true).await;
+
+    // Act
+    let image2_id = create_image_with_name(&app, "some/image:2".to_string(), true).await;
+
+    // Assert
+    let image1: ImageResponse = app
+        .read_image(image1_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(!image1.is_default);
+    let image2: ImageResponse = app
+        .read_image(image2_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(image2.is_default);
+}
@@ -0,0 +1,171 @@
+use reqwest::StatusCode;
+
+use crate::{
+    pipelines::{create_pipeline_with_config, new_pipeline_config},
+    sinks::create_sink,
+    sources::create_source,
+    tenants::{create_tenant, create_tenant_with_id_and_name},
+    test_app::{spawn_app, CreateJobResponse, JobResponse, ResyncTablesRequest, TestApp},
+};
+
+async fn read_job(app: &TestApp, tenant_id: &str, job_id: i64) -> JobResponse {
+    let response = app.read_job(tenant_id, job_id).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+async fn create_pipeline(app: &TestApp, tenant_id: &str) -> i64 {
+    let source_id = create_source(app, tenant_id).await;
+    let sink_id = create_sink(app, tenant_id).await;
+    create_pipeline_with_config(app, tenant_id, source_id, sink_id, new_pipeline_config()).await
+}
+
+#[tokio::test]
+async fn sink_validation_is_queued_as_a_job() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let response = app.validate_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let response: CreateJobResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let job = read_job(&app, tenant_id, response.id).await;
+    assert_eq!(job.id, response.id);
+    assert_eq!(&job.tenant_id, tenant_id);
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.params["kind"], "sink_validation");
+    assert_eq!(job.params["sink_id"], sink_id);
+    assert!(job.progress.is_none());
+    assert!(job.result.is_none());
+    assert!(job.error.is_none());
+    assert!(job.started_at.is_none());
+    assert!(job.finished_at.is_none());
+}
+
+#[tokio::test]
+async fn table_resync_is_queued_as_a_job() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let pipeline_id = create_pipeline(&app, tenant_id).await;
+    let resync = ResyncTablesRequest {
+        tables: vec!["public.users".to_string()],
+    };
+
+    // Act
+    let response = app.resync_tables(tenant_id, pipeline_id, &resync).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let response: CreateJobResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let job = read_job(&app, tenant_id, response.id).await;
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.params["kind"], "table_resync");
+    assert_eq!(job.params["pipeline_id"], pipeline_id);
+    assert_eq!(job.params["tables"][0], "public.users");
+}
+
+#[tokio::test]
+async fn initial_copy_is_queued_as_a_job() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let pipeline_id = create_pipeline(&app, tenant_id).await;
+
+    // Act
+    let response = app.initial_copy(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let response: CreateJobResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let job = read_job(&app, tenant_id, response.id).await;
+    assert_eq!(job.params["kind"], "initial_copy");
+    assert_eq!(job.params["pipeline_id"], pipeline_id);
+}
+
+#[tokio::test]
+async fn table_resync_needs_tables() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let pipeline_id = create_pipeline(&app, tenant_id).await;
+
+    // Act
+    let no_tables = app
+        .resync_tables(
+            tenant_id,
+            pipeline_id,
+            &ResyncTablesRequest { tables: vec![] },
+        )
+        .await;
+    let unqualified_table = app
+        .resync_tables(
+            tenant_id,
+            pipeline_id,
+            &ResyncTablesRequest {
+                tables: vec!["users".to_string()],
+            },
+        )
+        .await;
+
+    // Assert
+    assert_eq!(no_tables.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(unqualified_table.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn jobs_of_non_existing_resources_are_not_queued() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let validation = app.validate_sink(tenant_id, 42).await;
+    let initial_copy = app.initial_copy(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(validation.status(), StatusCode::NOT_FOUND);
+    assert_eq!(initial_copy.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn jobs_of_other_tenants_cant_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let other_tenant_id = &create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "Other Tenant".to_string(),
+    )
+    .await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let response: CreateJobResponse = app
+        .validate_sink(tenant_id, sink_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+
+    // Act
+    let response = app.read_job(other_tenant_id, response.id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -1,8 +1,15 @@
+mod api_keys;
+mod audit;
 mod database;
 mod health_check;
 mod images;
+mod jobs;
+mod metrics;
+mod openapi;
 mod pipelines;
+mod quotas;
 mod sinks;
 mod sources;
 mod tenants;
 mod test_app;
+mod webhooks;
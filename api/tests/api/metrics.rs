@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+
+use reqwest::StatusCode;
+
+use crate::{
+    api_keys::create_api_key,
+    pipelines::{create_pipeline_with_config, new_pipeline_config, new_status_report},
+    sinks::create_sink,
+    sources::create_source,
+    tenants::create_tenant,
+    test_app::{
+        spawn_app, LatencyBucket, ReportPipelineStatusRequest, TableMetricsReport, TestApp,
+    },
+};
+
+async fn read_metrics(app: &TestApp) -> String {
+    let response = app.read_metrics_with_api_key(&app.api_key).await;
+    assert!(response.status().is_success());
+    response.text().await.expect("failed to read response")
+}
+
+#[tokio::test]
+async fn metrics_cant_be_read_without_the_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/metrics", app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn metrics_cant_be_read_with_a_tenants_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let api_key = create_api_key(&app, tenant_id).await;
+
+    // Act
+    let response = app.read_metrics_with_api_key(&api_key.key).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn request_latencies_are_exposed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    app.read_tenant(tenant_id).await;
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    assert!(metrics.contains(
+        r#"api_http_request_duration_seconds_count{method="GET",path="/v1/tenants/{tenant_id}",status="200"} 1"#
+    ));
+    assert!(metrics.contains("api_db_pool_connections "));
+    assert!(metrics.contains("api_db_pool_idle_connections "));
+    assert!(metrics.contains("api_db_pool_max_connections "));
+}
+
+#[tokio::test]
+async fn pipeline_counts_by_status_are_exposed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+
+    // Act
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    assert!(metrics.contains(r#"api_pipelines{status="stopped"} 1"#));
+    assert!(metrics.contains(r#"api_pipelines{status="started"} 0"#));
+}
+
+fn table_metrics(table_name: &str, inserts: u64) -> TableMetricsReport {
+    TableMetricsReport {
+        table_name: table_name.to_string(),
+        inserts,
+        updates: 2,
+        deletes: 1,
+        conversion_errors: 0,
+        column_conversion_errors: BTreeMap::new(),
+        sink_latency_buckets: vec![
+            LatencyBucket {
+                le_secs: 0.005,
+                count: 1,
+            },
+            LatencyBucket {
+                le_secs: 0.5,
+                count: 2,
+            },
+        ],
+        sink_latency_sum_secs: 0.25,
+        sink_latency_count: 2,
+    }
+}
+
+#[tokio::test]
+async fn table_metrics_are_exposed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let status = ReportPipelineStatusRequest {
+        table_metrics: vec![table_metrics("public.users", 3)],
+        ..new_status_report()
+    };
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    let labels =
+        format!(r#"pipeline_id="{pipeline_id}",table="public.users",tenant_id="{tenant_id}""#);
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_changes_total{{op="insert",{labels}}} 3"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_changes_total{{op="update",{labels}}} 2"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_conversion_errors_total{{{labels}}} 0"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_sink_latency_seconds_bucket{{{labels},le="0.005"}} 1"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_sink_latency_seconds_count{{{labels}}} 2"#
+    )));
+}
+
+#[tokio::test]
+async fn column_conversion_errors_are_exposed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let status = ReportPipelineStatusRequest {
+        table_metrics: vec![TableMetricsReport {
+            conversion_errors: 3,
+            column_conversion_errors: [("created_at".to_string(), 2), ("payload".to_string(), 1)]
+                .into(),
+            ..table_metrics("public.events", 3)
+        }],
+        ..new_status_report()
+    };
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    let labels =
+        format!(r#"pipeline_id="{pipeline_id}",table="public.events",tenant_id="{tenant_id}""#);
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_conversion_errors_total{{{labels}}} 3"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_column_conversion_errors_total{{column="created_at",{labels}}} 2"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_column_conversion_errors_total{{column="payload",{labels}}} 1"#
+    )));
+}
+
+#[tokio::test]
+async fn pipeline_lag_is_exposed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let status = ReportPipelineStatusRequest {
+        phase: "cdc".to_string(),
+        last_flushed_lsn: Some("0/16B3748".to_string()),
+        lag_bytes: Some(4096),
+        lag_secs: Some(2),
+        source_wal_lsn: Some("0/16B4748".to_string()),
+        estimated_lag_secs: Some(5),
+        ..new_status_report()
+    };
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    let labels = format!(r#"pipeline_id="{pipeline_id}",tenant_id="{tenant_id}""#);
+    assert!(metrics.contains(&format!("replicator_lag_bytes{{{labels}}} 4096")));
+    assert!(metrics.contains(&format!("replicator_lag_seconds{{{labels}}} 2")));
+    assert!(metrics.contains(&format!("replicator_estimated_lag_seconds{{{labels}}} 5")));
+}
+
+#[tokio::test]
+async fn tables_past_the_limit_are_added_up() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    // the default limit is 100 tables per pipeline
+    let status = ReportPipelineStatusRequest {
+        table_metrics: (0..102)
+            .map(|i| table_metrics(&format!("public.table_{i}"), i + 10))
+            .collect(),
+        ..new_status_report()
+    };
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let metrics = read_metrics(&app).await;
+
+    // Assert
+    assert!(metrics.contains(r#"table="public.table_101""#));
+    assert!(!metrics.contains(r#"table="public.table_1""#));
+    assert!(!metrics.contains(r#"table="public.table_0""#));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_changes_total{{op="insert",pipeline_id="{pipeline_id}",table="_other",tenant_id="{tenant_id}"}} 21"#
+    )));
+    assert!(metrics.contains(&format!(
+        r#"replicator_table_sink_latency_seconds_count{{pipeline_id="{pipeline_id}",table="_other",tenant_id="{tenant_id}"}} 4"#
+    )));
+}
@@ -0,0 +1,77 @@
+use serde_json::Value;
+
+use crate::test_app::spawn_app;
+
+fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(reference) if key == "$ref" => refs.push(reference),
+                    value => collect_refs(value, refs),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_refs(value, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn openapi_document_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(format!("{}/api-docs/openapi.json", app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert!(response.status().is_success());
+    let document: Value = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(document["paths"]["/v1/sources"]["get"].is_object());
+    assert!(document["components"]["securitySchemes"]["bearer_auth"].is_object());
+}
+
+#[tokio::test]
+async fn openapi_document_references_only_defined_schemas() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(format!("{}/api-docs/openapi.json", app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    let document: Value = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let mut refs = vec![];
+    collect_refs(&document, &mut refs);
+    assert!(!refs.is_empty());
+    for reference in refs {
+        let name = reference
+            .strip_prefix("#/components/schemas/")
+            .expect("reference outside of the schemas");
+        assert!(
+            document["components"]["schemas"][name].is_object(),
+            "schema {name} is not defined"
+        );
+    }
+}
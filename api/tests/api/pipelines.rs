@@ -1,24 +1,34 @@
-use api::db::pipelines::{BatchConfig, PipelineConfig};
+use std::collections::BTreeMap;
+
+use api::db::pipelines::{
+    BatchConfig, PipelineConfig, TableBatchConfig, TableOverrides, TableSelection, WriteMode,
+};
 use reqwest::StatusCode;
 
 use crate::{
-    images::create_default_image,
+    images::{create_default_image, create_image_with_name},
     sinks::create_sink,
     sources::create_source,
     tenants::create_tenant,
     tenants::create_tenant_with_id_and_name,
     test_app::{
-        spawn_app, CreatePipelineRequest, CreatePipelineResponse, PipelineResponse, TestApp,
-        UpdatePipelineRequest,
+        spawn_app, BatchItem, BatchResponse, CreatePipelineRequest, CreatePipelineResponse,
+        ListResponse, PipelineImageResponse, PipelineResponse, PipelineVersionResponse,
+        ReportPipelineStatusRequest, RollbackPipelineResponse, TableCopyProgress, TestApp,
+        UpdatePipelineImageRequest, UpdatePipelineRequest, UpgradePipelinesRequest,
+        UpgradePipelinesResponse, ValidationErrorResponse,
     },
 };
 
-fn new_pipeline_config() -> PipelineConfig {
+pub fn new_pipeline_config() -> PipelineConfig {
     PipelineConfig {
         config: BatchConfig {
             max_size: 1000,
             max_fill_secs: 5,
+            transactional: false,
         },
+        tables: None,
+        overrides: BTreeMap::new(),
     }
 }
 
@@ -27,7 +37,22 @@ fn updated_pipeline_config() -> PipelineConfig {
         config: BatchConfig {
             max_size: 2000,
             max_fill_secs: 10,
+            transactional: true,
         },
+        tables: Some(TableSelection {
+            include: vec!["public.*".to_string()],
+            exclude: vec!["public.audit_*".to_string()],
+            mappings: BTreeMap::from([("public.users".to_string(), "customers".to_string())]),
+        }),
+        overrides: BTreeMap::from([(
+            "public.events".to_string(),
+            TableOverrides {
+                write_mode: Some(WriteMode::AppendOnly),
+                clustering: vec!["user_id".to_string()],
+                batch: Some(TableBatchConfig { max_size: 50000 }),
+                ..Default::default()
+            },
+        )]),
     }
 }
 
@@ -110,7 +135,111 @@ async fn pipeline_with_another_tenants_source_cant_be_created() {
     let response = app.create_pipeline(tenant1_id, &pipeline).await;
 
     // Assert
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn pipeline_with_an_invalid_table_selection_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let mut config = new_pipeline_config();
+    config.tables = Some(TableSelection {
+        mappings: BTreeMap::from([("users".to_string(), "customers".to_string())]),
+        ..Default::default()
+    });
+    let pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: "publication".to_string(),
+        config,
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn pipeline_with_conflicting_table_overrides_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let mut config = updated_pipeline_config();
+    config.overrides = BTreeMap::from([(
+        "public.users".to_string(),
+        TableOverrides {
+            destination: Some("users_v2".to_string()),
+            ..Default::default()
+        },
+    )]);
+    let pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: "publication".to_string(),
+        config,
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn pipeline_with_invalid_fields_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let mut config = new_pipeline_config();
+    config.config = BatchConfig {
+        max_size: 0,
+        max_fill_secs: 0,
+        transactional: false,
+    };
+    let pipeline = CreatePipelineRequest {
+        source_id: 42,
+        sink_id,
+        publication_name: "".to_string(),
+        config,
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response: ValidationErrorResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let fields: Vec<&str> = response
+        .fields
+        .iter()
+        .map(|field| field.field.as_str())
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            "source_id",
+            "publication_name",
+            "config.config.max_size",
+            "config.config.max_fill_secs"
+        ]
+    );
+    assert!(response.error.starts_with("invalid pipeline"));
+    assert_eq!(response.fields[0].message, "source with id 42 not found");
 }
 
 #[tokio::test]
@@ -143,7 +272,171 @@ async fn pipeline_with_another_tenants_sink_cant_be_created() {
     let response = app.create_pipeline(tenant1_id, &pipeline).await;
 
     // Assert
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn pipeline_with_the_same_source_and_sink_is_not_created_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: "publication".to_string(),
+        config: new_pipeline_config(),
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: CreatePipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, pipeline_id);
+    assert!(!response.created);
+    let pipelines: ListResponse<PipelineResponse> = app
+        .read_all_pipelines(tenant_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipelines.items.len(), 1);
+}
+
+#[tokio::test]
+async fn pipelines_can_be_created_in_a_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let other_sink_id = create_sink(&app, tenant_id).await;
+    let existing_pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let item = |sink_id| BatchItem {
+        id: None,
+        item: CreatePipelineRequest {
+            source_id,
+            sink_id,
+            publication_name: "publication".to_string(),
+            config: new_pipeline_config(),
+        },
+    };
+    let items = vec![item(sink_id), item(other_sink_id), item(42)];
+
+    // Act
+    let response = app.batch_pipelines(tenant_id, &items).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: BatchResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let results = response.results;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].index, 0);
+    assert_eq!(results[0].id, Some(existing_pipeline_id));
+    assert_eq!(results[0].status, 200);
+    assert!(results[0].error.is_none());
+    assert_eq!(results[1].index, 1);
+    let created_id = results[1].id.expect("pipeline not created");
+    assert_ne!(created_id, existing_pipeline_id);
+    assert_eq!(results[2].index, 2);
+    assert_eq!(results[2].id, None);
+    assert_eq!(results[2].status, 422);
+    assert_eq!(
+        results[2].error.as_deref(),
+        Some("invalid pipeline: sink_id: sink with id 42 not found")
+    );
+    let pipelines: ListResponse<PipelineResponse> = app
+        .read_all_pipelines(tenant_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipelines.items.len(), 2);
+}
+
+#[tokio::test]
+async fn pipeline_creation_can_be_retried_with_an_idempotency_key() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let pipeline = CreatePipelineRequest {
+        source_id: create_source(&app, tenant_id).await,
+        sink_id: create_sink(&app, tenant_id).await,
+        publication_name: "publication".to_string(),
+        config: new_pipeline_config(),
+    };
+    let response: CreatePipelineResponse = app
+        .create_pipeline_with_idempotency_key(tenant_id, &pipeline, "key")
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.created);
+    let pipeline_id = response.id;
+
+    // Act
+    let retried_pipeline = CreatePipelineRequest {
+        source_id: create_source(&app, tenant_id).await,
+        ..pipeline
+    };
+    let response = app
+        .create_pipeline_with_idempotency_key(tenant_id, &retried_pipeline, "key")
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: CreatePipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, pipeline_id);
+    assert!(!response.created);
+}
+
+#[tokio::test]
+async fn pipeline_cant_be_updated_to_the_source_and_sink_of_another() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+    let other_sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id = create_pipeline_with_config(
+        &app,
+        tenant_id,
+        source_id,
+        other_sink_id,
+        new_pipeline_config(),
+    )
+    .await;
+
+    // Act
+    let pipeline = UpdatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: "publication".to_string(),
+        config: new_pipeline_config(),
+    };
+    let response = app.update_pipeline(tenant_id, pipeline_id, &pipeline).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
 }
 
 #[tokio::test]
@@ -248,6 +541,120 @@ async fn an_existing_pipeline_can_be_updated() {
     assert_eq!(response.config, updated_config.config);
 }
 
+/// Creates a pipeline and updates it to a new source, sink, publication and
+/// config. Returns the pipeline's id and its first source's id.
+async fn create_and_update_pipeline(app: &TestApp, tenant_id: &str) -> (i64, i64) {
+    let source_id = create_source(app, tenant_id).await;
+    let sink_id = create_sink(app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let pipeline = UpdatePipelineRequest {
+        source_id: create_source(app, tenant_id).await,
+        sink_id: create_sink(app, tenant_id).await,
+        publication_name: "updated_publication".to_string(),
+        config: updated_pipeline_config(),
+    };
+    let response = app.update_pipeline(tenant_id, pipeline_id, &pipeline).await;
+    assert!(response.status().is_success());
+    (pipeline_id, source_id)
+}
+
+#[tokio::test]
+async fn pipeline_config_versions_are_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (pipeline_id, _) = create_and_update_pipeline(&app, tenant_id).await;
+
+    // Act
+    let response = app.read_pipeline_versions(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let versions: Vec<PipelineVersionResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, 2);
+    assert_eq!(versions[0].publication_name, "updated_publication");
+    assert_eq!(versions[0].config, updated_pipeline_config());
+    assert_eq!(versions[1].version, 1);
+    assert_eq!(versions[1].publication_name, "publication");
+    assert_eq!(versions[1].config, new_pipeline_config());
+}
+
+#[tokio::test]
+async fn versions_of_a_non_existing_pipeline_cant_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.read_pipeline_versions(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn pipeline_can_be_rolled_back() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (pipeline_id, source_id) = create_and_update_pipeline(&app, tenant_id).await;
+
+    // Act
+    let response = app.rollback_pipeline(tenant_id, pipeline_id, 1).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: RollbackPipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.version, 3);
+    let response = app.read_pipeline(tenant_id, pipeline_id).await;
+    let pipeline: PipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipeline.source_id, source_id);
+    assert_eq!(pipeline.publication_name, "publication");
+    assert_eq!(pipeline.config, new_pipeline_config());
+}
+
+#[tokio::test]
+async fn pipeline_cant_be_rolled_back_to_a_non_existing_version() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (pipeline_id, _) = create_and_update_pipeline(&app, tenant_id).await;
+
+    // Act
+    let response = app.rollback_pipeline(tenant_id, pipeline_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn pipeline_cant_be_rolled_back_to_a_version_with_a_deleted_source() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (pipeline_id, source_id) = create_and_update_pipeline(&app, tenant_id).await;
+    let response = app.delete_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.rollback_pipeline(tenant_id, pipeline_id, 1).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn pipeline_with_another_tenants_source_cant_be_updated() {
     // Arrange
@@ -294,7 +701,7 @@ async fn pipeline_with_another_tenants_source_cant_be_updated() {
         .await;
 
     // Assert
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
 #[tokio::test]
@@ -343,7 +750,7 @@ async fn pipeline_with_another_tenants_sink_cant_be_updated() {
         .await;
 
     // Assert
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
 #[tokio::test]
@@ -412,18 +819,93 @@ async fn a_non_existing_pipeline_cant_be_deleted() {
 }
 
 #[tokio::test]
-async fn all_pipelines_can_be_read() {
+async fn a_deleted_pipeline_can_be_restored() {
     // Arrange
     let app = spawn_app().await;
-    create_default_image(&app).await;
     let tenant_id = &create_tenant(&app).await;
-    let source1_id = create_source(&app, tenant_id).await;
-    let source2_id = create_source(&app, tenant_id).await;
-    let sink1_id = create_sink(&app, tenant_id).await;
-    let sink2_id = create_sink(&app, tenant_id).await;
-
-    let pipeline1_id =
-        create_pipeline_with_config(&app, tenant_id, source1_id, sink1_id, new_pipeline_config())
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.restore_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: PipelineResponse = app
+        .read_pipeline(tenant_id, pipeline_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, pipeline_id);
+    assert_eq!(response.source_id, source_id);
+    assert_eq!(response.sink_id, sink_id);
+}
+
+#[tokio::test]
+async fn a_pipeline_whose_source_is_deleted_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+    let response = app.delete_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.restore_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn a_deleted_pipeline_cant_be_restored_over_its_replacement() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+    let replacement_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    assert_ne!(replacement_id, pipeline_id);
+
+    // Act
+    let response = app.restore_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn all_pipelines_can_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source1_id = create_source(&app, tenant_id).await;
+    let source2_id = create_source(&app, tenant_id).await;
+    let sink1_id = create_sink(&app, tenant_id).await;
+    let sink2_id = create_sink(&app, tenant_id).await;
+
+    let pipeline1_id =
+        create_pipeline_with_config(&app, tenant_id, source1_id, sink1_id, new_pipeline_config())
             .await;
     let pipeline2_id = create_pipeline_with_config(
         &app,
@@ -439,11 +921,11 @@ async fn all_pipelines_can_be_read() {
 
     // Assert
     assert!(response.status().is_success());
-    let response: Vec<PipelineResponse> = response
+    let response: ListResponse<PipelineResponse> = response
         .json()
         .await
         .expect("failed to deserialize response");
-    for pipeline in response {
+    for pipeline in response.items {
         if pipeline.id == pipeline1_id {
             let config = new_pipeline_config();
             assert_eq!(&pipeline.tenant_id, tenant_id);
@@ -459,3 +941,383 @@ async fn all_pipelines_can_be_read() {
         }
     }
 }
+
+#[tokio::test]
+async fn pipelines_can_be_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let stopped = app
+        .read_pipelines_page(tenant_id, &[("status", "Stopped")])
+        .await;
+    let started = app
+        .read_pipelines_page(tenant_id, &[("status", "Started")])
+        .await;
+
+    // Assert
+    let stopped: ListResponse<PipelineResponse> = stopped
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let started: ListResponse<PipelineResponse> = started
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(stopped.items.len(), 1);
+    assert_eq!(stopped.items[0].id, pipeline_id);
+    assert!(started.items.is_empty());
+}
+
+pub fn new_status_report() -> ReportPipelineStatusRequest {
+    ReportPipelineStatusRequest {
+        phase: "copying".to_string(),
+        last_flushed_lsn: None,
+        lag_bytes: None,
+        lag_secs: None,
+        source_wal_lsn: None,
+        estimated_lag_secs: None,
+        tables: vec![TableCopyProgress {
+            table_name: "public.users".to_string(),
+            rows_copied: 100,
+            copied: false,
+            estimated_rows: None,
+            copy_secs: None,
+            eta_secs: None,
+        }],
+        table_metrics: vec![],
+        last_error: None,
+        pid: Some(1),
+        host: Some("replicator-0".to_string()),
+    }
+}
+
+#[tokio::test]
+async fn pipeline_status_can_be_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let first_response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &new_status_report())
+        .await;
+    let second_response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &new_status_report())
+        .await;
+
+    // Assert
+    assert!(first_response.status().is_success());
+    assert!(second_response.status().is_success());
+}
+
+#[tokio::test]
+async fn pipeline_status_can_be_reported_without_heartbeat_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let status = ReportPipelineStatusRequest {
+        pid: None,
+        host: None,
+        ..new_status_report()
+    };
+
+    // Act
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn status_of_a_non_existing_pipeline_cant_be_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app
+        .report_pipeline_status(tenant_id, 42, &new_status_report())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+/// Reads the events of a stream until one named `event` arrives
+async fn read_events_until(response: &mut reqwest::Response, event: &str) -> String {
+    let mut events = String::new();
+    let read = async {
+        while !events.contains(&format!("event: {event}\n")) {
+            let chunk = response
+                .chunk()
+                .await
+                .expect("failed to read event stream")
+                .expect("event stream ended");
+            events.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(10), read)
+        .await
+        .expect("timed out waiting for event");
+    events
+}
+
+#[tokio::test]
+async fn pipeline_events_start_with_the_reported_status() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &new_status_report())
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let mut response = app.stream_pipeline_events(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let events = read_events_until(&mut response, "health").await;
+    assert!(events.contains("event: phase\ndata: {\"phase\":\"copying\"}\n\n"));
+    assert!(events.contains(
+        "event: table_progress\ndata: {\"table_name\":\"public.users\",\"rows_copied\":100,\"copied\":false}\n\n"
+    ));
+}
+
+#[tokio::test]
+async fn pipeline_events_stream_the_estimated_copy_progress() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let mut status = new_status_report();
+    status.tables[0].estimated_rows = Some(1000);
+    status.tables[0].copy_secs = Some(10.0);
+    status.tables[0].eta_secs = Some(90);
+    let response = app
+        .report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let mut response = app.stream_pipeline_events(tenant_id, pipeline_id).await;
+
+    // Assert
+    let events = read_events_until(&mut response, "health").await;
+    assert!(events.contains(
+        "event: table_progress\ndata: {\"table_name\":\"public.users\",\"rows_copied\":100,\"copied\":false,\"estimated_rows\":1000,\"copy_secs\":10.0,\"eta_secs\":90}\n\n"
+    ));
+}
+
+#[tokio::test]
+async fn pipeline_events_stream_status_changes() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    app.report_pipeline_status(tenant_id, pipeline_id, &new_status_report())
+        .await;
+    let mut response = app.stream_pipeline_events(tenant_id, pipeline_id).await;
+    read_events_until(&mut response, "health").await;
+
+    // Act
+    let status = ReportPipelineStatusRequest {
+        phase: "errored".to_string(),
+        last_error: Some("connection reset".to_string()),
+        ..new_status_report()
+    };
+    app.report_pipeline_status(tenant_id, pipeline_id, &status)
+        .await;
+
+    // Assert
+    let events = read_events_until(&mut response, "error").await;
+    assert!(events.contains("event: phase\ndata: {\"phase\":\"errored\"}\n\n"));
+    assert!(events.contains("event: error\ndata: {\"error\":\"connection reset\"}\n\n"));
+    assert!(!events.contains("event: table_progress"));
+}
+
+#[tokio::test]
+async fn events_of_a_non_existing_pipeline_cant_be_streamed() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.stream_pipeline_events(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn read_pipeline_image(
+    app: &TestApp,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> PipelineImageResponse {
+    let response = app.read_pipeline_image(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn pipeline_runs_the_default_image_unpinned() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let image = read_pipeline_image(&app, tenant_id, pipeline_id).await;
+
+    // Assert
+    assert_eq!(image.image_id, 1);
+    assert_eq!(image.image_name, "some/image");
+    assert!(!image.pinned);
+}
+
+#[tokio::test]
+async fn pipeline_can_be_pinned_to_an_image_and_unpinned() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let image_id = create_image_with_name(&app, "some/image:canary".to_string(), false).await;
+
+    // Act
+    let pin = UpdatePipelineImageRequest {
+        image_id: Some(image_id),
+    };
+    let pinned = app
+        .update_pipeline_image(tenant_id, pipeline_id, &pin)
+        .await;
+    let pinned_image = read_pipeline_image(&app, tenant_id, pipeline_id).await;
+    let unpin = UpdatePipelineImageRequest { image_id: None };
+    let unpinned = app
+        .update_pipeline_image(tenant_id, pipeline_id, &unpin)
+        .await;
+
+    // Assert
+    assert!(pinned.status().is_success());
+    assert_eq!(pinned_image.image_id, image_id);
+    assert_eq!(pinned_image.image_name, "some/image:canary");
+    assert!(pinned_image.pinned);
+    assert!(unpinned.status().is_success());
+    let unpinned: PipelineImageResponse = unpinned
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(unpinned.image_id, 1);
+    assert!(!unpinned.pinned);
+}
+
+#[tokio::test]
+async fn pipeline_cant_be_pinned_to_a_non_existing_image() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let pin = UpdatePipelineImageRequest { image_id: Some(42) };
+    let response = app
+        .update_pipeline_image(tenant_id, pipeline_id, &pin)
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn unpinned_pipelines_are_upgraded_to_the_default_image() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pinned_pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let unpinned_pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let pinned_image_id = read_pipeline_image(&app, tenant_id, pinned_pipeline_id)
+        .await
+        .image_id;
+    let pin = UpdatePipelineImageRequest {
+        image_id: Some(pinned_image_id),
+    };
+    let response = app
+        .update_pipeline_image(tenant_id, pinned_pipeline_id, &pin)
+        .await;
+    assert!(response.status().is_success());
+    let image_id = create_image_with_name(&app, "some/image:2".to_string(), true).await;
+
+    // Act
+    let upgrade = UpgradePipelinesRequest { image_id: None };
+    let response = app.upgrade_pipelines(tenant_id, &upgrade).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: UpgradePipelinesResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.image_id, image_id);
+    assert_eq!(response.pipeline_ids, vec![unpinned_pipeline_id]);
+    let unpinned = read_pipeline_image(&app, tenant_id, unpinned_pipeline_id).await;
+    assert_eq!(unpinned.image_id, image_id);
+    let pinned = read_pipeline_image(&app, tenant_id, pinned_pipeline_id).await;
+    assert_eq!(pinned.image_id, pinned_image_id);
+}
@@ -0,0 +1,252 @@
+use api::db::{
+    pipelines::{PipelineConfig, TableSelection},
+    quotas::TenantQuotas,
+};
+use reqwest::StatusCode;
+
+use crate::{
+    pipelines::{create_pipeline_with_config, new_pipeline_config},
+    sinks::{create_sink, new_sink_config},
+    sources::create_source,
+    tenants::create_tenant,
+    test_app::{
+        spawn_app, CreatePipelineRequest, CreateSinkRequest, QuotaExceededResponse, TestApp,
+    },
+};
+
+async fn set_quotas(app: &TestApp, tenant_id: &str, quotas: &TenantQuotas) {
+    let response = app.update_tenant_quotas(tenant_id, quotas).await;
+    assert!(response.status().is_success());
+}
+
+async fn quota_exceeded(response: reqwest::Response) -> QuotaExceededResponse {
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn quotas_are_unset_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.read_tenant_quotas(tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let quotas: TenantQuotas = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(quotas, TenantQuotas::default());
+}
+
+#[tokio::test]
+async fn quotas_can_be_updated() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_pipelines: Some(10),
+        max_tables_per_pipeline: Some(100),
+        max_sinks: None,
+    };
+
+    // Act
+    let response = app.update_tenant_quotas(tenant_id, &quotas).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: TenantQuotas = app
+        .read_tenant_quotas(tenant_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response, quotas);
+}
+
+#[tokio::test]
+async fn negative_quotas_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_sinks: Some(-1),
+        ..TenantQuotas::default()
+    };
+
+    // Act
+    let response = app.update_tenant_quotas(tenant_id, &quotas).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn quotas_of_non_existing_tenant_cant_be_updated() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .update_tenant_quotas("abcdefghijklmnopqrst", &TenantQuotas::default())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn sinks_beyond_the_quota_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_sinks: Some(1),
+        ..TenantQuotas::default()
+    };
+    set_quotas(&app, tenant_id, &quotas).await;
+    create_sink(&app, tenant_id).await;
+
+    // Act
+    let sink = CreateSinkRequest {
+        name: "Another BigQuery Sink".to_string(),
+        config: new_sink_config(),
+    };
+    let response = app.create_sink(tenant_id, &sink).await;
+
+    // Assert
+    let response = quota_exceeded(response).await;
+    assert_eq!(response.error, "quota max_sinks of 1 exceeded, 2 requested");
+    assert_eq!(response.quota, "max_sinks");
+    assert_eq!(response.limit, 1);
+    assert_eq!(response.requested, 2);
+}
+
+#[tokio::test]
+async fn pipelines_beyond_the_quota_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_pipelines: Some(1),
+        ..TenantQuotas::default()
+    };
+    set_quotas(&app, tenant_id, &quotas).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+
+    // Act
+    let pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id: create_sink(&app, tenant_id).await,
+        publication_name: "another_publication".to_string(),
+        config: new_pipeline_config(),
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+
+    // Assert
+    let response = quota_exceeded(response).await;
+    assert_eq!(response.quota, "max_pipelines");
+    assert_eq!(response.limit, 1);
+    assert_eq!(response.requested, 2);
+}
+
+#[tokio::test]
+async fn concurrent_sinks_cant_exceed_the_quota() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_sinks: Some(1),
+        ..TenantQuotas::default()
+    };
+    set_quotas(&app, tenant_id, &quotas).await;
+    let sink = |name: &str| CreateSinkRequest {
+        name: name.to_string(),
+        config: new_sink_config(),
+    };
+    let (first_sink, second_sink) = (sink("First BigQuery Sink"), sink("Second BigQuery Sink"));
+
+    // Act
+    let (first, second) = tokio::join!(
+        app.create_sink(tenant_id, &first_sink),
+        app.create_sink(tenant_id, &second_sink),
+    );
+
+    // Assert
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::FORBIDDEN]);
+}
+
+#[tokio::test]
+async fn concurrent_pipelines_cant_exceed_the_quota() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_pipelines: Some(1),
+        ..TenantQuotas::default()
+    };
+    set_quotas(&app, tenant_id, &quotas).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let first_pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id: create_sink(&app, tenant_id).await,
+        publication_name: "publication".to_string(),
+        config: new_pipeline_config(),
+    };
+    let second_pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id: create_sink(&app, tenant_id).await,
+        publication_name: "another_publication".to_string(),
+        config: new_pipeline_config(),
+    };
+
+    // Act
+    let (first, second) = tokio::join!(
+        app.create_pipeline(tenant_id, &first_pipeline),
+        app.create_pipeline(tenant_id, &second_pipeline),
+    );
+
+    // Assert
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::FORBIDDEN]);
+}
+
+#[tokio::test]
+async fn pipeline_naming_fewer_tables_than_the_quota_can_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_tables_per_pipeline: Some(2),
+        ..TenantQuotas::default()
+    };
+    set_quotas(&app, tenant_id, &quotas).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let config = PipelineConfig {
+        tables: Some(TableSelection {
+            include: vec!["public.users".to_string(), "public.orders".to_string()],
+            ..TableSelection::default()
+        }),
+        ..new_pipeline_config()
+    };
+
+    // Act
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, config).await;
+
+    // Assert
+    let response = app.read_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+}
@@ -1,10 +1,11 @@
-use api::db::sinks::SinkConfig;
+use api::{connection_test::ConnectionTestResult, db::sinks::SinkConfig};
 use reqwest::StatusCode;
 
 use crate::{
     tenants::create_tenant,
     test_app::{
-        spawn_app, CreateSinkRequest, CreateSinkResponse, SinkResponse, TestApp, UpdateSinkRequest,
+        spawn_app, CreateSinkRequest, CreateSinkResponse, ListResponse, SinkResponse,
+        SinkVersionResponse, TestApp, UpdateSinkRequest,
     },
 };
 
@@ -12,7 +13,7 @@ fn new_name() -> String {
     "BigQuery Sink".to_string()
 }
 
-fn new_sink_config() -> SinkConfig {
+pub fn new_sink_config() -> SinkConfig {
     SinkConfig::BigQuery {
         project_id: "project-id".to_string(),
         dataset_id: "dataset-id".to_string(),
@@ -20,6 +21,20 @@ fn new_sink_config() -> SinkConfig {
     }
 }
 
+/// Sinks are returned with their service account keys redacted
+fn redacted(config: SinkConfig) -> SinkConfig {
+    let SinkConfig::BigQuery {
+        project_id,
+        dataset_id,
+        service_account_key: _,
+    } = config;
+    SinkConfig::BigQuery {
+        project_id,
+        dataset_id,
+        service_account_key: "REDACTED".to_string(),
+    }
+}
+
 fn updated_name() -> String {
     "BigQuery Sink (Updated)".to_string()
 }
@@ -102,7 +117,7 @@ async fn an_existing_sink_can_be_read() {
     assert_eq!(response.id, sink_id);
     assert_eq!(&response.tenant_id, tenant_id);
     assert_eq!(response.name, sink.name);
-    assert_eq!(response.config, sink.config);
+    assert_eq!(response.config, redacted(sink.config));
 }
 
 #[tokio::test]
@@ -152,7 +167,38 @@ async fn an_existing_sink_can_be_updated() {
     assert_eq!(response.id, sink_id);
     assert_eq!(&response.tenant_id, tenant_id);
     assert_eq!(response.name, updated_config.name);
-    assert_eq!(response.config, updated_config.config);
+    assert_eq!(response.config, redacted(updated_config.config));
+}
+
+#[tokio::test]
+async fn sink_config_versions_are_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let updated_config = UpdateSinkRequest {
+        name: updated_name(),
+        config: updated_sink_config(),
+    };
+    let response = app.update_sink(tenant_id, sink_id, &updated_config).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.read_sink_versions(tenant_id, sink_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let versions: Vec<SinkVersionResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, 2);
+    assert_eq!(versions[0].name, updated_name());
+    assert_eq!(versions[0].config, redacted(updated_sink_config()));
+    assert_eq!(versions[1].version, 1);
+    assert_eq!(versions[1].name, new_name());
+    assert_eq!(versions[1].config, redacted(new_sink_config()));
 }
 
 #[tokio::test]
@@ -211,6 +257,107 @@ async fn a_non_existing_sink_cant_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn a_deleted_sink_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let response = app.delete_sink(tenant_id, sink_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.restore_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_sink(tenant_id, sink_id).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn a_non_existing_sink_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.restore_sink(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn a_sink_with_an_invalid_key_fails_its_connection_test() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let response = app.test_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ConnectionTestResult = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(!response.passed);
+    assert_eq!(response.checks.len(), 1);
+    assert_eq!(response.checks[0].name, "service_account_key");
+    assert!(!response.checks[0].passed);
+}
+
+#[tokio::test]
+async fn updating_a_sink_with_a_redacted_key_keeps_the_stored_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let service_account_key = serde_json::json!({
+        "client_email": "replicator@project-id.iam.gserviceaccount.com",
+        "private_key": "not a key",
+        "token_uri": "http://127.0.0.1:1/token",
+    });
+    let config = SinkConfig::BigQuery {
+        project_id: "project-id".to_string(),
+        dataset_id: "dataset-id".to_string(),
+        service_account_key: service_account_key.to_string(),
+    };
+    let sink_id = create_sink_with_config(&app, tenant_id, new_name(), config).await;
+
+    // Act
+    let updated_sink = UpdateSinkRequest {
+        name: updated_name(),
+        config: redacted(new_sink_config()),
+    };
+    let response = app.update_sink(tenant_id, sink_id, &updated_sink).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.test_sink(tenant_id, sink_id).await;
+    let response: ConnectionTestResult = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.checks[0].name, "service_account_key");
+    assert!(response.checks[0].passed);
+}
+
+#[tokio::test]
+async fn a_non_existing_sink_cant_be_tested() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.test_sink(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn all_sinks_can_be_read() {
     // Arrange
@@ -225,20 +372,20 @@ async fn all_sinks_can_be_read() {
 
     // Assert
     assert!(response.status().is_success());
-    let response: Vec<SinkResponse> = response
+    let response: ListResponse<SinkResponse> = response
         .json()
         .await
         .expect("failed to deserialize response");
-    for sink in response {
+    for sink in response.items {
         if sink.id == sink1_id {
             let name = new_name();
-            let config = new_sink_config();
+            let config = redacted(new_sink_config());
             assert_eq!(&sink.tenant_id, tenant_id);
             assert_eq!(sink.name, name);
             assert_eq!(sink.config, config);
         } else if sink.id == sink2_id {
             let name = updated_name();
-            let config = updated_sink_config();
+            let config = redacted(updated_sink_config());
             assert_eq!(&sink.tenant_id, tenant_id);
             assert_eq!(sink.name, name);
             assert_eq!(sink.config, config);
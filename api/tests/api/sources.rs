@@ -2,9 +2,12 @@ use api::db::sources::SourceConfig;
 use reqwest::StatusCode;
 
 use crate::{
+    pipelines::{create_pipeline_with_config, new_pipeline_config},
+    sinks::create_sink,
     tenants::create_tenant,
     test_app::{
-        spawn_app, CreateSourceRequest, CreateSourceResponse, SourceResponse, TestApp,
+        spawn_app, BatchItem, BatchResponse, CreateSourceRequest, CreateSourceResponse,
+        ListResponse, SourceResponse, SourceVersionResponse, TestApp, TestSourceRequest,
         UpdateSourceRequest,
     },
 };
@@ -13,7 +16,7 @@ fn new_name() -> String {
     "Postgres Source".to_string()
 }
 
-fn new_source_config() -> SourceConfig {
+pub fn new_source_config() -> SourceConfig {
     SourceConfig::Postgres {
         host: "localhost".to_string(),
         port: 5432,
@@ -24,6 +27,26 @@ fn new_source_config() -> SourceConfig {
     }
 }
 
+/// Sources are returned with their passwords redacted
+fn redacted(config: SourceConfig) -> SourceConfig {
+    let SourceConfig::Postgres {
+        host,
+        port,
+        name,
+        username,
+        password,
+        slot_name,
+    } = config;
+    SourceConfig::Postgres {
+        host,
+        port,
+        name,
+        username,
+        password: password.map(|_| "REDACTED".to_string()),
+        slot_name,
+    }
+}
+
 fn updated_name() -> String {
     "Postgres Source (Updated)".to_string()
 }
@@ -109,7 +132,7 @@ async fn an_existing_source_can_be_read() {
     assert_eq!(response.id, source_id);
     assert_eq!(&response.tenant_id, tenant_id);
     assert_eq!(response.name, source.name);
-    assert_eq!(response.config, source.config);
+    assert_eq!(response.config, redacted(source.config));
 }
 
 #[tokio::test]
@@ -161,7 +184,40 @@ async fn an_existing_source_can_be_updated() {
     assert_eq!(response.id, source_id);
     assert_eq!(&response.tenant_id, tenant_id);
     assert_eq!(response.name, updated_config.name);
-    assert_eq!(response.config, updated_config.config);
+    assert_eq!(response.config, redacted(updated_config.config));
+}
+
+#[tokio::test]
+async fn source_config_versions_are_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let updated_config = UpdateSourceRequest {
+        name: updated_name(),
+        config: updated_source_config(),
+    };
+    let response = app
+        .update_source(tenant_id, source_id, &updated_config)
+        .await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.read_source_versions(tenant_id, source_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let versions: Vec<SourceVersionResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, 2);
+    assert_eq!(versions[0].name, updated_name());
+    assert_eq!(versions[0].config, redacted(updated_source_config()));
+    assert_eq!(versions[1].version, 1);
+    assert_eq!(versions[1].name, new_name());
+    assert_eq!(versions[1].config, redacted(new_source_config()));
 }
 
 #[tokio::test]
@@ -220,6 +276,171 @@ async fn a_non_existing_source_cant_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn a_deleted_source_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let response = app.delete_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app.restore_source(tenant_id, source_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: SourceResponse = app
+        .read_source(tenant_id, source_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, source_id);
+    assert_eq!(response.name, new_name());
+}
+
+#[tokio::test]
+async fn a_source_which_isnt_deleted_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+
+    // Act
+    let response = app.restore_source(tenant_id, source_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn a_source_used_by_a_pipeline_cant_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+
+    // Act
+    let response = app.delete_source(tenant_id, source_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = app.read_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn sources_can_be_created_and_updated_in_a_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let items = vec![
+        BatchItem {
+            id: None,
+            item: CreateSourceRequest {
+                name: new_name(),
+                config: new_source_config(),
+            },
+        },
+        BatchItem {
+            id: Some(source_id),
+            item: CreateSourceRequest {
+                name: updated_name(),
+                config: updated_source_config(),
+            },
+        },
+        BatchItem {
+            id: Some(42),
+            item: CreateSourceRequest {
+                name: updated_name(),
+                config: updated_source_config(),
+            },
+        },
+    ];
+
+    // Act
+    let response = app.batch_sources(tenant_id, &items).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: BatchResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let results = response.results;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].index, 0);
+    assert_eq!(results[0].status, 200);
+    let created_id = results[0].id.expect("source not created");
+    assert_ne!(created_id, source_id);
+    assert!(results[0].error.is_none());
+    assert_eq!(results[1].index, 1);
+    assert_eq!(results[1].id, Some(source_id));
+    assert_eq!(results[1].status, 200);
+    assert_eq!(results[2].index, 2);
+    assert_eq!(results[2].id, None);
+    assert_eq!(results[2].status, 404);
+    assert_eq!(
+        results[2].error.as_deref(),
+        Some("source with id 42 not found")
+    );
+    let response: SourceResponse = app
+        .read_source(tenant_id, source_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.name, updated_name());
+}
+
+#[tokio::test]
+async fn batches_of_too_many_sources_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let items: Vec<_> = (0..101)
+        .map(|_| BatchItem {
+            id: None,
+            item: CreateSourceRequest {
+                name: new_name(),
+                config: new_source_config(),
+            },
+        })
+        .collect();
+
+    // Act
+    let response = app.batch_sources(tenant_id, &items).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.read_all_sources(tenant_id).await;
+    let response: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.items.is_empty());
+}
+
+#[tokio::test]
+async fn a_non_existing_source_cant_be_tested() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let test = TestSourceRequest {
+        publication_name: None,
+    };
+    let response = app.test_source(tenant_id, 42, &test).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn all_sources_can_be_read() {
     // Arrange
@@ -235,23 +456,100 @@ async fn all_sources_can_be_read() {
 
     // Assert
     assert!(response.status().is_success());
-    let response: Vec<SourceResponse> = response
+    let response: ListResponse<SourceResponse> = response
         .json()
         .await
         .expect("failed to deserialize response");
-    for source in response {
+    for source in response.items {
         if source.id == source1_id {
             let name = new_name();
-            let config = new_source_config();
+            let config = redacted(new_source_config());
             assert_eq!(&source.tenant_id, tenant_id);
             assert_eq!(source.name, name);
             assert_eq!(source.config, config);
         } else if source.id == source2_id {
             let name = updated_name();
-            let config = updated_source_config();
+            let config = redacted(updated_source_config());
             assert_eq!(&source.tenant_id, tenant_id);
             assert_eq!(source.name, name);
             assert_eq!(source.config, config);
         }
     }
 }
+
+#[tokio::test]
+async fn sources_can_be_read_in_pages() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let mut source_ids = vec![];
+    for name in ["a", "b", "c"] {
+        let id =
+            create_source_with_config(&app, tenant_id, name.to_string(), new_source_config()).await;
+        source_ids.push(id);
+    }
+
+    // Act
+    let response = app.read_sources_page(tenant_id, &[("limit", "2")]).await;
+    let first_page: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let cursor = first_page.next_cursor.expect("missing next cursor");
+    let response = app
+        .read_sources_page(tenant_id, &[("limit", "2"), ("cursor", &cursor)])
+        .await;
+    let second_page: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+
+    // Assert
+    let first_ids: Vec<i64> = first_page.items.iter().map(|s| s.id).collect();
+    let second_ids: Vec<i64> = second_page.items.iter().map(|s| s.id).collect();
+    assert_eq!(first_ids, source_ids[..2]);
+    assert_eq!(second_ids, source_ids[2..]);
+    assert!(second_page.next_cursor.is_none());
+}
+
+#[tokio::test]
+async fn sources_can_be_filtered_and_sorted_by_name() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    for name in ["orders replica", "Orders", "customers"] {
+        create_source_with_config(&app, tenant_id, name.to_string(), new_source_config()).await;
+    }
+
+    // Act
+    let response = app
+        .read_sources_page(
+            tenant_id,
+            &[("name", "order"), ("sort", "name"), ("order", "desc")],
+        )
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let names: Vec<String> = response.items.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["orders replica", "Orders"]);
+}
+
+#[tokio::test]
+async fn sources_cant_be_read_with_an_invalid_cursor() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app
+        .read_sources_page(tenant_id, &[("cursor", "not a cursor")])
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
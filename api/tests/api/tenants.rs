@@ -1,8 +1,17 @@
+use api::db::quotas::TenantQuotas;
 use reqwest::StatusCode;
 
-use crate::test_app::{
-    spawn_app, CreateTenantRequest, CreateTenantResponse, TenantResponse, TestApp,
-    UpdateTenantRequest,
+use crate::{
+    api_keys::create_api_key,
+    images::create_default_image,
+    pipelines::new_pipeline_config,
+    sinks::new_sink_config,
+    sources::new_source_config,
+    test_app::{
+        spawn_app, ApplyPlanResponse, CreateTenantRequest, CreateTenantResponse, DeclaredPipeline,
+        DeclaredSink, DeclaredSource, ListResponse, PipelineResponse, SinkResponse, SourceResponse,
+        TenantConfig, TenantResponse, TestApp, UpdateTenantRequest,
+    },
 };
 
 pub async fn create_tenant(app: &TestApp) -> String {
@@ -267,11 +276,11 @@ async fn all_tenants_can_be_read() {
 
     // Assert
     assert!(response.status().is_success());
-    let response: Vec<TenantResponse> = response
+    let response: ListResponse<TenantResponse> = response
         .json()
         .await
         .expect("failed to deserialize response");
-    for tenant in response {
+    for tenant in response.items {
         if tenant.id == tenant1_id {
             assert_eq!(tenant.name, "Tenant1");
         } else if tenant.id == tenant2_id {
@@ -279,3 +288,282 @@ async fn all_tenants_can_be_read() {
         }
     }
 }
+
+fn new_tenant_config() -> TenantConfig {
+    TenantConfig {
+        sources: vec![DeclaredSource {
+            name: "Postgres Source".to_string(),
+            config: new_source_config(),
+        }],
+        sinks: vec![DeclaredSink {
+            name: "BigQuery Sink".to_string(),
+            config: new_sink_config(),
+        }],
+        pipelines: vec![DeclaredPipeline {
+            source: "Postgres Source".to_string(),
+            sink: "BigQuery Sink".to_string(),
+            publication_name: "publication".to_string(),
+            config: new_pipeline_config(),
+        }],
+    }
+}
+
+async fn apply_tenant_config(
+    app: &TestApp,
+    tenant_id: &str,
+    config: &TenantConfig,
+    dry_run: bool,
+) -> ApplyPlanResponse {
+    let response = app.apply_tenant_config(tenant_id, config, dry_run).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn a_tenant_config_can_be_applied() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+
+    // Act
+    let plan = apply_tenant_config(&app, &tenant_id, &new_tenant_config(), false).await;
+
+    // Assert
+    assert!(plan.applied);
+    assert_eq!(plan.sources.create, vec!["Postgres Source"]);
+    assert_eq!(plan.sinks.create, vec!["BigQuery Sink"]);
+    assert_eq!(
+        plan.pipelines.create,
+        vec!["Postgres Source -> BigQuery Sink"]
+    );
+
+    let response = app.read_all_sources(&tenant_id).await;
+    let sources: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(sources.items.len(), 1);
+    let source_id = sources.items[0].id;
+
+    let response = app.read_all_sinks(&tenant_id).await;
+    let sinks: ListResponse<SinkResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(sinks.items.len(), 1);
+    let sink_id = sinks.items[0].id;
+
+    let response = app.read_all_pipelines(&tenant_id).await;
+    let pipelines: ListResponse<PipelineResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipelines.items.len(), 1);
+    assert_eq!(pipelines.items[0].source_id, source_id);
+    assert_eq!(pipelines.items[0].sink_id, sink_id);
+    assert_eq!(pipelines.items[0].publication_name, "publication");
+}
+
+#[tokio::test]
+async fn applying_a_tenant_config_again_changes_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+    apply_tenant_config(&app, &tenant_id, &new_tenant_config(), false).await;
+
+    // Act
+    let plan = apply_tenant_config(&app, &tenant_id, &new_tenant_config(), false).await;
+
+    // Assert
+    assert!(plan.applied);
+    for changes in [&plan.sources, &plan.sinks, &plan.pipelines] {
+        assert!(changes.create.is_empty());
+        assert!(changes.update.is_empty());
+        assert!(changes.delete.is_empty());
+        assert_eq!(changes.unchanged.len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn a_changed_tenant_config_updates_its_resources() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+    apply_tenant_config(&app, &tenant_id, &new_tenant_config(), false).await;
+    let mut config = new_tenant_config();
+    config.pipelines[0].publication_name = "updated_publication".to_string();
+
+    // Act
+    let plan = apply_tenant_config(&app, &tenant_id, &config, false).await;
+
+    // Assert
+    assert_eq!(plan.sources.unchanged, vec!["Postgres Source"]);
+    assert_eq!(
+        plan.pipelines.update,
+        vec!["Postgres Source -> BigQuery Sink"]
+    );
+    let response = app.read_all_pipelines(&tenant_id).await;
+    let pipelines: ListResponse<PipelineResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipelines.items[0].publication_name, "updated_publication");
+}
+
+#[tokio::test]
+async fn a_dry_run_of_a_tenant_config_changes_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = create_tenant(&app).await;
+
+    // Act
+    let plan = apply_tenant_config(&app, &tenant_id, &new_tenant_config(), true).await;
+
+    // Assert
+    assert!(!plan.applied);
+    assert_eq!(plan.sources.create, vec!["Postgres Source"]);
+    let response = app.read_all_sources(&tenant_id).await;
+    let sources: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(sources.items.is_empty());
+}
+
+#[tokio::test]
+async fn resources_left_out_of_a_tenant_config_are_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+    apply_tenant_config(&app, &tenant_id, &new_tenant_config(), false).await;
+    let empty = TenantConfig {
+        sources: vec![],
+        sinks: vec![],
+        pipelines: vec![],
+    };
+
+    // Act
+    let plan = apply_tenant_config(&app, &tenant_id, &empty, false).await;
+
+    // Assert
+    assert_eq!(plan.sources.delete, vec!["Postgres Source"]);
+    assert_eq!(plan.sinks.delete, vec!["BigQuery Sink"]);
+    assert_eq!(
+        plan.pipelines.delete,
+        vec!["Postgres Source -> BigQuery Sink"]
+    );
+    let response = app.read_all_sources(&tenant_id).await;
+    let sources: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(sources.items.is_empty());
+    let response = app.read_all_pipelines(&tenant_id).await;
+    let pipelines: ListResponse<PipelineResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(pipelines.items.is_empty());
+}
+
+#[tokio::test]
+async fn a_tenant_config_with_an_undeclared_source_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = create_tenant(&app).await;
+    let mut config = new_tenant_config();
+    config.sources.clear();
+
+    // Act
+    let response = app.apply_tenant_config(&tenant_id, &config, false).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn a_failed_tenant_config_apply_changes_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+    let quotas = TenantQuotas {
+        max_pipelines: Some(0),
+        max_tables_per_pipeline: None,
+        max_sinks: None,
+    };
+    let response = app.update_tenant_quotas(&tenant_id, &quotas).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let response = app
+        .apply_tenant_config(&tenant_id, &new_tenant_config(), false)
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.read_all_sources(&tenant_id).await;
+    let sources: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(sources.items.is_empty());
+    let response = app.read_all_sinks(&tenant_id).await;
+    let sinks: ListResponse<SinkResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(sinks.items.is_empty());
+}
+
+#[tokio::test]
+async fn a_tenant_config_can_be_applied_with_the_tenants_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = create_tenant(&app).await;
+    let api_key = create_api_key(&app, &tenant_id).await;
+
+    // Act
+    let response = app
+        .apply_tenant_config_with_api_key(&api_key.key, &tenant_id, &new_tenant_config())
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_all_sources(&tenant_id).await;
+    let sources: ListResponse<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(sources.items.len(), 1);
+}
+
+#[tokio::test]
+async fn a_tenant_config_cant_be_applied_with_another_tenants_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = create_tenant(&app).await;
+    let other_tenant_id = create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "OtherTenant".to_string(),
+    )
+    .await;
+    let api_key = create_api_key(&app, &other_tenant_id).await;
+
+    // Act
+    let response = app
+        .apply_tenant_config_with_api_key(&api_key.key, &tenant_id, &new_tenant_config())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
@@ -1,13 +1,30 @@
-use std::net::TcpListener;
+use std::{
+    collections::BTreeMap,
+    net::TcpListener,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use actix_web::{web, App, HttpResponse, HttpServer};
 use api::{
-    configuration::get_configuration,
-    db::{pipelines::PipelineConfig, sinks::SinkConfig, sources::SourceConfig},
+    configuration::{get_configuration, OidcSettings},
+    db::{
+        pipelines::PipelineConfig, quotas::TenantQuotas, sinks::SinkConfig, sources::SourceConfig,
+    },
     encryption::{self, generate_random_key},
+    oidc::OidcValidator,
     startup::{get_connection_pool, run},
 };
+use aws_lc_rs::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use base64::{
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    Engine,
+};
 use reqwest::{IntoUrl, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
 
 use crate::database::configure_database;
@@ -16,6 +33,89 @@ pub struct TestApp {
     pub address: String,
     pub api_client: reqwest::Client,
     pub api_key: String,
+    /// Url of the OpenID Connect issuer whose tokens the app accepts, if
+    /// spawned with [spawn_app_with_oidc]
+    pub oidc_issuer: Option<String>,
+}
+
+/// A page of a list endpoint's items
+#[derive(Deserialize)]
+pub struct ListResponse<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TestSourceRequest {
+    pub publication_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyResponse {
+    pub id: i64,
+    pub key: String,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyResponse {
+    pub id: i64,
+    pub tenant_id: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct CreateWebhookRequest {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub lag_threshold_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookResponse {
+    pub id: i64,
+    pub secret: String,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookResponse {
+    pub id: i64,
+    pub tenant_id: String,
+    pub name: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub lag_threshold_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveryResponse {
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogResponse {
+    pub actor: String,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub changes: Vec<AuditChange>,
+}
+
+#[derive(Deserialize)]
+pub struct AuditChange {
+    pub path: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
 }
 
 #[derive(Serialize)]
@@ -40,6 +140,77 @@ pub struct TenantResponse {
     pub name: String,
 }
 
+#[derive(Serialize)]
+pub struct TenantConfig {
+    pub sources: Vec<DeclaredSource>,
+    pub sinks: Vec<DeclaredSink>,
+    pub pipelines: Vec<DeclaredPipeline>,
+}
+
+#[derive(Serialize)]
+pub struct DeclaredSource {
+    pub name: String,
+    pub config: SourceConfig,
+}
+
+#[derive(Serialize)]
+pub struct DeclaredSink {
+    pub name: String,
+    pub config: SinkConfig,
+}
+
+#[derive(Serialize)]
+pub struct DeclaredPipeline {
+    pub source: String,
+    pub sink: String,
+    pub publication_name: String,
+    pub config: PipelineConfig,
+}
+
+#[derive(Deserialize)]
+pub struct ApplyPlanResponse {
+    pub applied: bool,
+    pub sources: PlannedChanges,
+    pub sinks: PlannedChanges,
+    pub pipelines: PlannedChanges,
+}
+
+#[derive(Deserialize)]
+pub struct PlannedChanges {
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct QuotaExceededResponse {
+    pub error: String,
+    pub quota: String,
+    pub limit: i32,
+    pub requested: i64,
+}
+
+#[derive(Serialize)]
+pub struct BatchItem<T> {
+    pub id: Option<i64>,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+#[derive(Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub id: Option<i64>,
+    pub status: u16,
+    pub error: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct CreateSourceRequest {
     pub name: String,
@@ -65,6 +236,13 @@ pub struct SourceResponse {
     pub config: SourceConfig,
 }
 
+#[derive(Deserialize)]
+pub struct SourceVersionResponse {
+    pub version: i32,
+    pub name: String,
+    pub config: SourceConfig,
+}
+
 #[derive(Serialize)]
 pub struct CreateSinkRequest {
     pub name: String,
@@ -90,6 +268,13 @@ pub struct SinkResponse {
     pub config: SinkConfig,
 }
 
+#[derive(Deserialize)]
+pub struct SinkVersionResponse {
+    pub version: i32,
+    pub name: String,
+    pub config: SinkConfig,
+}
+
 #[derive(Serialize)]
 pub struct CreatePipelineRequest {
     pub source_id: i64,
@@ -101,6 +286,19 @@ pub struct CreatePipelineRequest {
 #[derive(Deserialize)]
 pub struct CreatePipelineResponse {
     pub id: i64,
+    pub created: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub fields: Vec<FieldErrorResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct FieldErrorResponse {
+    pub field: String,
+    pub message: String,
 }
 
 #[derive(Deserialize)]
@@ -114,6 +312,18 @@ pub struct PipelineResponse {
     pub config: PipelineConfig,
 }
 
+#[derive(Deserialize)]
+pub struct PipelineVersionResponse {
+    pub version: i32,
+    pub publication_name: String,
+    pub config: PipelineConfig,
+}
+
+#[derive(Deserialize)]
+pub struct RollbackPipelineResponse {
+    pub version: i32,
+}
+
 #[derive(Serialize)]
 pub struct UpdatePipelineRequest {
     pub source_id: i64,
@@ -122,6 +332,100 @@ pub struct UpdatePipelineRequest {
     pub config: PipelineConfig,
 }
 
+#[derive(Serialize)]
+pub struct TableCopyProgress {
+    pub table_name: String,
+    pub rows_copied: u64,
+    pub copied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_rows: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct LatencyBucket {
+    pub le_secs: f64,
+    pub count: u64,
+}
+
+#[derive(Serialize)]
+pub struct TableMetricsReport {
+    pub table_name: String,
+    pub inserts: u64,
+    pub updates: u64,
+    pub deletes: u64,
+    pub conversion_errors: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub column_conversion_errors: BTreeMap<String, u64>,
+    pub sink_latency_buckets: Vec<LatencyBucket>,
+    pub sink_latency_sum_secs: f64,
+    pub sink_latency_count: u64,
+}
+
+#[derive(Serialize)]
+pub struct ReportPipelineStatusRequest {
+    pub phase: String,
+    pub last_flushed_lsn: Option<String>,
+    pub lag_bytes: Option<u64>,
+    pub lag_secs: Option<u64>,
+    pub source_wal_lsn: Option<String>,
+    pub estimated_lag_secs: Option<u64>,
+    pub tables: Vec<TableCopyProgress>,
+    pub table_metrics: Vec<TableMetricsReport>,
+    pub last_error: Option<String>,
+    pub pid: Option<u32>,
+    pub host: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ResyncTablesRequest {
+    pub tables: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateJobResponse {
+    pub id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct JobResponse {
+    pub id: i64,
+    pub tenant_id: String,
+    pub params: serde_json::Value,
+    pub status: String,
+    pub progress: Option<serde_json::Value>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct UpdatePipelineImageRequest {
+    pub image_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct PipelineImageResponse {
+    pub image_id: i64,
+    pub image_name: String,
+    pub pinned: bool,
+}
+
+#[derive(Serialize)]
+pub struct UpgradePipelinesRequest {
+    pub image_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpgradePipelinesResponse {
+    pub image_id: i64,
+    pub pipeline_ids: Vec<i64>,
+}
+
 #[derive(Serialize)]
 pub struct CreateImageRequest {
     pub name: String,
@@ -218,6 +522,258 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn apply_tenant_config(
+        &self,
+        tenant_id: &str,
+        config: &TenantConfig,
+        dry_run: bool,
+    ) -> reqwest::Response {
+        self.put_authenticated(format!("{}/v1/tenants/{tenant_id}/config", &self.address))
+            .query(&[("dry_run", dry_run)])
+            .json(config)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn read_tenant_quotas(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/tenants/{tenant_id}/quotas", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn update_tenant_quotas(
+        &self,
+        tenant_id: &str,
+        quotas: &TenantQuotas,
+    ) -> reqwest::Response {
+        self.put_authenticated(format!("{}/v1/tenants/{tenant_id}/quotas", &self.address))
+            .json(quotas)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_audit_logs(
+        &self,
+        tenant_id: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/tenants/{tenant_id}/audit", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn create_api_key(
+        &self,
+        tenant_id: &str,
+        api_key: &CreateApiKeyRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/api-keys", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(api_key)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn rotate_api_key(&self, tenant_id: &str, api_key_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/api-keys/{api_key_id}/rotate", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn delete_api_key(&self, tenant_id: &str, api_key_id: i64) -> reqwest::Response {
+        self.delete_authenticated(format!("{}/v1/api-keys/{api_key_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_all_api_keys(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/api-keys", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn create_webhook(
+        &self,
+        tenant_id: &str,
+        webhook: &CreateWebhookRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/webhooks", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(webhook)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_webhook(&self, tenant_id: &str, webhook_id: i64) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/webhooks/{webhook_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn update_webhook(
+        &self,
+        tenant_id: &str,
+        webhook_id: i64,
+        webhook: &CreateWebhookRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/webhooks/{webhook_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(webhook)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn delete_webhook(&self, tenant_id: &str, webhook_id: i64) -> reqwest::Response {
+        self.delete_authenticated(format!("{}/v1/webhooks/{webhook_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_all_webhooks(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/webhooks", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_webhook_deliveries(
+        &self,
+        tenant_id: &str,
+        webhook_id: i64,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/webhooks/{webhook_id}/deliveries",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    /// Reads all sources authenticating with a tenant's api key instead of
+    /// the app's api key
+    pub async fn read_all_sources_with_api_key(
+        &self,
+        api_key: &str,
+        tenant_id: Option<&str>,
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/v1/sources", &self.address))
+            .bearer_auth(api_key);
+        if let Some(tenant_id) = tenant_id {
+            request = request.header("tenant_id", tenant_id);
+        }
+        request.send().await.expect("failed to execute request")
+    }
+
+    pub async fn read_metrics_with_api_key(&self, api_key: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    /// Applies a tenant config authenticating with a tenant's api key instead
+    /// of the app's api key
+    pub async fn apply_tenant_config_with_api_key(
+        &self,
+        api_key: &str,
+        tenant_id: &str,
+        config: &TenantConfig,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/v1/tenants/{tenant_id}/config", &self.address))
+            .bearer_auth(api_key)
+            .json(config)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    /// Reads the audit log authenticating with a token, e.g. from
+    /// [TestApp::oidc_token], instead of the app's api key
+    pub async fn read_audit_logs_with_token(
+        &self,
+        token: &str,
+        tenant_id: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/v1/tenants/{tenant_id}/audit", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    /// Returns a token of the app's OpenID Connect issuer granting the role
+    /// within the tenant
+    pub fn oidc_token(&self, role: &str, tenant_id: &str) -> String {
+        let issuer = self
+            .oidc_issuer
+            .as_deref()
+            .expect("app was spawned without an oidc issuer");
+        let header = json!({ "alg": "RS256", "typ": "JWT", "kid": OIDC_KEY_ID });
+        let claims = json!({
+            "iss": issuer,
+            "aud": OIDC_AUDIENCE,
+            "exp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 300,
+            "roles": [role],
+            "tenant_id": tenant_id,
+            "sub": "user",
+        });
+        let message = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let der = BASE64_STANDARD
+            .decode(OIDC_PRIVATE_KEY.replace('\n', ""))
+            .expect("invalid private key");
+        let key_pair = RsaKeyPair::from_pkcs8(&der).expect("invalid private key");
+        let mut signature = vec![0; key_pair.public_modulus_len()];
+        key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .expect("failed to sign token");
+        format!("{message}.{}", BASE64_URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    pub async fn read_all_tenants_with_api_key(&self, api_key: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/v1/tenants", &self.address))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_source(
         &self,
         tenant_id: &str,
@@ -231,6 +787,19 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn batch_sources(
+        &self,
+        tenant_id: &str,
+        items: &[BatchItem<CreateSourceRequest>],
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sources/batch", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(items)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn read_source(&self, tenant_id: &str, source_id: i64) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/sources/{source_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -253,6 +822,14 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_source_versions(&self, tenant_id: &str, source_id: i64) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/sources/{source_id}/versions", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn delete_source(&self, tenant_id: &str, source_id: i64) -> reqwest::Response {
         self.delete_authenticated(format!("{}/v1/sources/{source_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -261,6 +838,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn restore_source(&self, tenant_id: &str, source_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sources/{source_id}/restore", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn read_all_sources(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/sources", &self.address))
             .header("tenant_id", tenant_id)
@@ -269,6 +854,33 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_sources_page(
+        &self,
+        tenant_id: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/sources", &self.address))
+            .header("tenant_id", tenant_id)
+            .query(query)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn test_source(
+        &self,
+        tenant_id: &str,
+        source_id: i64,
+        test: &TestSourceRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sources/{source_id}/test", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(test)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_sink(
         &self,
         tenant_id: &str,
@@ -304,6 +916,14 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_sink_versions(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/sinks/{sink_id}/versions", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn delete_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
         self.delete_authenticated(format!("{}/v1/sinks/{sink_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -312,6 +932,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn restore_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sinks/{sink_id}/restore", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn read_all_sinks(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/sinks", &self.address))
             .header("tenant_id", tenant_id)
@@ -320,6 +948,14 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn test_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sinks/{sink_id}/test", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_pipeline(
         &self,
         tenant_id: &str,
@@ -333,6 +969,21 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn create_pipeline_with_idempotency_key(
+        &self,
+        tenant_id: &str,
+        pipeline: &CreatePipelineRequest,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/pipelines", &self.address))
+            .header("tenant_id", tenant_id)
+            .header("Idempotency-Key", idempotency_key)
+            .json(pipeline)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn read_pipeline(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/pipelines/{pipeline_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -341,6 +992,51 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_pipeline_image(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/image",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn update_pipeline_image(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        image: &UpdatePipelineImageRequest,
+    ) -> reqwest::Response {
+        self.put_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/image",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .json(image)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn upgrade_pipelines(
+        &self,
+        tenant_id: &str,
+        upgrade: &UpgradePipelinesRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/pipelines/upgrade", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(upgrade)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn update_pipeline(
         &self,
         tenant_id: &str,
@@ -355,6 +1051,51 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_pipeline_versions(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/versions",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn rollback_pipeline(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        version: i32,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/rollback",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .query(&[("version", version)])
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn batch_pipelines(
+        &self,
+        tenant_id: &str,
+        items: &[BatchItem<CreatePipelineRequest>],
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/pipelines/batch", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(items)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn delete_pipeline(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
         self.delete_authenticated(format!("{}/v1/pipelines/{pipeline_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -363,6 +1104,17 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn restore_pipeline(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/restore",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+    }
+
     pub async fn read_all_pipelines(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/pipelines", &self.address))
             .header("tenant_id", tenant_id)
@@ -371,6 +1123,95 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_pipelines_page(
+        &self,
+        tenant_id: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/pipelines", &self.address))
+            .header("tenant_id", tenant_id)
+            .query(query)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn report_pipeline_status(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        status: &ReportPipelineStatusRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/status",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .json(status)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn stream_pipeline_events(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/events",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn validate_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sinks/{sink_id}/validate", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn initial_copy(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/initial-copy",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn resync_tables(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        resync: &ResyncTablesRequest,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/resync",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .json(resync)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn read_job(&self, tenant_id: &str, job_id: i64) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/jobs/{job_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_image(&self, image: &CreateImageRequest) -> reqwest::Response {
         self.post_authenticated(format!("{}/v1/images", &self.address))
             .json(image)
@@ -413,7 +1254,88 @@ impl TestApp {
     }
 }
 
+const OIDC_AUDIENCE: &str = "pg_replicate";
+
+const OIDC_KEY_ID: &str = "test-key";
+
+const OIDC_PRIVATE_KEY: &str = "
+MIIEvgIBADANBgkqhkiG9w0BAQEFAASCBKgwggSkAgEAAoIBAQDwcUROt8zwCDfW
+OguImkavrR9ri6o5KhktKcR0TsxzZ1aLaOnLmImuAtu5CF2YCpImrSh3/Sn4/nvt
+trhSL2u7Af8EuanvGW2FFbC05sHSHVinHV9HNiKj7oGbkdYF5HR1FTzpvoydbZp+
+zPLamoNJwtn1U7Ck/51sj+x1PBzGglxOTDNs6kBzMfSm75vf+QWHJp09en6/pCpJ
+RXDYunuDuM27xV28LHkaD/dT/PUhjw1eqoYNuwlwW2CVMDD7LV16xiGehhUpyu/l
+Us6GouvPQt3hEdI2V5wd6oAtig8Q7JTKnO3kIwzwLicWSLZFMfMEW8YM6Un/uF/Y
+mUgrW+VdAgMBAAECggEAJA0vq0fm3BS3XpEseop7T8r6+5Kg7tpPlRR+ptvLzv/v
+G48bAxJ3jlZSYK+O7QWkvVTYTQlyGin6Jf3wLC59Djix5qD6CGY9JCfI647v/9Oz
+n7cJOrG0xvSzjUJ0H0UOayHIlNmj1tUAxcNwJHPnfI9RQ/YM9eh3z7fSwg9F10OZ
+MhA/7lcYujKFZJ5/px36C/r18ArOO1+CyU5HFjvnes+/eQ49f3guCyXmtojj82gG
+kfnYP0PKwx8m47ZDbLajl4NpVsG55jfKL1gRjzTgRjjYBrsPW4L8X1bFnm0xoooV
+v2yDSoGdc+zLykck/WM1/BpNFBJeH1Jd1zSpS6Xv5wKBgQD4Vu4QTpnw0EOtlLu4
+gn2FKGAkOMKOsMHwlkm7kKRJLASeVmXBu9L8p05FbI5CHl0e/heMwEL4BtSHOh19
+GovEgbzBdUFZSPA+X+l0GTRW7Nz8GE64v0gWWDp8CehUsKbV2LI3ozqQplAmM7Hu
+PxSWMvboKlkg0xpd1mMpozXyGwKBgQD32/m4SjKVgBEUq3uC05KhoHBs03X63XTr
+iO8CtTSsA0oYDvq/ZowDfkk77eCqBE2Iq5EoTC2tApJMcatPzJ8kBXnzPxXZaOSV
+LE6dBbHPLFSaX63aDXnYAoqRSKS8RZORmete4z2ZNImC3qF7U3Hg62LvJdSvgaoN
+e4/DoNo95wKBgQC5CgzYmbiadpP3ApvdH13nme4JD0HokEvxuAm4eE3xN5s5X6by
+c8ECAKeRkxFssrMwJKPnfFpp9KznFaZjUSRTh1fdzkCjBnX8A/svRph5LIR8UqBV
+iDFudM8fHUK/1+B39r7UNgutPD78OAicPORaBh9zXhqLGsDKqrO4kqPfgQKBgQCE
+EBIbTnVrUMRJFiGGEIL1WU3tvjIDi5Grmdd3wuMgr9P/w6N47gO2LMiKhPGKxSYm
+pvt91DEWGOOolXwo1acJg1157OgQYiJBFU2BvrIB5+XgJxCzuTBtltUMGf+kTRIW
+p0NQ2JVOlz6Zm1PBKTHpHKInOCn9pYHdEzQDkSYXiwKBgFiL9PomF4OW1d70C0AZ
+YRJCcst3TbOOAzmH3sa9B4wydn7oAJMN++DA97PxWJooDCYwHxg3d7t5CoiMJ8J3
+fn0Yy2cWQYTgAnrW6RbM6eZBCR9qcEShREOyb+XijZvwOw3BEYb8WkQ2+xSXgCHD
+TAyLguj4hJmwBXcSQwurGFk5
+";
+
+const OIDC_MODULUS: &str = "8HFETrfM8Ag31joLiJpGr60fa4uqOSoZLSnEdE7Mc2dWi2jpy5iJrgLbuQhdmAqSJq0od_0p-P577ba4Ui9ruwH_BLmp7xlthRWwtObB0h1Ypx1fRzYio-6Bm5HWBeR0dRU86b6MnW2afszy2pqDScLZ9VOwpP-dbI_sdTwcxoJcTkwzbOpAczH0pu-b3_kFhyadPXp-v6QqSUVw2Lp7g7jNu8VdvCx5Gg_3U_z1IY8NXqqGDbsJcFtglTAw-y1desYhnoYVKcrv5VLOhqLrz0Ld4RHSNlecHeqALYoPEOyUypzt5CMM8C4nFki2RTHzBFvGDOlJ_7hf2JlIK1vlXQ";
+
+/// Serves the discovery document and keys of a fake OpenID Connect issuer
+/// signing tokens with [OIDC_PRIVATE_KEY], returns its url
+fn spawn_oidc_issuer() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+    let issuer = format!("http://127.0.0.1:{port}");
+    let discovery = json!({ "issuer": issuer, "jwks_uri": format!("{issuer}/keys") });
+    let keys = json!({
+        "keys": [{ "kid": OIDC_KEY_ID, "kty": "RSA", "n": OIDC_MODULUS, "e": "AQAB" }]
+    });
+    let server = HttpServer::new(move || {
+        let discovery = discovery.clone();
+        let keys = keys.clone();
+        App::new()
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(move || {
+                    let discovery = discovery.clone();
+                    async move { HttpResponse::Ok().json(discovery) }
+                }),
+            )
+            .route(
+                "/keys",
+                web::get().to(move || {
+                    let keys = keys.clone();
+                    async move { HttpResponse::Ok().json(keys) }
+                }),
+            )
+    })
+    .listen(listener)
+    .expect("failed to bind address")
+    .run();
+    tokio::spawn(server);
+    issuer
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_oidc_issuer(None).await
+}
+
+/// Spawns the app also accepting tokens of a fake OpenID Connect issuer,
+/// which [TestApp::oidc_token] returns
+pub async fn spawn_app_with_oidc() -> TestApp {
+    spawn_app_with_oidc_issuer(Some(spawn_oidc_issuer())).await
+}
+
+async fn spawn_app_with_oidc_issuer(oidc_issuer: Option<String>) -> TestApp {
     let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind random port");
     let port = listener.local_addr().unwrap().port();
     let mut configuration = get_configuration().expect("Failed to read configuration");
@@ -423,12 +1345,23 @@ pub async fn spawn_app() -> TestApp {
     let key = generate_random_key::<32>().expect("failed to generate random key");
     let encryption_key = encryption::EncryptionKey { id: 0, key };
     let api_key = "XOUbHmWbt9h7nWl15wWwyWQnctmFGNjpawMc3lT5CFs=".to_string();
+    let oidc_validator = oidc_issuer.clone().map(|issuer| {
+        OidcValidator::new(OidcSettings {
+            issuer,
+            audience: OIDC_AUDIENCE.to_string(),
+            roles_claim: "roles".to_string(),
+            tenant_claim: "tenant_id".to_string(),
+        })
+    });
     let server = run(
         listener,
         connection_pool.clone(),
         encryption_key,
         api_key.clone(),
         None,
+        oidc_validator,
+        None,
+        configuration.metrics,
     )
     .await
     .expect("failed to bind address");
@@ -439,5 +1372,6 @@ pub async fn spawn_app() -> TestApp {
         address,
         api_client,
         api_key,
+        oidc_issuer,
     }
 }
@@ -0,0 +1,264 @@
+use reqwest::StatusCode;
+
+use crate::{
+    images::create_default_image,
+    pipelines::{create_pipeline_with_config, new_pipeline_config},
+    sinks::create_sink,
+    sources::create_source,
+    tenants::create_tenant,
+    test_app::{
+        spawn_app, CreateWebhookRequest, CreateWebhookResponse, ListResponse,
+        ReportPipelineStatusRequest, TableCopyProgress, TestApp, WebhookDeliveryResponse,
+        WebhookResponse,
+    },
+};
+
+fn new_webhook() -> CreateWebhookRequest {
+    CreateWebhookRequest {
+        name: "On-call alerts".to_string(),
+        url: "https://example.com/hooks/pg-replicate".to_string(),
+        events: vec![
+            "pipeline_errored".to_string(),
+            "lag_threshold_exceeded".to_string(),
+            "table_sync_completed".to_string(),
+        ],
+        lag_threshold_secs: Some(300),
+    }
+}
+
+async fn create_webhook(app: &TestApp, tenant_id: &str) -> CreateWebhookResponse {
+    let response = app.create_webhook(tenant_id, &new_webhook()).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+async fn create_pipeline(app: &TestApp, tenant_id: &str) -> i64 {
+    create_default_image(app).await;
+    let source_id = create_source(app, tenant_id).await;
+    let sink_id = create_sink(app, tenant_id).await;
+    create_pipeline_with_config(app, tenant_id, source_id, sink_id, new_pipeline_config()).await
+}
+
+fn status_report(phase: &str, lag_secs: u64, copied: bool) -> ReportPipelineStatusRequest {
+    ReportPipelineStatusRequest {
+        phase: phase.to_string(),
+        last_flushed_lsn: None,
+        lag_bytes: None,
+        lag_secs: Some(lag_secs),
+        source_wal_lsn: None,
+        estimated_lag_secs: None,
+        tables: vec![TableCopyProgress {
+            table_name: "public.users".to_string(),
+            rows_copied: 100,
+            copied,
+            estimated_rows: None,
+            copy_secs: None,
+            eta_secs: None,
+        }],
+        table_metrics: vec![],
+        last_error: (phase == "errored").then(|| "connection reset".to_string()),
+        pid: None,
+        host: None,
+    }
+}
+
+async fn read_deliveries(
+    app: &TestApp,
+    tenant_id: &str,
+    webhook_id: i64,
+) -> Vec<WebhookDeliveryResponse> {
+    let response = app.read_webhook_deliveries(tenant_id, webhook_id).await;
+    assert!(response.status().is_success());
+    response
+        .json()
+        .await
+        .expect("failed to deserialize response")
+}
+
+#[tokio::test]
+async fn webhook_can_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let webhook = create_webhook(&app, tenant_id).await;
+
+    // Assert
+    assert!(webhook.secret.starts_with("whsec_"));
+    let response = app.read_webhook(tenant_id, webhook.id).await;
+    assert!(response.status().is_success());
+    let response: WebhookResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, webhook.id);
+    assert_eq!(&response.tenant_id, tenant_id);
+    assert_eq!(response.name, "On-call alerts");
+    assert_eq!(response.url, "https://example.com/hooks/pg-replicate");
+    assert_eq!(response.events, new_webhook().events);
+    assert_eq!(response.lag_threshold_secs, Some(300));
+}
+
+#[tokio::test]
+async fn webhook_with_an_invalid_url_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let mut webhook = new_webhook();
+    webhook.url = "ftp://example.com/hooks".to_string();
+
+    // Act
+    let response = app.create_webhook(tenant_id, &webhook).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn webhook_for_lag_without_a_threshold_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let mut webhook = new_webhook();
+    webhook.lag_threshold_secs = None;
+
+    // Act
+    let response = app.create_webhook(tenant_id, &webhook).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn an_existing_webhook_can_be_updated() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let webhook = create_webhook(&app, tenant_id).await;
+    let updated_webhook = CreateWebhookRequest {
+        name: "Pipeline starts".to_string(),
+        url: "https://example.com/hooks/starts".to_string(),
+        events: vec!["pipeline_started".to_string()],
+        lag_threshold_secs: None,
+    };
+
+    // Act
+    let response = app
+        .update_webhook(tenant_id, webhook.id, &updated_webhook)
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_webhook(tenant_id, webhook.id).await;
+    let response: WebhookResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.name, updated_webhook.name);
+    assert_eq!(response.url, updated_webhook.url);
+    assert_eq!(response.events, updated_webhook.events);
+    assert_eq!(response.lag_threshold_secs, None);
+}
+
+#[tokio::test]
+async fn an_existing_webhook_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let webhook = create_webhook(&app, tenant_id).await;
+
+    // Act
+    let response = app.delete_webhook(tenant_id, webhook.id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_webhook(tenant_id, webhook.id).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn all_webhooks_can_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let webhook1 = create_webhook(&app, tenant_id).await;
+    let webhook2 = create_webhook(&app, tenant_id).await;
+
+    // Act
+    let response = app.read_all_webhooks(tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ListResponse<WebhookResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let ids: Vec<i64> = response.items.iter().map(|webhook| webhook.id).collect();
+    assert_eq!(ids, vec![webhook1.id, webhook2.id]);
+}
+
+#[tokio::test]
+async fn status_changes_are_queued_for_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let webhook = create_webhook(&app, tenant_id).await;
+    let pipeline_id = create_pipeline(&app, tenant_id).await;
+
+    // Act
+    for report in [
+        status_report("copying", 0, false),
+        status_report("cdc", 600, true),
+        status_report("errored", 900, true),
+        status_report("errored", 900, true),
+    ] {
+        let response = app
+            .report_pipeline_status(tenant_id, pipeline_id, &report)
+            .await;
+        assert!(response.status().is_success());
+    }
+
+    // Assert
+    let deliveries = read_deliveries(&app, tenant_id, webhook.id).await;
+    let mut events: Vec<&str> = deliveries
+        .iter()
+        .map(|delivery| delivery.event.as_str())
+        .collect();
+    events.sort();
+    assert_eq!(
+        events,
+        vec![
+            "lag_threshold_exceeded",
+            "pipeline_errored",
+            "table_sync_completed"
+        ]
+    );
+    for delivery in &deliveries {
+        assert_eq!(delivery.status, "pending");
+        assert_eq!(delivery.attempts, 0);
+        assert_eq!(delivery.payload["pipeline_id"], pipeline_id);
+        assert_eq!(delivery.payload["event"], delivery.event.as_str());
+    }
+    let errored = deliveries
+        .iter()
+        .find(|delivery| delivery.event == "pipeline_errored")
+        .unwrap();
+    assert_eq!(errored.payload["data"]["error"], "connection reset");
+}
+
+#[tokio::test]
+async fn deliveries_of_a_non_existing_webhook_cant_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.read_webhook_deliveries(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
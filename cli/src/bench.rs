@@ -0,0 +1,139 @@
+use std::{error::Error, time::Instant};
+
+use pg_replicate::pipeline::{
+    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+    sinks::BatchSink,
+    sources::synthetic::{ChangeMix, SyntheticSource, SyntheticSourceConfig},
+    status::PipelinePhase,
+    PipelineAction,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct BenchArgs {
+    /// Number of generated tables
+    #[arg(long, default_value_t = 4)]
+    tables: u32,
+
+    /// Number of columns of each table besides its primary key
+    #[arg(long, default_value_t = 8)]
+    columns: usize,
+
+    /// Number of rows of each table to copy
+    #[arg(long, default_value_t = 10_000)]
+    rows: u64,
+
+    /// Number of changes to stream after the copy
+    #[arg(long, default_value_t = 100_000)]
+    changes: u64,
+
+    /// Changes generated per second, as many as the sink writes if not set
+    #[arg(long)]
+    rate: Option<u32>,
+
+    /// Number of changes in each transaction
+    #[arg(long, default_value_t = 10)]
+    transaction_size: u32,
+
+    /// Relative frequencies of inserts, updates and deletes
+    #[arg(long, value_parser = parse_change_mix, default_value = "70,20,10")]
+    mix: ChangeMix,
+
+    /// Seed of the generated values
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl From<BenchArgs> for SyntheticSourceConfig {
+    fn from(args: BenchArgs) -> Self {
+        SyntheticSourceConfig {
+            num_tables: args.tables,
+            num_columns: args.columns,
+            rows_per_table: args.rows,
+            num_changes: Some(args.changes),
+            changes_per_second: args.rate,
+            changes_per_transaction: args.transaction_size,
+            change_mix: args.mix,
+            seed: args.seed,
+        }
+    }
+}
+
+fn parse_change_mix(mix: &str) -> Result<ChangeMix, String> {
+    let weights: Vec<u32> = mix
+        .split(',')
+        .map(|weight| weight.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid weight in `{mix}`: {e}"))?;
+    let [inserts, updates, deletes] = weights[..] else {
+        return Err(format!(
+            "expected the weights of inserts, updates and deletes, e.g. 70,20,10, got `{mix}`"
+        ));
+    };
+    Ok(ChangeMix {
+        inserts,
+        updates,
+        deletes,
+    })
+}
+
+/// Replicates generated tables and changes to the sink and prints how fast
+/// they were written
+pub async fn run<Snk: BatchSink>(
+    args: BenchArgs,
+    sink: Snk,
+    batch_config: BatchConfig,
+) -> Result<(), Box<dyn Error>> {
+    let num_changes = args.changes;
+    let source = SyntheticSource::new(args.into());
+    let mut pipeline = BatchDataPipeline::new(source, sink, PipelineAction::Both, batch_config);
+
+    let final_status = pipeline.status();
+    let mut status = pipeline.status();
+    let cdc_started = tokio::spawn(async move {
+        while status.changed().await.is_ok() {
+            if status.borrow().phase == PipelinePhase::Cdc {
+                return Some(Instant::now());
+            }
+        }
+        None
+    });
+
+    let start = Instant::now();
+    pipeline.start().await?;
+    let end = Instant::now();
+    // dropping the pipeline closes the status channel, ending the wait for
+    // the cdc phase if the changes weren't streamed
+    drop(pipeline);
+    let cdc_start = cdc_started.await?.unwrap_or(end);
+
+    let rows_copied: u64 = final_status
+        .borrow()
+        .tables
+        .values()
+        .map(|table| table.rows_copied)
+        .sum();
+    let copy_secs = (cdc_start - start).as_secs_f64();
+    let cdc_secs = (end - cdc_start).as_secs_f64();
+    println!(
+        "copied {rows_copied} rows in {copy_secs:.2}s ({:.0} rows/s)",
+        rows_copied as f64 / copy_secs.max(f64::EPSILON)
+    );
+    println!(
+        "streamed {num_changes} changes in {cdc_secs:.2}s ({:.0} changes/s)",
+        num_changes as f64 / cdc_secs.max(f64::EPSILON)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_change_mix;
+
+    #[test]
+    fn parses_change_mixes() {
+        let mix = parse_change_mix("1, 2,3").unwrap();
+        assert_eq!((mix.inserts, mix.updates, mix.deletes), (1, 2, 3));
+        assert!(parse_change_mix("1,2").is_err());
+        assert!(parse_change_mix("1,2,x").is_err());
+    }
+}
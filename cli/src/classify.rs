@@ -0,0 +1,29 @@
+use std::error::Error;
+
+use pg_replicate::pipeline::classification::{classify, ClassificationOptions};
+
+use crate::{configuration::SourceSettings, postgres_source, status::OutputFormat};
+
+/// Prints the columns of the source's tables which likely hold personal data
+pub async fn print_classifications(
+    source: SourceSettings,
+    options: &ClassificationOptions,
+    output: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let postgres_source = postgres_source(source, false, vec![]).await?;
+    let classifications = classify(&postgres_source, options).await?;
+
+    match output {
+        OutputFormat::Table => {
+            if classifications.is_empty() {
+                println!("no column likely holds personal data");
+            }
+            for classification in &classifications {
+                println!("{classification}");
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&classifications)?),
+    }
+
+    Ok(())
+}
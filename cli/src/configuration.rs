@@ -0,0 +1,986 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+use config::FileFormat;
+use pg_replicate::{
+    clients::postgres::{
+        ColumnOptions, ConnectionOptions, ReplicationClient, ReplicationClientError,
+    },
+    pipeline::{
+        classification::ClassificationOptions,
+        encryption::EncryptionOptions,
+        sinks::{naming::NamingRule, NamingStrategy, RemovedTablePolicy},
+        sources::postgres::TableFilter,
+    },
+};
+use thiserror::Error;
+
+#[cfg(feature = "bigquery")]
+use pg_replicate::clients::bigquery::BigQueryType;
+#[cfg(feature = "delta")]
+use pg_replicate::clients::delta::{DeltaTableOptions, DeltaType, ParquetCompression};
+
+#[derive(Debug, Error)]
+pub enum ConfigurationError {
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("{0} is not a yaml or toml file, expected a .yaml, .yml or .toml extension")]
+    UnsupportedFormat(String),
+
+    #[error("environment variable {0} is not set")]
+    MissingEnvVar(String),
+
+    #[error("unterminated ${{ in the configuration file")]
+    UnterminatedVariable,
+
+    #[error("invalid configuration: {0}")]
+    Config(#[from] config::ConfigError),
+
+    #[error("the {0} sink is not enabled in this build, rebuild with `--features {1}`")]
+    SinkNotEnabled(&'static str, &'static str),
+}
+
+#[derive(Clone, serde::Deserialize, PartialEq, Eq)]
+pub enum SourceSettings {
+    Postgres {
+        /// Host on which Postgres is running
+        host: String,
+
+        /// Port on which Postgres is running
+        port: u16,
+
+        /// Postgres database name
+        name: String,
+
+        /// Postgres database user name
+        username: String,
+
+        /// Postgres database user password
+        password: Option<String>,
+
+        /// Postgres slot name. Only needed to stream changes
+        slot_name: Option<String>,
+
+        /// Postgres publication name
+        publication: String,
+
+        /// Tables of the publication to replicate. All of them are
+        /// replicated if not set
+        #[serde(default)]
+        tables: Option<TableFilter>,
+
+        /// How generated, identity and serial columns, columns of
+        /// unsupported types, timestamps and large objects are replicated and
+        /// which types columns are replicated as
+        #[serde(default)]
+        columns: ColumnOptions,
+
+        /// Application name, session parameters and proxy of the
+        /// connections to the source
+        #[serde(default)]
+        connection: ConnectionOptions,
+    },
+}
+
+impl SourceSettings {
+    /// Connects to the source without starting a replication
+    pub async fn connect(&self) -> Result<ReplicationClient, ReplicationClientError> {
+        let Self::Postgres {
+            host,
+            port,
+            name,
+            username,
+            password,
+            connection,
+            ..
+        } = self;
+        ReplicationClient::connect_with_options(
+            host,
+            *port,
+            name,
+            username,
+            password.clone(),
+            connection,
+        )
+        .await
+    }
+}
+
+impl Debug for SourceSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Postgres {
+                host,
+                port,
+                name,
+                username,
+                password: _,
+                slot_name,
+                publication,
+                tables,
+                columns,
+                connection,
+            } => f
+                .debug_struct("Postgres")
+                .field("host", host)
+                .field("port", port)
+                .field("name", name)
+                .field("username", username)
+                .field("password", &"REDACTED")
+                .field("slot_name", slot_name)
+                .field("publication", publication)
+                .field("tables", tables)
+                .field("columns", columns)
+                .field("connection", connection)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Deserialize, PartialEq, Eq)]
+pub enum SinkSettings {
+    /// Prints the replicated rows and changes
+    Stdout,
+
+    /// Discards the replicated rows and changes after checking them against
+    /// the tables' schemas, confirming them to the source
+    Null,
+
+    BigQuery {
+        /// BigQuery project id
+        project_id: String,
+
+        /// BigQuery dataset id
+        dataset_id: String,
+
+        /// Path to GCP's service account key. If not set Application
+        /// Default Credentials are used
+        service_account_key_file: Option<String>,
+
+        /// How the tables are named, `schema_name` tables in the dataset by
+        /// default
+        #[serde(default)]
+        naming: NamingStrategy,
+
+        /// BigQuery types of the columns of Postgres types like `uuid`,
+        /// replacing their default types
+        #[cfg(feature = "bigquery")]
+        #[serde(default)]
+        type_mappings: HashMap<String, BigQueryType>,
+    },
+
+    DuckDb {
+        /// DuckDb file name
+        file: String,
+    },
+
+    MotherDuck {
+        /// MotherDuck access token
+        access_token: String,
+
+        /// MotherDuck database name
+        db_name: String,
+    },
+
+    Delta {
+        /// Path to the Delta Lake, a local directory or an object store url
+        path: String,
+
+        /// Object store options like AWS_REGION
+        #[serde(default)]
+        storage_options: HashMap<String, String>,
+
+        /// Enable the change data feed of the Delta tables
+        #[serde(default)]
+        change_data_feed: bool,
+
+        /// Codec and level compressing the tables' Parquet files, snappy
+        /// by default
+        #[cfg(feature = "delta")]
+        #[serde(default)]
+        compression: ParquetCompression,
+
+        /// How the tables are named, `schema_name` tables in the lake by
+        /// default
+        #[serde(default)]
+        naming: NamingStrategy,
+
+        /// Names of the Delta tables of source tables `schema.name`,
+        /// replacing their default names
+        #[serde(default)]
+        table_names: HashMap<String, String>,
+
+        /// Options of source tables `schema.name` used when their Delta
+        /// tables are created, like their partition columns
+        #[cfg(feature = "delta")]
+        #[serde(default)]
+        table_options: HashMap<String, DeltaTableOptions>,
+
+        /// Delta types of the columns of Postgres types like `numeric`,
+        /// replacing their default types
+        #[cfg(feature = "delta")]
+        #[serde(default)]
+        type_mappings: HashMap<String, DeltaType>,
+    },
+}
+
+impl SinkSettings {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SinkSettings::Stdout => "stdout",
+            SinkSettings::Null => "null",
+            SinkSettings::BigQuery { .. } => "bigquery",
+            SinkSettings::DuckDb { .. } => "duckdb",
+            SinkSettings::MotherDuck { .. } => "motherduck",
+            SinkSettings::Delta { .. } => "delta",
+        }
+    }
+
+    /// Feature of the build enabling the sink
+    fn feature(&self) -> &'static str {
+        match self {
+            SinkSettings::Stdout => "stdout",
+            SinkSettings::Null => "null",
+            SinkSettings::BigQuery { .. } => "bigquery",
+            SinkSettings::DuckDb { .. } | SinkSettings::MotherDuck { .. } => "duckdb",
+            SinkSettings::Delta { .. } => "delta",
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        match self {
+            SinkSettings::Stdout => cfg!(feature = "stdout"),
+            SinkSettings::Null => cfg!(feature = "null"),
+            SinkSettings::BigQuery { .. } => cfg!(feature = "bigquery"),
+            SinkSettings::DuckDb { .. } | SinkSettings::MotherDuck { .. } => {
+                cfg!(feature = "duckdb")
+            }
+            SinkSettings::Delta { .. } => cfg!(feature = "delta"),
+        }
+    }
+
+    /// Whether the sink can count its rows, to verify them against the
+    /// source's
+    pub fn supports_verification(&self) -> bool {
+        matches!(
+            self,
+            SinkSettings::BigQuery { .. }
+                | SinkSettings::DuckDb { .. }
+                | SinkSettings::MotherDuck { .. }
+        )
+    }
+
+    /// Whether the sink can hash its rows, to verify them against the
+    /// source's with checksums
+    pub fn supports_checksums(&self) -> bool {
+        matches!(self, SinkSettings::BigQuery { .. })
+    }
+
+    /// Whether the sink keeps the copied tables and the position of the
+    /// streamed changes, so that a state can be imported to it
+    pub fn keeps_state(&self) -> bool {
+        matches!(
+            self,
+            SinkSettings::BigQuery { .. }
+                | SinkSettings::DuckDb { .. }
+                | SinkSettings::MotherDuck { .. }
+        )
+    }
+}
+
+impl Debug for SinkSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stdout => f.write_str("Stdout"),
+            Self::Null => f.write_str("Null"),
+            Self::BigQuery {
+                project_id,
+                dataset_id,
+                service_account_key_file,
+                naming,
+                #[cfg(feature = "bigquery")]
+                type_mappings,
+            } => {
+                let mut debug_struct = f.debug_struct("BigQuery");
+                debug_struct
+                    .field("project_id", project_id)
+                    .field("dataset_id", dataset_id)
+                    .field("service_account_key_file", service_account_key_file)
+                    .field("naming", naming);
+                #[cfg(feature = "bigquery")]
+                debug_struct.field("type_mappings", type_mappings);
+                debug_struct.finish()
+            }
+            Self::DuckDb { file } => f.debug_struct("DuckDb").field("file", file).finish(),
+            Self::MotherDuck {
+                access_token: _,
+                db_name,
+            } => f
+                .debug_struct("MotherDuck")
+                .field("access_token", &"REDACTED")
+                .field("db_name", db_name)
+                .finish(),
+            Self::Delta {
+                path,
+                storage_options,
+                change_data_feed,
+                #[cfg(feature = "delta")]
+                compression,
+                naming,
+                table_names,
+                #[cfg(feature = "delta")]
+                table_options,
+                #[cfg(feature = "delta")]
+                type_mappings,
+            } => {
+                // storage options hold object store credentials, only their
+                // keys are shown
+                let mut storage_option_keys: Vec<&String> = storage_options.keys().collect();
+                storage_option_keys.sort();
+                let mut debug_struct = f.debug_struct("Delta");
+                debug_struct
+                    .field("path", path)
+                    .field("storage_options", &storage_option_keys)
+                    .field("change_data_feed", change_data_feed);
+                #[cfg(feature = "delta")]
+                debug_struct.field("compression", compression);
+                debug_struct
+                    .field("naming", naming)
+                    .field("table_names", table_names);
+                #[cfg(feature = "delta")]
+                debug_struct
+                    .field("table_options", table_options)
+                    .field("type_mappings", type_mappings);
+                debug_struct.finish()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+pub struct BatchSettings {
+    /// maximum batch size in number of events
+    pub max_size: usize,
+
+    /// maximum duration, in seconds, to wait for a batch to fill
+    pub max_fill_secs: u64,
+
+    /// whether batches end only on the commits of transactions, so that a
+    /// transaction is never split across batches
+    #[serde(default)]
+    pub transactional: bool,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        BatchSettings {
+            max_size: 1000,
+            max_fill_secs: 10,
+            transactional: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+pub struct Settings {
+    pub source: SourceSettings,
+    pub sink: SinkSettings,
+
+    #[serde(default)]
+    pub batch: BatchSettings,
+
+    /// Columns encrypted before their values are written to the sink, none
+    /// by default
+    #[serde(default)]
+    pub encryption: Option<EncryptionOptions>,
+
+    /// Classification of the columns likely holding personal data when a
+    /// pipeline is started, none by default
+    #[serde(default)]
+    pub classification: Option<ClassificationOptions>,
+
+    /// What to do with the sink's tables of tables which are no longer
+    /// replicated when a pipeline starts, kept by default. Only sinks which
+    /// keep a state know the tables they replicated
+    #[serde(default)]
+    pub removed_table_policy: RemovedTablePolicy,
+
+    /// Rules renaming the tables and columns in the BigQuery, Delta and
+    /// DuckDB sinks and in snapshots, applied in order, e.g.
+    /// `[SnakeCase, { EnvironmentPrefix: staging }]`. The names are kept by
+    /// default
+    #[serde(default)]
+    pub naming_conventions: Vec<NamingRule>,
+
+    /// File the rows which fail to convert with the `DeadLetter` policy of
+    /// the source's `conversion_errors` are appended to, as json lines. They
+    /// are only logged if not set
+    #[serde(default)]
+    pub dead_letter_file: Option<PathBuf>,
+
+    /// Filter of the logs in `RUST_LOG` syntax, e.g. `debug` or
+    /// `pg_replicate=debug`. `RUST_LOG` is used if not set
+    #[serde(default)]
+    pub log_level: Option<String>,
+}
+
+/// Reads the settings from a yaml or toml file, replacing `${NAME}` with the
+/// value of the environment variable `NAME` so that secrets can be kept out
+/// of the file. `$$` is replaced with a single `$`.
+pub fn load_settings(path: &Path) -> Result<Settings, ConfigurationError> {
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => FileFormat::Yaml,
+        Some("toml") => FileFormat::Toml,
+        _ => {
+            return Err(ConfigurationError::UnsupportedFormat(
+                path.display().to_string(),
+            ))
+        }
+    };
+    let contents = std::fs::read_to_string(path).map_err(|source| ConfigurationError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let contents = interpolate(&contents, |name| std::env::var(name).ok())?;
+    parse_settings(&contents, format)
+}
+
+fn parse_settings(contents: &str, format: FileFormat) -> Result<Settings, ConfigurationError> {
+    let settings = config::Config::builder()
+        .add_source(config::File::from_str(contents, format))
+        .build()?
+        .try_deserialize::<Settings>()?;
+
+    if !settings.sink.is_enabled() {
+        return Err(ConfigurationError::SinkNotEnabled(
+            settings.sink.name(),
+            settings.sink.feature(),
+        ));
+    }
+
+    Ok(settings)
+}
+
+fn interpolate(
+    contents: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<String, ConfigurationError> {
+    let mut interpolated = String::with_capacity(contents.len());
+    let mut rest = contents;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            interpolated.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or(ConfigurationError::UnterminatedVariable)?;
+            let name = &after[..end];
+            let value =
+                var(name).ok_or_else(|| ConfigurationError::MissingEnvVar(name.to_string()))?;
+            interpolated.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            interpolated.push('$');
+            rest = after;
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use config::FileFormat;
+    #[cfg(feature = "bigquery")]
+    use pg_replicate::{clients::bigquery::BigQueryType, pipeline::sinks::NamingStrategy};
+    use pg_replicate::{
+        clients::postgres::{
+            ColumnHandling, ColumnOptions, ColumnType, ConnectionOptions, ConversionErrorPolicy,
+            LargeObjectOptions, LargeObjectStore, Timestamps, UnknownTypes,
+        },
+        clients::{
+            auth::TokenAuth,
+            proxy::Proxy,
+            tls::{ChannelBinding, TlsMode, TlsOptions},
+        },
+        conversions::size::{OversizedValuePolicy, ValueSizeLimit},
+        pipeline::{
+            classification::ClassificationOptions,
+            encryption::{EncryptedColumn, EncryptionOptions, KeySource},
+            sinks::naming::NamingRule,
+            sources::postgres::TableFilter,
+        },
+        table::TypeOverride,
+    };
+
+    use crate::configuration::{
+        interpolate, parse_settings, BatchSettings, ConfigurationError, Settings, SinkSettings,
+        SourceSettings,
+    };
+
+    fn env(name: &str) -> Option<String> {
+        let vars = HashMap::from([("PG_PASSWORD", "secret"), ("PG_HOST", "localhost")]);
+        vars.get(name).map(|value| value.to_string())
+    }
+
+    #[test]
+    pub fn environment_variables_are_interpolated() {
+        let actual = interpolate("host: ${PG_HOST}\npassword: \"${PG_PASSWORD}\"", env);
+        assert_eq!(
+            actual.unwrap(),
+            "host: localhost\npassword: \"secret\"".to_string()
+        );
+    }
+
+    #[test]
+    pub fn dollar_signs_can_be_escaped() {
+        let actual = interpolate("password: \"pa$$word $${PG_HOST} $5\"", env);
+        assert_eq!(
+            actual.unwrap(),
+            "password: \"pa$word ${PG_HOST} $5\"".to_string()
+        );
+    }
+
+    #[test]
+    pub fn unset_environment_variables_are_rejected() {
+        let actual = interpolate("password: ${MISSING}", env);
+        assert!(matches!(
+            actual,
+            Err(ConfigurationError::MissingEnvVar(name)) if name == "MISSING"
+        ));
+
+        let actual = interpolate("password: ${PG_PASSWORD", env);
+        assert!(matches!(
+            actual,
+            Err(ConfigurationError::UnterminatedVariable)
+        ));
+    }
+
+    #[cfg(feature = "stdout")]
+    fn expected_settings() -> Settings {
+        Settings {
+            source: SourceSettings::Postgres {
+                host: "localhost".to_string(),
+                port: 5432,
+                name: "postgres".to_string(),
+                username: "postgres".to_string(),
+                password: Some("secret".to_string()),
+                slot_name: Some("stdout_slot".to_string()),
+                publication: "my_publication".to_string(),
+                tables: Some(TableFilter {
+                    include: vec!["public.*".to_string()],
+                    exclude: vec![],
+                }),
+                columns: ColumnOptions::default(),
+                connection: ConnectionOptions::default(),
+            },
+            sink: SinkSettings::Stdout,
+            batch: BatchSettings::default(),
+            encryption: None,
+            classification: None,
+            removed_table_policy: RemovedTablePolicy::Keep,
+            naming_conventions: vec![],
+            dead_letter_file: None,
+            log_level: None,
+        }
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn yaml_settings_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    password: "secret"
+    slot_name: "stdout_slot"
+    publication: "my_publication"
+    tables:
+      include: ["public.*"]
+sink: "Stdout"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml);
+        assert_eq!(actual.unwrap(), expected_settings());
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn toml_settings_are_parsed() {
+        let settings = r#"
+sink = "Stdout"
+
+[source.Postgres]
+host = "localhost"
+port = 5432
+name = "postgres"
+username = "postgres"
+password = "secret"
+slot_name = "stdout_slot"
+publication = "my_publication"
+tables = { include = ["public.*"] }
+"#;
+        let actual = parse_settings(settings, FileFormat::Toml);
+        assert_eq!(actual.unwrap(), expected_settings());
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn naming_conventions_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink: "Stdout"
+naming_conventions:
+  - "SnakeCase"
+  - EnvironmentPrefix: "staging"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml);
+        assert_eq!(
+            actual.unwrap().naming_conventions,
+            vec![
+                NamingRule::SnakeCase,
+                NamingRule::EnvironmentPrefix("staging".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn column_options_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+    columns:
+      identity: "Skip"
+      unknown_types: "Error"
+      timestamps:
+        TimeZone: "Europe/Paris"
+      large_objects:
+        columns: ["public.documents.*"]
+        store:
+          Directory: "/mnt/objects"
+      types:
+        - column: "public.events.payload"
+          type: "Json"
+        - column: "public.*.created_at"
+          type: "EpochMillis"
+      commit_timestamp: "_committed_at"
+      conversion_errors: "DeadLetter"
+      max_value_size:
+        max_bytes: 1048576
+        policy: "Null"
+sink: "Stdout"
+"#;
+        let SourceSettings::Postgres { columns, .. } =
+            parse_settings(settings, FileFormat::Yaml).unwrap().source;
+        assert_eq!(
+            columns,
+            ColumnOptions {
+                generated: ColumnHandling::Skip,
+                identity: ColumnHandling::Skip,
+                unknown_types: UnknownTypes::Error,
+                timestamps: Timestamps::TimeZone("Europe/Paris".to_string()),
+                large_objects: LargeObjectOptions {
+                    columns: vec!["public.documents.*".to_string()],
+                    store: LargeObjectStore::Directory("/mnt/objects".to_string()),
+                },
+                types: vec![
+                    ColumnType {
+                        column: "public.events.payload".to_string(),
+                        typ: TypeOverride::Json,
+                    },
+                    ColumnType {
+                        column: "public.*.created_at".to_string(),
+                        typ: TypeOverride::EpochMillis,
+                    },
+                ],
+                commit_timestamp: Some("_committed_at".to_string()),
+                conversion_errors: ConversionErrorPolicy::DeadLetter,
+                max_value_size: Some(ValueSizeLimit {
+                    max_bytes: 1048576,
+                    policy: OversizedValuePolicy::Null,
+                    marker: "...[truncated]".to_string(),
+                }),
+            }
+        );
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn connection_options_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "db.internal"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+    connection:
+      application_name: "pg_replicate"
+      copy_statement_timeout_ms: 600000
+      parameters:
+        search_path: "app"
+      proxy:
+        Ssh:
+          host: "bastion.example.com"
+          user: "ubuntu"
+      tls:
+        mode: "VerifyFull"
+        root_cert: "/etc/ssl/certs/db-ca.pem"
+      channel_binding: "Require"
+      auth: "CloudSqlMetadata"
+      status_interval_secs: 5
+sink: "Stdout"
+"#;
+        let SourceSettings::Postgres { connection, .. } =
+            parse_settings(settings, FileFormat::Yaml).unwrap().source;
+        assert_eq!(
+            connection,
+            ConnectionOptions {
+                application_name: Some("pg_replicate".to_string()),
+                copy_statement_timeout_ms: Some(600000),
+                parameters: [("search_path".to_string(), "app".to_string())].into(),
+                proxy: Some(Proxy::Ssh {
+                    host: "bastion.example.com".to_string(),
+                    port: 22,
+                    user: "ubuntu".to_string(),
+                    identity_file: None,
+                }),
+                tls: TlsOptions {
+                    mode: TlsMode::VerifyFull,
+                    root_cert: Some("/etc/ssl/certs/db-ca.pem".to_string()),
+                },
+                channel_binding: ChannelBinding::Require,
+                auth: Some(TokenAuth::CloudSqlMetadata),
+                standby: None,
+                status_interval_secs: Some(5),
+            }
+        );
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn encryption_settings_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink: "Stdout"
+encryption:
+  key:
+    AwsKms:
+      region: "eu-west-1"
+      encrypted_key: "AQIDAHg="
+  columns:
+    - column: "public.users.email"
+      deterministic: true
+    - column: "public.users.phone*"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml).unwrap();
+        assert_eq!(
+            actual.encryption,
+            Some(EncryptionOptions {
+                key: KeySource::AwsKms {
+                    region: "eu-west-1".to_string(),
+                    encrypted_key: "AQIDAHg=".to_string(),
+                },
+                columns: vec![
+                    EncryptedColumn {
+                        column: "public.users.email".to_string(),
+                        deterministic: true,
+                    },
+                    EncryptedColumn {
+                        column: "public.users.phone*".to_string(),
+                        deterministic: false,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[cfg(feature = "stdout")]
+    #[test]
+    pub fn classification_settings_are_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink: "Stdout"
+classification:
+  sample_size: 500
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml).unwrap();
+        assert_eq!(
+            actual.classification,
+            Some(ClassificationOptions {
+                sample_size: 500,
+                min_match_percent: 80,
+                encrypt: false,
+            })
+        );
+    }
+
+    #[cfg(not(feature = "bigquery"))]
+    #[test]
+    pub fn sinks_not_enabled_in_the_build_are_rejected() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink:
+  BigQuery:
+    project_id: "project-id"
+    dataset_id: "dataset-id"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml);
+        assert!(matches!(
+            actual,
+            Err(ConfigurationError::SinkNotEnabled("bigquery", "bigquery"))
+        ));
+    }
+
+    #[cfg(feature = "null")]
+    #[test]
+    pub fn null_sink_is_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink: "Null"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml);
+        assert_eq!(actual.unwrap().sink, SinkSettings::Null);
+    }
+
+    #[cfg(feature = "bigquery")]
+    #[test]
+    pub fn bigquery_naming_is_parsed() {
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink:
+  BigQuery:
+    project_id: "project-id"
+    dataset_id: "dataset-id"
+    naming: "PerSchema"
+    type_mappings:
+      uuid: "Bytes"
+      numeric: "Numeric"
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml);
+        assert_eq!(
+            actual.unwrap().sink,
+            SinkSettings::BigQuery {
+                project_id: "project-id".to_string(),
+                dataset_id: "dataset-id".to_string(),
+                service_account_key_file: None,
+                naming: NamingStrategy::PerSchema,
+                type_mappings: HashMap::from([
+                    ("uuid".to_string(), BigQueryType::Bytes),
+                    ("numeric".to_string(), BigQueryType::Numeric),
+                ]),
+            }
+        );
+    }
+    #[cfg(feature = "delta")]
+    #[test]
+    pub fn delta_table_options_are_parsed() {
+        use pg_replicate::clients::delta::{
+            DeltaPartitionColumn, DeltaTableOptions, LateEventPolicy, LateEvents,
+        };
+
+        let settings = r#"
+source:
+  Postgres:
+    host: "localhost"
+    port: 5432
+    name: "postgres"
+    username: "postgres"
+    publication: "my_publication"
+sink:
+  Delta:
+    path: "s3://lake"
+    table_options:
+      public.events:
+        partition_columns:
+          - EventDate: { column: "occurred_at", name: "dt" }
+          - EventHour: { column: "occurred_at", name: "hour" }
+        late_events:
+          max_lateness_secs: 86400
+"#;
+        let actual = parse_settings(settings, FileFormat::Yaml).unwrap();
+        let SinkSettings::Delta { table_options, .. } = actual.sink else {
+            panic!("expected the delta sink");
+        };
+        assert_eq!(
+            table_options,
+            HashMap::from([(
+                "public.events".to_string(),
+                DeltaTableOptions {
+                    partition_columns: vec![
+                        DeltaPartitionColumn::EventDate {
+                            column: "occurred_at".to_string(),
+                            name: "dt".to_string(),
+                        },
+                        DeltaPartitionColumn::EventHour {
+                            column: "occurred_at".to_string(),
+                            name: "hour".to_string(),
+                        },
+                    ],
+                    z_order_columns: vec![],
+                    late_events: Some(LateEvents {
+                        max_lateness_secs: 86400,
+                        policy: LateEventPolicy::CommitTime,
+                    }),
+                },
+            )])
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use std::{fmt::Display, path::Path};
+
+#[cfg(feature = "bigquery")]
+use pg_replicate::clients::bigquery::BigQueryClient;
+#[cfg(feature = "duckdb")]
+use pg_replicate::clients::duckdb::DuckDbClient;
+use pg_replicate::{
+    clients::postgres::{ReplicaIdentity, ReplicationClient, ReplicationClientError},
+    table::TableName,
+};
+
+use crate::configuration::{Settings, SinkSettings, SourceSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    /// Replication works, but not fully or not without a change on start
+    Warning,
+    /// Replication fails
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Ok => f.write_str("ok"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// The result of a check, with what to do about it if it didn't pass
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.check, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn push(&mut self, severity: Severity, check: &'static str, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity,
+            check,
+            message: message.into(),
+        });
+    }
+
+    fn ok(&mut self, check: &'static str, message: impl Into<String>) {
+        self.push(Severity::Ok, check, message);
+    }
+
+    fn warning(&mut self, check: &'static str, message: impl Into<String>) {
+        self.push(Severity::Warning, check, message);
+    }
+
+    fn error(&mut self, check: &'static str, message: impl Into<String>) {
+        self.push(Severity::Error, check, message);
+    }
+}
+
+/// Checks the prerequisites of replicating from the source to the sink
+/// without changing either of them
+pub async fn diagnose(settings: &Settings) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics::default();
+    if let Err(e) = diagnose_source(&settings.source, &mut diagnostics).await {
+        diagnostics.error("source", format!("failed to check the source: {e}"));
+    }
+    diagnose_sink(&settings.sink, &mut diagnostics).await;
+    diagnostics.0
+}
+
+async fn diagnose_source(
+    source: &SourceSettings,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ReplicationClientError> {
+    let SourceSettings::Postgres {
+        host,
+        port,
+        name,
+        username,
+        slot_name,
+        publication,
+        tables,
+        ..
+    } = source;
+
+    let client = match source.connect().await {
+        Ok(client) => client,
+        Err(e) => {
+            diagnostics.error(
+                "connection",
+                format!(
+                    "can't connect to database {name} at {host}:{port} as {username}: {e}. Check the host, port and credentials, and that pg_hba.conf allows replication connections of the user"
+                ),
+            );
+            return Ok(());
+        }
+    };
+    diagnostics.ok("connection", format!("connected to {host}:{port}"));
+
+    match client.get_setting("wal_level").await?.as_deref() {
+        Some("logical") => diagnostics.ok("wal_level", "logical"),
+        wal_level => diagnostics.error(
+            "wal_level",
+            format!(
+                "is {}, it must be logical. Set wal_level = logical in postgresql.conf and restart postgres",
+                wal_level.unwrap_or("unknown")
+            ),
+        ),
+    }
+
+    if client.is_in_recovery().await? {
+        let version = client.get_server_version_num().await?;
+        if version < 160000 {
+            diagnostics.error(
+                "standby",
+                "the source is a standby, logical decoding on a standby needs Postgres 16 or later. Connect to the primary instead",
+            );
+        } else if client.get_setting("hot_standby_feedback").await?.as_deref() != Some("on") {
+            diagnostics.warning(
+                "standby",
+                "hot_standby_feedback is off, the primary may remove rows the slot still needs and invalidate it. Set hot_standby_feedback = on on the standby",
+            );
+        } else {
+            diagnostics.ok(
+                "standby",
+                "the source is a standby which can decode changes",
+            );
+        }
+    }
+
+    let role = client.get_role_attributes().await?;
+    if role.superuser || role.replication {
+        diagnostics.ok("replication role", format!("{username} can replicate"));
+    } else {
+        diagnostics.error(
+            "replication role",
+            format!("{username} can't replicate. Run `alter role {username} with replication`"),
+        );
+    }
+
+    let max_slots: u64 = client
+        .get_setting("max_replication_slots")
+        .await?
+        .and_then(|max_slots| max_slots.parse().ok())
+        .unwrap_or(0);
+    let used_slots = client.count_replication_slots().await?;
+    match slot_name {
+        Some(slot_name) if client.slot_exists(slot_name).await? => diagnostics.ok(
+            "replication slots",
+            format!("slot {slot_name} exists, replication resumes from it"),
+        ),
+        Some(slot_name) if used_slots >= max_slots => diagnostics.error(
+            "replication slots",
+            format!(
+                "all {max_slots} slots are used, so slot {slot_name} can't be created. Drop unused slots or increase max_replication_slots in postgresql.conf and restart postgres"
+            ),
+        ),
+        Some(slot_name) => diagnostics.ok(
+            "replication slots",
+            format!(
+                "slot {slot_name} will be created, {} of {max_slots} slots are free",
+                max_slots - used_slots
+            ),
+        ),
+        None => diagnostics.ok(
+            "replication slots",
+            "no slot_name is set, changes can't be streamed",
+        ),
+    }
+
+    if !client.publication_exists(publication).await? {
+        diagnostics.error(
+            "publication",
+            format!(
+                "{publication} doesn't exist. Run `create publication {publication} for table ...`"
+            ),
+        );
+        return Ok(());
+    }
+    let table_names: Vec<TableName> = client
+        .get_publication_table_names(publication)
+        .await?
+        .into_iter()
+        .filter(|table_name| {
+            tables
+                .as_ref()
+                .map_or(true, |table_filter| table_filter.matches(table_name))
+        })
+        .collect();
+    if table_names.is_empty() {
+        diagnostics.error(
+            "publication",
+            format!(
+                "no tables of {publication} are selected. Add tables with `alter publication {publication} add table ...` or change the source's tables"
+            ),
+        );
+        return Ok(());
+    }
+    diagnostics.ok(
+        "publication",
+        format!("{} tables of {publication} are selected", table_names.len()),
+    );
+
+    for table_name in &table_names {
+        diagnose_replica_identity(&client, table_name, diagnostics).await?;
+    }
+
+    Ok(())
+}
+
+async fn diagnose_replica_identity(
+    client: &ReplicationClient,
+    table_name: &TableName,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ReplicationClientError> {
+    let quoted_table_name = table_name.as_quoted_identifier();
+    match client.get_replica_identity(table_name).await? {
+        Some(ReplicaIdentity::Default {
+            has_primary_key: true,
+        })
+        | Some(ReplicaIdentity::Full) => {}
+        Some(ReplicaIdentity::Default {
+            has_primary_key: false,
+        }) => diagnostics.warning(
+            "replica identity",
+            format!(
+                "{table_name} has no primary key, so postgres rejects its updates and deletes while it's published. Add a primary key or run `alter table {quoted_table_name} replica identity full`"
+            ),
+        ),
+        Some(ReplicaIdentity::Index) | Some(ReplicaIdentity::Nothing) => diagnostics.error(
+            "replica identity",
+            format!(
+                "the replica identity of {table_name} isn't supported. Run `alter table {quoted_table_name} replica identity full`, or `replica identity default` if it has a primary key"
+            ),
+        ),
+        None => diagnostics.error(
+            "replica identity",
+            format!("{table_name} of the publication doesn't exist"),
+        ),
+    }
+    Ok(())
+}
+
+async fn diagnose_sink(sink: &SinkSettings, diagnostics: &mut Diagnostics) {
+    match sink {
+        SinkSettings::Stdout => diagnostics.ok("sink", "stdout needs no checks"),
+        SinkSettings::Null => diagnostics.ok("sink", "null needs no checks"),
+        #[cfg(feature = "bigquery")]
+        SinkSettings::BigQuery {
+            project_id,
+            dataset_id,
+            service_account_key_file,
+            ..
+        } => diagnose_bigquery(project_id, dataset_id, service_account_key_file, diagnostics).await,
+        #[cfg(feature = "duckdb")]
+        SinkSettings::DuckDb { file } => diagnose_duckdb_file(Path::new(file), diagnostics),
+        #[cfg(feature = "duckdb")]
+        SinkSettings::MotherDuck {
+            access_token,
+            db_name,
+        } => match DuckDbClient::open_mother_duck(access_token, db_name) {
+            Ok(_) => diagnostics.ok("sink", format!("connected to motherduck database {db_name}")),
+            Err(e) => diagnostics.error(
+                "sink",
+                format!("can't connect to motherduck database {db_name}: {e}. Check the access token and database name"),
+            ),
+        },
+        SinkSettings::Delta { path, .. } => diagnose_delta_path(path, diagnostics),
+        // sinks not enabled in this build are rejected when the settings are
+        // loaded
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(feature = "bigquery")]
+async fn diagnose_bigquery(
+    project_id: &str,
+    dataset_id: &str,
+    service_account_key_file: &Option<String>,
+    diagnostics: &mut Diagnostics,
+) {
+    let client = match service_account_key_file {
+        Some(service_account_key_file) => {
+            BigQueryClient::new_with_key_path(project_id.to_string(), service_account_key_file)
+                .await
+        }
+        None => BigQueryClient::new_with_adc(project_id.to_string()).await,
+    };
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            diagnostics.error(
+                "sink",
+                format!("can't authenticate with bigquery: {e}. Check the service account key, or the application default credentials without one"),
+            );
+            return;
+        }
+    };
+
+    match client.dataset_exists(dataset_id).await {
+        Ok(true) => diagnostics.ok(
+            "sink",
+            format!("dataset {project_id}.{dataset_id} is readable"),
+        ),
+        Ok(false) => diagnostics.warning(
+            "sink",
+            format!(
+                "dataset {project_id}.{dataset_id} doesn't exist and will be created, which needs the bigquery.datasets.create permission"
+            ),
+        ),
+        Err(e) => diagnostics.error(
+            "sink",
+            format!(
+                "can't read dataset {project_id}.{dataset_id}: {e}. Grant the bigquery.dataEditor and bigquery.jobUser roles on the project"
+            ),
+        ),
+    }
+}
+
+#[cfg(feature = "duckdb")]
+fn diagnose_duckdb_file(file: &Path, diagnostics: &mut Diagnostics) {
+    // a missing file isn't opened, as that would create it
+    if !file.exists() {
+        diagnose_writable_dir(parent_dir(file), "duckdb file", diagnostics);
+        return;
+    }
+    match DuckDbClient::open_file(file) {
+        Ok(_) => diagnostics.ok("sink", format!("opened {}", file.display())),
+        Err(e) => diagnostics.error(
+            "sink",
+            format!(
+                "can't open {}: {e}. Check that it's a duckdb file which no other process has open",
+                file.display()
+            ),
+        ),
+    }
+}
+
+fn diagnose_delta_path(path: &str, diagnostics: &mut Diagnostics) {
+    if path.contains("://") && !path.starts_with("file://") {
+        diagnostics.warning(
+            "sink",
+            format!("access to the object store of {path} isn't checked"),
+        );
+        return;
+    }
+    let path = Path::new(path.trim_start_matches("file://"));
+    let dir = if path.exists() {
+        path
+    } else {
+        parent_dir(path)
+    };
+    diagnose_writable_dir(dir, "delta lake", diagnostics);
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Checks that files can be created in a directory by creating and removing
+/// one
+fn diagnose_writable_dir(dir: &Path, what: &str, diagnostics: &mut Diagnostics) {
+    let probe = dir.join(".pg_replicate_doctor");
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            diagnostics.ok("sink", format!("the {what} can be written to {}", dir.display()));
+        }
+        Err(e) => diagnostics.error(
+            "sink",
+            format!(
+                "the {what} can't be written to {}: {e}. Create the directory or fix its permissions",
+                dir.display()
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doctor::{diagnose_delta_path, Diagnostics, Severity};
+
+    #[test]
+    pub fn delta_lakes_in_writable_dirs_pass() {
+        let mut diagnostics = Diagnostics::default();
+        let path = std::env::temp_dir().join("pg_replicate_doctor_lake");
+        diagnose_delta_path(&path.display().to_string(), &mut diagnostics);
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(diagnostics.0[0].severity, Severity::Ok);
+    }
+
+    #[test]
+    pub fn delta_lakes_in_missing_dirs_fail() {
+        let mut diagnostics = Diagnostics::default();
+        let path = std::env::temp_dir().join("pg_replicate_doctor_missing/lake");
+        diagnose_delta_path(&path.display().to_string(), &mut diagnostics);
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(diagnostics.0[0].severity, Severity::Error);
+    }
+
+    #[test]
+    pub fn object_stores_are_not_checked() {
+        let mut diagnostics = Diagnostics::default();
+        diagnose_delta_path("s3://bucket/lake", &mut diagnostics);
+        assert_eq!(diagnostics.0[0].severity, Severity::Warning);
+    }
+}
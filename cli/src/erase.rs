@@ -0,0 +1,118 @@
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use pg_replicate::{
+    pipeline::{
+        erasure::{wait_for_lsn, ErasedRows, ErasureReport, SourceEraser},
+        sinks::{BatchSink, ErasureSink},
+        sources::Source,
+    },
+    table::{TableName, TableSchema},
+};
+
+use crate::{configuration::SourceSettings, postgres_source, publication::parse_table_name};
+
+#[derive(Debug, clap::Args)]
+pub struct EraseArgs {
+    /// Table to delete the rows from, as `schema.name` or `name` in the
+    /// public schema. It must have a primary key
+    #[arg(long, value_parser = parse_table_name)]
+    table: TableName,
+
+    /// Sql condition selecting the rows to delete, e.g. `customer_id = 42`
+    #[arg(long = "where")]
+    predicate: String,
+
+    /// Seconds to wait for the deletes to reach the sink
+    #[arg(long, default_value_t = 300)]
+    timeout_secs: u64,
+
+    /// File to write the report to as json, besides printing it. It lists
+    /// the erased keys, as a tombstone manifest for sinks keeping older
+    /// versions of their files
+    #[arg(long)]
+    report: Option<PathBuf>,
+}
+
+/// Deletes the rows from the source and waits for the deletes to reach the
+/// sink, whose tables can't be searched for the erased rows
+pub async fn erase<Snk: BatchSink>(
+    source: SourceSettings,
+    sink: &mut Snk,
+    args: EraseArgs,
+) -> Result<(), Box<dyn Error>> {
+    let (_, erased) = erase_rows(source, &args).await?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+    let (sink_lsn, reached_sink) = wait_for_lsn(sink, erased.delete_lsn, timeout).await?;
+    let report = ErasureReport {
+        erased,
+        sink_lsn,
+        reached_sink,
+        remaining_keys: None,
+    };
+    write_report(&report, args.report)
+}
+
+/// Deletes the rows from the source, waits for the deletes to reach the sink
+/// and then searches the sink for the erased rows
+pub async fn erase_and_check<Snk: ErasureSink>(
+    source: SourceSettings,
+    sink: &mut Snk,
+    args: EraseArgs,
+) -> Result<(), Box<dyn Error>> {
+    let (table_schema, erased) = erase_rows(source, &args).await?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+    let (sink_lsn, reached_sink) = wait_for_lsn(sink, erased.delete_lsn, timeout).await?;
+    let remaining_keys = sink
+        .find_keys(&table_schema, &erased.keys)
+        .await?
+        .unwrap_or_default();
+    let report = ErasureReport {
+        erased,
+        sink_lsn,
+        reached_sink,
+        remaining_keys: Some(remaining_keys),
+    };
+    write_report(&report, args.report)
+}
+
+async fn erase_rows(
+    source: SourceSettings,
+    args: &EraseArgs,
+) -> Result<(TableSchema, ErasedRows), Box<dyn Error>> {
+    let SourceSettings::Postgres {
+        host,
+        port,
+        name,
+        username,
+        password,
+        connection,
+        ..
+    } = &source;
+    let mut eraser =
+        SourceEraser::connect(host, *port, name, username, password.clone(), connection).await?;
+
+    // the schema is read with the source's column options, like the
+    // replicated schema
+    let postgres_source = postgres_source(source, false, vec![args.table.to_string()]).await?;
+    let table_schema = postgres_source
+        .get_table_schemas()
+        .values()
+        .next()
+        .cloned()
+        .ok_or_else(|| format!("table {} doesn't exist", args.table))?;
+
+    let erased = eraser.erase(&table_schema, &args.predicate).await?;
+    Ok((table_schema, erased))
+}
+
+fn write_report(report: &ErasureReport, file: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    println!("{report}");
+    if let Some(file) = file {
+        std::fs::write(&file, serde_json::to_string_pretty(report)?)?;
+        println!("wrote the report to {}", file.display());
+    }
+    if !report.verified() {
+        return Err("the erasure couldn't be verified".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+
+use pg_replicate::pipeline::{
+    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+    encryption::ColumnEncryptor,
+    sinks::BatchSink,
+    sources::{kafka::KafkaSource, Source},
+    PipelineAction,
+};
+
+use crate::{configuration::SourceSettings, postgres_source};
+
+#[derive(Debug, clap::Args)]
+pub struct KafkaArgs {
+    /// Comma separated `host:port` addresses of the Kafka brokers
+    #[arg(long)]
+    brokers: String,
+
+    /// Topic holding records of the stdout sink's json lines format
+    #[arg(long)]
+    topic: String,
+
+    /// Partition of the topic to consume, whose records must be in the
+    /// order they were written
+    #[arg(long, default_value_t = 0)]
+    partition: i32,
+
+    /// Consumer group whose committed offset the changes are resumed from
+    #[arg(long)]
+    group_id: String,
+
+    /// Property of the consumer as `key=value`, e.g.
+    /// `security.protocol=ssl`. May be repeated
+    #[arg(long = "property", value_parser = parse_property)]
+    properties: Vec<(String, String)>,
+}
+
+/// Streams the changes recorded in the topic to the sink, converting their
+/// rows with the schemas of the source's tables
+pub async fn run<Snk: BatchSink>(
+    source: SourceSettings,
+    sink: Snk,
+    args: KafkaArgs,
+    batch_config: BatchConfig,
+    encryptor: Option<ColumnEncryptor>,
+) -> Result<(), Box<dyn Error>> {
+    // only the schemas are read from the source, its slot isn't used
+    let table_schemas = postgres_source(source, false, vec![])
+        .await?
+        .get_table_schemas()
+        .clone();
+    let mut source = KafkaSource::new(
+        &args.brokers,
+        &args.group_id,
+        args.topic,
+        args.partition,
+        table_schemas,
+    );
+    for (key, value) in &args.properties {
+        source = source.with_property(key, value);
+    }
+
+    let mut pipeline = BatchDataPipeline::new(source, sink, PipelineAction::CdcOnly, batch_config);
+    if let Some(encryptor) = encryptor {
+        pipeline = pipeline.with_column_encryptor(encryptor);
+    }
+    pipeline.start().await?;
+    Ok(())
+}
+
+fn parse_property(property: &str) -> Result<(String, String), String> {
+    match property.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("expected key=value, got {property}")),
+    }
+}
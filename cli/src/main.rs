@@ -0,0 +1,807 @@
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use bench::BenchArgs;
+use clap::{Parser, Subcommand};
+use configuration::{load_settings, BatchSettings, Settings, SinkSettings, SourceSettings};
+use doctor::Severity;
+use erase::EraseArgs;
+#[cfg(feature = "kafka")]
+use kafka::KafkaArgs;
+#[cfg(feature = "bigquery")]
+use pg_replicate::pipeline::sinks::bigquery::{BigQueryBatchSink, NamingOptions};
+#[cfg(feature = "delta")]
+use pg_replicate::pipeline::sinks::delta::DeltaSink;
+#[cfg(feature = "duckdb")]
+use pg_replicate::pipeline::sinks::duckdb::DuckDbSink;
+#[cfg(feature = "null")]
+use pg_replicate::pipeline::sinks::null::NullSink;
+#[cfg(feature = "stdout")]
+use pg_replicate::pipeline::sinks::stdout::{self, StdoutSink};
+use pg_replicate::pipeline::{
+    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+    classification::{classify, ClassificationOptions, ColumnClassification},
+    dead_letters::DeadLetterWriter,
+    encryption::{ColumnEncryptor, EncryptionOptions},
+    sinks::{DdlSink, PreviewSink, RemovedTablePolicy},
+    sources::{
+        postgres::{PostgresSource, TableFilter, TableNamesFrom},
+        Source,
+    },
+    PipelineAction,
+};
+#[cfg(feature = "bigquery")]
+use pg_replicate::pipeline::{sinks::ChecksumSink, verification::ChecksumVerifier};
+#[cfg(any(feature = "bigquery", feature = "duckdb"))]
+use pg_replicate::pipeline::{
+    sinks::{ErasureSink, RowCountSink, StateSink},
+    verification::RowCountVerifier,
+};
+use preview::PreviewArgs;
+use publication::PublicationCommand;
+use replay::ReplayArgs;
+use slot::SlotCommand;
+#[cfg(feature = "delta")]
+use snapshot::SnapshotArgs;
+use state::StateCommand;
+use status::OutputFormat;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+use watch::{LogFilterHandle, Watcher};
+
+mod bench;
+mod classify;
+mod configuration;
+mod doctor;
+mod erase;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod preview;
+mod publication;
+mod replay;
+mod slot;
+#[cfg(feature = "delta")]
+mod snapshot;
+mod state;
+mod status;
+mod watch;
+
+#[derive(Debug, Parser)]
+#[command(name = "pg_replicate", version, about, arg_required_else_help = true)]
+struct AppArgs {
+    /// Path to the yaml or toml configuration file. `${NAME}` in the file is
+    /// replaced with the value of the environment variable `NAME`
+    #[arg(long, short)]
+    config: PathBuf,
+
+    /// Validate the configuration and print it, with its secrets redacted,
+    /// without replicating
+    #[arg(long)]
+    dry_run: bool,
+
+    /// How the stdout sink prints rows and changes. The structured formats
+    /// wrap each row in an envelope with its table, operation and lsn
+    #[arg(long, value_enum, default_value_t = StdoutFormat::Log)]
+    stdout_format: StdoutFormat,
+
+    /// File descriptor the stdout sink writes the structured formats to
+    /// instead of stdout, e.g. `3` for a pipe opened with `3>` by the shell
+    #[cfg(unix)]
+    #[arg(long)]
+    stdout_fd: Option<i32>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum StdoutFormat {
+    Log,
+    JsonLines,
+    PrettyJson,
+    Csv,
+}
+
+#[cfg(feature = "stdout")]
+impl From<StdoutFormat> for stdout::OutputFormat {
+    fn from(format: StdoutFormat) -> Self {
+        match format {
+            StdoutFormat::Log => stdout::OutputFormat::Log,
+            StdoutFormat::JsonLines => stdout::OutputFormat::JsonLines,
+            StdoutFormat::PrettyJson => stdout::OutputFormat::PrettyJson,
+            StdoutFormat::Csv => stdout::OutputFormat::Csv,
+        }
+    }
+}
+
+#[cfg(feature = "stdout")]
+fn stdout_sink(args: &AppArgs) -> StdoutSink {
+    let sink = StdoutSink::new().with_format(args.stdout_format.into());
+    #[cfg(unix)]
+    if let Some(fd) = args.stdout_fd {
+        use std::os::fd::FromRawFd;
+        // SAFETY: the descriptor was passed to the process to be written
+        // to, nothing else in the process owns it
+        let output = unsafe { std::fs::File::from_raw_fd(fd) };
+        return sink.with_output(output);
+    }
+    sink
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Copy the tables and then stream their changes
+    Run {
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+
+    /// Copy the tables without streaming their changes
+    CopyTables {
+        /// Table to copy instead of the publication's, as `schema.name` or
+        /// `name` in the public schema. `*` matches any characters, e.g.
+        /// `public.*` copies the whole schema. May be repeated
+        #[arg(long = "table", value_parser = parse_table_pattern)]
+        tables: Vec<String>,
+    },
+
+    /// Copy the tables to Parquet files, a consistent snapshot of them,
+    /// without using the sink or a slot
+    #[cfg(feature = "delta")]
+    Snapshot {
+        #[command(flatten)]
+        args: SnapshotArgs,
+    },
+
+    /// Stream the changes of the tables without copying them first
+    Cdc {
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+
+    /// Compare row counts of the tables with the sink
+    Verify {
+        /// Compare checksums of ranges of the tables' keys instead, which
+        /// also finds rows whose values differ and which ranges to copy again
+        #[arg(long)]
+        checksums: bool,
+
+        /// Number of key values in a range compared with checksums
+        #[arg(long, default_value_t = 100000, value_parser = clap::value_parser!(i64).range(1..))]
+        range_size: i64,
+    },
+
+    /// Check the prerequisites of replicating from the source to the sink,
+    /// without changing either of them
+    Doctor,
+
+    /// Print the statements the sink runs to create the tables, without
+    /// writing anything
+    Ddl,
+
+    /// Print what the sink would write for a few rows of each table and a
+    /// few changes, transformed like the pipeline transforms them, without
+    /// writing anything
+    Preview {
+        #[command(flatten)]
+        args: PreviewArgs,
+    },
+
+    /// Print the columns of the source's tables which likely hold personal
+    /// data, classified by their names and sampled values
+    Classify {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+
+    /// Print the copy progress of the tables, the position of the slot and
+    /// the replication's lag
+    Status {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+
+    /// Manage the publications of the source
+    Publication {
+        #[clap(subcommand)]
+        command: PublicationCommand,
+    },
+
+    /// Manage the replication slots of the source
+    Slot {
+        #[clap(subcommand)]
+        command: SlotCommand,
+    },
+
+    /// Move the state of the pipeline from one sink to another
+    State {
+        #[clap(subcommand)]
+        command: StateCommand,
+    },
+
+    /// Delete rows of a table from the source, e.g. to erase someone's
+    /// personal data, wait for the deletes to reach the sink and report
+    /// whether erased rows are left in it
+    Erase {
+        #[command(flatten)]
+        args: EraseArgs,
+    },
+
+    /// Replicate generated tables and changes to the sink, without a
+    /// source, and print how fast they were written. Use a sink which
+    /// doesn't hold data of other pipelines
+    Bench {
+        #[command(flatten)]
+        args: BenchArgs,
+    },
+
+    /// Replay the rows and changes of json lines dumps of the stdout sink to
+    /// the sink, to rebuild its tables or backfill it without using the
+    /// source's slot. Only the schemas of the tables are read from the source
+    Replay {
+        #[command(flatten)]
+        args: ReplayArgs,
+    },
+
+    /// Stream the changes recorded in a Kafka topic in the stdout sink's
+    /// json lines format to the sink, to fan the source's changes out to
+    /// many sinks. The sink's tables must have been copied, only the
+    /// schemas of the tables are read from the source
+    #[cfg(feature = "kafka")]
+    Kafka {
+        #[command(flatten)]
+        args: KafkaArgs,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct WatchArgs {
+    /// Keep running and reload the configuration file when it changes or on
+    /// SIGHUP. Changes of the batch settings, the log level and the source's
+    /// tables are applied without restarting the replication, added tables
+    /// are copied while the changes of the others are streamed
+    #[arg(long)]
+    watch: bool,
+}
+
+impl Command {
+    fn streams_changes(&self) -> bool {
+        matches!(self, Command::Run { .. } | Command::Cdc { .. })
+    }
+
+    fn watches(&self) -> bool {
+        matches!(
+            self,
+            Command::Run {
+                watch: WatchArgs { watch: true }
+            } | Command::Cdc {
+                watch: WatchArgs { watch: true }
+            }
+        )
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    if let Err(e) = main_impl().await {
+        error!("{e}");
+    }
+
+    Ok(())
+}
+
+/// Returns a handle to change the filter of the logs while running
+fn init_tracing() -> LogFilterHandle {
+    let (log_filter, log_filter_handle) = reload::Layer::new(log_filter(None));
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    log_filter_handle
+}
+
+/// Filters the logs by the configured log level, or by `RUST_LOG` if there
+/// is none
+fn log_filter(log_level: Option<&str>) -> EnvFilter {
+    match log_level {
+        Some(log_level) => EnvFilter::new(log_level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| "pg_replicate=info".into()),
+    }
+}
+
+fn set_log_level() {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+}
+
+/// Checks that the settings have what the command needs
+fn validate(settings: &Settings, command: &Command) -> Result<(), Box<dyn Error>> {
+    let SourceSettings::Postgres { slot_name, .. } = &settings.source;
+    if command.streams_changes() && slot_name.is_none() {
+        return Err("a slot_name of the source is needed to stream changes".into());
+    }
+    if matches!(command, Command::Preview { args } if args.reads_changes()) && slot_name.is_none() {
+        return Err("a slot_name of the source is needed to preview changes".into());
+    }
+    if matches!(command, Command::Verify { .. }) && !settings.sink.supports_verification() {
+        return Err(format!(
+            "the {} sink can't be verified, as it can't count its rows",
+            settings.sink.name()
+        )
+        .into());
+    }
+    if matches!(
+        command,
+        Command::Verify {
+            checksums: true,
+            ..
+        }
+    ) && !settings.sink.supports_checksums()
+    {
+        return Err(format!(
+            "the {} sink can't be verified with checksums, as it can't hash its rows",
+            settings.sink.name()
+        )
+        .into());
+    }
+    if matches!(
+        command,
+        Command::State {
+            command: StateCommand::Import { .. }
+        }
+    ) && !settings.sink.keeps_state()
+    {
+        return Err(format!(
+            "a state can't be imported to the {} sink, as it doesn't keep one",
+            settings.sink.name()
+        )
+        .into());
+    }
+    if matches!(
+        &settings.classification,
+        Some(ClassificationOptions { encrypt: true, .. })
+    ) && settings.encryption.is_none()
+    {
+        return Err(
+            "classified columns can only be encrypted with the encryption settings' key".into(),
+        );
+    }
+    if settings.removed_table_policy != RemovedTablePolicy::Keep && !settings.sink.keeps_state() {
+        return Err(format!(
+            "tables of the {} sink can't be pruned, as it doesn't keep the tables it replicated",
+            settings.sink.name()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn main_impl() -> Result<(), Box<dyn Error>> {
+    set_log_level();
+    let log_filter_handle = init_tracing();
+
+    let args = AppArgs::parse();
+    let settings = load_settings(&args.config)?;
+    validate(&settings, &args.command)?;
+
+    if args.dry_run {
+        println!("{settings:#?}");
+        println!("configuration is valid");
+        return Ok(());
+    }
+
+    if settings.log_level.is_some() {
+        log_filter_handle.reload(log_filter(settings.log_level.as_deref()))?;
+    }
+    info!("settings: {settings:#?}");
+
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .expect("failed to install default crypto provider");
+
+    #[cfg(feature = "stdout")]
+    let stdout_sink = stdout_sink(&args);
+    let command = match args.command {
+        Command::Doctor => {
+            let diagnostics = doctor::diagnose(&settings).await;
+            for diagnostic in &diagnostics {
+                println!("{diagnostic}");
+            }
+            if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                return Err("some checks failed, fix them before starting a pipeline".into());
+            }
+            println!("all checks passed");
+            return Ok(());
+        }
+        Command::Publication { command } => {
+            return publication::run(&settings.source, command).await
+        }
+        Command::Slot { command } => return slot::run(&settings.source, command).await,
+        #[cfg(feature = "delta")]
+        Command::Snapshot { args } => {
+            return snapshot::run(
+                settings.source,
+                settings.batch,
+                args,
+                settings.classification,
+                settings.encryption,
+                settings.dead_letter_file,
+                settings.naming_conventions,
+            )
+            .await
+        }
+        Command::Classify { output } => {
+            let classification = settings.classification.unwrap_or_default();
+            return classify::print_classifications(settings.source, &classification, output).await;
+        }
+        command => command,
+    };
+
+    let watcher = command.watches().then(|| Watcher {
+        path: args.config,
+        settings: settings.clone(),
+        log_filter: log_filter_handle,
+    });
+    let task = Task {
+        command,
+        source: settings.source,
+        batch: settings.batch,
+        encryption: settings.encryption,
+        classification: settings.classification,
+        removed_table_policy: settings.removed_table_policy,
+        dead_letter_file: settings.dead_letter_file,
+        watcher,
+    };
+    match settings.sink {
+        #[cfg(feature = "stdout")]
+        SinkSettings::Stdout => task.run(stdout_sink).await,
+        #[cfg(feature = "null")]
+        SinkSettings::Null => task.run(NullSink::default()).await,
+        #[cfg(feature = "bigquery")]
+        SinkSettings::BigQuery {
+            project_id,
+            dataset_id,
+            service_account_key_file,
+            naming,
+            type_mappings,
+        } => {
+            let bigquery_sink = match service_account_key_file {
+                Some(service_account_key_file) => {
+                    BigQueryBatchSink::new_with_key_path(
+                        project_id,
+                        dataset_id,
+                        &service_account_key_file,
+                    )
+                    .await?
+                }
+                None => BigQueryBatchSink::new_with_adc(project_id, dataset_id).await?,
+            };
+            let bigquery_sink = bigquery_sink
+                .with_naming_options(NamingOptions {
+                    strategy: naming,
+                    ..NamingOptions::default()
+                })
+                .with_naming_convention(settings.naming_conventions)
+                .with_type_mappings(type_mappings);
+            task.run_or_checksum(bigquery_sink).await
+        }
+        #[cfg(feature = "duckdb")]
+        SinkSettings::DuckDb { file } => {
+            // the statements and previews don't depend on the database,
+            // opening its file would create it
+            let duckdb_sink = match task.command {
+                Command::Ddl | Command::Preview { .. } => DuckDbSink::in_memory().await?,
+                _ => DuckDbSink::file(file).await?,
+            }
+            .with_naming_convention(settings.naming_conventions);
+            task.run_stateful(duckdb_sink).await
+        }
+        #[cfg(feature = "duckdb")]
+        SinkSettings::MotherDuck {
+            access_token,
+            db_name,
+        } => {
+            let duckdb_sink = DuckDbSink::mother_duck(&access_token, &db_name)
+                .await?
+                .with_naming_convention(settings.naming_conventions);
+            task.run_stateful(duckdb_sink).await
+        }
+        #[cfg(feature = "delta")]
+        SinkSettings::Delta {
+            path,
+            storage_options,
+            change_data_feed,
+            compression,
+            naming,
+            table_names,
+            table_options,
+            type_mappings,
+        } => {
+            let delta_sink = DeltaSink::new(path)
+                .with_storage_options(storage_options)
+                .with_change_data_feed(change_data_feed)
+                .with_compression(compression)
+                .with_naming(naming)
+                .with_naming_convention(settings.naming_conventions)
+                .with_table_names(table_names)
+                .with_table_options(table_options)
+                .with_type_mappings(type_mappings);
+            task.run(delta_sink).await
+        }
+        // sinks not enabled in this build are rejected when the settings are
+        // loaded
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
+/// A command which needs the sink of the settings
+struct Task {
+    command: Command,
+    source: SourceSettings,
+    batch: BatchSettings,
+    encryption: Option<EncryptionOptions>,
+    classification: Option<ClassificationOptions>,
+    removed_table_policy: RemovedTablePolicy,
+    dead_letter_file: Option<PathBuf>,
+    watcher: Option<Watcher>,
+}
+
+impl Task {
+    async fn run<Snk: DdlSink + PreviewSink>(self, mut sink: Snk) -> Result<(), Box<dyn Error>> {
+        let streams_changes = self.command.streams_changes();
+        let (action, tables) = match self.command {
+            Command::Run { .. } => (PipelineAction::Both, vec![]),
+            Command::CopyTables { tables } => (PipelineAction::TableCopiesOnly, tables),
+            Command::Cdc { .. } => (PipelineAction::CdcOnly, vec![]),
+            Command::Status { output } => {
+                return status::print_status(&self.source, &mut sink, output).await;
+            }
+            Command::Bench { args } => {
+                return bench::run(args, sink, batch_config(self.batch)).await;
+            }
+            Command::Replay { args } => {
+                let encryptor = column_encryptor(self.encryption).await?;
+                let batch_config = batch_config(self.batch);
+                return replay::run(self.source, sink, args, batch_config, encryptor).await;
+            }
+            #[cfg(feature = "kafka")]
+            Command::Kafka { args } => {
+                let encryptor = column_encryptor(self.encryption).await?;
+                let batch_config = batch_config(self.batch);
+                return kafka::run(self.source, sink, args, batch_config, encryptor).await;
+            }
+            Command::State {
+                command: StateCommand::Export { file },
+            } => {
+                return state::export(&mut sink, file).await;
+            }
+            Command::Erase { args } => {
+                return erase::erase(self.source, &mut sink, args).await;
+            }
+            Command::Preview { args } => {
+                return preview::run(
+                    self.source,
+                    &mut sink,
+                    args,
+                    self.classification,
+                    self.encryption,
+                )
+                .await;
+            }
+            Command::Ddl => {
+                let postgres_source = postgres_source(self.source, false, vec![]).await?;
+                let mut table_schemas = postgres_source.get_table_schemas().clone();
+                let encryption =
+                    classify_columns(&postgres_source, self.classification, self.encryption)
+                        .await?;
+                if let Some(mut encryptor) = column_encryptor(encryption).await? {
+                    table_schemas = encryptor.encrypt_schemas(table_schemas);
+                }
+                let statements = sink.table_ddl(&table_schemas).await?;
+                if statements.is_empty() {
+                    println!("the sink creates no tables");
+                }
+                for statement in statements {
+                    println!("{statement}\n");
+                }
+                return Ok(());
+            }
+            // verifying sinks without row counts and importing to sinks
+            // without a state is rejected when the settings are validated,
+            // and the other commands don't need the sink
+            Command::Verify { .. }
+            | Command::State {
+                command: StateCommand::Import { .. },
+            }
+            | Command::Doctor
+            | Command::Classify { .. }
+            | Command::Publication { .. }
+            | Command::Slot { .. } => {
+                unreachable!()
+            }
+            #[cfg(feature = "delta")]
+            Command::Snapshot { .. } => unreachable!(),
+        };
+
+        // the tables matching the patterns are only some of the replicated
+        // ones, the others aren't removed
+        let prunes_tables = tables.is_empty();
+        let postgres_source = postgres_source(self.source, streams_changes, tables).await?;
+        let encryption =
+            classify_columns(&postgres_source, self.classification, self.encryption).await?;
+        let mut pipeline =
+            BatchDataPipeline::new(postgres_source, sink, action, batch_config(self.batch));
+        if prunes_tables {
+            pipeline = pipeline.with_removed_table_policy(self.removed_table_policy);
+        }
+        if let Some(dead_letter_file) = &self.dead_letter_file {
+            pipeline =
+                pipeline.with_dead_letter_writer(DeadLetterWriter::open(dead_letter_file).await?);
+        }
+        if let Some(encryptor) = column_encryptor(encryption).await? {
+            pipeline = pipeline.with_column_encryptor(encryptor);
+        }
+        let mut pipeline = match self.watcher {
+            Some(watcher) => {
+                let (updates, updates_receiver) = mpsc::channel(16);
+                tokio::spawn(watcher.watch(updates));
+                pipeline.with_updates(updates_receiver)
+            }
+            None => pipeline,
+        };
+        pipeline.start().await?;
+        Ok(())
+    }
+
+    /// Runs the command with a sink which can count its rows and keeps a
+    /// state, which can also verify the sink or import a state to it
+    #[cfg(any(feature = "bigquery", feature = "duckdb"))]
+    async fn run_stateful<Snk: RowCountSink + StateSink + ErasureSink + DdlSink + PreviewSink>(
+        self,
+        mut sink: Snk,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.command {
+            Command::Verify { .. } => {}
+            Command::State {
+                command: StateCommand::Import { file },
+            } => return state::import(&mut sink, file).await,
+            Command::Erase { args } => {
+                return erase::erase_and_check(self.source, &mut sink, args).await
+            }
+            _ => return self.run(sink).await,
+        }
+
+        let postgres_source = postgres_source(self.source, false, vec![]).await?;
+        let mut verifier = RowCountVerifier::new(postgres_source, sink);
+        let report = verifier.verify().await?;
+        println!("{report}");
+        Ok(())
+    }
+
+    /// Runs the command with a sink which can also hash its rows, to verify
+    /// it with checksums
+    #[cfg(feature = "bigquery")]
+    async fn run_or_checksum<
+        Snk: ChecksumSink + RowCountSink + StateSink + ErasureSink + DdlSink + PreviewSink,
+    >(
+        self,
+        sink: Snk,
+    ) -> Result<(), Box<dyn Error>> {
+        let Command::Verify {
+            checksums: true,
+            range_size,
+        } = self.command
+        else {
+            return self.run_stateful(sink).await;
+        };
+
+        let postgres_source = postgres_source(self.source, false, vec![]).await?;
+        let mut verifier = ChecksumVerifier::new(postgres_source, sink, range_size);
+        let report = verifier.verify().await?;
+        println!("{report}");
+        Ok(())
+    }
+}
+
+/// Creates the encryptor of the configured columns, none if no column is
+/// encrypted. The data key is obtained from its provider once
+async fn column_encryptor(
+    encryption: Option<EncryptionOptions>,
+) -> Result<Option<ColumnEncryptor>, Box<dyn Error>> {
+    let Some(EncryptionOptions { key, columns }) = encryption else {
+        return Ok(None);
+    };
+    Ok(Some(ColumnEncryptor::new(&key, columns).await?))
+}
+
+/// Logs the source's columns which likely hold personal data, if they are
+/// classified, and adds them to the encrypted columns if they are to be
+/// encrypted. The configured columns come first, so that their settings win
+async fn classify_columns(
+    source: &PostgresSource,
+    classification: Option<ClassificationOptions>,
+    mut encryption: Option<EncryptionOptions>,
+) -> Result<Option<EncryptionOptions>, Box<dyn Error>> {
+    let Some(classification) = classification else {
+        return Ok(encryption);
+    };
+    let classifications = classify(source, &classification).await?;
+    for column_classification in &classifications {
+        info!("{column_classification}");
+    }
+    if classification.encrypt {
+        if let Some(encryption) = &mut encryption {
+            encryption.columns.extend(
+                classifications
+                    .iter()
+                    .map(ColumnClassification::encrypted_column),
+            );
+        }
+    }
+    Ok(encryption)
+}
+
+fn batch_config(batch: BatchSettings) -> BatchConfig {
+    let BatchSettings {
+        max_size,
+        max_fill_secs,
+        transactional,
+    } = batch;
+    BatchConfig::new(max_size, Duration::from_secs(max_fill_secs)).with_transactional(transactional)
+}
+
+/// Connects to the source. The slot is only used to stream changes. The
+/// tables matching the patterns are replicated instead of the publication's
+/// if there are any.
+async fn postgres_source(
+    source: SourceSettings,
+    streams_changes: bool,
+    table_patterns: Vec<String>,
+) -> Result<PostgresSource, Box<dyn Error>> {
+    let SourceSettings::Postgres {
+        host,
+        port,
+        name,
+        username,
+        password,
+        slot_name,
+        publication,
+        tables,
+        columns,
+        connection,
+    } = source;
+
+    let table_names_from = if !table_patterns.is_empty() {
+        TableNamesFrom::Filter(TableFilter {
+            include: table_patterns,
+            exclude: vec![],
+        })
+    } else {
+        match tables {
+            Some(table_filter) => TableNamesFrom::FilteredPublication(publication, table_filter),
+            None => TableNamesFrom::Publication(publication),
+        }
+    };
+    let slot_name = if streams_changes { slot_name } else { None };
+
+    let postgres_source = PostgresSource::new(
+        &host,
+        port,
+        &name,
+        &username,
+        password,
+        slot_name,
+        table_names_from,
+        columns,
+        connection,
+    )
+    .await?;
+    Ok(postgres_source)
+}
+
+/// Qualifies a table pattern without a schema with the public schema
+fn parse_table_pattern(pattern: &str) -> Result<String, String> {
+    publication::parse_table_name(pattern).map(|table_name| table_name.to_string())
+}
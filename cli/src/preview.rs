@@ -0,0 +1,62 @@
+use std::{error::Error, time::Duration};
+
+use pg_replicate::pipeline::{
+    classification::ClassificationOptions,
+    dry_run::{dry_run, DryRunOptions},
+    encryption::EncryptionOptions,
+    sinks::PreviewSink,
+};
+
+use crate::{classify_columns, column_encryptor, configuration::SourceSettings, postgres_source};
+
+#[derive(Debug, clap::Args)]
+pub struct PreviewArgs {
+    /// Number of rows of each table which are copied
+    #[arg(long, default_value_t = 5)]
+    rows: u32,
+
+    /// Number of changes which are streamed from the source's slot, which
+    /// is created if it's missing. The changes aren't confirmed, so the slot
+    /// doesn't advance. The slot of a running pipeline can't be read
+    #[arg(long, default_value_t = 0)]
+    changes: usize,
+
+    /// Seconds to wait for the changes
+    #[arg(long, default_value_t = 10)]
+    changes_timeout_secs: u64,
+}
+
+impl PreviewArgs {
+    pub fn reads_changes(&self) -> bool {
+        self.changes > 0
+    }
+}
+
+/// Prints what the sink would write for the sampled rows and changes, with
+/// the configured and classified columns encrypted
+pub async fn run<Snk: PreviewSink>(
+    source: SourceSettings,
+    sink: &mut Snk,
+    args: PreviewArgs,
+    classification: Option<ClassificationOptions>,
+    encryption: Option<EncryptionOptions>,
+) -> Result<(), Box<dyn Error>> {
+    let postgres_source = postgres_source(source, args.reads_changes(), vec![]).await?;
+    let encryption = classify_columns(&postgres_source, classification, encryption).await?;
+    let encryptor = column_encryptor(encryption).await?;
+
+    let options = DryRunOptions {
+        rows_per_table: args.rows,
+        changes: args.changes,
+        changes_timeout: Duration::from_secs(args.changes_timeout_secs),
+    };
+    let records = dry_run(&postgres_source, sink, encryptor, &options).await?;
+    if records.is_empty() {
+        println!("the sink would write nothing");
+    }
+    for record in records {
+        println!("{record}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use std::error::Error;
+
+use clap::Subcommand;
+use pg_replicate::table::TableName;
+
+use crate::configuration::SourceSettings;
+
+#[derive(Debug, Subcommand)]
+pub enum PublicationCommand {
+    /// Create a publication of the tables, or of all tables if none are given
+    Create {
+        /// Tables to publish, as `schema.name` or `name` in the public schema
+        #[arg(value_parser = parse_table_name)]
+        tables: Vec<TableName>,
+
+        /// Name of the publication, the source's publication if not set
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// List the publications and their tables
+    List,
+
+    /// Add a table to a publication
+    AddTable {
+        #[arg(value_parser = parse_table_name)]
+        table: TableName,
+
+        /// Name of the publication, the source's publication if not set
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Remove a table from a publication
+    RemoveTable {
+        #[arg(value_parser = parse_table_name)]
+        table: TableName,
+
+        /// Name of the publication, the source's publication if not set
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+pub fn parse_table_name(table_name: &str) -> Result<TableName, String> {
+    let (schema, name) = table_name.split_once('.').unwrap_or(("public", table_name));
+    if schema.is_empty() || name.is_empty() {
+        return Err(format!(
+            "invalid table name {table_name}, expected `schema.name` or `name`"
+        ));
+    }
+    Ok(TableName {
+        schema: schema.to_string(),
+        name: name.to_string(),
+    })
+}
+
+pub async fn run(
+    source: &SourceSettings,
+    command: PublicationCommand,
+) -> Result<(), Box<dyn Error>> {
+    let SourceSettings::Postgres { publication, .. } = source;
+    let client = source.connect().await?;
+
+    match command {
+        PublicationCommand::Create { tables, name } => {
+            let name = name.as_ref().unwrap_or(publication);
+            client.create_publication(name, &tables).await?;
+            if tables.is_empty() {
+                println!("created publication {name} of all tables");
+            } else {
+                println!("created publication {name} of {} tables", tables.len());
+            }
+        }
+        PublicationCommand::List => {
+            for name in client.get_publication_names().await? {
+                println!("{name}");
+                for table_name in client.get_publication_table_names(&name).await? {
+                    println!("  {table_name}");
+                }
+            }
+        }
+        PublicationCommand::AddTable { table, name } => {
+            let name = name.as_ref().unwrap_or(publication);
+            client.add_publication_table(name, &table).await?;
+            println!("added {table} to publication {name}");
+        }
+        PublicationCommand::RemoveTable { table, name } => {
+            let name = name.as_ref().unwrap_or(publication);
+            client.remove_publication_table(name, &table).await?;
+            println!("removed {table} from publication {name}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_table_name;
+
+    #[test]
+    fn table_names_default_to_the_public_schema() {
+        let table_name = parse_table_name("users").unwrap();
+        assert_eq!(table_name.schema, "public");
+        assert_eq!(table_name.name, "users");
+
+        let table_name = parse_table_name("sales.orders").unwrap();
+        assert_eq!(table_name.schema, "sales");
+        assert_eq!(table_name.name, "orders");
+
+        assert!(parse_table_name("sales.").is_err());
+        assert!(parse_table_name(".orders").is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use std::{error::Error, path::PathBuf};
+
+use pg_replicate::pipeline::{
+    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+    encryption::ColumnEncryptor,
+    sinks::BatchSink,
+    sources::{replay::ReplaySource, Source},
+    PipelineAction,
+};
+
+use crate::{configuration::SourceSettings, postgres_source};
+
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    /// Dump written by the stdout sink with `--stdout-format json-lines`.
+    /// May be repeated, the dumps are replayed in order
+    #[arg(long = "dump", required = true)]
+    dumps: Vec<PathBuf>,
+
+    /// Only replay the copied rows of the tables
+    #[arg(long, conflicts_with = "changes_only")]
+    copies_only: bool,
+
+    /// Only replay the changes, of the transactions committed after the
+    /// sink's last lsn
+    #[arg(long)]
+    changes_only: bool,
+}
+
+/// Replays the dumps to the sink, converting their rows with the schemas of
+/// the source's tables
+pub async fn run<Snk: BatchSink>(
+    source: SourceSettings,
+    sink: Snk,
+    args: ReplayArgs,
+    batch_config: BatchConfig,
+    encryptor: Option<ColumnEncryptor>,
+) -> Result<(), Box<dyn Error>> {
+    // only the schemas are read from the source, its slot isn't used
+    let table_schemas = postgres_source(source, false, vec![])
+        .await?
+        .get_table_schemas()
+        .clone();
+    let source = ReplaySource::new(args.dumps, table_schemas);
+
+    let action = if args.copies_only {
+        PipelineAction::TableCopiesOnly
+    } else if args.changes_only {
+        PipelineAction::CdcOnly
+    } else {
+        PipelineAction::Both
+    };
+    let mut pipeline = BatchDataPipeline::new(source, sink, action, batch_config);
+    if let Some(encryptor) = encryptor {
+        pipeline = pipeline.with_column_encryptor(encryptor);
+    }
+    pipeline.start().await?;
+    Ok(())
+}
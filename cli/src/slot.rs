@@ -0,0 +1,74 @@
+use std::error::Error;
+
+use clap::Subcommand;
+
+use crate::configuration::SourceSettings;
+
+#[derive(Debug, Subcommand)]
+pub enum SlotCommand {
+    /// Create a logical replication slot. Changes made after it's created are
+    /// kept for it, tables copied later may stream some of them again
+    Create {
+        /// Name of the slot, the source's slot_name if not set
+        name: Option<String>,
+    },
+
+    /// Drop a replication slot, freeing the WAL kept for it
+    Drop {
+        /// Name of the slot, the source's slot_name if not set
+        name: Option<String>,
+    },
+
+    /// List the replication slots and the WAL kept for them
+    List,
+}
+
+pub async fn run(source: &SourceSettings, command: SlotCommand) -> Result<(), Box<dyn Error>> {
+    let SourceSettings::Postgres { slot_name, .. } = source;
+    let slot_name = |name: Option<String>| {
+        name.or_else(|| slot_name.clone())
+            .ok_or("a slot name or a slot_name of the source is needed")
+    };
+    let client = source.connect().await?;
+
+    match command {
+        SlotCommand::Create { name } => {
+            let name = slot_name(name)?;
+            let consistent_point = client.create_slot_without_snapshot(&name).await?;
+            println!("created slot {name} at {consistent_point}");
+        }
+        SlotCommand::Drop { name } => {
+            let name = slot_name(name)?;
+            client.drop_slot(&name).await?;
+            println!("dropped slot {name}");
+        }
+        SlotCommand::List => {
+            let slots = client.get_slots().await?;
+            let width = slots
+                .iter()
+                .map(|slot| slot.name.len())
+                .max()
+                .unwrap_or(0)
+                .max("slot".len());
+            let none = "-".to_string();
+            println!(
+                "{:width$}  {:8}  {:8}  {:19}  retained wal bytes",
+                "slot", "plugin", "state", "confirmed flush lsn"
+            );
+            for slot in slots {
+                println!(
+                    "{:width$}  {:8}  {:8}  {:19}  {}",
+                    slot.name,
+                    slot.plugin.as_ref().unwrap_or(&none),
+                    if slot.active { "active" } else { "inactive" },
+                    slot.confirmed_flush_lsn
+                        .map_or(none.clone(), |lsn| lsn.to_string()),
+                    slot.retained_wal_bytes
+                        .map_or(none.clone(), |bytes| bytes.to_string()),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::{error::Error, path::PathBuf};
+
+use pg_replicate::pipeline::{
+    batching::data_pipeline::BatchDataPipeline,
+    classification::ClassificationOptions,
+    dead_letters::DeadLetterWriter,
+    encryption::EncryptionOptions,
+    sinks::{naming::NamingRule, parquet::ParquetSink},
+    PipelineAction,
+};
+
+use crate::{
+    batch_config, classify_columns, column_encryptor,
+    configuration::{BatchSettings, SourceSettings},
+    parse_table_pattern, postgres_source,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct SnapshotArgs {
+    /// Local directory or object store url, e.g. `s3://bucket/path`, the
+    /// files are written to as `schema_name.parquet`. Object store
+    /// credentials are read from the environment
+    #[arg(long)]
+    path: String,
+
+    /// Table to export instead of the publication's, as `schema.name` or
+    /// `name` in the public schema. `*` matches any characters. May be
+    /// repeated
+    #[arg(long = "table", value_parser = parse_table_pattern)]
+    tables: Vec<String>,
+}
+
+/// Copies the tables to Parquet files, with the configured and classified
+/// columns encrypted. The tables are read in one transaction, so the files
+/// are a consistent snapshot of them, and no slot is created
+pub async fn run(
+    source: SourceSettings,
+    batch: BatchSettings,
+    args: SnapshotArgs,
+    classification: Option<ClassificationOptions>,
+    encryption: Option<EncryptionOptions>,
+    dead_letter_file: Option<PathBuf>,
+    naming_conventions: Vec<NamingRule>,
+) -> Result<(), Box<dyn Error>> {
+    let postgres_source = postgres_source(source, false, args.tables).await?;
+    let encryption = classify_columns(&postgres_source, classification, encryption).await?;
+
+    let sink = ParquetSink::new(args.path).with_naming_convention(naming_conventions);
+    let mut pipeline = BatchDataPipeline::new(
+        postgres_source,
+        sink,
+        PipelineAction::TableCopiesOnly,
+        batch_config(batch),
+    );
+    if let Some(dead_letter_file) = &dead_letter_file {
+        pipeline =
+            pipeline.with_dead_letter_writer(DeadLetterWriter::open(dead_letter_file).await?);
+    }
+    if let Some(encryptor) = column_encryptor(encryption).await? {
+        pipeline = pipeline.with_column_encryptor(encryptor);
+    }
+    pipeline.start().await?;
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::{error::Error, path::PathBuf};
+
+use clap::Subcommand;
+use pg_replicate::pipeline::{
+    sinks::{BatchSink, StateSink},
+    state::{export_state, import_state, PipelineState},
+};
+
+#[derive(Debug, Subcommand)]
+pub enum StateCommand {
+    /// Print the state the sink keeps of the pipeline, the copied tables and
+    /// the position of the streamed changes, as json
+    Export {
+        /// File to write the state to instead of printing it
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+
+    /// Write a state exported from another sink to the sink, so that the
+    /// pipeline resumes with it without copying the tables again. The sink
+    /// must not hold a state yet
+    Import {
+        /// File holding the exported state
+        file: PathBuf,
+    },
+}
+
+pub async fn export<Snk: BatchSink>(
+    sink: &mut Snk,
+    file: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let state = export_state(sink).await?;
+    let json = serde_json::to_string_pretty(&state)?;
+    match file {
+        Some(file) => {
+            std::fs::write(&file, json)?;
+            println!(
+                "exported {} copied tables and lsn {} to {}",
+                state.copied_tables.len(),
+                state.last_lsn,
+                file.display()
+            );
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+pub async fn import<Snk: StateSink>(sink: &mut Snk, file: PathBuf) -> Result<(), Box<dyn Error>> {
+    let state: PipelineState = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    import_state(sink, &state).await?;
+    println!(
+        "imported {} copied tables and lsn {}",
+        state.copied_tables.len(),
+        state.last_lsn
+    );
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::error::Error;
+
+use pg_replicate::{pipeline::sinks::BatchSink, table::TableName};
+use serde::Serialize;
+
+use crate::configuration::SourceSettings;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Status {
+    /// Current write position of the source's WAL
+    current_lsn: String,
+
+    /// Position up to which the sink has written the source's changes,
+    /// none if it hasn't written any
+    last_flushed_lsn: Option<String>,
+
+    /// Bytes of the WAL the sink hasn't written yet
+    lag_bytes: Option<u64>,
+
+    /// Seconds between a change being written to the WAL and the
+    /// replication flushing it, none if no replication is streaming changes
+    lag_secs: Option<f64>,
+
+    slot: Option<SlotState>,
+    tables: Vec<TableState>,
+}
+
+#[derive(Serialize)]
+struct SlotState {
+    name: String,
+    confirmed_flush_lsn: String,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct TableState {
+    name: String,
+    copied: bool,
+}
+
+/// Prints the state of the replication from the source's slot and the
+/// sink's resumption state. Reading the resumption state creates the sink's
+/// state tables if they are missing.
+pub async fn print_status<Snk: BatchSink>(
+    source: &SourceSettings,
+    sink: &mut Snk,
+    output: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let SourceSettings::Postgres {
+        slot_name,
+        publication,
+        tables,
+        ..
+    } = source;
+
+    let client = source.connect().await?;
+    let resumption_state = sink.get_resumption_state().await?;
+
+    let current_lsn = client.get_current_wal_lsn().await?;
+    let last_flushed_lsn =
+        (u64::from(resumption_state.last_lsn) != 0).then_some(resumption_state.last_lsn);
+    let lag_bytes = last_flushed_lsn
+        .map(|last_flushed_lsn| u64::from(current_lsn).saturating_sub(u64::from(last_flushed_lsn)));
+
+    let (slot, lag_secs) = match slot_name {
+        Some(slot_name) => match client.get_slot_status(slot_name).await? {
+            Some(slot_status) => (
+                Some(SlotState {
+                    name: slot_name.clone(),
+                    confirmed_flush_lsn: slot_status.confirmed_flush_lsn.to_string(),
+                    active: slot_status.active,
+                }),
+                slot_status.flush_lag_secs,
+            ),
+            None => (None, None),
+        },
+        None => (None, None),
+    };
+
+    let mut table_states = vec![];
+    let table_names: Vec<TableName> = client
+        .get_publication_table_names(publication)
+        .await?
+        .into_iter()
+        .filter(|table_name| {
+            tables
+                .as_ref()
+                .map_or(true, |table_filter| table_filter.matches(table_name))
+        })
+        .collect();
+    for table_name in table_names {
+        let copied = match client.get_table_id(&table_name).await? {
+            Some(table_id) => resumption_state.copied_tables.contains(&table_id),
+            None => false,
+        };
+        table_states.push(TableState {
+            name: table_name.to_string(),
+            copied,
+        });
+    }
+
+    let status = Status {
+        current_lsn: current_lsn.to_string(),
+        last_flushed_lsn: last_flushed_lsn.map(|lsn| lsn.to_string()),
+        lag_bytes,
+        lag_secs,
+        slot,
+        tables: table_states,
+    };
+    match output {
+        OutputFormat::Table => print_table(&status),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&status)?),
+    }
+
+    Ok(())
+}
+
+fn print_table(status: &Status) {
+    let none = "-".to_string();
+    println!("current lsn       {}", status.current_lsn);
+    println!(
+        "last flushed lsn  {}",
+        status.last_flushed_lsn.as_ref().unwrap_or(&none)
+    );
+    println!(
+        "lag               {} bytes, {} seconds",
+        status
+            .lag_bytes
+            .map_or(none.clone(), |lag_bytes| lag_bytes.to_string()),
+        status
+            .lag_secs
+            .map_or(none.clone(), |lag_secs| format!("{lag_secs:.1}")),
+    );
+    match &status.slot {
+        Some(slot) => println!(
+            "slot              {} at {}, {}",
+            slot.name,
+            slot.confirmed_flush_lsn,
+            if slot.active { "active" } else { "inactive" }
+        ),
+        None => println!("slot              {none}"),
+    }
+
+    println!();
+    let width = status
+        .tables
+        .iter()
+        .map(|table| table.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("table".len());
+    println!("{:width$}  copy", "table");
+    for table in &status.tables {
+        let state = if table.copied { "copied" } else { "pending" };
+        println!("{:width$}  {state}", table.name);
+    }
+}
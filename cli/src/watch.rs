@@ -0,0 +1,280 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use pg_replicate::pipeline::{batching::BatchConfig, PipelineUpdate};
+use tokio::{
+    signal::unix::{signal, Signal, SignalKind},
+    sync::mpsc,
+};
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::{
+    configuration::{load_settings, BatchSettings, Settings, SourceSettings},
+    log_filter,
+};
+
+/// Seconds between checks of the configuration file for changes
+const POLL_INTERVAL_SECS: u64 = 2;
+
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Reloads the configuration file of a running pipeline when it changes or
+/// the process receives SIGHUP
+pub struct Watcher {
+    pub path: PathBuf,
+    pub settings: Settings,
+    pub log_filter: LogFilterHandle,
+}
+
+/// What changed between the applied and the reloaded settings
+#[derive(Debug, Default)]
+struct Reload {
+    updates: Vec<PipelineUpdate>,
+
+    /// The new log level, if it changed. `Some(None)` restores the default
+    log_level: Option<Option<String>>,
+
+    /// Settings whose changes can't be applied to a running pipeline
+    needs_restart: Vec<&'static str>,
+}
+
+impl Watcher {
+    /// Sends the changes of the batch settings and the source's tables to the
+    /// pipeline and applies changes of the log level, until the pipeline
+    /// stops receiving updates
+    pub async fn watch(mut self, updates: mpsc::Sender<PipelineUpdate>) {
+        let mut hangups = signal(SignalKind::hangup())
+            .map_err(|e| {
+                warn!("failed to listen for SIGHUP, only changes of the file are reloaded: {e}")
+            })
+            .ok();
+        let mut interval = tokio::time::interval(Duration::from_secs(POLL_INTERVAL_SECS));
+        let mut modified = modified_time(&self.path);
+        loop {
+            tokio::select! {
+                Some(()) = next_hangup(&mut hangups) => {
+                    info!("reloading {} on SIGHUP", self.path.display());
+                }
+                _ = interval.tick() => {
+                    let last_modified = modified_time(&self.path);
+                    if last_modified == modified {
+                        continue;
+                    }
+                    modified = last_modified;
+                    info!("reloading {} after it changed", self.path.display());
+                }
+            }
+            if !self.reload(&updates).await {
+                return;
+            }
+        }
+    }
+
+    /// Applies the changes of the configuration file, returning false once
+    /// the pipeline has stopped
+    async fn reload(&mut self, updates: &mpsc::Sender<PipelineUpdate>) -> bool {
+        let reloaded = match load_settings(&self.path) {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                warn!("keeping the current settings, failed to reload them: {e}");
+                return true;
+            }
+        };
+
+        let reload = plan_reload(&self.settings, &reloaded);
+        for settings in reload.needs_restart {
+            warn!("changes of the {settings} settings need a restart, they were not applied");
+        }
+        if let Some(log_level) = reload.log_level {
+            if let Err(e) = self.log_filter.reload(log_filter(log_level.as_deref())) {
+                warn!("failed to change the log level: {e}");
+            }
+        }
+        for update in reload.updates {
+            info!("applying {update:?}");
+            if updates.send(update).await.is_err() {
+                return false;
+            }
+        }
+
+        let SourceSettings::Postgres { tables, .. } = &mut self.settings.source;
+        let SourceSettings::Postgres {
+            tables: reloaded_tables,
+            ..
+        } = reloaded.source;
+        *tables = reloaded_tables;
+        self.settings.batch = reloaded.batch;
+        self.settings.log_level = reloaded.log_level;
+        true
+    }
+}
+
+async fn next_hangup(hangups: &mut Option<Signal>) -> Option<()> {
+    match hangups {
+        Some(hangups) => hangups.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn plan_reload(current: &Settings, reloaded: &Settings) -> Reload {
+    let mut reload = Reload::default();
+
+    let SourceSettings::Postgres { tables, .. } = &current.source;
+    let SourceSettings::Postgres {
+        tables: reloaded_tables,
+        ..
+    } = &reloaded.source;
+    if without_tables(&current.source) != without_tables(&reloaded.source) {
+        reload.needs_restart.push("source");
+    }
+    if tables != reloaded_tables {
+        reload
+            .updates
+            .push(PipelineUpdate::Tables(reloaded_tables.clone()));
+    }
+
+    if current.sink != reloaded.sink {
+        reload.needs_restart.push("sink");
+    }
+
+    if current.encryption != reloaded.encryption {
+        reload.needs_restart.push("encryption");
+    }
+
+    if current.classification != reloaded.classification {
+        reload.needs_restart.push("classification");
+    }
+
+    if current.removed_table_policy != reloaded.removed_table_policy {
+        reload.needs_restart.push("removed table policy");
+    }
+
+    if current.naming_conventions != reloaded.naming_conventions {
+        reload.needs_restart.push("naming conventions");
+    }
+
+    if current.dead_letter_file != reloaded.dead_letter_file {
+        reload.needs_restart.push("dead letter file");
+    }
+
+    if current.batch != reloaded.batch {
+        let BatchSettings {
+            max_size,
+            max_fill_secs,
+            transactional,
+        } = reloaded.batch;
+        reload.updates.push(PipelineUpdate::BatchConfig(
+            BatchConfig::new(max_size, Duration::from_secs(max_fill_secs))
+                .with_transactional(transactional),
+        ));
+    }
+
+    if current.log_level != reloaded.log_level {
+        reload.log_level = Some(reloaded.log_level.clone());
+    }
+
+    reload
+}
+
+fn without_tables(source: &SourceSettings) -> SourceSettings {
+    let mut source = source.clone();
+    let SourceSettings::Postgres { tables, .. } = &mut source;
+    *tables = None;
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use pg_replicate::{
+        clients::postgres::{ColumnOptions, ConnectionOptions},
+        pipeline::{sinks::RemovedTablePolicy, sources::postgres::TableFilter, PipelineUpdate},
+    };
+
+    use crate::configuration::{BatchSettings, Settings, SinkSettings, SourceSettings};
+
+    use super::plan_reload;
+
+    fn settings() -> Settings {
+        Settings {
+            source: SourceSettings::Postgres {
+                host: "localhost".to_string(),
+                port: 5432,
+                name: "postgres".to_string(),
+                username: "postgres".to_string(),
+                password: None,
+                slot_name: Some("slot".to_string()),
+                publication: "publication".to_string(),
+                tables: None,
+                columns: ColumnOptions::default(),
+                connection: ConnectionOptions::default(),
+            },
+            sink: SinkSettings::Stdout,
+            batch: BatchSettings::default(),
+            encryption: None,
+            classification: None,
+            removed_table_policy: RemovedTablePolicy::Keep,
+            naming_conventions: vec![],
+            dead_letter_file: None,
+            log_level: None,
+        }
+    }
+
+    #[test]
+    fn unchanged_settings_have_nothing_to_reload() {
+        let reload = plan_reload(&settings(), &settings());
+        assert!(reload.updates.is_empty());
+        assert!(reload.log_level.is_none());
+        assert!(reload.needs_restart.is_empty());
+    }
+
+    #[test]
+    fn tables_batch_and_log_level_are_reloaded() {
+        let mut reloaded = settings();
+        let SourceSettings::Postgres { tables, .. } = &mut reloaded.source;
+        *tables = Some(TableFilter {
+            include: vec!["public.*".to_string()],
+            exclude: vec![],
+        });
+        reloaded.batch.max_size = 10;
+        reloaded.log_level = Some("debug".to_string());
+
+        let reload = plan_reload(&settings(), &reloaded);
+        assert!(matches!(
+            reload.updates.as_slice(),
+            [
+                PipelineUpdate::Tables(Some(_)),
+                PipelineUpdate::BatchConfig(_)
+            ]
+        ));
+        assert_eq!(reload.log_level, Some(Some("debug".to_string())));
+        assert!(reload.needs_restart.is_empty());
+    }
+
+    #[test]
+    fn other_changes_need_a_restart() {
+        let mut reloaded = settings();
+        let SourceSettings::Postgres { host, .. } = &mut reloaded.source;
+        *host = "replica".to_string();
+        reloaded.sink = SinkSettings::DuckDb {
+            file: "replica.db".into(),
+        };
+        reloaded.removed_table_policy = RemovedTablePolicy::Drop;
+        reloaded.dead_letter_file = Some("dead_letters.jsonl".into());
+
+        let reload = plan_reload(&settings(), &reloaded);
+        assert!(reload.updates.is_empty());
+        assert_eq!(
+            reload.needs_restart,
+            vec!["source", "sink", "removed table policy", "dead letter file"]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pg_replicate::{
+    bench::{Dataset, DatasetSource},
+    conversions::table_row::TableRowConverter,
+    pipeline::{
+        batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+        sinks::null::NullSink,
+        sources::synthetic::{SyntheticSource, SyntheticSourceConfig},
+        PipelineAction,
+    },
+};
+use tokio::runtime::Runtime;
+
+const NUM_ROWS: u64 = 10_000;
+const NUM_CHANGES: u64 = 10_000;
+const SEED: u64 = 42;
+
+fn batch_config() -> BatchConfig {
+    BatchConfig::new(1000, Duration::from_secs(10))
+}
+
+/// Decoding of the rows sent by Postgres during table copies
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for dataset in Dataset::standard(NUM_ROWS, SEED) {
+        group.throughput(Throughput::Bytes(dataset.num_bytes()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(dataset.name),
+            &dataset,
+            |b, dataset| {
+                let column_schemas = &dataset.table_schema.column_schemas;
+                let mut buffer = String::new();
+                b.iter(|| {
+                    for row in dataset.rows() {
+                        let row = TableRowConverter::try_from_with_buffer(
+                            row,
+                            column_schemas,
+                            &mut buffer,
+                        )
+                        .expect("datasets only contain valid rows");
+                        black_box(row);
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Table copies through the whole pipeline, from decoding to batching
+fn copy(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("failed to start a tokio runtime");
+    let mut group = c.benchmark_group("copy");
+    for dataset in Dataset::standard(NUM_ROWS, SEED) {
+        group.throughput(Throughput::Elements(dataset.num_rows()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(dataset.name),
+            &dataset,
+            |b, dataset| {
+                b.to_async(&runtime).iter(|| async {
+                    let source = DatasetSource::new(vec![dataset.clone()]);
+                    let mut pipeline = BatchDataPipeline::new(
+                        source,
+                        NullSink::default(),
+                        PipelineAction::TableCopiesOnly,
+                        batch_config(),
+                    );
+                    pipeline.start().await.expect("failed to copy the dataset");
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Changes streamed through the whole pipeline, from decoding to batching
+fn cdc(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("failed to start a tokio runtime");
+    let mut group = c.benchmark_group("cdc");
+    group.throughput(Throughput::Elements(NUM_CHANGES));
+    for num_columns in [4, 32] {
+        group.bench_with_input(
+            BenchmarkId::new("columns", num_columns),
+            &num_columns,
+            |b, &num_columns| {
+                b.to_async(&runtime).iter(|| async move {
+                    let source = SyntheticSource::new(SyntheticSourceConfig {
+                        num_tables: 4,
+                        num_columns,
+                        num_changes: Some(NUM_CHANGES),
+                        seed: SEED,
+                        ..SyntheticSourceConfig::default()
+                    });
+                    let mut pipeline = BatchDataPipeline::new(
+                        source,
+                        NullSink::default(),
+                        PipelineAction::CdcOnly,
+                        batch_config(),
+                    );
+                    pipeline
+                        .start()
+                        .await
+                        .expect("failed to stream the changes");
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, copy, cdc);
+criterion_main!(benches);
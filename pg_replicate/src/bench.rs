@@ -0,0 +1,368 @@
+//! Reproducible datasets and a source replaying them, to measure the
+//! throughput of conversions, batching and sinks with `cargo bench`
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::Stream;
+use thiserror::Error;
+use tokio_postgres::types::{PgLsn, Type};
+
+use crate::{
+    conversions::table_row::{TableRow, TableRowConverter},
+    pipeline::sources::{
+        postgres::{TableCopier, TableCopyStreamError, TableFilter},
+        synthetic::{Rng, SyntheticCdcStream},
+        Source, SourceError,
+    },
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
+
+/// Percentage of the values of nullable columns which are null
+const NULL_PERCENT: u64 = 5;
+
+const WORDS: [&str; 8] = [
+    "replication",
+    "postgres",
+    "slot",
+    "publication",
+    "wal",
+    "snapshot",
+    "tuple",
+    "lsn",
+];
+
+/// The rows of a table in the text format of `COPY`, generated from a seed so
+/// that every run measures the same data
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub name: &'static str,
+    pub table_schema: TableSchema,
+    rows: Arc<Vec<Vec<u8>>>,
+}
+
+impl Dataset {
+    /// The standard datasets, each with `num_rows` rows:
+    /// - `narrow`: an id and a few integer and boolean columns
+    /// - `wide`: 64 columns cycling through the common scalar types
+    /// - `text`: long text values, some with characters `COPY` escapes
+    /// - `arrays`: integer and text arrays
+    pub fn standard(num_rows: u64, seed: u64) -> Vec<Dataset> {
+        let scalar_types = [
+            Type::INT4,
+            Type::INT8,
+            Type::FLOAT8,
+            Type::NUMERIC,
+            Type::BOOL,
+            Type::TEXT,
+            Type::TIMESTAMPTZ,
+            Type::DATE,
+        ];
+        vec![
+            Dataset::generate(
+                "narrow",
+                &[Type::INT4, Type::INT4, Type::BOOL],
+                num_rows,
+                seed,
+            ),
+            Dataset::generate(
+                "wide",
+                &scalar_types
+                    .iter()
+                    .cycle()
+                    .take(64)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                num_rows,
+                seed,
+            ),
+            Dataset::generate("text", &[Type::TEXT, Type::TEXT], num_rows, seed),
+            Dataset::generate(
+                "arrays",
+                &[Type::INT4_ARRAY, Type::TEXT_ARRAY],
+                num_rows,
+                seed,
+            ),
+        ]
+    }
+
+    /// Generates `num_rows` rows of a table with an `id` primary key and
+    /// columns of the given types
+    pub fn generate(
+        name: &'static str,
+        column_types: &[Type],
+        num_rows: u64,
+        seed: u64,
+    ) -> Dataset {
+        let mut column_schemas = vec![ColumnSchema {
+            name: "id".to_string(),
+            typ: Type::INT8,
+            modifier: -1,
+            nullable: false,
+            primary: true,
+        }];
+        column_schemas.extend(
+            column_types
+                .iter()
+                .enumerate()
+                .map(|(i, typ)| ColumnSchema {
+                    name: format!("column_{i}"),
+                    typ: typ.clone(),
+                    modifier: -1,
+                    nullable: true,
+                    primary: false,
+                }),
+        );
+
+        let mut rng = Rng::new(seed);
+        let mut line = String::new();
+        let rows = (1..=num_rows)
+            .map(|id| {
+                line.clear();
+                write!(line, "{id}").expect("writing to a string can't fail");
+                for typ in column_types {
+                    line.push('\t');
+                    if rng.below(100) < NULL_PERCENT {
+                        line.push_str("\\N");
+                    } else {
+                        write_value(&mut line, typ, &mut rng, name == "text");
+                    }
+                }
+                line.push('\n');
+                line.as_bytes().to_vec()
+            })
+            .collect();
+
+        Dataset {
+            name,
+            table_schema: TableSchema {
+                table_name: TableName {
+                    schema: "bench".to_string(),
+                    name: name.to_string(),
+                },
+                table_id: 0,
+                column_schemas,
+                skipped_columns: vec![],
+                local_timestamp_columns: vec![],
+                large_object_columns: vec![],
+                type_overrides: HashMap::new(),
+                commit_timestamp_column: None,
+            },
+            rows: Arc::new(rows),
+        }
+    }
+
+    /// Rows in the text format of `COPY`, each ending with a newline
+    pub fn rows(&self) -> &[Vec<u8>] {
+        &self.rows
+    }
+
+    pub fn num_rows(&self) -> u64 {
+        self.rows.len() as u64
+    }
+
+    /// Size of the rows as sent by Postgres during a table copy
+    pub fn num_bytes(&self) -> u64 {
+        self.rows.iter().map(|row| row.len() as u64).sum()
+    }
+}
+
+fn write_value(line: &mut String, typ: &Type, rng: &mut Rng, long_text: bool) {
+    let result = match *typ {
+        Type::INT4 => write!(line, "{}", rng.next_u64() as i32),
+        Type::INT8 => write!(line, "{}", rng.next_u64() as i64),
+        Type::FLOAT8 => write!(line, "{:.3}", rng.below(1_000_000) as f64 / 7.0),
+        Type::NUMERIC => write!(line, "{}.{:02}", rng.below(1_000_000), rng.below(100)),
+        Type::BOOL => write!(line, "{}", if rng.below(2) == 0 { 'f' } else { 't' }),
+        Type::DATE => write!(
+            line,
+            "20{:02}-{:02}-{:02}",
+            rng.below(30),
+            rng.below(12) + 1,
+            rng.below(28) + 1
+        ),
+        Type::TIMESTAMPTZ => write!(
+            line,
+            "2024-{:02}-{:02} {:02}:{:02}:{:02}.{:06}+00",
+            rng.below(12) + 1,
+            rng.below(28) + 1,
+            rng.below(24),
+            rng.below(60),
+            rng.below(60),
+            rng.below(1_000_000)
+        ),
+        Type::TEXT => {
+            let num_words = if long_text {
+                32 + rng.below(32)
+            } else {
+                1 + rng.below(4)
+            };
+            for i in 0..num_words {
+                if i > 0 {
+                    // long texts have tabs and newlines, escaped by COPY
+                    let separator = match rng.below(16) {
+                        0 if long_text => "\\t",
+                        1 if long_text => "\\n",
+                        _ => " ",
+                    };
+                    line.push_str(separator);
+                }
+                line.push_str(WORDS[rng.below(WORDS.len() as u64) as usize]);
+            }
+            Ok(())
+        }
+        Type::INT4_ARRAY => {
+            line.push('{');
+            for i in 0..rng.below(8) {
+                if i > 0 {
+                    line.push(',');
+                }
+                let _ = write!(line, "{}", rng.next_u64() as i32);
+            }
+            line.push('}');
+            Ok(())
+        }
+        Type::TEXT_ARRAY => {
+            line.push('{');
+            for i in 0..rng.below(8) {
+                if i > 0 {
+                    line.push(',');
+                }
+                let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+                // quoted elements take the slower path of the array parser
+                if rng.below(2) == 0 {
+                    let _ = write!(line, "\"{word} {word}\"");
+                } else {
+                    line.push_str(word);
+                }
+            }
+            line.push('}');
+            Ok(())
+        }
+        _ => unreachable!("datasets only generate the types above"),
+    };
+    result.expect("writing to a string can't fail");
+}
+
+#[derive(Debug, Error)]
+pub enum DatasetSourceError {
+    #[error("table {0} is not a dataset of the source")]
+    UnknownTable(String),
+
+    #[error("dataset sources only copy tables")]
+    ChangesNotSupported,
+}
+
+impl SourceError for DatasetSourceError {}
+
+/// A source copying datasets as if they were tables of a Postgres database,
+/// decoding their rows like the Postgres source does
+pub struct DatasetSource {
+    datasets: HashMap<TableId, Dataset>,
+    table_schemas: HashMap<TableId, TableSchema>,
+}
+
+impl DatasetSource {
+    pub fn new(datasets: Vec<Dataset>) -> DatasetSource {
+        let datasets: HashMap<TableId, Dataset> = datasets
+            .into_iter()
+            .zip(1..)
+            .map(|(mut dataset, table_id)| {
+                dataset.table_schema.table_id = table_id;
+                (table_id, dataset)
+            })
+            .collect();
+        let table_schemas = datasets
+            .iter()
+            .map(|(table_id, dataset)| (*table_id, dataset.table_schema.clone()))
+            .collect();
+        DatasetSource {
+            datasets,
+            table_schemas,
+        }
+    }
+
+    fn find_dataset(&self, table_name: &TableName) -> Result<&Dataset, DatasetSourceError> {
+        self.datasets
+            .values()
+            .find(|dataset| {
+                dataset.table_schema.table_name.schema == table_name.schema
+                    && dataset.table_schema.table_name.name == table_name.name
+            })
+            .ok_or_else(|| DatasetSourceError::UnknownTable(table_name.to_string()))
+    }
+}
+
+#[async_trait]
+impl Source for DatasetSource {
+    type Error = DatasetSourceError;
+    type TableCopyStream = DatasetCopyStream;
+    type CdcStream = SyntheticCdcStream;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<DatasetCopyStream, Self::Error> {
+        let dataset = self.find_dataset(table_name)?;
+        Ok(DatasetCopyStream {
+            rows: dataset.rows.clone(),
+            next_row: 0,
+            column_schemas: column_schemas.to_vec(),
+            buffer: String::new(),
+        })
+    }
+
+    async fn count_table_rows(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        Ok(self.find_dataset(table_name)?.num_rows())
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn get_table_copier(
+        &self,
+        _table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error> {
+        Err(DatasetSourceError::ChangesNotSupported)
+    }
+
+    async fn get_cdc_stream(&self, _start_lsn: PgLsn) -> Result<SyntheticCdcStream, Self::Error> {
+        Err(DatasetSourceError::ChangesNotSupported)
+    }
+}
+
+/// Decodes the rows of a dataset
+pub struct DatasetCopyStream {
+    rows: Arc<Vec<Vec<u8>>>,
+    next_row: usize,
+    column_schemas: Vec<ColumnSchema>,
+    buffer: String,
+}
+
+impl Stream for DatasetCopyStream {
+    type Item = Result<TableRow, TableCopyStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let Some(row) = this.rows.get(this.next_row) else {
+            return Poll::Ready(None);
+        };
+        this.next_row += 1;
+        let row =
+            TableRowConverter::try_from_with_buffer(row, &this.column_schemas, &mut this.buffer)
+                .map_err(TableCopyStreamError::ConversionError);
+        Poll::Ready(Some(row))
+    }
+}
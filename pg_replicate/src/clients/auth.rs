@@ -0,0 +1,232 @@
+//! Authentication to managed Postgres services with short lived tokens of
+//! the cloud's identity service instead of a password. A new token is
+//! requested for each connection, so that reconnecting after a token
+//! expired succeeds.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_lc_rs::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use base64::{
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Resource of the tokens of Azure Database for PostgreSQL
+const AZURE_RESOURCE: &str = "https://ossrdbms-aad.database.windows.net";
+
+const AZURE_METADATA_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Scope of the tokens of Cloud SQL IAM database authentication
+const CLOUD_SQL_SCOPE: &str = "https://www.googleapis.com/auth/sqlservice.login";
+
+const GOOGLE_METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("failed to request a token: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("the token request was rejected with status {0}: {1}")]
+    Rejected(u16, String),
+
+    #[error("failed to read the service account key {0}: {1}")]
+    KeyFile(String, String),
+
+    #[error("invalid service account key: {0}")]
+    InvalidKey(String),
+}
+
+/// Where the token used as the password of the connections comes from. The
+/// connections' user is the identity the token is issued to, e.g. the
+/// service account's email without `.gserviceaccount.com` for Cloud SQL
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TokenAuth {
+    /// An Azure AD token of the managed identity of the host, or of its user
+    /// assigned identity with the client id
+    AzureManagedIdentity {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_id: Option<String>,
+    },
+
+    /// An Azure AD token of an application, obtained with its secret
+    AzureClientSecret {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+
+    /// A Cloud SQL IAM token of the service account attached to the host,
+    /// from the metadata server
+    CloudSqlMetadata,
+
+    /// A Cloud SQL IAM token of the service account of a key file
+    CloudSqlServiceAccount { key_file: String },
+}
+
+impl std::fmt::Debug for TokenAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenAuth::AzureManagedIdentity { client_id } => f
+                .debug_struct("AzureManagedIdentity")
+                .field("client_id", client_id)
+                .finish(),
+            TokenAuth::AzureClientSecret {
+                tenant_id,
+                client_id,
+                client_secret: _,
+            } => f
+                .debug_struct("AzureClientSecret")
+                .field("tenant_id", tenant_id)
+                .field("client_id", client_id)
+                .field("client_secret", &"REDACTED")
+                .finish(),
+            TokenAuth::CloudSqlMetadata => write!(f, "CloudSqlMetadata"),
+            TokenAuth::CloudSqlServiceAccount { key_file } => f
+                .debug_struct("CloudSqlServiceAccount")
+                .field("key_file", key_file)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// The fields of a Google service account key needed to authenticate
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+impl TokenAuth {
+    /// Requests a new token
+    pub async fn token(&self) -> Result<String, AuthError> {
+        let client = reqwest::Client::new();
+        let request = match self {
+            TokenAuth::AzureManagedIdentity { client_id } => {
+                let mut query = vec![("api-version", "2018-02-01"), ("resource", AZURE_RESOURCE)];
+                if let Some(client_id) = client_id {
+                    query.push(("client_id", client_id.as_str()));
+                }
+                client
+                    .get(AZURE_METADATA_TOKEN_URL)
+                    .query(&query)
+                    .header("Metadata", "true")
+            }
+            TokenAuth::AzureClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let scope = format!("{AZURE_RESOURCE}/.default");
+                client
+                    .post(format!(
+                        "https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token"
+                    ))
+                    .form(&[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", client_id.as_str()),
+                        ("client_secret", client_secret.as_str()),
+                        ("scope", scope.as_str()),
+                    ])
+            }
+            TokenAuth::CloudSqlMetadata => return google_token(None, CLOUD_SQL_SCOPE).await,
+            TokenAuth::CloudSqlServiceAccount { key_file } => {
+                return google_token(Some(key_file.as_str()), CLOUD_SQL_SCOPE).await
+            }
+        };
+        request_token(request).await
+    }
+}
+
+/// Requests a Google token with the scope for the service account of the
+/// key file, or for the one attached to the host if there is none
+pub(crate) async fn google_token(key_file: Option<&str>, scope: &str) -> Result<String, AuthError> {
+    let client = reqwest::Client::new();
+    let request = match key_file {
+        None => client
+            .get(GOOGLE_METADATA_TOKEN_URL)
+            .query(&[("scopes", scope)])
+            .header("Metadata-Flavor", "Google"),
+        Some(key_file) => {
+            let key = std::fs::read_to_string(key_file)
+                .map_err(|e| AuthError::KeyFile(key_file.to_string(), e.to_string()))?;
+            let key: ServiceAccountKey = serde_json::from_str(&key)
+                .map_err(|e| AuthError::KeyFile(key_file.to_string(), e.to_string()))?;
+            let assertion = signed_jwt(&key, scope)?;
+            client.post(&key.token_uri).form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+        }
+    };
+    request_token(request).await
+}
+
+async fn request_token(request: reqwest::RequestBuilder) -> Result<String, AuthError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AuthError::Rejected(status.as_u16(), body));
+    }
+    let token: TokenResponse = response.json().await?;
+    Ok(token.access_token)
+}
+
+/// Signs a JWT asking for a token with the scopes, which is exchanged for
+/// the token at the key's token uri
+fn signed_jwt(key: &ServiceAccountKey, scopes: &str) -> Result<String, AuthError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let header = serde_json::json!({ "alg": "RS256", "typ": "JWT" });
+    let claims = serde_json::json!({
+        "iss": key.client_email,
+        "scope": scopes,
+        "aud": key.token_uri,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    let message = format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+        BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+
+    let pem_body: String = key
+        .private_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = BASE64_STANDARD
+        .decode(pem_body)
+        .map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+    let key_pair =
+        RsaKeyPair::from_pkcs8(&der).map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+    let mut signature = vec![0; key_pair.public_modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|_| AuthError::InvalidKey("failed to sign the token request".to_string()))?;
+
+    Ok(format!(
+        "{message}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashSet, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    time::Duration,
+};
 
 use bytes::{Buf, BufMut};
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
@@ -7,29 +11,333 @@ use gcp_bigquery_client::storage::{ColumnMode, StorageApi};
 use gcp_bigquery_client::yup_oauth2::parse_service_account_key;
 use gcp_bigquery_client::{
     error::BQError,
-    google::cloud::bigquery::storage::v1::{WriteStream, WriteStreamView},
-    model::{
-        query_request::QueryRequest, query_response::ResultSet,
-        table_data_insert_all_request::TableDataInsertAllRequest,
+    google::cloud::bigquery::storage::v1::{
+        append_rows_response::Response, WriteStream, WriteStreamView,
     },
+    model::{query_request::QueryRequest, query_response::ResultSet},
     storage::{ColumnType, FieldDescriptor, StreamName, TableDescriptor},
     Client,
 };
 use prost::Message;
+use rand::Rng;
+use thiserror::Error;
 use tokio_postgres::types::{PgLsn, Type};
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 use crate::conversions::numeric::PgNumeric;
-use crate::conversions::{ArrayCell, Cell};
+use crate::conversions::{json::cell_to_text, ArrayCell, Cell};
 use crate::{
     conversions::table_row::TableRow,
-    table::{ColumnSchema, TableId, TableSchema},
+    pipeline::{
+        reporting::{ErrorHook, ErrorOrigin},
+        verification::{ChecksumSpec, HashedValue, RangeChecksum},
+    },
+    table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
+#[derive(Clone)]
 pub struct BigQueryClient {
     project_id: String,
     client: Client,
+    retry_config: RetryConfig,
+    error_hook: Option<ErrorHook>,
+}
+
+#[derive(Debug, Error)]
+pub enum StreamRowsError {
+    #[error("big query error: {0}")]
+    BigQuery(#[from] BQError),
+
+    #[error("row {0} is larger than the maximum append rows request size")]
+    RowTooLarge(usize),
+
+    #[error("append rows failed with status {code}: {message}")]
+    AppendFailed { code: i32, message: String },
+
+    /// Index and error message of each rejected row
+    #[error("append rows failed for {} rows: {:?}", .0.len(), .0)]
+    RowErrors(Vec<(i64, String)>),
+}
+
+#[derive(Debug, Error)]
+pub enum QueryResultError {
+    #[error("big query error: {0}")]
+    BigQuery(#[from] BQError),
+
+    #[error("no value in column `{0}` of the query result")]
+    MissingColumn(&'static str),
+}
+
+/// A Postgres table or column name and the BigQuery name it is written to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameMapping {
+    /// Table name in `schema.name` form
+    pub source_table: String,
+    /// `None` if this is the mapping of the table's name
+    pub source_column: Option<String>,
+    pub bigquery_name: String,
+}
+
+/// Column of a change log table holding the type of change,
+/// `INSERT`, `UPDATE` or `DELETE`
+pub const CHANGE_TYPE_COLUMN: &str = "_pg_change_type";
+
+/// Column of a change log table holding the final lsn of the change's
+/// transaction
+pub const CHANGE_LSN_COLUMN: &str = "_pg_change_lsn";
+
+/// Column of a change log table holding the position of the change within
+/// its transaction
+pub const CHANGE_INDEX_COLUMN: &str = "_pg_change_index";
+
+/// Controls how requests failing with a retryable error are retried.
+/// Retries are delayed with exponential backoff and full jitter.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RetryConfig {
+    /// Maximum number of retries of a single request, 0 disables retries
+    pub max_retries: u32,
+
+    /// Backoff before the first retry, in milliseconds
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound of the backoff between retries, in milliseconds
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max_backoff_ms = self
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt))
+            .min(self.max_backoff_ms);
+        let backoff_ms = rand::thread_rng().gen_range(0..=max_backoff_ms);
+        Duration::from_millis(backoff_ms)
+    }
+}
+
+/// Returns true for errors which are expected to go away when the request
+/// is retried: rate limits, transient backend errors and reset streams.
+pub(crate) fn is_retryable(e: &BQError) -> bool {
+    match e {
+        BQError::RequestError(e) => e.is_timeout() || e.is_connect(),
+        BQError::ResponseError { error } => {
+            let retryable_reason = error.error.errors.iter().any(|e| {
+                matches!(
+                    e.get("reason").map(String::as_str),
+                    Some("rateLimitExceeded" | "backendError" | "internalError")
+                )
+            });
+            retryable_reason || matches!(error.error.code, 429 | 500 | 502 | 503 | 504)
+        }
+        BQError::TonicStatusError(status) => {
+            // gRPC status codes: DEADLINE_EXCEEDED (4), RESOURCE_EXHAUSTED (8),
+            // ABORTED (10), INTERNAL (13) and UNAVAILABLE (14)
+            matches!(status.code() as i32, 4 | 8 | 10 | 13 | 14)
+        }
+        BQError::TonicTransportError(_) => true,
+        _ => false,
+    }
+}
+
+/// Options used when creating a missing dataset
+#[derive(Debug, Clone, Default)]
+pub struct DatasetOptions {
+    /// Location of the dataset, e.g. `US` or `europe-west1`. If not set
+    /// BigQuery's default location is used.
+    pub location: Option<String>,
+
+    /// Labels to attach to the dataset
+    pub labels: HashMap<String, String>,
+}
+
+/// Granularity of time unit partitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionGranularity {
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+impl PartitionGranularity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PartitionGranularity::Hour => "hour",
+            PartitionGranularity::Day => "day",
+            PartitionGranularity::Month => "month",
+            PartitionGranularity::Year => "year",
+        }
+    }
+}
+
+/// How a table is partitioned
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TablePartitioning {
+    /// Partition by a date or timestamp column
+    Column {
+        column: String,
+        granularity: PartitionGranularity,
+    },
+
+    /// Partition by the time rows were ingested into BigQuery
+    IngestionTime { granularity: PartitionGranularity },
+}
+
+/// Default maximum staleness of tables, used when not configured
+pub const DEFAULT_MAX_STALENESS_MINS: u16 = 5;
+
+/// Options of a table. Partitioning and clustering are only used when
+/// creating a missing table, the other options are also applied to
+/// existing tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TableOptions {
+    /// Partitioning of the table, unpartitioned if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<TablePartitioning>,
+
+    /// Number of days after which partitions are deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_expiration_days: Option<u32>,
+
+    /// Columns to cluster the table by, at most four
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clustering: Vec<String>,
+
+    /// How stale, in minutes, query results are allowed to be. Lower values
+    /// make queries see changes sooner at a higher query cost. Defaults to
+    /// [DEFAULT_MAX_STALENESS_MINS]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_staleness_mins: Option<u16>,
+
+    /// Description of the table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Labels to attach to the table
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+/// A BigQuery type columns of a Postgres type are written as instead of
+/// their default type. Values which can't be converted to it are written as
+/// nulls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BigQueryType {
+    /// The text of the values
+    String,
+
+    /// Uuids as their 16 bytes, other values as the bytes of their text
+    Bytes,
+
+    /// Numbers rounded to integers
+    Int64,
+
+    /// Numbers as floats, numerics lose their precision
+    Float64,
+
+    /// Numbers with 38 digits, 9 of them after the decimal point
+    Numeric,
+
+    /// Numbers with about 76 digits, 38 of them after the decimal point
+    BigNumeric,
+}
+
+impl BigQueryType {
+    fn ddl_type(&self) -> &'static str {
+        match self {
+            BigQueryType::String => "string",
+            BigQueryType::Bytes => "bytes",
+            BigQueryType::Int64 => "int64",
+            BigQueryType::Float64 => "float64",
+            BigQueryType::Numeric => "numeric",
+            BigQueryType::BigNumeric => "bignumeric",
+        }
+    }
+
+    fn column_type(&self) -> ColumnType {
+        match self {
+            BigQueryType::String => ColumnType::String,
+            BigQueryType::Bytes => ColumnType::Bytes,
+            BigQueryType::Int64 => ColumnType::Int64,
+            BigQueryType::Float64 => ColumnType::Double,
+            // sent as strings which BigQuery parses, like numerics
+            BigQueryType::Numeric | BigQueryType::BigNumeric => ColumnType::String,
+        }
+    }
+
+    /// Converts a value to the cell encoding the type
+    fn convert(&self, cell: Cell) -> Cell {
+        match (self, cell) {
+            (_, Cell::Null) => Cell::Null,
+            (BigQueryType::String, Cell::String(s)) => Cell::String(s),
+            (BigQueryType::Bytes, Cell::Uuid(uuid)) => Cell::Bytes(uuid.as_bytes().to_vec()),
+            (BigQueryType::Bytes, Cell::Bytes(bytes)) => Cell::Bytes(bytes),
+            (BigQueryType::Bytes, cell) => Cell::Bytes(cell_to_text(&cell).into_bytes()),
+            (BigQueryType::Int64, Cell::I16(i)) => Cell::I64(i as i64),
+            (BigQueryType::Int64, Cell::I32(i)) => Cell::I64(i as i64),
+            (BigQueryType::Int64, Cell::U32(i)) => Cell::I64(i as i64),
+            (BigQueryType::Int64, Cell::I64(i)) => Cell::I64(i),
+            (BigQueryType::Int64, cell) => {
+                let text = cell_to_text(&cell);
+                match text.parse::<i64>() {
+                    Ok(i) => Cell::I64(i),
+                    Err(_) => match text.parse::<f64>() {
+                        Ok(f) if f.is_finite() => Cell::I64(f.round() as i64),
+                        _ => Cell::Null,
+                    },
+                }
+            }
+            (BigQueryType::Float64, Cell::F64(f)) => Cell::F64(f),
+            (BigQueryType::Float64, Cell::F32(f)) => Cell::F64(f as f64),
+            (BigQueryType::Float64, cell) => match cell_to_text(&cell).parse::<f64>() {
+                Ok(f) => Cell::F64(f),
+                Err(_) => Cell::Null,
+            },
+            (_, cell) => Cell::String(cell_to_text(&cell)),
+        }
+    }
+}
+
+/// BigQuery types columns of Postgres types are written as instead of their
+/// default types, keyed by the names of the Postgres types, e.g. `uuid`
+pub type TypeMappings = HashMap<String, BigQueryType>;
+
+/// Returns the type a column is mapped to. Columns of array types and the
+/// change columns of change logs aren't mapped
+fn mapped_type(type_mappings: &TypeMappings, column_schema: &ColumnSchema) -> Option<BigQueryType> {
+    if BigQueryClient::is_array_type(&column_schema.typ)
+        || [CHANGE_TYPE_COLUMN, CHANGE_LSN_COLUMN, CHANGE_INDEX_COLUMN]
+            .contains(&column_schema.name.as_str())
+    {
+        return None;
+    }
+    type_mappings.get(column_schema.typ.name()).copied()
+}
+
+/// Converts the values of a row's columns of mapped types to their types.
+/// Values of the columns added by the sink are left as they are
+pub fn map_row(type_mappings: &TypeMappings, column_schemas: &[ColumnSchema], row: &mut TableRow) {
+    if type_mappings.is_empty() {
+        return;
+    }
+    for (column_schema, cell) in column_schemas.iter().zip(row.values.iter_mut()) {
+        if let Some(bigquery_type) = mapped_type(type_mappings, column_schema) {
+            let value = std::mem::replace(cell, Cell::Null);
+            *cell = bigquery_type.convert(value);
+        }
+    }
 }
 
 //TODO: fix all SQL injections
@@ -42,7 +350,12 @@ impl BigQueryClient {
         let service_account_key = parse_service_account_key(gcp_sa_key)?;
         let client = Client::from_service_account_key(service_account_key, false).await?;
 
-        Ok(BigQueryClient { project_id, client })
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            retry_config: RetryConfig::default(),
+            error_hook: None,
+        })
     }
 
     pub async fn new_with_key(
@@ -52,7 +365,109 @@ impl BigQueryClient {
         let service_account_key = parse_service_account_key(gcp_sa_key)?;
         let client = Client::from_service_account_key(service_account_key, false).await?;
 
-        Ok(BigQueryClient { project_id, client })
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            retry_config: RetryConfig::default(),
+            error_hook: None,
+        })
+    }
+
+    /// Creates a client using Application Default Credentials: the key file
+    /// pointed to by `GOOGLE_APPLICATION_CREDENTIALS` if set, otherwise the
+    /// credentials of the attached service account from the metadata server.
+    pub async fn new_with_adc(project_id: String) -> Result<BigQueryClient, BQError> {
+        let client = Client::from_application_default_credentials().await?;
+
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            retry_config: RetryConfig::default(),
+            error_hook: None,
+        })
+    }
+
+    /// Creates a client using credentials from the metadata server, e.g. with
+    /// GKE workload identity or on Cloud Run, without needing an exported key.
+    pub async fn new_with_workload_identity(project_id: String) -> Result<BigQueryClient, BQError> {
+        let client = Client::with_workload_identity(false).await?;
+
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            retry_config: RetryConfig::default(),
+            error_hook: None,
+        })
+    }
+
+    pub fn set_retry_config(&mut self, retry_config: RetryConfig) {
+        self.retry_config = retry_config;
+    }
+
+    pub fn set_error_hook(&mut self, error_hook: ErrorHook) {
+        self.error_hook = Some(error_hook);
+    }
+
+    /// Reports a failed request which is retried. The table is named by its
+    /// dataset and its name
+    fn report_retry(&self, e: &BQError, table: Option<TableName>, attempt: u32) {
+        if let Some(error_hook) = &self.error_hook {
+            error_hook.report(e, ErrorOrigin::Sink, table, None, Some(attempt + 1), false);
+        }
+    }
+
+    /// Creates a dataset if it doesn't exist. Options are only applied
+    /// when the dataset is created, an existing dataset is left untouched.
+    pub async fn create_dataset_if_missing(
+        &self,
+        dataset_id: &str,
+        options: &DatasetOptions,
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        let dataset_options = Self::dataset_options(options);
+        info!("creating dataset {project_id}.{dataset_id} in bigquery if missing");
+        let query =
+            format!("create schema if not exists `{project_id}.{dataset_id}` {dataset_options}",);
+        let _ = self.query(query).await?;
+        Ok(())
+    }
+
+    fn dataset_options(options: &DatasetOptions) -> String {
+        let mut option_list = vec![];
+
+        if let Some(location) = &options.location {
+            option_list.push(format!("location = {}", Self::string_literal(location)));
+        }
+
+        if !options.labels.is_empty() {
+            option_list.push(Self::labels_option(&options.labels));
+        }
+
+        if option_list.is_empty() {
+            String::new()
+        } else {
+            format!("options ({})", option_list.join(", "))
+        }
+    }
+
+    fn labels_option(labels: &HashMap<String, String>) -> String {
+        let mut labels: Vec<_> = labels.iter().collect();
+        labels.sort();
+        let labels: Vec<String> = labels
+            .into_iter()
+            .map(|(key, value)| {
+                format!(
+                    "({}, {})",
+                    Self::string_literal(key),
+                    Self::string_literal(value)
+                )
+            })
+            .collect();
+        format!("labels = [{}]", labels.join(", "))
+    }
+
+    fn string_literal(s: &str) -> String {
+        format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
     }
 
     pub async fn create_table_if_missing(
@@ -60,12 +475,20 @@ impl BigQueryClient {
         dataset_id: &str,
         table_name: &str,
         column_schemas: &[ColumnSchema],
+        type_mappings: &TypeMappings,
+        table_options: &TableOptions,
     ) -> Result<bool, BQError> {
         if self.table_exists(dataset_id, table_name).await? {
             Ok(false)
         } else {
-            self.create_table(dataset_id, table_name, column_schemas)
-                .await?;
+            self.create_table(
+                dataset_id,
+                table_name,
+                column_schemas,
+                type_mappings,
+                table_options,
+            )
+            .await?;
             Ok(true)
         }
     }
@@ -131,12 +554,15 @@ impl BigQueryClient {
         )
     }
 
-    fn column_spec(column_schema: &ColumnSchema, s: &mut String) {
+    fn column_spec(column_schema: &ColumnSchema, type_mappings: &TypeMappings, s: &mut String) {
         s.push('`');
         s.push_str(&column_schema.name);
         s.push('`');
         s.push(' ');
-        let typ = Self::postgres_to_bigquery_type(&column_schema.typ);
+        let typ = match mapped_type(type_mappings, column_schema) {
+            Some(bigquery_type) => bigquery_type.ddl_type(),
+            None => Self::postgres_to_bigquery_type(&column_schema.typ),
+        };
         s.push_str(typ);
         if !column_schema.nullable && !Self::is_array_type(&column_schema.typ) {
             s.push_str(" not null");
@@ -159,12 +585,15 @@ impl BigQueryClient {
         s.push_str(") not enforced");
     }
 
-    fn create_columns_spec(column_schemas: &[ColumnSchema]) -> String {
+    fn create_columns_spec(
+        column_schemas: &[ColumnSchema],
+        type_mappings: &TypeMappings,
+    ) -> String {
         let mut s = String::new();
         s.push('(');
 
         for column_schema in column_schemas.iter() {
-            Self::column_spec(column_schema, &mut s);
+            Self::column_spec(column_schema, type_mappings, &mut s);
             s.push(',');
         }
 
@@ -180,8 +609,87 @@ impl BigQueryClient {
         s
     }
 
-    fn max_staleness_option(max_staleness_mins: u16) -> String {
-        format!("options (max_staleness = interval {max_staleness_mins} minute)")
+    fn partition_by_clause(
+        partitioning: &TablePartitioning,
+        column_schemas: &[ColumnSchema],
+    ) -> String {
+        match partitioning {
+            TablePartitioning::Column {
+                column,
+                granularity,
+            } => {
+                let is_date_column = column_schemas
+                    .iter()
+                    .any(|c| &c.name == column && c.typ == Type::DATE);
+                match (is_date_column, granularity) {
+                    (true, PartitionGranularity::Day) => format!("partition by `{column}`"),
+                    (true, granularity) => {
+                        format!(
+                            "partition by date_trunc(`{column}`, {})",
+                            granularity.as_str()
+                        )
+                    }
+                    (false, granularity) => format!(
+                        "partition by timestamp_trunc(`{column}`, {})",
+                        granularity.as_str()
+                    ),
+                }
+            }
+            TablePartitioning::IngestionTime { granularity } => format!(
+                "partition by timestamp_trunc(_PARTITIONTIME, {})",
+                granularity.as_str()
+            ),
+        }
+    }
+
+    fn cluster_by_clause(clustering: &[String]) -> String {
+        let columns: Vec<String> = clustering.iter().map(|c| format!("`{c}`")).collect();
+        format!("cluster by {}", columns.join(", "))
+    }
+
+    fn table_options_clause(table_options: &TableOptions) -> String {
+        let max_staleness_mins = table_options
+            .max_staleness_mins
+            .unwrap_or(DEFAULT_MAX_STALENESS_MINS);
+        let mut option_list = vec![format!(
+            "max_staleness = interval {max_staleness_mins} minute"
+        )];
+
+        if let Some(partition_expiration_days) = table_options.partition_expiration_days {
+            option_list.push(format!(
+                "partition_expiration_days = {partition_expiration_days}"
+            ));
+        }
+
+        if let Some(description) = &table_options.description {
+            option_list.push(format!(
+                "description = {}",
+                Self::string_literal(description)
+            ));
+        }
+
+        if !table_options.labels.is_empty() {
+            option_list.push(Self::labels_option(&table_options.labels));
+        }
+
+        format!("options ({})", option_list.join(", "))
+    }
+
+    /// Sets the options of an existing table. Partitioning and clustering
+    /// can't be changed and are ignored.
+    pub async fn set_table_options(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        table_options: &TableOptions,
+    ) -> Result<(), BQError> {
+        let options_clause = Self::table_options_clause(table_options);
+        let project_id = &self.project_id;
+        info!("setting options of table {project_id}.{dataset_id}.{table_name} in bigquery");
+        let query =
+            format!("alter table `{project_id}.{dataset_id}.{table_name}` set {options_clause}",);
+        let _ = self.query(query).await?;
+        Ok(())
     }
 
     pub async fn create_table(
@@ -189,17 +697,50 @@ impl BigQueryClient {
         dataset_id: &str,
         table_name: &str,
         column_schemas: &[ColumnSchema],
+        type_mappings: &TypeMappings,
+        table_options: &TableOptions,
     ) -> Result<(), BQError> {
-        let columns_spec = Self::create_columns_spec(column_schemas);
-        let max_staleness_option = Self::max_staleness_option(5);
         let project_id = &self.project_id;
         info!("creating table {project_id}.{dataset_id}.{table_name} in bigquery");
-        let query =
-            format!("create table `{project_id}.{dataset_id}.{table_name}` {columns_spec} {max_staleness_option}",);
+        let query = self.create_table_ddl(
+            dataset_id,
+            table_name,
+            column_schemas,
+            type_mappings,
+            table_options,
+        );
         let _ = self.query(query).await?;
         Ok(())
     }
 
+    /// Returns the statement creating a table
+    pub fn create_table_ddl(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_schemas: &[ColumnSchema],
+        type_mappings: &TypeMappings,
+        table_options: &TableOptions,
+    ) -> String {
+        let columns_spec = Self::create_columns_spec(column_schemas, type_mappings);
+
+        let mut table_spec = columns_spec;
+        if let Some(partitioning) = &table_options.partitioning {
+            table_spec.push(' ');
+            table_spec.push_str(&Self::partition_by_clause(partitioning, column_schemas));
+        }
+        if !table_options.clustering.is_empty() {
+            table_spec.push(' ');
+            table_spec.push_str(&Self::cluster_by_clause(&table_options.clustering));
+        }
+
+        let options_clause = Self::table_options_clause(table_options);
+        let project_id = &self.project_id;
+        format!(
+            "create table `{project_id}.{dataset_id}.{table_name}` {table_spec} {options_clause}",
+        )
+    }
+
     pub async fn get_default_stream(
         &mut self,
         dataset_id: &str,
@@ -218,6 +759,21 @@ impl BigQueryClient {
         Ok(write_stream)
     }
 
+    /// Returns whether a dataset exists, which also checks that the client's
+    /// credentials can read it
+    pub async fn dataset_exists(&self, dataset_id: &str) -> Result<bool, BQError> {
+        match self
+            .client
+            .dataset()
+            .get(&self.project_id, dataset_id)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(BQError::ResponseError { error }) if error.error.code == 404 => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn table_exists(&self, dataset_id: &str, table_name: &str) -> Result<bool, BQError> {
         let query = format!(
             "select exists
@@ -241,39 +797,270 @@ impl BigQueryClient {
         Ok(exists)
     }
 
-    pub async fn get_last_lsn(&self, dataset_id: &str) -> Result<PgLsn, BQError> {
+    pub async fn count_rows(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+    ) -> Result<u64, QueryResultError> {
         let project_id = &self.project_id;
-        let query = format!("select lsn from `{project_id}.{dataset_id}.last_lsn`",);
+        let query =
+            format!("select count(*) as row_count from `{project_id}.{dataset_id}.{table_name}`",);
 
         let mut rs = self.query(query).await?;
 
-        let lsn: i64 = if rs.next_row() {
-            rs.get_i64_by_name("lsn")?
-                .expect("no column named `lsn` found in query result")
-        } else {
-            //TODO: return error instead of panicking
-            panic!("failed to get lsn");
+        let mut row_count = 0;
+        if rs.next_row() {
+            row_count = rs
+                .get_i64_by_name("row_count")?
+                .ok_or(QueryResultError::MissingColumn("row_count"))?;
+        }
+
+        Ok(row_count as u64)
+    }
+
+    /// Returns the row count and checksum of each range of the table's keys
+    /// which holds rows, hashing rows the same way as
+    /// [`ReplicationClient::checksum_ranges`](crate::clients::postgres::ReplicationClient::checksum_ranges).
+    /// The spec's columns are the table's BigQuery columns.
+    pub async fn checksum_ranges(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        spec: &ChecksumSpec,
+    ) -> Result<Vec<RangeChecksum>, QueryResultError> {
+        let project_id = &self.project_id;
+        let values: Vec<String> = spec
+            .columns
+            .iter()
+            .map(|(name, hashed_value)| {
+                format!(
+                    "coalesce({}, r'\\N')",
+                    hashed_value_expr(name, *hashed_value)
+                )
+            })
+            .collect();
+        // concat_ws doesn't exist in BigQuery, array_to_string skips nulls
+        // which were replaced above
+        let hash = format!(
+            "cast(concat('0x', substr(to_hex(md5(array_to_string([{}], '|'))), 1, 8)) as int64)",
+            if values.is_empty() {
+                "''".to_string()
+            } else {
+                values.join(", ")
+            }
+        );
+        let (key_range, group_by) = match &spec.key_column {
+            Some(key_column) => {
+                let n = spec.range_size;
+                (
+                    format!("div(`{key_column}`, {n}) - if(mod(`{key_column}`, {n}) < 0, 1, 0)"),
+                    " group by 1",
+                )
+            }
+            None => ("0".to_string(), " having count(*) > 0"),
         };
+        let query = format!(
+            "select {key_range} as key_range, count(*) as row_count, coalesce(sum({hash}), 0) as checksum from `{project_id}.{dataset_id}.{table_name}`{group_by}",
+        );
+
+        let mut rs = self.query(query).await?;
+        let mut ranges = vec![];
+        while rs.next_row() {
+            let range = rs
+                .get_i64_by_name("key_range")?
+                .ok_or(QueryResultError::MissingColumn("key_range"))?;
+            let rows = rs
+                .get_i64_by_name("row_count")?
+                .ok_or(QueryResultError::MissingColumn("row_count"))?;
+            let checksum = rs
+                .get_i64_by_name("checksum")?
+                .ok_or(QueryResultError::MissingColumn("checksum"))?;
+            ranges.push(RangeChecksum {
+                range,
+                rows: rows as u64,
+                checksum: checksum as u64,
+            });
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns which of the keys are keys of rows of the table. A key is
+    /// the values of the key columns rendered as with
+    /// [`checksum_ranges`](BigQueryClient::checksum_ranges) and joined by
+    /// `|`.
+    pub async fn find_keys(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        key_columns: &[(String, HashedValue)],
+        keys: &[String],
+    ) -> Result<Vec<String>, QueryResultError> {
+        let project_id = &self.project_id;
+        let values: Vec<String> = key_columns
+            .iter()
+            .map(|(name, hashed_value)| hashed_value_expr(name, *hashed_value))
+            .collect();
+        let key = format!("array_to_string([{}], '|')", values.join(", "));
+
+        let mut found_keys = vec![];
+        // keeps queries well below BigQuery's maximum query length
+        for keys in keys.chunks(1000) {
+            let keys: Vec<String> = keys.iter().map(|key| quote_string(key)).collect();
+            let query = format!(
+                "select distinct {key} as found_key from `{project_id}.{dataset_id}.{table_name}` where {key} in ({})",
+                keys.join(", ")
+            );
+            let mut rs = self.query(query).await?;
+            while rs.next_row() {
+                let found_key = rs
+                    .get_string_by_name("found_key")?
+                    .ok_or(QueryResultError::MissingColumn("found_key"))?;
+                found_keys.push(found_key);
+            }
+        }
+
+        Ok(found_keys)
+    }
+
+    pub async fn get_column_names(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+    ) -> Result<HashSet<String>, QueryResultError> {
+        let project_id = &self.project_id;
+        let query = format!(
+            "select column_name from `{project_id}.{dataset_id}.INFORMATION_SCHEMA.COLUMNS` where table_name = '{table_name}'",
+        );
+
+        let mut rs = self.query(query).await?;
+        let mut column_names = HashSet::new();
+        while rs.next_row() {
+            let column_name = rs
+                .get_string_by_name("column_name")?
+                .ok_or(QueryResultError::MissingColumn("column_name"))?;
+            column_names.insert(column_name);
+        }
+
+        Ok(column_names)
+    }
+
+    /// Adds columns to a table. Added columns are always nullable
+    /// because BigQuery can't add required columns to existing tables.
+    pub async fn add_columns(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_schemas: &[&ColumnSchema],
+        type_mappings: &TypeMappings,
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        let add_columns: Vec<String> = column_schemas
+            .iter()
+            .map(|column_schema| {
+                let typ = match mapped_type(type_mappings, column_schema) {
+                    Some(bigquery_type) => bigquery_type.ddl_type(),
+                    None => Self::postgres_to_bigquery_type(&column_schema.typ),
+                };
+                format!("add column if not exists `{}` {typ}", column_schema.name)
+            })
+            .collect();
+        info!(
+            "adding columns to table {project_id}.{dataset_id}.{table_name} in bigquery: {}",
+            add_columns.join(", ")
+        );
+        let query = format!(
+            "alter table `{project_id}.{dataset_id}.{table_name}` {}",
+            add_columns.join(", ")
+        );
+
+        let _ = self.query(query).await?;
+
+        Ok(())
+    }
+
+    pub async fn drop_columns(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_names: &[&str],
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        info!("dropping columns {column_names:?} from table {project_id}.{dataset_id}.{table_name} in bigquery");
+        let drop_columns: Vec<String> = column_names
+            .iter()
+            .map(|column_name| format!("drop column if exists `{column_name}`"))
+            .collect();
+        let query = format!(
+            "alter table `{project_id}.{dataset_id}.{table_name}` {}",
+            drop_columns.join(", ")
+        );
+
+        let _ = self.query(query).await?;
+
+        Ok(())
+    }
+
+    pub async fn drop_not_null(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_names: &[&str],
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        let alter_columns: Vec<String> = column_names
+            .iter()
+            .map(|column_name| format!("alter column `{column_name}` drop not null"))
+            .collect();
+        let query = format!(
+            "alter table `{project_id}.{dataset_id}.{table_name}` {}",
+            alter_columns.join(", ")
+        );
+
+        let _ = self.query(query).await?;
+
+        Ok(())
+    }
 
-        Ok((lsn as u64).into())
+    /// Returns the last lsn of the row with the id, or `None` if the
+    /// `last_lsn` table has no such row or the row's lsn is null
+    pub async fn get_last_lsn(&self, dataset_id: &str, id: i64) -> Result<Option<PgLsn>, BQError> {
+        let project_id = &self.project_id;
+        let query =
+            format!("select lsn from `{project_id}.{dataset_id}.last_lsn` where id = {id}",);
+
+        let mut rs = self.query(query).await?;
+
+        if !rs.next_row() {
+            return Ok(None);
+        }
+
+        let lsn = rs.get_i64_by_name("lsn")?;
+        Ok(lsn.map(|lsn| (lsn as u64).into()))
     }
 
-    pub async fn set_last_lsn(&self, dataset_id: &str, lsn: PgLsn) -> Result<(), BQError> {
+    pub async fn set_last_lsn(&self, dataset_id: &str, id: i64, lsn: PgLsn) -> Result<(), BQError> {
         let lsn: u64 = lsn.into();
 
         let project_id = &self.project_id;
         let query =
-            format!("update `{project_id}.{dataset_id}.last_lsn` set lsn = {lsn} where id = 1",);
+            format!("update `{project_id}.{dataset_id}.last_lsn` set lsn = {lsn} where id = {id}",);
 
         let _ = self.query(query).await?;
 
         Ok(())
     }
 
-    pub async fn insert_last_lsn_row(&self, dataset_id: &str) -> Result<(), BQError> {
+    /// Inserts the initial row with the id into the `last_lsn` table unless
+    /// it already exists, so that it is safe to call again after a partial
+    /// bootstrap
+    pub async fn insert_last_lsn_row(&self, dataset_id: &str, id: i64) -> Result<(), BQError> {
         let project_id = &self.project_id;
-        let query =
-            format!("insert into `{project_id}.{dataset_id}.last_lsn` (id, lsn) values (1, 0)",);
+        let query = format!(
+            "insert into `{project_id}.{dataset_id}.last_lsn` (id, lsn)
+            select {id}, 0 from unnest([1])
+            where not exists (select 1 from `{project_id}.{dataset_id}.last_lsn` where id = {id})",
+        );
 
         let _ = self.query(query).await?;
 
@@ -314,230 +1101,286 @@ impl BigQueryClient {
         Ok(())
     }
 
-    pub async fn insert_row(
+    pub async fn delete_from_copied_tables(
         &self,
         dataset_id: &str,
-        table_name: &str,
-        table_row: &TableRow,
+        table_id: TableId,
     ) -> Result<(), BQError> {
-        let query = self.create_insert_row_query(dataset_id, table_name, table_row);
+        let project_id = &self.project_id;
+        let query = format!(
+            "delete from `{project_id}.{dataset_id}.copied_tables` where table_id = {table_id}",
+        );
 
         let _ = self.query(query).await?;
 
         Ok(())
     }
 
-    pub async fn stream_rows(
-        &mut self,
+    pub async fn get_name_mappings(
+        &self,
         dataset_id: &str,
-        table_name: String,
-        table_descriptor: &TableDescriptor,
-        mut table_rows: &[TableRow],
-    ) -> Result<(), BQError> {
-        let default_stream = StreamName::new_default(
-            self.project_id.clone(),
-            dataset_id.to_string(),
-            table_name.to_string(),
+    ) -> Result<Vec<NameMapping>, QueryResultError> {
+        let project_id = &self.project_id;
+        let query = format!(
+            "select source_table, source_column, bigquery_name from `{project_id}.{dataset_id}.name_mappings`",
         );
 
-        loop {
-            let (rows, num_processed_rows) = StorageApi::create_rows(table_descriptor, table_rows);
-            let trace_id = "pg_replicate bigquery client".to_string();
-            let mut response_stream = self
-                .client
-                .storage_mut()
-                .append_rows(&default_stream, rows, trace_id)
-                .await?;
-
-            if let Some(r) = response_stream.next().await {
-                let _ = r?;
-            }
-
-            table_rows = &table_rows[num_processed_rows..];
-            if table_rows.is_empty() {
-                break;
-            }
+        let mut rs = self.query(query).await?;
+        let mut name_mappings = vec![];
+        while rs.next_row() {
+            let source_table = rs
+                .get_string_by_name("source_table")?
+                .ok_or(QueryResultError::MissingColumn("source_table"))?;
+            let source_column = rs.get_string_by_name("source_column")?;
+            let bigquery_name = rs
+                .get_string_by_name("bigquery_name")?
+                .ok_or(QueryResultError::MissingColumn("bigquery_name"))?;
+            name_mappings.push(NameMapping {
+                source_table,
+                source_column,
+                bigquery_name,
+            });
         }
 
-        Ok(())
+        Ok(name_mappings)
     }
 
-    pub async fn insert_rows(
+    pub async fn insert_name_mappings(
         &self,
         dataset_id: &str,
-        table_name: &str,
-        insert_request: TableDataInsertAllRequest,
+        name_mappings: &[NameMapping],
     ) -> Result<(), BQError> {
-        self.client
-            .tabledata()
-            .insert_all(&self.project_id, dataset_id, table_name, insert_request)
-            .await?;
+        if name_mappings.is_empty() {
+            return Ok(());
+        }
+
+        let values: Vec<String> = name_mappings
+            .iter()
+            .map(|mapping| {
+                let source_column = match &mapping.source_column {
+                    Some(source_column) => Self::string_literal(source_column),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "({}, {source_column}, {})",
+                    Self::string_literal(&mapping.source_table),
+                    Self::string_literal(&mapping.bigquery_name)
+                )
+            })
+            .collect();
+
+        let project_id = &self.project_id;
+        let query = format!(
+            "insert into `{project_id}.{dataset_id}.name_mappings` (source_table, source_column, bigquery_name) values {}",
+            values.join(", ")
+        );
+
+        let _ = self.query(query).await?;
+
         Ok(())
     }
 
-    fn create_insert_row_query(
+    pub async fn get_compacted_lsns(
         &self,
         dataset_id: &str,
-        table_name: &str,
-        table_row: &TableRow,
-    ) -> String {
-        let mut s = String::new();
-
+    ) -> Result<HashMap<TableId, PgLsn>, QueryResultError> {
         let project_id = &self.project_id;
-        s.push_str(&format!(
-            "insert into `{project_id}.{dataset_id}.{table_name}`"
-        ));
-        s.push_str(" values(");
-
-        for (i, value) in table_row.values.iter().enumerate() {
-            Self::cell_to_query_value(value, &mut s);
+        let query =
+            format!("select table_id, lsn from `{project_id}.{dataset_id}.compacted_lsns`",);
 
-            if i < table_row.values.len() - 1 {
-                s.push(',');
-            }
+        let mut rs = self.query(query).await?;
+        let mut compacted_lsns = HashMap::new();
+        while rs.next_row() {
+            let table_id = rs
+                .get_i64_by_name("table_id")?
+                .ok_or(QueryResultError::MissingColumn("table_id"))?;
+            let lsn = rs
+                .get_i64_by_name("lsn")?
+                .ok_or(QueryResultError::MissingColumn("lsn"))?;
+            compacted_lsns.insert(table_id as TableId, (lsn as u64).into());
         }
 
-        s.push(')');
-
-        s
+        Ok(compacted_lsns)
     }
 
-    fn cell_to_query_value(cell: &Cell, s: &mut String) {
-        match cell {
-            Cell::Null => s.push_str("null"),
-            Cell::Bool(b) => s.push_str(&format!("{b}")),
-            Cell::String(str) => s.push_str(&format!("'{str}'")),
-            Cell::I16(i) => s.push_str(&format!("{i}")),
-            Cell::I32(i) => s.push_str(&format!("{i}")),
-            Cell::I64(i) => s.push_str(&format!("{i}")),
-            Cell::F32(i) => s.push_str(&format!("{i}")),
-            Cell::F64(i) => s.push_str(&format!("{i}")),
-            Cell::Numeric(n) => s.push_str(&format!("{n}")),
-            Cell::Date(t) => s.push_str(&format!("'{t}'")),
-            Cell::Time(t) => s.push_str(&format!("'{t}'")),
-            Cell::TimeStamp(t) => s.push_str(&format!("'{t}'")),
-            Cell::TimeStampTz(t) => s.push_str(&format!("'{t}'")),
-            Cell::Uuid(t) => s.push_str(&format!("'{t}'")),
-            Cell::Json(j) => s.push_str(&format!("'{j}'")),
-            Cell::U32(u) => s.push_str(&format!("{u}")),
-            Cell::Bytes(b) => {
-                let bytes: String = b.iter().map(|b| *b as char).collect();
-                s.push_str(&format!("b'{bytes}'"))
-            }
-            Cell::Array(_) => unreachable!(),
-        }
-    }
-
-    pub async fn update_row(
+    pub async fn set_compacted_lsn(
         &self,
         dataset_id: &str,
-        table_schema: &TableSchema,
-        table_row: &TableRow,
+        table_id: TableId,
+        lsn: PgLsn,
     ) -> Result<(), BQError> {
+        let lsn: u64 = lsn.into();
+
         let project_id = &self.project_id;
-        let table_name = &table_schema.table_name.name;
-        let table_name = &format!("`{project_id}.{dataset_id}.{table_name}`");
-        let column_schemas = &table_schema.column_schemas;
-        let query = Self::create_update_row_query(table_name, column_schemas, table_row);
+        let query = format!(
+            "merge `{project_id}.{dataset_id}.compacted_lsns` t
+            using (select {table_id} as table_id, {lsn} as lsn) s
+            on t.table_id = s.table_id
+            when matched then update set lsn = s.lsn
+            when not matched then insert (table_id, lsn) values (s.table_id, s.lsn)",
+        );
+
         let _ = self.query(query).await?;
+
         Ok(())
     }
 
-    fn create_update_row_query(
-        table_name: &str,
+    /// Merges the changes in a change log table with an lsn in
+    /// `(after_lsn, up_to_lsn]` into a snapshot table. Only the latest change
+    /// of each primary key is applied: deletes remove the row from the
+    /// snapshot, inserts and updates replace it. Merging the same range
+    /// again has no effect, so a merge can be retried.
+    pub async fn merge_change_log(
+        &self,
+        dataset_id: &str,
+        change_log_table_name: &str,
+        snapshot_table_name: &str,
         column_schemas: &[ColumnSchema],
-        table_row: &TableRow,
-    ) -> String {
-        let mut s = String::new();
-
-        s.push_str("update ");
-        s.push_str(table_name);
-        s.push_str(" set ");
+        after_lsn: Option<PgLsn>,
+        up_to_lsn: PgLsn,
+    ) -> Result<(), BQError> {
+        let quote = |name: &str| format!("`{name}`");
+        let key_columns: Vec<String> = column_schemas
+            .iter()
+            .filter(|c| c.primary)
+            .map(|c| quote(&c.name))
+            .collect();
+        let columns: Vec<String> = column_schemas.iter().map(|c| quote(&c.name)).collect();
+        let on_clause = key_columns
+            .iter()
+            .map(|k| format!("t.{k} = s.{k}"))
+            .collect::<Vec<_>>()
+            .join(" and ");
+        let update_list = column_schemas
+            .iter()
+            .filter(|c| !c.primary)
+            .map(|c| format!("{0} = s.{0}", quote(&c.name)))
+            .collect::<Vec<_>>();
+        let insert_values = columns
+            .iter()
+            .map(|c| format!("s.{c}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let change_type = quote(CHANGE_TYPE_COLUMN);
+        let lsn = quote(CHANGE_LSN_COLUMN);
+        let index = quote(CHANGE_INDEX_COLUMN);
+        let after_lsn = after_lsn.map(|lsn| u64::from(lsn) as i64).unwrap_or(-1);
+        let up_to_lsn = u64::from(up_to_lsn);
+
+        let update_clause = if update_list.is_empty() {
+            String::new()
+        } else {
+            format!("when matched then update set {}", update_list.join(", "))
+        };
 
-        let mut remove_comma = false;
+        let project_id = &self.project_id;
+        let query = format!(
+            "merge `{project_id}.{dataset_id}.{snapshot_table_name}` t
+            using (
+                select {}, {change_type}
+                from `{project_id}.{dataset_id}.{change_log_table_name}`
+                where {lsn} > {after_lsn} and {lsn} <= {up_to_lsn}
+                qualify row_number() over (partition by {} order by {lsn} desc, {index} desc) = 1
+            ) s
+            on {on_clause}
+            when matched and s.{change_type} = 'DELETE' then delete
+            {update_clause}
+            when not matched and s.{change_type} != 'DELETE' then insert ({}) values ({insert_values})",
+            columns.join(", "),
+            key_columns.join(", "),
+            columns.join(", "),
+        );
 
-        for (cell, column) in table_row.values.iter().zip(column_schemas) {
-            if !column.primary {
-                s.push_str(&column.name);
-                s.push_str(" = ");
-                Self::cell_to_query_value(cell, &mut s);
-                s.push(',');
-                remove_comma = true;
-            }
-        }
+        let _ = self.query(query).await?;
 
-        if remove_comma {
-            s.pop();
-        }
+        Ok(())
+    }
 
-        Self::add_identities_where_clause(&mut s, column_schemas, table_row);
+    /// Streams rows to the default stream of a table. Rows are split into
+    /// multiple append requests if they don't fit in a single request.
+    pub async fn stream_rows(
+        &mut self,
+        dataset_id: &str,
+        table_name: String,
+        table_descriptor: &TableDescriptor,
+        mut table_rows: &[TableRow],
+    ) -> Result<(), StreamRowsError> {
+        let default_stream = StreamName::new_default(
+            self.project_id.clone(),
+            dataset_id.to_string(),
+            table_name.to_string(),
+        );
 
-        s
-    }
+        let mut num_streamed_rows = 0;
+        while !table_rows.is_empty() {
+            let mut attempt = 0;
+            let num_processed_rows = loop {
+                match self
+                    .append_rows(&default_stream, table_descriptor, table_rows)
+                    .await
+                {
+                    Ok(num_processed_rows) => break num_processed_rows,
+                    Err(StreamRowsError::BigQuery(e))
+                        if attempt < self.retry_config.max_retries && is_retryable(&e) =>
+                    {
+                        let backoff = self.retry_config.backoff(attempt);
+                        warn!("retrying append rows in {backoff:?} after error: {e}");
+                        let table = TableName {
+                            schema: dataset_id.to_string(),
+                            name: table_name.to_string(),
+                        };
+                        self.report_retry(&e, Some(table), attempt);
+                        tokio::time::sleep(backoff).await;
+                        attempt += 1;
+                    }
+                    Err(StreamRowsError::RowErrors(row_errors)) => {
+                        // row indexes are relative to the failed request
+                        let row_errors = row_errors
+                            .into_iter()
+                            .map(|(index, message)| (index + num_streamed_rows as i64, message))
+                            .collect();
+                        return Err(StreamRowsError::RowErrors(row_errors));
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
 
-    /// Adds a where clause for the identity columns
-    fn add_identities_where_clause(
-        s: &mut String,
-        column_schemas: &[ColumnSchema],
-        table_row: &TableRow,
-    ) {
-        s.push_str(" where ");
-
-        let mut remove_and = false;
-        for (cell, column) in table_row.values.iter().zip(column_schemas) {
-            if column.primary {
-                s.push_str(&column.name);
-                s.push_str(" = ");
-                Self::cell_to_query_value(cell, s);
-                s.push_str(" and ");
-                remove_and = true;
+            // not even a single row fits in a request
+            if num_processed_rows == 0 {
+                return Err(StreamRowsError::RowTooLarge(num_streamed_rows));
             }
-        }
 
-        if remove_and {
-            s.pop(); //' '
-            s.pop(); //'d'
-            s.pop(); //'n'
-            s.pop(); //'a'
-            s.pop(); //' '
+            table_rows = &table_rows[num_processed_rows..];
+            num_streamed_rows += num_processed_rows;
         }
+
+        Ok(())
     }
 
-    pub async fn delete_row(
-        &self,
-        dataset_id: &str,
-        table_schema: &TableSchema,
-        table_row: &TableRow,
-    ) -> Result<(), BQError> {
+    pub async fn drop_table(&self, dataset_id: &str, table_name: &str) -> Result<(), BQError> {
         let project_id = &self.project_id;
-        let table_name = &table_schema.table_name.name;
-        let table_name = &format!("`{project_id}.{dataset_id}.{table_name}`");
-        let column_schemas = &table_schema.column_schemas;
-        let query = Self::create_delete_row_query(table_name, column_schemas, table_row);
+        info!("dropping table {project_id}.{dataset_id}.{table_name} in bigquery");
+        let query = format!("drop table `{project_id}.{dataset_id}.{table_name}`",);
+
         let _ = self.query(query).await?;
 
         Ok(())
     }
 
-    fn create_delete_row_query(
+    pub async fn rename_table(
+        &self,
+        dataset_id: &str,
         table_name: &str,
-        column_schemas: &[ColumnSchema],
-        table_row: &TableRow,
-    ) -> String {
-        let mut s = String::new();
-
-        s.push_str("delete from ");
-        s.push_str(table_name);
-
-        Self::add_identities_where_clause(&mut s, column_schemas, table_row);
-
-        s
-    }
-
-    pub async fn drop_table(&self, dataset_id: &str, table_name: &str) -> Result<(), BQError> {
+        new_table_name: &str,
+    ) -> Result<(), BQError> {
         let project_id = &self.project_id;
-        info!("dropping table {project_id}.{dataset_id}.{table_name} in bigquery");
-        let query = format!("drop table `{project_id}.{dataset_id}.{table_name}`",);
+        info!(
+            "renaming table {project_id}.{dataset_id}.{table_name} to {new_table_name} in bigquery"
+        );
+        let query = format!(
+            "alter table `{project_id}.{dataset_id}.{table_name}` rename to `{new_table_name}`",
+        );
 
         let _ = self.query(query).await?;
 
@@ -559,23 +1402,111 @@ impl BigQueryClient {
     pub async fn set_last_lsn_and_commit_transaction(
         &self,
         dataset_id: &str,
+        id: i64,
         last_lsn: PgLsn,
     ) -> Result<(), BQError> {
-        self.set_last_lsn(dataset_id, last_lsn).await?;
+        self.set_last_lsn(dataset_id, id, last_lsn).await?;
         self.commit_transaction().await?;
         Ok(())
     }
 
-    async fn query(&self, query: String) -> Result<ResultSet, BQError> {
-        let query_response = self
+    /// Appends rows to a stream and returns the number of appended rows,
+    /// which can be less than the number of rows passed in. All responses
+    /// are checked for errors, including errors of individual rows.
+    async fn append_rows(
+        &mut self,
+        stream_name: &StreamName,
+        table_descriptor: &TableDescriptor,
+        table_rows: &[TableRow],
+    ) -> Result<usize, StreamRowsError> {
+        let (rows, num_processed_rows) = StorageApi::create_rows(table_descriptor, table_rows);
+        let trace_id = "pg_replicate bigquery client".to_string();
+        let mut response_stream = self
             .client
-            .job()
-            .query(&self.project_id, QueryRequest::new(query))
+            .storage_mut()
+            .append_rows(stream_name, rows, trace_id)
             .await?;
-        Ok(ResultSet::new_from_query_response(query_response))
+
+        while let Some(response) = response_stream.next().await {
+            let response = response.map_err(BQError::from)?;
+
+            if !response.row_errors.is_empty() {
+                let row_errors = response
+                    .row_errors
+                    .into_iter()
+                    .map(|row_error| (row_error.index, row_error.message))
+                    .collect();
+                return Err(StreamRowsError::RowErrors(row_errors));
+            }
+
+            if let Some(Response::Error(status)) = response.response {
+                return Err(StreamRowsError::AppendFailed {
+                    code: status.code,
+                    message: status.message,
+                });
+            }
+        }
+
+        Ok(num_processed_rows)
+    }
+
+    async fn query(&self, query: String) -> Result<ResultSet, BQError> {
+        let mut attempt = 0;
+        loop {
+            match self
+                .client
+                .job()
+                .query(&self.project_id, QueryRequest::new(query.clone()))
+                .await
+            {
+                Ok(query_response) => {
+                    return Ok(ResultSet::new_from_query_response(query_response))
+                }
+                Err(e) if attempt < self.retry_config.max_retries && is_retryable(&e) => {
+                    let backoff = self.retry_config.backoff(attempt);
+                    warn!("retrying query in {backoff:?} after error: {e}");
+                    self.report_retry(&e, None, attempt);
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 }
 
+/// Returns an expression rendering the values of a column as text the same
+/// way as in Postgres, see [`HashedValue`]
+fn hashed_value_expr(column: &str, hashed_value: HashedValue) -> String {
+    match hashed_value {
+        HashedValue::Text => format!("cast(`{column}` as string)"),
+        HashedValue::Date => format!("cast(unix_date(`{column}`) as string)"),
+        HashedValue::Timestamp => format!("cast(unix_micros(`{column}`) as string)"),
+        HashedValue::Bytes => format!("to_hex(`{column}`)"),
+    }
+}
+
+fn quote_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn date_to_days_since_epoch(date: &NaiveDate) -> i32 {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("invalid unix epoch date");
+    date.signed_duration_since(epoch).num_days() as i32
+}
+
 impl Message for TableRow {
     fn encode_raw(&self, buf: &mut impl BufMut)
     where
@@ -649,20 +1580,20 @@ impl Cell {
                 ::prost::encoding::string::encode(tag, &s, buf);
             }
             Cell::Date(t) => {
-                let s = t.format("%Y-%m-%d").to_string();
-                ::prost::encoding::string::encode(tag, &s, buf);
+                let days = date_to_days_since_epoch(t);
+                ::prost::encoding::int32::encode(tag, &days, buf);
             }
             Cell::Time(t) => {
                 let s = t.format("%H:%M:%S%.f").to_string();
                 ::prost::encoding::string::encode(tag, &s, buf);
             }
             Cell::TimeStamp(t) => {
-                let s = t.format("%Y-%m-%d %H:%M:%S%.f").to_string();
-                ::prost::encoding::string::encode(tag, &s, buf);
+                let micros = t.and_utc().timestamp_micros();
+                ::prost::encoding::int64::encode(tag, &micros, buf);
             }
             Cell::TimeStampTz(t) => {
-                let s = t.format("%Y-%m-%d %H:%M:%S%.f%:z").to_string();
-                ::prost::encoding::string::encode(tag, &s, buf);
+                let micros = t.timestamp_micros();
+                ::prost::encoding::int64::encode(tag, &micros, buf);
             }
             Cell::Uuid(u) => {
                 let s = u.to_string();
@@ -702,20 +1633,20 @@ impl Cell {
                 ::prost::encoding::string::encoded_len(tag, &s)
             }
             Cell::Date(t) => {
-                let s = t.format("%Y-%m-%d").to_string();
-                ::prost::encoding::string::encoded_len(tag, &s)
+                let days = date_to_days_since_epoch(t);
+                ::prost::encoding::int32::encoded_len(tag, &days)
             }
             Cell::Time(t) => {
                 let s = t.format("%H:%M:%S%.f").to_string();
                 ::prost::encoding::string::encoded_len(tag, &s)
             }
             Cell::TimeStamp(t) => {
-                let s = t.format("%Y-%m-%d %H:%M:%S%.f").to_string();
-                ::prost::encoding::string::encoded_len(tag, &s)
+                let micros = t.and_utc().timestamp_micros();
+                ::prost::encoding::int64::encoded_len(tag, &micros)
             }
             Cell::TimeStampTz(t) => {
-                let s = t.format("%Y-%m-%d %H:%M:%S%.f%:z").to_string();
-                ::prost::encoding::string::encoded_len(tag, &s)
+                let micros = t.timestamp_micros();
+                ::prost::encoding::int64::encoded_len(tag, &micros)
             }
             Cell::Uuid(u) => {
                 let s = u.to_string();
@@ -806,12 +1737,12 @@ impl ArrayCell {
                 ::prost::encoding::string::encode_repeated(tag, &vec, buf);
             }
             ArrayCell::Date(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i32> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d").to_string())
+                    .flatten()
+                    .map(|v| date_to_days_since_epoch(&v))
                     .collect();
-                ::prost::encoding::string::encode_repeated(tag, &vec, buf);
+                ::prost::encoding::int32::encode_packed(tag, &vec, buf);
             }
             ArrayCell::Time(mut vec) => {
                 let vec: Vec<String> = vec
@@ -822,20 +1753,20 @@ impl ArrayCell {
                 ::prost::encoding::string::encode_repeated(tag, &vec, buf);
             }
             ArrayCell::TimeStamp(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i64> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d %H:%M:%S%.f").to_string())
+                    .flatten()
+                    .map(|v| v.and_utc().timestamp_micros())
                     .collect();
-                ::prost::encoding::string::encode_repeated(tag, &vec, buf);
+                ::prost::encoding::int64::encode_packed(tag, &vec, buf);
             }
             ArrayCell::TimeStampTz(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i64> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d %H:%M:%S%.f%:z").to_string())
+                    .flatten()
+                    .map(|v| v.timestamp_micros())
                     .collect();
-                ::prost::encoding::string::encode_repeated(tag, &vec, buf);
+                ::prost::encoding::int64::encode_packed(tag, &vec, buf);
             }
             ArrayCell::Uuid(mut vec) => {
                 let vec: Vec<String> = vec
@@ -908,12 +1839,12 @@ impl ArrayCell {
                 ::prost::encoding::string::encoded_len_repeated(tag, &vec)
             }
             ArrayCell::Date(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i32> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d").to_string())
+                    .flatten()
+                    .map(|v| date_to_days_since_epoch(&v))
                     .collect();
-                ::prost::encoding::string::encoded_len_repeated(tag, &vec)
+                ::prost::encoding::int32::encoded_len_packed(tag, &vec)
             }
             ArrayCell::Time(mut vec) => {
                 let vec: Vec<String> = vec
@@ -924,20 +1855,20 @@ impl ArrayCell {
                 ::prost::encoding::string::encoded_len_repeated(tag, &vec)
             }
             ArrayCell::TimeStamp(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i64> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d %H:%M:%S%.f").to_string())
+                    .flatten()
+                    .map(|v| v.and_utc().timestamp_micros())
                     .collect();
-                ::prost::encoding::string::encoded_len_repeated(tag, &vec)
+                ::prost::encoding::int64::encoded_len_packed(tag, &vec)
             }
             ArrayCell::TimeStampTz(mut vec) => {
-                let vec: Vec<String> = vec
+                let vec: Vec<i64> = vec
                     .drain(..)
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().format("%Y-%m-%d %H:%M:%S%.f%:z").to_string())
+                    .flatten()
+                    .map(|v| v.timestamp_micros())
                     .collect();
-                ::prost::encoding::string::encoded_len_repeated(tag, &vec)
+                ::prost::encoding::int64::encoded_len_packed(tag, &vec)
             }
             ArrayCell::Uuid(mut vec) => {
                 let vec: Vec<String> = vec
@@ -985,12 +1916,48 @@ impl ArrayCell {
     }
 }
 
-impl From<&TableSchema> for TableDescriptor {
-    fn from(table_schema: &TableSchema) -> Self {
-        let mut field_descriptors = Vec::with_capacity(table_schema.column_schemas.len());
-        let mut number = 1;
-        for column_schema in &table_schema.column_schemas {
-            let typ = match column_schema.typ {
+/// Returns the descriptor of a table's columns with the `_CHANGE_TYPE`
+/// and `_CHANGE_SEQUENCE_NUMBER` pseudo columns
+pub fn table_descriptor(
+    table_schema: &TableSchema,
+    type_mappings: &TypeMappings,
+) -> TableDescriptor {
+    let TableDescriptor {
+        mut field_descriptors,
+    } = append_only_table_descriptor(table_schema, type_mappings);
+    let mut number = field_descriptors.last().map_or(0, |f| f.number) + 1;
+
+    field_descriptors.push(FieldDescriptor {
+        number,
+        name: "_CHANGE_TYPE".to_string(),
+        typ: ColumnType::String,
+        mode: ColumnMode::Required,
+    });
+    number += 1;
+
+    field_descriptors.push(FieldDescriptor {
+        number,
+        name: "_CHANGE_SEQUENCE_NUMBER".to_string(),
+        typ: ColumnType::String,
+        mode: ColumnMode::Required,
+    });
+
+    TableDescriptor { field_descriptors }
+}
+
+/// Returns the descriptor of a table's columns without the `_CHANGE_TYPE` and
+/// `_CHANGE_SEQUENCE_NUMBER` pseudo columns, for tables whose rows are only
+/// appended
+pub fn append_only_table_descriptor(
+    table_schema: &TableSchema,
+    type_mappings: &TypeMappings,
+) -> TableDescriptor {
+    let mut field_descriptors = Vec::with_capacity(table_schema.column_schemas.len());
+    let mut number = 1;
+    for column_schema in &table_schema.column_schemas {
+        let typ = match mapped_type(type_mappings, column_schema) {
+            Some(bigquery_type) => bigquery_type.column_type(),
+            None => match column_schema.typ {
                 Type::BOOL => ColumnType::Bool,
                 Type::CHAR | Type::BPCHAR | Type::VARCHAR | Type::NAME | Type::TEXT => {
                     ColumnType::String
@@ -1000,11 +1967,15 @@ impl From<&TableSchema> for TableDescriptor {
                 Type::INT8 => ColumnType::Int64,
                 Type::FLOAT4 => ColumnType::Float,
                 Type::FLOAT8 => ColumnType::Double,
+                // numerics and json are sent as strings which BigQuery
+                // parses into the bignumeric and json columns
                 Type::NUMERIC => ColumnType::String,
-                Type::DATE => ColumnType::String,
+                // dates are sent as days and timestamps as microseconds
+                // since the unix epoch
+                Type::DATE => ColumnType::Int32,
                 Type::TIME => ColumnType::String,
-                Type::TIMESTAMP => ColumnType::String,
-                Type::TIMESTAMPTZ => ColumnType::String,
+                Type::TIMESTAMP => ColumnType::Int64,
+                Type::TIMESTAMPTZ => ColumnType::Int64,
                 Type::UUID => ColumnType::String,
                 Type::JSON => ColumnType::String,
                 Type::JSONB => ColumnType::String,
@@ -1022,65 +1993,58 @@ impl From<&TableSchema> for TableDescriptor {
                 Type::FLOAT4_ARRAY => ColumnType::Float,
                 Type::FLOAT8_ARRAY => ColumnType::Double,
                 Type::NUMERIC_ARRAY => ColumnType::String,
-                Type::DATE_ARRAY => ColumnType::String,
+                Type::DATE_ARRAY => ColumnType::Int32,
                 Type::TIME_ARRAY => ColumnType::String,
-                Type::TIMESTAMP_ARRAY => ColumnType::String,
-                Type::TIMESTAMPTZ_ARRAY => ColumnType::String,
+                Type::TIMESTAMP_ARRAY => ColumnType::Int64,
+                Type::TIMESTAMPTZ_ARRAY => ColumnType::Int64,
                 Type::UUID_ARRAY => ColumnType::String,
                 Type::JSON_ARRAY => ColumnType::String,
                 Type::JSONB_ARRAY => ColumnType::String,
                 Type::OID_ARRAY => ColumnType::Int32,
                 Type::BYTEA_ARRAY => ColumnType::Bytes,
                 _ => ColumnType::String,
-            };
+            },
+        };
 
-            let mode = match column_schema.typ {
-                Type::BOOL_ARRAY
-                | Type::CHAR_ARRAY
-                | Type::BPCHAR_ARRAY
-                | Type::VARCHAR_ARRAY
-                | Type::NAME_ARRAY
-                | Type::TEXT_ARRAY
-                | Type::INT2_ARRAY
-                | Type::INT4_ARRAY
-                | Type::INT8_ARRAY
-                | Type::FLOAT4_ARRAY
-                | Type::FLOAT8_ARRAY
-                | Type::NUMERIC_ARRAY
-                | Type::DATE_ARRAY
-                | Type::TIME_ARRAY
-                | Type::TIMESTAMP_ARRAY
-                | Type::TIMESTAMPTZ_ARRAY
-                | Type::UUID_ARRAY
-                | Type::JSON_ARRAY
-                | Type::JSONB_ARRAY
-                | Type::OID_ARRAY
-                | Type::BYTEA_ARRAY => ColumnMode::Repeated,
-                _ => {
-                    if column_schema.nullable {
-                        ColumnMode::Nullable
-                    } else {
-                        ColumnMode::Required
-                    }
+        let mode = match column_schema.typ {
+            Type::BOOL_ARRAY
+            | Type::CHAR_ARRAY
+            | Type::BPCHAR_ARRAY
+            | Type::VARCHAR_ARRAY
+            | Type::NAME_ARRAY
+            | Type::TEXT_ARRAY
+            | Type::INT2_ARRAY
+            | Type::INT4_ARRAY
+            | Type::INT8_ARRAY
+            | Type::FLOAT4_ARRAY
+            | Type::FLOAT8_ARRAY
+            | Type::NUMERIC_ARRAY
+            | Type::DATE_ARRAY
+            | Type::TIME_ARRAY
+            | Type::TIMESTAMP_ARRAY
+            | Type::TIMESTAMPTZ_ARRAY
+            | Type::UUID_ARRAY
+            | Type::JSON_ARRAY
+            | Type::JSONB_ARRAY
+            | Type::OID_ARRAY
+            | Type::BYTEA_ARRAY => ColumnMode::Repeated,
+            _ => {
+                if column_schema.nullable {
+                    ColumnMode::Nullable
+                } else {
+                    ColumnMode::Required
                 }
-            };
-
-            field_descriptors.push(FieldDescriptor {
-                number,
-                name: column_schema.name.clone(),
-                typ,
-                mode,
-            });
-            number += 1;
-        }
+            }
+        };
 
         field_descriptors.push(FieldDescriptor {
             number,
-            name: "_CHANGE_TYPE".to_string(),
-            typ: ColumnType::String,
-            mode: ColumnMode::Required,
+            name: column_schema.name.clone(),
+            typ,
+            mode,
         });
-
-        TableDescriptor { field_descriptors }
+        number += 1;
     }
+
+    TableDescriptor { field_descriptors }
 }
@@ -1,27 +1,344 @@
 use chrono::Timelike;
-use chrono::{NaiveDate, NaiveTime, Utc};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use deltalake::arrow::datatypes::{DataType as ArrowDataType, Field, Schema, TimeUnit};
+use deltalake::datafusion::common::Column;
 use deltalake::datafusion::execution::context::SessionContext;
-use deltalake::datafusion::prelude::col;
-use deltalake::open_table;
+use deltalake::datafusion::prelude::{col, lit, Expr};
 use deltalake::operations::create::CreateBuilder;
-use deltalake::{kernel::DataType, DeltaOps, DeltaTableError};
+use deltalake::operations::optimize::OptimizeType;
+use deltalake::operations::write::SchemaMode;
+use deltalake::parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use deltalake::parquet::file::properties::WriterProperties;
+use deltalake::{
+    kernel::{DataType, StructField},
+    DeltaOps, DeltaTableError, TableProperty,
+};
+use deltalake::{open_table_with_storage_options, DeltaTable};
 use std::{collections::HashMap, sync::Arc};
 use tokio_postgres::types::{PgLsn, Type};
+use tracing::warn;
 
 use crate::{
     conversions::{table_row::TableRow, Cell},
+    pipeline::sinks::{naming::NamingConvention, NamingStrategy},
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 use deltalake::arrow::array::{
-    Array, BooleanArray, Date32Array, Float32Array, Float64Array, Int32Array,
+    new_null_array, Array, BooleanArray, Date32Array, Float32Array, Float64Array, Int32Array,
     RecordBatch as DeltaRecordBatch, StringArray, TimestampMicrosecondArray, UInt32Array,
 };
+use deltalake::arrow::compute::{cast_with_options, CastOptions};
+use deltalake::arrow::error::ArrowError;
+
+/// A column a Delta table is partitioned by
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DeltaPartitionColumn {
+    /// Partition by the values of a column
+    Column(String),
+    /// Partition by the date of a date or timestamp column. The date is
+    /// written to an added `<column>_date` column.
+    Date(String),
+    /// Partition by the date of a date or timestamp column holding the time
+    /// of the rows' events, written to an added column with the given name,
+    /// e.g. `dt`
+    EventDate { column: String, name: String },
+    /// Partition by the hour of the day, from 0 to 23, of a timestamp column
+    /// holding the time of the rows' events, written to an added column with
+    /// the given name, e.g. `hour`
+    EventHour { column: String, name: String },
+}
+
+impl DeltaPartitionColumn {
+    /// Name of the column the table is partitioned by
+    pub fn partition_column_name(&self) -> String {
+        match self {
+            DeltaPartitionColumn::Column(column) => column.clone(),
+            DeltaPartitionColumn::Date(column) => format!("{column}_date"),
+            DeltaPartitionColumn::EventDate { name, .. }
+            | DeltaPartitionColumn::EventHour { name, .. } => name.clone(),
+        }
+    }
+
+    /// Type of the column added for the partition, none if the table is
+    /// partitioned by one of its columns
+    fn added_column_type(&self) -> Option<ArrowDataType> {
+        match self {
+            DeltaPartitionColumn::Column(_) => None,
+            DeltaPartitionColumn::Date(_) | DeltaPartitionColumn::EventDate { .. } => {
+                Some(ArrowDataType::Date32)
+            }
+            DeltaPartitionColumn::EventHour { .. } => Some(ArrowDataType::Int32),
+        }
+    }
+}
+
+/// What is done with rows whose event time is older than the allowed
+/// lateness when their change is committed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LateEventPolicy {
+    /// Write the rows to the partitions of their commit time instead, so
+    /// that closed partitions aren't written to
+    #[default]
+    CommitTime,
+    /// Drop the rows. Their changes are lost
+    Drop,
+}
+
+/// Handling of rows written long after the time of their events, by the
+/// time partition columns of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LateEvents {
+    /// How long after their event time rows are still written to the
+    /// partitions of their event time
+    pub max_lateness_secs: u64,
+
+    #[serde(default)]
+    pub policy: LateEventPolicy,
+}
+
+/// Options used when creating a missing table
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DeltaTableOptions {
+    /// Columns to partition the table by. Partitioning of existing
+    /// tables is not changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub partition_columns: Vec<DeltaPartitionColumn>,
+
+    /// Columns to Z-order the table's files by when it is optimized
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub z_order_columns: Vec<String>,
+
+    /// Handling of late rows by the time partition columns. Rows are
+    /// written to the partitions of their event time however late they are
+    /// if not set. Copied rows are never late
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub late_events: Option<LateEvents>,
+}
+
+/// Codec compressing the Parquet files of Delta tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompressionCodec {
+    Uncompressed,
+    #[default]
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+/// Compression of the Parquet files written to Delta tables, by appends,
+/// merges and optimizations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ParquetCompression {
+    #[serde(default)]
+    pub codec: CompressionCodec,
+
+    /// Level of the gzip (0 to 10) and zstd (1 to 22) codecs. The codec's
+    /// default level is used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<i32>,
+}
+
+impl ParquetCompression {
+    pub(crate) fn writer_properties(&self) -> Result<WriterProperties, DeltaTableError> {
+        let compression = match self.codec {
+            CompressionCodec::Uncompressed => Compression::UNCOMPRESSED,
+            CompressionCodec::Snappy => Compression::SNAPPY,
+            CompressionCodec::Gzip => Compression::GZIP(match self.level {
+                Some(level) => {
+                    let level = u32::try_from(level).map_err(|_| {
+                        DeltaTableError::Generic(format!("invalid gzip level {level}"))
+                    })?;
+                    GzipLevel::try_new(level)?
+                }
+                None => GzipLevel::default(),
+            }),
+            CompressionCodec::Zstd => Compression::ZSTD(match self.level {
+                Some(level) => ZstdLevel::try_new(level)?,
+                None => ZstdLevel::default(),
+            }),
+        };
+
+        Ok(WriterProperties::builder()
+            .set_compression(compression)
+            .build())
+    }
+}
+
+/// A Delta type columns of a Postgres type are written as instead of their
+/// default type. Values are cast to it, values which can't be cast are
+/// written as nulls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DeltaType {
+    String,
+    Integer,
+    Long,
+    Float,
+    Double,
+    /// Decimals of `precision` digits, at most 38, `scale` of them after
+    /// the decimal point, e.g. for numerics
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+    /// The bytes of the values' text, e.g. for uuids
+    Binary,
+}
+
+impl DeltaType {
+    fn delta_type(&self) -> Result<DataType, DeltaTableError> {
+        Ok(match self {
+            DeltaType::String => DataType::STRING,
+            DeltaType::Integer => DataType::INTEGER,
+            DeltaType::Long => DataType::LONG,
+            DeltaType::Float => DataType::FLOAT,
+            DeltaType::Double => DataType::DOUBLE,
+            DeltaType::Decimal { precision, scale } => DataType::decimal(*precision, *scale)
+                .map_err(|e| DeltaTableError::Generic(format!("invalid decimal type: {e}")))?,
+            DeltaType::Binary => DataType::BINARY,
+        })
+    }
+
+    fn arrow_type(&self) -> ArrowDataType {
+        match self {
+            DeltaType::String => ArrowDataType::Utf8,
+            DeltaType::Integer => ArrowDataType::Int32,
+            DeltaType::Long => ArrowDataType::Int64,
+            DeltaType::Float => ArrowDataType::Float32,
+            DeltaType::Double => ArrowDataType::Float64,
+            DeltaType::Decimal { precision, scale } => {
+                ArrowDataType::Decimal128(*precision, *scale as i8)
+            }
+            DeltaType::Binary => ArrowDataType::Binary,
+        }
+    }
+}
+
+/// Object store a Delta lake is stored in and the credentials to access it.
+/// Settings which aren't set are read from the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DeltaStorage {
+    /// Local file system
+    #[default]
+    Local,
+    /// Amazon S3 or an S3 compatible store like MinIO
+    S3 {
+        region: Option<String>,
+        /// Endpoint of S3 compatible stores
+        endpoint: Option<String>,
+        access_key_id: Option<String>,
+        secret_access_key: Option<String>,
+        session_token: Option<String>,
+        /// Role to assume with the credentials
+        assume_role_arn: Option<String>,
+        assume_role_session_name: Option<String>,
+        /// Allow plain http endpoints, e.g. a local MinIO
+        #[serde(default)]
+        allow_http: bool,
+    },
+    /// Azure Blob Storage or Data Lake Storage
+    Azure {
+        account_name: Option<String>,
+        access_key: Option<String>,
+        sas_token: Option<String>,
+    },
+    /// Google Cloud Storage
+    Gcs {
+        /// Path to a service account key file
+        service_account_path: Option<String>,
+        /// Contents of a service account key file
+        service_account_key: Option<String>,
+    },
+}
+
+impl DeltaStorage {
+    /// Returns the storage options understood by delta-rs
+    pub fn storage_options(&self) -> HashMap<String, String> {
+        let options: Vec<(&str, &Option<String>)> = match self {
+            DeltaStorage::Local => vec![],
+            DeltaStorage::S3 {
+                region,
+                endpoint,
+                access_key_id,
+                secret_access_key,
+                session_token,
+                assume_role_arn,
+                assume_role_session_name,
+                allow_http: _,
+            } => vec![
+                ("AWS_REGION", region),
+                ("AWS_ENDPOINT_URL", endpoint),
+                ("AWS_ACCESS_KEY_ID", access_key_id),
+                ("AWS_SECRET_ACCESS_KEY", secret_access_key),
+                ("AWS_SESSION_TOKEN", session_token),
+                ("AWS_IAM_ROLE_ARN", assume_role_arn),
+                ("AWS_IAM_ROLE_SESSION_NAME", assume_role_session_name),
+            ],
+            DeltaStorage::Azure {
+                account_name,
+                access_key,
+                sas_token,
+            } => vec![
+                ("AZURE_STORAGE_ACCOUNT_NAME", account_name),
+                ("AZURE_STORAGE_ACCOUNT_KEY", access_key),
+                ("AZURE_STORAGE_SAS_KEY", sas_token),
+            ],
+            DeltaStorage::Gcs {
+                service_account_path,
+                service_account_key,
+            } => vec![
+                ("GOOGLE_SERVICE_ACCOUNT", service_account_path),
+                ("GOOGLE_SERVICE_ACCOUNT_KEY", service_account_key),
+            ],
+        };
+
+        let mut storage_options: HashMap<String, String> = options
+            .into_iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| (key.to_string(), value.clone())))
+            .collect();
+
+        if let DeltaStorage::S3 {
+            allow_http: true, ..
+        } = self
+        {
+            storage_options.insert("AWS_ALLOW_HTTP".to_string(), "true".to_string());
+        }
+
+        storage_options
+    }
+}
+
+/// Registers the object stores enabled by the `delta-*` features, without
+/// which only local paths can be opened
+pub fn register_object_stores() {
+    #[cfg(feature = "delta-s3")]
+    deltalake::aws::register_handlers(None);
+    #[cfg(feature = "delta-azure")]
+    deltalake::azure::register_handlers(None);
+    #[cfg(feature = "delta-gcs")]
+    deltalake::gcp::register_handlers(None);
+}
 
 pub struct DeltaClient {
     pub path: String,
+    /// Passed to the object store of every table
+    pub storage_options: HashMap<String, String>,
     pub table_schemas: Option<HashMap<TableId, TableSchema>>,
     pub delta_schemas: Option<HashMap<String, Arc<Schema>>>,
+    /// Keyed by the Postgres table name in `schema.name` form
+    pub table_options: HashMap<String, DeltaTableOptions>,
+    /// Create tables with the change data feed enabled
+    pub change_data_feed: bool,
+    pub compression: ParquetCompression,
+    pub naming: NamingStrategy,
+    /// Names of the tables of Postgres tables in `schema.name` form, instead
+    /// of the names given by the naming strategy
+    pub table_names: HashMap<String, String>,
+    /// Types of the columns of Postgres types, keyed by the types' names,
+    /// instead of their default types
+    pub type_mappings: HashMap<String, DeltaType>,
+    /// Renames the tables and columns before they're named by the naming
+    /// strategy
+    pub naming_convention: Arc<dyn NamingConvention>,
 }
 
 impl DeltaClient {
@@ -66,9 +383,35 @@ impl DeltaClient {
         }
     }
 
+    /// Returns the Delta type of a column of a Postgres type, its mapped type
+    /// if it has one
+    fn delta_type(&self, typ: &Type) -> Result<DataType, DeltaTableError> {
+        match self.type_mappings.get(typ.name()) {
+            Some(delta_type) => delta_type.delta_type(),
+            None => Ok(Self::postgres_to_delta(typ)),
+        }
+    }
+
+    /// Returns the arrow type of a column of a Postgres type, its mapped type
+    /// if it has one
+    fn arrow_type(&self, typ: &Type) -> ArrowDataType {
+        match self.type_mappings.get(typ.name()) {
+            Some(delta_type) => delta_type.arrow_type(),
+            None => Self::postgres_to_arrow(typ),
+        }
+    }
+
+    async fn open_table(&self, uri: impl AsRef<str>) -> Result<DeltaTable, DeltaTableError> {
+        open_table_with_storage_options(uri, self.storage_options.clone()).await
+    }
+
+    async fn ops(&self, uri: impl AsRef<str>) -> Result<DeltaOps, DeltaTableError> {
+        DeltaOps::try_from_uri_with_storage_options(uri, self.storage_options.clone()).await
+    }
+
     pub async fn delta_table_exists(&self, table_name: &str) -> bool {
         let uri = self.delta_full_path(table_name);
-        open_table(uri).await.is_ok()
+        self.open_table(uri).await.is_ok()
     }
 
     pub async fn set_last_lsn(&self, lsn: PgLsn) -> Result<(), DeltaTableError> {
@@ -90,9 +433,10 @@ impl DeltaClient {
             DeltaRecordBatch::try_new(schema, vec![id_array, lsn_array, operation, inserted_at])?;
         let source = ctx.read_batch(batch)?;
 
-        let table = open_table(uri).await?;
+        let table = self.open_table(uri).await?;
         DeltaOps(table)
             .merge(source, col("target.id").eq(col("source.id")))
+            .with_writer_properties(self.compression.writer_properties()?)
             .with_source_alias("source")
             .with_target_alias("target")
             .when_matched_update(|update| {
@@ -108,7 +452,7 @@ impl DeltaClient {
 
     pub async fn get_last_lsn(&self) -> Result<PgLsn, DeltaTableError> {
         let uri = self.delta_full_path("last_lsn");
-        let table = open_table(uri).await?;
+        let table = self.open_table(uri).await?;
 
         let ctx = SessionContext::new();
         ctx.register_table("last_lsn", Arc::new(table))?;
@@ -145,42 +489,50 @@ impl DeltaClient {
         &mut self,
         table_name: &str,
         columns: &[ColumnSchema],
+        partition_columns: &[DeltaPartitionColumn],
     ) -> Result<Arc<Schema>, DeltaTableError> {
         let full_path = self.delta_full_path(table_name);
 
-        let table = DeltaOps::try_from_uri(&full_path)
+        let table = self
+            .ops(&full_path)
             .await?
             .create()
             .with_table_name(table_name);
 
-        let arrow_schema = Self::generate_schema(columns, table)?;
+        let arrow_schema = self.generate_schema(columns, partition_columns, table)?;
 
         Ok(arrow_schema)
     }
 
     fn generate_schema(
+        &self,
         columns: &[ColumnSchema],
+        partition_columns: &[DeltaPartitionColumn],
         table: CreateBuilder,
     ) -> Result<Arc<Schema>, DeltaTableError> {
-        let mut schema: Vec<Field> = vec![];
-
         let mut final_table = table;
 
         for column in columns {
             final_table = final_table.with_column(
-                column.name.as_str(),
-                Self::postgres_to_delta(&column.typ),
+                self.column_name_in_delta(&column.name),
+                self.delta_type(&column.typ)?,
                 false,
                 None,
             );
-
-            schema.push(Field::new(
-                column.name.as_str(),
-                Self::postgres_to_arrow(&column.typ),
-                true,
-            ));
         }
 
+        Ok(self.arrow_schema(columns, partition_columns))
+    }
+
+    /// Returns the schema of a table's rows, with the columns added by the
+    /// sink
+    pub fn arrow_schema(
+        &self,
+        columns: &[ColumnSchema],
+        partition_columns: &[DeltaPartitionColumn],
+    ) -> Arc<Schema> {
+        let mut schema = self.column_fields(columns);
+
         schema.push(Field::new("OP", ArrowDataType::Utf8, true));
         schema.push(Field::new(
             "pg_replicate_inserted_time",
@@ -188,7 +540,110 @@ impl DeltaClient {
             true,
         ));
 
-        Ok(Arc::new(Schema::new(schema)))
+        for partition_column in partition_columns {
+            if let Some(data_type) = partition_column.added_column_type() {
+                schema.push(Field::new(
+                    partition_column.partition_column_name(),
+                    data_type,
+                    true,
+                ));
+            }
+        }
+
+        Arc::new(Schema::new(schema))
+    }
+
+    /// Returns the schema of a table's columns alone, without the columns
+    /// added by the sink
+    pub fn columns_arrow_schema(&self, columns: &[ColumnSchema]) -> Arc<Schema> {
+        Arc::new(Schema::new(self.column_fields(columns)))
+    }
+
+    fn column_fields(&self, columns: &[ColumnSchema]) -> Vec<Field> {
+        columns
+            .iter()
+            .map(|column| {
+                Field::new(
+                    self.column_name_in_delta(&column.name),
+                    self.arrow_type(&column.typ),
+                    true,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the columns whose type maps to a different arrow type than
+    /// the type they were written with
+    pub fn changed_type_columns(
+        &self,
+        table_name: &str,
+        columns: &[ColumnSchema],
+    ) -> Result<Vec<String>, DeltaTableError> {
+        let delta_schema = self.get_delta_schema(table_name)?;
+        let changed_type_columns = columns
+            .iter()
+            .filter(|column| {
+                delta_schema
+                    .field_with_name(&self.column_name_in_delta(&column.name))
+                    .is_ok_and(|field| field.data_type() != &self.arrow_type(&column.typ))
+            })
+            .map(|column| self.column_name_in_delta(&column.name))
+            .collect();
+
+        Ok(changed_type_columns)
+    }
+
+    /// Evolves a table to a new list of columns. New columns are added to
+    /// the Delta table, columns which were removed are kept and written as
+    /// nulls, and existing columns keep their type, values of a column whose
+    /// type changed are cast to it.
+    pub async fn evolve_table(
+        &mut self,
+        table_name: &str,
+        columns: &[ColumnSchema],
+        partition_columns: &[DeltaPartitionColumn],
+    ) -> Result<(), DeltaTableError> {
+        let old_schema = self.get_delta_schema(table_name)?.clone();
+        let new_schema = self
+            .create_table(table_name, columns, partition_columns)
+            .await?;
+        let fields: Vec<Field> = new_schema
+            .fields()
+            .iter()
+            .map(|field| match old_schema.field_with_name(field.name()) {
+                Ok(old_field) => old_field.clone(),
+                Err(_) => field.as_ref().clone(),
+            })
+            .collect();
+
+        if self.delta_table_exists(table_name).await {
+            let table = self.open_table(self.delta_full_path(table_name)).await?;
+            let table_fields: Vec<String> = table
+                .get_schema()?
+                .fields()
+                .map(|field| field.name().clone())
+                .collect();
+            let added_fields: Vec<StructField> = columns
+                .iter()
+                .map(|column| (self.column_name_in_delta(&column.name), column))
+                .filter(|(name, _)| !table_fields.contains(name))
+                .map(|(name, column)| {
+                    Ok(StructField::new(name, self.delta_type(&column.typ)?, true))
+                })
+                .collect::<Result<_, DeltaTableError>>()?;
+            if !added_fields.is_empty() {
+                DeltaOps(table)
+                    .add_columns()
+                    .with_fields(added_fields)
+                    .await?;
+            }
+        }
+
+        self.delta_schemas
+            .get_or_insert_with(HashMap::new)
+            .insert(table_name.to_string(), Arc::new(Schema::new(fields)));
+
+        Ok(())
     }
 
     fn get_table_schema(&self, table_id: TableId) -> Result<&TableSchema, DeltaTableError> {
@@ -265,11 +720,25 @@ impl DeltaClient {
         }
     }
 
-    pub fn table_name_in_delta(table_name: &TableName) -> String {
-        format!("{}_{}", table_name.schema, table_name.name)
+    /// Path of the table relative to the Delta Lake's path
+    pub fn table_name_in_delta(&self, table_name: &TableName) -> String {
+        let destination = self.naming_convention.table_name(table_name);
+        let name = match self.table_names.get(&table_name.to_string()) {
+            Some(name) => name.clone(),
+            None => self.naming.table_name(&destination),
+        };
+        match self.naming {
+            NamingStrategy::SchemaPrefixed => name,
+            NamingStrategy::PerSchema => format!("{}/{name}", destination.schema),
+        }
     }
 
-    fn delta_full_path(&self, table_name: &str) -> String {
+    /// Name of the column of a source column in the Delta table
+    pub fn column_name_in_delta(&self, column_name: &str) -> String {
+        self.naming_convention.column_name(column_name)
+    }
+
+    pub fn delta_full_path(&self, table_name: &str) -> String {
         format!("{}/{}", self.path, table_name)
     }
 
@@ -327,7 +796,11 @@ impl DeltaClient {
         let batches = DeltaRecordBatch::try_new(delta_schema, arrow_vect)?;
         let data: Vec<DeltaRecordBatch> = vec![batches];
 
-        DeltaOps::try_from_uri(full_path).await?.write(data).await?;
+        self.ops(full_path)
+            .await?
+            .write(data)
+            .with_writer_properties(self.compression.writer_properties()?)
+            .await?;
 
         Ok(())
     }
@@ -339,7 +812,7 @@ impl DeltaClient {
         op: &str,
     ) -> Result<(), DeltaTableError> {
         let table_schema = self.get_table_schema(table_id)?;
-        let table_name = Self::table_name_in_delta(&table_schema.table_name);
+        let table_name = self.table_name_in_delta(&table_schema.table_name);
 
         let full_path = self.delta_full_path(&table_name);
 
@@ -363,7 +836,11 @@ impl DeltaClient {
 
         data.push(batches);
 
-        DeltaOps::try_from_uri(full_path).await?.write(data).await?;
+        self.ops(full_path)
+            .await?
+            .write(data)
+            .with_writer_properties(self.compression.writer_properties()?)
+            .await?;
 
         Ok(())
     }
@@ -374,26 +851,357 @@ impl DeltaClient {
     ) -> Result<(), DeltaTableError> {
         for (table_id, data) in rows_batch {
             let table_schema = self.get_table_schema(table_id)?;
-            let table_name = Self::table_name_in_delta(&table_schema.table_name);
+            let table_name = self.table_name_in_delta(&table_schema.table_name);
 
             let full_path = self.delta_full_path(&table_name);
             let delta_schema = self.get_delta_schema(&table_name)?; // Move schema retrieval outside the loop
 
-            let data: Vec<DeltaRecordBatch> = data
+            let data = self.rows_to_batches(data, table_schema, delta_schema)?;
+
+            self.append(table_schema, &table_name, full_path, data)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Merges rows into a table on its primary key. Only the latest row of
+    /// each key in `rows` is applied: rows with the `D` operation delete the
+    /// matching row, other rows update it or are inserted if there is none.
+    pub async fn merge_to_table(
+        &self,
+        table_id: TableId,
+        rows: Vec<TableRow>,
+    ) -> Result<(), DeltaTableError> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let table_name = self.table_name_in_delta(&table_schema.table_name);
+
+        let full_path = self.delta_full_path(&table_name);
+        let delta_schema = self.get_delta_schema(&table_name)?;
+
+        let key_columns: Vec<(usize, String)> = table_schema
+            .column_schemas
+            .iter()
+            .enumerate()
+            .filter(|(_, column_schema)| column_schema.primary)
+            .map(|(i, column_schema)| (i, self.column_name_in_delta(&column_schema.name)))
+            .collect();
+        let op_index = table_schema.column_schemas.len();
+
+        // a merge fails if multiple source rows match the same target row
+        let mut latest_rows: Vec<TableRow> = vec![];
+        let mut key_positions: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            let key: Vec<String> = key_columns
+                .iter()
+                .map(|(i, _)| format!("{:?}", row.values[*i]))
+                .collect();
+            match key_positions.get(&key.join(",")) {
+                Some(position) => latest_rows[*position] = row,
+                None => {
+                    key_positions.insert(key.join(","), latest_rows.len());
+                    latest_rows.push(row);
+                }
+            }
+        }
+
+        if !self.delta_table_exists(&table_name).await {
+            // an empty table has no rows to update or delete
+            let rows: Vec<TableRow> = latest_rows
                 .into_iter()
-                .map(|row| {
-                    let data = row.values;
-                    let arrow_vect: Vec<Arc<dyn Array>> =
-                        data.iter().map(|cell| self.cell_to_arrow(cell)).collect();
+                .filter(|row| !Self::is_delete(row, op_index))
+                .collect();
+            if !rows.is_empty() {
+                let data = self.rows_to_batches(rows, table_schema, delta_schema)?;
+                self.append(table_schema, &table_name, full_path, data)
+                    .await?;
+            }
+            return Ok(());
+        }
 
-                    // Return DeltaRecordBatch or propagate the error
-                    DeltaRecordBatch::try_new(delta_schema.clone(), arrow_vect)
+        if latest_rows.is_empty() {
+            return Ok(());
+        }
+
+        let ctx = SessionContext::new();
+        let source =
+            ctx.read_batches(self.rows_to_batches(latest_rows, table_schema, delta_schema)?)?;
+
+        let source_col = |name: &str| col(Column::new(Some("source"), name));
+        let target_col = |name: &str| col(Column::new(Some("target"), name));
+        let predicate = key_columns
+            .iter()
+            .map(|(_, name)| target_col(name).eq(source_col(name)))
+            .reduce(Expr::and)
+            .ok_or_else(|| {
+                DeltaTableError::Generic(format!("Table {table_name} has no primary key"))
+            })?;
+        let is_delete = source_col("OP").eq(lit("D"));
+        let column_names: Vec<String> = delta_schema
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+
+        let table = self.open_table(full_path).await?;
+        DeltaOps(table)
+            .merge(source, predicate)
+            .with_writer_properties(self.compression.writer_properties()?)
+            .with_source_alias("source")
+            .with_target_alias("target")
+            .when_matched_delete(|delete| delete.predicate(is_delete.clone()))?
+            .when_matched_update(|update| {
+                column_names.iter().fold(update, |update, name| {
+                    update.update(name.as_str(), source_col(name))
                 })
-                .collect::<Result<_, _>>()?; // Collect into Vec and handle errors
+            })?
+            .when_not_matched_insert(|insert| {
+                column_names
+                    .iter()
+                    .fold(insert.predicate(!is_delete.clone()), |insert, name| {
+                        insert.set(name.as_str(), source_col(name))
+                    })
+            })?
+            .await?;
 
-            DeltaOps::try_from_uri(full_path).await?.write(data).await?;
+        Ok(())
+    }
+
+    fn is_delete(row: &TableRow, op_index: usize) -> bool {
+        matches!(row.values.get(op_index), Some(Cell::String(op)) if op == "D")
+    }
+
+    /// Enables the change data feed of an existing table, if not enabled yet
+    pub async fn enable_change_data_feed(&self, table_name: &str) -> Result<(), DeltaTableError> {
+        let full_path = self.delta_full_path(table_name);
+        let table = self.open_table(full_path).await?;
+
+        let property = TableProperty::EnableChangeDataFeed.as_ref().to_string();
+        let enabled = table
+            .metadata()?
+            .configuration
+            .get(&property)
+            .is_some_and(|value| value.as_deref() == Some("true"));
+        if !enabled {
+            DeltaOps(table)
+                .set_tbl_properties()
+                .with_properties(HashMap::from([(property, "true".to_string())]))
+                .await?;
         }
 
         Ok(())
     }
+
+    /// Rewrites the small files of a table into larger ones, Z-ordered by
+    /// `z_order_columns` if not empty. Returns the number of files removed
+    /// and added.
+    pub async fn optimize_table(
+        &self,
+        table_name: &str,
+        z_order_columns: &[String],
+        target_file_size: Option<i64>,
+    ) -> Result<(u64, u64), DeltaTableError> {
+        let full_path = self.delta_full_path(table_name);
+        let table = self.open_table(full_path).await?;
+
+        let optimize_type = if z_order_columns.is_empty() {
+            OptimizeType::Compact
+        } else {
+            OptimizeType::ZOrder(z_order_columns.to_vec())
+        };
+        let mut optimize = DeltaOps(table)
+            .optimize()
+            .with_type(optimize_type)
+            .with_writer_properties(self.compression.writer_properties()?);
+        if let Some(target_file_size) = target_file_size {
+            optimize = optimize.with_target_size(target_file_size);
+        }
+        let (_, metrics) = optimize.await?;
+
+        Ok((metrics.num_files_removed, metrics.num_files_added))
+    }
+
+    /// Deletes files no longer referenced by versions of a table younger
+    /// than `retention_hours`. Returns the number of deleted files.
+    pub async fn vacuum_table(
+        &self,
+        table_name: &str,
+        retention_hours: u64,
+    ) -> Result<usize, DeltaTableError> {
+        let full_path = self.delta_full_path(table_name);
+        let table = self.open_table(full_path).await?;
+
+        // the retention is configured explicitly, so it is allowed to be
+        // shorter than the table's default of seven days
+        let (_, metrics) = DeltaOps(table)
+            .vacuum()
+            .with_retention_period(chrono::Duration::hours(retention_hours as i64))
+            .with_enforce_retention_duration(false)
+            .await?;
+
+        Ok(metrics.files_deleted.len())
+    }
+
+    /// Appends record batches to a table, creating it with the configured
+    /// partition columns if it doesn't exist
+    async fn append(
+        &self,
+        table_schema: &TableSchema,
+        table_name: &str,
+        full_path: String,
+        data: Vec<DeltaRecordBatch>,
+    ) -> Result<(), DeltaTableError> {
+        let partition_columns: Vec<String> = self
+            .partition_columns(table_schema)
+            .iter()
+            .map(DeltaPartitionColumn::partition_column_name)
+            .collect();
+        let create_options = !partition_columns.is_empty() || self.change_data_feed;
+        let creating = create_options && !self.delta_table_exists(table_name).await;
+
+        // merging the schemas allows writing batches without the columns
+        // which were removed from the source table
+        let mut write = self
+            .ops(full_path)
+            .await?
+            .write(data)
+            .with_writer_properties(self.compression.writer_properties()?)
+            .with_schema_mode(SchemaMode::Merge);
+        if creating {
+            write = write.with_partition_columns(partition_columns);
+            if self.change_data_feed {
+                write = write
+                    .with_configuration_property(TableProperty::EnableChangeDataFeed, Some("true"));
+            }
+        }
+        write.await?;
+
+        Ok(())
+    }
+
+    pub fn partition_columns(&self, table_schema: &TableSchema) -> &[DeltaPartitionColumn] {
+        self.table_options
+            .get(&table_schema.table_name.to_string())
+            .map(|table_options| table_options.partition_columns.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Appends the values derived for the table's time partition columns.
+    /// Returns false if the row is late and dropped
+    fn add_partition_values(&self, table_schema: &TableSchema, row: &mut TableRow) -> bool {
+        let late_events = self
+            .table_options
+            .get(&table_schema.table_name.to_string())
+            .and_then(|table_options| table_options.late_events);
+        let mut values = vec![];
+        for partition_column in self.partition_columns(table_schema) {
+            let column = match partition_column {
+                DeltaPartitionColumn::Column(_) => continue,
+                DeltaPartitionColumn::Date(column)
+                | DeltaPartitionColumn::EventDate { column, .. }
+                | DeltaPartitionColumn::EventHour { column, .. } => column,
+            };
+            let cell = table_schema
+                .column_schemas
+                .iter()
+                .position(|column_schema| &column_schema.name == column)
+                .and_then(|i| row.values.get(i));
+            let mut time = match cell {
+                Some(Cell::Date(date)) => Some(date.and_time(NaiveTime::MIN)),
+                Some(Cell::TimeStamp(timestamp)) => Some(*timestamp),
+                Some(Cell::TimeStampTz(timestamp)) => Some(timestamp.naive_utc()),
+                _ => None,
+            };
+            if let (Some(late_events), Some(event_time), Some(commit_timestamp)) =
+                (late_events, time, row.commit_timestamp)
+            {
+                let commit_time = commit_timestamp.naive_utc();
+                if Self::is_late(event_time, commit_time, &late_events) {
+                    match late_events.policy {
+                        LateEventPolicy::CommitTime => time = Some(commit_time),
+                        LateEventPolicy::Drop => return false,
+                    }
+                }
+            }
+            let value = match (partition_column, time) {
+                (_, None) => Cell::Null,
+                (DeltaPartitionColumn::EventHour { .. }, Some(time)) => {
+                    Cell::I32(time.hour() as i32)
+                }
+                (_, Some(time)) => Cell::Date(time.date()),
+            };
+            values.push(value);
+        }
+        row.values.extend(values);
+        true
+    }
+
+    fn is_late(
+        event_time: NaiveDateTime,
+        commit_time: NaiveDateTime,
+        late_events: &LateEvents,
+    ) -> bool {
+        (commit_time - event_time).num_seconds() > late_events.max_lateness_secs as i64
+    }
+
+    /// Converts rows to record batches of the table's schema, one per row
+    fn rows_to_batches(
+        &self,
+        rows: Vec<TableRow>,
+        table_schema: &TableSchema,
+        delta_schema: &Arc<Schema>,
+    ) -> Result<Vec<DeltaRecordBatch>, DeltaTableError> {
+        let mut late_rows = 0;
+        let rows: Vec<TableRow> = rows
+            .into_iter()
+            .filter_map(|mut row| {
+                if self.add_partition_values(table_schema, &mut row) {
+                    Some(row)
+                } else {
+                    late_rows += 1;
+                    None
+                }
+            })
+            .collect();
+        if late_rows > 0 {
+            warn!(
+                "dropped {late_rows} late rows of table {}",
+                table_schema.table_name
+            );
+        }
+
+        let batches = rows
+            .iter()
+            .map(|row| self.row_to_batch(&row.values, delta_schema))
+            .collect::<Result<_, _>>()?;
+
+        Ok(batches)
+    }
+
+    /// Converts the values of a row to a record batch of the schema, with
+    /// the values cast to the types of its fields
+    pub fn row_to_batch(
+        &self,
+        values: &[Cell],
+        schema: &Arc<Schema>,
+    ) -> Result<DeltaRecordBatch, ArrowError> {
+        let arrow_vect: Vec<Arc<dyn Array>> = values
+            .iter()
+            .zip(schema.fields())
+            .map(|(cell, field)| match cell {
+                // a null must have the column's type
+                Cell::Null => Ok(new_null_array(field.data_type(), 1)),
+                cell => {
+                    let array = self.cell_to_arrow(cell);
+                    if array.data_type() == field.data_type() {
+                        Ok(array)
+                    } else {
+                        // values which can't be cast become nulls
+                        cast_with_options(&array, field.data_type(), &CastOptions::default())
+                    }
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        DeltaRecordBatch::try_new(schema.clone(), arrow_vect)
+    }
 }
@@ -8,7 +8,8 @@ use duckdb::{
 use tokio_postgres::types::{PgLsn, Type};
 
 use crate::{
-    conversions::{table_row::TableRow, ArrayCell, Cell},
+    conversions::{json::cell_to_json, table_row::TableRow, ArrayCell, Cell},
+    pipeline::verification::HashedValue,
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
@@ -72,7 +73,7 @@ impl DuckDbClient {
     }
 
     pub fn create_schema(&self, schema_name: &str) -> Result<(), duckdb::Error> {
-        let query = format!("create schema {schema_name}");
+        let query = Self::create_schema_ddl(schema_name);
         self.conn.execute(&query, [])?;
         Ok(())
     }
@@ -163,18 +164,26 @@ impl DuckDbClient {
         s
     }
 
+    pub fn create_schema_ddl(schema_name: &str) -> String {
+        format!("create schema {schema_name}")
+    }
+
     pub fn create_table(
         &self,
         table_name: &TableName,
         column_schemas: &[ColumnSchema],
     ) -> Result<(), duckdb::Error> {
+        let query = Self::create_table_ddl(table_name, column_schemas);
+        self.conn.execute(&query, [])?;
+        Ok(())
+    }
+
+    pub fn create_table_ddl(table_name: &TableName, column_schemas: &[ColumnSchema]) -> String {
         let columns_spec = Self::create_columns_spec(column_schemas);
-        let query = format!(
+        format!(
             "create table {}.{} {}",
             table_name.schema, table_name.name, columns_spec
-        );
-        self.conn.execute(&query, [])?;
-        Ok(())
+        )
     }
 
     pub fn table_exists(&self, table_name: &TableName) -> Result<bool, duckdb::Error> {
@@ -199,6 +208,41 @@ impl DuckDbClient {
         Ok(())
     }
 
+    /// Returns the statement a row is inserted, updated or deleted with,
+    /// followed by its parameters as json, without running it
+    pub fn row_statement(
+        table_schema: &TableSchema,
+        table_row: &TableRow,
+        change_type: &str,
+    ) -> String {
+        let table_name = &table_schema.table_name;
+        let column_schemas = &table_schema.column_schemas;
+        let table_name = format!("{}.{}", table_name.schema, table_name.name);
+        let cells_of = |primary: bool| {
+            column_schemas
+                .iter()
+                .zip(table_row.values.iter())
+                .filter(move |(s, _)| s.primary == primary)
+                .map(|(_, c)| c)
+        };
+        let (query, params): (String, Vec<&Cell>) = match change_type {
+            "UPDATE" => (
+                Self::create_update_row_query(&table_name, column_schemas),
+                cells_of(false).chain(cells_of(true)).collect(),
+            ),
+            "DELETE" => (
+                Self::create_delete_row_query(&table_name, column_schemas),
+                cells_of(true).collect(),
+            ),
+            _ => (
+                Self::create_insert_row_query(&table_name, table_row.values.len()),
+                table_row.values.iter().collect(),
+            ),
+        };
+        let params: Vec<serde_json::Value> = params.into_iter().map(cell_to_json).collect();
+        format!("{query} {}", serde_json::Value::Array(params))
+    }
+
     fn create_insert_row_query(table_name: &str, column_count: usize) -> String {
         let mut s = String::new();
 
@@ -359,6 +403,56 @@ impl DuckDbClient {
         Ok(())
     }
 
+    pub fn count_rows(&self, table_name: &TableName) -> Result<u64, duckdb::Error> {
+        let query = format!(
+            "select count(*) from {}.{}",
+            table_name.schema, table_name.name
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let row_count = stmt.query_row::<u64, _, _>([], |r| r.get(0))?;
+        Ok(row_count)
+    }
+
+    /// Returns which of the keys are keys of rows of the table. A key is
+    /// the values of the key columns rendered as described by
+    /// [`HashedValue`] and joined by `|`.
+    pub fn find_keys(
+        &self,
+        table_name: &TableName,
+        key_columns: &[(String, HashedValue)],
+        keys: &[String],
+    ) -> Result<Vec<String>, duckdb::Error> {
+        let values: Vec<String> = key_columns
+            .iter()
+            .map(|(name, hashed_value)| {
+                let column = format!("\"{}\"", name.replace('"', "\"\""));
+                match hashed_value {
+                    HashedValue::Text => format!("cast({column} as varchar)"),
+                    HashedValue::Date => {
+                        format!("cast({column} - date '1970-01-01' as varchar)")
+                    }
+                    HashedValue::Timestamp => format!("cast(epoch_us({column}) as varchar)"),
+                    HashedValue::Bytes => format!("lower(hex({column}))"),
+                }
+            })
+            .collect();
+        let query = format!(
+            "select exists (select 1 from {}.{} where concat_ws('|', {}) = ?)",
+            table_name.schema,
+            table_name.name,
+            values.join(", ")
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let mut found_keys = vec![];
+        for key in keys {
+            let found = stmt.query_row::<bool, _, _>([key], |r| r.get(0))?;
+            if found {
+                found_keys.push(key.clone());
+            }
+        }
+        Ok(found_keys)
+    }
+
     pub fn truncate_table(&self, table_name: &TableName) -> Result<(), duckdb::Error> {
         let query = format!("delete from {}.{}", table_name.schema, table_name.name);
         let mut stmt = self.conn.prepare(&query)?;
@@ -366,6 +460,55 @@ impl DuckDbClient {
         Ok(())
     }
 
+    pub fn drop_table(&self, table_name: &TableName) -> Result<(), duckdb::Error> {
+        let query = format!(
+            "drop table if exists {}.{}",
+            table_name.schema, table_name.name
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        stmt.execute([])?;
+        Ok(())
+    }
+
+    pub fn rename_table(
+        &self,
+        table_name: &TableName,
+        new_table_name: &str,
+    ) -> Result<(), duckdb::Error> {
+        let query = format!(
+            "alter table {}.{} rename to {new_table_name}",
+            table_name.schema, table_name.name
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        stmt.execute([])?;
+        Ok(())
+    }
+
+    pub fn comment_on_table(
+        &self,
+        table_name: &TableName,
+        comment: &str,
+    ) -> Result<(), duckdb::Error> {
+        let query = format!(
+            "comment on table {}.{} is '{}'",
+            table_name.schema,
+            table_name.name,
+            comment.replace('\'', "''")
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        stmt.execute([])?;
+        Ok(())
+    }
+
+    pub fn delete_from_copied_tables(&self, table_id: TableId) -> Result<(), duckdb::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("delete from pg_replicate.copied_tables where table_id = ?")?;
+        stmt.execute([table_id])?;
+
+        Ok(())
+    }
+
     pub fn begin_transaction(&self) -> Result<(), duckdb::Error> {
         let mut stmt = self.conn.prepare("begin transaction")?;
         stmt.execute([])?;
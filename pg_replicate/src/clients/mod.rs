@@ -1,3 +1,4 @@
+pub mod auth;
 #[cfg(feature = "bigquery")]
 pub mod bigquery;
 #[cfg(feature = "delta")]
@@ -5,3 +6,5 @@ pub mod delta;
 #[cfg(feature = "duckdb")]
 pub mod duckdb;
 pub mod postgres;
+pub mod proxy;
+pub mod tls;
@@ -1,21 +1,477 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    time::Duration,
+};
 
+use chrono_tz::Tz;
 use pg_escape::{quote_identifier, quote_literal};
 use postgres_replication::LogicalReplicationStream;
 use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    task::JoinHandle,
+};
 use tokio_postgres::{
-    config::ReplicationMode,
+    config::{ReplicationMode, SslMode},
+    error::SqlState,
+    tls::MakeTlsConnect,
     types::{Kind, PgLsn, Type},
-    Client as PostgresClient, Config, CopyOutStream, NoTls, SimpleQueryMessage,
+    Client as PostgresClient, Config, Connection, CopyOutStream, NoTls, SimpleQueryMessage,
 };
 use tracing::{info, warn};
 
-use crate::table::{ColumnSchema, TableId, TableName, TableSchema};
+use crate::{
+    clients::{
+        auth::{AuthError, TokenAuth},
+        proxy::{Proxy, ProxyError, Tunnel},
+        tls::{ChannelBinding, TlsError, TlsMode, TlsOptions},
+    },
+    conversions::{
+        size::ValueSizeLimit, table_row::TableRow, text::TextFormatConverter, Cell,
+        ConversionPolicy,
+    },
+    pipeline::{
+        sources::postgres::glob_matches,
+        verification::{ChecksumSpec, HashedValue, RangeChecksum},
+    },
+    table::{ColumnSchema, TableId, TableName, TableSchema, TypeOverride},
+};
 
 pub struct SlotInfo {
     pub confirmed_flush_lsn: PgLsn,
 }
 
+/// Position of a replication slot and the lag of the replication using it
+pub struct SlotStatus {
+    pub confirmed_flush_lsn: PgLsn,
+    /// Whether a replication is streaming changes from the slot
+    pub active: bool,
+    /// Seconds between a change being written to the WAL and the active
+    /// replication flushing it, none without an active replication
+    pub flush_lag_secs: Option<f64>,
+}
+
+/// A replication slot of the server
+pub struct SlotDescription {
+    pub name: String,
+    /// Output plugin of a logical slot, none for a physical slot
+    pub plugin: Option<String>,
+    pub active: bool,
+    pub confirmed_flush_lsn: Option<PgLsn>,
+    /// Bytes of WAL the server keeps for the slot
+    pub retained_wal_bytes: Option<u64>,
+}
+
+/// Size of a table estimated from the planner's statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableSizeEstimate {
+    /// Rows of the table as of its last vacuum or analyze, none if it was
+    /// never analyzed
+    pub rows: Option<u64>,
+    /// Bytes the table and its toast table take on disk
+    pub bytes: u64,
+}
+
+/// Attributes of the connected role which allow it to replicate
+pub struct RoleAttributes {
+    pub superuser: bool,
+    pub replication: bool,
+}
+
+/// Which old values of a table's rows are logged for updates and deletes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaIdentity {
+    /// The primary key's columns, nothing if the table has no primary key
+    Default {
+        has_primary_key: bool,
+    },
+    Full,
+    Index,
+    Nothing,
+}
+
+/// Whether columns of a kind are replicated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ColumnHandling {
+    Skip,
+    Replicate,
+}
+
+/// How columns of types whose values aren't parsed, like enums, domains,
+/// intervals or types of extensions, are replicated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UnknownTypes {
+    /// Fail when reading the tables' schemas
+    Error,
+
+    /// Cast the columns to text in the source and replicate text columns
+    #[default]
+    Text,
+
+    /// Don't replicate the columns
+    Skip,
+
+    /// Keep the columns' types and pass their values on as the text
+    /// Postgres sends, unparsed
+    Bytes,
+}
+
+/// What happens to a row with a value which fails to convert, like text
+/// which isn't valid UTF-8 or a timestamp out of the range of timestamps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ConversionErrorPolicy {
+    /// Fail the pipeline
+    #[default]
+    Fail,
+
+    /// Replace the value with null, even in columns which aren't nullable
+    Null,
+
+    /// Write the row to the pipeline's dead letters instead of the sink
+    DeadLetter,
+
+    /// Coerce the value to the closest value of its type and log it, e.g.
+    /// invalid UTF-8 to its replacement characters or infinite timestamps
+    /// to the latest timestamp. Values with no closest value are nulled
+    Coerce,
+}
+
+/// How the values of `timestamp` columns, which have no time zone, are
+/// interpreted
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Timestamps {
+    /// Replicate them as they are, as timestamps without a time zone
+    #[default]
+    PassThrough,
+
+    /// Take them as UTC times and replicate them as `timestamptz` columns
+    Utc,
+
+    /// Take them as local times of a time zone of the tz database, like
+    /// `Europe/Paris`, and replicate them as `timestamptz` columns
+    TimeZone(String),
+}
+
+impl Timestamps {
+    /// Returns the time zone the values are converted from, none if they
+    /// don't need to be converted
+    pub fn time_zone(&self) -> Result<Option<Tz>, ReplicationClientError> {
+        match self {
+            Timestamps::PassThrough | Timestamps::Utc => Ok(None),
+            Timestamps::TimeZone(name) => name
+                .parse()
+                .map(Some)
+                .map_err(|_| ReplicationClientError::UnknownTimeZone(name.clone())),
+        }
+    }
+}
+
+/// Where the contents of large objects are written
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LargeObjectStore {
+    /// In their columns, which are replicated as `bytea` columns
+    #[default]
+    Inline,
+
+    /// To files named after their oids in a directory, e.g. a mounted
+    /// bucket. Their columns are replicated as text columns holding the
+    /// files' paths.
+    Directory(String),
+}
+
+/// Columns holding the oids of large objects whose contents are replicated.
+/// Logical decoding doesn't send the contents of large objects, so they are
+/// read when rows are copied or changes streamed, and reflect the objects
+/// at that time. Objects which don't exist anymore are replicated as nulls.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LargeObjectOptions {
+    /// Columns in `schema.table.column` form. Patterns may contain `*`
+    /// wildcards matching any number of characters.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+
+    pub store: LargeObjectStore,
+}
+
+impl LargeObjectOptions {
+    pub fn matches(&self, table_name: &TableName, column: &str) -> bool {
+        let column = format!("{table_name}.{column}");
+        self.columns
+            .iter()
+            .any(|pattern| glob_matches(pattern, &column))
+    }
+}
+
+/// A type columns are replicated as instead of their source type
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ColumnType {
+    /// Columns in `schema.table.column` form. Patterns may contain `*`
+    /// wildcards matching any number of characters.
+    pub column: String,
+
+    #[serde(rename = "type")]
+    pub typ: TypeOverride,
+}
+
+/// How columns whose values Postgres computes or whose types aren't
+/// supported are replicated
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ColumnOptions {
+    /// Stored generated columns, skipped by default. Logical replication
+    /// only sends their values from Postgres 18 on, for publications with
+    /// `publish_generated_columns = stored`
+    pub generated: ColumnHandling,
+
+    /// Identity and serial columns, replicated by default. Columns of the
+    /// primary key can't be skipped.
+    pub identity: ColumnHandling,
+
+    /// Columns of types whose values aren't parsed, cast to text by default
+    pub unknown_types: UnknownTypes,
+
+    /// How values of `timestamp` columns are interpreted, passed through by
+    /// default
+    pub timestamps: Timestamps,
+
+    /// Columns of large objects whose contents are replicated instead of
+    /// their oids, none by default
+    pub large_objects: LargeObjectOptions,
+
+    /// Types columns are replicated as instead of their source types. The
+    /// first override matching a column applies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<ColumnType>,
+
+    /// Name of a `timestamptz` column added to the replicated tables, which
+    /// holds the commit timestamp of the transaction of each change, none
+    /// by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_timestamp: Option<String>,
+
+    /// What happens to rows with values which fail to convert, the pipeline
+    /// fails by default
+    pub conversion_errors: ConversionErrorPolicy,
+
+    /// Limit on the size of text, bytea and json values, none by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value_size: Option<ValueSizeLimit>,
+}
+
+impl ColumnOptions {
+    /// Returns how the converters handle values which fail to convert or
+    /// are too large
+    pub fn conversion_policy(&self) -> ConversionPolicy {
+        ConversionPolicy {
+            errors: self.conversion_errors,
+            max_value_size: self.max_value_size.clone(),
+        }
+    }
+
+    /// Returns the type overriding the source type of a column
+    pub fn type_override(&self, table_name: &TableName, column: &str) -> Option<TypeOverride> {
+        let column = format!("{table_name}.{column}");
+        self.types
+            .iter()
+            .find(|column_type| glob_matches(&column_type.column, &column))
+            .map(|column_type| column_type.typ)
+    }
+}
+
+impl Default for ColumnOptions {
+    fn default() -> Self {
+        ColumnOptions {
+            generated: ColumnHandling::Skip,
+            identity: ColumnHandling::Replicate,
+            unknown_types: UnknownTypes::default(),
+            timestamps: Timestamps::default(),
+            large_objects: LargeObjectOptions::default(),
+            types: vec![],
+            commit_timestamp: None,
+            conversion_errors: ConversionErrorPolicy::default(),
+            max_value_size: None,
+        }
+    }
+}
+
+/// Options of the connections to the source
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ConnectionOptions {
+    /// Name of the connections shown in `pg_stat_activity`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_name: Option<String>,
+
+    /// `statement_timeout` of the transactions copying tables, in
+    /// milliseconds. The server's is used if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy_statement_timeout_ms: Option<u64>,
+
+    /// Runtime parameters set when connecting, like the `-c name=value`
+    /// settings of libpq's `options`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+
+    /// Proxy or SSH tunnel the connections go through, for sources only
+    /// reachable through a bastion host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Proxy>,
+
+    /// Tls of the connections, which are not encrypted by default
+    pub tls: TlsOptions,
+
+    /// Whether SCRAM authentication is bound to the tls session. Kerberos
+    /// and GSSAPI authentication aren't supported by the Postgres driver
+    pub channel_binding: ChannelBinding,
+
+    /// Token of a cloud's identity service authenticating the connections
+    /// instead of the password, e.g. for Azure AD or Cloud SQL IAM
+    /// authentication
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<TokenAuth>,
+
+    /// Whether the source is a hot standby decoding the changes, see
+    /// [`StandbyOptions`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub standby: Option<StandbyOptions>,
+
+    /// Seconds between the standby status updates telling the source the
+    /// position of the written changes, like `wal_receiver_status_interval`.
+    /// 10 if not set, with 0 updates are only sent when the source asks for
+    /// them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_interval_secs: Option<u64>,
+}
+
+/// Options of a source which is a hot standby of Postgres 16 or later, which
+/// decodes the changes instead of the primary. Both need `wal_level =
+/// logical`, and the standby should have `hot_standby_feedback = on`, or the
+/// primary may remove rows the slot still needs and invalidate it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StandbyOptions {
+    /// Host of the primary. Creating a slot on a standby waits until the
+    /// primary logs its running transactions, which it only does every few
+    /// minutes, so they are logged on the primary while the slot is created.
+    /// Needs the `pg_log_standby_snapshot` function to be executable by the
+    /// user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_host: Option<String>,
+
+    /// Port of the primary, the standby's if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_port: Option<u16>,
+
+    /// Whether the primary is replicated from when the standby can't be
+    /// connected to. The slot must exist on the primary too, it is advanced
+    /// to the standby's slot's position whenever the source connects to the
+    /// standby
+    pub failback: bool,
+}
+
+impl StandbyOptions {
+    /// Returns the host and port of the primary, if it is configured
+    pub fn primary(&self, standby_port: u16) -> Option<(&str, u16)> {
+        let primary_host = self.primary_host.as_deref()?;
+        Some((primary_host, self.primary_port.unwrap_or(standby_port)))
+    }
+}
+
+impl ConnectionOptions {
+    /// Interval of the standby status updates, none if they are only sent
+    /// when the source asks for them
+    pub fn status_interval(&self) -> Option<Duration> {
+        match self.status_interval_secs.unwrap_or(10) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    fn configure(&self, config: &mut Config) {
+        config
+            .ssl_mode(match self.tls.mode {
+                TlsMode::Disable => SslMode::Disable,
+                TlsMode::Require | TlsMode::VerifyFull => SslMode::Require,
+            })
+            .channel_binding(self.channel_binding.into());
+        if let Some(application_name) = &self.application_name {
+            config.application_name(application_name);
+        }
+        if !self.parameters.is_empty() {
+            // spaces and backslashes of the values are escaped like libpq
+            // expects them
+            let options: Vec<String> = self
+                .parameters
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.replace('\\', "\\\\").replace(' ', "\\ ");
+                    format!("-c {name}={value}")
+                })
+                .collect();
+            config.options(options.join(" "));
+        }
+    }
+}
+
+/// Connects to the database at `host:port` with `config`, directly or
+/// through the proxy of the options. The connection is driven by the
+/// returned task, which completes when the connection closes
+pub(crate) async fn connect(
+    mut config: Config,
+    host: &str,
+    port: u16,
+    options: &ConnectionOptions,
+    name: &'static str,
+) -> Result<(PostgresClient, JoinHandle<()>), ReplicationClientError> {
+    config.host(host).port(port);
+    options.configure(&mut config);
+    if let Some(auth) = &options.auth {
+        // tokens expire, so every connection gets a new one
+        config.password(auth.token().await?);
+    }
+    let tls = options.tls.connector()?;
+    let Some(proxy) = &options.proxy else {
+        return Ok(match tls {
+            None => spawn_connection(config.connect(NoTls).await?, None, name),
+            Some(tls) => spawn_connection(config.connect(tls).await?, None, name),
+        });
+    };
+
+    let (stream, tunnel) = proxy.connect(host, port).await?;
+    Ok(match tls {
+        None => spawn_connection(config.connect_raw(stream, NoTls).await?, tunnel, name),
+        Some(mut tls) => {
+            // the server's certificate is verified against the database's
+            // host rather than the proxy's
+            let tls = MakeTlsConnect::<TcpStream>::make_tls_connect(&mut tls, host)?;
+            spawn_connection(config.connect_raw(stream, tls).await?, tunnel, name)
+        }
+    })
+}
+
+/// Drives a connection in a task, keeping its tunnel open until the
+/// connection closes
+fn spawn_connection<S, T>(
+    (postgres_client, connection): (PostgresClient, Connection<S, T>),
+    tunnel: Option<Tunnel>,
+    name: &'static str,
+) -> (PostgresClient, JoinHandle<()>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let task = tokio::spawn(async move {
+        let _tunnel = tunnel;
+        if let Err(e) = connection.await {
+            warn!("{name} connection error: {e}");
+        }
+    });
+    (postgres_client, task)
+}
+
 /// A client for Postgres logical replication
 pub struct ReplicationClient {
     postgres_client: PostgresClient,
@@ -47,11 +503,110 @@ pub enum ReplicationClientError {
     #[error("table {0} doesn't exist")]
     MissingTable(TableName),
 
+    #[error("no table matches {0}")]
+    NoMatchingTable(String),
+
     #[error("not a valid PgLsn")]
     InvalidPgLsn,
 
     #[error("failed to create slot")]
     FailedToCreateSlot,
+
+    #[error("logical decoding on a standby needs Postgres 16 or later, the source runs {0}")]
+    StandbyDecodingUnsupported(String),
+
+    #[error("slot {0} was invalidated by a conflict with the standby's recovery, it must be dropped and the tables copied again. Set hot_standby_feedback = on on the standby to avoid it")]
+    SlotConflictsWithRecovery(String),
+
+    #[error("can't fail back to the primary, slot {0} doesn't exist on it")]
+    MissingFailbackSlot(String),
+
+    #[error("row count column is not a valid u64")]
+    RowCountNotU64,
+
+    #[error("size estimate of table {0} is not a valid number")]
+    InvalidTableSizeEstimate(TableId),
+
+    #[error("slot count column is not a valid u64")]
+    SlotCountNotU64,
+
+    #[error("checksum of table {0} is not a valid number")]
+    InvalidChecksum(TableName),
+
+    #[error("column {0} of table {1} is part of its primary key and can't be skipped")]
+    SkippedPrimaryKeyColumn(String, TableName),
+
+    #[error("generated columns can't be replicated, publication {0} doesn't publish them")]
+    GeneratedColumnsNotPublished(String),
+
+    #[error("time zone {0} isn't in the tz database")]
+    UnknownTimeZone(String),
+
+    #[error("column {0} of table {1} of type {2} can't hold large objects")]
+    NotALargeObjectColumn(String, TableName, Type),
+
+    #[error("column {0} of table {1} of type {2} can't be replicated as {3:?}")]
+    IncompatibleTypeOverride(String, TableName, Type, TypeOverride),
+
+    #[error("table {1} already has a column {0}, which can't hold the commit timestamps")]
+    CommitTimestampColumnExists(String, TableName),
+
+    #[error("proxy error: {0}")]
+    Proxy(#[from] ProxyError),
+
+    #[error("tls error: {0}")]
+    Tls(#[from] TlsError),
+
+    #[error("authentication token error: {0}")]
+    Auth(#[from] AuthError),
+
+    #[error("failed to write large object {0}: {1}")]
+    LargeObjectWrite(u32, std::io::Error),
+}
+
+impl ReplicationClientError {
+    /// Whether retrying may succeed, see [`is_transient`]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ReplicationClientError::TokioPostgresError(e) => is_transient(e),
+            _ => false,
+        }
+    }
+}
+
+/// Returns true for errors which are expected to go away when retried: lost
+/// connections, servers shutting down or refusing connections for now and
+/// transactions failing to serialize
+pub(crate) fn is_transient(e: &tokio_postgres::Error) -> bool {
+    let io_error = std::error::Error::source(e).is_some_and(|source| source.is::<std::io::Error>());
+    if e.is_closed() || io_error {
+        return true;
+    }
+    e.code().is_some_and(|code| {
+        [
+            SqlState::ADMIN_SHUTDOWN,
+            SqlState::CRASH_SHUTDOWN,
+            SqlState::CANNOT_CONNECT_NOW,
+            SqlState::TOO_MANY_CONNECTIONS,
+            SqlState::T_R_SERIALIZATION_FAILURE,
+            SqlState::T_R_DEADLOCK_DETECTED,
+        ]
+        .contains(code)
+    })
+}
+
+/// Returns an expression rendering the values of a column as text the same
+/// way in every database, see [`HashedValue`]
+pub(crate) fn hashed_value_expr(column: &str, hashed_value: HashedValue) -> String {
+    let column = quote_identifier(column);
+    match hashed_value {
+        HashedValue::Text => format!("{column}::text"),
+        HashedValue::Date => format!("({column} - date '1970-01-01')::text"),
+        HashedValue::Timestamp => {
+            format!("(extract(epoch from {column}) * 1000000)::bigint::text")
+        }
+        HashedValue::Bytes => format!("encode({column}, 'hex')"),
+    }
 }
 
 impl ReplicationClient {
@@ -62,13 +617,32 @@ impl ReplicationClient {
         database: &str,
         username: &str,
         password: Option<String>,
+    ) -> Result<ReplicationClient, ReplicationClientError> {
+        Self::connect_with_options(
+            host,
+            port,
+            database,
+            username,
+            password,
+            &ConnectionOptions::default(),
+        )
+        .await
+    }
+
+    /// Connect to a postgres database in logical replication mode with the
+    /// connection options, which choose whether TLS is used
+    pub async fn connect_with_options(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        options: &ConnectionOptions,
     ) -> Result<ReplicationClient, ReplicationClientError> {
         info!("connecting to postgres");
 
         let mut config = Config::new();
         config
-            .host(host)
-            .port(port)
             .dbname(database)
             .user(username)
             .replication_mode(ReplicationMode::Logical);
@@ -77,14 +651,7 @@ impl ReplicationClient {
             config.password(password);
         }
 
-        let (postgres_client, connection) = config.connect(NoTls).await?;
-
-        tokio::spawn(async move {
-            info!("waiting for connection to terminate");
-            if let Err(e) = connection.await {
-                warn!("connection error: {}", e);
-            }
-        });
+        let (postgres_client, _) = connect(config, host, port, options, "replication").await?;
 
         info!("successfully connected to postgres");
 
@@ -99,6 +666,17 @@ impl ReplicationClient {
         Ok(())
     }
 
+    /// Sets the `statement_timeout` of the current transaction
+    pub async fn set_local_statement_timeout(
+        &self,
+        timeout_ms: u64,
+    ) -> Result<(), ReplicationClientError> {
+        self.postgres_client
+            .simple_query(&format!("set local statement_timeout = {timeout_ms};"))
+            .await?;
+        Ok(())
+    }
+
     /// Commits a transaction
     pub async fn commit_txn(&self) -> Result<(), ReplicationClientError> {
         self.postgres_client.simple_query("commit;").await?;
@@ -110,13 +688,51 @@ impl ReplicationClient {
         Ok(())
     }
 
-    /// Returns a [CopyOutStream] for a table
+    /// Returns a [CopyOutStream] for the columns of a table. The columns are
+    /// selected because generated columns can't be listed in a copy. Text
+    /// columns are cast, as columns of unknown types are replicated as text.
     pub async fn get_table_copy_stream(
         &self,
         table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<CopyOutStream, ReplicationClientError> {
+        self.copy_out(table_name, column_schemas, None).await
+    }
+
+    /// Returns a [CopyOutStream] for the columns of up to `limit` rows of a
+    /// table, like [`get_table_copy_stream`](Self::get_table_copy_stream)
+    pub async fn get_table_sample_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+        limit: u32,
+    ) -> Result<CopyOutStream, ReplicationClientError> {
+        self.copy_out(table_name, column_schemas, Some(limit)).await
+    }
+
+    async fn copy_out(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+        limit: Option<u32>,
     ) -> Result<CopyOutStream, ReplicationClientError> {
+        let columns: Vec<String> = column_schemas
+            .iter()
+            .map(|column_schema| {
+                let column = quote_identifier(&column_schema.name);
+                if column_schema.typ == Type::TEXT {
+                    format!("{column}::text")
+                } else {
+                    column.to_string()
+                }
+            })
+            .collect();
+        let limit = limit
+            .map(|limit| format!(" limit {limit}"))
+            .unwrap_or_default();
         let copy_query = format!(
-            r#"COPY {} TO STDOUT WITH (FORMAT text);"#,
+            r#"COPY (select {} from {}{limit}) TO STDOUT WITH (FORMAT text);"#,
+            columns.join(", "),
             table_name.as_quoted_identifier()
         );
 
@@ -125,31 +741,162 @@ impl ReplicationClient {
         Ok(stream)
     }
 
-    /// Returns a vector of columns of a table
+    /// Returns the number of rows in a table
+    pub async fn count_table_rows(
+        &self,
+        table_name: &TableName,
+    ) -> Result<u64, ReplicationClientError> {
+        let count_query = format!(
+            "select count(*) as row_count from {};",
+            table_name.as_quoted_identifier()
+        );
+
+        for msg in self.postgres_client.simple_query(&count_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let row_count = row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "row_count".to_string(),
+                        table_name.to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::RowCountNotU64)?;
+                return Ok(row_count);
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// Returns the values of the columns of up to `limit` rows of the table,
+    /// as text
+    pub async fn sample_column_values(
+        &self,
+        table_name: &TableName,
+        columns: &[String],
+        limit: u32,
+    ) -> Result<Vec<Vec<Option<String>>>, ReplicationClientError> {
+        let columns_list: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{}::text", quote_identifier(column)))
+            .collect();
+        let sample_query = format!(
+            "select {} from {} limit {limit};",
+            columns_list.join(", "),
+            table_name.as_quoted_identifier()
+        );
+
+        let mut rows = vec![];
+        for msg in self.postgres_client.simple_query(&sample_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let values = (0..columns.len())
+                    .map(|i| row.get(i).map(ToString::to_string))
+                    .collect();
+                rows.push(values);
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Returns the row count and checksum of each range of the table's keys
+    /// which holds rows, hashing rows as described by `spec`
+    pub async fn checksum_ranges(
+        &self,
+        table_name: &TableName,
+        spec: &ChecksumSpec,
+    ) -> Result<Vec<RangeChecksum>, ReplicationClientError> {
+        let values: Vec<String> = spec
+            .columns
+            .iter()
+            .map(|(name, hashed_value)| {
+                format!(
+                    "coalesce({}, '\\N')",
+                    hashed_value_expr(name, *hashed_value)
+                )
+            })
+            .collect();
+        let hash = format!(
+            "('x' || left(md5(concat_ws('|', {})), 8))::bit(32)::bigint",
+            if values.is_empty() {
+                "''".to_string()
+            } else {
+                values.join(", ")
+            }
+        );
+        let (key_range, group_by) = match &spec.key_column {
+            Some(key_column) => {
+                let key = quote_identifier(key_column);
+                let n = spec.range_size;
+                (
+                    format!(
+                        "{key}::int8 / {n} - case when {key}::int8 % {n} < 0 then 1 else 0 end"
+                    ),
+                    " group by 1",
+                )
+            }
+            // an empty table has no ranges rather than an empty one
+            None => ("0::int8".to_string(), " having count(*) > 0"),
+        };
+        let checksum_query = format!(
+            "select {key_range} as key_range, count(*) as row_count, coalesce(sum({hash}), 0) as checksum from {}{group_by};",
+            table_name.as_quoted_identifier()
+        );
+
+        let mut ranges = vec![];
+        for msg in self.postgres_client.simple_query(&checksum_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let invalid = || ReplicationClientError::InvalidChecksum(table_name.clone());
+                let parse = |i: usize| -> Option<i128> { row.get(i)?.parse().ok() };
+                let range = parse(0).ok_or_else(invalid)?;
+                let rows = parse(1).ok_or_else(invalid)?;
+                let checksum = parse(2).ok_or_else(invalid)?;
+                ranges.push(RangeChecksum {
+                    range: range as i64,
+                    rows: rows as u64,
+                    checksum: checksum as u64,
+                });
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns the replicated columns of a table and the names of the columns
+    /// skipped according to `column_options`
     pub async fn get_column_schemas(
         &self,
+        table_name: &TableName,
         table_id: TableId,
-    ) -> Result<Vec<ColumnSchema>, ReplicationClientError> {
+        column_options: &ColumnOptions,
+    ) -> Result<(Vec<ColumnSchema>, Vec<String>), ReplicationClientError> {
         let column_info_query = format!(
             "select a.attname,
                 a.atttypid,
                 a.atttypmod,
                 a.attnotnull,
-                coalesce(i.indisprimary, false) as primary
+                coalesce(i.indisprimary, false) as primary,
+                a.attgenerated <> '' as generated,
+                a.attidentity <> ''
+                    or coalesce(pg_get_expr(d.adbin, d.adrelid) like 'nextval(%', false)
+                    as identity
             from pg_attribute a
             left join pg_index i
                 on a.attrelid = i.indrelid
                 and a.attnum = any(i.indkey)
                 and i.indisprimary = true
+            left join pg_attrdef d
+                on d.adrelid = a.attrelid
+                and d.adnum = a.attnum
             where a.attnum > 0::int2
             and not a.attisdropped
-            and a.attgenerated = ''
             and a.attrelid = {table_id}
             order by a.attnum
             ",
         );
 
         let mut column_schemas = vec![];
+        let mut skipped_columns = vec![];
 
         for message in self
             .postgres_client
@@ -207,6 +954,102 @@ impl ReplicationClient {
                         ))?
                         == "t";
 
+                let generated =
+                    row.try_get("generated")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "attgenerated".to_string(),
+                            "pg_attribute".to_string(),
+                        ))?
+                        == "t";
+
+                let identity =
+                    row.try_get("identity")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "attidentity".to_string(),
+                            "pg_attribute".to_string(),
+                        ))?
+                        == "t";
+
+                let handling = if generated {
+                    column_options.generated
+                } else if identity {
+                    column_options.identity
+                } else {
+                    ColumnHandling::Replicate
+                };
+                if handling == ColumnHandling::Skip {
+                    if primary {
+                        return Err(ReplicationClientError::SkippedPrimaryKeyColumn(
+                            name,
+                            table_name.clone(),
+                        ));
+                    }
+                    skipped_columns.push(name);
+                    continue;
+                }
+
+                let typ = if column_options.large_objects.matches(table_name, &name) {
+                    // oids of large objects are either oids or of a domain
+                    // over oid, like `lo`
+                    if typ != Type::OID && TextFormatConverter::is_supported(&typ) {
+                        return Err(ReplicationClientError::NotALargeObjectColumn(
+                            name,
+                            table_name.clone(),
+                            typ,
+                        ));
+                    }
+                    match column_options.large_objects.store {
+                        LargeObjectStore::Inline => Type::BYTEA,
+                        LargeObjectStore::Directory(_) => Type::TEXT,
+                    }
+                } else if let Some(type_override) = column_options.type_override(table_name, &name)
+                {
+                    if !type_override.applies_to(&typ) {
+                        return Err(ReplicationClientError::IncompatibleTypeOverride(
+                            name,
+                            table_name.clone(),
+                            typ,
+                            type_override,
+                        ));
+                    }
+                    type_override.typ()
+                } else if TextFormatConverter::is_supported(&typ) {
+                    typ
+                } else {
+                    match column_options.unknown_types {
+                        UnknownTypes::Error => {
+                            return Err(ReplicationClientError::UnsupportedType(
+                                name,
+                                type_oid,
+                                table_name.to_string(),
+                            ));
+                        }
+                        UnknownTypes::Text => {
+                            info!(
+                                "replicating column {name} of {table_name} of type {typ} as text"
+                            );
+                            Type::TEXT
+                        }
+                        UnknownTypes::Skip if primary => {
+                            return Err(ReplicationClientError::SkippedPrimaryKeyColumn(
+                                name,
+                                table_name.clone(),
+                            ));
+                        }
+                        UnknownTypes::Skip => {
+                            info!("skipping column {name} of {table_name} of type {typ}");
+                            skipped_columns.push(name);
+                            continue;
+                        }
+                        UnknownTypes::Bytes => {
+                            info!(
+                                "replicating column {name} of {table_name} of type {typ} unparsed"
+                            );
+                            typ
+                        }
+                    }
+                };
+
                 column_schemas.push(ColumnSchema {
                     name,
                     typ,
@@ -217,17 +1060,20 @@ impl ReplicationClient {
             }
         }
 
-        Ok(column_schemas)
+        Ok((column_schemas, skipped_columns))
     }
 
     pub async fn get_table_schemas(
         &self,
         table_names: &[TableName],
+        column_options: &ColumnOptions,
     ) -> Result<HashMap<TableId, TableSchema>, ReplicationClientError> {
         let mut table_schemas = HashMap::new();
 
         for table_name in table_names {
-            let table_schema = self.get_table_schema(table_name.clone()).await?;
+            let table_schema = self
+                .get_table_schema(table_name.clone(), column_options)
+                .await?;
             if !table_schema.has_primary_keys() {
                 warn!(
                     "table {} with id {} will not be copied because it has no primary key",
@@ -244,16 +1090,80 @@ impl ReplicationClient {
     async fn get_table_schema(
         &self,
         table_name: TableName,
+        column_options: &ColumnOptions,
     ) -> Result<TableSchema, ReplicationClientError> {
         let table_id = self
             .get_table_id(&table_name)
             .await?
             .ok_or(ReplicationClientError::MissingTable(table_name.clone()))?;
-        let column_schemas = self.get_column_schemas(table_id).await?;
+        let (mut column_schemas, skipped_columns) = self
+            .get_column_schemas(&table_name, table_id, column_options)
+            .await?;
+
+        let mut local_timestamp_columns = vec![];
+        if column_options.timestamps != Timestamps::PassThrough {
+            for column_schema in &mut column_schemas {
+                let typ = match column_schema.typ {
+                    Type::TIMESTAMP => Type::TIMESTAMPTZ,
+                    Type::TIMESTAMP_ARRAY => Type::TIMESTAMPTZ_ARRAY,
+                    _ => continue,
+                };
+                column_schema.typ = typ;
+                local_timestamp_columns.push(column_schema.name.clone());
+            }
+        }
+
+        let large_object_columns: Vec<String> = column_schemas
+            .iter()
+            .filter(|column_schema| {
+                column_options
+                    .large_objects
+                    .matches(&table_name, &column_schema.name)
+            })
+            .map(|column_schema| column_schema.name.clone())
+            .collect();
+        let type_overrides = column_schemas
+            .iter()
+            .filter_map(|column_schema| {
+                let name = &column_schema.name;
+                if large_object_columns.contains(name) {
+                    return None;
+                }
+                column_options
+                    .type_override(&table_name, name)
+                    .map(|type_override| (name.clone(), type_override))
+            })
+            .collect();
+
+        let commit_timestamp_column = column_options.commit_timestamp.clone();
+        if let Some(name) = &commit_timestamp_column {
+            if column_schemas
+                .iter()
+                .any(|column_schema| &column_schema.name == name)
+            {
+                return Err(ReplicationClientError::CommitTimestampColumnExists(
+                    name.clone(),
+                    table_name,
+                ));
+            }
+            column_schemas.push(ColumnSchema {
+                name: name.clone(),
+                typ: Type::TIMESTAMPTZ,
+                modifier: -1,
+                nullable: true,
+                primary: false,
+            });
+        }
+
         Ok(TableSchema {
             table_name,
             table_id,
             column_schemas,
+            skipped_columns,
+            local_timestamp_columns,
+            large_object_columns,
+            type_overrides,
+            commit_timestamp_column,
         })
     }
 
@@ -309,6 +1219,81 @@ impl ReplicationClient {
         Ok(None)
     }
 
+    /// Returns the name of the table with the id, none if there is no such
+    /// table
+    pub async fn get_table_name(
+        &self,
+        table_id: TableId,
+    ) -> Result<Option<TableName>, ReplicationClientError> {
+        let table_name_query = format!(
+            "select n.nspname, c.relname
+            from pg_class c
+            join pg_namespace n
+                on (c.relnamespace = n.oid)
+            where c.oid = {table_id}
+            "
+        );
+
+        for message in self.postgres_client.simple_query(&table_name_query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let schema = row
+                    .try_get("nspname")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "nspname".to_string(),
+                        "pg_namespace".to_string(),
+                    ))?
+                    .to_string();
+                let name = row
+                    .try_get("relname")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "relname".to_string(),
+                        "pg_class".to_string(),
+                    ))?
+                    .to_string();
+                return Ok(Some(TableName { schema, name }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the estimated size of the table with the id, none if there
+    /// is no such table. The estimate is as good as the table's statistics
+    pub async fn get_table_size_estimate(
+        &self,
+        table_id: TableId,
+    ) -> Result<Option<TableSizeEstimate>, ReplicationClientError> {
+        let size_query = format!(
+            "select c.reltuples::int8 as reltuples, pg_table_size(c.oid) as table_size
+            from pg_class c
+            where c.oid = {table_id}
+            "
+        );
+
+        for message in self.postgres_client.simple_query(&size_query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let parse = |column: &str| -> Result<i64, ReplicationClientError> {
+                    row.try_get(column)?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            column.to_string(),
+                            "pg_class".to_string(),
+                        ))?
+                        .parse()
+                        .map_err(|_| ReplicationClientError::InvalidTableSizeEstimate(table_id))
+                };
+                // reltuples is -1 for tables which were never analyzed
+                let rows = parse("reltuples")?;
+                let bytes = parse("table_size")?;
+                return Ok(Some(TableSizeEstimate {
+                    rows: (rows >= 0).then_some(rows as u64),
+                    bytes: bytes.max(0) as u64,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Returns the slot info of an existing slot. The slot info currently only has the
     /// confirmed_flush_lsn column of the pg_replication_slots table.
     async fn get_slot(&self, slot_name: &str) -> Result<Option<SlotInfo>, ReplicationClientError> {
@@ -424,26 +1409,522 @@ impl ReplicationClient {
         Ok(table_names)
     }
 
-    pub async fn publication_exists(
-        &self,
-        publication: &str,
-    ) -> Result<bool, ReplicationClientError> {
-        let publication_exists_query = format!(
-            "select 1 as exists from pg_publication where pubname = {};",
-            quote_literal(publication)
-        );
-        for msg in self
-            .postgres_client
-            .simple_query(&publication_exists_query)
-            .await?
-        {
-            if let SimpleQueryMessage::Row(_) = msg {
+    /// Returns the names of all tables outside of the system schemas
+    pub async fn get_table_names(&self) -> Result<Vec<TableName>, ReplicationClientError> {
+        let tables_query = "select schemaname, tablename from pg_tables
+            where schemaname not in ('pg_catalog', 'information_schema')
+            order by schemaname, tablename;";
+
+        let mut table_names = vec![];
+        for msg in self.postgres_client.simple_query(tables_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let schema = row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "schemaname".to_string(),
+                        "pg_tables".to_string(),
+                    ))?
+                    .to_string();
+
+                let name = row
+                    .get(1)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "tablename".to_string(),
+                        "pg_tables".to_string(),
+                    ))?
+                    .to_string();
+
+                table_names.push(TableName { schema, name })
+            }
+        }
+
+        Ok(table_names)
+    }
+
+    /// Returns the value of a server setting, e.g. `wal_level`
+    pub async fn get_setting(&self, name: &str) -> Result<Option<String>, ReplicationClientError> {
+        let setting_query = format!(
+            "select setting from pg_settings where name = {};",
+            quote_literal(name)
+        );
+
+        for msg in self.postgres_client.simple_query(&setting_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let setting = row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "setting".to_string(),
+                        "pg_settings".to_string(),
+                    ))?
+                    .to_string();
+                return Ok(Some(setting));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the version of the server as a number, e.g. 160002 for 16.2
+    pub async fn get_server_version_num(&self) -> Result<u32, ReplicationClientError> {
+        self.get_setting("server_version_num")
+            .await?
+            .and_then(|version| version.parse().ok())
+            .ok_or(ReplicationClientError::MissingColumn(
+                "server_version_num".to_string(),
+                "pg_settings".to_string(),
+            ))
+    }
+
+    /// Whether the server is a standby in recovery
+    pub async fn is_in_recovery(&self) -> Result<bool, ReplicationClientError> {
+        let query = "select pg_is_in_recovery() as in_recovery;";
+
+        for msg in self.postgres_client.simple_query(query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let in_recovery =
+                    row.try_get("in_recovery")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "in_recovery".to_string(),
+                            "pg_is_in_recovery".to_string(),
+                        ))?;
+                return Ok(in_recovery == "t");
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Whether the slot of a standby was invalidated by a conflict with its
+    /// recovery, e.g. because the primary removed rows the slot still needs
+    pub async fn slot_conflicts_with_recovery(
+        &self,
+        slot_name: &str,
+    ) -> Result<bool, ReplicationClientError> {
+        let query = format!(
+            "select conflicting from pg_replication_slots where slot_name = {};",
+            quote_literal(slot_name)
+        );
+
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                // null for physical slots
+                return Ok(row.try_get("conflicting")? == Some("t"));
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Logs the running transactions on the primary, which a standby waits
+    /// for to create a slot
+    pub async fn log_standby_snapshot(&self) -> Result<(), ReplicationClientError> {
+        self.postgres_client
+            .simple_query("select pg_log_standby_snapshot();")
+            .await?;
+        Ok(())
+    }
+
+    /// Advances the slot up to the position, it isn't moved backwards
+    pub async fn advance_slot(
+        &self,
+        slot_name: &str,
+        lsn: PgLsn,
+    ) -> Result<(), ReplicationClientError> {
+        let query = format!(
+            "select pg_replication_slot_advance({}, '{lsn}');",
+            quote_literal(slot_name)
+        );
+        self.postgres_client.simple_query(&query).await?;
+        Ok(())
+    }
+
+    /// Returns the attributes of the connected role
+    pub async fn get_role_attributes(&self) -> Result<RoleAttributes, ReplicationClientError> {
+        let role_query =
+            "select rolsuper, rolreplication from pg_roles where rolname = current_user;";
+
+        for msg in self.postgres_client.simple_query(role_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let superuser =
+                    row.try_get("rolsuper")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "rolsuper".to_string(),
+                            "pg_roles".to_string(),
+                        ))?;
+                let replication =
+                    row.try_get("rolreplication")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "rolreplication".to_string(),
+                            "pg_roles".to_string(),
+                        ))?;
+                return Ok(RoleAttributes {
+                    superuser: superuser == "t",
+                    replication: replication == "t",
+                });
+            }
+        }
+
+        Ok(RoleAttributes {
+            superuser: false,
+            replication: false,
+        })
+    }
+
+    /// Returns the number of replication slots in use, out of the server's
+    /// `max_replication_slots`
+    pub async fn count_replication_slots(&self) -> Result<u64, ReplicationClientError> {
+        let count_query = "select count(*) as slot_count from pg_replication_slots;";
+
+        for msg in self.postgres_client.simple_query(count_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let slot_count = row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "slot_count".to_string(),
+                        "pg_replication_slots".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::SlotCountNotU64)?;
+                return Ok(slot_count);
+            }
+        }
+
+        Ok(0)
+    }
+
+    pub async fn slot_exists(&self, slot_name: &str) -> Result<bool, ReplicationClientError> {
+        Ok(self.get_slot(slot_name).await?.is_some())
+    }
+
+    /// Returns the position of a slot and the lag of the replication using
+    /// it, none if the slot doesn't exist
+    pub async fn get_slot_status(
+        &self,
+        slot_name: &str,
+    ) -> Result<Option<SlotStatus>, ReplicationClientError> {
+        let query = format!(
+            "select s.confirmed_flush_lsn,
+                s.active,
+                extract(epoch from r.flush_lag) as flush_lag_secs
+            from pg_replication_slots s
+            left join pg_stat_replication r
+                on (r.pid = s.active_pid)
+            where s.slot_name = {};",
+            quote_literal(slot_name)
+        );
+
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let confirmed_flush_lsn = row
+                    .try_get("confirmed_flush_lsn")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "confirmed_flush_lsn".to_string(),
+                        "pg_replication_slots".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
+                let active =
+                    row.try_get("active")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "active".to_string(),
+                            "pg_replication_slots".to_string(),
+                        ))?;
+                // null while no replication is active or nothing was flushed
+                let flush_lag_secs = row
+                    .try_get("flush_lag_secs")?
+                    .and_then(|flush_lag_secs| flush_lag_secs.parse().ok());
+                return Ok(Some(SlotStatus {
+                    confirmed_flush_lsn,
+                    active: active == "t",
+                    flush_lag_secs,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the current write position of the WAL
+    pub async fn get_current_wal_lsn(&self) -> Result<PgLsn, ReplicationClientError> {
+        let query = "select pg_current_wal_lsn() as current_lsn;";
+
+        for msg in self.postgres_client.simple_query(query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                return row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "current_lsn".to_string(),
+                        "pg_current_wal_lsn".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn);
+            }
+        }
+
+        Err(ReplicationClientError::InvalidPgLsn)
+    }
+
+    /// Returns the replica identity of a table, none if the table doesn't
+    /// exist
+    pub async fn get_replica_identity(
+        &self,
+        table: &TableName,
+    ) -> Result<Option<ReplicaIdentity>, ReplicationClientError> {
+        let replica_identity_query = format!(
+            "select c.relreplident,
+                exists (
+                    select 1 from pg_index i
+                    where i.indrelid = c.oid and i.indisprimary
+                ) as has_primary_key
+            from pg_class c
+            join pg_namespace n
+                on (c.relnamespace = n.oid)
+            where n.nspname = {}
+                and c.relname = {}
+            ",
+            quote_literal(&table.schema),
+            quote_literal(&table.name)
+        );
+
+        for msg in self
+            .postgres_client
+            .simple_query(&replica_identity_query)
+            .await?
+        {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let replica_identity =
+                    row.try_get("relreplident")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "relreplident".to_string(),
+                            "pg_class".to_string(),
+                        ))?;
+                let has_primary_key = row.try_get("has_primary_key")?.ok_or(
+                    ReplicationClientError::MissingColumn(
+                        "has_primary_key".to_string(),
+                        "pg_index".to_string(),
+                    ),
+                )?;
+                let replica_identity = match replica_identity {
+                    "d" => ReplicaIdentity::Default {
+                        has_primary_key: has_primary_key == "t",
+                    },
+                    "f" => ReplicaIdentity::Full,
+                    "i" => ReplicaIdentity::Index,
+                    "n" => ReplicaIdentity::Nothing,
+                    other => {
+                        return Err(ReplicationClientError::ReplicaIdentityNotSupported(
+                            other.to_string(),
+                        ))
+                    }
+                };
+                return Ok(Some(replica_identity));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the names of all publications
+    pub async fn get_publication_names(&self) -> Result<Vec<String>, ReplicationClientError> {
+        let publications_query = "select pubname from pg_publication order by pubname;";
+
+        let mut publications = vec![];
+        for msg in self
+            .postgres_client
+            .simple_query(publications_query)
+            .await?
+        {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let publication = row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "pubname".to_string(),
+                        "pg_publication".to_string(),
+                    ))?
+                    .to_string();
+                publications.push(publication);
+            }
+        }
+
+        Ok(publications)
+    }
+
+    /// Creates a publication of the tables, or of all tables if none are
+    /// given
+    pub async fn create_publication(
+        &self,
+        publication: &str,
+        table_names: &[TableName],
+    ) -> Result<(), ReplicationClientError> {
+        let tables = if table_names.is_empty() {
+            "all tables".to_string()
+        } else {
+            let table_names: Vec<String> = table_names
+                .iter()
+                .map(|table_name| table_name.as_quoted_identifier())
+                .collect();
+            format!("table {}", table_names.join(", "))
+        };
+        let query = format!(
+            "create publication {} for {tables};",
+            quote_identifier(publication)
+        );
+        self.postgres_client.simple_query(&query).await?;
+        Ok(())
+    }
+
+    pub async fn add_publication_table(
+        &self,
+        publication: &str,
+        table_name: &TableName,
+    ) -> Result<(), ReplicationClientError> {
+        let query = format!(
+            "alter publication {} add table {};",
+            quote_identifier(publication),
+            table_name.as_quoted_identifier()
+        );
+        self.postgres_client.simple_query(&query).await?;
+        Ok(())
+    }
+
+    pub async fn remove_publication_table(
+        &self,
+        publication: &str,
+        table_name: &TableName,
+    ) -> Result<(), ReplicationClientError> {
+        let query = format!(
+            "alter publication {} drop table {};",
+            quote_identifier(publication),
+            table_name.as_quoted_identifier()
+        );
+        self.postgres_client.simple_query(&query).await?;
+        Ok(())
+    }
+
+    /// Creates a logical replication slot with the pgoutput plugin and
+    /// returns its consistent point. Unlike [ReplicationClient::get_or_create_slot]
+    /// no snapshot is exported, so it works outside of a transaction.
+    pub async fn create_slot_without_snapshot(
+        &self,
+        slot_name: &str,
+    ) -> Result<PgLsn, ReplicationClientError> {
+        let query = format!(
+            "select lsn from pg_create_logical_replication_slot({}, 'pgoutput');",
+            quote_literal(slot_name)
+        );
+
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                return row
+                    .get(0)
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "lsn".to_string(),
+                        "pg_create_logical_replication_slot".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn);
+            }
+        }
+
+        Err(ReplicationClientError::FailedToCreateSlot)
+    }
+
+    /// Drops a slot, which fails while a replication is using it
+    pub async fn drop_slot(&self, slot_name: &str) -> Result<(), ReplicationClientError> {
+        let query = format!(
+            "select pg_drop_replication_slot({});",
+            quote_literal(slot_name)
+        );
+        self.postgres_client.simple_query(&query).await?;
+        Ok(())
+    }
+
+    /// Returns all replication slots of the server
+    pub async fn get_slots(&self) -> Result<Vec<SlotDescription>, ReplicationClientError> {
+        let slots_query = "select slot_name,
+                plugin,
+                active,
+                confirmed_flush_lsn,
+                pg_wal_lsn_diff(pg_current_wal_lsn(), restart_lsn)::bigint as retained_wal_bytes
+            from pg_replication_slots
+            order by slot_name;";
+
+        let mut slots = vec![];
+        for msg in self.postgres_client.simple_query(slots_query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let name = row
+                    .try_get("slot_name")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "slot_name".to_string(),
+                        "pg_replication_slots".to_string(),
+                    ))?
+                    .to_string();
+                let active =
+                    row.try_get("active")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "active".to_string(),
+                            "pg_replication_slots".to_string(),
+                        ))?;
+                let confirmed_flush_lsn = row
+                    .try_get("confirmed_flush_lsn")?
+                    .map(|lsn| lsn.parse())
+                    .transpose()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
+                slots.push(SlotDescription {
+                    name,
+                    plugin: row.try_get("plugin")?.map(|plugin| plugin.to_string()),
+                    active: active == "t",
+                    confirmed_flush_lsn,
+                    retained_wal_bytes: row
+                        .try_get("retained_wal_bytes")?
+                        .and_then(|bytes| bytes.parse().ok()),
+                });
+            }
+        }
+
+        Ok(slots)
+    }
+
+    pub async fn publication_exists(
+        &self,
+        publication: &str,
+    ) -> Result<bool, ReplicationClientError> {
+        let publication_exists_query = format!(
+            "select 1 as exists from pg_publication where pubname = {};",
+            quote_literal(publication)
+        );
+        for msg in self
+            .postgres_client
+            .simple_query(&publication_exists_query)
+            .await?
+        {
+            if let SimpleQueryMessage::Row(_) = msg {
                 return Ok(true);
             }
         }
         Ok(false)
     }
 
+    /// Returns whether the publication sends the values of stored generated
+    /// columns, which only publications of Postgres 18 and later can
+    pub async fn publishes_generated_columns(
+        &self,
+        publication: &str,
+    ) -> Result<bool, ReplicationClientError> {
+        // pubgencols only exists from Postgres 18 on
+        let query = format!(
+            "select coalesce(to_jsonb(p) ->> 'pubgencols' in ('s', 't'), false) as published
+            from pg_publication p where pubname = {};",
+            quote_literal(publication)
+        );
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                return Ok(row.try_get("published")? == Some("t"));
+            }
+        }
+        Ok(false)
+    }
+
     pub async fn get_logical_replication_stream(
         &self,
         publication: &str,
@@ -472,3 +1953,79 @@ impl ReplicationClient {
         Ok(stream)
     }
 }
+
+/// Reads the large objects of rows over a connection of its own, as the
+/// replication connection is busy copying tables or streaming changes
+pub struct LargeObjectReader {
+    postgres_client: PostgresClient,
+    store: LargeObjectStore,
+}
+
+impl LargeObjectReader {
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        store: LargeObjectStore,
+        options: &ConnectionOptions,
+    ) -> Result<LargeObjectReader, ReplicationClientError> {
+        let mut config = Config::new();
+        config.dbname(database).user(username);
+        if let Some(password) = password {
+            config.password(password);
+        }
+
+        let (postgres_client, _) = connect(config, host, port, options, "large object").await?;
+
+        Ok(LargeObjectReader {
+            postgres_client,
+            store,
+        })
+    }
+
+    /// Returns the contents of a large object, none if it doesn't exist
+    async fn read(&self, oid: u32) -> Result<Option<Vec<u8>>, ReplicationClientError> {
+        let row = self
+            .postgres_client
+            .query_one(
+                "select case
+                    when exists (select from pg_largeobject_metadata where oid = $1)
+                    then lo_get($1)
+                end",
+                &[&oid],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Replaces the oids in the columns at `positions` of a row with the
+    /// contents of their large objects, or with the paths of the files the
+    /// contents were written to
+    pub async fn resolve(
+        &self,
+        row: &mut TableRow,
+        positions: &[usize],
+    ) -> Result<(), ReplicationClientError> {
+        for &i in positions {
+            let Some(Cell::U32(oid)) = row.values.get(i) else {
+                continue;
+            };
+            let oid = *oid;
+            let cell = match (self.read(oid).await?, &self.store) {
+                (None, _) => Cell::Null,
+                (Some(contents), LargeObjectStore::Inline) => Cell::Bytes(contents),
+                (Some(contents), LargeObjectStore::Directory(directory)) => {
+                    let path = Path::new(directory).join(oid.to_string());
+                    tokio::fs::write(&path, contents)
+                        .await
+                        .map_err(|e| ReplicationClientError::LargeObjectWrite(oid, e))?;
+                    Cell::String(path.to_string_lossy().into_owned())
+                }
+            };
+            row.values[i] = cell;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+//! Connections to the source through a proxy or an SSH tunnel, for
+//! databases which are only reachable through a bastion host.
+
+use std::{fmt::Debug, process::Stdio, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command},
+    time::{sleep, Instant},
+};
+
+/// How long an SSH tunnel may take to accept connections
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Time between two attempts to connect to a starting SSH tunnel
+const TUNNEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Largest response header accepted from an HTTP proxy
+const MAX_HTTP_RESPONSE_LEN: usize = 8192;
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("socks proxy error: {0}")]
+    Socks(String),
+
+    #[error("http proxy error: {0}")]
+    Http(String),
+
+    #[error("ssh tunnel error: {0}")]
+    Ssh(String),
+}
+
+/// A proxy or tunnel the connections to the source go through
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Proxy {
+    /// A SOCKS5 proxy, like one opened with `ssh -D`
+    Socks5 {
+        host: String,
+        port: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+
+    /// An HTTP proxy supporting the `CONNECT` method
+    Http {
+        host: String,
+        port: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+
+    /// A tunnel through a bastion host, opened with the `ssh` client for
+    /// each connection. The client authenticates with its agent or the
+    /// identity file, without prompting, and must know the bastion's host
+    /// key.
+    Ssh {
+        host: String,
+        #[serde(default = "default_ssh_port")]
+        port: u16,
+        user: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        identity_file: Option<String>,
+    },
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+impl Debug for Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proxy::Socks5 {
+                host,
+                port,
+                username,
+                password: _,
+            } => f
+                .debug_struct("Socks5")
+                .field("host", host)
+                .field("port", port)
+                .field("username", username)
+                .field("password", &"REDACTED")
+                .finish(),
+            Proxy::Http {
+                host,
+                port,
+                username,
+                password: _,
+            } => f
+                .debug_struct("Http")
+                .field("host", host)
+                .field("port", port)
+                .field("username", username)
+                .field("password", &"REDACTED")
+                .finish(),
+            Proxy::Ssh {
+                host,
+                port,
+                user,
+                identity_file,
+            } => f
+                .debug_struct("Ssh")
+                .field("host", host)
+                .field("port", port)
+                .field("user", user)
+                .field("identity_file", identity_file)
+                .finish(),
+        }
+    }
+}
+
+impl Proxy {
+    /// Opens a stream to `host:port` through the proxy. The tunnel, if the
+    /// proxy opened one, must be kept until the stream is closed.
+    pub async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<(TcpStream, Option<Tunnel>), ProxyError> {
+        match self {
+            Proxy::Socks5 {
+                host: proxy_host,
+                port: proxy_port,
+                username,
+                password,
+            } => {
+                let mut stream = TcpStream::connect((proxy_host.as_str(), *proxy_port)).await?;
+                let credentials = username
+                    .as_deref()
+                    .map(|username| (username, password.as_deref().unwrap_or_default()));
+                socks5_connect(&mut stream, host, port, credentials).await?;
+                Ok((stream, None))
+            }
+            Proxy::Http {
+                host: proxy_host,
+                port: proxy_port,
+                username,
+                password,
+            } => {
+                let mut stream = TcpStream::connect((proxy_host.as_str(), *proxy_port)).await?;
+                let credentials = username
+                    .as_deref()
+                    .map(|username| (username, password.as_deref().unwrap_or_default()));
+                http_connect(&mut stream, host, port, credentials).await?;
+                Ok((stream, None))
+            }
+            Proxy::Ssh {
+                host: bastion,
+                port: ssh_port,
+                user,
+                identity_file,
+            } => {
+                let (stream, tunnel) = Tunnel::open(
+                    bastion,
+                    *ssh_port,
+                    user,
+                    identity_file.as_deref(),
+                    host,
+                    port,
+                )
+                .await?;
+                Ok((stream, Some(tunnel)))
+            }
+        }
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<(), ProxyError> {
+    const VERSION: u8 = 5;
+    const NO_AUTHENTICATION: u8 = 0;
+    const USERNAME_PASSWORD: u8 = 2;
+    const CONNECT: u8 = 1;
+    const DOMAIN_NAME: u8 = 3;
+
+    let method = if credentials.is_some() {
+        USERNAME_PASSWORD
+    } else {
+        NO_AUTHENTICATION
+    };
+    stream.write_all(&[VERSION, 1, method]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [VERSION, method] {
+        return Err(ProxyError::Socks(
+            "the proxy doesn't accept the authentication method".to_string(),
+        ));
+    }
+
+    if let Some((username, password)) = credentials {
+        let mut request = vec![1, socks5_len(username, "username")?];
+        request.extend_from_slice(username.as_bytes());
+        request.push(socks5_len(password, "password")?);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(ProxyError::Socks("authentication failed".to_string()));
+        }
+    }
+
+    let mut request = vec![
+        VERSION,
+        CONNECT,
+        0,
+        DOMAIN_NAME,
+        socks5_len(host, "host name")?,
+    ];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(ProxyError::Socks(format!(
+            "connecting to {host}:{port} failed with reply {}",
+            reply[1]
+        )));
+    }
+    // the address the proxy connected from, which isn't needed
+    let address_len = match reply[3] {
+        1 => 4,
+        3 => stream.read_u8().await? as usize,
+        4 => 16,
+        address_type => {
+            return Err(ProxyError::Socks(format!(
+                "unknown address type {address_type}"
+            )))
+        }
+    };
+    let mut address = vec![0; address_len + 2];
+    stream.read_exact(&mut address).await?;
+    Ok(())
+}
+
+fn socks5_len(value: &str, name: &str) -> Result<u8, ProxyError> {
+    u8::try_from(value.len())
+        .map_err(|_| ProxyError::Socks(format!("the {name} is longer than 255 bytes")))
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<(), ProxyError> {
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some((username, password)) = credentials {
+        let encoded = STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {encoded}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // the response is read byte by byte so that nothing sent after it by
+    // the database is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE_LEN {
+            return Err(ProxyError::Http("response header too long".to_string()));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1);
+    if status != Some("200") {
+        return Err(ProxyError::Http(format!(
+            "connecting to {host}:{port} failed: {status_line}"
+        )));
+    }
+    Ok(())
+}
+
+/// An `ssh` process forwarding a local port to the database through a
+/// bastion host. The process is killed when the tunnel is dropped.
+pub struct Tunnel {
+    _process: Child,
+}
+
+impl Tunnel {
+    async fn open(
+        bastion: &str,
+        ssh_port: u16,
+        user: &str,
+        identity_file: Option<&str>,
+        host: &str,
+        port: u16,
+    ) -> Result<(TcpStream, Tunnel), ProxyError> {
+        // a free local port, which the ssh client listens on once this
+        // listener is closed
+        let local_port = TcpListener::bind(("127.0.0.1", 0))
+            .await?
+            .local_addr()?
+            .port();
+
+        let mut command = Command::new("ssh");
+        command
+            .arg("-N")
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ExitOnForwardFailure=yes"])
+            .args(["-p", &ssh_port.to_string()])
+            .args(["-L", &format!("127.0.0.1:{local_port}:{host}:{port}")]);
+        if let Some(identity_file) = identity_file {
+            command.args(["-i", identity_file]);
+        }
+        command
+            .arg(format!("{user}@{bastion}"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .kill_on_drop(true);
+        let mut process = command
+            .spawn()
+            .map_err(|e| ProxyError::Ssh(format!("failed to start ssh: {e}")))?;
+
+        let deadline = Instant::now() + TUNNEL_TIMEOUT;
+        loop {
+            if let Some(status) = process.try_wait()? {
+                return Err(ProxyError::Ssh(format!(
+                    "ssh to {user}@{bastion} exited with {status}"
+                )));
+            }
+            match TcpStream::connect(("127.0.0.1", local_port)).await {
+                Ok(stream) => return Ok((stream, Tunnel { _process: process })),
+                Err(e) if Instant::now() >= deadline => {
+                    return Err(ProxyError::Ssh(format!(
+                        "the tunnel through {bastion} didn't open in time: {e}"
+                    )))
+                }
+                Err(_) => sleep(TUNNEL_POLL_INTERVAL).await,
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Tls connections to the source with rustls. The connections provide the
+//! `tls-server-end-point` channel binding to SCRAM authentication, which
+//! ties the authentication to the tls session so that a man in the middle
+//! can't relay it.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use aws_lc_rs::digest::{self, SHA256, SHA384, SHA512};
+use pin_project_lite::pin_project;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::tls::{self, MakeTlsConnect, TlsConnect, TlsStream};
+use tokio_rustls::TlsConnector;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("rustls error: {0}")]
+    Rustls(#[from] rustls::Error),
+
+    #[error("failed to read the root certificates in {0}: {1}")]
+    RootCert(String, String),
+
+    #[error("no trusted root certificates were found, set the tls root_cert")]
+    NoRootCertificates,
+
+    #[error("{0} is not a valid server name")]
+    InvalidServerName(String),
+}
+
+/// Whether the connections to the source are encrypted and how the server
+/// is authenticated, like libpq's `sslmode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TlsMode {
+    /// Connects without tls
+    #[default]
+    Disable,
+
+    /// Encrypts the connections without verifying the server's certificate.
+    /// Only channel binding then protects the authentication from a man in
+    /// the middle
+    Require,
+
+    /// Verifies that the server's certificate was issued by a trusted
+    /// authority for the host connected to
+    VerifyFull,
+}
+
+/// Tls of the connections to the source
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TlsOptions {
+    pub mode: TlsMode,
+
+    /// Pem file of the certificates of the authorities trusted to issue the
+    /// server's certificate. The system's trusted certificates are used if
+    /// not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_cert: Option<String>,
+}
+
+/// Whether SCRAM authentication is bound to the tls session, like libpq's
+/// `channel_binding`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ChannelBinding {
+    /// Never binds the authentication
+    Disable,
+
+    /// Binds the authentication if the connection uses tls and the server
+    /// supports it
+    #[default]
+    Prefer,
+
+    /// Fails to connect unless the authentication is bound, which requires
+    /// tls and SCRAM authentication
+    Require,
+}
+
+impl From<ChannelBinding> for tokio_postgres::config::ChannelBinding {
+    fn from(channel_binding: ChannelBinding) -> Self {
+        match channel_binding {
+            ChannelBinding::Disable => tokio_postgres::config::ChannelBinding::Disable,
+            ChannelBinding::Prefer => tokio_postgres::config::ChannelBinding::Prefer,
+            ChannelBinding::Require => tokio_postgres::config::ChannelBinding::Require,
+        }
+    }
+}
+
+impl TlsOptions {
+    /// The connector of tls connections, none if tls is disabled
+    pub(crate) fn connector(&self) -> Result<Option<MakeRustlsConnect>, TlsError> {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let config = match self.mode {
+            TlsMode::Disable => return Ok(None),
+            TlsMode::Require => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoServerVerification { provider }))
+                .with_no_client_auth(),
+            TlsMode::VerifyFull => builder
+                .with_root_certificates(self.root_certificates()?)
+                .with_no_client_auth(),
+        };
+        Ok(Some(MakeRustlsConnect {
+            config: Arc::new(config),
+        }))
+    }
+
+    fn root_certificates(&self) -> Result<RootCertStore, TlsError> {
+        let mut roots = RootCertStore::empty();
+        match &self.root_cert {
+            Some(path) => {
+                let certificates = CertificateDer::pem_file_iter(path)
+                    .map_err(|e| TlsError::RootCert(path.clone(), e.to_string()))?;
+                for certificate in certificates {
+                    let certificate =
+                        certificate.map_err(|e| TlsError::RootCert(path.clone(), e.to_string()))?;
+                    roots.add(certificate)?;
+                }
+            }
+            None => {
+                // unreadable certificates of the system are skipped
+                let native = rustls_native_certs::load_native_certs();
+                roots.add_parsable_certificates(native.certs);
+            }
+        }
+        if roots.is_empty() {
+            return Err(TlsError::NoRootCertificates);
+        }
+        Ok(roots)
+    }
+}
+
+/// Accepts any certificate of the server, while still checking that the
+/// server holds its key
+#[derive(Debug)]
+struct NoServerVerification {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for NoServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Makes the tls connectors of the connections to a host
+#[derive(Clone)]
+pub struct MakeRustlsConnect {
+    config: Arc<ClientConfig>,
+}
+
+impl<S> MakeTlsConnect<S> for MakeRustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type TlsConnect = RustlsConnect;
+    type Error = TlsError;
+
+    fn make_tls_connect(&mut self, domain: &str) -> Result<RustlsConnect, TlsError> {
+        let server_name = ServerName::try_from(domain.to_string())
+            .map_err(|_| TlsError::InvalidServerName(domain.to_string()))?;
+        Ok(RustlsConnect {
+            connector: TlsConnector::from(self.config.clone()),
+            server_name,
+        })
+    }
+}
+
+/// Negotiates tls over a connection to a host
+pub struct RustlsConnect {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl<S> TlsConnect<S> for RustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<RustlsStream<S>>> + Send>>;
+
+    fn connect(self, stream: S) -> Self::Future {
+        Box::pin(async move {
+            let inner = self.connector.connect(self.server_name, stream).await?;
+            Ok(RustlsStream { inner })
+        })
+    }
+}
+
+pin_project! {
+    /// A tls connection to a host
+    pub struct RustlsStream<S> {
+        #[pin]
+        inner: tokio_rustls::client::TlsStream<S>,
+    }
+}
+
+impl<S> TlsStream for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn channel_binding(&self) -> tls::ChannelBinding {
+        let (_, session) = self.inner.get_ref();
+        match session.peer_certificates() {
+            Some([certificate, ..]) => {
+                tls::ChannelBinding::tls_server_end_point(end_point_hash(certificate))
+            }
+            _ => tls::ChannelBinding::none(),
+        }
+    }
+}
+
+impl<S> AsyncRead for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Object identifiers of the signature algorithms hashing with SHA-384 and
+/// SHA-512, in their DER encoding
+const SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const ECDSA_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+
+/// Hashes the server's certificate for the `tls-server-end-point` channel
+/// binding, with the hash function of the certificate's signature algorithm
+/// or SHA-256 for weaker ones, like the server does (RFC 5929)
+fn end_point_hash(certificate: &[u8]) -> Vec<u8> {
+    let algorithm = match signature_algorithm(certificate) {
+        Some(SHA384_WITH_RSA | ECDSA_WITH_SHA384) => &SHA384,
+        Some(SHA512_WITH_RSA | ECDSA_WITH_SHA512) => &SHA512,
+        _ => &SHA256,
+    };
+    digest::digest(algorithm, certificate).as_ref().to_vec()
+}
+
+/// Reads the object identifier of the signature algorithm of a DER encoded
+/// certificate, which follows the signed part of the certificate
+fn signature_algorithm(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const OBJECT_IDENTIFIER: u8 = 0x06;
+
+    let (certificate, _) = der_element(certificate, SEQUENCE)?;
+    let (_, rest) = der_element(certificate, SEQUENCE)?;
+    let (algorithm, _) = der_element(rest, SEQUENCE)?;
+    let (oid, _) = der_element(algorithm, OBJECT_IDENTIFIER)?;
+    Some(oid)
+}
+
+/// Splits the DER element with `tag` at the start of `bytes` into its
+/// contents and the bytes following it
+fn der_element(bytes: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&first, bytes) = bytes.split_first()?;
+    if first != tag {
+        return None;
+    }
+    let (&length, bytes) = bytes.split_first()?;
+    let (length, bytes) = if length < 0x80 {
+        (length as usize, bytes)
+    } else {
+        // the length is encoded in the next bytes
+        let count = (length & 0x7f) as usize;
+        if count == 0 || count > 4 || bytes.len() < count {
+            return None;
+        }
+        let (length_bytes, bytes) = bytes.split_at(count);
+        let length = length_bytes
+            .iter()
+            .fold(0, |length, byte| (length << 8) | *byte as usize);
+        (length, bytes)
+    };
+    if bytes.len() < length {
+        return None;
+    }
+    Some(bytes.split_at(length))
+}
@@ -1,19 +1,31 @@
 use core::str;
-use std::{collections::HashMap, str::Utf8Error};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    str::Utf8Error,
+};
 
+use chrono::{DateTime, Utc};
 use postgres_replication::protocol::{
     BeginBody, CommitBody, DeleteBody, InsertBody, LogicalReplicationMessage, RelationBody,
     ReplicationMessage, TupleData, TypeBody, UpdateBody,
 };
 use thiserror::Error;
+use tokio_postgres::types::{Kind, PgLsn, Type};
+use tracing::warn;
 
 use crate::{
-    pipeline::batching::BatchBoundary,
-    table::{ColumnSchema, TableId, TableSchema},
+    clients::postgres::ConversionErrorPolicy,
+    conversions::ConversionPolicy,
+    pipeline::{
+        batching::BatchBoundary, dead_letters::DeadLetter, sinks::RowOperation,
+        status::POSTGRES_EPOCH_UNIX_SECS,
+    },
+    table::{ColumnSchema, TableId, TableSchema, TypeOverride},
 };
 
 use super::{
-    table_row::TableRow,
+    table_row::{RowAllocator, TableRow},
     text::{FromTextError, TextFormatConverter},
     Cell,
 };
@@ -43,76 +55,376 @@ pub enum CdcEventConversionError {
 
     #[error("invalid string value")]
     InvalidStr(#[from] Utf8Error),
+
+    #[error("invalid relation message: {0}")]
+    InvalidRelation(#[from] io::Error),
+
+    #[error("a change of table {0} has no value for column {1}")]
+    MissingColumnValue(TableId, String),
+
+    #[error("column {} of table {} failed to convert: {}", .1.column, .1.table_name, .1.error)]
+    DeadLetter(TableId, Box<DeadLetter>),
 }
 
 pub struct CdcEventConverter;
 
 impl CdcEventConverter {
+    /// Converts the values of a change. Columns of large objects are
+    /// converted to the objects' oids, which the source replaces with the
+    /// objects' contents, and columns of overridden types to the overriding
+    /// types.
     fn try_from_tuple_data_slice(
-        column_schemas: &[ColumnSchema],
+        table_schema: &TableSchema,
+        tuple_indexes: Option<&Vec<usize>>,
         tuple_data: &[TupleData],
+        operation: RowOperation,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
     ) -> Result<TableRow, CdcEventConversionError> {
-        let mut values = Vec::with_capacity(column_schemas.len());
+        let column_schemas = &table_schema.column_schemas;
+        let mut table_row = rows.row(column_schemas.len());
 
         for (i, column_schema) in column_schemas.iter().enumerate() {
-            let cell = match &tuple_data[i] {
+            // the column of commit timestamps isn't sent by the source, the
+            // stream sets it
+            if table_schema.commit_timestamp_column.as_ref() == Some(&column_schema.name) {
+                table_row.values.push(Cell::Null);
+                continue;
+            }
+            let index = tuple_indexes.and_then(|indexes| indexes.get(i).copied());
+            let value = tuple_data.get(index.unwrap_or(i)).ok_or_else(|| {
+                CdcEventConversionError::MissingColumnValue(
+                    table_schema.table_id,
+                    column_schema.name.clone(),
+                )
+            })?;
+            let type_override = table_schema.type_overrides.get(&column_schema.name);
+            let parsed_typ;
+            let typ = if !table_schema.large_object_columns.is_empty()
+                && table_schema
+                    .large_object_columns
+                    .contains(&column_schema.name)
+            {
+                &Type::OID
+            } else if let Some(type_override) = type_override {
+                parsed_typ = type_override.parsed_typ();
+                &parsed_typ
+            } else {
+                &column_schema.typ
+            };
+            let dead_letter = |error: String| {
+                let dead_letter = DeadLetter {
+                    table_name: table_schema.table_name.clone(),
+                    operation,
+                    column: column_schema.name.clone(),
+                    error,
+                    values: Self::tuple_texts(table_schema, tuple_indexes, tuple_data),
+                };
+                CdcEventConversionError::DeadLetter(table_schema.table_id, Box::new(dead_letter))
+            };
+            let cell = match value {
                 TupleData::Null => Cell::Null,
                 TupleData::UnchangedToast => TextFormatConverter::default_value(&column_schema.typ),
                 TupleData::Binary(_) => {
                     return Err(CdcEventConversionError::BinaryFormatNotSupported)
                 }
-                TupleData::Text(bytes) => {
-                    let str = str::from_utf8(&bytes[..])?;
-                    TextFormatConverter::try_from_str(&column_schema.typ, str)?
-                }
+                TupleData::Text(bytes) => match Self::try_from_text(bytes, typ, type_override) {
+                    Ok(mut cell) => {
+                        let limited = match &policy.max_value_size {
+                            Some(limit) => limit.apply(&mut cell, bytes.len()),
+                            None => Ok(false),
+                        };
+                        match limited {
+                            Ok(false) => {}
+                            Ok(true) => table_row.failed_columns.push(column_schema.name.clone()),
+                            Err(e) => return Err(dead_letter(e.to_string())),
+                        }
+                        cell
+                    }
+                    Err(e) => match policy.errors {
+                        ConversionErrorPolicy::Fail => return Err(e),
+                        ConversionErrorPolicy::Null => {
+                            table_row.failed_columns.push(column_schema.name.clone());
+                            Cell::Null
+                        }
+                        ConversionErrorPolicy::Coerce => {
+                            let text = String::from_utf8_lossy(bytes);
+                            let cell = TextFormatConverter::coerce_from_str(typ, &text);
+                            let cell = match type_override {
+                                Some(type_override) => {
+                                    TextFormatConverter::coerce_override(*type_override, cell)
+                                }
+                                None => cell,
+                            };
+                            warn!(
+                                "coerced column `{}` of type `{typ}` from text `{text}` to {cell:?}: {e}",
+                                column_schema.name
+                            );
+                            table_row.failed_columns.push(column_schema.name.clone());
+                            cell
+                        }
+                        ConversionErrorPolicy::DeadLetter => {
+                            return Err(dead_letter(e.to_string()))
+                        }
+                    },
+                },
             };
-            values.push(cell);
+            table_row.values.push(cell);
         }
 
-        Ok(TableRow { values })
+        Ok(table_row)
+    }
+
+    fn try_from_text(
+        bytes: &[u8],
+        typ: &Type,
+        type_override: Option<&TypeOverride>,
+    ) -> Result<Cell, CdcEventConversionError> {
+        let str = str::from_utf8(bytes)?;
+        let cell = TextFormatConverter::try_from_str(typ, str)?;
+        Ok(match type_override {
+            Some(type_override) => TextFormatConverter::try_override(*type_override, cell)?,
+            None => cell,
+        })
+    }
+
+    /// Returns the text of the values of a change by column, for its dead
+    /// letter
+    fn tuple_texts(
+        table_schema: &TableSchema,
+        tuple_indexes: Option<&Vec<usize>>,
+        tuple_data: &[TupleData],
+    ) -> BTreeMap<String, Option<String>> {
+        table_schema
+            .column_schemas
+            .iter()
+            .enumerate()
+            .map(|(i, column_schema)| {
+                let index = tuple_indexes.and_then(|indexes| indexes.get(i).copied());
+                let text = match tuple_data.get(index.unwrap_or(i)) {
+                    Some(TupleData::Text(bytes)) => {
+                        Some(String::from_utf8_lossy(bytes).into_owned())
+                    }
+                    _ => None,
+                };
+                (column_schema.name.clone(), text)
+            })
+            .collect()
     }
 
     fn try_from_insert_body(
-        table_id: TableId,
-        column_schemas: &[ColumnSchema],
+        table_schema: &TableSchema,
+        tuple_indexes: Option<&Vec<usize>>,
         insert_body: InsertBody,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
     ) -> Result<CdcEvent, CdcEventConversionError> {
-        let row =
-            Self::try_from_tuple_data_slice(column_schemas, insert_body.tuple().tuple_data())?;
+        let table_id = table_schema.table_id;
+        let row = Self::try_from_tuple_data_slice(
+            table_schema,
+            tuple_indexes,
+            insert_body.tuple().tuple_data(),
+            RowOperation::Insert,
+            rows,
+            policy,
+        )?;
 
         Ok(CdcEvent::Insert((table_id, row)))
     }
 
     //TODO: handle when identity columns are changed
     fn try_from_update_body(
-        table_id: TableId,
-        column_schemas: &[ColumnSchema],
+        table_schema: &TableSchema,
+        tuple_indexes: Option<&Vec<usize>>,
         update_body: UpdateBody,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
     ) -> Result<CdcEvent, CdcEventConversionError> {
-        let row =
-            Self::try_from_tuple_data_slice(column_schemas, update_body.new_tuple().tuple_data())?;
+        let table_id = table_schema.table_id;
+        let row = Self::try_from_tuple_data_slice(
+            table_schema,
+            tuple_indexes,
+            update_body.new_tuple().tuple_data(),
+            RowOperation::Update,
+            rows,
+            policy,
+        )?;
 
         Ok(CdcEvent::Update((table_id, row)))
     }
 
     fn try_from_delete_body(
-        table_id: TableId,
-        column_schemas: &[ColumnSchema],
+        table_schema: &TableSchema,
+        tuple_indexes: Option<&Vec<usize>>,
         delete_body: DeleteBody,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
     ) -> Result<CdcEvent, CdcEventConversionError> {
         let tuple = delete_body
             .key_tuple()
             .or(delete_body.old_tuple())
             .ok_or(CdcEventConversionError::MissingTupleInDeleteBody)?;
 
-        let row = Self::try_from_tuple_data_slice(column_schemas, tuple.tuple_data())?;
+        let table_id = table_schema.table_id;
+        let row = Self::try_from_tuple_data_slice(
+            table_schema,
+            tuple_indexes,
+            tuple.tuple_data(),
+            RowOperation::Delete,
+            rows,
+            policy,
+        )?;
 
         Ok(CdcEvent::Delete((table_id, row)))
     }
 
+    /// Returns the schema of a table as described by a relation message.
+    /// Relation messages don't carry nullability so it is taken from the
+    /// matching column in `table_schema`, new columns are assumed nullable.
+    /// The skipped columns of `table_schema` stay skipped and its columns of
+    /// unsupported types, of local timestamps, of large objects or of
+    /// overridden types keep the type they are replicated with.
+    pub fn try_table_schema_from_relation(
+        relation_body: &RelationBody,
+        table_schema: &TableSchema,
+    ) -> Result<TableSchema, CdcEventConversionError> {
+        let mut column_schemas = Vec::with_capacity(relation_body.columns().len());
+        let mut local_timestamp_columns = vec![];
+        let mut large_object_columns = vec![];
+        let mut type_overrides = HashMap::new();
+
+        for column in relation_body.columns() {
+            let name = column.name()?.to_string();
+            if table_schema.skipped_columns.contains(&name) {
+                continue;
+            }
+            let type_oid = column.type_id() as u32;
+            let typ = Type::from_oid(type_oid).unwrap_or(Type::new(
+                format!("unnamed(oid: {type_oid})"),
+                type_oid,
+                Kind::Simple,
+                "pg_catalog".to_string(),
+            ));
+            let existing_column = table_schema.column_schemas.iter().find(|c| c.name == name);
+            let (nullable, primary) = match existing_column {
+                Some(c) => (c.nullable, c.primary),
+                None => (true, column.flags() == 1),
+            };
+            // columns of unsupported types keep the type they're replicated
+            // with, like text
+            let typ = match existing_column {
+                Some(c)
+                    if !TextFormatConverter::is_supported(&typ)
+                        && (c.typ == Type::TEXT || !TextFormatConverter::is_supported(&c.typ)) =>
+                {
+                    c.typ.clone()
+                }
+                _ => typ,
+            };
+            // columns of large objects keep the type of their contents or
+            // pointers and columns of overridden types the overriding type
+            let typ = match existing_column {
+                Some(c) if table_schema.large_object_columns.contains(&name) => {
+                    large_object_columns.push(name.clone());
+                    c.typ.clone()
+                }
+                Some(c) if table_schema.type_overrides.contains_key(&name) => {
+                    type_overrides.insert(name.clone(), table_schema.type_overrides[&name]);
+                    c.typ.clone()
+                }
+                _ => typ,
+            };
+            let local_typ = match typ {
+                Type::TIMESTAMP => Some(Type::TIMESTAMPTZ),
+                Type::TIMESTAMP_ARRAY => Some(Type::TIMESTAMPTZ_ARRAY),
+                _ => None,
+            };
+            let typ = match local_typ {
+                Some(local_typ) if table_schema.local_timestamp_columns.contains(&name) => {
+                    local_timestamp_columns.push(name.clone());
+                    local_typ
+                }
+                _ => typ,
+            };
+
+            column_schemas.push(ColumnSchema {
+                name,
+                typ,
+                modifier: column.type_modifier(),
+                nullable,
+                primary,
+            });
+        }
+
+        // the column of commit timestamps stays the last column
+        if let Some(name) = &table_schema.commit_timestamp_column {
+            if let Some(column_schema) = table_schema
+                .column_schemas
+                .iter()
+                .find(|column_schema| &column_schema.name == name)
+            {
+                column_schemas.push(column_schema.clone());
+            }
+        }
+
+        Ok(TableSchema {
+            table_name: table_schema.table_name.clone(),
+            table_id: table_schema.table_id,
+            column_schemas,
+            skipped_columns: table_schema.skipped_columns.clone(),
+            local_timestamp_columns,
+            large_object_columns,
+            type_overrides,
+            commit_timestamp_column: table_schema.commit_timestamp_column.clone(),
+        })
+    }
+
+    /// Returns the positions in the rows of a relation's changes of the
+    /// columns which aren't skipped, in order
+    pub fn tuple_indexes_from_relation(
+        relation_body: &RelationBody,
+        table_schema: &TableSchema,
+    ) -> Result<Vec<usize>, CdcEventConversionError> {
+        let mut tuple_indexes = Vec::with_capacity(relation_body.columns().len());
+        for (i, column) in relation_body.columns().iter().enumerate() {
+            let name = column.name()?;
+            if !table_schema
+                .skipped_columns
+                .iter()
+                .any(|skipped| skipped == name)
+            {
+                tuple_indexes.push(i);
+            }
+        }
+        Ok(tuple_indexes)
+    }
+
+    /// Converts a replication message with the schemas of the tables and
+    /// the positions of their columns in the rows of their changes, which
+    /// are the columns' positions in the schema for tables without any
     pub fn try_from(
         value: ReplicationMessage<LogicalReplicationMessage>,
         table_schemas: &HashMap<TableId, TableSchema>,
+        tuple_indexes: &HashMap<TableId, Vec<usize>>,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        Self::try_from_with_policy(
+            value,
+            table_schemas,
+            tuple_indexes,
+            &mut RowAllocator::default(),
+            &ConversionPolicy::default(),
+        )
+    }
+
+    /// Converts a replication message like [`CdcEventConverter::try_from`],
+    /// applying the policy to the values of changes which fail to convert or
+    /// are too large. The rows of changes are created by `rows`.
+    pub fn try_from_with_policy(
+        value: ReplicationMessage<LogicalReplicationMessage>,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        tuple_indexes: &HashMap<TableId, Vec<usize>>,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
     ) -> Result<CdcEvent, CdcEventConversionError> {
         match value {
             ReplicationMessage::XLogData(xlog_data) => match xlog_data.into_data() {
@@ -127,38 +439,41 @@ impl CdcEventConverter {
                 LogicalReplicationMessage::Type(type_body) => Ok(CdcEvent::Type(type_body)),
                 LogicalReplicationMessage::Insert(insert_body) => {
                     let table_id = insert_body.rel_id();
-                    let column_schemas = &table_schemas
+                    let table_schema = table_schemas
                         .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
+                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?;
                     Ok(Self::try_from_insert_body(
-                        table_id,
-                        column_schemas,
+                        table_schema,
+                        tuple_indexes.get(&table_id),
                         insert_body,
+                        rows,
+                        policy,
                     )?)
                 }
                 LogicalReplicationMessage::Update(update_body) => {
                     let table_id = update_body.rel_id();
-                    let column_schemas = &table_schemas
+                    let table_schema = table_schemas
                         .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
+                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?;
                     Ok(Self::try_from_update_body(
-                        table_id,
-                        column_schemas,
+                        table_schema,
+                        tuple_indexes.get(&table_id),
                         update_body,
+                        rows,
+                        policy,
                     )?)
                 }
                 LogicalReplicationMessage::Delete(delete_body) => {
                     let table_id = delete_body.rel_id();
-                    let column_schemas = &table_schemas
+                    let table_schema = table_schemas
                         .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
+                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?;
                     Ok(Self::try_from_delete_body(
-                        table_id,
-                        column_schemas,
+                        table_schema,
+                        tuple_indexes.get(&table_id),
                         delete_body,
+                        rows,
+                        policy,
                     )?)
                 }
                 LogicalReplicationMessage::Truncate(_) => {
@@ -168,12 +483,20 @@ impl CdcEventConverter {
             },
             ReplicationMessage::PrimaryKeepAlive(keep_alive) => Ok(CdcEvent::KeepAliveRequested {
                 reply: keep_alive.reply() == 1,
+                wal_end: keep_alive.wal_end().into(),
             }),
             _ => Err(CdcEventConversionError::UnknownReplicationMessage),
         }
     }
 }
 
+/// Converts a timestamp of the replication protocol, in microseconds since
+/// 2000-01-01, to a UTC time
+pub fn replication_timestamp(micros: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(POSTGRES_EPOCH_UNIX_SECS as i64 * 1_000_000 + micros)
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub enum CdcEvent {
     Begin(BeginBody),
@@ -183,14 +506,26 @@ pub enum CdcEvent {
     Delete((TableId, TableRow)),
     Relation(RelationBody),
     Type(TypeBody),
-    KeepAliveRequested { reply: bool },
+    /// A keepalive from the server, `wal_end` is the end of its WAL
+    KeepAliveRequested {
+        reply: bool,
+        wal_end: PgLsn,
+    },
 }
 
 impl BatchBoundary for CdcEvent {
     fn is_last_in_batch(&self) -> bool {
         matches!(
             self,
-            CdcEvent::Commit(_) | CdcEvent::KeepAliveRequested { reply: _ }
+            CdcEvent::Commit(_) | CdcEvent::KeepAliveRequested { .. }
         )
     }
+
+    fn begins_transaction(&self) -> bool {
+        matches!(self, CdcEvent::Begin(_))
+    }
+
+    fn ends_transaction(&self) -> bool {
+        matches!(self, CdcEvent::Commit(_))
+    }
 }
@@ -0,0 +1,99 @@
+//! Conversions of values to json and text, as the stdout sink prints them
+
+use serde_json::{Map, Number, Value};
+
+use crate::table::ColumnSchema;
+
+use super::{table_row::TableRow, ArrayCell, Cell};
+
+fn f64_to_json(value: f64) -> Value {
+    // json has no NaN or infinities
+    Number::from_f64(value).map_or_else(|| Value::String(value.to_string()), Value::Number)
+}
+
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + bytes.len() * 2);
+    hex.push_str("\\x");
+    for byte in bytes {
+        hex.push_str(&format!("{byte:02x}"));
+    }
+    hex
+}
+
+fn array_to_json<T>(values: &[Option<T>], to_json: impl Fn(&T) -> Value) -> Value {
+    Value::Array(
+        values
+            .iter()
+            .map(|value| value.as_ref().map_or(Value::Null, &to_json))
+            .collect(),
+    )
+}
+
+/// Converts a value to json. Numerics are strings so that they keep their
+/// precision, bytes are hex strings like Postgres prints them.
+pub fn cell_to_json(cell: &Cell) -> Value {
+    match cell {
+        Cell::Null => Value::Null,
+        Cell::Bool(b) => Value::Bool(*b),
+        Cell::String(s) => Value::String(s.clone()),
+        Cell::I16(i) => Value::from(*i),
+        Cell::I32(i) => Value::from(*i),
+        Cell::U32(i) => Value::from(*i),
+        Cell::I64(i) => Value::from(*i),
+        Cell::F32(f) => f64_to_json(*f as f64),
+        Cell::F64(f) => f64_to_json(*f),
+        Cell::Numeric(n) => Value::String(n.to_string()),
+        Cell::Date(d) => Value::String(d.to_string()),
+        Cell::Time(t) => Value::String(t.to_string()),
+        Cell::TimeStamp(t) => Value::String(t.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        Cell::TimeStampTz(t) => Value::String(t.to_rfc3339()),
+        Cell::Uuid(u) => Value::String(u.to_string()),
+        Cell::Json(j) => j.clone(),
+        Cell::Bytes(b) => Value::String(bytes_to_hex(b)),
+        Cell::Array(array) => match array {
+            ArrayCell::Null => Value::Null,
+            ArrayCell::Bool(v) => array_to_json(v, |b| Value::Bool(*b)),
+            ArrayCell::String(v) => array_to_json(v, |s| Value::String(s.clone())),
+            ArrayCell::I16(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::I32(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::U32(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::I64(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::F32(v) => array_to_json(v, |f| f64_to_json(*f as f64)),
+            ArrayCell::F64(v) => array_to_json(v, |f| f64_to_json(*f)),
+            ArrayCell::Numeric(v) => array_to_json(v, |n| Value::String(n.to_string())),
+            ArrayCell::Date(v) => array_to_json(v, |d| Value::String(d.to_string())),
+            ArrayCell::Time(v) => array_to_json(v, |t| Value::String(t.to_string())),
+            ArrayCell::TimeStamp(v) => array_to_json(v, |t| {
+                Value::String(t.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+            }),
+            ArrayCell::TimeStampTz(v) => array_to_json(v, |t| Value::String(t.to_rfc3339())),
+            ArrayCell::Uuid(v) => array_to_json(v, |u| Value::String(u.to_string())),
+            ArrayCell::Json(v) => array_to_json(v, |j| j.clone()),
+            ArrayCell::Bytes(v) => array_to_json(v, |b| Value::String(bytes_to_hex(b))),
+        },
+    }
+}
+
+/// Converts a row to a json object of its columns' values, by the columns'
+/// names
+pub fn row_to_json(column_schemas: &[ColumnSchema], row: &TableRow) -> Map<String, Value> {
+    column_schemas
+        .iter()
+        .zip(&row.values)
+        .map(|(column_schema, cell)| (column_schema.name.clone(), cell_to_json(cell)))
+        .collect()
+}
+
+/// Converts a value to the text of a csv field, arrays and json values are
+/// printed as json
+pub fn cell_to_text(cell: &Cell) -> String {
+    match cell {
+        Cell::Null => String::new(),
+        Cell::String(s) => s.clone(),
+        Cell::Json(j) => j.to_string(),
+        cell => match cell_to_json(cell) {
+            Value::String(s) => s,
+            value => value.to_string(),
+        },
+    }
+}
@@ -2,15 +2,27 @@ use std::fmt::Debug;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use numeric::PgNumeric;
+use size::ValueSizeLimit;
 use uuid::Uuid;
 
+use crate::clients::postgres::ConversionErrorPolicy;
+
 pub mod bool;
 pub mod cdc_event;
 pub mod hex;
+pub mod json;
 pub mod numeric;
+pub mod size;
 pub mod table_row;
 pub mod text;
 
+/// How the converters handle values which fail to convert or are too large
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionPolicy {
+    pub errors: ConversionErrorPolicy,
+    pub max_value_size: Option<ValueSizeLimit>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Cell {
     Null,
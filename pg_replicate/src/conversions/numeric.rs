@@ -27,11 +27,11 @@ impl FromStr for PgNumeric {
         match BigDecimal::from_str(s) {
             Ok(n) => Ok(PgNumeric::Value(n)),
             Err(e) => {
-                if s.to_lowercase() == "infinity" {
+                if s.eq_ignore_ascii_case("infinity") {
                     Ok(PgNumeric::PositiveInf)
-                } else if s.to_lowercase() == "-infinity" {
+                } else if s.eq_ignore_ascii_case("-infinity") {
                     Ok(PgNumeric::NegativeInf)
-                } else if s.to_lowercase() == "nan" {
+                } else if s.eq_ignore_ascii_case("nan") {
                     Ok(PgNumeric::NaN)
                 } else {
                     Err(e)
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Cell;
+
+/// What happens to a value larger than the limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OversizedValuePolicy {
+    /// Truncate the value to the limit, ending it with the marker. Json
+    /// values are replaced by a json string of their truncated text, or by
+    /// null if the limit is too small for any json string
+    #[default]
+    Truncate,
+
+    /// Replace the value with null, even in columns which aren't nullable
+    Null,
+
+    /// Write the row to the pipeline's dead letters instead of the sink
+    DeadLetter,
+}
+
+/// Limit on the size of text, bytea and json values, for sinks whose rows
+/// or records have a maximum size, like BigQuery's rows or Kinesis'
+/// records. Values of arrays aren't limited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueSizeLimit {
+    /// Bytes a value may hold, as UTF-8 for text and json values
+    pub max_bytes: usize,
+
+    #[serde(default)]
+    pub policy: OversizedValuePolicy,
+
+    /// Marker ending truncated values, which counts towards the limit. A
+    /// marker larger than the limit is cut to fit
+    #[serde(default = "default_marker")]
+    pub marker: String,
+}
+
+fn default_marker() -> String {
+    "...[truncated]".to_string()
+}
+
+#[derive(Debug, Error)]
+#[error("value of {size} bytes is larger than the limit of {max_bytes} bytes")]
+pub struct OversizedValueError {
+    pub size: usize,
+    pub max_bytes: usize,
+}
+
+impl ValueSizeLimit {
+    /// Applies the limit to a value, whose text of `text_size` bytes gives
+    /// the size of json values. Returns whether the value was truncated or
+    /// nulled, and fails for values larger than the limit with the dead
+    /// letter policy.
+    pub fn apply(&self, cell: &mut Cell, text_size: usize) -> Result<bool, OversizedValueError> {
+        let size = match cell {
+            Cell::String(value) => value.len(),
+            Cell::Bytes(value) => value.len(),
+            Cell::Json(_) => text_size,
+            _ => return Ok(false),
+        };
+        if size <= self.max_bytes {
+            return Ok(false);
+        }
+
+        match self.policy {
+            OversizedValuePolicy::DeadLetter => {
+                return Err(OversizedValueError {
+                    size,
+                    max_bytes: self.max_bytes,
+                })
+            }
+            OversizedValuePolicy::Null => *cell = Cell::Null,
+            OversizedValuePolicy::Truncate => match cell {
+                Cell::String(value) => {
+                    truncate(value, &self.marker, self.max_bytes, char::len_utf8)
+                }
+                Cell::Bytes(value) => {
+                    let marker = fit(&self.marker, self.max_bytes, char::len_utf8);
+                    value.truncate(self.max_bytes - marker.len());
+                    value.extend_from_slice(marker.as_bytes());
+                }
+                // not even the quotes of an empty json string fit
+                Cell::Json(_) if self.max_bytes < 2 => *cell = Cell::Null,
+                // the string is serialized between quotes and with its
+                // special characters escaped
+                Cell::Json(value) => {
+                    let mut text = value.to_string();
+                    truncate(&mut text, &self.marker, self.max_bytes - 2, escaped_len);
+                    *value = serde_json::Value::String(text);
+                }
+                _ => {}
+            },
+        }
+        Ok(true)
+    }
+}
+
+/// Truncates text, at a character boundary, and appends the marker so that
+/// the text takes at most `max_bytes`, with characters taking `size` bytes
+fn truncate(text: &mut String, marker: &str, max_bytes: usize, size: fn(char) -> usize) {
+    let marker = fit(marker, max_bytes, size);
+    let marker_size: usize = marker.chars().map(size).sum();
+    let len = fit(text, max_bytes - marker_size, size).len();
+    text.truncate(len);
+    text.push_str(marker);
+}
+
+/// Longest prefix of `text` taking at most `max_bytes`, with characters
+/// taking `size` bytes
+fn fit(text: &str, max_bytes: usize, size: fn(char) -> usize) -> &str {
+    let mut bytes = 0;
+    for (i, c) in text.char_indices() {
+        bytes += size(c);
+        if bytes > max_bytes {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// Bytes a character takes in a json string, as escaped by serde_json
+fn escaped_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\u{08}' | '\u{0c}' | '\n' | '\r' | '\t' => 2,
+        '\u{00}'..='\u{1f}' => 6,
+        c => c.len_utf8(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conversions::Cell;
+
+    use super::{OversizedValuePolicy, ValueSizeLimit};
+
+    fn limit(max_bytes: usize, marker: &str) -> ValueSizeLimit {
+        ValueSizeLimit {
+            max_bytes,
+            policy: OversizedValuePolicy::Truncate,
+            marker: marker.to_string(),
+        }
+    }
+
+    fn truncated_string(limit: &ValueSizeLimit, value: &str) -> String {
+        let mut cell = Cell::String(value.to_string());
+        assert!(limit.apply(&mut cell, value.len()).unwrap());
+        match cell {
+            Cell::String(value) => value,
+            cell => panic!("expected a string, got {cell:?}"),
+        }
+    }
+
+    /// Returns the serialized json of the truncated value
+    fn truncated_json(limit: &ValueSizeLimit, value: serde_json::Value) -> String {
+        let text_size = value.to_string().len();
+        let mut cell = Cell::Json(value);
+        assert!(limit.apply(&mut cell, text_size).unwrap());
+        match cell {
+            Cell::Json(value) => value.to_string(),
+            cell => panic!("expected json, got {cell:?}"),
+        }
+    }
+
+    #[test]
+    fn values_within_the_limit_are_kept() {
+        let mut cell = Cell::String("abc".to_string());
+        assert!(!limit(3, "~").apply(&mut cell, 3).unwrap());
+        assert!(matches!(cell, Cell::String(value) if value == "abc"));
+    }
+
+    #[test]
+    fn text_is_truncated_at_a_character_boundary() {
+        // é takes two bytes and € three, so a character is never split
+        assert_eq!(truncated_string(&limit(6, "~"), "ééééé"), "éé~");
+        assert_eq!(truncated_string(&limit(7, "~"), "ééééé"), "ééé~");
+        assert_eq!(truncated_string(&limit(5, "~"), "€€€€"), "€~");
+        assert_eq!(truncated_string(&limit(5, "…"), "€€€€"), "…");
+    }
+
+    #[test]
+    fn bytes_are_truncated_with_the_marker() {
+        let mut cell = Cell::Bytes(vec![1, 2, 3, 4, 5]);
+        assert!(limit(4, "~").apply(&mut cell, 5).unwrap());
+        assert!(matches!(cell, Cell::Bytes(value) if value == [1, 2, 3, b'~']));
+    }
+
+    #[test]
+    fn truncated_json_fits_once_escaped() {
+        // the text "\n\n\n\n" takes 10 bytes, but 18 once serialized as a
+        // json string
+        let value = serde_json::json!("\n\n\n\n");
+        let truncated = truncated_json(&limit(9, "~"), value);
+        assert_eq!(truncated, r#""\"\\n~""#);
+        assert!(truncated.len() <= 9);
+    }
+
+    #[test]
+    fn unicode_escapes_in_json_text_are_escaped_again() {
+        // \u0001 takes 6 bytes in the text, and 7 once its backslash is
+        // escaped
+        let value = serde_json::Value::String("\u{01}\u{02}".to_string());
+        let truncated = truncated_json(&limit(12, ""), value);
+        assert_eq!(truncated, r#""\"\\u0001""#);
+        assert!(truncated.len() <= 12);
+    }
+
+    #[test]
+    fn oversized_markers_are_cut_to_the_limit() {
+        let marker = "...[truncated]";
+        assert_eq!(truncated_string(&limit(4, marker), "abcdefgh"), "...[");
+
+        let mut cell = Cell::Bytes(vec![0; 8]);
+        assert!(limit(4, marker).apply(&mut cell, 8).unwrap());
+        assert!(matches!(cell, Cell::Bytes(value) if value == b"...["));
+
+        // only one of the marker's quotes fits once escaped
+        let truncated = truncated_json(&limit(5, r#""""#), serde_json::json!([1, 2, 3, 4]));
+        assert_eq!(truncated, r#""[\"""#);
+    }
+
+    #[test]
+    fn json_is_nulled_if_no_json_string_fits() {
+        let mut cell = Cell::Json(serde_json::json!([1, 2, 3]));
+        assert!(limit(1, "~").apply(&mut cell, 7).unwrap());
+        assert!(matches!(cell, Cell::Null));
+    }
+}
@@ -1,17 +1,118 @@
 use core::str;
-use std::str::Utf8Error;
+use std::{
+    str::Utf8Error,
+    sync::{Arc, Mutex, PoisonError},
+};
 
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 use tokio_postgres::types::Type;
-use tracing::error;
+use tracing::{error, warn};
 
-use crate::{conversions::text::TextFormatConverter, pipeline::batching::BatchBoundary};
+use crate::{
+    clients::postgres::ConversionErrorPolicy,
+    conversions::{size::OversizedValueError, text::TextFormatConverter, ConversionPolicy},
+    pipeline::{batching::BatchBoundary, dead_letters::DeadLetter, sinks::RowOperation},
+    table::TableName,
+};
 
 use super::{text::FromTextError, Cell};
 
 #[derive(Debug)]
 pub struct TableRow {
     pub values: Vec<Cell>,
+    /// Commit timestamp of the transaction of a change's row, none for
+    /// copied rows
+    pub commit_timestamp: Option<DateTime<Utc>>,
+    /// Columns whose values failed to convert and were nulled or coerced,
+    /// or were truncated or nulled for their size, which the pipeline
+    /// counts
+    pub failed_columns: Vec<String>,
+}
+
+/// Upper bound on the number of pooled vectors, which keeps the memory held
+/// by a pool bounded after a burst of large batches
+const MAX_POOLED_ROWS: usize = 4096;
+
+/// Upper bound on the capacity of pooled vectors, so that the vectors of a
+/// wide table aren't kept around for the rows of narrow ones
+const MAX_POOLED_ROW_CAPACITY: usize = 128;
+
+impl TableRow {
+    /// Creates an empty row with room for `num_cols` values
+    pub fn with_capacity(num_cols: usize) -> TableRow {
+        TableRow::from_values(Vec::with_capacity(num_cols))
+    }
+
+    fn from_values(values: Vec<Cell>) -> TableRow {
+        TableRow {
+            values,
+            commit_timestamp: None,
+            failed_columns: vec![],
+        }
+    }
+}
+
+/// Cell vectors of the rows a pipeline's sink is done with, which its
+/// source reuses for the rows it decodes so that streaming many rows doesn't
+/// allocate a new vector per row. Vectors are handed back a batch at a time
+/// and taken all at once, so the lock is rarely taken per row.
+#[derive(Debug, Clone, Default)]
+pub struct RowPool {
+    values: Arc<Mutex<Vec<Vec<Cell>>>>,
+}
+
+impl RowPool {
+    /// Hands written rows back to the pool. Sinks should call this once per
+    /// batch; rows which are simply dropped are not reused.
+    pub fn recycle(&self, rows: impl IntoIterator<Item = TableRow>) {
+        let mut pool = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        for mut row in rows {
+            if pool.len() >= MAX_POOLED_ROWS {
+                break;
+            }
+            if row.values.capacity() > MAX_POOLED_ROW_CAPACITY {
+                continue;
+            }
+            row.values.clear();
+            pool.push(row.values);
+        }
+    }
+
+    fn take_all(&self) -> Vec<Vec<Cell>> {
+        std::mem::take(&mut *self.values.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// Creates the rows a stream decodes, from the vectors of a [`RowPool`] if
+/// it has one
+#[derive(Debug, Default)]
+pub struct RowAllocator {
+    pool: Option<RowPool>,
+    /// Vectors taken from the pool which weren't used yet
+    free: Vec<Vec<Cell>>,
+}
+
+impl RowAllocator {
+    pub fn new(pool: Option<RowPool>) -> RowAllocator {
+        RowAllocator { pool, free: vec![] }
+    }
+
+    /// Creates an empty row with room for `num_cols` values
+    pub fn row(&mut self, num_cols: usize) -> TableRow {
+        if self.free.is_empty() {
+            if let Some(pool) = &self.pool {
+                self.free = pool.take_all();
+            }
+        }
+        match self.free.pop() {
+            Some(mut values) => {
+                values.reserve(num_cols);
+                TableRow::from_values(values)
+            }
+            None => TableRow::with_capacity(num_cols),
+        }
+    }
 }
 
 impl BatchBoundary for TableRow {
@@ -36,6 +137,12 @@ pub enum TableRowConversionError {
 
     #[error("invalid value: {0}")]
     InvalidValue(#[from] FromTextError),
+
+    #[error("oversized value: {0}")]
+    OversizedValue(#[from] OversizedValueError),
+
+    #[error("column {} of table {} failed to convert: {}", .0.column, .0.table_name, .0.error)]
+    DeadLetter(Box<DeadLetter>),
 }
 
 pub struct TableRowConverter;
@@ -45,74 +152,335 @@ impl TableRowConverter {
         row: &[u8],
         column_schemas: &[crate::table::ColumnSchema],
     ) -> Result<TableRow, TableRowConversionError> {
-        let mut values = Vec::with_capacity(column_schemas.len());
+        Self::try_from_with_buffer(row, column_schemas, &mut String::new())
+    }
+
+    /// Parses a row in the text format of `COPY`. Values without escapes are
+    /// parsed directly from slices of `row`, the others are unescaped into
+    /// `buffer` first, which callers can reuse across rows.
+    pub fn try_from_with_buffer(
+        row: &[u8],
+        column_schemas: &[crate::table::ColumnSchema],
+        buffer: &mut String,
+    ) -> Result<TableRow, TableRowConversionError> {
+        Self::convert(
+            row,
+            column_schemas,
+            buffer,
+            &mut RowAllocator::default(),
+            &ConversionPolicy::default(),
+            None,
+        )
+    }
+
+    /// Parses a row of the table like
+    /// [`TableRowConverter::try_from_with_buffer`], applying the policy to
+    /// the values which fail to convert or are too large. The row is created
+    /// by `rows`.
+    pub fn try_from_with_policy(
+        row: &[u8],
+        table_name: &TableName,
+        column_schemas: &[crate::table::ColumnSchema],
+        buffer: &mut String,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
+    ) -> Result<TableRow, TableRowConversionError> {
+        Self::convert(row, column_schemas, buffer, rows, policy, Some(table_name))
+    }
 
-        let row_str = str::from_utf8(row)?;
+    fn convert(
+        row: &[u8],
+        column_schemas: &[crate::table::ColumnSchema],
+        buffer: &mut String,
+        rows: &mut RowAllocator,
+        policy: &ConversionPolicy,
+        table_name: Option<&TableName>,
+    ) -> Result<TableRow, TableRowConversionError> {
+        let Some(row) = row.strip_suffix(b"\n") else {
+            return Err(TableRowConversionError::UnterminatedRow);
+        };
+
+        let mut table_row = rows.row(column_schemas.len());
         let mut column_schemas_iter = column_schemas.iter();
-        let mut chars = row_str.chars();
-        let mut val_str = String::with_capacity(10);
-        let mut in_escape = false;
-        let mut row_terminated = false;
-        let mut done = false;
-
-        while !done {
-            loop {
-                match chars.next() {
-                    Some(c) => match c {
-                        c if in_escape => {
-                            if c == 'N' {
-                                val_str.push('\\');
+
+        // tabs are never part of multibyte characters, so the fields are
+        // split before they are decoded
+        for field in row.split(|b| *b == b'\t') {
+            let Some(column_schema) = column_schemas_iter.next() else {
+                return Err(TableRowConversionError::NumColsMismatch);
+            };
+
+            let value = if field == b"\\N" {
+                Cell::Null
+            } else {
+                match Self::parse_field(field, &column_schema.typ, buffer) {
+                    Ok(mut value) => {
+                        let limited = match &policy.max_value_size {
+                            Some(limit) => limit.apply(&mut value, field.len()),
+                            None => Ok(false),
+                        };
+                        match limited {
+                            Ok(false) => {}
+                            Ok(true) => table_row.failed_columns.push(column_schema.name.clone()),
+                            Err(e) => {
+                                let Some(table_name) = table_name else {
+                                    return Err(e.into());
+                                };
+                                return Err(TableRowConversionError::DeadLetter(Box::new(
+                                    copy_row_dead_letter(
+                                        row,
+                                        table_name,
+                                        column_schemas,
+                                        &column_schema.name,
+                                        e.to_string(),
+                                    ),
+                                )));
                             }
-                            val_str.push(c);
-                            in_escape = false;
                         }
-                        '\t' => {
-                            break;
-                        }
-                        '\n' => {
-                            row_terminated = true;
-                            break;
-                        }
-                        '\\' => in_escape = true,
-                        c => {
-                            val_str.push(c);
-                        }
-                    },
-                    None => {
-                        if !row_terminated {
-                            return Err(TableRowConversionError::UnterminatedRow);
+                        value
+                    }
+                    Err(e) => {
+                        let text = field_text(field);
+                        match policy.errors {
+                            ConversionErrorPolicy::Fail => {
+                                error!(
+                                    "error parsing column `{}` of type `{}` from text `{text}`",
+                                    column_schema.name, column_schema.typ
+                                );
+                                return Err(e);
+                            }
+                            ConversionErrorPolicy::Null => {
+                                table_row.failed_columns.push(column_schema.name.clone());
+                                Cell::Null
+                            }
+                            ConversionErrorPolicy::Coerce => {
+                                let value =
+                                    TextFormatConverter::coerce_from_str(&column_schema.typ, &text);
+                                warn!(
+                                    "coerced column `{}` of type `{}` from text `{text}` to {value:?}: {e}",
+                                    column_schema.name, column_schema.typ
+                                );
+                                table_row.failed_columns.push(column_schema.name.clone());
+                                value
+                            }
+                            ConversionErrorPolicy::DeadLetter => {
+                                // rows of unknown tables can't be dead lettered
+                                let Some(table_name) = table_name else {
+                                    return Err(e);
+                                };
+                                return Err(TableRowConversionError::DeadLetter(Box::new(
+                                    copy_row_dead_letter(
+                                        row,
+                                        table_name,
+                                        column_schemas,
+                                        &column_schema.name,
+                                        e.to_string(),
+                                    ),
+                                )));
+                            }
                         }
-                        done = true;
-                        break;
                     }
                 }
-            }
+            };
 
-            if !done {
-                let Some(column_schema) = column_schemas_iter.next() else {
-                    return Err(TableRowConversionError::NumColsMismatch);
-                };
-
-                let value = if val_str == "\\N" {
-                    Cell::Null
-                } else {
-                    match TextFormatConverter::try_from_str(&column_schema.typ, &val_str) {
-                        Ok(value) => value,
-                        Err(e) => {
-                            error!(
-                                "error parsing column `{}` of type `{}` from text `{val_str}`",
-                                column_schema.name, column_schema.typ
-                            );
-                            return Err(e.into());
-                        }
-                    }
-                };
+            table_row.values.push(value);
+        }
 
-                values.push(value);
-                val_str.clear();
-            }
+        if column_schemas_iter.next().is_some() {
+            return Err(TableRowConversionError::NumColsMismatch);
         }
 
-        Ok(TableRow { values })
+        Ok(table_row)
+    }
+
+    fn parse_field(
+        field: &[u8],
+        typ: &Type,
+        buffer: &mut String,
+    ) -> Result<Cell, TableRowConversionError> {
+        let field = str::from_utf8(field)?;
+        let val_str = if field.contains('\\') {
+            buffer.clear();
+            unescape_copy_text(field, buffer);
+            buffer.as_str()
+        } else {
+            field
+        };
+        Ok(TextFormatConverter::try_from_str(typ, val_str)?)
+    }
+}
+
+/// Returns the dead letter of a row of a table's copy whose column failed
+/// to convert
+pub(crate) fn copy_row_dead_letter(
+    row: &[u8],
+    table_name: &TableName,
+    column_schemas: &[crate::table::ColumnSchema],
+    column: &str,
+    error: String,
+) -> DeadLetter {
+    let row = row.strip_suffix(b"\n").unwrap_or(row);
+    let values = row
+        .split(|b| *b == b'\t')
+        .zip(column_schemas)
+        .map(|(field, column_schema)| {
+            let text = (field != b"\\N").then(|| field_text(field));
+            (column_schema.name.clone(), text)
+        })
+        .collect();
+    DeadLetter {
+        table_name: table_name.clone(),
+        operation: RowOperation::Copy,
+        column: column.to_string(),
+        error,
+        values,
+    }
+}
+
+/// Returns the unescaped text of a field, with invalid UTF-8 replaced
+fn field_text(field: &[u8]) -> String {
+    let mut text = String::new();
+    unescape_copy_text(&String::from_utf8_lossy(field), &mut text);
+    text
+}
+
+/// Undoes the backslash escapes `COPY` applies to values in its text format.
+/// Its output never has octal or hex escapes, so a backslash before any
+/// other character only escapes that character.
+fn unescape_copy_text(field: &str, buffer: &mut String) {
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            buffer.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('b') => buffer.push('\u{8}'),
+            Some('f') => buffer.push('\u{c}'),
+            Some('n') => buffer.push('\n'),
+            Some('r') => buffer.push('\r'),
+            Some('t') => buffer.push('\t'),
+            Some('v') => buffer.push('\u{b}'),
+            Some(c) => buffer.push(c),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Type;
+
+    use crate::{conversions::Cell, table::ColumnSchema};
+
+    use super::{TableRowConversionError, TableRowConverter};
+
+    fn text_columns(num_cols: usize) -> Vec<ColumnSchema> {
+        (0..num_cols)
+            .map(|i| ColumnSchema {
+                name: format!("column_{i}"),
+                typ: Type::TEXT,
+                modifier: -1,
+                nullable: true,
+                primary: false,
+            })
+            .collect()
+    }
+
+    fn text_values(
+        row: &[u8],
+        num_cols: usize,
+        buffer: &mut String,
+    ) -> Result<Vec<Option<String>>, TableRowConversionError> {
+        let row = TableRowConverter::try_from_with_buffer(row, &text_columns(num_cols), buffer)?;
+        Ok(row
+            .values
+            .into_iter()
+            .map(|value| match value {
+                Cell::Null => None,
+                Cell::String(value) => Some(value),
+                value => panic!("expected text, got {value:?}"),
+            })
+            .collect())
+    }
+
+    #[test]
+    fn escapes_are_undone() {
+        let cases: [(&[u8], Option<&str>); 17] = [
+            (b"\\N\n", None),
+            (b"\n", Some("")),
+            (b"\\\\N\n", Some("\\N")),
+            (b"a\\\\b\n", Some("a\\b")),
+            (b"\\\\\\\\\n", Some("\\\\")),
+            (b"a\\tb\n", Some("a\tb")),
+            (b"a\\nb\n", Some("a\nb")),
+            (b"a\\rb\n", Some("a\rb")),
+            (b"a\\bb\n", Some("a\u{8}b")),
+            (b"a\\fb\n", Some("a\u{c}b")),
+            (b"a\\vb\n", Some("a\u{b}b")),
+            (b"\\N\\N\n", Some("NN")),
+            (b"caf\xc3\xa9\\t\xe2\x82\xac\n", Some("café\t€")),
+            // COPY only reads octal and hex escapes, its output never has
+            // them, so their digits are kept as they are
+            (b"\\101\n", Some("101")),
+            (b"\\x41\n", Some("x41")),
+            (b"\\.\n", Some(".")),
+            // a trailing backslash has nothing to escape
+            (b"a\\\n", Some("a")),
+        ];
+        // the buffer is reused across rows like in a table's copy
+        let mut buffer = String::new();
+        for (row, expected) in cases {
+            let values = text_values(row, 1, &mut buffer).unwrap();
+            assert_eq!(
+                values,
+                [expected.map(str::to_string)],
+                "row {:?}",
+                String::from_utf8_lossy(row)
+            );
+        }
+    }
+
+    #[test]
+    fn empty_strings_and_nulls_are_distinct() {
+        let values = text_values(b"\t\\N\t\n", 3, &mut String::new()).unwrap();
+        assert_eq!(values, [Some(String::new()), None, Some(String::new())]);
+    }
+
+    #[test]
+    fn trailing_delimiters_end_an_empty_field() {
+        let values = text_values(b"a\t\n", 2, &mut String::new()).unwrap();
+        assert_eq!(values, [Some("a".to_string()), Some(String::new())]);
+
+        let values = text_values(b"a\t\t\n", 3, &mut String::new()).unwrap();
+        assert_eq!(
+            values,
+            [
+                Some("a".to_string()),
+                Some(String::new()),
+                Some(String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_must_have_a_field_per_column() {
+        assert!(matches!(
+            text_values(b"a\t\n", 1, &mut String::new()),
+            Err(TableRowConversionError::NumColsMismatch)
+        ));
+        assert!(matches!(
+            text_values(b"a\n", 2, &mut String::new()),
+            Err(TableRowConversionError::NumColsMismatch)
+        ));
+    }
+
+    #[test]
+    fn rows_must_be_terminated() {
+        assert!(matches!(
+            text_values(b"a\tb", 2, &mut String::new()),
+            Err(TableRowConversionError::UnterminatedRow)
+        ));
     }
 }
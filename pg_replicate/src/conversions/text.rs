@@ -7,7 +7,10 @@ use thiserror::Error;
 use tokio_postgres::types::Type;
 use uuid::Uuid;
 
-use crate::conversions::{bool::parse_bool, hex};
+use crate::{
+    conversions::{bool::parse_bool, hex},
+    table::TypeOverride,
+};
 
 use super::{bool::ParseBoolError, hex::ByteaHexParseError, numeric::PgNumeric, ArrayCell, Cell};
 
@@ -42,6 +45,9 @@ pub enum FromTextError {
 
     #[error("row get error: {0:?}")]
     RowGetError(#[from] Box<dyn std::error::Error + Sync + Send>),
+
+    #[error("epoch {0} is out of the range of timestamps")]
+    EpochOutOfRange(i64),
 }
 
 pub struct TextFormatConverter;
@@ -55,6 +61,23 @@ pub enum ArrayParseError {
     MissingBraces,
 }
 
+/// Parses a `timestamptz` value. Values without an offset are values of
+/// `timestamp` columns replicated as `timestamptz` columns, which are taken
+/// as UTC times.
+fn parse_timestamptz(str: &str) -> Result<DateTime<Utc>, FromTextError> {
+    let val = match DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%#z") {
+        Ok(val) => val,
+        Err(_) => match DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%:z") {
+            Ok(val) => val,
+            Err(_) => {
+                let val = NaiveDateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f")?;
+                return Ok(val.and_utc());
+            }
+        },
+    };
+    Ok(val.into())
+}
+
 impl TextFormatConverter {
     pub fn default_value(typ: &Type) -> Cell {
         match *typ {
@@ -99,12 +122,112 @@ impl TextFormatConverter {
             Type::JSON_ARRAY | Type::JSONB_ARRAY => Cell::Array(ArrayCell::Json(Vec::default())),
             Type::OID => Cell::U32(u32::default()),
             Type::OID_ARRAY => Cell::Array(ArrayCell::U32(Vec::default())),
-            #[cfg(feature = "unknown_types_to_bytes")]
             _ => Cell::String(String::default()),
-            #[cfg(not(feature = "unknown_types_to_bytes"))]
-            _ => Err(CdcEventConversionError::UnsupportedType(
-                typ.name().to_string(),
-            )),
+        }
+    }
+
+    /// Returns whether values of the type are parsed. Values of other types
+    /// are kept as the text Postgres sends for them.
+    pub fn is_supported(typ: &Type) -> bool {
+        matches!(
+            *typ,
+            Type::BOOL
+                | Type::BOOL_ARRAY
+                | Type::CHAR
+                | Type::BPCHAR
+                | Type::VARCHAR
+                | Type::NAME
+                | Type::TEXT
+                | Type::CHAR_ARRAY
+                | Type::BPCHAR_ARRAY
+                | Type::VARCHAR_ARRAY
+                | Type::NAME_ARRAY
+                | Type::TEXT_ARRAY
+                | Type::INT2
+                | Type::INT2_ARRAY
+                | Type::INT4
+                | Type::INT4_ARRAY
+                | Type::INT8
+                | Type::INT8_ARRAY
+                | Type::FLOAT4
+                | Type::FLOAT4_ARRAY
+                | Type::FLOAT8
+                | Type::FLOAT8_ARRAY
+                | Type::NUMERIC
+                | Type::NUMERIC_ARRAY
+                | Type::BYTEA
+                | Type::BYTEA_ARRAY
+                | Type::DATE
+                | Type::DATE_ARRAY
+                | Type::TIME
+                | Type::TIME_ARRAY
+                | Type::TIMESTAMP
+                | Type::TIMESTAMP_ARRAY
+                | Type::TIMESTAMPTZ
+                | Type::TIMESTAMPTZ_ARRAY
+                | Type::UUID
+                | Type::UUID_ARRAY
+                | Type::JSON
+                | Type::JSONB
+                | Type::JSON_ARRAY
+                | Type::JSONB_ARRAY
+                | Type::OID
+                | Type::OID_ARRAY
+        )
+    }
+
+    /// Converts a value parsed as [`TypeOverride::parsed_typ`] to the type
+    /// of the override
+    pub fn try_override(type_override: TypeOverride, cell: Cell) -> Result<Cell, FromTextError> {
+        let Cell::I64(epoch) = cell else {
+            return Ok(cell);
+        };
+        let value = match type_override {
+            TypeOverride::EpochSeconds => DateTime::from_timestamp(epoch, 0),
+            TypeOverride::EpochMillis => DateTime::from_timestamp_millis(epoch),
+            TypeOverride::EpochMicros => DateTime::from_timestamp_micros(epoch),
+            TypeOverride::Json | TypeOverride::Text => return Ok(Cell::I64(epoch)),
+        };
+        value
+            .map(Cell::TimeStampTz)
+            .ok_or(FromTextError::EpochOutOfRange(epoch))
+    }
+
+    /// Converts a value which may fail to convert to the closest value of
+    /// its type. Postgres only sends values which fail to convert for dates
+    /// and timestamps out of the range of the parsed types, like `infinity`
+    /// or BC dates, which are coerced to the earliest or latest one. Values
+    /// of other types are coerced to null.
+    pub fn coerce_from_str(typ: &Type, str: &str) -> Cell {
+        if let Ok(cell) = Self::try_from_str(typ, str) {
+            return cell;
+        }
+        let earliest = str.starts_with('-') || str.ends_with(" BC");
+        match *typ {
+            Type::DATE if earliest => Cell::Date(NaiveDate::MIN),
+            Type::DATE => Cell::Date(NaiveDate::MAX),
+            Type::TIMESTAMP if earliest => Cell::TimeStamp(NaiveDateTime::MIN),
+            Type::TIMESTAMP => Cell::TimeStamp(NaiveDateTime::MAX),
+            Type::TIMESTAMPTZ if earliest => Cell::TimeStampTz(DateTime::<Utc>::MIN_UTC),
+            Type::TIMESTAMPTZ => Cell::TimeStampTz(DateTime::<Utc>::MAX_UTC),
+            _ => Cell::Null,
+        }
+    }
+
+    /// Converts a value like [`TextFormatConverter::try_override`], with
+    /// epochs out of the range of timestamps coerced to the earliest or
+    /// latest one
+    pub fn coerce_override(type_override: TypeOverride, cell: Cell) -> Cell {
+        match cell {
+            Cell::I64(epoch) => {
+                Self::try_override(type_override, Cell::I64(epoch)).unwrap_or_else(|_| {
+                    Cell::TimeStampTz(match epoch < 0 {
+                        true => DateTime::<Utc>::MIN_UTC,
+                        false => DateTime::<Utc>::MAX_UTC,
+                    })
+                })
+            }
+            cell => cell,
         }
     }
 
@@ -192,46 +315,12 @@ impl TextFormatConverter {
                 },
                 ArrayCell::TimeStamp,
             ),
-            Type::TIMESTAMPTZ => {
-                let val =
-                    match DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%#z") {
-                        Ok(val) => val,
-                        Err(_) => {
-                            DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%:z")?
-                        }
-                    };
-                Ok(Cell::TimeStampTz(val.into()))
-            }
-            Type::TIMESTAMPTZ_ARRAY => {
-                match TextFormatConverter::parse_array(
-                    str,
-                    |str| {
-                        Ok(Some(
-                            DateTime::<FixedOffset>::parse_from_str(
-                                str,
-                                "%Y-%m-%d %H:%M:%S%.f%#z",
-                            )?
-                            .into(),
-                        ))
-                    },
-                    ArrayCell::TimeStampTz,
-                ) {
-                    Ok(val) => Ok(val),
-                    Err(_) => TextFormatConverter::parse_array(
-                        str,
-                        |str| {
-                            Ok(Some(
-                                DateTime::<FixedOffset>::parse_from_str(
-                                    str,
-                                    "%Y-%m-%d %H:%M:%S%.f%:z",
-                                )?
-                                .into(),
-                            ))
-                        },
-                        ArrayCell::TimeStampTz,
-                    ),
-                }
-            }
+            Type::TIMESTAMPTZ => Ok(Cell::TimeStampTz(parse_timestamptz(str)?)),
+            Type::TIMESTAMPTZ_ARRAY => TextFormatConverter::parse_array(
+                str,
+                |str| Ok(Some(parse_timestamptz(str)?)),
+                ArrayCell::TimeStampTz,
+            ),
             Type::UUID => {
                 let val = Uuid::parse_str(str)?;
                 Ok(Cell::Uuid(val))
@@ -257,12 +346,7 @@ impl TextFormatConverter {
             Type::OID_ARRAY => {
                 TextFormatConverter::parse_array(str, |str| Ok(Some(str.parse()?)), ArrayCell::U32)
             }
-            #[cfg(feature = "unknown_types_to_bytes")]
             _ => Ok(Cell::String(str.to_string())),
-            #[cfg(not(feature = "unknown_types_to_bytes"))]
-            _ => Err(CdcEventConversionError::UnsupportedType(
-                typ.name().to_string(),
-            )),
         }
     }
 
@@ -281,44 +365,73 @@ impl TextFormatConverter {
 
         let mut res = vec![];
         let str = &str[1..(str.len() - 1)];
-        let mut val_str = String::with_capacity(10);
-        let mut in_quotes = false;
-        let mut in_escape = false;
-        let mut chars = str.chars();
-        let mut done = str.is_empty();
-
-        while !done {
-            loop {
-                match chars.next() {
-                    Some(c) => match c {
-                        c if in_escape => {
-                            val_str.push(c);
-                            in_escape = false;
-                        }
-                        '"' => in_quotes = !in_quotes,
-                        '\\' => in_escape = true,
-                        ',' if !in_quotes => {
-                            break;
-                        }
-                        c => {
-                            val_str.push(c);
-                        }
-                    },
-                    None => {
-                        done = true;
-                        break;
+        if str.is_empty() {
+            return Ok(Cell::Array(m(res)));
+        }
+
+        // Elements without quotes or escapes are parsed straight from `str`,
+        // only the others are unescaped into `val_str`.
+        let bytes = str.as_bytes();
+        let mut val_str = String::new();
+        let mut start = 0;
+
+        loop {
+            let mut end = start;
+            let mut quoted = false;
+            let mut escaped = false;
+            let mut in_quotes = false;
+            let mut in_escape = false;
+
+            while end < bytes.len() {
+                match bytes[end] {
+                    _ if in_escape => in_escape = false,
+                    b'"' => {
+                        in_quotes = !in_quotes;
+                        quoted = true;
                     }
+                    b'\\' => {
+                        in_escape = true;
+                        escaped = true;
+                    }
+                    b',' if !in_quotes => break,
+                    _ => {}
                 }
+                end += 1;
             }
-            let val = if val_str.to_lowercase() == "null" {
+
+            let element = &str[start..end];
+            let val = if quoted || escaped {
+                val_str.clear();
+                Self::unescape_array_element(element, &mut val_str);
+                parse(&val_str)?
+            } else if element.eq_ignore_ascii_case("null") {
                 None
             } else {
-                parse(&val_str)?
+                parse(element)?
             };
             res.push(val);
-            val_str.clear();
+
+            if end == bytes.len() {
+                break;
+            }
+            start = end + 1;
         }
 
         Ok(Cell::Array(m(res)))
     }
+
+    fn unescape_array_element(element: &str, val_str: &mut String) {
+        let mut in_escape = false;
+        for c in element.chars() {
+            match c {
+                c if in_escape => {
+                    val_str.push(c);
+                    in_escape = false;
+                }
+                '"' => {}
+                '\\' => in_escape = true,
+                c => val_str.push(c),
+            }
+        }
+    }
 }
@@ -0,0 +1,124 @@
+//! Errors of pipelines classified by where they happened and whether
+//! retrying may succeed, so that embedders can restart pipelines failing
+//! with errors which go away, like lost connections and rate limits, and
+//! alert on the others, like missing tables or values which can't be
+//! converted.
+
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::pipeline::{
+    dead_letters::DeadLetterError, encryption::EncryptionError, sinks::SinkError,
+    sources::SourceError, PipelineError,
+};
+
+/// Where in a pipeline an error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading the source's tables or changes
+    Source,
+
+    /// Writing to the sink
+    Sink,
+
+    /// Converting the values of the source's rows, which fails again when
+    /// the rows are read again
+    Conversion,
+
+    /// Encrypting the values of columns
+    Encryption,
+
+    /// Writing the rows which failed to convert to the dead letters
+    DeadLetter,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::Source => write!(f, "source"),
+            ErrorKind::Sink => write!(f, "sink"),
+            ErrorKind::Conversion => write!(f, "conversion"),
+            ErrorKind::Encryption => write!(f, "encryption"),
+            ErrorKind::DeadLetter => write!(f, "dead letter"),
+        }
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The error of a pipeline with the types of its source and sink erased,
+/// for embedders which handle the errors of pipelines with different
+/// sources and sinks alike
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("source error: {error}")]
+    Source {
+        #[source]
+        error: BoxError,
+        retryable: bool,
+    },
+
+    #[error("sink error: {error}")]
+    Sink {
+        #[source]
+        error: BoxError,
+        retryable: bool,
+    },
+
+    #[error("conversion error: {0}")]
+    Conversion(#[source] BoxError),
+
+    #[error("encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+
+    #[error("dead letter error: {0}")]
+    DeadLetter(#[from] DeadLetterError),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Source { .. } => ErrorKind::Source,
+            Error::Sink { .. } => ErrorKind::Sink,
+            Error::Conversion(_) => ErrorKind::Conversion,
+            Error::Encryption(_) => ErrorKind::Encryption,
+            Error::DeadLetter(_) => ErrorKind::DeadLetter,
+        }
+    }
+
+    /// Whether starting the pipeline again may succeed. It resumes after
+    /// the changes the sink wrote, so nothing is lost by retrying
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Source { retryable, .. } | Error::Sink { retryable, .. } => *retryable,
+            Error::Conversion(_) | Error::Encryption(_) | Error::DeadLetter(_) => false,
+        }
+    }
+}
+
+impl<SrcErr: SourceError, SnkErr: SinkError> From<PipelineError<SrcErr, SnkErr>> for Error {
+    fn from(e: PipelineError<SrcErr, SnkErr>) -> Self {
+        let kind = e.kind();
+        let retryable = e.is_retryable();
+        match e {
+            PipelineError::Source(error) => Error::Source {
+                error: Box::new(error),
+                retryable,
+            },
+            PipelineError::Sink(error) => Error::Sink {
+                error: Box::new(error),
+                retryable,
+            },
+            PipelineError::CommonSource(error) if kind == ErrorKind::Conversion => {
+                Error::Conversion(Box::new(error))
+            }
+            PipelineError::CommonSource(error) => Error::Source {
+                error: Box::new(error),
+                retryable,
+            },
+            PipelineError::Encryption(error) => Error::Encryption(error),
+            PipelineError::DeadLetter(error) => Error::DeadLetter(error),
+        }
+    }
+}
@@ -1,4 +1,9 @@
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod clients;
 pub mod conversions;
+pub mod error;
 pub mod pipeline;
 pub mod table;
+#[cfg(feature = "testing")]
+pub mod testing;
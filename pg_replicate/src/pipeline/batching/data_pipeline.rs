@@ -1,19 +1,39 @@
-use std::{collections::HashSet, time::Instant};
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    pin::Pin,
+    time::Instant,
+};
 
-use futures::StreamExt;
-use tokio::pin;
+use futures::{Stream, StreamExt};
+use tokio::{
+    pin,
+    sync::{mpsc, watch},
+};
 use tokio_postgres::types::PgLsn;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::{
-    conversions::cdc_event::{CdcEvent, CdcEventConversionError},
+    conversions::{
+        cdc_event::{CdcEvent, CdcEventConversionError},
+        table_row::{TableRow, TableRowConversionError},
+    },
+    error::ErrorKind,
     pipeline::{
         batching::stream::BatchTimeoutStream,
-        sinks::BatchSink,
-        sources::{postgres::CdcStreamError, CommonSourceError, Source},
-        PipelineAction, PipelineError,
+        dead_letters::{write_dead_letter, DeadLetterWriter},
+        encryption::ColumnEncryptor,
+        lag::WalPositionMonitor,
+        reporting::{ErrorHook, ErrorOrigin},
+        sinks::{BatchSink, RemovedTablePolicy, TransactionMetadata},
+        sources::{
+            postgres::{CdcStreamError, TableCopyStreamError, TableFilter},
+            CdcEventStream, CommonSourceError, Source, StandbyStatus,
+        },
+        status::{PipelinePhase, PipelineStatus, TableCopyStatus, TableMetrics},
+        PipelineAction, PipelineError, PipelineUpdate,
     },
-    table::TableId,
+    table::{TableId, TableName, TableSchema},
 };
 
 use super::BatchConfig;
@@ -23,21 +43,100 @@ pub struct BatchDataPipeline<Src: Source, Snk: BatchSink> {
     sink: Snk,
     action: PipelineAction,
     batch_config: BatchConfig,
+    status: watch::Sender<PipelineStatus>,
+    updates: Option<mpsc::Receiver<PipelineUpdate>>,
+    error_hook: Option<ErrorHook>,
+    /// Table being copied, for the context of errors
+    copying_table: Option<TableName>,
+    wal_position_monitor: Option<WalPositionMonitor>,
+    encryptor: Option<ColumnEncryptor>,
+    removed_table_policy: RemovedTablePolicy,
+    dead_letters: Option<DeadLetterWriter>,
 }
 
 impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
-    pub fn new(source: Src, sink: Snk, action: PipelineAction, batch_config: BatchConfig) -> Self {
+    pub fn new(
+        mut source: Src,
+        sink: Snk,
+        action: PipelineAction,
+        batch_config: BatchConfig,
+    ) -> Self {
+        // rows are decoded into the vectors of the rows the sink hands back
+        if let Some(row_pool) = sink.row_pool() {
+            source.set_row_pool(row_pool);
+        }
         BatchDataPipeline {
             source,
             sink,
             action,
             batch_config,
+            status: watch::Sender::new(PipelineStatus::default()),
+            updates: None,
+            error_hook: None,
+            copying_table: None,
+            wal_position_monitor: None,
+            encryptor: None,
+            removed_table_policy: RemovedTablePolicy::default(),
+            dead_letters: None,
         }
     }
 
+    /// Applies the updates received while changes are streamed. Updates
+    /// received while tables are copied wait until the copies are done
+    pub fn with_updates(mut self, updates: mpsc::Receiver<PipelineUpdate>) -> Self {
+        self.updates = Some(updates);
+        self
+    }
+
+    /// Reports the error stopping the pipeline to the hook
+    pub fn with_error_hook(mut self, error_hook: ErrorHook) -> Self {
+        self.error_hook = Some(error_hook);
+        self
+    }
+
+    /// Computes the lag from the source's WAL position queried by the
+    /// monitor while changes are streamed, in addition to the positions
+    /// the source sends with its keepalives
+    pub fn with_wal_position_monitor(mut self, monitor: WalPositionMonitor) -> Self {
+        self.wal_position_monitor = Some(monitor);
+        self
+    }
+
+    /// Encrypts columns of the rows and changes before they are written to
+    /// the sink
+    pub fn with_column_encryptor(mut self, encryptor: ColumnEncryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Applies the policy to the destination tables of the copied tables
+    /// which the source no longer replicates when the pipeline starts. The
+    /// source must replicate all of the pipeline's tables, not a subset of
+    /// them, or the destination tables of the others would be pruned too.
+    pub fn with_removed_table_policy(mut self, removed_table_policy: RemovedTablePolicy) -> Self {
+        self.removed_table_policy = removed_table_policy;
+        self
+    }
+
+    /// Writes the rows which fail to convert with the dead letter policy to
+    /// the writer. They are only logged without one
+    pub fn with_dead_letter_writer(mut self, writer: DeadLetterWriter) -> Self {
+        self.dead_letters = Some(writer);
+        self
+    }
+
+    /// Returns a receiver of the pipeline's status, which is updated after
+    /// every batch
+    pub fn status(&self) -> watch::Receiver<PipelineStatus> {
+        self.status.subscribe()
+    }
+
     async fn copy_table_schemas(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
         let table_schemas = self.source.get_table_schemas();
-        let table_schemas = table_schemas.clone();
+        let mut table_schemas = table_schemas.clone();
+        if let Some(encryptor) = &mut self.encryptor {
+            table_schemas = encryptor.encrypt_schemas(table_schemas);
+        }
 
         if !table_schemas.is_empty() {
             self.sink
@@ -49,6 +148,67 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
         Ok(())
     }
 
+    /// Prunes the destination tables of the copied tables which the source
+    /// no longer replicates
+    async fn prune_removed_tables(
+        &mut self,
+        copied_tables: &HashSet<TableId>,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let table_schemas = self.source.get_table_schemas();
+        let mut removed_tables: Vec<TableId> = copied_tables
+            .iter()
+            .filter(|table_id| !table_schemas.contains_key(table_id))
+            .copied()
+            .collect();
+        removed_tables.sort();
+
+        for table_id in removed_tables {
+            let table_name = self
+                .source
+                .get_table_name(table_id)
+                .await
+                .map_err(PipelineError::Source)?;
+            match &table_name {
+                Some(table_name) => info!(
+                    "pruning table {table_name} which is no longer replicated: {:?}",
+                    self.removed_table_policy
+                ),
+                None => warn!("forgetting the copy of table {table_id} which no longer exists"),
+            }
+            self.sink
+                .prune_table(table_id, table_name.as_ref(), self.removed_table_policy)
+                .await
+                .map_err(PipelineError::Sink)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the status of a table before its copy, with its size
+    /// estimated if it's to be copied. The table is copied without an
+    /// estimate if its size can't be estimated
+    async fn table_copy_status(
+        source: &Src,
+        table_schema: &TableSchema,
+        copied: bool,
+    ) -> TableCopyStatus {
+        let estimate = if copied {
+            None
+        } else {
+            match source.get_table_size_estimate(table_schema.table_id).await {
+                Ok(estimate) => estimate,
+                Err(e) => {
+                    warn!(
+                        "failed to estimate the size of table {}: {e}",
+                        table_schema.table_name
+                    );
+                    None
+                }
+            }
+        };
+        TableCopyStatus::new(table_schema.table_name.clone(), copied, estimate)
+    }
+
     async fn copy_tables(
         &mut self,
         copied_tables: &HashSet<TableId>,
@@ -59,6 +219,18 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
         let mut keys: Vec<u32> = table_schemas.keys().copied().collect();
         keys.sort();
 
+        let mut table_statuses = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let table_schema = table_schemas.get(key).expect("failed to get table key");
+            let copied = copied_tables.contains(&table_schema.table_id);
+            let table_status = Self::table_copy_status(&self.source, table_schema, copied).await;
+            table_statuses.push((table_schema.table_id, table_status));
+        }
+        self.status.send_modify(|status| {
+            status.phase = PipelinePhase::CopyingTables;
+            status.tables.extend(table_statuses);
+        });
+
         for key in keys {
             let table_schema = table_schemas.get(&key).expect("failed to get table key");
             if copied_tables.contains(&table_schema.table_id) {
@@ -66,6 +238,7 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
                 continue;
             }
 
+            self.copying_table = Some(table_schema.table_name.clone());
             self.sink
                 .truncate_table(table_schema.table_id)
                 .await
@@ -77,37 +250,185 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
                 .await
                 .map_err(PipelineError::Source)?;
 
-            let batch_timeout_stream =
-                BatchTimeoutStream::new(table_rows, self.batch_config.clone());
+            Self::copy_table_rows(
+                &mut self.sink,
+                &self.status,
+                &self.batch_config.for_table_copy(&table_schema.table_name),
+                self.encryptor.as_ref(),
+                self.dead_letters.as_mut(),
+                table_schema.table_id,
+                table_rows,
+            )
+            .await?;
+        }
+        self.copying_table = None;
+        self.source
+            .commit_transaction()
+            .await
+            .map_err(PipelineError::Source)?;
 
-            pin!(batch_timeout_stream);
+        let end = Instant::now();
+        let seconds = (end - start).as_secs();
+        debug!("took {seconds} seconds to copy tables");
 
-            while let Some(batch) = batch_timeout_stream.next().await {
-                info!("got {} table copy events in a batch", batch.len());
-                //TODO: Avoid a vec copy
-                let mut rows = Vec::with_capacity(batch.len());
-                for row in batch {
-                    rows.push(row.map_err(CommonSourceError::TableCopyStream)?);
+        Ok(())
+    }
+
+    /// Writes the rows of a table copy to the sink and marks the table as
+    /// copied
+    async fn copy_table_rows(
+        sink: &mut Snk,
+        status: &watch::Sender<PipelineStatus>,
+        batch_config: &BatchConfig,
+        encryptor: Option<&ColumnEncryptor>,
+        mut dead_letters: Option<&mut DeadLetterWriter>,
+        table_id: TableId,
+        table_rows: impl Stream<Item = Result<TableRow, TableCopyStreamError>>,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let copy_start = Instant::now();
+        let batch_timeout_stream = BatchTimeoutStream::new(table_rows, batch_config.clone());
+
+        pin!(batch_timeout_stream);
+
+        let mut prefetched = None;
+        loop {
+            let batch = match prefetched.take() {
+                Some(batch) => batch,
+                None => batch_timeout_stream.next().await,
+            };
+            let Some(batch) = batch else {
+                break;
+            };
+            info!("got {} table copy events in a batch", batch.len());
+            //TODO: Avoid a vec copy
+            let mut rows = Vec::with_capacity(batch.len());
+            let mut failed_columns = vec![];
+            for row in batch {
+                let mut row = match row {
+                    Err(TableCopyStreamError::ConversionError(
+                        TableRowConversionError::DeadLetter(dead_letter),
+                    )) => {
+                        write_dead_letter(dead_letters.as_deref_mut(), &dead_letter).await?;
+                        failed_columns.push(dead_letter.column);
+                        continue;
+                    }
+                    row => row.map_err(CommonSourceError::TableCopyStream)?,
+                };
+                failed_columns.append(&mut row.failed_columns);
+                if let Some(encryptor) = encryptor {
+                    encryptor.encrypt_row(table_id, &mut row)?;
                 }
-                self.sink
-                    .write_table_rows(rows, table_schema.table_id)
-                    .await
-                    .map_err(PipelineError::Sink)?;
+                rows.push(row);
+            }
+            let row_count = rows.len() as u64;
+            let write_start = Instant::now();
+            write_while_prefetching(
+                sink.write_table_rows(rows, table_id),
+                batch_timeout_stream.as_mut(),
+                &mut prefetched,
+            )
+            .await
+            .map_err(PipelineError::Sink)?;
+            let sink_latency = write_start.elapsed();
+            status.send_modify(|status| {
+                if let Some(table) = status.tables.get_mut(&table_id) {
+                    table.rows_copied += row_count;
+                    table.copy_duration = copy_start.elapsed();
+                }
+                let metrics = status.table_metrics.entry(table_id).or_default();
+                metrics.sink_latency.observe(sink_latency);
+                for column in &failed_columns {
+                    metrics.count_conversion_error(column);
+                }
+            });
+        }
+
+        sink.table_copied(table_id)
+            .await
+            .map_err(PipelineError::Sink)?;
+        status.send_modify(|status| {
+            if let Some(table) = status.tables.get_mut(&table_id) {
+                table.copied = true;
+                table.copy_duration = copy_start.elapsed();
             }
+        });
 
+        Ok(())
+    }
+
+    /// Copies the tables selected by the filter which the cdc stream doesn't
+    /// replicate yet over a new connection, and adds them to the stream
+    async fn add_tables(
+        &mut self,
+        table_filter: Option<&TableFilter>,
+        mut cdc_stream: Pin<&mut Src::CdcStream>,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let table_copier = self
+            .source
+            .get_table_copier(table_filter)
+            .await
+            .map_err(PipelineError::Source)?;
+        let mut added_table_schemas: Vec<TableSchema> = table_copier
+            .table_schemas()
+            .values()
+            .filter(|table_schema| {
+                !cdc_stream
+                    .table_schemas()
+                    .contains_key(&table_schema.table_id)
+            })
+            .cloned()
+            .collect();
+        added_table_schemas.sort_by_key(|table_schema| table_schema.table_id);
+
+        if !added_table_schemas.is_empty() {
+            cdc_stream.as_mut().add_table_schemas(
+                added_table_schemas
+                    .iter()
+                    .map(|table_schema| (table_schema.table_id, table_schema.clone()))
+                    .collect(),
+            );
+            let mut table_schemas = cdc_stream.table_schemas().clone();
+            if let Some(encryptor) = &mut self.encryptor {
+                table_schemas = encryptor.encrypt_schemas(table_schemas);
+            }
             self.sink
-                .table_copied(table_schema.table_id)
+                .write_table_schemas(table_schemas)
                 .await
                 .map_err(PipelineError::Sink)?;
         }
-        self.source
+
+        for table_schema in added_table_schemas {
+            info!("adding table {}", table_schema.table_name);
+            let table_status = Self::table_copy_status(&self.source, &table_schema, false).await;
+            self.status.send_modify(|status| {
+                status.tables.insert(table_schema.table_id, table_status);
+            });
+
+            self.sink
+                .truncate_table(table_schema.table_id)
+                .await
+                .map_err(PipelineError::Sink)?;
+
+            let table_rows = table_copier
+                .get_table_copy_stream(&table_schema.table_name, &table_schema.column_schemas)
+                .await
+                .map_err(CommonSourceError::Postgres)?;
+
+            Self::copy_table_rows(
+                &mut self.sink,
+                &self.status,
+                &self.batch_config.for_table_copy(&table_schema.table_name),
+                self.encryptor.as_ref(),
+                self.dead_letters.as_mut(),
+                table_schema.table_id,
+                table_rows,
+            )
+            .await?;
+        }
+        table_copier
             .commit_transaction()
             .await
-            .map_err(PipelineError::Source)?;
-
-        let end = Instant::now();
-        let seconds = (end - start).as_secs();
-        debug!("took {seconds} seconds to copy tables");
+            .map_err(CommonSourceError::Postgres)?;
 
         Ok(())
     }
@@ -130,39 +451,168 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
 
         pin!(batch_timeout_stream);
 
-        while let Some(batch) = batch_timeout_stream.next().await {
+        self.status
+            .send_modify(|status| status.phase = PipelinePhase::Cdc);
+        let wal_position = self
+            .wal_position_monitor
+            .take()
+            .map(WalPositionMonitor::spawn);
+
+        let mut prefetched = None;
+        let mut transaction = None;
+        let mut applied_lsn = PgLsn::from(0);
+        loop {
+            let batch = match prefetched.take() {
+                Some(batch) => batch,
+                None => tokio::select! {
+                    batch = batch_timeout_stream.next() => batch,
+                    Some(update) = next_update(&mut self.updates) => {
+                        match update {
+                            PipelineUpdate::BatchConfig(batch_config) => {
+                                info!("updating batch config to {batch_config:?}");
+                                batch_timeout_stream
+                                    .as_mut()
+                                    .set_batch_config(batch_config.clone());
+                                self.batch_config = batch_config;
+                            }
+                            PipelineUpdate::Tables(table_filter) => {
+                                let inner = unsafe {
+                                    batch_timeout_stream
+                                        .as_mut()
+                                        .get_unchecked_mut()
+                                        .get_inner_mut()
+                                };
+                                self.add_tables(table_filter.as_ref(), inner.as_mut())
+                                    .await?;
+                            }
+                        }
+                        continue;
+                    }
+                },
+            };
+            let Some(batch) = batch else {
+                break;
+            };
             info!("got {} cdc events in a batch", batch.len());
             let mut send_status_update = false;
+            let mut wal_end = None;
+            let mut last_commit_timestamp = None;
             let mut events = Vec::with_capacity(batch.len());
+            let mut batch_metrics: BTreeMap<TableId, TableMetrics> = BTreeMap::new();
             for event in batch {
-                if let Err(CdcStreamError::CdcEventConversion(
-                    CdcEventConversionError::MissingSchema(_),
-                )) = event
+                let event = match event {
+                    // changes of tables which aren't replicated are skipped
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::MissingSchema(_),
+                    )) => continue,
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::DeadLetter(table_id, dead_letter),
+                    )) => {
+                        write_dead_letter(self.dead_letters.as_mut(), &dead_letter).await?;
+                        batch_metrics
+                            .entry(table_id)
+                            .or_default()
+                            .count_conversion_error(&dead_letter.column);
+                        continue;
+                    }
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::MissingColumnValue(table_id, column),
+                    )) => {
+                        self.status.send_modify(|status| {
+                            status
+                                .table_metrics
+                                .entry(table_id)
+                                .or_default()
+                                .conversion_errors += 1;
+                        });
+                        let e = CdcStreamError::CdcEventConversion(
+                            CdcEventConversionError::MissingColumnValue(table_id, column),
+                        );
+                        return Err(CommonSourceError::CdcStream(e).into());
+                    }
+                    event => event.map_err(CommonSourceError::CdcStream)?,
+                };
+                match &event {
+                    CdcEvent::KeepAliveRequested {
+                        reply,
+                        wal_end: end,
+                    } => {
+                        send_status_update = *reply;
+                        wal_end = Some(*end);
+                    }
+                    CdcEvent::Commit(commit_body) => {
+                        last_commit_timestamp = Some(commit_body.timestamp());
+                    }
+                    CdcEvent::Insert((table_id, _)) => {
+                        batch_metrics.entry(*table_id).or_default().inserts += 1;
+                    }
+                    CdcEvent::Update((table_id, _)) => {
+                        batch_metrics.entry(*table_id).or_default().updates += 1;
+                    }
+                    CdcEvent::Delete((table_id, _)) => {
+                        batch_metrics.entry(*table_id).or_default().deletes += 1;
+                    }
+                    _ => {}
+                }
+                if let CdcEvent::Insert((table_id, row))
+                | CdcEvent::Update((table_id, row))
+                | CdcEvent::Delete((table_id, row)) = &event
                 {
-                    continue;
+                    if !row.failed_columns.is_empty() {
+                        let metrics = batch_metrics.entry(*table_id).or_default();
+                        for column in &row.failed_columns {
+                            metrics.count_conversion_error(column);
+                        }
+                    }
                 }
-                let event = event.map_err(CommonSourceError::CdcStream)?;
-                if let CdcEvent::KeepAliveRequested { reply } = event {
-                    send_status_update = reply;
+                let event = match &mut self.encryptor {
+                    Some(encryptor) => encryptor.encrypt_event(event)?,
+                    None => event,
                 };
                 events.push(event);
             }
-            let last_lsn = self
-                .sink
-                .write_cdc_events(events)
-                .await
-                .map_err(PipelineError::Sink)?;
-            if send_status_update {
-                info!("sending status update with lsn: {last_lsn}");
-                let inner = unsafe {
-                    batch_timeout_stream
-                        .as_mut()
-                        .get_unchecked_mut()
-                        .get_inner_mut()
+            let write_start = Instant::now();
+            let last_lsn = write_while_prefetching(
+                write_cdc_events(&mut self.sink, events, &mut transaction, &mut applied_lsn),
+                batch_timeout_stream.as_mut(),
+                &mut prefetched,
+            )
+            .await
+            .map_err(PipelineError::Sink)?;
+            let sink_latency = write_start.elapsed();
+            self.status.send_modify(|status| {
+                status.last_flushed_lsn = Some(last_lsn);
+                status.record_batch(batch_metrics, sink_latency);
+                if let Some(position) = wal_position
+                    .as_ref()
+                    .and_then(|position| *position.borrow())
+                {
+                    status.update_wal_position(position);
+                }
+                if let Some(commit_timestamp) = last_commit_timestamp {
+                    status.update_lag(commit_timestamp);
+                }
+                if let Some(wal_end) = wal_end {
+                    status.update_lag_bytes(wal_end);
+                }
+            });
+            let inner = unsafe {
+                batch_timeout_stream
+                    .as_mut()
+                    .get_unchecked_mut()
+                    .get_inner_mut()
+            };
+            if send_status_update || inner.status_update_due() {
+                debug!("sending status update with lsn: {last_lsn}");
+                // the position the sink returned is the one it committed,
+                // which the source clamps the applied position to
+                let standby_status = StandbyStatus {
+                    flushed: last_lsn,
+                    applied: applied_lsn,
                 };
                 inner
                     .as_mut()
-                    .send_status_update(last_lsn)
+                    .send_status_update(standby_status)
                     .await
                     .map_err(CommonSourceError::StatusUpdate)?;
             }
@@ -172,12 +622,45 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
     }
 
     pub async fn start(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let result = self.run().await;
+        if let Err(e) = &result {
+            self.status.send_modify(|status| {
+                status.phase = PipelinePhase::Errored;
+                status.last_error = Some(e.to_string());
+            });
+            if let Some(error_hook) = &self.error_hook {
+                let origin = match e.kind() {
+                    ErrorKind::Sink => ErrorOrigin::Sink,
+                    ErrorKind::Source
+                    | ErrorKind::Conversion
+                    | ErrorKind::Encryption
+                    | ErrorKind::DeadLetter => ErrorOrigin::Source,
+                };
+                error_hook.report(
+                    e,
+                    origin,
+                    self.copying_table.clone(),
+                    self.status.borrow().last_flushed_lsn,
+                    None,
+                    true,
+                );
+            }
+        }
+        result
+    }
+
+    async fn run(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
         let resumption_state = self
             .sink
             .get_resumption_state()
             .await
             .map_err(PipelineError::Sink)?;
 
+        if self.removed_table_policy != RemovedTablePolicy::Keep {
+            self.prune_removed_tables(&resumption_state.copied_tables)
+                .await?;
+        }
+
         match self.action {
             PipelineAction::TableCopiesOnly => {
                 self.copy_table_schemas().await?;
@@ -197,3 +680,88 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
         Ok(())
     }
 }
+
+/// Waits for the next update, forever if there are none or their sender is
+/// gone
+async fn next_update(
+    updates: &mut Option<mpsc::Receiver<PipelineUpdate>>,
+) -> Option<PipelineUpdate> {
+    if let Some(receiver) = updates {
+        if let Some(update) = receiver.recv().await {
+            return Some(update);
+        }
+        *updates = None;
+    }
+    std::future::pending().await
+}
+
+/// Writes the changes of a batch to the sink. Sinks with transaction
+/// callbacks get the changes of each transaction in a separate write,
+/// between the callbacks. `transaction` is the transaction which began but
+/// wasn't committed in the previous batches. Returns the position the sink
+/// committed, and sets `applied_lsn` to the one of the last transaction it
+/// applied.
+async fn write_cdc_events<Snk: BatchSink>(
+    sink: &mut Snk,
+    events: Vec<CdcEvent>,
+    transaction: &mut Option<TransactionMetadata>,
+    applied_lsn: &mut PgLsn,
+) -> Result<PgLsn, Snk::Error> {
+    if !sink.transaction_callbacks() {
+        let last_lsn = sink.write_cdc_events(events).await?;
+        *applied_lsn = last_lsn;
+        return Ok(last_lsn);
+    }
+
+    let mut last_lsn = None;
+    let mut pending = Vec::with_capacity(events.len());
+    for event in events {
+        match &event {
+            CdcEvent::Begin(begin_body) => {
+                if !pending.is_empty() {
+                    last_lsn = Some(sink.write_cdc_events(std::mem::take(&mut pending)).await?);
+                }
+                let begun = TransactionMetadata::from(begin_body);
+                sink.begin_transaction(&begun).await?;
+                *transaction = Some(begun);
+                pending.push(event);
+            }
+            CdcEvent::Commit(_) => {
+                pending.push(event);
+                last_lsn = Some(sink.write_cdc_events(std::mem::take(&mut pending)).await?);
+                if let Some(committed) = transaction.take() {
+                    sink.commit_transaction(&committed).await?;
+                    *applied_lsn = committed.commit_lsn;
+                }
+            }
+            _ => pending.push(event),
+        }
+    }
+    match last_lsn {
+        Some(last_lsn) if pending.is_empty() => Ok(last_lsn),
+        _ => sink.write_cdc_events(pending).await,
+    }
+}
+
+/// Drives a sink write to completion while reading the next batch from
+/// `batches` into `prefetched`, so that decoding the next batch overlaps with
+/// the sink writing the current one. At most one batch is read ahead; an
+/// ended stream is stored as `Some(None)`.
+async fn write_while_prefetching<W, S>(
+    write: W,
+    mut batches: Pin<&mut S>,
+    prefetched: &mut Option<Option<S::Item>>,
+) -> W::Output
+where
+    W: Future,
+    S: Stream,
+{
+    pin!(write);
+    loop {
+        tokio::select! {
+            biased;
+            output = &mut write => return output,
+            batch = batches.next(), if prefetched.is_none() => *prefetched = Some(batch),
+        }
+    }
+}
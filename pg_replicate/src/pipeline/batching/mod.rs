@@ -1,4 +1,6 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
+
+use crate::table::TableName;
 
 pub mod data_pipeline;
 pub mod stream;
@@ -6,6 +8,17 @@ pub mod stream;
 /// A trait to indicate which items in a stream can be the last in a batch.
 pub trait BatchBoundary: Sized {
     fn is_last_in_batch(&self) -> bool;
+
+    /// Whether the item begins a transaction of the source. In transactional
+    /// batches the items of a transaction are never split across batches.
+    fn begins_transaction(&self) -> bool {
+        false
+    }
+
+    /// Whether the item ends a transaction of the source
+    fn ends_transaction(&self) -> bool {
+        false
+    }
 }
 
 // For an item wrapped in a result we fall back to the item
@@ -18,12 +31,30 @@ impl<T: BatchBoundary, E> BatchBoundary for Result<T, E> {
             Err(_) => true,
         }
     }
+
+    fn begins_transaction(&self) -> bool {
+        match self {
+            Ok(v) => v.begins_transaction(),
+            Err(_) => false,
+        }
+    }
+
+    fn ends_transaction(&self) -> bool {
+        match self {
+            Ok(v) => v.ends_transaction(),
+            Err(_) => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BatchConfig {
     max_batch_size: usize,
     max_batch_fill_time: Duration,
+    transactional: bool,
+    /// Sizes of the batches of tables' copies, keyed by the table name in
+    /// `schema.name` form
+    table_max_batch_sizes: HashMap<String, usize>,
 }
 
 impl BatchConfig {
@@ -31,6 +62,40 @@ impl BatchConfig {
         BatchConfig {
             max_batch_size,
             max_batch_fill_time,
+            transactional: false,
+            table_max_batch_sizes: HashMap::new(),
+        }
+    }
+
+    /// Ends batches only on the commits of the source's transactions, when
+    /// the batch is full or its fill time elapsed, so that sinks applying a
+    /// batch atomically never expose part of a transaction. Batches otherwise
+    /// also end on keepalives, which the source sends in the middle of large
+    /// transactions too. A transaction is held in memory until its commit,
+    /// and the keepalives received meanwhile are only answered after it, so
+    /// the source's `wal_sender_timeout` must be longer than it takes to
+    /// receive the largest transaction.
+    pub fn with_transactional(mut self, transactional: bool) -> Self {
+        self.transactional = transactional;
+        self
+    }
+
+    /// Fills the batches of a table's copy up to another size, e.g. larger
+    /// batches for a large table with small rows. The table is named in
+    /// `schema.name` form. Changes of all tables share batches, which are
+    /// filled up to the config's size.
+    pub fn with_table_max_batch_size(mut self, table_name: String, max_batch_size: usize) -> Self {
+        self.table_max_batch_sizes
+            .insert(table_name, max_batch_size);
+        self
+    }
+
+    /// Returns the config of the batches of the table's copy
+    pub(crate) fn for_table_copy(&self, table_name: &TableName) -> BatchConfig {
+        let mut batch_config = self.clone();
+        if let Some(max_batch_size) = self.table_max_batch_sizes.get(&table_name.to_string()) {
+            batch_config.max_batch_size = *max_batch_size;
         }
+        batch_config
     }
 }
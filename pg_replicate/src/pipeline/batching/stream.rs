@@ -12,7 +12,8 @@ pin_project! {
     /// Adapter stream which batches the items of the underlying stream when it
     /// reaches max_size or when a timeout expires. The underlying streams items
     /// must implement [`BatchBoundary`]. A batch is guaranteed to end on an
-    /// item which returns true from [`BatchBoundary::is_last_in_batch`]
+    /// item which returns true from [`BatchBoundary::is_last_in_batch`], and
+    /// with a transactional [`BatchConfig`] outside of a transaction
     #[must_use = "streams do nothing unless polled"]
     #[derive(Debug)]
     pub struct BatchTimeoutStream<B: BatchBoundary, S: Stream<Item = B>> {
@@ -24,6 +25,8 @@ pin_project! {
         batch_config: BatchConfig,
         reset_timer: bool,
         inner_stream_ended: bool,
+        in_transaction: bool,
+        last_is_boundary: bool,
     }
 }
 
@@ -36,12 +39,19 @@ impl<B: BatchBoundary, S: Stream<Item = B>> BatchTimeoutStream<B, S> {
             batch_config,
             reset_timer: true,
             inner_stream_ended: false,
+            in_transaction: false,
+            last_is_boundary: false,
         }
     }
 
     pub fn get_inner_mut(&mut self) -> &mut S {
         &mut self.stream
     }
+
+    /// Changes the size and fill time of the batches from the next batch on
+    pub fn set_batch_config(self: Pin<&mut Self>, batch_config: BatchConfig) {
+        *self.project().batch_config = batch_config;
+    }
 }
 
 impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S> {
@@ -64,7 +74,15 @@ impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S>
             match this.stream.as_mut().poll_next(cx) {
                 Poll::Pending => break,
                 Poll::Ready(Some(item)) => {
-                    let is_last_in_batch = item.is_last_in_batch();
+                    if item.begins_transaction() {
+                        *this.in_transaction = true;
+                    }
+                    if item.ends_transaction() {
+                        *this.in_transaction = false;
+                    }
+                    let is_last_in_batch = item.is_last_in_batch()
+                        && !(this.batch_config.transactional && *this.in_transaction);
+                    *this.last_is_boundary = is_last_in_batch;
                     this.items.push(item);
                     if this.items.len() >= this.batch_config.max_batch_size && is_last_in_batch {
                         *this.reset_timer = true;
@@ -91,8 +109,7 @@ impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S>
                 ready!(deadline.poll(cx));
             }
 
-            let last_item = this.items.last().expect("missing last item");
-            if last_item.is_last_in_batch() {
+            if *this.last_is_boundary {
                 *this.reset_timer = true;
                 return Poll::Ready(Some(std::mem::take(this.items)));
             }
@@ -0,0 +1,334 @@
+//! Classification of the columns of the source's tables which likely hold
+//! personal data, like email addresses, phone numbers, social security
+//! numbers and card numbers, before a pipeline is started.
+//!
+//! Columns are classified by their names and by the values of a sample of
+//! the first rows of their tables. Values are only sampled from text
+//! columns, and a column is classified by its values if enough of its
+//! sampled non-null values look like personal data of the same kind. The
+//! classification is a heuristic, it may miss columns and classify columns
+//! which hold no personal data. The classified columns can be encrypted
+//! with the configured ones, see [`ColumnClassification::encrypted_column`].
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::Type;
+
+use crate::{
+    pipeline::{
+        encryption::EncryptedColumn,
+        sources::{
+            postgres::{PostgresSource, PostgresSourceError},
+            Source,
+        },
+    },
+    table::TableName,
+};
+
+/// A kind of personal data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+    Email,
+    PhoneNumber,
+    Ssn,
+    CardNumber,
+}
+
+impl Display for PiiKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PiiKind::Email => "email addresses",
+            PiiKind::PhoneNumber => "phone numbers",
+            PiiKind::Ssn => "social security numbers",
+            PiiKind::CardNumber => "card numbers",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassificationOptions {
+    /// Number of rows of each table whose values are sampled
+    #[serde(default = "default_sample_size")]
+    pub sample_size: u32,
+
+    /// Percentage of a column's sampled non-null values which must look
+    /// like personal data of the same kind to classify it
+    #[serde(default = "default_min_match_percent")]
+    pub min_match_percent: u8,
+
+    /// Whether the classified columns are encrypted deterministically, in
+    /// addition to the configured encrypted columns
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+impl Default for ClassificationOptions {
+    fn default() -> Self {
+        ClassificationOptions {
+            sample_size: default_sample_size(),
+            min_match_percent: default_min_match_percent(),
+            encrypt: false,
+        }
+    }
+}
+
+fn default_sample_size() -> u32 {
+    100
+}
+
+fn default_min_match_percent() -> u8 {
+    80
+}
+
+/// Why a column was classified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "by")]
+pub enum Evidence {
+    /// The column's name suggests the kind of its values
+    Name,
+
+    /// Some of the column's sampled non-null values look like the kind
+    Values { matching: u32, sampled: u32 },
+}
+
+/// A column which likely holds personal data
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnClassification {
+    #[serde(serialize_with = "serialize_table_name")]
+    pub table_name: TableName,
+    pub column: String,
+    pub kind: PiiKind,
+    pub evidence: Evidence,
+}
+
+impl ColumnClassification {
+    /// The deterministically encrypted column, so that it can still be
+    /// joined on
+    pub fn encrypted_column(&self) -> EncryptedColumn {
+        EncryptedColumn {
+            column: format!("{}.{}", self.table_name, self.column),
+            deterministic: true,
+        }
+    }
+}
+
+impl Display for ColumnClassification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{} likely holds {}",
+            self.table_name, self.column, self.kind
+        )?;
+        match self.evidence {
+            Evidence::Name => write!(f, " (by its name)"),
+            Evidence::Values { matching, sampled } => {
+                write!(f, " ({matching} of {sampled} sampled values)")
+            }
+        }
+    }
+}
+
+fn serialize_table_name<S: serde::Serializer>(
+    table_name: &TableName,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(table_name)
+}
+
+/// Classifies the columns of the source's tables, ordered by the tables'
+/// and the columns' names
+pub async fn classify(
+    source: &PostgresSource,
+    options: &ClassificationOptions,
+) -> Result<Vec<ColumnClassification>, PostgresSourceError> {
+    let mut table_schemas: Vec<_> = source.get_table_schemas().values().collect();
+    table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+
+    let mut classifications = vec![];
+    for table_schema in table_schemas {
+        let text_columns: Vec<String> = table_schema
+            .column_schemas
+            .iter()
+            .filter(|column_schema| is_text(&column_schema.typ))
+            .map(|column_schema| column_schema.name.clone())
+            .collect();
+        let rows = if text_columns.is_empty() || options.sample_size == 0 {
+            vec![]
+        } else {
+            source
+                .sample_column_values(&table_schema.table_name, &text_columns, options.sample_size)
+                .await?
+        };
+
+        let mut table_classifications = BTreeMap::new();
+        for (i, column) in text_columns.iter().enumerate() {
+            let values = rows.iter().filter_map(|row| row[i].as_deref());
+            if let Some((kind, evidence)) = classify_values(values, options.min_match_percent) {
+                table_classifications.insert(column.clone(), (kind, evidence));
+            }
+        }
+        for column_schema in &table_schema.column_schemas {
+            if table_classifications.contains_key(&column_schema.name) {
+                continue;
+            }
+            if let Some(kind) = kind_of_name(&column_schema.name) {
+                table_classifications.insert(column_schema.name.clone(), (kind, Evidence::Name));
+            }
+        }
+
+        classifications.extend(table_classifications.into_iter().map(
+            |(column, (kind, evidence))| ColumnClassification {
+                table_name: table_schema.table_name.clone(),
+                column,
+                kind,
+                evidence,
+            },
+        ));
+    }
+
+    Ok(classifications)
+}
+
+fn is_text(typ: &Type) -> bool {
+    matches!(*typ, Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME)
+}
+
+/// Returns the kind most of the values look like, if enough of them do
+fn classify_values<'a>(
+    values: impl Iterator<Item = &'a str>,
+    min_match_percent: u8,
+) -> Option<(PiiKind, Evidence)> {
+    let mut sampled = 0;
+    let mut matching: BTreeMap<PiiKind, u32> = BTreeMap::new();
+    for value in values {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        sampled += 1;
+        if let Some(kind) = kind_of_value(value) {
+            *matching.entry(kind).or_default() += 1;
+        }
+    }
+
+    let (kind, matching) = matching.into_iter().max_by_key(|(_, matching)| *matching)?;
+    (matching as u64 * 100 >= min_match_percent as u64 * sampled as u64)
+        .then_some((kind, Evidence::Values { matching, sampled }))
+}
+
+/// Returns the kind the column's name suggests, from the words of the name
+fn kind_of_name(name: &str) -> Option<PiiKind> {
+    let name = name.to_lowercase();
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let has = |word: &str| words.contains(&word);
+    let has_pair = |first: &str, second: &str| {
+        words
+            .windows(2)
+            .any(|pair| pair[0] == first && pair[1] == second)
+    };
+
+    if has("email") || has("mail") || has_pair("e", "mail") {
+        Some(PiiKind::Email)
+    } else if has("phone") || has("mobile") || has("msisdn") || has("tel") || has("fax") {
+        Some(PiiKind::PhoneNumber)
+    } else if has("ssn") || has_pair("social", "security") {
+        Some(PiiKind::Ssn)
+    } else if has_pair("card", "number") || has_pair("credit", "card") || has("pan") {
+        Some(PiiKind::CardNumber)
+    } else {
+        None
+    }
+}
+
+/// Returns the kind a trimmed value looks like
+fn kind_of_value(value: &str) -> Option<PiiKind> {
+    if is_email(value) {
+        Some(PiiKind::Email)
+    } else if is_ssn(value) {
+        Some(PiiKind::Ssn)
+    } else if is_card_number(value) {
+        Some(PiiKind::CardNumber)
+    } else if is_phone_number(value) {
+        Some(PiiKind::PhoneNumber)
+    } else {
+        None
+    }
+}
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && !value.contains(char::is_whitespace)
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-'))
+        && domain.contains('.')
+}
+
+/// Whether the value is a US social security number written as
+/// `ddd-dd-dddd`, without the numbers which are never assigned
+fn is_ssn(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [area, group, serial] = parts[..] else {
+        return false;
+    };
+    let digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    digits(area, 3)
+        && digits(group, 2)
+        && digits(serial, 4)
+        && area != "000"
+        && area != "666"
+        && !area.starts_with('9')
+        && group != "00"
+        && serial != "0000"
+}
+
+/// Whether the value is a number of 13 to 19 digits, which may be grouped by
+/// spaces or dashes, passing the Luhn check
+fn is_card_number(value: &str) -> bool {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == ' ' || c == '-')
+    {
+        return false;
+    }
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| match i % 2 {
+            0 => *digit,
+            _ if *digit * 2 > 9 => *digit * 2 - 9,
+            _ => *digit * 2,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Whether the value is a number of 10 to 15 digits, which may start with a
+/// `+` and be grouped by spaces, dashes, dots or parentheses
+fn is_phone_number(value: &str) -> bool {
+    let number = value.strip_prefix('+').unwrap_or(value);
+    if !number
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '.' | '(' | ')'))
+    {
+        return false;
+    }
+    let digits = number.chars().filter(char::is_ascii_digit).count();
+    (10..=15).contains(&digits)
+}
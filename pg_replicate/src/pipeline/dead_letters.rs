@@ -0,0 +1,104 @@
+//! Dead letters, the rows which failed to convert with the
+//! [`ConversionErrorPolicy::DeadLetter`](crate::clients::postgres::ConversionErrorPolicy)
+//! policy or whose values are larger than their limit with the
+//! [`OversizedValuePolicy::DeadLetter`](crate::conversions::size::OversizedValuePolicy)
+//! policy, which are written to a file instead of the sink so that the
+//! pipeline doesn't stop. Each dead letter is a line of json with the table,
+//! the operation, the column which failed to convert, the error and the text
+//! of the row's values, e.g.
+//!
+//! ```json
+//! {"table":"public.events","operation":"insert","column":"payload","error":"invalid json: ...","values":{"id":"1","payload":"{"}}
+//! ```
+//!
+//! Dead letters of a batch are written before the batch is written to the
+//! sink, so they may be written again if the pipeline restarts before the
+//! batch is confirmed.
+
+use std::{collections::BTreeMap, path::Path};
+
+use thiserror::Error;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::error;
+
+use crate::{pipeline::sinks::RowOperation, table::TableName};
+
+#[derive(Debug, Error)]
+pub enum DeadLetterError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A row which failed to convert
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub table_name: TableName,
+    pub operation: RowOperation,
+    /// Column whose value failed to convert
+    pub column: String,
+    pub error: String,
+    /// Text of the row's values by column, none for nulls and for unchanged
+    /// toasted values
+    pub values: BTreeMap<String, Option<String>>,
+}
+
+impl DeadLetter {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "table": self.table_name.to_string(),
+            "operation": self.operation.as_str(),
+            "column": self.column,
+            "error": self.error,
+            "values": self.values,
+        })
+    }
+}
+
+/// Appends dead letters to a file of json lines
+pub struct DeadLetterWriter {
+    file: File,
+}
+
+impl DeadLetterWriter {
+    /// Opens the file to append to, creating it if it doesn't exist
+    pub async fn open(path: &Path) -> Result<DeadLetterWriter, DeadLetterError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(DeadLetterWriter { file })
+    }
+
+    pub async fn write(&mut self, dead_letter: &DeadLetter) -> Result<(), DeadLetterError> {
+        let mut line = serde_json::to_vec(&dead_letter.to_json())?;
+        line.push(b'\n');
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Writes a dead letter with the writer, or logs it if the pipeline has
+/// none
+pub(crate) async fn write_dead_letter(
+    writer: Option<&mut DeadLetterWriter>,
+    dead_letter: &DeadLetter,
+) -> Result<(), DeadLetterError> {
+    match writer {
+        Some(writer) => writer.write(dead_letter).await,
+        None => {
+            error!(
+                "row failed to convert, no dead letter file is configured: {}",
+                dead_letter.to_json()
+            );
+            Ok(())
+        }
+    }
+}
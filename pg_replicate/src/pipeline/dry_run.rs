@@ -0,0 +1,151 @@
+//! A dry run of a pipeline, which reads a few rows of each of the source's
+//! tables and a few changes, transforms them like the pipeline does and
+//! returns what the sink would write for them, without writing anything.
+//! It shows the destination tables the rows go to and the values they get,
+//! e.g. with their encrypted columns encrypted, so that the pipeline's
+//! mappings and transforms can be reviewed before it goes live.
+//!
+//! Changes are read from the source's slot from its confirmed position, and
+//! aren't confirmed, so the slot doesn't advance and the pipeline streams
+//! them again. A slot can only be read by one connection at a time, so the
+//! changes of a running pipeline's slot can't be read.
+
+use std::{collections::HashMap, time::Duration};
+
+use futures::StreamExt;
+use thiserror::Error;
+use tokio::{pin, time::timeout_at};
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::cdc_event::CdcEvent,
+    pipeline::{
+        encryption::{ColumnEncryptor, EncryptionError},
+        sinks::{PreviewRow, PreviewSink, RowOperation, SinkError},
+        sources::{
+            postgres::{CdcStreamError, PostgresSource, PostgresSourceError, TableCopyStreamError},
+            Source,
+        },
+    },
+    table::{TableId, TableSchema},
+};
+
+#[derive(Debug, Error)]
+pub enum DryRunError<SnkErr: SinkError> {
+    #[error("source error: {0}")]
+    Source(#[from] PostgresSourceError),
+
+    #[error("table copy stream error: {0}")]
+    TableCopyStream(#[from] TableCopyStreamError),
+
+    #[error("cdc stream error: {0}")]
+    CdcStream(#[from] CdcStreamError),
+
+    #[error("encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+
+    #[error("sink error: {0}")]
+    Sink(#[source] SnkErr),
+}
+
+#[derive(Debug, Clone)]
+pub struct DryRunOptions {
+    /// Number of rows of each table which are copied
+    pub rows_per_table: u32,
+
+    /// Number of inserts, updates and deletes which are streamed. No
+    /// changes are read if zero, so the source needs no slot.
+    pub changes: usize,
+
+    /// Duration to wait for the changes, as the slot may hold fewer of them
+    pub changes_timeout: Duration,
+}
+
+impl Default for DryRunOptions {
+    fn default() -> Self {
+        DryRunOptions {
+            rows_per_table: 5,
+            changes: 0,
+            changes_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Returns what the sink would write for the sampled rows and changes, in
+/// the sink's serialized form. The rows of the tables' copies come first,
+/// ordered by the tables' names, followed by the changes in the order they
+/// were committed.
+pub async fn dry_run<Snk: PreviewSink>(
+    source: &PostgresSource,
+    sink: &mut Snk,
+    mut encryptor: Option<ColumnEncryptor>,
+    options: &DryRunOptions,
+) -> Result<Vec<String>, DryRunError<Snk::Error>> {
+    let table_schemas = source.get_table_schemas();
+    let sink_table_schemas: HashMap<TableId, TableSchema> = match &mut encryptor {
+        Some(encryptor) => encryptor.encrypt_schemas(table_schemas.clone()),
+        None => table_schemas.clone(),
+    };
+
+    let mut sorted_table_schemas: Vec<&TableSchema> = table_schemas.values().collect();
+    sorted_table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+
+    let mut rows = vec![];
+    for table_schema in sorted_table_schemas {
+        if options.rows_per_table == 0 {
+            break;
+        }
+        let table_rows = source
+            .get_table_sample_stream(
+                &table_schema.table_name,
+                &table_schema.column_schemas,
+                options.rows_per_table,
+            )
+            .await?;
+        pin!(table_rows);
+        while let Some(row) = table_rows.next().await {
+            let mut row = row?;
+            if let Some(encryptor) = &encryptor {
+                encryptor.encrypt_row(table_schema.table_id, &mut row)?;
+            }
+            rows.push(PreviewRow {
+                table_id: table_schema.table_id,
+                operation: RowOperation::Copy,
+                row,
+            });
+        }
+    }
+
+    if options.changes > 0 {
+        // streamed from the slot's confirmed position
+        let cdc_events = source.get_cdc_stream(PgLsn::from(0)).await?;
+        pin!(cdc_events);
+        let deadline = tokio::time::Instant::now() + options.changes_timeout;
+        let mut changes = 0;
+        while changes < options.changes {
+            let Ok(Some(event)) = timeout_at(deadline, cdc_events.next()).await else {
+                break;
+            };
+            let event = match &mut encryptor {
+                Some(encryptor) => encryptor.encrypt_event(event?)?,
+                None => event?,
+            };
+            let (table_id, operation, row) = match event {
+                CdcEvent::Insert((table_id, row)) => (table_id, RowOperation::Insert, row),
+                CdcEvent::Update((table_id, row)) => (table_id, RowOperation::Update, row),
+                CdcEvent::Delete((table_id, row)) => (table_id, RowOperation::Delete, row),
+                _ => continue,
+            };
+            rows.push(PreviewRow {
+                table_id,
+                operation,
+                row,
+            });
+            changes += 1;
+        }
+    }
+
+    sink.preview_rows(&sink_table_schemas, rows)
+        .await
+        .map_err(DryRunError::Sink)
+}
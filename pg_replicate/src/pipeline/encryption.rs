@@ -0,0 +1,569 @@
+//! Encryption of columns before their values reach the sinks, so that
+//! sensitive values can only be read in the sinks with the key.
+//!
+//! The values are encrypted with AES-256-GCM, as the text the stdout sink's
+//! csv format prints for them, and the columns are replicated as `bytea`
+//! columns holding the 12 byte nonce followed by the ciphertext and its tag.
+//! Nulls stay null. The nonces of deterministically encrypted columns are
+//! derived from the values, so that equal values are encrypted to equal
+//! bytes and the columns can be joined and grouped on, at the cost of
+//! revealing which values are equal. Columns of primary keys are always
+//! encrypted deterministically so that the sinks find the rows of updates
+//! and deletes.
+//!
+//! The 32 byte data key is obtained once, when the encryptor is created,
+//! from a [`KeyProvider`] like a key management service decrypting it.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_lc_rs::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    digest::{self, SHA256},
+    hmac::{self, HMAC_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::{BufMut, BytesMut};
+use chrono::Utc;
+use postgres_replication::protocol::{LogicalReplicationMessage, RelationBody, ReplicaIdentity};
+use serde_json::Value;
+use thiserror::Error;
+use tokio_postgres::types::Type;
+
+use crate::{
+    clients::auth::{google_token, AuthError},
+    conversions::{
+        cdc_event::{CdcEvent, CdcEventConversionError, CdcEventConverter},
+        json::cell_to_text,
+        table_row::TableRow,
+        Cell,
+    },
+    table::{TableId, TableName, TableSchema},
+};
+
+use super::sources::postgres::glob_matches;
+
+/// Length of the data keys of AES-256-GCM
+const KEY_LEN: usize = 32;
+
+/// Scope of the tokens of Cloud KMS
+const CLOUD_KMS_SCOPE: &str = "https://www.googleapis.com/auth/cloudkms";
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("invalid data key: {0}")]
+    InvalidKey(String),
+
+    #[error("aws credentials are missing, {0} is not set")]
+    MissingAwsCredentials(&'static str),
+
+    #[error("failed to request the data key: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("the key service rejected the request with status {0}: {1}")]
+    Rejected(u16, String),
+
+    #[error("auth error: {0}")]
+    Auth(#[from] AuthError),
+
+    #[error("failed to encrypt a value of column {0}")]
+    Encrypt(String),
+
+    #[error("invalid relation message: {0}")]
+    Relation(#[from] CdcEventConversionError),
+}
+
+/// Provides the data key the columns are encrypted with
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// Returns the 32 byte data key
+    async fn data_key(&self) -> Result<Vec<u8>, EncryptionError>;
+}
+
+/// Where the data key comes from
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum KeySource {
+    /// The key encoded in base64, e.g. by `openssl rand -base64 32`
+    Local { key: String },
+
+    /// The key encrypted with an AWS KMS key, as the base64 encoded
+    /// `CiphertextBlob` of `aws kms generate-data-key --key-spec AES_256`.
+    /// It's decrypted with the credentials of the `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables.
+    AwsKms {
+        region: String,
+        encrypted_key: String,
+    },
+
+    /// The key encrypted with the Cloud KMS key of the resource name
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*`, in base64. It's
+    /// decrypted as the service account of the key file, or as the one
+    /// attached to the host if there is none.
+    GcpKms {
+        key_name: String,
+        encrypted_key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_file: Option<String>,
+    },
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Local { key: _ } => {
+                f.debug_struct("Local").field("key", &"REDACTED").finish()
+            }
+            KeySource::AwsKms {
+                region,
+                encrypted_key,
+            } => f
+                .debug_struct("AwsKms")
+                .field("region", region)
+                .field("encrypted_key", encrypted_key)
+                .finish(),
+            KeySource::GcpKms {
+                key_name,
+                encrypted_key,
+                key_file,
+            } => f
+                .debug_struct("GcpKms")
+                .field("key_name", key_name)
+                .field("encrypted_key", encrypted_key)
+                .field("key_file", key_file)
+                .finish(),
+        }
+    }
+}
+
+#[async_trait]
+impl KeyProvider for KeySource {
+    async fn data_key(&self) -> Result<Vec<u8>, EncryptionError> {
+        match self {
+            KeySource::Local { key } => decode_key(key),
+            KeySource::AwsKms {
+                region,
+                encrypted_key,
+            } => aws_kms_decrypt(region, encrypted_key).await,
+            KeySource::GcpKms {
+                key_name,
+                encrypted_key,
+                key_file,
+            } => {
+                let token = google_token(key_file.as_deref(), CLOUD_KMS_SCOPE).await?;
+                let request = reqwest::Client::new()
+                    .post(format!(
+                        "https://cloudkms.googleapis.com/v1/{key_name}:decrypt"
+                    ))
+                    .bearer_auth(token)
+                    .json(&serde_json::json!({ "ciphertext": encrypted_key }));
+                decrypted_key(request, "plaintext").await
+            }
+        }
+    }
+}
+
+/// Decrypts the key with the `Decrypt` action of AWS KMS, signing the
+/// request with AWS Signature Version 4
+async fn aws_kms_decrypt(region: &str, encrypted_key: &str) -> Result<Vec<u8>, EncryptionError> {
+    let access_key_id = aws_env("AWS_ACCESS_KEY_ID")?;
+    let secret_access_key = aws_env("AWS_SECRET_ACCESS_KEY")?;
+    let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+
+    let host = format!("kms.{region}.amazonaws.com");
+    let body = serde_json::json!({ "CiphertextBlob": encrypted_key }).to_string();
+    let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    // signed headers, sorted by name
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("host", host.clone()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(session_token) = session_token {
+        headers.push(("x-amz-security-token", session_token));
+    }
+    headers.push(("x-amz-target", "TrentService.Decrypt".to_string()));
+
+    let authorization = aws_sigv4_authorization(
+        &access_key_id,
+        &secret_access_key,
+        region,
+        "kms",
+        &amz_date,
+        &headers,
+        body.as_bytes(),
+    );
+
+    let mut request = reqwest::Client::new()
+        .post(format!("https://{host}/"))
+        .header("authorization", authorization)
+        .body(body);
+    for (name, value) in headers {
+        // the host header is set by the client
+        if name != "host" {
+            request = request.header(name, value);
+        }
+    }
+    decrypted_key(request, "Plaintext").await
+}
+
+fn aws_env(name: &'static str) -> Result<String, EncryptionError> {
+    std::env::var(name).map_err(|_| EncryptionError::MissingAwsCredentials(name))
+}
+
+/// Signs a POST request to the root path with AWS Signature Version 4.
+/// `headers` must be sorted by name and include the host.
+fn aws_sigv4_authorization(
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> String {
+    let date = &amz_date[..8];
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex(digest::digest(&SHA256, body).as_ref())
+    );
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(digest::digest(&SHA256, canonical_request.as_bytes()).as_ref())
+    );
+    let mut signing_key = hmac_sha256(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        date.as_bytes(),
+    );
+    for part in [region, service, "aws4_request"] {
+        signing_key = hmac_sha256(&signing_key, part.as_bytes());
+    }
+    let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={access_key_id}/{scope}, SignedHeaders={signed_headers}, Signature={signature}"
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(HMAC_SHA256, key), data)
+        .as_ref()
+        .to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Sends a request to a key service and returns the key of the response's
+/// field
+async fn decrypted_key(
+    request: reqwest::RequestBuilder,
+    field: &str,
+) -> Result<Vec<u8>, EncryptionError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(EncryptionError::Rejected(status.as_u16(), body));
+    }
+    let response: Value = response.json().await?;
+    let Some(key) = response.get(field).and_then(Value::as_str) else {
+        return Err(EncryptionError::InvalidKey(format!(
+            "the response has no {field}"
+        )));
+    };
+    decode_key(key)
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, EncryptionError> {
+    BASE64_STANDARD
+        .decode(key.trim())
+        .map_err(|e| EncryptionError::InvalidKey(e.to_string()))
+}
+
+/// Columns encrypted before their values reach the sinks
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EncryptionOptions {
+    pub key: KeySource,
+
+    pub columns: Vec<EncryptedColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EncryptedColumn {
+    /// Columns in `schema.table.column` form. Patterns may contain `*`
+    /// wildcards matching any number of characters.
+    pub column: String,
+
+    /// Whether equal values are encrypted to equal bytes, so that the
+    /// column can be joined on
+    #[serde(default)]
+    pub deterministic: bool,
+}
+
+/// The encrypted columns of a table, as the sinks see it
+struct EncryptedTable {
+    table_schema: TableSchema,
+    /// Positions of the encrypted columns in the rows, and whether they are
+    /// encrypted deterministically
+    columns: Vec<(usize, bool)>,
+}
+
+/// Encrypts the columns of the rows and changes written to the sinks
+pub struct ColumnEncryptor {
+    columns: Vec<EncryptedColumn>,
+    key: LessSafeKey,
+    /// Key of the nonces of deterministically encrypted values
+    nonce_key: hmac::Key,
+    random: SystemRandom,
+    tables: HashMap<TableId, EncryptedTable>,
+}
+
+impl ColumnEncryptor {
+    /// Encrypts the columns with the provider's data key
+    pub async fn new(
+        key_provider: &dyn KeyProvider,
+        columns: Vec<EncryptedColumn>,
+    ) -> Result<ColumnEncryptor, EncryptionError> {
+        let data_key = key_provider.data_key().await?;
+        if data_key.len() != KEY_LEN {
+            return Err(EncryptionError::InvalidKey(format!(
+                "the key has {} bytes instead of {KEY_LEN}",
+                data_key.len()
+            )));
+        }
+        let key = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map_err(|_| EncryptionError::InvalidKey("the key was rejected".to_string()))?;
+        let nonce_key = hmac::Key::new(
+            HMAC_SHA256,
+            &hmac_sha256(&data_key, b"pg_replicate deterministic nonces"),
+        );
+        Ok(ColumnEncryptor {
+            columns,
+            key: LessSafeKey::new(key),
+            nonce_key,
+            random: SystemRandom::new(),
+            tables: HashMap::new(),
+        })
+    }
+
+    /// Returns whether the column is encrypted, and if it is whether
+    /// deterministically
+    fn column(&self, table_name: &TableName, column: &str) -> Option<bool> {
+        let column = format!("{table_name}.{column}");
+        self.columns
+            .iter()
+            .find(|encrypted_column| glob_matches(&encrypted_column.column, &column))
+            .map(|encrypted_column| encrypted_column.deterministic)
+    }
+
+    fn encrypt_schema(&mut self, mut table_schema: TableSchema) -> TableSchema {
+        let mut columns = vec![];
+        for (i, column_schema) in table_schema.column_schemas.iter_mut().enumerate() {
+            if Some(&column_schema.name) == table_schema.commit_timestamp_column.as_ref() {
+                continue;
+            }
+            let Some(deterministic) = self.column(&table_schema.table_name, &column_schema.name)
+            else {
+                continue;
+            };
+            column_schema.typ = Type::BYTEA;
+            column_schema.modifier = -1;
+            table_schema.type_overrides.remove(&column_schema.name);
+            columns.push((i, deterministic || column_schema.primary));
+        }
+        self.tables.insert(
+            table_schema.table_id,
+            EncryptedTable {
+                table_schema: table_schema.clone(),
+                columns,
+            },
+        );
+        table_schema
+    }
+
+    /// Returns the schemas of the tables as the sinks see them, with their
+    /// encrypted columns as `bytea` columns
+    pub fn encrypt_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> HashMap<TableId, TableSchema> {
+        table_schemas
+            .into_iter()
+            .map(|(table_id, table_schema)| (table_id, self.encrypt_schema(table_schema)))
+            .collect()
+    }
+
+    /// Encrypts the values of the encrypted columns of a row of the table
+    pub fn encrypt_row(
+        &self,
+        table_id: TableId,
+        table_row: &mut TableRow,
+    ) -> Result<(), EncryptionError> {
+        let Some(table) = self.tables.get(&table_id) else {
+            return Ok(());
+        };
+        for &(i, deterministic) in &table.columns {
+            let Some(cell) = table_row.values.get_mut(i) else {
+                continue;
+            };
+            if matches!(cell, Cell::Null) {
+                continue;
+            }
+            let plaintext = cell_to_text(cell);
+            let value = self
+                .encrypt(plaintext.as_bytes(), deterministic)
+                .ok_or_else(|| {
+                    EncryptionError::Encrypt(table.table_schema.column_schemas[i].name.clone())
+                })?;
+            *cell = Cell::Bytes(value);
+        }
+        Ok(())
+    }
+
+    fn encrypt(&self, plaintext: &[u8], deterministic: bool) -> Option<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        if deterministic {
+            let tag = hmac::sign(&self.nonce_key, plaintext);
+            nonce.copy_from_slice(&tag.as_ref()[..NONCE_LEN]);
+        } else {
+            self.random.fill(&mut nonce).ok()?;
+        }
+        let mut ciphertext = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut ciphertext,
+            )
+            .ok()?;
+        let mut value = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        value.extend_from_slice(&nonce);
+        value.extend_from_slice(&ciphertext);
+        Some(value)
+    }
+
+    /// Encrypts the row of a change. The encrypted columns of a relation
+    /// message are changed to `bytea`, so that the sinks keep replicating
+    /// them as such after the table's schema changed.
+    pub fn encrypt_event(&mut self, event: CdcEvent) -> Result<CdcEvent, EncryptionError> {
+        match event {
+            CdcEvent::Insert((table_id, mut table_row)) => {
+                self.encrypt_row(table_id, &mut table_row)?;
+                Ok(CdcEvent::Insert((table_id, table_row)))
+            }
+            CdcEvent::Update((table_id, mut table_row)) => {
+                self.encrypt_row(table_id, &mut table_row)?;
+                Ok(CdcEvent::Update((table_id, table_row)))
+            }
+            CdcEvent::Delete((table_id, mut table_row)) => {
+                self.encrypt_row(table_id, &mut table_row)?;
+                Ok(CdcEvent::Delete((table_id, table_row)))
+            }
+            CdcEvent::Relation(relation_body) => {
+                let Some(table) = self.tables.get(&relation_body.rel_id()) else {
+                    return Ok(CdcEvent::Relation(relation_body));
+                };
+                let table_name = table.table_schema.table_name.clone();
+                let relation_body = self.encrypt_relation(&table_name, &relation_body)?;
+                let table_schema = CdcEventConverter::try_table_schema_from_relation(
+                    &relation_body,
+                    &table.table_schema,
+                )?;
+                self.encrypt_schema(table_schema);
+                Ok(CdcEvent::Relation(relation_body))
+            }
+            event => Ok(event),
+        }
+    }
+
+    /// Rebuilds a relation message with the type of the encrypted columns
+    fn encrypt_relation(
+        &self,
+        table_name: &TableName,
+        relation_body: &RelationBody,
+    ) -> Result<RelationBody, EncryptionError> {
+        let invalid = |e: std::io::Error| EncryptionError::Relation(e.into());
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        buf.put_u32(relation_body.rel_id());
+        put_cstr(&mut buf, relation_body.namespace().map_err(invalid)?);
+        put_cstr(&mut buf, relation_body.name().map_err(invalid)?);
+        buf.put_u8(match relation_body.replica_identity() {
+            ReplicaIdentity::Default => b'd',
+            ReplicaIdentity::Nothing => b'n',
+            ReplicaIdentity::Full => b'f',
+            ReplicaIdentity::Index => b'i',
+        });
+        buf.put_i16(relation_body.columns().len() as i16);
+        for column in relation_body.columns() {
+            let name = column.name().map_err(invalid)?;
+            buf.put_i8(column.flags());
+            put_cstr(&mut buf, name);
+            if self.column(table_name, name).is_some() {
+                buf.put_i32(Type::BYTEA.oid() as i32);
+                buf.put_i32(-1);
+            } else {
+                buf.put_i32(column.type_id());
+                buf.put_i32(column.type_modifier());
+            }
+        }
+        match LogicalReplicationMessage::parse(&buf.freeze()) {
+            Ok(LogicalReplicationMessage::Relation(relation_body)) => Ok(relation_body),
+            Ok(_) => panic!("failed to parse a generated relation message"),
+            Err(e) => Err(invalid(e)),
+        }
+    }
+}
+
+fn put_cstr(buf: &mut BytesMut, s: &str) {
+    buf.put_slice(s.as_bytes());
+    buf.put_u8(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::aws_sigv4_authorization;
+
+    #[test]
+    fn request_is_signed_with_the_derived_key() {
+        // The example credentials and date from the AWS documentation
+        let headers = [
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", "kms.us-east-1.amazonaws.com".to_string()),
+            ("x-amz-date", "20150830T123600Z".to_string()),
+            ("x-amz-target", "TrentService.Decrypt".to_string()),
+        ];
+
+        let authorization = aws_sigv4_authorization(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+            "kms",
+            "20150830T123600Z",
+            &headers,
+            b"{}",
+        );
+
+        // Signed by botocore's SigV4Auth with the same request, as in the
+        // api's test of its key provider
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/kms/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-target, \
+             Signature=8a164eed79ee074124d2cc2a08ef2e71a1127fca419e26ba952221db2024db6a"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Erasure of rows of a source table, e.g. to comply with a request to erase
+//! someone's personal data, and verification that the deletes reached a
+//! sink.
+//!
+//! The rows are deleted from the source in a regular transaction, which is
+//! replicated to the sink like any other change. The sink's position is then
+//! polled until it passes the deletes, and sinks whose tables can be queried
+//! are checked for rows with the erased keys. Tables keeping the history of
+//! their rows, like change logs, still hold the erased rows after their
+//! deletes were replicated. Files of older versions of Delta tables hold
+//! them until the tables are vacuumed, the erased keys are listed in a
+//! tombstone manifest for these.
+
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::time::Instant;
+use tokio_postgres::{
+    types::{PgLsn, Type},
+    Client, Config,
+};
+use tracing::info;
+
+use crate::{
+    clients::postgres::{connect, hashed_value_expr, ConnectionOptions, ReplicationClientError},
+    table::{TableName, TableSchema},
+};
+
+use super::{sinks::BatchSink, state::serialize_lsn, verification::HashedValue};
+
+/// Time between two reads of the sink's position while waiting for the
+/// deletes to reach it
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum ErasureError {
+    #[error("tokio_postgres error: {0}")]
+    TokioPostgres(#[from] tokio_postgres::Error),
+
+    #[error("table {0} has no primary key, its erased rows can't be found in the sink")]
+    NoPrimaryKey(TableName),
+
+    #[error("column {0} of the primary key of table {1} has type {2}, which can't be looked up in the sink")]
+    UnsupportedKeyType(String, TableName, Type),
+}
+
+/// Returns the primary key columns of a table and how their values are
+/// rendered in the erased keys
+pub fn key_columns(table_schema: &TableSchema) -> Result<Vec<(String, HashedValue)>, ErasureError> {
+    let key_columns: Vec<(String, HashedValue)> = table_schema
+        .column_schemas
+        .iter()
+        .filter(|column_schema| column_schema.primary)
+        .map(|column_schema| {
+            let hashed_value = HashedValue::of(&column_schema.typ).ok_or_else(|| {
+                ErasureError::UnsupportedKeyType(
+                    column_schema.name.clone(),
+                    table_schema.table_name.clone(),
+                    column_schema.typ.clone(),
+                )
+            })?;
+            Ok((column_schema.name.clone(), hashed_value))
+        })
+        .collect::<Result<_, ErasureError>>()?;
+    if key_columns.is_empty() {
+        return Err(ErasureError::NoPrimaryKey(table_schema.table_name.clone()));
+    }
+    Ok(key_columns)
+}
+
+/// Rows deleted from a source table. Each key is the values of the primary
+/// key columns of a deleted row rendered as described by [`HashedValue`]
+/// and joined by `|`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasedRows {
+    pub table: String,
+    pub predicate: String,
+    pub key_columns: Vec<String>,
+    pub keys: Vec<String>,
+    pub erased_at: DateTime<Utc>,
+
+    /// Position in the source's WAL after the deletes. The sink holds the
+    /// deletes once its position is past it
+    #[serde(serialize_with = "serialize_lsn")]
+    pub delete_lsn: PgLsn,
+}
+
+/// Deletes rows of the source's tables through a regular connection
+pub struct SourceEraser {
+    client: Client,
+}
+
+impl SourceEraser {
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        options: &ConnectionOptions,
+    ) -> Result<SourceEraser, ReplicationClientError> {
+        let mut config = Config::new();
+        config.dbname(database).user(username);
+        if let Some(password) = password {
+            config.password(password);
+        }
+
+        let (client, _) = connect(config, host, port, options, "eraser").await?;
+
+        Ok(SourceEraser { client })
+    }
+
+    /// Deletes the rows of the table matching `predicate`, an sql condition
+    /// like `customer_id = 42`, in a single transaction
+    pub async fn erase(
+        &mut self,
+        table_schema: &TableSchema,
+        predicate: &str,
+    ) -> Result<ErasedRows, ErasureError> {
+        let key_columns = key_columns(table_schema)?;
+        let values: Vec<String> = key_columns
+            .iter()
+            .map(|(name, hashed_value)| hashed_value_expr(name, *hashed_value))
+            .collect();
+
+        let transaction = self.client.transaction().await?;
+        let rows = transaction
+            .query(
+                &format!(
+                    "delete from {} where ({predicate}) returning concat_ws('|', {}) as erased_key",
+                    table_schema.table_name.as_quoted_identifier(),
+                    values.join(", ")
+                ),
+                &[],
+            )
+            .await?;
+        // the commit of the deletes is written after this position
+        let delete_lsn: PgLsn = transaction
+            .query_one("select pg_current_wal_insert_lsn()", &[])
+            .await?
+            .get(0);
+        transaction.commit().await?;
+
+        let keys: Vec<String> = rows.iter().map(|row| row.get("erased_key")).collect();
+        info!(
+            "erased {} rows of table {}",
+            keys.len(),
+            table_schema.table_name
+        );
+
+        Ok(ErasedRows {
+            table: table_schema.table_name.to_string(),
+            predicate: predicate.to_string(),
+            key_columns: key_columns.into_iter().map(|(name, _)| name).collect(),
+            keys,
+            erased_at: Utc::now(),
+            delete_lsn,
+        })
+    }
+}
+
+/// Waits until the position of the sink passes `lsn` or the timeout
+/// elapses. Returns the last position of the sink and whether it passed
+/// `lsn`.
+pub async fn wait_for_lsn<Snk: BatchSink>(
+    sink: &mut Snk,
+    lsn: PgLsn,
+    timeout: Duration,
+) -> Result<(PgLsn, bool), Snk::Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let sink_lsn = sink.get_resumption_state().await?.last_lsn;
+        if sink_lsn >= lsn {
+            return Ok((sink_lsn, true));
+        }
+        if Instant::now() >= deadline {
+            return Ok((sink_lsn, false));
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())))
+            .await;
+    }
+}
+
+/// What an erasure left in a sink, written as json to be kept as a record of
+/// the erasure
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasureReport {
+    #[serde(flatten)]
+    pub erased: ErasedRows,
+
+    #[serde(serialize_with = "serialize_lsn")]
+    pub sink_lsn: PgLsn,
+
+    /// Whether the sink's position passed the deletes
+    pub reached_sink: bool,
+
+    /// Erased keys still found in the sink, none for sinks whose tables
+    /// can't be queried
+    pub remaining_keys: Option<Vec<String>>,
+}
+
+impl ErasureReport {
+    /// Whether the deletes reached the sink and no erased rows were found in
+    /// it, if it could be searched
+    pub fn verified(&self) -> bool {
+        self.reached_sink
+            && !self
+                .remaining_keys
+                .as_ref()
+                .is_some_and(|remaining_keys| !remaining_keys.is_empty())
+    }
+}
+
+impl Display for ErasureReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "erased {} rows of table {} up to lsn {}",
+            self.erased.keys.len(),
+            self.erased.table,
+            self.erased.delete_lsn
+        )?;
+        if self.reached_sink {
+            writeln!(f, "the deletes reached the sink, at lsn {}", self.sink_lsn)?;
+        } else {
+            writeln!(
+                f,
+                "the deletes haven't reached the sink yet, which is at lsn {}",
+                self.sink_lsn
+            )?;
+        }
+        match &self.remaining_keys {
+            Some(remaining_keys) if remaining_keys.is_empty() => {
+                write!(f, "no erased rows were found in the sink")
+            }
+            Some(remaining_keys) => write!(
+                f,
+                "{} erased rows were found in the sink: {}",
+                remaining_keys.len(),
+                remaining_keys.join(", ")
+            ),
+            None => write!(
+                f,
+                "the sink's tables can't be queried, older versions of its files keep the erased rows until they are removed"
+            ),
+        }
+    }
+}
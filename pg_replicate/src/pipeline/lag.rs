@@ -0,0 +1,124 @@
+//! Lag of a pipeline behind its source, computed from the position of the
+//! source's WAL which is queried periodically over a regular connection.
+//!
+//! Lsns only tell how many bytes of WAL the pipeline is behind. The rate at
+//! which the source writes WAL, measured between two queries, turns the
+//! bytes into an estimate of the time the pipeline needs to catch up.
+
+use std::time::Duration;
+
+use tokio::{sync::watch, time::Instant};
+use tokio_postgres::{types::PgLsn, Client, Config};
+use tracing::warn;
+
+use crate::clients::postgres::{connect, ConnectionOptions, ReplicationClientError};
+
+/// Weight of the latest measurement in the averaged WAL rate, which smooths
+/// the bursts of writes
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Position of the source's WAL at the last query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WalPosition {
+    pub lsn: PgLsn,
+    /// Bytes of WAL written per second, averaged over the previous queries.
+    /// None until the position was queried twice
+    pub bytes_per_sec: Option<f64>,
+}
+
+impl WalPosition {
+    /// Estimates the time needed to replay `lag_bytes` of WAL at the rate
+    /// the source writes it. None while the rate is unknown or the source
+    /// doesn't write
+    pub fn estimate_lag(&self, lag_bytes: u64) -> Option<Duration> {
+        if lag_bytes == 0 {
+            return Some(Duration::ZERO);
+        }
+        self.bytes_per_sec
+            .filter(|bytes_per_sec| *bytes_per_sec > 0.0)
+            .map(|bytes_per_sec| Duration::from_secs_f64(lag_bytes as f64 / bytes_per_sec))
+    }
+}
+
+/// Queries the position of the source's WAL every `interval`
+pub struct WalPositionMonitor {
+    client: Client,
+    interval: Duration,
+}
+
+impl WalPositionMonitor {
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        interval: Duration,
+        options: &ConnectionOptions,
+    ) -> Result<WalPositionMonitor, ReplicationClientError> {
+        let mut config = Config::new();
+        config.dbname(database).user(username);
+        if let Some(password) = password {
+            config.password(password);
+        }
+
+        let (client, _) = connect(config, host, port, options, "wal position monitor").await?;
+
+        Ok(WalPositionMonitor { client, interval })
+    }
+
+    async fn current_lsn(&self) -> Result<PgLsn, tokio_postgres::Error> {
+        // a standby has no current position, it reports the position it
+        // received up to
+        let row = self
+            .client
+            .query_one(
+                "select case when pg_is_in_recovery() then pg_last_wal_receive_lsn() else pg_current_wal_lsn() end",
+                &[],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Queries the position in a task until the returned receiver is
+    /// dropped. Failed queries are logged and the last position is kept.
+    pub(crate) fn spawn(self) -> watch::Receiver<Option<WalPosition>> {
+        let (sender, receiver) = watch::channel(None);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            let mut previous: Option<(PgLsn, Instant)> = None;
+            let mut bytes_per_sec: Option<f64> = None;
+            loop {
+                interval.tick().await;
+                let lsn = match self.current_lsn().await {
+                    Ok(lsn) => lsn,
+                    Err(e) => {
+                        warn!("failed to query the source's wal position: {e}");
+                        continue;
+                    }
+                };
+                let now = Instant::now();
+                if let Some((previous_lsn, previous_time)) = previous {
+                    let elapsed = now.duration_since(previous_time).as_secs_f64();
+                    if elapsed > 0.0 {
+                        let bytes = u64::from(lsn).saturating_sub(previous_lsn.into());
+                        let rate = bytes as f64 / elapsed;
+                        bytes_per_sec = Some(match bytes_per_sec {
+                            Some(average) => {
+                                RATE_SMOOTHING * rate + (1.0 - RATE_SMOOTHING) * average
+                            }
+                            None => rate,
+                        });
+                    }
+                }
+                previous = Some((lsn, now));
+
+                let position = WalPosition { lsn, bytes_per_sec };
+                if sender.send(Some(position)).is_err() {
+                    return;
+                }
+            }
+        });
+        receiver
+    }
+}
@@ -0,0 +1,106 @@
+//! Leader election between replicators replicating the same slot, so that a
+//! standby takes over when the replicating one fails.
+//!
+//! The leader holds a session level advisory lock in the source database,
+//! which Postgres releases as soon as the leader's connection closes, e.g.
+//! because its process stopped. Standbys keep trying to acquire the lock and
+//! the first one succeeding replicates next.
+
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_postgres::{Client, Config};
+use tracing::{info, warn};
+
+use crate::clients::postgres::{connect, ConnectionOptions, ReplicationClientError};
+
+/// Time the leader waits for the database to answer a check of its
+/// connection before assuming the lock is lost
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An advisory lock held by the leader
+pub struct LeaderLock {
+    client: Client,
+    closed: JoinHandle<()>,
+    check_interval: Duration,
+}
+
+impl LeaderLock {
+    /// Acquires the lock named `name`, trying again every `retry_interval`
+    /// while another replicator holds it
+    #[allow(clippy::too_many_arguments)]
+    pub async fn acquire(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        name: &str,
+        retry_interval: Duration,
+        options: &ConnectionOptions,
+    ) -> Result<LeaderLock, ReplicationClientError> {
+        let mut config = Config::new();
+        config
+            .dbname(database)
+            .user(username)
+            .keepalives_idle(retry_interval);
+        if let Some(password) = password {
+            config.password(password);
+        }
+
+        // the connection's task completes when the connection closes
+        let (client, closed) = connect(config, host, port, options, "leader lock").await?;
+
+        let mut waiting = false;
+        loop {
+            let acquired: bool = client
+                .query_one(
+                    "select pg_try_advisory_lock(hashtext('pg_replicate.' || $1))",
+                    &[&name],
+                )
+                .await?
+                .get(0);
+            if acquired {
+                info!("acquired the leader lock {name}");
+                return Ok(LeaderLock {
+                    client,
+                    closed,
+                    check_interval: retry_interval,
+                });
+            }
+            if !waiting {
+                info!("waiting for the leader lock {name}, another replicator holds it");
+                waiting = true;
+            }
+            tokio::time::sleep(retry_interval).await;
+        }
+    }
+
+    /// Completes when the lock is lost, because its connection closed or
+    /// the database stopped answering. Another replicator may hold the lock
+    /// afterwards, so the leader must stop replicating.
+    pub async fn lost(&mut self) {
+        loop {
+            tokio::select! {
+                _ = &mut self.closed => return,
+                _ = tokio::time::sleep(self.check_interval) => {
+                    let check = tokio::time::timeout(
+                        CHECK_TIMEOUT,
+                        self.client.simple_query("select 1"),
+                    );
+                    match check.await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => {
+                            warn!("leader lock connection check failed: {e}");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("leader lock connection check timed out");
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
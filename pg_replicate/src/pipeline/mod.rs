@@ -1,15 +1,30 @@
 use std::collections::HashSet;
 
+use batching::BatchConfig;
+use dead_letters::DeadLetterError;
+use encryption::EncryptionError;
 use sinks::SinkError;
-use sources::SourceError;
+use sources::{postgres::TableFilter, SourceError};
 use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 
-use crate::table::TableId;
+use crate::{error::ErrorKind, table::TableId};
 
 pub mod batching;
+pub mod classification;
+pub mod dead_letters;
+pub mod dry_run;
+pub mod encryption;
+pub mod erasure;
+pub mod lag;
+pub mod leadership;
+pub mod reporting;
+pub mod sharding;
 pub mod sinks;
 pub mod sources;
+pub mod state;
+pub mod status;
+pub mod verification;
 
 #[derive(Debug)]
 pub enum PipelineAction {
@@ -18,6 +33,21 @@ pub enum PipelineAction {
     Both,
 }
 
+/// A change applied to a running pipeline while it streams changes, without
+/// restarting the replication
+#[derive(Debug)]
+pub enum PipelineUpdate {
+    /// Fills the following batches according to the config
+    BatchConfig(BatchConfig),
+
+    /// Copies the publication's tables selected by the filter, all of them
+    /// if there is none, which aren't replicated yet and then streams their
+    /// changes. Tables no longer selected keep being replicated. Changes
+    /// made during the copy may be written twice, which tables without a
+    /// primary key can't absorb
+    Tables(Option<TableFilter>),
+}
+
 pub struct PipelineResumptionState {
     pub copied_tables: HashSet<TableId>,
     pub last_lsn: PgLsn,
@@ -33,4 +63,34 @@ pub enum PipelineError<SrcErr: SourceError, SnkErr: SinkError> {
 
     #[error("source error: {0}")]
     CommonSource(#[from] sources::CommonSourceError),
+
+    #[error("encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+
+    #[error("dead letter error: {0}")]
+    DeadLetter(#[from] DeadLetterError),
+}
+
+impl<SrcErr: SourceError, SnkErr: SinkError> PipelineError<SrcErr, SnkErr> {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PipelineError::Source(_) => ErrorKind::Source,
+            PipelineError::Sink(_) => ErrorKind::Sink,
+            PipelineError::CommonSource(e) if e.is_conversion() => ErrorKind::Conversion,
+            PipelineError::CommonSource(_) => ErrorKind::Source,
+            PipelineError::Encryption(_) => ErrorKind::Encryption,
+            PipelineError::DeadLetter(_) => ErrorKind::DeadLetter,
+        }
+    }
+
+    /// Whether starting the pipeline again may succeed, see
+    /// [`Error::is_retryable`](crate::error::Error::is_retryable)
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PipelineError::Source(e) => e.is_retryable(),
+            PipelineError::Sink(e) => e.is_retryable(),
+            PipelineError::CommonSource(e) => e.is_retryable(),
+            PipelineError::Encryption(_) | PipelineError::DeadLetter(_) => false,
+        }
+    }
 }
@@ -0,0 +1,94 @@
+//! Hooks reporting the errors of a pipeline and the failures of its sink to
+//! an error tracker, so that they surface where production incidents are
+//! alerted on.
+
+use std::{fmt::Display, sync::Arc};
+
+use tokio_postgres::types::PgLsn;
+
+use crate::table::TableName;
+
+#[cfg(feature = "sentry")]
+pub mod sentry;
+
+/// Where a reported error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorOrigin {
+    Source,
+    Sink,
+}
+
+impl Display for ErrorOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorOrigin::Source => write!(f, "source"),
+            ErrorOrigin::Sink => write!(f, "sink"),
+        }
+    }
+}
+
+/// What the pipeline was doing when an error happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub origin: ErrorOrigin,
+    pub pipeline_id: Option<String>,
+    /// Table being written or copied, if the error concerns a single table
+    pub table: Option<TableName>,
+    /// Position in the source's WAL up to which changes were written to the
+    /// sink
+    pub lsn: Option<PgLsn>,
+    /// Number of times the failed operation was retried. A failure which
+    /// is retried is reported before each retry. Fatal errors don't carry
+    /// the retries which led up to them, so they have none
+    pub retry_count: Option<u32>,
+    /// Whether the error stopped the pipeline, rather than being retried
+    pub fatal: bool,
+}
+
+/// Receives the errors of a pipeline, e.g. to send them to an error tracker.
+/// Reporting must not block, as it's called from the pipeline's task.
+pub trait ErrorReporter: Send + Sync {
+    fn report(&self, error: &(dyn std::error::Error + 'static), context: &ErrorContext);
+}
+
+/// An error reporter shared by a pipeline and its sink, which adds the id of
+/// the pipeline to the errors' context
+#[derive(Clone)]
+pub struct ErrorHook {
+    reporter: Arc<dyn ErrorReporter>,
+    pipeline_id: Option<String>,
+}
+
+impl ErrorHook {
+    pub fn new(reporter: impl ErrorReporter + 'static) -> ErrorHook {
+        ErrorHook {
+            reporter: Arc::new(reporter),
+            pipeline_id: None,
+        }
+    }
+
+    pub fn with_pipeline_id(mut self, pipeline_id: String) -> Self {
+        self.pipeline_id = Some(pipeline_id);
+        self
+    }
+
+    pub(crate) fn report(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+        origin: ErrorOrigin,
+        table: Option<TableName>,
+        lsn: Option<PgLsn>,
+        retry_count: Option<u32>,
+        fatal: bool,
+    ) {
+        let context = ErrorContext {
+            origin,
+            pipeline_id: self.pipeline_id.clone(),
+            table,
+            lsn,
+            retry_count,
+            fatal,
+        };
+        self.reporter.report(error, &context);
+    }
+}
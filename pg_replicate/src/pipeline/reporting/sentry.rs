@@ -0,0 +1,55 @@
+use sentry::{ClientInitGuard, ClientOptions, Level};
+
+use super::{ErrorContext, ErrorReporter};
+
+/// Reports errors to Sentry as events tagged with their context. Fatal
+/// errors are reported as errors, retried failures as warnings.
+pub struct SentryReporter {
+    // events are sent until the guard is dropped
+    _guard: ClientInitGuard,
+}
+
+impl SentryReporter {
+    /// Initializes the Sentry client with the dsn of a project. The
+    /// environment, e.g. `production`, tells the events of several
+    /// deployments apart
+    pub fn init(dsn: &str, environment: Option<String>) -> SentryReporter {
+        let guard = sentry::init((
+            dsn,
+            ClientOptions {
+                release: sentry::release_name!(),
+                environment: environment.map(Into::into),
+                ..ClientOptions::default()
+            },
+        ));
+        SentryReporter { _guard: guard }
+    }
+}
+
+impl ErrorReporter for SentryReporter {
+    fn report(&self, error: &(dyn std::error::Error + 'static), context: &ErrorContext) {
+        sentry::with_scope(
+            |scope| {
+                scope.set_level(Some(if context.fatal {
+                    Level::Error
+                } else {
+                    Level::Warning
+                }));
+                scope.set_tag("origin", context.origin);
+                if let Some(pipeline_id) = &context.pipeline_id {
+                    scope.set_tag("pipeline_id", pipeline_id);
+                }
+                if let Some(table) = &context.table {
+                    scope.set_tag("table", table);
+                }
+                if let Some(lsn) = context.lsn {
+                    scope.set_extra("lsn", lsn.to_string().into());
+                }
+                if let Some(retry_count) = context.retry_count {
+                    scope.set_extra("retry_count", retry_count.into());
+                }
+            },
+            || sentry::capture_error(error),
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Leases of a publication's tables, so that several workers replicating
+//! the same publication each replicate a disjoint subset of its tables.
+//!
+//! Leases are kept in the `pg_replicate.table_leases` table of a Postgres
+//! database all workers connect to, usually the source database. A worker
+//! owns a table while its lease hasn't expired and has to renew its leases
+//! by claiming its tables again before they do. A table whose lease expired,
+//! e.g. because its worker stopped, can be claimed by any worker.
+
+use std::time::Duration;
+
+use tokio_postgres::{Client, Config};
+use tracing::info;
+
+use crate::{
+    clients::postgres::{connect, ConnectionOptions, ReplicationClientError},
+    table::TableId,
+};
+
+/// A table claimed by a worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClaimedTable {
+    pub table_id: TableId,
+
+    /// Whether another worker held the table's lease before, in which case
+    /// the table has to be copied again as the other worker may have
+    /// stopped before writing all of its changes
+    pub taken_over: bool,
+}
+
+/// Claims, renews and releases the leases of a worker on the tables of a
+/// publication
+pub struct TableLeases {
+    client: Client,
+    publication: String,
+    worker: u32,
+}
+
+impl TableLeases {
+    /// Connects to the database holding the leases and creates the leases
+    /// table if it doesn't exist
+    #[allow(clippy::too_many_arguments)]
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        publication: String,
+        worker: u32,
+        options: &ConnectionOptions,
+    ) -> Result<TableLeases, ReplicationClientError> {
+        let mut config = Config::new();
+        config.dbname(database).user(username);
+        if let Some(password) = password {
+            config.password(password);
+        }
+
+        let (client, _) = connect(config, host, port, options, "table leases").await?;
+
+        client
+            .batch_execute(
+                "create schema if not exists pg_replicate;
+                create table if not exists pg_replicate.table_leases (
+                    publication text not null,
+                    table_id oid not null,
+                    worker int8 not null,
+                    expires_at timestamptz not null,
+                    primary key (publication, table_id)
+                );",
+            )
+            .await?;
+
+        Ok(TableLeases {
+            client,
+            publication,
+            worker,
+        })
+    }
+
+    /// Claims up to `max_tables` of the tables for `lease`, renewing the
+    /// leases the worker already holds first and then taking tables which
+    /// have no lease or whose lease expired. Returns the claimed tables,
+    /// which don't include tables the worker held but lost to another
+    /// worker after their lease expired.
+    pub async fn claim(
+        &self,
+        table_ids: &[TableId],
+        max_tables: usize,
+        lease: Duration,
+    ) -> Result<Vec<ClaimedTable>, tokio_postgres::Error> {
+        let rows = self
+            .client
+            .query(
+                "with candidates as (
+                    select t.table_id, l.worker as previous_worker
+                    from unnest($3::oid[]) as t(table_id)
+                    left join pg_replicate.table_leases l
+                        on l.publication = $1 and l.table_id = t.table_id
+                    where l.table_id is null or l.worker = $2 or l.expires_at <= now()
+                    order by l.worker = $2 desc nulls last, t.table_id
+                    limit $4
+                ), claimed as (
+                    insert into pg_replicate.table_leases (publication, table_id, worker, expires_at)
+                    select $1, table_id, $2, now() + make_interval(secs => $5)
+                    from candidates
+                    on conflict (publication, table_id) do update
+                    set worker = excluded.worker, expires_at = excluded.expires_at
+                    where table_leases.worker = excluded.worker or table_leases.expires_at <= now()
+                    returning table_id
+                )
+                select claimed.table_id, candidates.previous_worker
+                from claimed join candidates using (table_id)",
+                &[
+                    &self.publication,
+                    &(self.worker as i64),
+                    &table_ids,
+                    &(max_tables as i64),
+                    &lease.as_secs_f64(),
+                ],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let previous_worker: Option<i64> = row.get("previous_worker");
+                ClaimedTable {
+                    table_id: row.get("table_id"),
+                    taken_over: previous_worker
+                        .is_some_and(|previous_worker| previous_worker != self.worker as i64),
+                }
+            })
+            .collect())
+    }
+
+    /// Releases the worker's leases, so that other workers can claim its
+    /// tables without waiting for the leases to expire. The leases are
+    /// expired rather than deleted, so that the workers claiming the tables
+    /// next know that they take them over.
+    pub async fn release(&self) -> Result<(), tokio_postgres::Error> {
+        let released = self
+            .client
+            .execute(
+                "update pg_replicate.table_leases set expires_at = now()
+                where publication = $1 and worker = $2",
+                &[&self.publication, &(self.worker as i64)],
+            )
+            .await?;
+        info!("released the leases of {released} tables");
+        Ok(())
+    }
+}
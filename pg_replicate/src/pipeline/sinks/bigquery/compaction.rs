@@ -0,0 +1,54 @@
+use tokio_postgres::types::Type;
+
+use crate::{
+    clients::bigquery::{CHANGE_INDEX_COLUMN, CHANGE_LSN_COLUMN, CHANGE_TYPE_COLUMN},
+    table::{ColumnSchema, TableSchema},
+};
+
+/// Options of the periodic compaction of change logs into snapshot tables.
+///
+/// With compaction enabled every change is appended to the table's change
+/// log instead of being applied to the table, and tables with a primary key
+/// get a snapshot table which is periodically brought up to date by merging
+/// the latest change of each key from the change log into it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CompactionOptions {
+    /// Seconds between compactions
+    pub interval_secs: u64,
+
+    /// Appended to the name of a change log to name its snapshot table
+    #[serde(default = "default_snapshot_suffix")]
+    pub snapshot_suffix: String,
+}
+
+fn default_snapshot_suffix() -> String {
+    "_snapshot".to_string()
+}
+
+/// Returns the schema of a table's change log: the table's columns, none of
+/// which is a key and all of which except the key columns are nullable since
+/// deletes only carry the key, followed by the columns describing the change
+pub(super) fn change_log_table_schema(table_schema: &TableSchema) -> TableSchema {
+    let mut change_log_table_schema = table_schema.clone();
+    for column_schema in &mut change_log_table_schema.column_schemas {
+        column_schema.nullable = column_schema.nullable || !column_schema.primary;
+        column_schema.primary = false;
+    }
+
+    let change_columns = [
+        (CHANGE_TYPE_COLUMN, Type::TEXT),
+        (CHANGE_LSN_COLUMN, Type::INT8),
+        (CHANGE_INDEX_COLUMN, Type::INT8),
+    ];
+    for (name, typ) in change_columns {
+        change_log_table_schema.column_schemas.push(ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: 0,
+            nullable: false,
+            primary: false,
+        });
+    }
+
+    change_log_table_schema
+}
@@ -0,0 +1,9 @@
+pub use compaction::CompactionOptions;
+pub use naming::NamingOptions;
+pub use sink::{
+    BigQueryBatchSink, BigQuerySinkError, RemovedColumnPolicy, DEFAULT_MAX_CONCURRENT_STREAMS,
+};
+
+mod compaction;
+mod naming;
+mod sink;
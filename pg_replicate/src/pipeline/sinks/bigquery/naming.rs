@@ -0,0 +1,314 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    clients::bigquery::NameMapping,
+    pipeline::sinks::{
+        naming::{NamingConvention, SourceNames},
+        NamingStrategy,
+    },
+    table::{TableName, TableSchema},
+};
+
+use super::BigQuerySinkError;
+
+const MAX_TABLE_NAME_BYTES: usize = 1024;
+const MAX_COLUMN_NAME_BYTES: usize = 300;
+
+/// Column name prefixes reserved by BigQuery, compared case insensitively
+const RESERVED_COLUMN_PREFIXES: [&str; 7] = [
+    "_TABLE_",
+    "_FILE_",
+    "_PARTITION",
+    "_ROW_TIMESTAMP",
+    "__ROOT__",
+    "_COLON_",
+    "_CHANGE_",
+];
+
+/// Options controlling the names of tables created in BigQuery
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NamingOptions {
+    /// Prepended to the name of every replicated table
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub table_prefix: String,
+
+    /// Appended to the name of every replicated table
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub table_suffix: String,
+
+    /// Names of the BigQuery tables, without the prefix and suffix, of
+    /// Postgres tables in `schema.name` form, instead of the default names
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub table_names: BTreeMap<String, String>,
+
+    /// Whether tables are named `schema_name` in the sink's dataset, the
+    /// default, or `name` in a dataset of their schema. The dataset of a
+    /// schema is the one it is mapped to, or `<dataset_id>_<schema>`
+    #[serde(default)]
+    pub strategy: NamingStrategy,
+}
+
+/// Maps Postgres table and column names to valid BigQuery names.
+///
+/// A table `schema.name` is written to `<prefix>schema_name<suffix>`, or
+/// `<prefix>name<suffix>` with the per schema strategy, and
+/// columns keep their names, as long as these are valid BigQuery names.
+/// The naming convention renames the tables and columns first.
+/// Otherwise invalid characters are replaced by underscores, reserved
+/// column prefixes and leading digits are escaped, names which are too
+/// long are truncated and suffixed with a hash, and names colliding with
+/// an earlier name (column names collide case insensitively) are suffixed
+/// with a counter. Names which differ from the default are recorded in the
+/// `name_mappings` table so that they stay the same across restarts even
+/// if the source tables change. Table names set in the options take
+/// precedence and are not recorded.
+#[derive(Debug)]
+pub(super) struct Names {
+    options: NamingOptions,
+    convention: Arc<dyn NamingConvention>,
+    /// Postgres table name in `schema.name` form to BigQuery table name
+    /// without the prefix and suffix
+    tables: HashMap<String, String>,
+    /// Postgres table name in `schema.name` form to its column names
+    columns: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Names {
+    fn default() -> Names {
+        Names::new(NamingOptions::default(), Arc::new(SourceNames))
+    }
+}
+
+impl Names {
+    pub(super) fn new(options: NamingOptions, convention: Arc<dyn NamingConvention>) -> Names {
+        Names {
+            options,
+            convention,
+            tables: HashMap::new(),
+            columns: HashMap::new(),
+        }
+    }
+
+    pub(super) fn options(&self) -> &NamingOptions {
+        &self.options
+    }
+
+    pub(super) fn convention(&self) -> Arc<dyn NamingConvention> {
+        self.convention.clone()
+    }
+
+    pub(super) fn load(&mut self, mappings: Vec<NameMapping>) {
+        for mapping in mappings {
+            match mapping.source_column {
+                Some(source_column) => {
+                    self.columns
+                        .entry(mapping.source_table)
+                        .or_default()
+                        .insert(source_column, mapping.bigquery_name);
+                }
+                None => {
+                    self.tables
+                        .insert(mapping.source_table, mapping.bigquery_name);
+                }
+            }
+        }
+    }
+
+    /// Chooses BigQuery names for the table and for its columns which don't
+    /// have one yet. Returns the mappings which need to be recorded.
+    pub(super) fn resolve(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<Vec<NameMapping>, BigQuerySinkError> {
+        let source_table = table_schema.table_name.to_string();
+        let mut new_mappings = vec![];
+
+        if let Some(name) = self.options.table_names.get(&source_table) {
+            self.tables
+                .insert(source_table.clone(), sanitize_table_name(name));
+        } else if !self.tables.contains_key(&source_table) {
+            let default_name = self.default_table_name(&table_schema.table_name);
+            let name = unique_name(sanitize_table_name(&default_name), |name| {
+                self.tables.values().any(|t| t == name)
+            })
+            .ok_or_else(|| BigQuerySinkError::NoUniqueName(default_name.clone()))?;
+            if name != default_name {
+                new_mappings.push(NameMapping {
+                    source_table: source_table.clone(),
+                    source_column: None,
+                    bigquery_name: name.clone(),
+                });
+            }
+            self.tables.insert(source_table.clone(), name);
+        }
+
+        let columns = self.columns.entry(source_table.clone()).or_default();
+        for column_schema in &table_schema.column_schemas {
+            if columns.contains_key(&column_schema.name) {
+                continue;
+            }
+            let default_name = self.convention.column_name(&column_schema.name);
+            let name = unique_name(sanitize_column_name(&default_name), |name| {
+                columns.values().any(|c| c.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| BigQuerySinkError::NoUniqueName(default_name.clone()))?;
+            if name != default_name {
+                new_mappings.push(NameMapping {
+                    source_table: source_table.clone(),
+                    source_column: Some(column_schema.name.clone()),
+                    bigquery_name: name.clone(),
+                });
+            }
+            columns.insert(column_schema.name.clone(), name);
+        }
+
+        Ok(new_mappings)
+    }
+
+    pub(super) fn table_name(&self, table_name: &TableName) -> String {
+        let source_table = table_name.to_string();
+        let name = match self.options.table_names.get(&source_table) {
+            Some(name) => sanitize_table_name(name),
+            None => match self.tables.get(&source_table) {
+                Some(name) => name.clone(),
+                None => sanitize_table_name(&self.default_table_name(table_name)),
+            },
+        };
+        format!(
+            "{}{name}{}",
+            self.options.table_prefix, self.options.table_suffix
+        )
+    }
+
+    pub(super) fn strategy(&self) -> NamingStrategy {
+        self.options.strategy
+    }
+
+    /// Schema of the table after the naming convention renamed it, which
+    /// names its dataset with the per schema strategy
+    pub(super) fn schema(&self, table_name: &TableName) -> String {
+        self.convention.table_name(table_name).schema
+    }
+
+    fn default_table_name(&self, table_name: &TableName) -> String {
+        self.options
+            .strategy
+            .table_name(&self.convention.table_name(table_name))
+    }
+
+    pub(super) fn column_name(&self, table_name: &TableName, column_name: &str) -> String {
+        self.columns
+            .get(&table_name.to_string())
+            .and_then(|columns| columns.get(column_name))
+            .cloned()
+            .unwrap_or_else(|| sanitize_column_name(&self.convention.column_name(column_name)))
+    }
+
+    /// Returns a copy of the table schema with the columns renamed to their
+    /// BigQuery names
+    pub(super) fn table_schema(&self, table_schema: &TableSchema) -> TableSchema {
+        let mut table_schema = table_schema.clone();
+        for column_schema in &mut table_schema.column_schemas {
+            column_schema.name = self.column_name(&table_schema.table_name, &column_schema.name);
+        }
+        table_schema
+    }
+}
+
+/// Dataset ids can contain ascii letters, digits and underscores
+pub(super) fn sanitize_dataset_id(dataset_id: &str) -> String {
+    dataset_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Table names can contain letters, marks, numbers, connectors, dashes and
+/// spaces
+fn sanitize_table_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    truncate(sanitized, name, MAX_TABLE_NAME_BYTES)
+}
+
+/// Column names are also used as field names of the protobuf messages sent
+/// to the Storage Write API, so they are restricted to ascii letters, digits
+/// and underscores and must not start with a digit
+fn sanitize_column_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let starts_with_digit = sanitized
+        .chars()
+        .next()
+        .map_or(true, |c| c.is_ascii_digit());
+    let upper = sanitized.to_ascii_uppercase();
+    if starts_with_digit {
+        sanitized.insert(0, '_');
+    } else if RESERVED_COLUMN_PREFIXES
+        .iter()
+        .any(|prefix| upper.starts_with(prefix))
+    {
+        sanitized.insert(0, 'c');
+    }
+
+    truncate(sanitized, name, MAX_COLUMN_NAME_BYTES)
+}
+
+/// Truncates `name` to `max_bytes`, replacing its end with a hash of the
+/// original name to keep truncated names unique
+fn truncate(mut name: String, original: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name;
+    }
+
+    let hash = format!("_{:08x}", fnv1a(original.as_bytes()) as u32);
+    let mut len = max_bytes - hash.len();
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    name.truncate(len);
+    name.push_str(&hash);
+    name
+}
+
+/// A hash which, unlike the std hashers, is stable across releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Returns the name, or the name with the lowest number suffix which isn't
+/// used yet
+fn unique_name(name: String, is_used: impl Fn(&str) -> bool) -> Option<String> {
+    if !is_used(&name) {
+        return Some(name);
+    }
+
+    (2..=u32::MAX)
+        .map(|i| format!("{name}_{i}"))
+        .find(|candidate| !is_used(candidate))
+}
@@ -0,0 +1,1389 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::try_join_all;
+use gcp_bigquery_client::error::BQError;
+use postgres_replication::protocol::RelationBody;
+use thiserror::Error;
+use tokio::sync::{AcquireError, Semaphore};
+use tokio_postgres::types::{PgLsn, Type};
+use tracing::{info, warn};
+
+use crate::{
+    clients::bigquery::{
+        append_only_table_descriptor, is_retryable, map_row, table_descriptor, BigQueryClient,
+        DatasetOptions, QueryResultError, RetryConfig, StreamRowsError, TableOptions, TypeMappings,
+    },
+    conversions::{
+        cdc_event::{CdcEvent, CdcEventConversionError, CdcEventConverter},
+        json::{cell_to_json, row_to_json},
+        table_row::{RowPool, TableRow},
+        Cell,
+    },
+    pipeline::{
+        erasure::{key_columns, ErasureError},
+        reporting::ErrorHook,
+        sinks::{
+            naming::NamingConvention, BatchSink, ChecksumSink, DdlSink, ErasureSink,
+            NamingStrategy, PreviewRow, PreviewSink, RemovedTablePolicy, RowCountSink,
+            RowOperation, SinkError, StateSink, WriteMode,
+        },
+        verification::{ChecksumSpec, RangeChecksum},
+        PipelineResumptionState,
+    },
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
+
+use super::{
+    compaction::{change_log_table_schema, CompactionOptions},
+    naming::{sanitize_dataset_id, Names, NamingOptions},
+};
+
+#[derive(Debug, Error)]
+pub enum BigQuerySinkError {
+    #[error("big query error: {0}")]
+    BigQuery(#[from] BQError),
+
+    #[error("stream rows error: {0}")]
+    StreamRows(#[from] StreamRowsError),
+
+    #[error("missing table schemas")]
+    MissingTableSchemas,
+
+    #[error("missing table id: {0}")]
+    MissingTableId(TableId),
+
+    #[error("incorrect commit lsn: {0}(expected: {0})")]
+    IncorrectCommitLsn(PgLsn, PgLsn),
+
+    #[error("commit message without begin message")]
+    CommitWithoutBegin,
+
+    #[error("cdc event conversion error: {0}")]
+    CdcEventConversion(#[from] CdcEventConversionError),
+
+    #[error("columns {0:?} were removed from table {1}")]
+    ColumnsRemoved(Vec<String>, TableName),
+
+    #[error("last lsn not found in dataset {0}")]
+    MissingLastLsn(String),
+
+    #[error("table {0} has a change log without a snapshot, its checksums can't be compared")]
+    ChecksumOfChangeLog(TableName),
+
+    #[error("erasure error: {0}")]
+    Erasure(#[from] ErasureError),
+
+    #[error("the lsn of the last committed transaction isn't known")]
+    MissingCommittedLsn,
+
+    #[error("no value in column `{0}` of a query result")]
+    MissingColumn(&'static str),
+
+    #[error("no unique BigQuery name is left for {0}")]
+    NoUniqueName(String),
+
+    #[error("stream permits error: {0}")]
+    StreamPermits(#[from] AcquireError),
+}
+
+impl From<QueryResultError> for BigQuerySinkError {
+    fn from(e: QueryResultError) -> Self {
+        match e {
+            QueryResultError::BigQuery(e) => BigQuerySinkError::BigQuery(e),
+            QueryResultError::MissingColumn(column) => BigQuerySinkError::MissingColumn(column),
+        }
+    }
+}
+
+impl SinkError for BigQuerySinkError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            BigQuerySinkError::BigQuery(e)
+            | BigQuerySinkError::StreamRows(StreamRowsError::BigQuery(e)) => is_retryable(e),
+            // the same gRPC status codes as the errors of the client
+            BigQuerySinkError::StreamRows(StreamRowsError::AppendFailed { code, .. }) => {
+                matches!(code, 4 | 8 | 10 | 13 | 14)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What to do with a BigQuery column when its source column is dropped or
+/// renamed. A renamed column is seen as a dropped column and an added one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RemovedColumnPolicy {
+    /// Keep the column, new rows will have nulls in it
+    #[default]
+    Keep,
+
+    /// Drop the column from the BigQuery table
+    Drop,
+
+    /// Fail the pipeline
+    Fail,
+}
+
+pub struct BigQueryBatchSink {
+    client: BigQueryClient,
+    dataset_id: String,
+    dataset_options: DatasetOptions,
+    schema_datasets: HashMap<String, String>,
+    table_options: HashMap<String, TableOptions>,
+    default_table_options: TableOptions,
+    removed_column_policy: RemovedColumnPolicy,
+    write_mode: WriteMode,
+    /// Write modes of tables, keyed by the Postgres table name in
+    /// `schema.name` form
+    table_write_modes: HashMap<String, WriteMode>,
+    type_mappings: TypeMappings,
+    names: Names,
+    names_loaded: bool,
+    compaction: Option<CompactionOptions>,
+    compacted_lsns: HashMap<TableId, PgLsn>,
+    last_compaction: Instant,
+    table_schemas: Option<HashMap<TableId, TableSchema>>,
+    committed_lsn: Option<PgLsn>,
+    final_lsn: Option<PgLsn>,
+    change_sequence_number: u64,
+    stream_permits: Semaphore,
+    last_lsn_id: i64,
+    row_pool: RowPool,
+}
+
+/// Number of tables whose rows are appended at the same time by default
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 4;
+
+impl BigQueryBatchSink {
+    pub async fn new_with_key_path(
+        project_id: String,
+        dataset_id: String,
+        gcp_sa_key_path: &str,
+    ) -> Result<BigQueryBatchSink, BQError> {
+        let client = BigQueryClient::new_with_key_path(project_id, gcp_sa_key_path).await?;
+        Ok(Self::new(client, dataset_id))
+    }
+
+    pub async fn new_with_key(
+        project_id: String,
+        dataset_id: String,
+        gcp_sa_key: &str,
+    ) -> Result<BigQueryBatchSink, BQError> {
+        let client = BigQueryClient::new_with_key(project_id, gcp_sa_key).await?;
+        Ok(Self::new(client, dataset_id))
+    }
+
+    /// Creates a sink authenticating with Application Default Credentials
+    pub async fn new_with_adc(
+        project_id: String,
+        dataset_id: String,
+    ) -> Result<BigQueryBatchSink, BQError> {
+        let client = BigQueryClient::new_with_adc(project_id).await?;
+        Ok(Self::new(client, dataset_id))
+    }
+
+    /// Creates a sink authenticating with the metadata server's credentials
+    pub async fn new_with_workload_identity(
+        project_id: String,
+        dataset_id: String,
+    ) -> Result<BigQueryBatchSink, BQError> {
+        let client = BigQueryClient::new_with_workload_identity(project_id).await?;
+        Ok(Self::new(client, dataset_id))
+    }
+
+    fn new(client: BigQueryClient, dataset_id: String) -> BigQueryBatchSink {
+        BigQueryBatchSink {
+            client,
+            dataset_id,
+            dataset_options: DatasetOptions::default(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: TableOptions::default(),
+            removed_column_policy: RemovedColumnPolicy::default(),
+            write_mode: WriteMode::default(),
+            table_write_modes: HashMap::new(),
+            type_mappings: TypeMappings::new(),
+            names: Names::default(),
+            names_loaded: false,
+            compaction: None,
+            compacted_lsns: HashMap::new(),
+            last_compaction: Instant::now(),
+            table_schemas: None,
+            committed_lsn: None,
+            final_lsn: None,
+            change_sequence_number: 0,
+            stream_permits: Semaphore::new(DEFAULT_MAX_CONCURRENT_STREAMS),
+            last_lsn_id: 1,
+            row_pool: RowPool::default(),
+        }
+    }
+
+    /// Sets how requests to BigQuery failing with retryable errors are retried
+    pub fn with_retry_config(mut self, retry_config: RetryConfig) -> BigQueryBatchSink {
+        self.client.set_retry_config(retry_config);
+        self
+    }
+
+    /// Reports the failed requests which are retried to the hook
+    pub fn with_error_hook(mut self, error_hook: ErrorHook) -> BigQueryBatchSink {
+        self.client.set_error_hook(error_hook);
+        self
+    }
+
+    /// Sets the BigQuery types columns of Postgres types are written as,
+    /// keyed by the names of the Postgres types, e.g. `uuid`, instead of
+    /// their default types. Columns of array types can't be mapped. Tables
+    /// which were already created keep the types of their columns.
+    pub fn with_type_mappings(mut self, type_mappings: TypeMappings) -> BigQueryBatchSink {
+        self.type_mappings = type_mappings;
+        self
+    }
+
+    /// Sets the options used when creating missing datasets
+    pub fn with_dataset_options(mut self, dataset_options: DatasetOptions) -> BigQueryBatchSink {
+        self.dataset_options = dataset_options;
+        self
+    }
+
+    /// Maps Postgres schema names to dataset ids. Tables in a schema
+    /// without a mapping are written to the sink's default dataset.
+    pub fn with_schema_datasets(
+        mut self,
+        schema_datasets: HashMap<String, String>,
+    ) -> BigQueryBatchSink {
+        self.schema_datasets = schema_datasets;
+        self
+    }
+
+    /// Sets the options of tables, keyed by the Postgres table name in
+    /// `schema.name` form. Tables without options use the default table
+    /// options.
+    pub fn with_table_options(
+        mut self,
+        table_options: HashMap<String, TableOptions>,
+    ) -> BigQueryBatchSink {
+        self.table_options = table_options;
+        self
+    }
+
+    /// Sets the options of tables which don't have their own options
+    pub fn with_default_table_options(
+        mut self,
+        default_table_options: TableOptions,
+    ) -> BigQueryBatchSink {
+        self.default_table_options = default_table_options;
+        self
+    }
+
+    /// Sets what to do with columns removed from source tables
+    pub fn with_removed_column_policy(
+        mut self,
+        removed_column_policy: RemovedColumnPolicy,
+    ) -> BigQueryBatchSink {
+        self.removed_column_policy = removed_column_policy;
+        self
+    }
+
+    /// Sets the prefix and suffix of the names of replicated tables
+    pub fn with_naming_options(mut self, naming_options: NamingOptions) -> BigQueryBatchSink {
+        self.names = Names::new(naming_options, self.names.convention());
+        self
+    }
+
+    /// Sets the convention renaming the tables and columns, e.g. to snake
+    /// case, before they're named like the naming options name them
+    pub fn with_naming_convention(
+        mut self,
+        naming_convention: impl NamingConvention + 'static,
+    ) -> BigQueryBatchSink {
+        self.names = Names::new(self.names.options().clone(), Arc::new(naming_convention));
+        self
+    }
+
+    /// Sets how changes are written. In append-only mode every change is
+    /// appended to the table's change log, like with compaction but
+    /// without snapshot tables.
+    pub fn with_write_mode(mut self, write_mode: WriteMode) -> BigQueryBatchSink {
+        self.write_mode = write_mode;
+        self
+    }
+
+    /// Sets the write modes of tables, keyed by the Postgres table name in
+    /// `schema.name` form. Tables without one use the sink's write mode.
+    /// Changing the write mode of a table which was written to requires
+    /// copying it again.
+    pub fn with_table_write_modes(
+        mut self,
+        table_write_modes: HashMap<String, WriteMode>,
+    ) -> BigQueryBatchSink {
+        self.table_write_modes = table_write_modes;
+        self
+    }
+
+    /// Enables the periodic compaction of change logs into snapshot tables
+    pub fn with_compaction_options(
+        mut self,
+        compaction_options: CompactionOptions,
+    ) -> BigQueryBatchSink {
+        self.compaction = Some(compaction_options);
+        self
+    }
+
+    /// Sets how many tables' rows are appended at the same time. Rows of
+    /// different tables in a batch are appended over separate streams;
+    /// rows of a single table are always appended in order.
+    pub fn with_max_concurrent_streams(
+        mut self,
+        max_concurrent_streams: usize,
+    ) -> BigQueryBatchSink {
+        self.stream_permits = Semaphore::new(max_concurrent_streams.max(1));
+        self
+    }
+
+    /// Sets the id of the row of the `last_lsn` table holding the position
+    /// of the sink, 1 by default. Pipelines writing to the same dataset from
+    /// different slots, like the workers of a sharded replicator, keep
+    /// their positions in different rows.
+    pub fn with_last_lsn_id(mut self, last_lsn_id: i64) -> BigQueryBatchSink {
+        self.last_lsn_id = last_lsn_id;
+        self
+    }
+
+    fn dataset_id_for(&self, table_name: &TableName) -> String {
+        match self.schema_datasets.get(&table_name.schema) {
+            Some(dataset_id) => dataset_id.clone(),
+            None => match self.names.strategy() {
+                NamingStrategy::SchemaPrefixed => self.dataset_id.clone(),
+                NamingStrategy::PerSchema => format!(
+                    "{}_{}",
+                    self.dataset_id,
+                    sanitize_dataset_id(&self.names.schema(table_name))
+                ),
+            },
+        }
+    }
+
+    fn get_table_schema(&self, table_id: TableId) -> Result<&TableSchema, BigQuerySinkError> {
+        self.table_schemas
+            .as_ref()
+            .ok_or(BigQuerySinkError::MissingTableSchemas)?
+            .get(&table_id)
+            .ok_or(BigQuerySinkError::MissingTableId(table_id))
+    }
+
+    /// Whether rows of the table are appended to its change log instead of
+    /// being applied to it
+    fn writes_change_log(&self, table_name: &TableName) -> bool {
+        let write_mode = self
+            .table_write_modes
+            .get(&table_name.to_string())
+            .unwrap_or(&self.write_mode);
+        *write_mode == WriteMode::AppendOnly || self.compaction.is_some()
+    }
+
+    /// Returns the name of the table's snapshot table, if it has one
+    fn snapshot_table_name(&self, table_schema: &TableSchema) -> Option<String> {
+        let compaction = self.compaction.as_ref()?;
+        if !table_schema.has_primary_keys() {
+            return None;
+        }
+        let table_name = self.names.table_name(&table_schema.table_name);
+        Some(format!("{table_name}{}", compaction.snapshot_suffix))
+    }
+
+    /// Returns the schema of the BigQuery table the table's rows are
+    /// written to, which is its change log in append-only mode or if
+    /// compaction is enabled
+    fn written_table_schema(&self, table_schema: &TableSchema) -> TableSchema {
+        let writes_change_log = self.writes_change_log(&table_schema.table_name);
+        let table_schema = self.names.table_schema(table_schema);
+        if writes_change_log {
+            change_log_table_schema(&table_schema)
+        } else {
+            table_schema
+        }
+    }
+
+    /// Appends the columns describing a change to a row. When writing
+    /// change logs these are the change log's change columns, otherwise
+    /// they are the `_CHANGE_TYPE` and `_CHANGE_SEQUENCE_NUMBER` pseudo
+    /// columns which BigQuery uses to apply the row as an upsert or a
+    /// delete.
+    fn add_change_columns(
+        &self,
+        table_id: TableId,
+        table_row: &mut TableRow,
+        change_type: &str,
+        lsn: u64,
+        index: u64,
+    ) -> Result<(), BigQuerySinkError> {
+        let table_name = &self.get_table_schema(table_id)?.table_name;
+        self.add_table_change_columns(table_name, table_row, change_type, lsn, index);
+        Ok(())
+    }
+
+    fn add_table_change_columns(
+        &self,
+        table_name: &TableName,
+        table_row: &mut TableRow,
+        change_type: &str,
+        lsn: u64,
+        index: u64,
+    ) {
+        if self.writes_change_log(table_name) {
+            table_row.values.push(Cell::String(change_type.to_string()));
+            table_row.values.push(Cell::I64(lsn as i64));
+            table_row.values.push(Cell::I64(index as i64));
+        } else {
+            let change_type = if change_type == "DELETE" {
+                "DELETE"
+            } else {
+                "UPSERT"
+            };
+            table_row.values.push(Cell::String(change_type.to_string()));
+            table_row
+                .values
+                .push(Cell::String(format!("{lsn:X}/{index:X}")));
+        }
+    }
+
+    /// Returns the final lsn of the current transaction and the position
+    /// of the next change within it. Changes are ordered by these, so
+    /// that BigQuery applies multiple changes to a row in the right order
+    /// even when they are appended in the same request.
+    fn next_change(&mut self) -> (u64, u64) {
+        let final_lsn = self.final_lsn.map(u64::from).unwrap_or(0);
+        self.change_sequence_number += 1;
+        (final_lsn, self.change_sequence_number)
+    }
+}
+
+impl BigQueryBatchSink {
+    /// Loads the recorded names of tables and columns. With `create` set
+    /// the `name_mappings` table is created if missing, otherwise the
+    /// default names are used if it is missing.
+    async fn load_names(&mut self, create: bool) -> Result<(), BigQuerySinkError> {
+        if self.names_loaded {
+            return Ok(());
+        }
+
+        let name_mappings_column_schemas = [
+            ColumnSchema {
+                name: "source_table".to_string(),
+                typ: Type::TEXT,
+                modifier: 0,
+                nullable: false,
+                primary: false,
+            },
+            ColumnSchema {
+                name: "source_column".to_string(),
+                typ: Type::TEXT,
+                modifier: 0,
+                nullable: true,
+                primary: false,
+            },
+            ColumnSchema {
+                name: "bigquery_name".to_string(),
+                typ: Type::TEXT,
+                modifier: 0,
+                nullable: false,
+                primary: false,
+            },
+        ];
+        let exists = if create {
+            self.client
+                .create_table_if_missing(
+                    &self.dataset_id,
+                    "name_mappings",
+                    &name_mappings_column_schemas,
+                    &TypeMappings::new(),
+                    &TableOptions::default(),
+                )
+                .await?;
+            true
+        } else {
+            self.client
+                .table_exists(&self.dataset_id, "name_mappings")
+                .await?
+        };
+
+        if exists {
+            let name_mappings = self.client.get_name_mappings(&self.dataset_id).await?;
+            self.names.load(name_mappings);
+        }
+        self.names_loaded = true;
+
+        Ok(())
+    }
+
+    /// Chooses the BigQuery names of a table and its new columns and
+    /// records those which differ from the source names
+    async fn resolve_names(&mut self, table_schema: &TableSchema) -> Result<(), BigQuerySinkError> {
+        let name_mappings = self.names.resolve(table_schema)?;
+        self.client
+            .insert_name_mappings(&self.dataset_id, &name_mappings)
+            .await?;
+        Ok(())
+    }
+
+    /// Adds columns present in `column_schemas` but missing from the BigQuery table
+    async fn add_missing_columns(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<(), BigQuerySinkError> {
+        let existing_columns = self.client.get_column_names(dataset_id, table_name).await?;
+        let missing_columns: Vec<&ColumnSchema> = column_schemas
+            .iter()
+            .filter(|c| !existing_columns.contains(&c.name))
+            .collect();
+
+        if !missing_columns.is_empty() {
+            self.client
+                .add_columns(
+                    dataset_id,
+                    table_name,
+                    &missing_columns,
+                    &self.type_mappings,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Evolves the BigQuery table to the schema described by a relation
+    /// message and updates the table schema used to write its rows
+    async fn apply_relation(
+        &mut self,
+        relation_body: &RelationBody,
+    ) -> Result<(), BigQuerySinkError> {
+        let table_id = relation_body.rel_id();
+        let Some(old_table_schema) = self
+            .table_schemas
+            .as_ref()
+            .and_then(|table_schemas| table_schemas.get(&table_id))
+        else {
+            return Ok(());
+        };
+
+        let old_table_schema = old_table_schema.clone();
+        let new_table_schema =
+            CdcEventConverter::try_table_schema_from_relation(relation_body, &old_table_schema)?;
+        self.resolve_names(&new_table_schema).await?;
+
+        let dataset_id = &self.dataset_id_for(&new_table_schema.table_name);
+        let table_name = self.names.table_name(&new_table_schema.table_name);
+        let snapshot_table_name = self.snapshot_table_name(&new_table_schema);
+        let bq_table_schema = self.names.table_schema(&new_table_schema);
+
+        self.add_missing_columns(dataset_id, &table_name, &bq_table_schema.column_schemas)
+            .await?;
+        if let Some(snapshot_table_name) = &snapshot_table_name {
+            self.add_missing_columns(
+                dataset_id,
+                snapshot_table_name,
+                &bq_table_schema.column_schemas,
+            )
+            .await?;
+        }
+
+        let removed_columns: Vec<&ColumnSchema> = old_table_schema
+            .column_schemas
+            .iter()
+            .filter(|old| {
+                !new_table_schema
+                    .column_schemas
+                    .iter()
+                    .any(|new| new.name == old.name)
+            })
+            .collect();
+
+        if !removed_columns.is_empty() {
+            match self.removed_column_policy {
+                RemovedColumnPolicy::Keep => {
+                    // rows written from now on won't have values for these columns
+                    let required_columns: Vec<String> = removed_columns
+                        .iter()
+                        .filter(|c| !c.nullable && !c.primary)
+                        .map(|c| {
+                            self.names
+                                .column_name(&new_table_schema.table_name, &c.name)
+                        })
+                        .collect();
+                    let required_columns: Vec<&str> =
+                        required_columns.iter().map(String::as_str).collect();
+                    // all columns of change logs except their keys are nullable
+                    let required_table_name =
+                        if self.writes_change_log(&new_table_schema.table_name) {
+                            snapshot_table_name.as_ref()
+                        } else {
+                            Some(&table_name)
+                        };
+                    if let Some(required_table_name) = required_table_name {
+                        if !required_columns.is_empty() {
+                            self.client
+                                .drop_not_null(dataset_id, required_table_name, &required_columns)
+                                .await?;
+                        }
+                    }
+                }
+                RemovedColumnPolicy::Drop => {
+                    let column_names: Vec<String> = removed_columns
+                        .iter()
+                        .map(|c| {
+                            self.names
+                                .column_name(&new_table_schema.table_name, &c.name)
+                        })
+                        .collect();
+                    let column_names: Vec<&str> = column_names.iter().map(String::as_str).collect();
+                    self.client
+                        .drop_columns(dataset_id, &table_name, &column_names)
+                        .await?;
+                    if let Some(snapshot_table_name) = &snapshot_table_name {
+                        self.client
+                            .drop_columns(dataset_id, snapshot_table_name, &column_names)
+                            .await?;
+                    }
+                }
+                RemovedColumnPolicy::Fail => {
+                    let column_names = removed_columns.iter().map(|c| c.name.clone()).collect();
+                    return Err(BigQuerySinkError::ColumnsRemoved(
+                        column_names,
+                        new_table_schema.table_name,
+                    ));
+                }
+            }
+        }
+
+        for new in &new_table_schema.column_schemas {
+            let changed_type = old_table_schema
+                .column_schemas
+                .iter()
+                .any(|old| old.name == new.name && old.typ != new.typ);
+            if changed_type {
+                warn!(
+                    "type of column {} in table {} changed to {}, the bigquery column is not altered",
+                    new.name, new_table_schema.table_name, new.typ
+                );
+            }
+        }
+
+        if let Some(table_schemas) = self.table_schemas.as_mut() {
+            table_schemas.insert(table_id, new_table_schema);
+        }
+
+        Ok(())
+    }
+
+    /// Merges the changes committed since the last compaction into the
+    /// snapshot tables, if compaction is enabled and due
+    async fn compact_if_due(&mut self) -> Result<(), BigQuerySinkError> {
+        let Some(compaction) = &self.compaction else {
+            return Ok(());
+        };
+        if self.last_compaction.elapsed() < Duration::from_secs(compaction.interval_secs) {
+            return Ok(());
+        }
+        let Some(committed_lsn) = self.committed_lsn else {
+            return Ok(());
+        };
+
+        let Some(table_schemas) = &self.table_schemas else {
+            return Ok(());
+        };
+        let mut table_schemas: Vec<&TableSchema> = table_schemas.values().collect();
+        table_schemas.sort_by_key(|table_schema| table_schema.table_id);
+
+        let mut compacted_lsns = vec![];
+        for table_schema in table_schemas {
+            let Some(snapshot_table_name) = self.snapshot_table_name(table_schema) else {
+                continue;
+            };
+            let after_lsn = self.compacted_lsns.get(&table_schema.table_id).copied();
+            if after_lsn.is_some_and(|after_lsn| after_lsn >= committed_lsn) {
+                continue;
+            }
+
+            let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+            let table_name = self.names.table_name(&table_schema.table_name);
+            let bq_table_schema = self.names.table_schema(table_schema);
+            info!("compacting {table_name} into {snapshot_table_name} up to lsn {committed_lsn}");
+            self.client
+                .merge_change_log(
+                    dataset_id,
+                    &table_name,
+                    &snapshot_table_name,
+                    &bq_table_schema.column_schemas,
+                    after_lsn,
+                    committed_lsn,
+                )
+                .await?;
+            self.client
+                .set_compacted_lsn(&self.dataset_id, table_schema.table_id, committed_lsn)
+                .await?;
+            compacted_lsns.push(table_schema.table_id);
+        }
+
+        for table_id in compacted_lsns {
+            self.compacted_lsns.insert(table_id, committed_lsn);
+        }
+        self.last_compaction = Instant::now();
+
+        Ok(())
+    }
+
+    async fn stream_table_rows(
+        &mut self,
+        table_id: TableId,
+        table_rows: Vec<TableRow>,
+    ) -> Result<(), BigQuerySinkError> {
+        self.stream_tables_rows(HashMap::from([(table_id, table_rows)]))
+            .await
+    }
+
+    /// Appends the rows of each table over its own stream, with at most
+    /// as many appends in flight as the sink has stream permits
+    async fn stream_tables_rows(
+        &mut self,
+        tables_rows: HashMap<TableId, Vec<TableRow>>,
+    ) -> Result<(), BigQuerySinkError> {
+        let mut appends = Vec::with_capacity(tables_rows.len());
+        for (table_id, mut table_rows) in tables_rows {
+            let table_schema = self.get_table_schema(table_id)?;
+            let dataset_id = self.dataset_id_for(&table_schema.table_name);
+            let table_name = self.names.table_name(&table_schema.table_name);
+            let written_table_schema = self.written_table_schema(table_schema);
+            let table_descriptor = if self.writes_change_log(&table_schema.table_name) {
+                append_only_table_descriptor(&written_table_schema, &self.type_mappings)
+            } else {
+                table_descriptor(&written_table_schema, &self.type_mappings)
+            };
+            for table_row in &mut table_rows {
+                map_row(
+                    &self.type_mappings,
+                    &written_table_schema.column_schemas,
+                    table_row,
+                );
+            }
+            let mut client = self.client.clone();
+            let stream_permits = &self.stream_permits;
+            let row_pool = &self.row_pool;
+            appends.push(async move {
+                let _permit = stream_permits.acquire().await?;
+                client
+                    .stream_rows(&dataset_id, table_name, &table_descriptor, &table_rows)
+                    .await?;
+                row_pool.recycle(table_rows);
+                Ok::<(), BigQuerySinkError>(())
+            });
+        }
+        try_join_all(appends).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BatchSink for BigQueryBatchSink {
+    type Error = BigQuerySinkError;
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        info!("getting resumption state from bigquery");
+        self.client
+            .create_dataset_if_missing(&self.dataset_id, &self.dataset_options)
+            .await?;
+
+        let copied_table_column_schemas = [ColumnSchema {
+            name: "table_id".to_string(),
+            typ: Type::INT4,
+            modifier: 0,
+            nullable: false,
+            primary: true,
+        }];
+
+        self.client
+            .create_table_if_missing(
+                &self.dataset_id,
+                "copied_tables",
+                &copied_table_column_schemas,
+                &TypeMappings::new(),
+                &TableOptions::default(),
+            )
+            .await?;
+
+        let last_lsn_column_schemas = [
+            ColumnSchema {
+                name: "id".to_string(),
+                typ: Type::INT8,
+                modifier: 0,
+                nullable: false,
+                primary: true,
+            },
+            ColumnSchema {
+                name: "lsn".to_string(),
+                typ: Type::INT8,
+                modifier: 0,
+                nullable: false,
+                primary: false,
+            },
+        ];
+        self.client
+            .create_table_if_missing(
+                &self.dataset_id,
+                "last_lsn",
+                &last_lsn_column_schemas,
+                &TypeMappings::new(),
+                &TableOptions::default(),
+            )
+            .await?;
+
+        // the initial row is inserted whenever it is missing, not only when
+        // the table was just created, so that a previous run which failed
+        // between creating the table and inserting the row can still resume
+        let last_lsn = match self
+            .client
+            .get_last_lsn(&self.dataset_id, self.last_lsn_id)
+            .await?
+        {
+            Some(last_lsn) => last_lsn,
+            None => {
+                self.client
+                    .insert_last_lsn_row(&self.dataset_id, self.last_lsn_id)
+                    .await?;
+                self.client
+                    .get_last_lsn(&self.dataset_id, self.last_lsn_id)
+                    .await?
+                    .ok_or_else(|| BigQuerySinkError::MissingLastLsn(self.dataset_id.clone()))?
+            }
+        };
+
+        self.load_names(true).await?;
+
+        if self.compaction.is_some() {
+            let compacted_lsns_column_schemas = [
+                ColumnSchema {
+                    name: "table_id".to_string(),
+                    typ: Type::INT4,
+                    modifier: 0,
+                    nullable: false,
+                    primary: false,
+                },
+                ColumnSchema {
+                    name: "lsn".to_string(),
+                    typ: Type::INT8,
+                    modifier: 0,
+                    nullable: false,
+                    primary: false,
+                },
+            ];
+            self.client
+                .create_table_if_missing(
+                    &self.dataset_id,
+                    "compacted_lsns",
+                    &compacted_lsns_column_schemas,
+                    &TypeMappings::new(),
+                    &TableOptions::default(),
+                )
+                .await?;
+            self.compacted_lsns = self.client.get_compacted_lsns(&self.dataset_id).await?;
+        }
+
+        let copied_tables = self.client.get_copied_table_ids(&self.dataset_id).await?;
+
+        self.committed_lsn = Some(last_lsn);
+
+        Ok(PipelineResumptionState {
+            copied_tables,
+            last_lsn,
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        let dataset_ids: HashSet<String> = table_schemas
+            .values()
+            .map(|table_schema| self.dataset_id_for(&table_schema.table_name))
+            .collect();
+        for dataset_id in dataset_ids {
+            self.client
+                .create_dataset_if_missing(&dataset_id, &self.dataset_options)
+                .await?;
+        }
+
+        // names are resolved in a fixed order so that tables whose names
+        // collide get the same names on every run
+        let mut sorted_table_schemas: Vec<&TableSchema> = table_schemas.values().collect();
+        sorted_table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+        for table_schema in sorted_table_schemas {
+            self.resolve_names(table_schema).await?;
+
+            let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+            let table_name = self.names.table_name(&table_schema.table_name);
+            let bq_table_schema = self.names.table_schema(table_schema);
+            let written_table_schema = self.written_table_schema(table_schema);
+            let table_options = self
+                .table_options
+                .get(&table_schema.table_name.to_string())
+                .unwrap_or(&self.default_table_options);
+            let created = self
+                .client
+                .create_table_if_missing(
+                    dataset_id,
+                    &table_name,
+                    &written_table_schema.column_schemas,
+                    &self.type_mappings,
+                    table_options,
+                )
+                .await?;
+            if !created {
+                self.add_missing_columns(
+                    dataset_id,
+                    &table_name,
+                    &written_table_schema.column_schemas,
+                )
+                .await?;
+                // apply options changed since the table was created, tables
+                // without configured options are left alone to avoid a ddl
+                // statement per table on every start
+                if *table_options != TableOptions::default() {
+                    self.client
+                        .set_table_options(dataset_id, &table_name, table_options)
+                        .await?;
+                }
+            }
+
+            if let Some(snapshot_table_name) = self.snapshot_table_name(table_schema) {
+                let created = self
+                    .client
+                    .create_table_if_missing(
+                        dataset_id,
+                        &snapshot_table_name,
+                        &bq_table_schema.column_schemas,
+                        &self.type_mappings,
+                        table_options,
+                    )
+                    .await?;
+                if !created {
+                    self.add_missing_columns(
+                        dataset_id,
+                        &snapshot_table_name,
+                        &bq_table_schema.column_schemas,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        self.table_schemas = Some(table_schemas);
+
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        mut table_rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        // Copied rows get the lowest sequence number so that they never
+        // override changes streamed after the copy
+        for table_row in &mut table_rows {
+            self.add_change_columns(table_id, table_row, "INSERT", 0, 0)?;
+        }
+
+        self.stream_table_rows(table_id, table_rows).await?;
+
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut table_name_to_table_rows = HashMap::new();
+        let mut new_last_lsn = PgLsn::from(0);
+        for event in events {
+            match event {
+                CdcEvent::Begin(begin_body) => {
+                    let final_lsn_u64 = begin_body.final_lsn();
+                    self.final_lsn = Some(final_lsn_u64.into());
+                    self.change_sequence_number = 0;
+                }
+                CdcEvent::Commit(commit_body) => {
+                    let commit_lsn: PgLsn = commit_body.commit_lsn().into();
+                    if let Some(final_lsn) = self.final_lsn {
+                        if commit_lsn == final_lsn {
+                            new_last_lsn = commit_lsn;
+                        } else {
+                            Err(BigQuerySinkError::IncorrectCommitLsn(commit_lsn, final_lsn))?
+                        }
+                    } else {
+                        Err(BigQuerySinkError::CommitWithoutBegin)?
+                    }
+                }
+                CdcEvent::Insert((table_id, mut table_row)) => {
+                    let (lsn, index) = self.next_change();
+                    self.add_change_columns(table_id, &mut table_row, "INSERT", lsn, index)?;
+                    let table_rows: &mut Vec<TableRow> =
+                        table_name_to_table_rows.entry(table_id).or_default();
+                    table_rows.push(table_row);
+                }
+                CdcEvent::Update((table_id, mut table_row)) => {
+                    let (lsn, index) = self.next_change();
+                    self.add_change_columns(table_id, &mut table_row, "UPDATE", lsn, index)?;
+                    let table_rows: &mut Vec<TableRow> =
+                        table_name_to_table_rows.entry(table_id).or_default();
+                    table_rows.push(table_row);
+                }
+                CdcEvent::Delete((table_id, mut table_row)) => {
+                    let (lsn, index) = self.next_change();
+                    self.add_change_columns(table_id, &mut table_row, "DELETE", lsn, index)?;
+                    let table_rows: &mut Vec<TableRow> =
+                        table_name_to_table_rows.entry(table_id).or_default();
+                    table_rows.push(table_row);
+                }
+                CdcEvent::Relation(relation_body) => {
+                    // buffered rows of the table were decoded with the old schema
+                    let table_id = relation_body.rel_id();
+                    if let Some(table_rows) = table_name_to_table_rows.remove(&table_id) {
+                        self.stream_table_rows(table_id, table_rows).await?;
+                    }
+                    self.apply_relation(&relation_body).await?;
+                }
+                CdcEvent::KeepAliveRequested { .. } => {}
+                CdcEvent::Type(_) => {}
+            }
+        }
+
+        self.stream_tables_rows(table_name_to_table_rows).await?;
+
+        if new_last_lsn != PgLsn::from(0) {
+            self.client
+                .set_last_lsn(&self.dataset_id, self.last_lsn_id, new_last_lsn)
+                .await?;
+            self.committed_lsn = Some(new_last_lsn);
+        }
+
+        self.compact_if_due().await?;
+
+        self.committed_lsn
+            .ok_or(BigQuerySinkError::MissingCommittedLsn)
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.client
+            .insert_into_copied_tables(&self.dataset_id, table_id)
+            .await?;
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn prune_table(
+        &mut self,
+        table_id: TableId,
+        table_name: Option<&TableName>,
+        policy: RemovedTablePolicy,
+    ) -> Result<(), Self::Error> {
+        if let Some(table_name) = table_name {
+            self.load_names(false).await?;
+            let dataset_id = self.dataset_id_for(table_name);
+            let bq_table_name = self.names.table_name(table_name);
+            let mut bq_table_names = vec![bq_table_name.clone()];
+            if let Some(compaction) = &self.compaction {
+                bq_table_names.push(format!("{bq_table_name}{}", compaction.snapshot_suffix));
+            }
+
+            for bq_table_name in bq_table_names {
+                if !self
+                    .client
+                    .table_exists(&dataset_id, &bq_table_name)
+                    .await?
+                {
+                    continue;
+                }
+                match policy {
+                    RemovedTablePolicy::Keep => {}
+                    RemovedTablePolicy::Drop => {
+                        self.client.drop_table(&dataset_id, &bq_table_name).await?;
+                    }
+                    RemovedTablePolicy::Archive => {
+                        let archived_table_name =
+                            format!("{bq_table_name}_archived_{}", Utc::now().format("%Y%m%d"));
+                        self.client
+                            .rename_table(&dataset_id, &bq_table_name, &archived_table_name)
+                            .await?;
+                    }
+                    RemovedTablePolicy::Freeze => {
+                        let mut table_options = self
+                            .table_options
+                            .get(&table_name.to_string())
+                            .unwrap_or(&self.default_table_options)
+                            .clone();
+                        table_options
+                            .labels
+                            .insert("pg_replicate_frozen".to_string(), "true".to_string());
+                        self.client
+                            .set_table_options(&dataset_id, &bq_table_name, &table_options)
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        self.client
+            .delete_from_copied_tables(&self.dataset_id, table_id)
+            .await?;
+        Ok(())
+    }
+
+    fn row_pool(&self) -> Option<RowPool> {
+        Some(self.row_pool.clone())
+    }
+}
+
+#[async_trait]
+impl StateSink for BigQueryBatchSink {
+    async fn set_last_lsn(&mut self, last_lsn: PgLsn) -> Result<(), Self::Error> {
+        self.client
+            .set_last_lsn(&self.dataset_id, self.last_lsn_id, last_lsn)
+            .await?;
+        self.committed_lsn = Some(last_lsn);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RowCountSink for BigQueryBatchSink {
+    async fn count_table_rows(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<Option<u64>, Self::Error> {
+        self.load_names(false).await?;
+
+        let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+        // a change log has a row per change, its snapshot a row per row
+        let table_name = self
+            .snapshot_table_name(table_schema)
+            .unwrap_or_else(|| self.names.table_name(&table_schema.table_name));
+        if !self.client.table_exists(dataset_id, &table_name).await? {
+            return Ok(None);
+        }
+
+        let row_count = self.client.count_rows(dataset_id, &table_name).await?;
+        Ok(Some(row_count))
+    }
+}
+
+#[async_trait]
+impl ChecksumSink for BigQueryBatchSink {
+    async fn checksum_ranges(
+        &mut self,
+        table_schema: &TableSchema,
+        spec: &ChecksumSpec,
+    ) -> Result<Option<Vec<RangeChecksum>>, Self::Error> {
+        self.load_names(false).await?;
+
+        let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+        let table_name = match self.snapshot_table_name(table_schema) {
+            Some(snapshot_table_name) => snapshot_table_name,
+            None if self.writes_change_log(&table_schema.table_name) => {
+                return Err(BigQuerySinkError::ChecksumOfChangeLog(
+                    table_schema.table_name.clone(),
+                ))
+            }
+            None => self.names.table_name(&table_schema.table_name),
+        };
+        if !self.client.table_exists(dataset_id, &table_name).await? {
+            return Ok(None);
+        }
+
+        let column_name = |name: &String| self.names.column_name(&table_schema.table_name, name);
+        let spec = ChecksumSpec {
+            key_column: spec.key_column.as_ref().map(column_name),
+            columns: spec
+                .columns
+                .iter()
+                .map(|(name, hashed_value)| (column_name(name), *hashed_value))
+                .collect(),
+            ..spec.clone()
+        };
+        let ranges = self
+            .client
+            .checksum_ranges(dataset_id, &table_name, &spec)
+            .await?;
+        Ok(Some(ranges))
+    }
+}
+
+#[async_trait]
+impl ErasureSink for BigQueryBatchSink {
+    async fn find_keys(
+        &mut self,
+        table_schema: &TableSchema,
+        keys: &[String],
+    ) -> Result<Option<Vec<String>>, Self::Error> {
+        self.load_names(false).await?;
+
+        let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+        // a change log keeps the erased rows, in their changes
+        let table_name = self.names.table_name(&table_schema.table_name);
+        if !self.client.table_exists(dataset_id, &table_name).await? {
+            return Ok(None);
+        }
+
+        let key_columns: Vec<_> = key_columns(table_schema)?
+            .into_iter()
+            .map(|(name, hashed_value)| {
+                (
+                    self.names.column_name(&table_schema.table_name, &name),
+                    hashed_value,
+                )
+            })
+            .collect();
+        let mut found_keys = self
+            .client
+            .find_keys(dataset_id, &table_name, &key_columns, keys)
+            .await?;
+        if let Some(snapshot_table_name) = self.snapshot_table_name(table_schema) {
+            if self
+                .client
+                .table_exists(dataset_id, &snapshot_table_name)
+                .await?
+            {
+                found_keys.extend(
+                    self.client
+                        .find_keys(dataset_id, &snapshot_table_name, &key_columns, keys)
+                        .await?,
+                );
+                found_keys.sort();
+                found_keys.dedup();
+            }
+        }
+        Ok(Some(found_keys))
+    }
+}
+
+#[async_trait]
+impl DdlSink for BigQueryBatchSink {
+    async fn table_ddl(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error> {
+        self.load_names(false).await?;
+
+        // resolved in the same order as when the tables are created, but
+        // only in memory
+        let mut table_schemas: Vec<&TableSchema> = table_schemas.values().collect();
+        table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+        let mut statements = vec![];
+        for table_schema in table_schemas {
+            self.names.resolve(table_schema)?;
+
+            let dataset_id = &self.dataset_id_for(&table_schema.table_name);
+            let table_name = self.names.table_name(&table_schema.table_name);
+            let written_table_schema = self.written_table_schema(table_schema);
+            let table_options = self
+                .table_options
+                .get(&table_schema.table_name.to_string())
+                .unwrap_or(&self.default_table_options);
+            statements.push(self.client.create_table_ddl(
+                dataset_id,
+                &table_name,
+                &written_table_schema.column_schemas,
+                &self.type_mappings,
+                table_options,
+            ));
+
+            if let Some(snapshot_table_name) = self.snapshot_table_name(table_schema) {
+                let bq_table_schema = self.names.table_schema(table_schema);
+                statements.push(self.client.create_table_ddl(
+                    dataset_id,
+                    &snapshot_table_name,
+                    &bq_table_schema.column_schemas,
+                    &self.type_mappings,
+                    table_options,
+                ));
+            }
+        }
+
+        Ok(statements)
+    }
+}
+
+#[async_trait]
+impl PreviewSink for BigQueryBatchSink {
+    /// Returns the rows as json objects of the BigQuery table's columns,
+    /// prefixed by the table, including the `_CHANGE_TYPE` and
+    /// `_CHANGE_SEQUENCE_NUMBER` pseudo columns of upserted rows
+    async fn preview_rows(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error> {
+        self.load_names(false).await?;
+
+        let mut records = Vec::with_capacity(rows.len());
+        for (i, preview_row) in rows.into_iter().enumerate() {
+            let PreviewRow {
+                table_id,
+                operation,
+                mut row,
+            } = preview_row;
+            let table_schema = table_schemas
+                .get(&table_id)
+                .ok_or(BigQuerySinkError::MissingTableId(table_id))?;
+            // resolved only in memory, like the names of the table ddl
+            self.names.resolve(table_schema)?;
+
+            // copied rows get the lowest sequence number, like when they
+            // are written
+            let (change_type, index) = match operation {
+                RowOperation::Copy => ("INSERT", 0),
+                RowOperation::Insert => ("INSERT", i as u64 + 1),
+                RowOperation::Update => ("UPDATE", i as u64 + 1),
+                RowOperation::Delete => ("DELETE", i as u64 + 1),
+            };
+            self.add_table_change_columns(
+                &table_schema.table_name,
+                &mut row,
+                change_type,
+                0,
+                index,
+            );
+
+            let written_table_schema = self.written_table_schema(table_schema);
+            map_row(
+                &self.type_mappings,
+                &written_table_schema.column_schemas,
+                &mut row,
+            );
+            let mut values = row_to_json(&written_table_schema.column_schemas, &row);
+            if !self.writes_change_log(&table_schema.table_name) {
+                let pseudo_columns = ["_CHANGE_TYPE", "_CHANGE_SEQUENCE_NUMBER"];
+                let cells = row
+                    .values
+                    .iter()
+                    .skip(written_table_schema.column_schemas.len());
+                for (name, cell) in pseudo_columns.into_iter().zip(cells) {
+                    values.insert(name.to_string(), cell_to_json(cell));
+                }
+            }
+
+            records.push(format!(
+                "{}.{} {}",
+                self.dataset_id_for(&table_schema.table_name),
+                self.names.table_name(&table_schema.table_name),
+                serde_json::Value::Object(values)
+            ));
+        }
+
+        Ok(records)
+    }
+}
@@ -1,17 +1,31 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
 use chrono::Utc;
+use postgres_replication::protocol::RelationBody;
 use tokio_postgres::types::{PgLsn, Type};
-use tracing::info;
+use tracing::{info, warn};
 
-use super::{BatchSink, SinkError};
+use super::{
+    naming::{NamingConvention, SourceNames},
+    BatchSink, DdlSink, NamingStrategy, PreviewRow, PreviewSink, RowOperation, SinkError,
+    WriteMode,
+};
 use crate::{
-    clients::delta::DeltaClient,
-    conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+    clients::delta::{
+        register_object_stores, DeltaClient, DeltaStorage, DeltaTableOptions, DeltaType,
+        ParquetCompression,
+    },
+    conversions::{
+        cdc_event::{CdcEvent, CdcEventConversionError, CdcEventConverter},
+        json::cell_to_json,
+        table_row::TableRow,
+        Cell,
+    },
     pipeline::PipelineResumptionState,
     table::{ColumnSchema, TableId, TableSchema},
 };
@@ -41,25 +55,308 @@ pub enum DeltaSinkError {
 
     #[error("commit message without begin message")]
     CommitWithoutBegin,
+
+    #[error("cdc event conversion error: {0}")]
+    CdcEventConversion(#[from] CdcEventConversionError),
+
+    #[error("type of columns {0:?} in table {1} changed")]
+    IncompatibleTypeChange(Vec<String>, String),
+
+    #[error("column {0} of table {1} is reserved by the change data feed")]
+    ReservedColumn(String, String),
+
+    #[error("the lsn of the last committed transaction isn't known")]
+    MissingCommittedLsn,
+}
+
+/// Columns the change data feed adds to the changes it returns
+const CHANGE_DATA_FEED_COLUMNS: [&str; 3] =
+    ["_change_type", "_commit_version", "_commit_timestamp"];
+
+/// What to do when the type of a source column changes to one which is
+/// written with a different Delta type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TypeChangePolicy {
+    /// Fail the pipeline
+    #[default]
+    Fail,
+
+    /// Keep the column's type and cast new values to it, values which
+    /// can't be cast are written as nulls
+    KeepType,
+}
+
+/// Options of the periodic maintenance of tables. Frequent small CDC
+/// batches write many small files, which make reads slow.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DeltaMaintenanceOptions {
+    /// Seconds between maintenance runs
+    pub interval_secs: u64,
+
+    /// Size in bytes optimized files are rewritten to, the delta-rs
+    /// default is used if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_file_size: Option<i64>,
+
+    /// Hours of history to keep when vacuuming. Tables are not vacuumed
+    /// if not set. Readers of versions older than this will fail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vacuum_retention_hours: Option<u64>,
 }
 
 pub struct DeltaSink {
     client: DeltaClient,
     committed_lsn: Option<PgLsn>,
     final_lsn: Option<PgLsn>,
+    maintenance: Option<DeltaMaintenanceOptions>,
+    last_maintenance: Instant,
+    type_change_policy: TypeChangePolicy,
+    write_mode: WriteMode,
 }
 
 impl DeltaSink {
     pub fn new(path: String) -> Self {
+        register_object_stores();
         DeltaSink {
             client: DeltaClient {
                 path,
+                storage_options: HashMap::new(),
                 table_schemas: None,
                 delta_schemas: None,
+                table_options: HashMap::new(),
+                change_data_feed: false,
+                compression: ParquetCompression::default(),
+                naming: NamingStrategy::default(),
+                table_names: HashMap::new(),
+                type_mappings: HashMap::new(),
+                naming_convention: Arc::new(SourceNames),
             },
             committed_lsn: None,
             final_lsn: None,
+            maintenance: None,
+            last_maintenance: Instant::now(),
+            type_change_policy: TypeChangePolicy::default(),
+            write_mode: WriteMode::default(),
+        }
+    }
+
+    /// Merges the rows of tables with a primary key so that the tables hold
+    /// the current state of the source tables, and appends the rows of
+    /// other tables
+    async fn write_rows_batch(
+        &mut self,
+        rows_batch: HashMap<TableId, Vec<TableRow>>,
+    ) -> Result<(), DeltaSinkError> {
+        let mut append_batch = HashMap::new();
+        for (table_id, rows) in rows_batch {
+            let has_primary_keys = self
+                .client
+                .table_schemas
+                .as_ref()
+                .ok_or(DeltaSinkError::MissingTableSchemas)?
+                .get(&table_id)
+                .ok_or(DeltaSinkError::MissingTableId(table_id))?
+                .has_primary_keys();
+            if has_primary_keys && self.write_mode == WriteMode::Upsert {
+                self.client.merge_to_table(table_id, rows).await?;
+            } else {
+                append_batch.insert(table_id, rows);
+            }
+        }
+
+        self.client.write_to_table_batch(append_batch).await?;
+
+        Ok(())
+    }
+
+    /// Sets the object store the Delta lake is stored in. Options set with
+    /// [DeltaSink::with_storage_options] take precedence over it.
+    pub fn with_storage(mut self, storage: DeltaStorage) -> Self {
+        let mut storage_options = storage.storage_options();
+        storage_options.extend(self.client.storage_options);
+        self.client.storage_options = storage_options;
+        self
+    }
+
+    /// Adds raw delta-rs storage options, e.g. `AWS_S3_ALLOW_UNSAFE_RENAME`
+    pub fn with_storage_options(mut self, storage_options: HashMap<String, String>) -> Self {
+        self.client.storage_options.extend(storage_options);
+        self
+    }
+
+    /// Enables the periodic optimization and vacuuming of tables
+    pub fn with_maintenance(mut self, maintenance: DeltaMaintenanceOptions) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Enables the change data feed of tables so that readers can read
+    /// the changes made to them incrementally. Changes merged into tables
+    /// with a primary key are recorded as inserts, updates and deletes,
+    /// appended rows are recorded as inserts.
+    pub fn with_change_data_feed(mut self, enabled: bool) -> Self {
+        self.client.change_data_feed = enabled;
+        self
+    }
+
+    /// Sets the compression of the Parquet files written to the tables.
+    /// Files are compressed with snappy by default.
+    pub fn with_compression(mut self, compression: ParquetCompression) -> Self {
+        self.client.compression = compression;
+        self
+    }
+
+    /// Sets whether tables are written to `<path>/schema_name`, the default,
+    /// or to `<path>/schema/name`
+    pub fn with_naming(mut self, naming: NamingStrategy) -> Self {
+        self.client.naming = naming;
+        self
+    }
+
+    /// Sets the convention renaming the tables and columns, e.g. to snake
+    /// case, before the tables are named by the naming strategy
+    pub fn with_naming_convention(
+        mut self,
+        naming_convention: impl NamingConvention + 'static,
+    ) -> Self {
+        self.client.naming_convention = Arc::new(naming_convention);
+        self
+    }
+
+    /// Sets the names of the tables of Postgres tables in `schema.name`
+    /// form, instead of the names given by the naming strategy
+    pub fn with_table_names(mut self, table_names: HashMap<String, String>) -> Self {
+        self.client.table_names = table_names;
+        self
+    }
+
+    /// Sets how changes are written. In append-only mode rows of tables
+    /// with a primary key are appended with their `OP` column like the
+    /// rows of other tables instead of being merged.
+    pub fn with_write_mode(mut self, write_mode: WriteMode) -> Self {
+        self.write_mode = write_mode;
+        self
+    }
+
+    /// Sets the Delta types columns of Postgres types are written as, keyed
+    /// by the names of the Postgres types, e.g. `numeric`, instead of their
+    /// default types. Columns of array types can't be mapped.
+    pub fn with_type_mappings(mut self, type_mappings: HashMap<String, DeltaType>) -> Self {
+        self.client.type_mappings = type_mappings;
+        self
+    }
+
+    /// Sets what to do when the type of a source column changes
+    pub fn with_type_change_policy(mut self, type_change_policy: TypeChangePolicy) -> Self {
+        self.type_change_policy = type_change_policy;
+        self
+    }
+
+    /// Sets the options used when creating missing tables, keyed by the
+    /// Postgres table name in `schema.name` form
+    pub fn with_table_options(mut self, table_options: HashMap<String, DeltaTableOptions>) -> Self {
+        self.client.table_options = table_options;
+        self
+    }
+
+    /// Evolves a table to the schema described by a relation message and
+    /// updates the table schema used to write its rows
+    async fn apply_relation(&mut self, relation_body: &RelationBody) -> Result<(), DeltaSinkError> {
+        let table_id = relation_body.rel_id();
+        let Some(old_table_schema) = self
+            .client
+            .table_schemas
+            .as_ref()
+            .and_then(|table_schemas| table_schemas.get(&table_id))
+        else {
+            return Ok(());
+        };
+
+        let new_table_schema =
+            CdcEventConverter::try_table_schema_from_relation(relation_body, old_table_schema)?;
+        let table_name = self
+            .client
+            .table_name_in_delta(&new_table_schema.table_name);
+
+        let changed_type_columns = self
+            .client
+            .changed_type_columns(&table_name, &new_table_schema.column_schemas)?;
+        if !changed_type_columns.is_empty() {
+            match self.type_change_policy {
+                TypeChangePolicy::Fail => {
+                    return Err(DeltaSinkError::IncompatibleTypeChange(
+                        changed_type_columns,
+                        table_name,
+                    ));
+                }
+                TypeChangePolicy::KeepType => {
+                    warn!(
+                        "type of columns {changed_type_columns:?} in table {table_name} changed, keeping their delta type"
+                    );
+                }
+            }
+        }
+
+        let partition_columns = self.client.partition_columns(&new_table_schema).to_vec();
+        self.client
+            .evolve_table(
+                &table_name,
+                &new_table_schema.column_schemas,
+                &partition_columns,
+            )
+            .await?;
+
+        if let Some(table_schemas) = self.client.table_schemas.as_mut() {
+            table_schemas.insert(table_id, new_table_schema);
         }
+
+        Ok(())
+    }
+
+    /// Optimizes and vacuums all tables, if maintenance is enabled and due
+    async fn maintain_if_due(&mut self) -> Result<(), DeltaSinkError> {
+        let Some(maintenance) = &self.maintenance else {
+            return Ok(());
+        };
+        if self.last_maintenance.elapsed() < Duration::from_secs(maintenance.interval_secs) {
+            return Ok(());
+        }
+        let Some(table_schemas) = &self.client.table_schemas else {
+            return Ok(());
+        };
+
+        for table_schema in table_schemas.values() {
+            let table_name = self.client.table_name_in_delta(&table_schema.table_name);
+            if !self.client.delta_table_exists(&table_name).await {
+                continue;
+            }
+
+            let z_order_columns = self
+                .client
+                .table_options
+                .get(&table_schema.table_name.to_string())
+                .map(|table_options| table_options.z_order_columns.as_slice())
+                .unwrap_or_default();
+            let (files_removed, files_added) = self
+                .client
+                .optimize_table(&table_name, z_order_columns, maintenance.target_file_size)
+                .await?;
+            info!(
+                "optimized table {table_name}: {files_removed} files rewritten into {files_added}"
+            );
+
+            if let Some(retention_hours) = maintenance.vacuum_retention_hours {
+                let files_deleted = self
+                    .client
+                    .vacuum_table(&table_name, retention_hours)
+                    .await?;
+                info!("vacuumed table {table_name}: {files_deleted} files deleted");
+            }
+        }
+
+        self.last_maintenance = Instant::now();
+
+        Ok(())
     }
 
     fn add_optional_columns(table_row: &mut TableRow, op: &str) {
@@ -70,7 +367,20 @@ impl DeltaSink {
     }
 }
 
-impl SinkError for DeltaSinkError {}
+impl SinkError for DeltaSinkError {
+    /// Errors of the object store and commits conflicting with concurrent
+    /// writers may go away when retried
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            DeltaSinkError::Delta(
+                DeltaTableError::ObjectStore { .. }
+                    | DeltaTableError::Io { .. }
+                    | DeltaTableError::VersionAlreadyExists(_)
+            )
+        )
+    }
+}
 
 #[async_trait]
 impl BatchSink for DeltaSink {
@@ -95,7 +405,7 @@ impl BatchSink for DeltaSink {
 
         if !self.client.delta_table_exists("last_lsn").await {
             self.client
-                .create_table("last_lsn", &last_lsn_column_schemas)
+                .create_table("last_lsn", &last_lsn_column_schemas, &[])
                 .await?;
 
             self.client.insert_last_lsn_row().await?;
@@ -119,11 +429,31 @@ impl BatchSink for DeltaSink {
         let mut delta_schema: HashMap<String, Arc<Schema>> = HashMap::new();
 
         for table_schema in table_schemas.values() {
-            let table_name = DeltaClient::table_name_in_delta(&table_schema.table_name);
+            let table_name = self.client.table_name_in_delta(&table_schema.table_name);
+
+            if self.client.change_data_feed {
+                let reserved_column = table_schema
+                    .column_schemas
+                    .iter()
+                    .map(|column_schema| self.client.column_name_in_delta(&column_schema.name))
+                    .find(|name| CHANGE_DATA_FEED_COLUMNS.contains(&name.as_str()));
+                if let Some(reserved_column) = reserved_column {
+                    return Err(DeltaSinkError::ReservedColumn(reserved_column, table_name));
+                }
+                if self.client.delta_table_exists(&table_name).await {
+                    self.client.enable_change_data_feed(&table_name).await?;
+                }
+            }
+
+            let partition_columns = self.client.partition_columns(table_schema).to_vec();
 
             let schema = self
                 .client
-                .create_table(&table_name, &table_schema.column_schemas)
+                .create_table(
+                    &table_name,
+                    &table_schema.column_schemas,
+                    &partition_columns,
+                )
                 .await?;
 
             delta_schema.insert(table_name, schema);
@@ -150,7 +480,7 @@ impl BatchSink for DeltaSink {
             .collect();
 
         rows_batch.entry(table_id).or_default().extend(updated_rows);
-        self.client.write_to_table_batch(rows_batch).await?;
+        self.write_rows_batch(rows_batch).await?;
 
         Ok(())
     }
@@ -192,21 +522,31 @@ impl BatchSink for DeltaSink {
                     Self::add_optional_columns(&mut table_row, "D");
                     rows_batch.entry(table_id).or_default().push(table_row);
                 }
-                CdcEvent::Relation(_) => {}
-                CdcEvent::KeepAliveRequested { reply: _ } => {}
+                CdcEvent::Relation(relation_body) => {
+                    // buffered rows of the table were decoded with the old schema
+                    let table_id = relation_body.rel_id();
+                    if let Some(rows) = rows_batch.remove(&table_id) {
+                        self.write_rows_batch(HashMap::from([(table_id, rows)]))
+                            .await?;
+                    }
+                    self.apply_relation(&relation_body).await?;
+                }
+                CdcEvent::KeepAliveRequested { .. } => {}
                 CdcEvent::Type(_) => {}
             };
         }
 
-        self.client.write_to_table_batch(rows_batch).await?;
+        self.write_rows_batch(rows_batch).await?;
 
         if new_last_lsn != PgLsn::from(0) {
             self.client.set_last_lsn(new_last_lsn).await?;
             self.committed_lsn = Some(new_last_lsn);
         }
 
-        let committed_lsn = self.committed_lsn.expect("committed lsn is none");
-        Ok(committed_lsn)
+        self.maintain_if_due().await?;
+
+        self.committed_lsn
+            .ok_or(DeltaSinkError::MissingCommittedLsn)
     }
 
     async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
@@ -219,3 +559,109 @@ impl BatchSink for DeltaSink {
         Ok(())
     }
 }
+
+#[async_trait]
+impl DdlSink for DeltaSink {
+    /// Delta tables are created from the schema of the rows written to them,
+    /// so their schemas are returned instead of statements
+    async fn table_ddl(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut table_schemas: Vec<&TableSchema> = table_schemas.values().collect();
+        table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+
+        let mut schemas = vec![];
+        for table_schema in table_schemas {
+            let table_name = self.client.table_name_in_delta(&table_schema.table_name);
+            let partition_columns = self.client.partition_columns(table_schema);
+            let arrow_schema = self
+                .client
+                .arrow_schema(&table_schema.column_schemas, partition_columns);
+            let fields: Vec<String> = arrow_schema
+                .fields()
+                .iter()
+                .map(|field| format!("{} {}", field.name(), field.data_type()))
+                .collect();
+
+            let mut schema = format!(
+                "delta table {} ({})",
+                self.client.delta_full_path(&table_name),
+                fields.join(", ")
+            );
+            if !partition_columns.is_empty() {
+                let partition_columns: Vec<String> = partition_columns
+                    .iter()
+                    .map(|partition_column| partition_column.partition_column_name())
+                    .collect();
+                schema.push_str(&format!(
+                    " partitioned by ({})",
+                    partition_columns.join(", ")
+                ));
+            }
+            if self.client.change_data_feed {
+                schema.push_str(" with change data feed");
+            }
+            schemas.push(schema);
+        }
+
+        Ok(schemas)
+    }
+}
+
+#[async_trait]
+impl PreviewSink for DeltaSink {
+    /// Returns the rows as json objects of the Delta table's columns,
+    /// prefixed by whether they are merged into or appended to the table.
+    /// The values of added partition columns are derived when the rows are
+    /// written and left out.
+    async fn preview_rows(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut records = Vec::with_capacity(rows.len());
+        for preview_row in rows {
+            let PreviewRow {
+                table_id,
+                operation,
+                mut row,
+            } = preview_row;
+            let table_schema = table_schemas
+                .get(&table_id)
+                .ok_or(DeltaSinkError::MissingTableId(table_id))?;
+            let op = match operation {
+                RowOperation::Copy | RowOperation::Insert => "I",
+                RowOperation::Update => "U",
+                RowOperation::Delete => "D",
+            };
+            Self::add_optional_columns(&mut row, op);
+
+            let partition_columns = self.client.partition_columns(table_schema);
+            let arrow_schema = self
+                .client
+                .arrow_schema(&table_schema.column_schemas, partition_columns);
+            let values: serde_json::Map<String, serde_json::Value> = arrow_schema
+                .fields()
+                .iter()
+                .zip(&row.values)
+                .map(|(field, cell)| (field.name().clone(), cell_to_json(cell)))
+                .collect();
+
+            let action = if table_schema.has_primary_keys() && self.write_mode == WriteMode::Upsert
+            {
+                "merge into"
+            } else {
+                "append to"
+            };
+            let table_name = self.client.table_name_in_delta(&table_schema.table_name);
+            records.push(format!(
+                "{action} {} {}",
+                self.client.delta_full_path(&table_name),
+                serde_json::Value::Object(values)
+            ));
+        }
+
+        Ok(records)
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use chrono::Utc;
 use thiserror::Error;
 use tokio::sync::mpsc::{error::SendError, Receiver, Sender};
 use tokio_postgres::types::{PgLsn, Type};
@@ -7,18 +8,27 @@ use tracing::error;
 
 use crate::{
     clients::duckdb::DuckDbClient,
-    conversions::{cdc_event::CdcEvent, table_row::TableRow},
-    pipeline::{sinks::SinkError, PipelineResumptionState},
+    conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+    pipeline::{
+        erasure::ErasureError,
+        sinks::{RemovedTablePolicy, SinkError, WriteMode},
+        verification::HashedValue,
+        PipelineResumptionState,
+    },
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
 pub enum DuckDbRequest {
     GetResumptionState,
-    CreateTables(HashMap<TableId, TableSchema>),
+    CreateTables(HashMap<TableId, TableSchema>, WriteMode),
     InsertRow(TableRow, TableId),
     HandleCdcEvent(CdcEvent),
     TableCopied(TableId),
     TruncateTable(TableId),
+    PruneTable(TableId, Option<TableName>, RemovedTablePolicy),
+    CountRows(TableName),
+    SetLastLsn(PgLsn),
+    FindKeys(TableName, Vec<(String, HashedValue)>, Vec<String>),
 }
 
 pub enum DuckDbResponse {
@@ -28,6 +38,10 @@ pub enum DuckDbResponse {
     HandleCdcEventResponse(Result<PgLsn, DuckDbExecutorError>),
     TableCopiedResponse(Result<(), DuckDbExecutorError>),
     TruncateTableResponse(Result<(), DuckDbExecutorError>),
+    PruneTableResponse(Result<(), DuckDbExecutorError>),
+    CountRowsResponse(Result<Option<u64>, DuckDbExecutorError>),
+    SetLastLsnResponse(Result<(), DuckDbExecutorError>),
+    FindKeysResponse(Result<Option<Vec<String>>, DuckDbExecutorError>),
 }
 
 #[derive(Debug, Error)]
@@ -52,10 +66,24 @@ pub enum DuckDbExecutorError {
 
     #[error("failed to send duckdb request")]
     SendError(#[from] SendError<DuckDbRequest>),
+
+    #[error("invalid response to {0} request")]
+    InvalidResponse(&'static str),
+
+    #[error("the lsn of the last committed transaction isn't known")]
+    MissingCommittedLsn,
+
+    #[error("erasure error: {0}")]
+    Erasure(#[from] ErasureError),
 }
 
 impl SinkError for DuckDbExecutorError {}
 
+/// Columns appended to the rows of tables in append-only mode
+const CHANGE_TYPE_COLUMN: &str = "_pg_change_type";
+const CHANGE_LSN_COLUMN: &str = "_pg_change_lsn";
+const CHANGE_INDEX_COLUMN: &str = "_pg_change_index";
+
 pub(super) struct DuckDbExecutor {
     pub(super) client: DuckDbClient,
     pub(super) req_receiver: Receiver<DuckDbRequest>,
@@ -63,6 +91,8 @@ pub(super) struct DuckDbExecutor {
     pub(super) table_schemas: Option<HashMap<TableId, TableSchema>>,
     pub(super) final_lsn: Option<PgLsn>,
     pub(super) committed_lsn: Option<PgLsn>,
+    pub(super) write_mode: WriteMode,
+    pub(super) change_sequence_number: u64,
 }
 
 impl DuckDbExecutor {
@@ -78,14 +108,16 @@ impl DuckDbExecutor {
                         let response = DuckDbResponse::ResumptionState(result);
                         self.send_response(response).await;
                     }
-                    DuckDbRequest::CreateTables(table_schemas) => {
+                    DuckDbRequest::CreateTables(table_schemas, write_mode) => {
+                        self.write_mode = write_mode;
                         let result = self.create_tables(&table_schemas);
                         self.table_schemas = Some(table_schemas);
                         let response = DuckDbResponse::CreateTablesResponse(result);
                         self.send_response(response).await;
                     }
                     DuckDbRequest::InsertRow(row, table_id) => {
-                        let result = self.insert_row(table_id, row);
+                        // copied rows come before every streamed change
+                        let result = self.write_change(table_id, row, "INSERT", 0, 0);
                         let response = DuckDbResponse::InsertRowResponse(result);
                         self.send_response(response).await;
                     }
@@ -94,6 +126,7 @@ impl DuckDbExecutor {
                             CdcEvent::Begin(begin_body) => {
                                 let final_lsn = begin_body.final_lsn();
                                 self.final_lsn = Some(final_lsn.into());
+                                self.change_sequence_number = 0;
                                 self.begin_transaction()
                             }
                             CdcEvent::Commit(commit_body) => {
@@ -114,21 +147,26 @@ impl DuckDbExecutor {
                                 }
                             }
                             CdcEvent::Insert((table_id, table_row)) => {
-                                self.insert_row(table_id, table_row)
+                                let (lsn, index) = self.next_change();
+                                self.write_change(table_id, table_row, "INSERT", lsn, index)
                             }
                             CdcEvent::Update((table_id, table_row)) => {
-                                self.update_row(table_id, table_row)
+                                let (lsn, index) = self.next_change();
+                                self.write_change(table_id, table_row, "UPDATE", lsn, index)
                             }
                             CdcEvent::Delete((table_id, table_row)) => {
-                                self.delete_row(table_id, table_row)
+                                let (lsn, index) = self.next_change();
+                                self.write_change(table_id, table_row, "DELETE", lsn, index)
                             }
                             CdcEvent::Relation(_) => Ok(()),
-                            CdcEvent::KeepAliveRequested { reply: _ } => Ok(()),
+                            CdcEvent::KeepAliveRequested { .. } => Ok(()),
                             CdcEvent::Type(_) => Ok(()),
                         };
 
-                        let committed_lsn = self.committed_lsn.expect("committed lsn is none");
-                        let result = result.map(|_| committed_lsn);
+                        let result = result.and_then(|_| {
+                            self.committed_lsn
+                                .ok_or(DuckDbExecutorError::MissingCommittedLsn)
+                        });
                         let response = DuckDbResponse::HandleCdcEventResponse(result);
                         self.send_response(response).await;
                     }
@@ -142,6 +180,29 @@ impl DuckDbExecutor {
                         let response = DuckDbResponse::TruncateTableResponse(result);
                         self.send_response(response).await;
                     }
+                    DuckDbRequest::PruneTable(table_id, table_name, policy) => {
+                        let result = self.prune_table(table_id, table_name.as_ref(), policy);
+                        let response = DuckDbResponse::PruneTableResponse(result);
+                        self.send_response(response).await;
+                    }
+                    DuckDbRequest::CountRows(table_name) => {
+                        let result = self.count_rows(&table_name);
+                        let response = DuckDbResponse::CountRowsResponse(result);
+                        self.send_response(response).await;
+                    }
+                    DuckDbRequest::SetLastLsn(last_lsn) => {
+                        let result = self.client.set_last_lsn(last_lsn).map_err(Into::into);
+                        if result.is_ok() {
+                            self.committed_lsn = Some(last_lsn);
+                        }
+                        let response = DuckDbResponse::SetLastLsnResponse(result);
+                        self.send_response(response).await;
+                    }
+                    DuckDbRequest::FindKeys(table_name, key_columns, keys) => {
+                        let result = self.find_keys(&table_name, &key_columns, &keys);
+                        let response = DuckDbResponse::FindKeysResponse(result);
+                        self.send_response(response).await;
+                    }
                 }
             }
         });
@@ -207,13 +268,98 @@ impl DuckDbExecutor {
             let schema = &table_schema.table_name.schema;
 
             self.client.create_schema_if_missing(schema)?;
+            let column_schemas = match self.write_mode {
+                WriteMode::Upsert => table_schema.column_schemas.clone(),
+                WriteMode::AppendOnly => Self::change_log_column_schemas(table_schema),
+            };
             self.client
-                .create_table_if_missing(&table_schema.table_name, &table_schema.column_schemas)?;
+                .create_table_if_missing(&table_schema.table_name, &column_schemas)?;
         }
 
         Ok(())
     }
 
+    /// Returns the columns of a table in append-only mode: the table's
+    /// columns, none of which is a key and all of which except the key
+    /// columns are nullable since deletes only carry the key, followed by
+    /// the columns describing the change
+    pub(super) fn change_log_column_schemas(table_schema: &TableSchema) -> Vec<ColumnSchema> {
+        let mut column_schemas = table_schema.column_schemas.clone();
+        for column_schema in &mut column_schemas {
+            column_schema.nullable = column_schema.nullable || !column_schema.primary;
+            column_schema.primary = false;
+        }
+
+        let change_columns = [
+            (CHANGE_TYPE_COLUMN, Type::TEXT),
+            (CHANGE_LSN_COLUMN, Type::INT8),
+            (CHANGE_INDEX_COLUMN, Type::INT8),
+        ];
+        for (name, typ) in change_columns {
+            column_schemas.push(ColumnSchema {
+                name: name.to_string(),
+                typ,
+                modifier: 0,
+                nullable: false,
+                primary: false,
+            });
+        }
+
+        column_schemas
+    }
+
+    /// Returns the final lsn of the current transaction and the position
+    /// of the next change within it
+    fn next_change(&mut self) -> (u64, u64) {
+        let final_lsn = self.final_lsn.map(u64::from).unwrap_or(0);
+        self.change_sequence_number += 1;
+        (final_lsn, self.change_sequence_number)
+    }
+
+    /// Applies a change to its table in upsert mode, or appends it with
+    /// its type and position in append-only mode
+    fn write_change(
+        &self,
+        table_id: TableId,
+        mut table_row: TableRow,
+        change_type: &str,
+        lsn: u64,
+        index: u64,
+    ) -> Result<(), DuckDbExecutorError> {
+        match (self.write_mode, change_type) {
+            (WriteMode::Upsert, "UPDATE") => self.update_row(table_id, table_row),
+            (WriteMode::Upsert, "DELETE") => self.delete_row(table_id, table_row),
+            (WriteMode::Upsert, _) => self.insert_row(table_id, table_row),
+            (WriteMode::AppendOnly, _) => {
+                table_row.values.push(Cell::String(change_type.to_string()));
+                table_row.values.push(Cell::I64(lsn as i64));
+                table_row.values.push(Cell::I64(index as i64));
+                self.insert_row(table_id, table_row)
+            }
+        }
+    }
+
+    /// Returns the statement a change is written with, like
+    /// [`write_change`](Self::write_change), without running it
+    pub(super) fn change_statement(
+        write_mode: WriteMode,
+        table_schema: &TableSchema,
+        mut table_row: TableRow,
+        change_type: &str,
+        lsn: u64,
+        index: u64,
+    ) -> String {
+        match write_mode {
+            WriteMode::Upsert => DuckDbClient::row_statement(table_schema, &table_row, change_type),
+            WriteMode::AppendOnly => {
+                table_row.values.push(Cell::String(change_type.to_string()));
+                table_row.values.push(Cell::I64(lsn as i64));
+                table_row.values.push(Cell::I64(index as i64));
+                DuckDbClient::row_statement(table_schema, &table_row, "INSERT")
+            }
+        }
+    }
+
     fn insert_row(
         &self,
         table_id: TableId,
@@ -264,6 +410,60 @@ impl DuckDbExecutor {
         Ok(())
     }
 
+    fn prune_table(
+        &self,
+        table_id: TableId,
+        table_name: Option<&TableName>,
+        policy: RemovedTablePolicy,
+    ) -> Result<(), DuckDbExecutorError> {
+        if let Some(table_name) = table_name {
+            if self.client.table_exists(table_name)? {
+                match policy {
+                    RemovedTablePolicy::Keep => {}
+                    RemovedTablePolicy::Drop => self.client.drop_table(table_name)?,
+                    RemovedTablePolicy::Archive => {
+                        let archived_table_name = format!(
+                            "{}_archived_{}",
+                            table_name.name,
+                            Utc::now().format("%Y%m%d")
+                        );
+                        self.client.rename_table(table_name, &archived_table_name)?;
+                    }
+                    RemovedTablePolicy::Freeze => {
+                        let comment = format!(
+                            "frozen by pg_replicate on {}, no longer replicated",
+                            Utc::now().format("%Y-%m-%d")
+                        );
+                        self.client.comment_on_table(table_name, &comment)?;
+                    }
+                }
+            }
+        }
+        self.client.delete_from_copied_tables(table_id)?;
+        Ok(())
+    }
+
+    fn count_rows(&self, table_name: &TableName) -> Result<Option<u64>, DuckDbExecutorError> {
+        if !self.client.table_exists(table_name)? {
+            return Ok(None);
+        }
+        let row_count = self.client.count_rows(table_name)?;
+        Ok(Some(row_count))
+    }
+
+    fn find_keys(
+        &self,
+        table_name: &TableName,
+        key_columns: &[(String, HashedValue)],
+        keys: &[String],
+    ) -> Result<Option<Vec<String>>, DuckDbExecutorError> {
+        if !self.client.table_exists(table_name)? {
+            return Ok(None);
+        }
+        let found_keys = self.client.find_keys(table_name, key_columns, keys)?;
+        Ok(Some(found_keys))
+    }
+
     fn begin_transaction(&self) -> Result<(), DuckDbExecutorError> {
         self.client.begin_transaction()?;
         Ok(())
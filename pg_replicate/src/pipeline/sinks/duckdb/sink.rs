@@ -1,4 +1,4 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, path::Path, sync::Arc};
 
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
@@ -8,8 +8,16 @@ use tokio_postgres::types::PgLsn;
 use crate::{
     clients::duckdb::DuckDbClient,
     conversions::{cdc_event::CdcEvent, table_row::TableRow},
-    pipeline::{sinks::BatchSink, PipelineResumptionState},
-    table::{TableId, TableSchema},
+    pipeline::{
+        erasure::key_columns,
+        sinks::{
+            naming::{NamingConvention, SourceNames},
+            BatchSink, DdlSink, ErasureSink, PreviewRow, PreviewSink, RemovedTablePolicy,
+            RowCountSink, RowOperation, StateSink, WriteMode,
+        },
+        PipelineResumptionState,
+    },
+    table::{TableId, TableName, TableSchema},
 };
 
 use super::{
@@ -19,6 +27,8 @@ use super::{
 pub struct DuckDbSink {
     req_sender: Sender<DuckDbRequest>,
     res_receiver: Receiver<DuckDbResponse>,
+    write_mode: WriteMode,
+    naming_convention: Arc<dyn NamingConvention>,
 }
 
 const CHANNEL_SIZE: usize = 32;
@@ -35,11 +45,15 @@ impl DuckDbSink {
             table_schemas: None,
             final_lsn: None,
             committed_lsn: None,
+            write_mode: WriteMode::default(),
+            change_sequence_number: 0,
         };
         executor.start();
         Ok(DuckDbSink {
             req_sender,
             res_receiver,
+            write_mode: WriteMode::default(),
+            naming_convention: Arc::new(SourceNames),
         })
     }
 
@@ -57,11 +71,15 @@ impl DuckDbSink {
             table_schemas: None,
             final_lsn: None,
             committed_lsn: None,
+            write_mode: WriteMode::default(),
+            change_sequence_number: 0,
         };
         executor.start();
         Ok(DuckDbSink {
             req_sender,
             res_receiver,
+            write_mode: WriteMode::default(),
+            naming_convention: Arc::new(SourceNames),
         })
     }
 
@@ -76,14 +94,36 @@ impl DuckDbSink {
             table_schemas: None,
             final_lsn: None,
             committed_lsn: None,
+            write_mode: WriteMode::default(),
+            change_sequence_number: 0,
         };
         executor.start();
         Ok(DuckDbSink {
             req_sender,
             res_receiver,
+            write_mode: WriteMode::default(),
+            naming_convention: Arc::new(SourceNames),
         })
     }
 
+    /// Sets how changes are written. In append-only mode every change is
+    /// inserted as a row with the type of the change and its position in
+    /// the replication stream.
+    pub fn with_write_mode(mut self, write_mode: WriteMode) -> DuckDbSink {
+        self.write_mode = write_mode;
+        self
+    }
+
+    /// Sets the convention renaming the schemas, tables and columns, e.g.
+    /// to snake case
+    pub fn with_naming_convention(
+        mut self,
+        naming_convention: impl NamingConvention + 'static,
+    ) -> DuckDbSink {
+        self.naming_convention = Arc::new(naming_convention);
+        self
+    }
+
     pub async fn execute(
         &mut self,
         req: DuckDbRequest,
@@ -108,7 +148,7 @@ impl BatchSink for DuckDbSink {
                 let resumption_state = res?;
                 Ok(resumption_state)
             }
-            _ => panic!("invalid response to GetResumptionState request"),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("GetResumptionState")),
         }
     }
 
@@ -116,12 +156,18 @@ impl BatchSink for DuckDbSink {
         &mut self,
         table_schemas: HashMap<TableId, TableSchema>,
     ) -> Result<(), Self::Error> {
-        let req = DuckDbRequest::CreateTables(table_schemas);
+        let table_schemas = table_schemas
+            .into_iter()
+            .map(|(table_id, table_schema)| {
+                (table_id, self.naming_convention.table_schema(&table_schema))
+            })
+            .collect();
+        let req = DuckDbRequest::CreateTables(table_schemas, self.write_mode);
         match self.execute(req).await? {
             DuckDbResponse::CreateTablesResponse(res) => {
                 let _ = res?;
             }
-            _ => panic!("invalid response to CreateTables request"),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("CreateTables")),
         }
 
         Ok(())
@@ -139,7 +185,7 @@ impl BatchSink for DuckDbSink {
                 DuckDbResponse::InsertRowResponse(res) => {
                     let _ = res?;
                 }
-                _ => panic!("invalid response to InsertRow request"),
+                _ => return Err(DuckDbExecutorError::InvalidResponse("InsertRow")),
             }
         }
 
@@ -153,10 +199,10 @@ impl BatchSink for DuckDbSink {
             let req = DuckDbRequest::HandleCdcEvent(event);
             last_lsn = Some(match self.execute(req).await? {
                 DuckDbResponse::HandleCdcEventResponse(res) => res?,
-                _ => panic!("invalid response to HandleCdcEvent request"),
+                _ => return Err(DuckDbExecutorError::InvalidResponse("HandleCdcEvent")),
             });
         }
-        Ok(last_lsn.expect("no last_lsn"))
+        last_lsn.ok_or(DuckDbExecutorError::MissingCommittedLsn)
     }
 
     async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
@@ -165,7 +211,7 @@ impl BatchSink for DuckDbSink {
             DuckDbResponse::TableCopiedResponse(res) => {
                 let _ = res?;
             }
-            _ => panic!("invalid response to TableCopied request"),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("TableCopied")),
         }
         Ok(())
     }
@@ -176,8 +222,146 @@ impl BatchSink for DuckDbSink {
             DuckDbResponse::TruncateTableResponse(res) => {
                 let _ = res?;
             }
-            _ => panic!("invalid response to TruncateTable request"),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("TruncateTable")),
         }
         Ok(())
     }
+
+    async fn prune_table(
+        &mut self,
+        table_id: TableId,
+        table_name: Option<&TableName>,
+        policy: RemovedTablePolicy,
+    ) -> Result<(), Self::Error> {
+        let table_name = table_name.map(|table_name| self.naming_convention.table_name(table_name));
+        let req = DuckDbRequest::PruneTable(table_id, table_name, policy);
+        match self.execute(req).await? {
+            DuckDbResponse::PruneTableResponse(res) => {
+                let _ = res?;
+            }
+            _ => return Err(DuckDbExecutorError::InvalidResponse("PruneTable")),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StateSink for DuckDbSink {
+    async fn set_last_lsn(&mut self, last_lsn: PgLsn) -> Result<(), Self::Error> {
+        let req = DuckDbRequest::SetLastLsn(last_lsn);
+        match self.execute(req).await? {
+            DuckDbResponse::SetLastLsnResponse(res) => res,
+            _ => return Err(DuckDbExecutorError::InvalidResponse("SetLastLsn")),
+        }
+    }
+}
+
+#[async_trait]
+impl RowCountSink for DuckDbSink {
+    async fn count_table_rows(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<Option<u64>, Self::Error> {
+        let req =
+            DuckDbRequest::CountRows(self.naming_convention.table_name(&table_schema.table_name));
+        match self.execute(req).await? {
+            DuckDbResponse::CountRowsResponse(res) => Ok(res?),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("CountRows")),
+        }
+    }
+}
+
+#[async_trait]
+impl ErasureSink for DuckDbSink {
+    async fn find_keys(
+        &mut self,
+        table_schema: &TableSchema,
+        keys: &[String],
+    ) -> Result<Option<Vec<String>>, Self::Error> {
+        let table_schema = self.naming_convention.table_schema(table_schema);
+        let key_columns = key_columns(&table_schema)?;
+        let req = DuckDbRequest::FindKeys(table_schema.table_name, key_columns, keys.to_vec());
+        match self.execute(req).await? {
+            DuckDbResponse::FindKeysResponse(res) => Ok(res?),
+            _ => return Err(DuckDbExecutorError::InvalidResponse("FindKeys")),
+        }
+    }
+}
+
+#[async_trait]
+impl DdlSink for DuckDbSink {
+    async fn table_ddl(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut table_schemas: Vec<TableSchema> = table_schemas
+            .values()
+            .map(|table_schema| self.naming_convention.table_schema(table_schema))
+            .collect();
+        table_schemas.sort_by_key(|table_schema| table_schema.table_name.to_string());
+
+        let mut schemas: Vec<&str> = table_schemas
+            .iter()
+            .map(|table_schema| table_schema.table_name.schema.as_str())
+            .collect();
+        schemas.dedup();
+        let mut statements: Vec<String> = schemas
+            .into_iter()
+            .map(DuckDbClient::create_schema_ddl)
+            .collect();
+
+        for table_schema in &table_schemas {
+            let column_schemas = match self.write_mode {
+                WriteMode::Upsert => table_schema.column_schemas.clone(),
+                WriteMode::AppendOnly => DuckDbExecutor::change_log_column_schemas(table_schema),
+            };
+            statements.push(DuckDbClient::create_table_ddl(
+                &table_schema.table_name,
+                &column_schemas,
+            ));
+        }
+
+        Ok(statements)
+    }
+}
+
+#[async_trait]
+impl PreviewSink for DuckDbSink {
+    /// Returns the statements the rows are written with, followed by their
+    /// parameters
+    async fn preview_rows(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut statements = Vec::with_capacity(rows.len());
+        for (i, preview_row) in rows.into_iter().enumerate() {
+            let PreviewRow {
+                table_id,
+                operation,
+                row,
+            } = preview_row;
+            let table_schema = table_schemas
+                .get(&table_id)
+                .ok_or(DuckDbExecutorError::MissingTableId(table_id))?;
+            let table_schema = self.naming_convention.table_schema(table_schema);
+            // copied rows come before every streamed change
+            let (change_type, index) = match operation {
+                RowOperation::Copy => ("INSERT", 0),
+                RowOperation::Insert => ("INSERT", i as u64 + 1),
+                RowOperation::Update => ("UPDATE", i as u64 + 1),
+                RowOperation::Delete => ("DELETE", i as u64 + 1),
+            };
+            statements.push(DuckDbExecutor::change_statement(
+                self.write_mode,
+                &table_schema,
+                row,
+                change_type,
+                0,
+                index,
+            ));
+        }
+
+        Ok(statements)
+    }
 }
@@ -0,0 +1,407 @@
+//! A sink keeping everything written to it in memory, so that tests can
+//! assert on the tables, rows, changes and commits a pipeline writes, e.g.
+//! to check the results of type overrides or encrypted columns.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{
+        cdc_event::{replication_timestamp, CdcEvent},
+        json::cell_to_json,
+        table_row::TableRow,
+        Cell,
+    },
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableName, TableSchema},
+};
+
+use super::{BatchSink, RemovedTablePolicy, RowOperation, SinkError};
+
+#[derive(Debug, Error)]
+pub enum MemorySinkError {
+    #[error("invalid relation message: {0}")]
+    InvalidRelation(#[from] std::io::Error),
+}
+
+impl SinkError for MemorySinkError {}
+
+/// A change of the tables written to a [`MemorySink`]
+#[derive(Debug, Clone)]
+pub enum SchemaChange {
+    /// Schema of a table written when the pipeline started or the table was
+    /// added to it
+    Created(TableSchema),
+
+    /// Names of the columns of a table after its schema changed in the
+    /// source
+    Altered {
+        table_id: TableId,
+        columns: Vec<String>,
+    },
+
+    /// A table truncated before its copy
+    Truncated(TableId),
+
+    /// A copied table which is no longer replicated
+    Pruned {
+        table_id: TableId,
+        table_name: Option<TableName>,
+        policy: RemovedTablePolicy,
+    },
+}
+
+/// A change of a row written to a [`MemorySink`]
+#[derive(Debug, Clone)]
+pub struct MemoryChange {
+    pub table_id: TableId,
+    pub operation: RowOperation,
+    pub values: Vec<Cell>,
+    /// Lsn of the commit of the change's transaction
+    pub commit_lsn: Option<PgLsn>,
+}
+
+/// A transaction committed to a [`MemorySink`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryCommit {
+    pub commit_lsn: PgLsn,
+    pub end_lsn: PgLsn,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Everything written to a [`MemorySink`], in the order it was written
+#[derive(Debug, Clone, Default)]
+pub struct MemorySinkContents {
+    /// Schemas of the tables as written when the pipeline started or the
+    /// tables were added to it
+    pub table_schemas: HashMap<TableId, TableSchema>,
+    pub schema_changes: Vec<SchemaChange>,
+    /// Values of the copied rows of each table
+    pub copied_rows: HashMap<TableId, Vec<Vec<Cell>>>,
+    pub copied_tables: HashSet<TableId>,
+    pub changes: Vec<MemoryChange>,
+    pub commits: Vec<MemoryCommit>,
+}
+
+impl MemorySinkContents {
+    /// Returns the id of the table named like `public.users`
+    pub fn table_id(&self, table_name: &str) -> Option<TableId> {
+        self.table_schemas
+            .values()
+            .find(|table_schema| table_schema.table_name.to_string() == table_name)
+            .map(|table_schema| table_schema.table_id)
+    }
+
+    /// Lsn of the last committed transaction, zero if none was
+    pub fn last_lsn(&self) -> PgLsn {
+        self.commits
+            .last()
+            .map(|commit| commit.commit_lsn)
+            .unwrap_or(PgLsn::from(0))
+    }
+
+    /// Changes of a table, in the order they were written
+    pub fn table_changes(&self, table_id: TableId) -> impl Iterator<Item = &MemoryChange> {
+        self.changes
+            .iter()
+            .filter(move |change| change.table_id == table_id)
+    }
+
+    /// Rows a table holds after its copy and changes: inserted rows are
+    /// appended, and updated rows are replaced and deleted rows removed by
+    /// their primary key. Tables without a primary key only get the inserted
+    /// rows appended.
+    pub fn table_rows(&self, table_id: TableId) -> Vec<Vec<Cell>> {
+        let mut rows = self.copied_rows.get(&table_id).cloned().unwrap_or_default();
+        let key_positions: Vec<usize> = self
+            .table_schemas
+            .get(&table_id)
+            .map(|table_schema| {
+                table_schema
+                    .column_schemas
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, column_schema)| column_schema.primary)
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let key = |values: &[Cell]| -> Vec<Value> {
+            key_positions
+                .iter()
+                .map(|&i| values.get(i).map(cell_to_json).unwrap_or(Value::Null))
+                .collect()
+        };
+
+        for change in self.table_changes(table_id) {
+            if change.operation != RowOperation::Insert && key_positions.is_empty() {
+                continue;
+            }
+            let change_key = key(&change.values);
+            match change.operation {
+                RowOperation::Insert | RowOperation::Copy => rows.push(change.values.clone()),
+                RowOperation::Update => match rows.iter_mut().find(|row| key(row) == change_key) {
+                    Some(row) => *row = change.values.clone(),
+                    None => rows.push(change.values.clone()),
+                },
+                RowOperation::Delete => rows.retain(|row| key(row) != change_key),
+            }
+        }
+        rows
+    }
+}
+
+/// A sink keeping what's written to it in memory. Clones share what was
+/// written, so a clone kept by a test sees what the pipeline running the
+/// sink writes.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    contents: Arc<Mutex<MemorySinkContents>>,
+    /// Commit lsn of the transaction whose changes are written
+    commit_lsn: Option<PgLsn>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// What was written so far. The sink waits for the returned guard to
+    /// be dropped before it writes again
+    pub fn contents(&self) -> MutexGuard<'_, MemorySinkContents> {
+        // the contents stay usable if a test panicked while holding them
+        self.contents.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl BatchSink for MemorySink {
+    type Error = MemorySinkError;
+
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        let contents = self.contents();
+        Ok(PipelineResumptionState {
+            copied_tables: contents.copied_tables.clone(),
+            last_lsn: contents.last_lsn(),
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        let mut table_schemas: Vec<TableSchema> = table_schemas.into_values().collect();
+        table_schemas.sort_by_key(|table_schema| table_schema.table_id);
+        let mut contents = self.contents();
+        for table_schema in table_schemas {
+            contents
+                .schema_changes
+                .push(SchemaChange::Created(table_schema.clone()));
+            contents
+                .table_schemas
+                .insert(table_schema.table_id, table_schema);
+        }
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        self.contents()
+            .copied_rows
+            .entry(table_id)
+            .or_default()
+            .extend(rows.into_iter().map(|row| row.values));
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut contents = self.contents.lock().unwrap_or_else(PoisonError::into_inner);
+        for event in events {
+            let (table_id, operation, row) = match event {
+                CdcEvent::Begin(begin_body) => {
+                    self.commit_lsn = Some(PgLsn::from(begin_body.final_lsn()));
+                    continue;
+                }
+                CdcEvent::Commit(commit_body) => {
+                    contents.commits.push(MemoryCommit {
+                        commit_lsn: PgLsn::from(commit_body.commit_lsn()),
+                        end_lsn: PgLsn::from(commit_body.end_lsn()),
+                        timestamp: replication_timestamp(commit_body.timestamp()),
+                    });
+                    self.commit_lsn = None;
+                    continue;
+                }
+                CdcEvent::Relation(relation_body) => {
+                    let columns = relation_body
+                        .columns()
+                        .iter()
+                        .map(|column| column.name().map(str::to_string))
+                        .collect::<Result<_, _>>()?;
+                    contents.schema_changes.push(SchemaChange::Altered {
+                        table_id: relation_body.rel_id(),
+                        columns,
+                    });
+                    continue;
+                }
+                CdcEvent::Insert((table_id, row)) => (table_id, RowOperation::Insert, row),
+                CdcEvent::Update((table_id, row)) => (table_id, RowOperation::Update, row),
+                CdcEvent::Delete((table_id, row)) => (table_id, RowOperation::Delete, row),
+                CdcEvent::Type(_) | CdcEvent::KeepAliveRequested { .. } => continue,
+            };
+            contents.changes.push(MemoryChange {
+                table_id,
+                operation,
+                values: row.values,
+                commit_lsn: self.commit_lsn,
+            });
+        }
+        Ok(contents.last_lsn())
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.contents().copied_tables.insert(table_id);
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        let mut contents = self.contents();
+        contents.copied_rows.remove(&table_id);
+        contents
+            .schema_changes
+            .push(SchemaChange::Truncated(table_id));
+        Ok(())
+    }
+
+    async fn prune_table(
+        &mut self,
+        table_id: TableId,
+        table_name: Option<&TableName>,
+        policy: RemovedTablePolicy,
+    ) -> Result<(), Self::Error> {
+        let mut contents = self.contents();
+        contents.copied_tables.remove(&table_id);
+        contents.copied_rows.remove(&table_id);
+        contents.schema_changes.push(SchemaChange::Pruned {
+            table_id,
+            table_name: table_name.cloned(),
+            policy,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashSet},
+        time::Duration,
+    };
+
+    use serde_json::Value;
+
+    use crate::{
+        conversions::{json::cell_to_json, Cell},
+        pipeline::{
+            batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+            sinks::RowOperation,
+            sources::synthetic::{SyntheticSource, SyntheticSourceConfig},
+            PipelineAction,
+        },
+    };
+
+    use super::MemorySink;
+
+    const ROWS_PER_TABLE: u64 = 20;
+    const NUM_CHANGES: u64 = 120;
+    const CHANGES_PER_TRANSACTION: u32 = 6;
+
+    #[tokio::test]
+    async fn tables_hold_the_copied_rows_with_the_changes_applied() {
+        let source = SyntheticSource::new(SyntheticSourceConfig {
+            num_tables: 2,
+            num_columns: 3,
+            rows_per_table: ROWS_PER_TABLE,
+            num_changes: Some(NUM_CHANGES),
+            changes_per_transaction: CHANGES_PER_TRANSACTION,
+            seed: 7,
+            ..SyntheticSourceConfig::default()
+        });
+        let sink = MemorySink::new();
+        // small batches end in the middle of transactions
+        let batch_config = BatchConfig::new(4, Duration::from_millis(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink.clone(), PipelineAction::Both, batch_config);
+        pipeline.start().await.unwrap();
+
+        let contents = sink.contents();
+        assert_eq!(contents.copied_tables, HashSet::from([1, 2]));
+        assert_eq!(contents.changes.len(), NUM_CHANGES as usize);
+        assert_eq!(
+            contents.commits.len(),
+            (NUM_CHANGES / CHANGES_PER_TRANSACTION as u64) as usize
+        );
+        let commit_lsns: HashSet<_> = contents
+            .commits
+            .iter()
+            .map(|commit| commit.commit_lsn)
+            .collect();
+        assert!(contents.changes.iter().all(|change| change
+            .commit_lsn
+            .is_some_and(|lsn| commit_lsns.contains(&lsn))));
+        for operation in [
+            RowOperation::Insert,
+            RowOperation::Update,
+            RowOperation::Delete,
+        ] {
+            assert!(contents
+                .changes
+                .iter()
+                .any(|change| change.operation == operation));
+        }
+
+        for table_id in [1, 2] {
+            let copied_rows = &contents.copied_rows[&table_id];
+            assert_eq!(copied_rows.len(), ROWS_PER_TABLE as usize);
+
+            // the rows by their id, with the table's changes applied in order
+            let json_row =
+                |values: &[Cell]| -> Vec<Value> { values.iter().map(cell_to_json).collect() };
+            let mut expected: BTreeMap<i64, Vec<Value>> = copied_rows
+                .iter()
+                .map(|values| json_row(values.as_slice()))
+                .map(|row| (row[0].as_i64().unwrap(), row))
+                .collect();
+            for change in contents.table_changes(table_id) {
+                let change_row = json_row(change.values.as_slice());
+                let id = change_row[0].as_i64().unwrap();
+                match change.operation {
+                    RowOperation::Delete => {
+                        expected.remove(&id);
+                    }
+                    _ => {
+                        expected.insert(id, change_row);
+                    }
+                }
+            }
+
+            let mut rows: Vec<Vec<Value>> = contents
+                .table_rows(table_id)
+                .iter()
+                .map(|values| json_row(values.as_slice()))
+                .collect();
+            rows.sort_by_key(|row| row[0].as_i64());
+            assert_eq!(rows, expected.into_values().collect::<Vec<_>>());
+        }
+    }
+}
@@ -1,12 +1,18 @@
 use std::collections::HashMap;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use postgres_replication::protocol::BeginBody;
 use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 
 use crate::{
-    conversions::{cdc_event::CdcEvent, table_row::TableRow},
-    table::{TableId, TableSchema},
+    conversions::{
+        cdc_event::{replication_timestamp, CdcEvent},
+        table_row::{RowPool, TableRow},
+    },
+    pipeline::verification::{ChecksumSpec, RangeChecksum},
+    table::{TableId, TableName, TableSchema},
 };
 
 use super::PipelineResumptionState;
@@ -17,18 +23,91 @@ pub mod bigquery;
 pub mod delta;
 #[cfg(feature = "duckdb")]
 pub mod duckdb;
+#[cfg(feature = "memory")]
+pub mod memory;
+pub mod naming;
+#[cfg(feature = "null")]
+pub mod null;
+#[cfg(feature = "delta")]
+pub mod parquet;
 #[cfg(feature = "stdout")]
 pub mod stdout;
 
-pub trait SinkError: std::error::Error + Send + Sync + 'static {}
+/// How a sink writes the changes of replicated tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WriteMode {
+    /// Apply changes to the tables so that they hold the current state of
+    /// the source tables. Tables without a primary key can't be updated
+    /// and get every change appended instead.
+    #[default]
+    Upsert,
+
+    /// Append every change to the tables as a row with the type of the
+    /// change and its position in the replication stream
+    AppendOnly,
+}
+
+/// How sinks without schemas of their own, like BigQuery and Delta, name
+/// the destination table of a source table `schema.name`. Explicitly named
+/// tables only replace the table's name, its namespace still follows the
+/// strategy. DuckDB creates the source schemas and keeps the names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum NamingStrategy {
+    /// A table `schema_name`
+    #[default]
+    SchemaPrefixed,
+
+    /// A table `name` in a namespace of the schema: a dataset in BigQuery,
+    /// a directory in Delta
+    PerSchema,
+}
+
+impl NamingStrategy {
+    /// Name of the destination table, without its namespace
+    pub fn table_name(&self, table_name: &TableName) -> String {
+        match self {
+            NamingStrategy::SchemaPrefixed => format!("{}_{}", table_name.schema, table_name.name),
+            NamingStrategy::PerSchema => table_name.name.clone(),
+        }
+    }
+}
+
+pub trait SinkError: std::error::Error + Send + Sync + 'static {
+    /// Whether retrying the failed write may succeed, e.g. after a rate
+    /// limit or a lost connection. Errors are fatal by default
+    fn is_retryable(&self) -> bool {
+        false
+    }
+}
 
 #[derive(Debug, Error)]
 #[error("unreachable")]
 pub enum InfallibleSinkError {}
 impl SinkError for InfallibleSinkError {}
 
+/// A transaction of the source whose changes are written to a sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionMetadata {
+    pub xid: u32,
+
+    /// Position of the transaction's commit in the source's WAL
+    pub commit_lsn: PgLsn,
+
+    pub commit_timestamp: DateTime<Utc>,
+}
+
+impl From<&BeginBody> for TransactionMetadata {
+    fn from(begin_body: &BeginBody) -> Self {
+        TransactionMetadata {
+            xid: begin_body.xid(),
+            commit_lsn: PgLsn::from(begin_body.final_lsn()),
+            commit_timestamp: replication_timestamp(begin_body.timestamp()),
+        }
+    }
+}
+
 #[async_trait]
-pub trait BatchSink {
+pub trait BatchSink: Send {
     type Error: SinkError;
     async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error>;
     async fn write_table_schemas(
@@ -43,4 +122,190 @@ pub trait BatchSink {
     async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error>;
     async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error>;
     async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error>;
+
+    /// Applies the policy to the destination table of a copied table which
+    /// is no longer replicated and forgets that the table was copied, so
+    /// that it's copied again if it's replicated again. The table's name is
+    /// unknown if it was dropped from the source, only its copy is then
+    /// forgotten. Sinks which don't keep track of copied tables ignore it.
+    async fn prune_table(
+        &mut self,
+        _table_id: TableId,
+        _table_name: Option<&TableName>,
+        _policy: RemovedTablePolicy,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Whether the pipeline calls [`begin_transaction`](Self::begin_transaction)
+    /// and [`commit_transaction`](Self::commit_transaction). The changes of
+    /// a batch are then written by a call of
+    /// [`write_cdc_events`](Self::write_cdc_events) per transaction, which
+    /// costs sinks with expensive writes their batching.
+    fn transaction_callbacks(&self) -> bool {
+        false
+    }
+
+    /// The pool the sink hands written rows back to, which the pipeline
+    /// gives its source to decode rows with. None if the sink doesn't hand
+    /// rows back.
+    fn row_pool(&self) -> Option<RowPool> {
+        None
+    }
+
+    /// Called before the changes of a transaction are written, e.g. to emit
+    /// a marker of its beginning
+    async fn begin_transaction(
+        &mut self,
+        _transaction: &TransactionMetadata,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called after the changes of a transaction, up to its commit, were
+    /// written. Transactions whose beginning was written before the
+    /// pipeline restarted aren't committed, their changes are streamed
+    /// again from their beginning.
+    async fn commit_transaction(
+        &mut self,
+        _transaction: &TransactionMetadata,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A sink which can show how it would create the destination tables of
+/// source tables, so that they can be reviewed before replicating
+#[async_trait]
+pub trait DdlSink: BatchSink {
+    /// Returns the statements creating the destination tables of the table
+    /// schemas, or their schemas for sinks which create tables without
+    /// statements, without writing anything. The sink only runs the
+    /// statements of the tables which don't exist yet.
+    async fn table_ddl(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error>;
+}
+
+/// How a row reaches a sink, by a table's copy or by a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOperation {
+    Copy,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl RowOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RowOperation::Copy => "copy",
+            RowOperation::Insert => "insert",
+            RowOperation::Update => "update",
+            RowOperation::Delete => "delete",
+        }
+    }
+}
+
+/// A row of a source table to preview, with the values the sink would get,
+/// e.g. with its encrypted columns encrypted
+#[derive(Debug)]
+pub struct PreviewRow {
+    pub table_id: TableId,
+    pub operation: RowOperation,
+    pub row: TableRow,
+}
+
+/// A sink which can show what it would write for rows of source tables, so
+/// that the names of destination tables and the transforms of the values
+/// can be reviewed before replicating. See
+/// [`dry_run`](crate::pipeline::dry_run::dry_run).
+#[async_trait]
+pub trait PreviewSink: BatchSink {
+    /// Returns the rows as the sink would write them to the destination
+    /// tables of the table schemas, in the sink's serialized form and in
+    /// the order of the rows, without writing anything. The table schemas
+    /// are the ones the sink would get, e.g. with encrypted columns as
+    /// `bytea` columns.
+    async fn preview_rows(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error>;
+}
+
+/// What to do with the destination table of a table which is no longer
+/// replicated, because it was removed from the publication or from the
+/// selected tables. Applied when a pipeline starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RemovedTablePolicy {
+    /// Keep the table as it is. It isn't copied again if it's replicated
+    /// again, so it misses the changes made in the meantime.
+    #[default]
+    Keep,
+
+    /// Drop the table
+    Drop,
+
+    /// Rename the table with an `_archived_YYYYMMDD` suffix of the day it
+    /// was archived
+    Archive,
+
+    /// Keep the table with the rows it had and mark it as frozen, with a
+    /// label or comment depending on the sink
+    Freeze,
+}
+
+/// A sink keeping the state of a pipeline, which can be written without
+/// replicating, to move the state of a pipeline from another sink. See
+/// [`import_state`](crate::pipeline::state::import_state).
+#[async_trait]
+pub trait StateSink: BatchSink {
+    /// Sets the position in the source's WAL up to which changes were
+    /// written
+    async fn set_last_lsn(&mut self, last_lsn: PgLsn) -> Result<(), Self::Error>;
+}
+
+/// A sink whose destination tables can be hashed in ranges of their keys,
+/// the same way as a [`ChecksumSource`](crate::pipeline::sources::ChecksumSource)
+/// hashes the source tables. Used by the
+/// [verification](crate::pipeline::verification) subsystem.
+#[async_trait]
+pub trait ChecksumSink: BatchSink {
+    /// Returns the row count and checksum of each range of the keys of the
+    /// destination table for `table_schema` which holds rows, or `None` if
+    /// the destination table doesn't exist.
+    async fn checksum_ranges(
+        &mut self,
+        table_schema: &TableSchema,
+        spec: &ChecksumSpec,
+    ) -> Result<Option<Vec<RangeChecksum>>, Self::Error>;
+}
+
+/// A sink whose destination tables can be searched for rows by their keys.
+/// Used by the [erasure](crate::pipeline::erasure) subsystem.
+#[async_trait]
+pub trait ErasureSink: BatchSink {
+    /// Returns which of the keys are keys of rows of the destination table
+    /// for `table_schema`, as rendered by
+    /// [`SourceEraser`](crate::pipeline::erasure::SourceEraser), or `None`
+    /// if the destination table doesn't exist.
+    async fn find_keys(
+        &mut self,
+        table_schema: &TableSchema,
+        keys: &[String],
+    ) -> Result<Option<Vec<String>>, Self::Error>;
+}
+
+/// A sink whose destination tables can be queried for their row counts.
+/// Used by the [verification](crate::pipeline::verification) subsystem.
+#[async_trait]
+pub trait RowCountSink: BatchSink {
+    /// Returns the number of rows in the destination table for `table_schema`
+    /// or `None` if the destination table doesn't exist.
+    async fn count_table_rows(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<Option<u64>, Self::Error>;
 }
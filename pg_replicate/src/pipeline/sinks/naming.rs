@@ -0,0 +1,135 @@
+//! Conventions the names of destination tables and columns follow, e.g.
+//! snake_case names in a warehouse replicating a source which uses camel
+//! case, or the tables of a staging environment kept apart from the
+//! production ones. A convention renames the source's tables and columns
+//! before a sink lays them out with its [`NamingStrategy`] and makes them
+//! valid names of its destination, so names set explicitly for tables
+//! aren't renamed.
+//!
+//! [`NamingStrategy`]: super::NamingStrategy
+
+use std::fmt::Debug;
+
+use crate::table::{TableName, TableSchema};
+
+/// Renames the tables and columns of the source in the destination. Both
+/// names are kept by default, custom conventions override either of them
+pub trait NamingConvention: Debug + Send + Sync {
+    /// Name of the destination table of a source table, with the schema
+    /// it's laid out by
+    fn table_name(&self, table_name: &TableName) -> TableName {
+        table_name.clone()
+    }
+
+    /// Name of the destination column of a source column
+    fn column_name(&self, column_name: &str) -> String {
+        column_name.to_string()
+    }
+
+    /// Returns a copy of the table schema with the table and its columns
+    /// renamed
+    fn table_schema(&self, table_schema: &TableSchema) -> TableSchema {
+        let mut table_schema = table_schema.clone();
+        table_schema.table_name = self.table_name(&table_schema.table_name);
+        for column_schema in &mut table_schema.column_schemas {
+            column_schema.name = self.column_name(&column_schema.name);
+        }
+        table_schema
+    }
+}
+
+/// Keeps the names of the source, the default convention
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceNames;
+
+impl NamingConvention for SourceNames {}
+
+/// A built-in convention. A list of rules is a convention applying them in
+/// order, e.g. `[SnakeCase, { EnvironmentPrefix: staging }]`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum NamingRule {
+    /// Converts camel case and other separators to snake case, e.g.
+    /// `OrderItems`, `orderItems` and `order-items` to `order_items`
+    SnakeCase,
+
+    /// Lowercases the names, e.g. `Orders` to `orders`
+    Lowercase,
+
+    /// Prefixes the schemas of the tables with an environment, e.g.
+    /// `staging` writes the table `public.orders` as `staging_public_orders`,
+    /// or as `orders` in the namespace `staging_public` with the per schema
+    /// strategy. Column names are kept
+    EnvironmentPrefix(String),
+}
+
+impl NamingConvention for NamingRule {
+    fn table_name(&self, table_name: &TableName) -> TableName {
+        match self {
+            NamingRule::SnakeCase => TableName {
+                schema: snake_case(&table_name.schema),
+                name: snake_case(&table_name.name),
+            },
+            NamingRule::Lowercase => TableName {
+                schema: table_name.schema.to_lowercase(),
+                name: table_name.name.to_lowercase(),
+            },
+            NamingRule::EnvironmentPrefix(environment) => TableName {
+                schema: format!("{environment}_{}", table_name.schema),
+                name: table_name.name.clone(),
+            },
+        }
+    }
+
+    fn column_name(&self, column_name: &str) -> String {
+        match self {
+            NamingRule::SnakeCase => snake_case(column_name),
+            NamingRule::Lowercase => column_name.to_lowercase(),
+            NamingRule::EnvironmentPrefix(_) => column_name.to_string(),
+        }
+    }
+}
+
+impl NamingConvention for Vec<NamingRule> {
+    fn table_name(&self, table_name: &TableName) -> TableName {
+        self.iter().fold(table_name.clone(), |table_name, rule| {
+            rule.table_name(&table_name)
+        })
+    }
+
+    fn column_name(&self, column_name: &str) -> String {
+        self.iter()
+            .fold(column_name.to_string(), |column_name, rule| {
+                rule.column_name(&column_name)
+            })
+    }
+}
+
+/// Inserts underscores between words, starting with an uppercase letter
+/// after a lowercase letter or a digit, or with the last of a run of
+/// uppercase letters followed by a lowercase one, replaces other characters
+/// than letters and digits with underscores and lowercases the letters.
+/// Runs of underscores are collapsed to one
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !snake.ends_with('_') {
+                snake.push('_');
+            }
+            continue;
+        }
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lowercase = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            let starts_word = previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next_is_lowercase);
+            if starts_word && !snake.ends_with('_') {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{
+        cdc_event::CdcEvent,
+        table_row::{RowPool, TableRow},
+    },
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableName, TableSchema},
+};
+
+use super::{BatchSink, DdlSink, PreviewRow, PreviewSink, RemovedTablePolicy, SinkError};
+
+#[derive(Debug, Error)]
+pub enum NullSinkError {
+    #[error("no schema was written for table {0}")]
+    MissingTableSchema(TableId),
+
+    #[error("a row of table {table_id} has {actual} columns instead of {expected}")]
+    ColumnCountMismatch {
+        table_id: TableId,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("commit lsn {commit_lsn} is before the last committed lsn {last_lsn}")]
+    LsnWentBackwards { commit_lsn: PgLsn, last_lsn: PgLsn },
+}
+
+impl SinkError for NullSinkError {}
+
+/// A sink discarding everything written to it after checking that rows
+/// match their table's schema and that commits are in order. It confirms
+/// the changes it discards, which measures the throughput of sources and of
+/// the pipeline without the cost of a sink, and drains a slot without
+/// writing anywhere. Nothing is persisted, so a restarted pipeline copies
+/// the tables again.
+#[derive(Debug, Default)]
+pub struct NullSink {
+    /// Number of columns of the tables
+    num_columns: HashMap<TableId, usize>,
+    copied_tables: HashSet<TableId>,
+    last_lsn: u64,
+    row_pool: RowPool,
+}
+
+impl NullSink {
+    fn check_row(&self, table_id: TableId, row: &TableRow) -> Result<(), NullSinkError> {
+        let expected = *self
+            .num_columns
+            .get(&table_id)
+            .ok_or(NullSinkError::MissingTableSchema(table_id))?;
+        let actual = row.values.len();
+        if actual != expected {
+            return Err(NullSinkError::ColumnCountMismatch {
+                table_id,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BatchSink for NullSink {
+    type Error = NullSinkError;
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        Ok(PipelineResumptionState {
+            copied_tables: self.copied_tables.clone(),
+            last_lsn: PgLsn::from(self.last_lsn),
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.num_columns.extend(
+            table_schemas
+                .into_iter()
+                .map(|(table_id, table_schema)| (table_id, table_schema.column_schemas.len())),
+        );
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        for row in &rows {
+            self.check_row(table_id, row)?;
+        }
+        self.row_pool.recycle(rows);
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut written_rows = Vec::with_capacity(events.len());
+        for event in events {
+            match event {
+                CdcEvent::Commit(commit_body) => {
+                    let commit_lsn = commit_body.commit_lsn();
+                    if commit_lsn < self.last_lsn {
+                        return Err(NullSinkError::LsnWentBackwards {
+                            commit_lsn: PgLsn::from(commit_lsn),
+                            last_lsn: PgLsn::from(self.last_lsn),
+                        });
+                    }
+                    self.last_lsn = commit_lsn;
+                }
+                CdcEvent::Insert((table_id, row))
+                | CdcEvent::Update((table_id, row))
+                | CdcEvent::Delete((table_id, row)) => {
+                    self.check_row(table_id, &row)?;
+                    written_rows.push(row);
+                }
+                CdcEvent::Relation(relation_body) => {
+                    self.num_columns
+                        .insert(relation_body.rel_id(), relation_body.columns().len());
+                }
+                CdcEvent::Begin(_) | CdcEvent::Type(_) | CdcEvent::KeepAliveRequested { .. } => {}
+            }
+        }
+        self.row_pool.recycle(written_rows);
+        Ok(PgLsn::from(self.last_lsn))
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.copied_tables.insert(table_id);
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn prune_table(
+        &mut self,
+        table_id: TableId,
+        _table_name: Option<&TableName>,
+        _policy: RemovedTablePolicy,
+    ) -> Result<(), Self::Error> {
+        self.copied_tables.remove(&table_id);
+        Ok(())
+    }
+
+    fn row_pool(&self) -> Option<RowPool> {
+        Some(self.row_pool.clone())
+    }
+}
+
+#[async_trait]
+impl DdlSink for NullSink {
+    async fn table_ddl(
+        &mut self,
+        _table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl PreviewSink for NullSink {
+    async fn preview_rows(
+        &mut self,
+        _table_schemas: &HashMap<TableId, TableSchema>,
+        _rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(vec![])
+    }
+}
@@ -0,0 +1,281 @@
+//! A sink writing snapshots of tables to Parquet files, one file per table
+//! in a local directory or an object store. Its pipelines only copy tables:
+//! a pipeline with the [`PipelineAction::TableCopiesOnly`] action copying
+//! from a [`PostgresSource`] without a slot reads all tables in one
+//! transaction, so the files are a consistent snapshot of the tables, and
+//! no slot is left behind.
+//!
+//! ```no_run
+//! # async fn snapshot(
+//! #     source: pg_replicate::pipeline::sources::postgres::PostgresSource,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//!
+//! use pg_replicate::pipeline::{
+//!     batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+//!     sinks::parquet::ParquetSink,
+//!     PipelineAction,
+//! };
+//!
+//! let sink = ParquetSink::new("s3://bucket/snapshots/2024-06-01".to_string());
+//! let batch_config = BatchConfig::new(10000, Duration::from_secs(10));
+//! let mut pipeline =
+//!     BatchDataPipeline::new(source, sink, PipelineAction::TableCopiesOnly, batch_config);
+//! pipeline.start().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`PipelineAction::TableCopiesOnly`]: crate::pipeline::PipelineAction::TableCopiesOnly
+//! [`PostgresSource`]: crate::pipeline::sources::postgres::PostgresSource
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use deltalake::{
+    arrow::{compute::concat_batches, datatypes::Schema, error::ArrowError},
+    parquet::{arrow::AsyncArrowWriter, errors::ParquetError},
+    storage::{object_store::buffered::BufWriter, ObjectStoreRef},
+    DeltaTableBuilder, DeltaTableError, Path,
+};
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+use tracing::info;
+
+use super::{
+    naming::{NamingConvention, SourceNames},
+    BatchSink, NamingStrategy, SinkError,
+};
+use crate::{
+    clients::delta::{register_object_stores, DeltaClient, DeltaType, ParquetCompression},
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+#[derive(Debug, Error)]
+pub enum ParquetSinkError {
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+
+    #[error("delta error: {0}")]
+    Delta(#[from] DeltaTableError),
+
+    #[error("missing table id: {0}")]
+    MissingTableId(TableId),
+
+    #[error("the parquet sink only writes copies of tables, not their changes")]
+    ChangesNotSupported,
+}
+
+impl SinkError for ParquetSinkError {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ParquetSinkError::Delta(DeltaTableError::ObjectStore { .. })
+                | ParquetSinkError::Parquet(ParquetError::External(_))
+        )
+    }
+}
+
+/// The file a table's copy is being written to
+struct TableFile {
+    path: Path,
+    schema: Arc<Schema>,
+    writer: AsyncArrowWriter<BufWriter>,
+    rows: usize,
+}
+
+/// Writes the copy of each table to a Parquet file named like the tables of
+/// the Delta sink, `<path>/schema_name.parquet` by default, with the columns
+/// typed like the Delta sink types them. Files of tables copied again are
+/// replaced. Every pipeline copies all the tables again, as the sink keeps
+/// no state.
+pub struct ParquetSink {
+    client: DeltaClient,
+    /// Store rooted at the path, connected when the first file is written
+    store: Option<ObjectStoreRef>,
+    table_schemas: HashMap<TableId, TableSchema>,
+    files: HashMap<TableId, TableFile>,
+}
+
+impl ParquetSink {
+    /// Creates a sink writing to a local directory or an object store url,
+    /// e.g. `s3://bucket/path`. Object store credentials which aren't set
+    /// with [ParquetSink::with_storage_options] are read from the
+    /// environment
+    pub fn new(path: String) -> Self {
+        register_object_stores();
+        ParquetSink {
+            client: DeltaClient {
+                path,
+                storage_options: HashMap::new(),
+                table_schemas: None,
+                delta_schemas: None,
+                table_options: HashMap::new(),
+                change_data_feed: false,
+                compression: ParquetCompression::default(),
+                naming: NamingStrategy::default(),
+                table_names: HashMap::new(),
+                type_mappings: HashMap::new(),
+                naming_convention: Arc::new(SourceNames),
+            },
+            store: None,
+            table_schemas: HashMap::new(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Adds raw delta-rs storage options, e.g. `AWS_REGION`
+    pub fn with_storage_options(mut self, storage_options: HashMap<String, String>) -> Self {
+        self.client.storage_options.extend(storage_options);
+        self
+    }
+
+    /// Sets the compression of the files, snappy by default
+    pub fn with_compression(mut self, compression: ParquetCompression) -> Self {
+        self.client.compression = compression;
+        self
+    }
+
+    /// Sets whether files are written to `<path>/schema_name.parquet`, the
+    /// default, or to `<path>/schema/name.parquet`
+    pub fn with_naming(mut self, naming: NamingStrategy) -> Self {
+        self.client.naming = naming;
+        self
+    }
+
+    /// Sets the convention renaming the tables and columns, e.g. to snake
+    /// case, before the files are named by the naming strategy
+    pub fn with_naming_convention(
+        mut self,
+        naming_convention: impl NamingConvention + 'static,
+    ) -> Self {
+        self.client.naming_convention = Arc::new(naming_convention);
+        self
+    }
+
+    /// Sets the types columns of Postgres types are written as, like the
+    /// Delta sink's type mappings
+    pub fn with_type_mappings(mut self, type_mappings: HashMap<String, DeltaType>) -> Self {
+        self.client.type_mappings = type_mappings;
+        self
+    }
+
+    fn store(&mut self) -> Result<ObjectStoreRef, ParquetSinkError> {
+        if let Some(store) = &self.store {
+            return Ok(store.clone());
+        }
+        let store = DeltaTableBuilder::from_uri(&self.client.path)
+            .with_storage_options(self.client.storage_options.clone())
+            .build_storage()?
+            .object_store();
+        self.store = Some(store.clone());
+        Ok(store)
+    }
+
+    /// Starts writing the file of a table, replacing the one being written
+    fn create_file(&mut self, table_id: TableId) -> Result<(), ParquetSinkError> {
+        let store = self.store()?;
+        let table_schema = self
+            .table_schemas
+            .get(&table_id)
+            .ok_or(ParquetSinkError::MissingTableId(table_id))?;
+        let path = Path::from(format!(
+            "{}.parquet",
+            self.client.table_name_in_delta(&table_schema.table_name)
+        ));
+        let schema = self
+            .client
+            .columns_arrow_schema(&table_schema.column_schemas);
+        let properties = self.client.compression.writer_properties()?;
+        let writer = AsyncArrowWriter::try_new(
+            BufWriter::new(store, path.clone()),
+            schema.clone(),
+            Some(properties),
+        )?;
+        self.files.insert(
+            table_id,
+            TableFile {
+                path,
+                schema,
+                writer,
+                rows: 0,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BatchSink for ParquetSink {
+    type Error = ParquetSinkError;
+
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        Ok(PipelineResumptionState {
+            copied_tables: Default::default(),
+            last_lsn: PgLsn::from(0),
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.table_schemas.extend(table_schemas);
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        if !self.files.contains_key(&table_id) {
+            self.create_file(table_id)?;
+        }
+        let file = self
+            .files
+            .get_mut(&table_id)
+            .ok_or(ParquetSinkError::MissingTableId(table_id))?;
+        let batches = rows
+            .iter()
+            .map(|row| self.client.row_to_batch(&row.values, &file.schema))
+            .collect::<Result<Vec<_>, _>>()?;
+        let batch = concat_batches(&file.schema, &batches)?;
+        file.writer.write(&batch).await?;
+        file.rows += rows.len();
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, _events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        Err(ParquetSinkError::ChangesNotSupported)
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        // tables without rows get a file without rows
+        if !self.files.contains_key(&table_id) {
+            self.create_file(table_id)?;
+        }
+        if let Some(file) = self.files.remove(&table_id) {
+            file.writer.close().await?;
+            info!(
+                "wrote {} rows to {} in {}",
+                file.rows, file.path, self.client.path
+            );
+        }
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        // the file of a copy which failed is never completed
+        self.files.remove(&table_id);
+        Ok(())
+    }
+}
@@ -1,22 +1,213 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufWriter, Write},
+};
 
 use async_trait::async_trait;
+use serde_json::{Map, Value};
+use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 use tracing::info;
 
 use crate::{
-    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    conversions::{
+        cdc_event::CdcEvent,
+        json::{cell_to_text, row_to_json},
+        table_row::{RowPool, TableRow},
+    },
     pipeline::PipelineResumptionState,
     table::{TableId, TableSchema},
 };
 
-use super::{BatchSink, InfallibleSinkError};
+use super::{BatchSink, DdlSink, PreviewRow, PreviewSink, SinkError, TransactionMetadata};
+
+#[derive(Debug, Error)]
+pub enum StdoutSinkError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("no schema was written for table {0}")]
+    MissingTableSchema(TableId),
+}
+
+impl SinkError for StdoutSinkError {}
+
+/// How the stdout sink prints rows and changes. The structured formats wrap
+/// each row in an envelope with the name of its table, the operation, one of
+/// `copy`, `insert`, `update` and `delete`, and the lsn of the commit of its
+/// transaction, which copied rows have none of. The changes of each
+/// transaction are preceded by a `begin` and followed by a `commit` marker
+/// with the transaction's xid, commit lsn and commit timestamp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Logs the rows and changes in their debug representation
+    #[default]
+    Log,
+
+    /// One json object per line, with the row as an object of its columns
+    JsonLines,
+
+    /// Indented json objects
+    PrettyJson,
+
+    /// A csv record per row, whose fields are the table, the operation, the
+    /// lsn and then the row's values in the order of the table's columns.
+    /// Nulls are empty fields. The markers of transactions have an empty
+    /// table and the xid and commit timestamp as values.
+    Csv,
+}
+
+/// A sink printing the rows and changes written to it, to stdout or to
+/// another output like a pipe. Nothing is persisted, so a restarted
+/// pipeline copies the tables again.
+pub struct StdoutSink {
+    format: OutputFormat,
+    output: BufWriter<Box<dyn Write + Send>>,
+    table_schemas: HashMap<TableId, TableSchema>,
+    /// Lsn of the commit of the transaction whose changes are written
+    commit_lsn: Option<PgLsn>,
+    row_pool: RowPool,
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        StdoutSink::new()
+    }
+}
+
+impl StdoutSink {
+    pub fn new() -> StdoutSink {
+        StdoutSink {
+            format: OutputFormat::default(),
+            output: BufWriter::new(Box::new(io::stdout())),
+            table_schemas: HashMap::new(),
+            commit_lsn: None,
+            row_pool: RowPool::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prints the structured formats to `output` instead of stdout, e.g. to
+    /// a pipe or a file. The log format is always printed by the logger.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = BufWriter::new(Box::new(output));
+        self
+    }
+
+    fn write_row(
+        &mut self,
+        table_id: TableId,
+        op: &str,
+        lsn: Option<PgLsn>,
+        row: &TableRow,
+    ) -> Result<(), StdoutSinkError> {
+        let table_schema = self
+            .table_schemas
+            .get(&table_id)
+            .ok_or(StdoutSinkError::MissingTableSchema(table_id))?;
+        let record = self.row_record(table_schema, op, lsn, row)?;
+        if self.format == OutputFormat::Log {
+            info!("{record}");
+        } else {
+            writeln!(self.output, "{record}")?;
+        }
+        Ok(())
+    }
+
+    /// Returns how a row is printed in the sink's format, without the line
+    /// break
+    fn row_record(
+        &self,
+        table_schema: &TableSchema,
+        op: &str,
+        lsn: Option<PgLsn>,
+        row: &TableRow,
+    ) -> Result<String, StdoutSinkError> {
+        let table = table_schema.table_name.to_string();
+        let lsn = lsn.map(|lsn| lsn.to_string());
+
+        let record = match self.format {
+            OutputFormat::Log => format!("{op} {table} {row:?}"),
+            OutputFormat::JsonLines | OutputFormat::PrettyJson => {
+                let values = row_to_json(&table_schema.column_schemas, row);
+                let mut envelope = Map::new();
+                envelope.insert("table".to_string(), Value::String(table));
+                envelope.insert("op".to_string(), Value::String(op.to_string()));
+                envelope.insert("lsn".to_string(), lsn.map_or(Value::Null, Value::String));
+                envelope.insert("row".to_string(), Value::Object(values));
+                if self.format == OutputFormat::PrettyJson {
+                    serde_json::to_string_pretty(&envelope)?
+                } else {
+                    serde_json::to_string(&envelope)?
+                }
+            }
+            OutputFormat::Csv => {
+                let mut fields = vec![table, op.to_string(), lsn.unwrap_or_default()];
+                fields.extend(row.values.iter().map(cell_to_text));
+                let record: Vec<String> =
+                    fields.iter().map(String::as_str).map(csv_field).collect();
+                record.join(",")
+            }
+        };
+        Ok(record)
+    }
+
+    fn write_transaction_marker(
+        &mut self,
+        op: &str,
+        transaction: &TransactionMetadata,
+    ) -> Result<(), StdoutSinkError> {
+        let lsn = transaction.commit_lsn.to_string();
+        let commit_timestamp = transaction.commit_timestamp.to_rfc3339();
+        match self.format {
+            OutputFormat::Log => info!("{op} {transaction:?}"),
+            OutputFormat::JsonLines | OutputFormat::PrettyJson => {
+                let mut envelope = Map::new();
+                envelope.insert("op".to_string(), Value::String(op.to_string()));
+                envelope.insert("lsn".to_string(), Value::String(lsn));
+                envelope.insert("xid".to_string(), Value::from(transaction.xid));
+                envelope.insert(
+                    "commit_timestamp".to_string(),
+                    Value::String(commit_timestamp),
+                );
+                if self.format == OutputFormat::PrettyJson {
+                    serde_json::to_writer_pretty(&mut self.output, &envelope)?;
+                } else {
+                    serde_json::to_writer(&mut self.output, &envelope)?;
+                }
+                self.output.write_all(b"\n")?;
+            }
+            OutputFormat::Csv => {
+                writeln!(
+                    self.output,
+                    ",{op},{lsn},{},{commit_timestamp}",
+                    transaction.xid
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
 
-pub struct StdoutSink;
+/// Quotes a csv field if it holds separators, quotes or line breaks
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
 
 #[async_trait]
 impl BatchSink for StdoutSink {
-    type Error = InfallibleSinkError;
+    type Error = StdoutSinkError;
     async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
         Ok(PipelineResumptionState {
             copied_tables: HashSet::new(),
@@ -28,25 +219,57 @@ impl BatchSink for StdoutSink {
         &mut self,
         table_schemas: HashMap<TableId, TableSchema>,
     ) -> Result<(), Self::Error> {
-        info!("{table_schemas:?}");
+        if self.format == OutputFormat::Log {
+            info!("{table_schemas:?}");
+        }
+        self.table_schemas.extend(table_schemas);
         Ok(())
     }
 
     async fn write_table_rows(
         &mut self,
         rows: Vec<TableRow>,
-        _table_id: TableId,
+        table_id: TableId,
     ) -> Result<(), Self::Error> {
-        for row in rows {
-            info!("{row:?}");
+        for row in &rows {
+            self.write_row(table_id, "copy", None, row)?;
         }
+        self.output.flush()?;
+        self.row_pool.recycle(rows);
         Ok(())
     }
 
     async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut written_rows = Vec::with_capacity(events.len());
         for event in events {
-            info!("{event:?}");
+            if self.format == OutputFormat::Log {
+                info!("{event:?}");
+                continue;
+            }
+            match event {
+                CdcEvent::Begin(begin_body) => {
+                    self.commit_lsn = Some(PgLsn::from(begin_body.final_lsn()));
+                }
+                CdcEvent::Insert((table_id, row)) => {
+                    self.write_row(table_id, "insert", self.commit_lsn, &row)?;
+                    written_rows.push(row);
+                }
+                CdcEvent::Update((table_id, row)) => {
+                    self.write_row(table_id, "update", self.commit_lsn, &row)?;
+                    written_rows.push(row);
+                }
+                CdcEvent::Delete((table_id, row)) => {
+                    self.write_row(table_id, "delete", self.commit_lsn, &row)?;
+                    written_rows.push(row);
+                }
+                CdcEvent::Commit(_)
+                | CdcEvent::Relation(_)
+                | CdcEvent::Type(_)
+                | CdcEvent::KeepAliveRequested { .. } => {}
+            }
         }
+        self.output.flush()?;
+        self.row_pool.recycle(written_rows);
         Ok(PgLsn::from(0))
     }
 
@@ -59,4 +282,57 @@ impl BatchSink for StdoutSink {
         info!("table {table_id} truncated");
         Ok(())
     }
+
+    fn transaction_callbacks(&self) -> bool {
+        // the log format logs the begin and commit events themselves
+        self.format != OutputFormat::Log
+    }
+
+    fn row_pool(&self) -> Option<RowPool> {
+        Some(self.row_pool.clone())
+    }
+
+    async fn begin_transaction(
+        &mut self,
+        transaction: &TransactionMetadata,
+    ) -> Result<(), Self::Error> {
+        self.write_transaction_marker("begin", transaction)
+    }
+
+    async fn commit_transaction(
+        &mut self,
+        transaction: &TransactionMetadata,
+    ) -> Result<(), Self::Error> {
+        self.write_transaction_marker("commit", transaction)?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DdlSink for StdoutSink {
+    async fn table_ddl(
+        &mut self,
+        _table_schemas: &HashMap<TableId, TableSchema>,
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl PreviewSink for StdoutSink {
+    async fn preview_rows(
+        &mut self,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        rows: Vec<PreviewRow>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            let table_schema = table_schemas
+                .get(&row.table_id)
+                .ok_or(StdoutSinkError::MissingTableSchema(row.table_id))?;
+            records.push(self.row_record(table_schema, row.operation.as_str(), None, &row.row)?);
+        }
+        Ok(records)
+    }
 }
@@ -0,0 +1,245 @@
+//! A source consuming the changes of a Kafka topic holding records of the
+//! stdout sink's json lines format, one per message, to fan a source's
+//! changes out to many sinks through Kafka. The topic's partition must hold
+//! the records in the order they were written, with the `begin` and
+//! `commit` markers of the transactions.
+//!
+//! The source only streams changes, the sinks' tables are copied from the
+//! Postgres source or replayed from dumps. Changes are resumed from the
+//! offset committed for the consumer group, or the start of the partition,
+//! and the transactions committed before the sink's last lsn are skipped.
+//! The offset of the last transaction the sink wrote is committed with each
+//! status update.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{future::BoxFuture, ready, stream, Stream};
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    error::{KafkaError, KafkaResult},
+    message::OwnedMessage,
+    ClientConfig, Message, Offset, TopicPartitionList,
+};
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
+
+use super::{
+    postgres::{CdcStreamError, StatusUpdateError, TableCopier, TableCopyStreamError, TableFilter},
+    records::{Record, RecordConverter, RecordError},
+    CdcEventStream, Source, SourceError, StandbyStatus,
+};
+
+#[derive(Debug, Error)]
+pub enum KafkaSourceError {
+    #[error("kafka error: {0}")]
+    Kafka(#[from] KafkaError),
+
+    #[error("invalid record at offset {0} of the topic: {1}")]
+    InvalidRecord(i64, RecordError),
+
+    #[error("the kafka source doesn't copy tables, they are copied from the postgres source or replayed from dumps")]
+    CopiesNotSupported,
+
+    #[error("the kafka source can't add tables to its cdc stream")]
+    AddingTablesNotSupported,
+}
+
+impl SourceError for KafkaSourceError {}
+
+/// A source streaming the changes recorded in a partition of a Kafka topic
+pub struct KafkaSource {
+    config: ClientConfig,
+    topic: String,
+    partition: i32,
+    table_schemas: HashMap<TableId, TableSchema>,
+}
+
+impl KafkaSource {
+    /// Consumes the partition of the topic as the consumer group, converting
+    /// the rows of the tables with the schemas. Changes of other tables are
+    /// skipped.
+    pub fn new(
+        brokers: &str,
+        group_id: &str,
+        topic: String,
+        partition: i32,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> KafkaSource {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest");
+        KafkaSource {
+            config,
+            topic,
+            partition,
+            table_schemas,
+        }
+    }
+
+    /// Sets a property of the consumer, like `security.protocol` or
+    /// `sasl.mechanisms`
+    pub fn with_property(mut self, key: &str, value: &str) -> KafkaSource {
+        self.config.set(key, value);
+        self
+    }
+}
+
+#[async_trait]
+impl Source for KafkaSource {
+    type Error = KafkaSourceError;
+    type TableCopyStream = stream::Empty<Result<TableRow, TableCopyStreamError>>;
+    type CdcStream = KafkaCdcStream;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        _table_name: &TableName,
+        _column_schemas: &[ColumnSchema],
+    ) -> Result<Self::TableCopyStream, Self::Error> {
+        Err(KafkaSourceError::CopiesNotSupported)
+    }
+
+    async fn count_table_rows(&self, _table_name: &TableName) -> Result<u64, Self::Error> {
+        Err(KafkaSourceError::CopiesNotSupported)
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn get_table_copier(
+        &self,
+        _table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error> {
+        Err(KafkaSourceError::AddingTablesNotSupported)
+    }
+
+    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<KafkaCdcStream, Self::Error> {
+        let consumer: Arc<StreamConsumer> = Arc::new(self.config.create()?);
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition_offset(&self.topic, self.partition, Offset::Stored)?;
+        consumer.assign(&assignment)?;
+
+        let messages = stream::unfold(consumer.clone(), |consumer| async move {
+            let message = consumer.recv().await.map(|message| message.detach());
+            Some((message, consumer))
+        });
+        Ok(KafkaCdcStream {
+            consumer,
+            messages: Box::pin(messages),
+            topic: self.topic.clone(),
+            partition: self.partition,
+            converter: RecordConverter::new(self.table_schemas.clone(), start_lsn),
+            commits: VecDeque::new(),
+        })
+    }
+}
+
+/// The recorded transactions committed after the start lsn
+pub struct KafkaCdcStream {
+    consumer: Arc<StreamConsumer>,
+    messages: Pin<Box<dyn Stream<Item = KafkaResult<OwnedMessage>> + Send>>,
+    topic: String,
+    partition: i32,
+    converter: RecordConverter,
+    /// Lsns and offsets of the commit markers read since the last status
+    /// update
+    commits: VecDeque<(PgLsn, i64)>,
+}
+
+impl KafkaCdcStream {
+    /// Returns the event of a message, if it isn't skipped
+    fn message_event(&mut self, message: &OwnedMessage) -> Result<Option<CdcEvent>, RecordError> {
+        // messages without a payload, like tombstones, hold no records
+        let Some(payload) = message.payload() else {
+            return Ok(None);
+        };
+        let record = Record::parse(payload)?;
+        if record.op == "commit" {
+            if let Some(lsn) = record.lsn()? {
+                self.commits.push_back((lsn, message.offset()));
+            }
+        }
+        self.converter.event(record)
+    }
+}
+
+impl CdcEventStream for KafkaCdcStream {
+    fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        self.converter.table_schemas()
+    }
+
+    fn add_table_schemas(self: Pin<&mut Self>, table_schemas: HashMap<TableId, TableSchema>) {
+        self.get_mut().converter.add_table_schemas(table_schemas);
+    }
+
+    /// Commits the offset following the last transaction committed at or
+    /// before the flushed lsn
+    fn send_status_update(
+        self: Pin<&mut Self>,
+        status: StandbyStatus,
+    ) -> BoxFuture<'_, Result<(), StatusUpdateError>> {
+        let this = self.get_mut();
+        let mut offset = None;
+        while let Some((commit_lsn, commit_offset)) = this.commits.front().copied() {
+            if commit_lsn > status.flushed {
+                break;
+            }
+            offset = Some(commit_offset);
+            this.commits.pop_front();
+        }
+        Box::pin(async move {
+            let Some(offset) = offset else {
+                return Ok(());
+            };
+            let mut offsets = TopicPartitionList::new();
+            offsets
+                .add_partition_offset(&this.topic, this.partition, Offset::Offset(offset + 1))
+                .map_err(KafkaSourceError::from)?;
+            this.consumer
+                .commit(&offsets, CommitMode::Async)
+                .map_err(KafkaSourceError::from)?;
+            Ok(())
+        })
+    }
+}
+
+impl Stream for KafkaCdcStream {
+    type Item = Result<CdcEvent, CdcStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let message = match ready!(this.messages.as_mut().poll_next(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(KafkaSourceError::from(e).into()))),
+                None => return Poll::Ready(None),
+            };
+            match this.message_event(&message) {
+                Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                Ok(None) => continue,
+                Err(e) => {
+                    let error = KafkaSourceError::InvalidRecord(message.offset(), e);
+                    return Poll::Ready(Some(Err(error.into())));
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,41 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, pin::Pin};
 
 use async_trait::async_trait;
+use bytes::{BufMut, BytesMut};
+use futures::{future::BoxFuture, Stream};
+use postgres_replication::protocol::{BeginBody, CommitBody, LogicalReplicationMessage};
 use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 
-use crate::table::{ColumnSchema, TableId, TableName, TableSchema};
+use crate::{
+    clients::postgres::{is_transient, TableSizeEstimate},
+    conversions::{
+        cdc_event::CdcEvent,
+        table_row::{RowPool, TableRow},
+    },
+    pipeline::verification::{ChecksumSpec, RangeChecksum},
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
 
 use self::postgres::{
-    CdcStream, CdcStreamError, PostgresSourceError, StatusUpdateError, TableCopyStream,
-    TableCopyStreamError,
+    CdcStreamError, PostgresSourceError, StatusUpdateError, TableCopier, TableCopyStreamError,
+    TableFilter,
 };
 
+#[cfg(feature = "kafka")]
+pub mod kafka;
 pub mod postgres;
-
-pub trait SourceError: std::error::Error + Send + Sync + 'static {}
+pub mod records;
+pub mod replay;
+pub mod synthetic;
+
+pub trait SourceError: std::error::Error + Send + Sync + 'static {
+    /// Whether retrying the failed operation may succeed, e.g. after the
+    /// connection to the source was lost. Errors are fatal by default
+    fn is_retryable(&self) -> bool {
+        false
+    }
+}
 
 #[derive(Debug, Error)]
 #[error("unreachable")]
@@ -35,11 +57,76 @@ pub enum CommonSourceError {
     StatusUpdate(#[from] StatusUpdateError),
 }
 
-impl SourceError for CommonSourceError {}
+impl SourceError for CommonSourceError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            CommonSourceError::Postgres(e) => e.is_retryable(),
+            CommonSourceError::TableCopyStream(TableCopyStreamError::TokioPostgresError(e))
+            | CommonSourceError::CdcStream(CdcStreamError::TokioPostgresError(e))
+            | CommonSourceError::StatusUpdate(StatusUpdateError::TokioPostgres(e)) => {
+                is_transient(e)
+            }
+            CommonSourceError::TableCopyStream(TableCopyStreamError::LargeObject(e))
+            | CommonSourceError::CdcStream(CdcStreamError::LargeObject(e)) => e.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl CommonSourceError {
+    /// Whether the error is a value of the source's rows which failed to
+    /// convert
+    pub fn is_conversion(&self) -> bool {
+        matches!(
+            self,
+            CommonSourceError::TableCopyStream(TableCopyStreamError::ConversionError(_))
+                | CommonSourceError::CdcStream(CdcStreamError::CdcEventConversion(_))
+        )
+    }
+}
+
+/// A stream of the changes of a source's tables
+pub trait CdcEventStream: Stream<Item = Result<CdcEvent, CdcStreamError>> {
+    /// Schemas of the streamed tables
+    fn table_schemas(&self) -> &HashMap<TableId, TableSchema>;
+
+    /// Streams the changes of more tables from the next event on
+    fn add_table_schemas(self: Pin<&mut Self>, table_schemas: HashMap<TableId, TableSchema>);
+
+    /// Tells the source the positions of the changes which were written
+    fn send_status_update(
+        self: Pin<&mut Self>,
+        status: StandbyStatus,
+    ) -> BoxFuture<'_, Result<(), StatusUpdateError>>;
+
+    /// Whether a status update is due although the source didn't ask for
+    /// one, because its interval elapsed. Never by default
+    fn status_update_due(&self) -> bool {
+        false
+    }
+}
+
+/// Positions of the changes written to a sink, reported to the source in
+/// its standby status updates. The position of the changes received from
+/// the source, which may be ahead of them, is tracked by the source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StandbyStatus {
+    /// End of the changes the sink wrote durably. The source may remove
+    /// the WAL before it
+    pub flushed: PgLsn,
+    /// End of the changes the sink applied to its tables
+    pub applied: PgLsn,
+}
 
 #[async_trait]
 pub trait Source {
     type Error: SourceError;
+    type TableCopyStream: Stream<Item = Result<TableRow, TableCopyStreamError>> + Send;
+    type CdcStream: CdcEventStream + Send;
+
+    /// Sets the pool of the sink whose vectors the rows the source decodes
+    /// reuse. Sources which don't decode rows ignore it.
+    fn set_row_pool(&mut self, _row_pool: RowPool) {}
 
     fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema>;
 
@@ -47,9 +134,77 @@ pub trait Source {
         &self,
         table_name: &TableName,
         column_schemas: &[ColumnSchema],
-    ) -> Result<TableCopyStream, Self::Error>;
+    ) -> Result<Self::TableCopyStream, Self::Error>;
+
+    async fn count_table_rows(&self, table_name: &TableName) -> Result<u64, Self::Error>;
 
     async fn commit_transaction(&self) -> Result<(), Self::Error>;
 
-    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error>;
+    /// Opens a connection to copy the publication's tables selected by the
+    /// filter, all of them if there is none, while changes are streamed.
+    /// Sources which can't add tables to their cdc stream return an error.
+    async fn get_table_copier(
+        &self,
+        table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error>;
+
+    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<Self::CdcStream, Self::Error>;
+
+    /// Returns the name of a table which isn't replicated, e.g. because it
+    /// was removed from the publication, none if the table doesn't exist or
+    /// the source can't tell
+    async fn get_table_name(&self, _table_id: TableId) -> Result<Option<TableName>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the estimated size of a table, to report the progress of its
+    /// copy, none if the source can't tell
+    async fn get_table_size_estimate(
+        &self,
+        _table_id: TableId,
+    ) -> Result<Option<TableSizeEstimate>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// A source whose tables can be hashed in ranges of their keys. Used by the
+/// [verification](crate::pipeline::verification) subsystem.
+#[async_trait]
+pub trait ChecksumSource: Source {
+    /// Returns the row count and checksum of each range of the table's keys
+    /// which holds rows
+    async fn checksum_ranges(
+        &self,
+        table_name: &TableName,
+        spec: &ChecksumSpec,
+    ) -> Result<Vec<RangeChecksum>, Self::Error>;
+}
+
+// The bodies of begin and commit messages can only be parsed, so sources
+// other than Postgres build them from the messages the server would send.
+// Timestamps are in microseconds since the Postgres epoch, 2000-01-01.
+
+pub(crate) fn begin_body(final_lsn: u64, timestamp: i64, xid: u32) -> BeginBody {
+    let mut buf = BytesMut::with_capacity(21);
+    buf.put_u8(b'B');
+    buf.put_u64(final_lsn);
+    buf.put_i64(timestamp);
+    buf.put_u32(xid);
+    match LogicalReplicationMessage::parse(&buf.freeze()) {
+        Ok(LogicalReplicationMessage::Begin(begin_body)) => begin_body,
+        _ => panic!("failed to parse a generated begin message"),
+    }
+}
+
+pub(crate) fn commit_body(commit_lsn: u64, timestamp: i64) -> CommitBody {
+    let mut buf = BytesMut::with_capacity(26);
+    buf.put_u8(b'C');
+    buf.put_i8(0);
+    buf.put_u64(commit_lsn);
+    buf.put_u64(commit_lsn);
+    buf.put_i64(timestamp);
+    match LogicalReplicationMessage::parse(&buf.freeze()) {
+        Ok(LogicalReplicationMessage::Commit(commit_body)) => commit_body,
+        _ => panic!("failed to parse a generated commit message"),
+    }
 }
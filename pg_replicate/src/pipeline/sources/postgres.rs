@@ -1,32 +1,111 @@
 use std::{
     collections::HashMap,
+    future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
-    time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, SystemTimeError, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
-use futures::{ready, Stream};
+use chrono::{DateTime, LocalResult, Offset, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::{future::BoxFuture, ready, Stream};
 use pin_project_lite::pin_project;
-use postgres_replication::LogicalReplicationStream;
+use postgres_replication::{
+    protocol::{RelationBody, ReplicationMessage},
+    LogicalReplicationStream,
+};
 use thiserror::Error;
-use tokio_postgres::{types::PgLsn, CopyOutStream};
-use tracing::info;
+use tokio_postgres::{
+    types::{PgLsn, Type},
+    CopyOutStream,
+};
+use tracing::{info, warn};
 
 use crate::{
-    clients::postgres::{ReplicationClient, ReplicationClientError},
+    clients::postgres::{
+        ColumnHandling, ColumnOptions, ConnectionOptions, ConversionErrorPolicy, LargeObjectReader,
+        ReplicationClient, ReplicationClientError, TableSizeEstimate,
+    },
     conversions::{
-        cdc_event::{CdcEvent, CdcEventConversionError, CdcEventConverter},
-        table_row::{TableRow, TableRowConversionError, TableRowConverter},
+        cdc_event::{replication_timestamp, CdcEvent, CdcEventConversionError, CdcEventConverter},
+        table_row::{
+            copy_row_dead_letter, RowAllocator, RowPool, TableRow, TableRowConversionError,
+            TableRowConverter,
+        },
+        text::{FromTextError, TextFormatConverter},
+        ArrayCell, Cell, ConversionPolicy,
     },
-    table::{ColumnSchema, TableId, TableName, TableSchema},
+    pipeline::verification::{ChecksumSpec, RangeChecksum},
+    table::{ColumnSchema, TableId, TableName, TableSchema, TypeOverride},
 };
 
-use super::{Source, SourceError};
+use super::{
+    replay::ReplaySourceError, CdcEventStream, ChecksumSource, Source, SourceError, StandbyStatus,
+};
 
 pub enum TableNamesFrom {
     Vec(Vec<TableName>),
     Publication(String),
+    /// Only the tables of the publication selected by the filter
+    FilteredPublication(String, TableFilter),
+    /// The tables of the database selected by the filter. Each include
+    /// pattern must match at least one table
+    Filter(TableFilter),
+}
+
+/// Selects tables by their name in `schema.name` form. Patterns may contain
+/// `*` wildcards matching any number of characters. A table is selected if
+/// it matches any include pattern, or there are none, and no exclude pattern.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TableFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl TableFilter {
+    pub fn matches(&self, table_name: &TableName) -> bool {
+        let table_name = table_name.to_string();
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| glob_matches(pattern, &table_name));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| glob_matches(pattern, &table_name))
+    }
+}
+
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it matched up to, to
+    // backtrack to when the rest of the pattern doesn't match
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 #[derive(Debug, Error)]
@@ -41,16 +120,46 @@ pub enum PostgresSourceError {
     MissingSlotName,
 }
 
-impl SourceError for PostgresSourceError {}
+impl SourceError for PostgresSourceError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            PostgresSourceError::ReplicationClient(e) => e.is_retryable(),
+            PostgresSourceError::MissingPublication | PostgresSourceError::MissingSlotName => false,
+        }
+    }
+}
+
+/// Parameters to open further connections to the source with
+struct ConnectionConfig {
+    host: String,
+    port: u16,
+    database: String,
+    username: String,
+    password: Option<String>,
+    options: ConnectionOptions,
+}
 
 pub struct PostgresSource {
     replication_client: ReplicationClient,
+    connection_config: ConnectionConfig,
     table_schemas: HashMap<TableId, TableSchema>,
     slot_name: Option<String>,
     publication: Option<String>,
+    /// Whether only some of the publication's tables are replicated
+    filtered: bool,
+    column_options: ColumnOptions,
+    /// Time zone of the local timestamps, none if they are UTC times
+    time_zone: Option<Tz>,
+    /// Reader of the large objects of the rows, none if no column holds
+    /// large objects
+    large_objects: Option<Arc<LargeObjectReader>>,
+    /// Pool of the sink the decoded rows are taken from, if it hands rows
+    /// back
+    row_pool: Option<RowPool>,
 }
 
 impl PostgresSource {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         host: &str,
         port: u16,
@@ -59,24 +168,290 @@ impl PostgresSource {
         password: Option<String>,
         slot_name: Option<String>,
         table_names_from: TableNamesFrom,
+        column_options: ColumnOptions,
+        connection_options: ConnectionOptions,
     ) -> Result<PostgresSource, PostgresSourceError> {
+        let time_zone = column_options.timestamps.time_zone()?;
+        let mut connection_config = ConnectionConfig {
+            host: host.to_string(),
+            port,
+            database: database.to_string(),
+            username: username.to_string(),
+            password: password.clone(),
+            options: connection_options,
+        };
         let replication_client =
-            ReplicationClient::connect_no_tls(host, port, database, username, password).await?;
+            Self::connect_or_fail_back(&mut connection_config, slot_name.as_deref()).await?;
+        // connected after the replication client, to the primary if it
+        // failed back
+        let large_objects = if column_options.large_objects.columns.is_empty() {
+            None
+        } else {
+            let reader = LargeObjectReader::connect(
+                &connection_config.host,
+                connection_config.port,
+                database,
+                username,
+                password.clone(),
+                column_options.large_objects.store.clone(),
+                &connection_config.options,
+            )
+            .await?;
+            Some(Arc::new(reader))
+        };
+        let in_recovery = replication_client.is_in_recovery().await?;
+        if in_recovery {
+            Self::check_standby(&replication_client).await?;
+        } else if connection_config.options.standby.is_some() {
+            info!("the source isn't in recovery, it was either failed back to or promoted to a primary");
+        }
         replication_client.begin_readonly_transaction().await?;
+        if let Some(timeout_ms) = connection_config.options.copy_statement_timeout_ms {
+            replication_client
+                .set_local_statement_timeout(timeout_ms)
+                .await?;
+        }
         if let Some(ref slot_name) = slot_name {
-            replication_client.get_or_create_slot(slot_name).await?;
+            if in_recovery {
+                Self::get_or_create_standby_slot(
+                    &replication_client,
+                    &connection_config,
+                    slot_name,
+                )
+                .await?;
+            } else {
+                replication_client.get_or_create_slot(slot_name).await?;
+            }
         }
+        let filtered = matches!(table_names_from, TableNamesFrom::FilteredPublication(..));
         let (table_names, publication) =
             Self::get_table_names_and_publication(&replication_client, table_names_from).await?;
-        let table_schemas = replication_client.get_table_schemas(&table_names).await?;
+        if let Some(ref publication) = publication {
+            if column_options.generated == ColumnHandling::Replicate
+                && !replication_client
+                    .publishes_generated_columns(publication)
+                    .await?
+            {
+                return Err(ReplicationClientError::GeneratedColumnsNotPublished(
+                    publication.clone(),
+                )
+                .into());
+            }
+        }
+        let table_schemas = replication_client
+            .get_table_schemas(&table_names, &column_options)
+            .await?;
         Ok(PostgresSource {
             replication_client,
+            connection_config,
             table_schemas,
             publication,
             slot_name,
+            filtered,
+            column_options,
+            time_zone,
+            large_objects,
+            row_pool: None,
         })
     }
 
+    /// Connects to the source, or to the primary if the source is a standby
+    /// which can't be connected to and failback is enabled. The connection
+    /// config is switched to the primary when it fails back, so that the
+    /// further connections go to it too.
+    async fn connect_or_fail_back(
+        connection_config: &mut ConnectionConfig,
+        slot_name: Option<&str>,
+    ) -> Result<ReplicationClient, PostgresSourceError> {
+        let err = match ReplicationClient::connect_with_options(
+            &connection_config.host,
+            connection_config.port,
+            &connection_config.database,
+            &connection_config.username,
+            connection_config.password.clone(),
+            &connection_config.options,
+        )
+        .await
+        {
+            Ok(replication_client) => return Ok(replication_client),
+            Err(e) => e,
+        };
+        let Some((primary_host, primary_port)) = connection_config
+            .options
+            .standby
+            .as_ref()
+            .filter(|standby| standby.failback)
+            .and_then(|standby| standby.primary(connection_config.port))
+        else {
+            return Err(err.into());
+        };
+        let (primary_host, primary_port) = (primary_host.to_string(), primary_port);
+        warn!("failed to connect to the standby, failing back to the primary at {primary_host}:{primary_port}: {err}");
+
+        let replication_client = ReplicationClient::connect_with_options(
+            &primary_host,
+            primary_port,
+            &connection_config.database,
+            &connection_config.username,
+            connection_config.password.clone(),
+            &connection_config.options,
+        )
+        .await?;
+        // a slot created on the primary now would miss the changes the
+        // standby's slot didn't stream yet
+        if let Some(slot_name) = slot_name {
+            if !replication_client.slot_exists(slot_name).await? {
+                return Err(
+                    ReplicationClientError::MissingFailbackSlot(slot_name.to_string()).into(),
+                );
+            }
+        }
+        connection_config.host = primary_host;
+        connection_config.port = primary_port;
+        Ok(replication_client)
+    }
+
+    /// Checks that the standby can decode the changes
+    async fn check_standby(
+        replication_client: &ReplicationClient,
+    ) -> Result<(), PostgresSourceError> {
+        if replication_client.get_server_version_num().await? < 160000 {
+            let version = replication_client
+                .get_setting("server_version")
+                .await?
+                .unwrap_or_default();
+            return Err(ReplicationClientError::StandbyDecodingUnsupported(version).into());
+        }
+        if replication_client
+            .get_setting("hot_standby_feedback")
+            .await?
+            .as_deref()
+            != Some("on")
+        {
+            warn!("hot_standby_feedback is off on the standby, the primary may remove rows the slot still needs and invalidate it");
+        }
+        Ok(())
+    }
+
+    /// Gets or creates the slot on the standby. The running transactions
+    /// are logged on the primary while the slot is created, if the primary
+    /// is configured, as the standby waits for them. The failback slot on
+    /// the primary is advanced to the standby's slot's position, so that it
+    /// doesn't hold back the primary's WAL more than needed.
+    async fn get_or_create_standby_slot(
+        replication_client: &ReplicationClient,
+        connection_config: &ConnectionConfig,
+        slot_name: &str,
+    ) -> Result<(), PostgresSourceError> {
+        let slot_exists = replication_client.slot_exists(slot_name).await?;
+        if slot_exists
+            && replication_client
+                .slot_conflicts_with_recovery(slot_name)
+                .await?
+        {
+            return Err(
+                ReplicationClientError::SlotConflictsWithRecovery(slot_name.to_string()).into(),
+            );
+        }
+        let standby = connection_config
+            .options
+            .standby
+            .clone()
+            .unwrap_or_default();
+        let Some((primary_host, primary_port)) = standby.primary(connection_config.port) else {
+            if !slot_exists {
+                info!("creating slot {slot_name} on the standby, which waits until the primary logs its running transactions");
+            }
+            replication_client.get_or_create_slot(slot_name).await?;
+            return Ok(());
+        };
+        let primary_client = ReplicationClient::connect_with_options(
+            primary_host,
+            primary_port,
+            &connection_config.database,
+            &connection_config.username,
+            connection_config.password.clone(),
+            &connection_config.options,
+        )
+        .await?;
+
+        if slot_exists {
+            replication_client.get_or_create_slot(slot_name).await?;
+        } else {
+            let log_standby_snapshots = async {
+                loop {
+                    if let Err(e) = primary_client.log_standby_snapshot().await {
+                        return e;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            };
+            tokio::select! {
+                slot = replication_client.get_or_create_slot(slot_name) => {
+                    slot?;
+                }
+                e = log_standby_snapshots => return Err(e.into()),
+            }
+        }
+
+        if standby.failback {
+            if !primary_client.slot_exists(slot_name).await? {
+                return Err(
+                    ReplicationClientError::MissingFailbackSlot(slot_name.to_string()).into(),
+                );
+            }
+            if let Some(status) = replication_client.get_slot_status(slot_name).await? {
+                primary_client
+                    .advance_slot(slot_name, status.confirmed_flush_lsn)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the values of the columns of up to `limit` rows of the table,
+    /// as text
+    pub async fn sample_column_values(
+        &self,
+        table_name: &TableName,
+        columns: &[String],
+        limit: u32,
+    ) -> Result<Vec<Vec<Option<String>>>, PostgresSourceError> {
+        Ok(self
+            .replication_client
+            .sample_column_values(table_name, columns, limit)
+            .await?)
+    }
+
+    /// Returns a stream of up to `limit` rows of the table, decoded like the
+    /// rows of its copy
+    pub async fn get_table_sample_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+        limit: u32,
+    ) -> Result<TableCopyStream, PostgresSourceError> {
+        let stream = self
+            .replication_client
+            .get_table_sample_stream(
+                table_name,
+                &source_column_schemas(&self.table_schemas, table_name, column_schemas),
+                limit,
+            )
+            .await?;
+
+        Ok(TableCopyStream::new(
+            stream,
+            &self.table_schemas,
+            table_name,
+            column_schemas,
+            self.time_zone,
+            self.large_objects.clone(),
+            self.column_options.conversion_policy(),
+            self.row_pool.clone(),
+        ))
+    }
+
     fn publication(&self) -> Option<&String> {
         self.publication.as_ref()
     }
@@ -104,6 +479,38 @@ impl PostgresSource {
                     Some(publication),
                 )
             }
+            TableNamesFrom::FilteredPublication(publication, table_filter) => {
+                if !replication_client.publication_exists(&publication).await? {
+                    return Err(ReplicationClientError::MissingPublication(
+                        publication.to_string(),
+                    ));
+                }
+                let table_names = replication_client
+                    .get_publication_table_names(&publication)
+                    .await?
+                    .into_iter()
+                    .filter(|table_name| table_filter.matches(table_name))
+                    .collect();
+                (table_names, Some(publication))
+            }
+            TableNamesFrom::Filter(table_filter) => {
+                let table_names: Vec<TableName> = replication_client
+                    .get_table_names()
+                    .await?
+                    .into_iter()
+                    .filter(|table_name| table_filter.matches(table_name))
+                    .collect();
+                let table_name_strings: Vec<String> =
+                    table_names.iter().map(ToString::to_string).collect();
+                if let Some(pattern) = table_filter.include.iter().find(|pattern| {
+                    !table_name_strings
+                        .iter()
+                        .any(|table_name| glob_matches(pattern, table_name))
+                }) {
+                    return Err(ReplicationClientError::NoMatchingTable(pattern.clone()));
+                }
+                (table_names, None)
+            }
         })
     }
 }
@@ -111,6 +518,12 @@ impl PostgresSource {
 #[async_trait]
 impl Source for PostgresSource {
     type Error = PostgresSourceError;
+    type TableCopyStream = TableCopyStream;
+    type CdcStream = CdcStream;
+
+    fn set_row_pool(&mut self, row_pool: RowPool) {
+        self.row_pool = Some(row_pool);
+    }
 
     fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
         &self.table_schemas
@@ -125,14 +538,32 @@ impl Source for PostgresSource {
 
         let stream = self
             .replication_client
-            .get_table_copy_stream(table_name)
+            .get_table_copy_stream(
+                table_name,
+                &source_column_schemas(&self.table_schemas, table_name, column_schemas),
+            )
             .await
             .map_err(PostgresSourceError::ReplicationClient)?;
 
-        Ok(TableCopyStream {
+        Ok(TableCopyStream::new(
             stream,
-            column_schemas: column_schemas.to_vec(),
-        })
+            &self.table_schemas,
+            table_name,
+            column_schemas,
+            self.time_zone,
+            self.large_objects.clone(),
+            self.column_options.conversion_policy(),
+            self.row_pool.clone(),
+        ))
+    }
+
+    async fn count_table_rows(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        let row_count = self
+            .replication_client
+            .count_table_rows(table_name)
+            .await
+            .map_err(PostgresSourceError::ReplicationClient)?;
+        Ok(row_count)
     }
 
     async fn commit_transaction(&self) -> Result<(), Self::Error> {
@@ -143,6 +574,69 @@ impl Source for PostgresSource {
         Ok(())
     }
 
+    async fn get_table_copier(
+        &self,
+        table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error> {
+        let publication = self
+            .publication()
+            .ok_or(PostgresSourceError::MissingPublication)?;
+        let ConnectionConfig {
+            host,
+            port,
+            database,
+            username,
+            password,
+            options,
+        } = &self.connection_config;
+        let replication_client = ReplicationClient::connect_with_options(
+            host,
+            *port,
+            database,
+            username,
+            password.clone(),
+            options,
+        )
+        .await?;
+        replication_client.begin_readonly_transaction().await?;
+        if let Some(timeout_ms) = options.copy_statement_timeout_ms {
+            replication_client
+                .set_local_statement_timeout(timeout_ms)
+                .await?;
+        }
+        let table_names: Vec<TableName> = replication_client
+            .get_publication_table_names(publication)
+            .await?
+            .into_iter()
+            .filter(|table_name| table_filter.map_or(true, |filter| filter.matches(table_name)))
+            .collect();
+        let table_schemas = replication_client
+            .get_table_schemas(&table_names, &self.column_options)
+            .await?;
+        Ok(TableCopier {
+            replication_client,
+            table_schemas,
+            time_zone: self.time_zone,
+            large_objects: self.large_objects.clone(),
+            conversion_policy: self.column_options.conversion_policy(),
+            row_pool: self.row_pool.clone(),
+        })
+    }
+
+    async fn get_table_name(&self, table_id: TableId) -> Result<Option<TableName>, Self::Error> {
+        Ok(self.replication_client.get_table_name(table_id).await?)
+    }
+
+    async fn get_table_size_estimate(
+        &self,
+        table_id: TableId,
+    ) -> Result<Option<TableSizeEstimate>, Self::Error> {
+        Ok(self
+            .replication_client
+            .get_table_size_estimate(table_id)
+            .await?)
+    }
+
     async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error> {
         info!("starting cdc stream at lsn {start_lsn}");
         let publication = self
@@ -163,11 +657,90 @@ impl Source for PostgresSource {
         Ok(CdcStream {
             stream,
             table_schemas: self.table_schemas.clone(),
+            tuple_indexes: HashMap::new(),
             postgres_epoch,
+            skip_unknown_tables: self.filtered,
+            time_zone: self.time_zone,
+            large_objects: self.large_objects.clone(),
+            resolving: None,
+            commit_timestamp: None,
+            conversion_policy: self.column_options.conversion_policy(),
+            rows: RowAllocator::new(self.row_pool.clone()),
+            positions: StandbyPositions::new(
+                start_lsn,
+                self.connection_config.options.status_interval(),
+                Instant::now(),
+            ),
         })
     }
 }
 
+#[async_trait]
+impl ChecksumSource for PostgresSource {
+    async fn checksum_ranges(
+        &self,
+        table_name: &TableName,
+        spec: &ChecksumSpec,
+    ) -> Result<Vec<RangeChecksum>, Self::Error> {
+        let ranges = self
+            .replication_client
+            .checksum_ranges(table_name, spec)
+            .await
+            .map_err(PostgresSourceError::ReplicationClient)?;
+        Ok(ranges)
+    }
+}
+
+/// A connection with its own snapshot of the publication's tables, to copy
+/// tables while the changes of others are streamed over the source's
+/// connection
+pub struct TableCopier {
+    replication_client: ReplicationClient,
+    table_schemas: HashMap<TableId, TableSchema>,
+    time_zone: Option<Tz>,
+    large_objects: Option<Arc<LargeObjectReader>>,
+    conversion_policy: ConversionPolicy,
+    row_pool: Option<RowPool>,
+}
+
+impl TableCopier {
+    pub fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    pub async fn get_table_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<TableCopyStream, PostgresSourceError> {
+        info!("starting table copy stream for added table {table_name}");
+
+        let stream = self
+            .replication_client
+            .get_table_copy_stream(
+                table_name,
+                &source_column_schemas(&self.table_schemas, table_name, column_schemas),
+            )
+            .await?;
+
+        Ok(TableCopyStream::new(
+            stream,
+            &self.table_schemas,
+            table_name,
+            column_schemas,
+            self.time_zone,
+            self.large_objects.clone(),
+            self.conversion_policy.clone(),
+            self.row_pool.clone(),
+        ))
+    }
+
+    pub async fn commit_transaction(&self) -> Result<(), PostgresSourceError> {
+        self.replication_client.commit_txn().await?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum TableCopyStreamError {
     #[error("tokio_postgres error: {0}")]
@@ -175,6 +748,12 @@ pub enum TableCopyStreamError {
 
     #[error("conversion error: {0}")]
     ConversionError(TableRowConversionError),
+
+    #[error("large object error: {0}")]
+    LargeObject(#[from] ReplicationClientError),
+
+    #[error("replay error: {0}")]
+    Replay(#[from] ReplaySourceError),
 }
 
 pin_project! {
@@ -182,18 +761,184 @@ pin_project! {
     pub struct TableCopyStream {
         #[pin]
         stream: CopyOutStream,
+        // Schemas the rows are converted with, in which the columns of large
+        // objects hold oids and columns of overridden types their parsed type
         column_schemas: Vec<ColumnSchema>,
+        // Positions of the columns of overridden types
+        type_overrides: Vec<(usize, TypeOverride)>,
+        buffer: String,
+        // Positions of the columns of local timestamps
+        local_timestamps: Vec<usize>,
+        time_zone: Option<Tz>,
+        // Positions of the columns of large objects
+        large_object_columns: Vec<usize>,
+        large_objects: Option<Arc<LargeObjectReader>>,
+        // Reading of the large objects of the last row
+        resolving: Option<BoxFuture<'static, Result<TableRow, ReplicationClientError>>>,
+        // Whether the rows get a null column of commit timestamps
+        commit_timestamp_column: bool,
+        table_name: TableName,
+        conversion_policy: ConversionPolicy,
+        rows: RowAllocator,
+    }
+}
+
+impl TableCopyStream {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        stream: CopyOutStream,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+        time_zone: Option<Tz>,
+        large_objects: Option<Arc<LargeObjectReader>>,
+        conversion_policy: ConversionPolicy,
+        row_pool: Option<RowPool>,
+    ) -> TableCopyStream {
+        let table_schema = find_table_schema(table_schemas, table_name);
+        let commit_timestamp_column =
+            table_schema.is_some_and(|table_schema| table_schema.commit_timestamp_column.is_some());
+        let column_schemas = &source_column_schemas(table_schemas, table_name, column_schemas);
+        let local_timestamps = table_schema
+            .map(|table_schema| {
+                column_positions(&table_schema.local_timestamp_columns, column_schemas)
+            })
+            .unwrap_or_default();
+        let large_object_columns = table_schema
+            .map(|table_schema| {
+                column_positions(&table_schema.large_object_columns, column_schemas)
+            })
+            .unwrap_or_default();
+        let type_overrides: Vec<(usize, TypeOverride)> = table_schema
+            .map(|table_schema| {
+                column_schemas
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, column_schema)| {
+                        table_schema
+                            .type_overrides
+                            .get(&column_schema.name)
+                            .map(|type_override| (i, *type_override))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut column_schemas = column_schemas.to_vec();
+        for &i in &large_object_columns {
+            column_schemas[i].typ = Type::OID;
+        }
+        for &(i, type_override) in &type_overrides {
+            column_schemas[i].typ = type_override.parsed_typ();
+        }
+        TableCopyStream {
+            stream,
+            column_schemas,
+            type_overrides,
+            buffer: String::new(),
+            local_timestamps,
+            time_zone,
+            large_object_columns,
+            large_objects,
+            resolving: None,
+            commit_timestamp_column,
+            table_name: table_name.clone(),
+            conversion_policy,
+            rows: RowAllocator::new(row_pool),
+        }
     }
 }
 
+fn find_table_schema<'a>(
+    table_schemas: &'a HashMap<TableId, TableSchema>,
+    table_name: &TableName,
+) -> Option<&'a TableSchema> {
+    table_schemas.values().find(|table_schema| {
+        table_schema.table_name.schema == table_name.schema
+            && table_schema.table_name.name == table_name.name
+    })
+}
+
+/// Returns the columns of a table which are read from the source, without
+/// its column of commit timestamps
+fn source_column_schemas(
+    table_schemas: &HashMap<TableId, TableSchema>,
+    table_name: &TableName,
+    column_schemas: &[ColumnSchema],
+) -> Vec<ColumnSchema> {
+    let commit_timestamp_column = find_table_schema(table_schemas, table_name)
+        .and_then(|table_schema| table_schema.commit_timestamp_column.as_ref());
+    column_schemas
+        .iter()
+        .filter(|column_schema| Some(&column_schema.name) != commit_timestamp_column)
+        .cloned()
+        .collect()
+}
+
 impl Stream for TableCopyStream {
     type Item = Result<TableRow, TableCopyStreamError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
+        if let Some(resolving) = this.resolving {
+            let row = ready!(resolving.as_mut().poll(cx));
+            *this.resolving = None;
+            return Poll::Ready(Some(row.map_err(Into::into)));
+        }
         match ready!(this.stream.poll_next(cx)) {
-            Some(Ok(row)) => match TableRowConverter::try_from(&row, this.column_schemas) {
-                Ok(row) => Poll::Ready(Some(Ok(row))),
+            Some(Ok(bytes)) => match TableRowConverter::try_from_with_policy(
+                &bytes,
+                this.table_name,
+                this.column_schemas,
+                this.buffer,
+                this.rows,
+                this.conversion_policy,
+            ) {
+                Ok(mut row) => {
+                    if *this.commit_timestamp_column {
+                        row.values.push(Cell::Null);
+                    }
+                    if let Err((i, e)) = override_types(
+                        &mut row,
+                        this.type_overrides,
+                        this.column_schemas,
+                        this.conversion_policy.errors,
+                    ) {
+                        let e = match this.conversion_policy.errors {
+                            ConversionErrorPolicy::DeadLetter => {
+                                TableRowConversionError::DeadLetter(Box::new(copy_row_dead_letter(
+                                    &bytes,
+                                    this.table_name,
+                                    this.column_schemas,
+                                    &this.column_schemas[i].name,
+                                    e.to_string(),
+                                )))
+                            }
+                            _ => e.into(),
+                        };
+                        let e = TableCopyStreamError::ConversionError(e);
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    if let Some(time_zone) = this.time_zone {
+                        localize_timestamps(&mut row, this.local_timestamps, *time_zone);
+                    }
+                    match this.large_objects {
+                        Some(reader) if !this.large_object_columns.is_empty() => {
+                            let mut resolving = resolve_large_objects(
+                                reader.clone(),
+                                row,
+                                this.large_object_columns.clone(),
+                            );
+                            match resolving.as_mut().poll(cx) {
+                                Poll::Ready(row) => Poll::Ready(Some(row.map_err(Into::into))),
+                                Poll::Pending => {
+                                    *this.resolving = Some(resolving);
+                                    Poll::Pending
+                                }
+                            }
+                        }
+                        _ => Poll::Ready(Some(Ok(row))),
+                    }
+                }
                 Err(e) => {
                     let e = TableCopyStreamError::ConversionError(e);
                     Poll::Ready(Some(Err(e)))
@@ -212,6 +957,16 @@ pub enum CdcStreamError {
 
     #[error("cdc event conversion error: {0}")]
     CdcEventConversion(#[from] CdcEventConversionError),
+
+    #[error("large object error: {0}")]
+    LargeObject(#[from] ReplicationClientError),
+
+    #[error("replay error: {0}")]
+    Replay(#[from] ReplaySourceError),
+
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(#[from] super::kafka::KafkaSourceError),
 }
 
 pin_project! {
@@ -220,7 +975,77 @@ pin_project! {
         #[pin]
         stream: LogicalReplicationStream,
         table_schemas: HashMap<TableId, TableSchema>,
+        // Positions of the replicated columns in the rows of the changes of
+        // tables, as sent in their last relation message
+        tuple_indexes: HashMap<TableId, Vec<usize>>,
         postgres_epoch: SystemTime,
+        // Publication tables excluded by a filter have no schema, their
+        // changes are skipped instead of failing the stream
+        skip_unknown_tables: bool,
+        time_zone: Option<Tz>,
+        large_objects: Option<Arc<LargeObjectReader>>,
+        // Reading of the large objects of the last change
+        resolving: Option<BoxFuture<'static, Result<CdcEvent, ReplicationClientError>>>,
+        // Commit timestamp of the transaction whose changes are streamed
+        commit_timestamp: Option<DateTime<Utc>>,
+        conversion_policy: ConversionPolicy,
+        rows: RowAllocator,
+        positions: StandbyPositions,
+    }
+}
+
+/// Positions reported in the standby status updates of a cdc stream, and
+/// when the next update is due
+#[derive(Debug)]
+struct StandbyPositions {
+    /// End of the WAL received from the server, reported as written
+    received_lsn: PgLsn,
+    /// Highest positions reported as flushed and applied, which never move
+    /// backwards
+    flushed_lsn: PgLsn,
+    applied_lsn: PgLsn,
+    /// Whether the server asked for a reply in a keepalive since the last
+    /// update
+    reply_requested: bool,
+    interval: Option<Duration>,
+    last_update: Instant,
+}
+
+impl StandbyPositions {
+    fn new(start_lsn: PgLsn, interval: Option<Duration>, now: Instant) -> StandbyPositions {
+        StandbyPositions {
+            received_lsn: start_lsn,
+            flushed_lsn: PgLsn::from(0),
+            applied_lsn: PgLsn::from(0),
+            reply_requested: false,
+            interval,
+            last_update: now,
+        }
+    }
+
+    /// Records the end of the WAL of a message from the server
+    fn receive(&mut self, wal_end: PgLsn, reply_requested: bool) {
+        self.received_lsn = self.received_lsn.max(wal_end);
+        self.reply_requested |= reply_requested;
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.reply_requested
+            || self
+                .interval
+                .is_some_and(|interval| now.duration_since(self.last_update) >= interval)
+    }
+
+    /// Returns the written, flushed and applied positions of an update with
+    /// the status of the sink. Nothing is reported as applied past the
+    /// position flushed by the sink, nor as written before it.
+    fn update(&mut self, status: StandbyStatus, now: Instant) -> (PgLsn, PgLsn, PgLsn) {
+        self.flushed_lsn = self.flushed_lsn.max(status.flushed);
+        self.applied_lsn = self.applied_lsn.max(status.applied.min(status.flushed));
+        self.received_lsn = self.received_lsn.max(self.flushed_lsn);
+        self.reply_requested = false;
+        self.last_update = now;
+        (self.received_lsn, self.flushed_lsn, self.applied_lsn)
     }
 }
 
@@ -231,35 +1056,502 @@ pub enum StatusUpdateError {
 
     #[error("tokio_postgres error: {0}")]
     TokioPostgres(#[from] tokio_postgres::Error),
+
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(#[from] super::kafka::KafkaSourceError),
 }
 
-impl CdcStream {
-    pub async fn send_status_update(
+impl CdcEventStream for CdcStream {
+    /// Schemas of the streamed tables, updated by the relation messages
+    /// preceding schema changes
+    fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    fn add_table_schemas(self: Pin<&mut Self>, table_schemas: HashMap<TableId, TableSchema>) {
+        self.project().table_schemas.extend(table_schemas);
+    }
+
+    /// Reports the end of the received WAL as written, and the positions
+    /// of the status as flushed and applied
+    fn send_status_update(
         self: Pin<&mut Self>,
-        lsn: PgLsn,
-    ) -> Result<(), StatusUpdateError> {
-        let this = self.project();
-        let ts = this.postgres_epoch.elapsed()?.as_micros() as i64;
-        this.stream
-            .standby_status_update(lsn, lsn, lsn, ts, 0)
-            .await?;
+        status: StandbyStatus,
+    ) -> BoxFuture<'_, Result<(), StatusUpdateError>> {
+        Box::pin(async move {
+            let this = self.project();
+            let ts = this.postgres_epoch.elapsed()?.as_micros() as i64;
+            let (written, flushed, applied) = this.positions.update(status, Instant::now());
+            this.stream
+                .standby_status_update(written, flushed, applied, ts, 0)
+                .await?;
 
-        Ok(())
+            Ok(())
+        })
+    }
+
+    /// Due when the server asked for a reply or the interval elapsed
+    fn status_update_due(&self) -> bool {
+        self.positions.is_due(Instant::now())
+    }
+}
+
+/// Converts the values of the columns of overridden types, applying the
+/// policy to the values which fail to convert. Returns the position of the
+/// column which failed the row
+fn override_types(
+    row: &mut TableRow,
+    type_overrides: &[(usize, TypeOverride)],
+    column_schemas: &[ColumnSchema],
+    policy: ConversionErrorPolicy,
+) -> Result<(), (usize, FromTextError)> {
+    for &(i, type_override) in type_overrides {
+        if let Some(cell) = row.values.get_mut(i) {
+            let value = std::mem::replace(cell, Cell::Null);
+            *cell = match TextFormatConverter::try_override(type_override, value) {
+                Ok(value) => value,
+                Err(e) => {
+                    let value = match (policy, &e) {
+                        (ConversionErrorPolicy::Null, _) => Cell::Null,
+                        (ConversionErrorPolicy::Coerce, FromTextError::EpochOutOfRange(epoch)) => {
+                            TextFormatConverter::coerce_override(type_override, Cell::I64(*epoch))
+                        }
+                        (ConversionErrorPolicy::Coerce, _) => Cell::Null,
+                        _ => return Err((i, e)),
+                    };
+                    if policy == ConversionErrorPolicy::Coerce {
+                        warn!(
+                            "coerced column `{}` to {value:?}: {e}",
+                            column_schemas[i].name
+                        );
+                    }
+                    row.failed_columns.push(column_schemas[i].name.clone());
+                    value
+                }
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Returns the positions in `column_schemas` of the columns named `names`
+fn column_positions(names: &[String], column_schemas: &[ColumnSchema]) -> Vec<usize> {
+    column_schemas
+        .iter()
+        .enumerate()
+        .filter(|(_, column_schema)| names.contains(&column_schema.name))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Converts local times of `time_zone`, parsed as UTC times, to UTC times.
+/// Ambiguous local times take the earlier time and local times skipped by
+/// a clock change the offset before it, like Postgres does.
+fn localize(value: DateTime<Utc>, time_zone: Tz) -> DateTime<Utc> {
+    let local = value.naive_utc();
+    match time_zone.from_local_datetime(&local) {
+        LocalResult::Single(value) | LocalResult::Ambiguous(value, _) => value.with_timezone(&Utc),
+        LocalResult::None => {
+            // the offset of the local time read as a UTC time may already be
+            // the one after the clock change, east of UTC, but a day earlier
+            // it's the one before it
+            let before = local - TimeDelta::days(1);
+            let offset = time_zone.offset_from_utc_datetime(&before).fix();
+            value - TimeDelta::seconds(offset.local_minus_utc() as i64)
+        }
+    }
+}
+
+fn localize_timestamps(row: &mut TableRow, columns: &[usize], time_zone: Tz) {
+    for &i in columns {
+        match row.values.get_mut(i) {
+            Some(Cell::TimeStampTz(value)) => *value = localize(*value, time_zone),
+            Some(Cell::Array(ArrayCell::TimeStampTz(values))) => {
+                for value in values.iter_mut().flatten() {
+                    *value = localize(*value, time_zone);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn localize_event_timestamps(
+    event: &mut CdcEvent,
+    table_schemas: &HashMap<TableId, TableSchema>,
+    time_zone: Tz,
+) {
+    let (CdcEvent::Insert((table_id, row))
+    | CdcEvent::Update((table_id, row))
+    | CdcEvent::Delete((table_id, row))) = event
+    else {
+        return;
+    };
+    let Some(table_schema) = table_schemas.get(table_id) else {
+        return;
+    };
+    if table_schema.local_timestamp_columns.is_empty() {
+        return;
+    }
+    let columns = column_positions(
+        &table_schema.local_timestamp_columns,
+        &table_schema.column_schemas,
+    );
+    localize_timestamps(row, &columns, time_zone);
+}
+
+/// Sets the commit timestamp of a change, in its row and in its table's
+/// column of commit timestamps if it has one
+fn set_commit_timestamp(
+    event: &mut CdcEvent,
+    table_schemas: &HashMap<TableId, TableSchema>,
+    commit_timestamp: Option<DateTime<Utc>>,
+) {
+    let (CdcEvent::Insert((table_id, row))
+    | CdcEvent::Update((table_id, row))
+    | CdcEvent::Delete((table_id, row))) = event
+    else {
+        return;
+    };
+    row.commit_timestamp = commit_timestamp;
+    let Some(table_schema) = table_schemas.get(table_id) else {
+        return;
+    };
+    let Some(name) = &table_schema.commit_timestamp_column else {
+        return;
+    };
+    let position = column_positions(std::slice::from_ref(name), &table_schema.column_schemas);
+    if let (Some(&i), Some(commit_timestamp)) = (position.first(), commit_timestamp) {
+        if let Some(cell) = row.values.get_mut(i) {
+            *cell = Cell::TimeStampTz(commit_timestamp);
+        }
+    }
+}
+
+fn resolve_large_objects(
+    reader: Arc<LargeObjectReader>,
+    mut row: TableRow,
+    columns: Vec<usize>,
+) -> BoxFuture<'static, Result<TableRow, ReplicationClientError>> {
+    Box::pin(async move {
+        reader.resolve(&mut row, &columns).await?;
+        Ok(row)
+    })
+}
+
+/// Returns the positions of the columns of large objects in a change's
+/// row, none if it has none
+fn event_large_object_columns(
+    event: &CdcEvent,
+    table_schemas: &HashMap<TableId, TableSchema>,
+) -> Option<Vec<usize>> {
+    let (CdcEvent::Insert((table_id, _))
+    | CdcEvent::Update((table_id, _))
+    | CdcEvent::Delete((table_id, _))) = event
+    else {
+        return None;
+    };
+    let table_schema = table_schemas.get(table_id)?;
+    if table_schema.large_object_columns.is_empty() {
+        return None;
     }
+    Some(column_positions(
+        &table_schema.large_object_columns,
+        &table_schema.column_schemas,
+    ))
+}
+
+fn resolve_event_large_objects(
+    reader: Arc<LargeObjectReader>,
+    mut event: CdcEvent,
+    columns: Vec<usize>,
+) -> BoxFuture<'static, Result<CdcEvent, ReplicationClientError>> {
+    Box::pin(async move {
+        if let CdcEvent::Insert((_, row))
+        | CdcEvent::Update((_, row))
+        | CdcEvent::Delete((_, row)) = &mut event
+        {
+            reader.resolve(row, &columns).await?;
+        }
+        Ok(event)
+    })
+}
+
+/// Returns the schema of a table described by a relation message and the
+/// positions of its columns in the rows of the changes following it
+fn relation_table_schema(
+    relation_body: &RelationBody,
+    table_schema: &TableSchema,
+) -> Result<(TableSchema, Vec<usize>), CdcEventConversionError> {
+    let table_schema =
+        CdcEventConverter::try_table_schema_from_relation(relation_body, table_schema)?;
+    let tuple_indexes =
+        CdcEventConverter::tuple_indexes_from_relation(relation_body, &table_schema)?;
+    Ok((table_schema, tuple_indexes))
 }
 
 impl Stream for CdcStream {
     type Item = Result<CdcEvent, CdcStreamError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.project();
-        match ready!(this.stream.poll_next(cx)) {
-            Some(Ok(msg)) => match CdcEventConverter::try_from(msg, this.table_schemas) {
-                Ok(row) => Poll::Ready(Some(Ok(row))),
-                Err(e) => Poll::Ready(Some(Err(e.into()))),
-            },
-            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-            None => Poll::Ready(None),
+        let mut this = self.project();
+        if let Some(resolving) = this.resolving {
+            let event = ready!(resolving.as_mut().poll(cx));
+            *this.resolving = None;
+            return Poll::Ready(Some(event.map_err(Into::into)));
+        }
+        loop {
+            return match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(msg)) => {
+                    match &msg {
+                        ReplicationMessage::XLogData(xlog_data) => {
+                            this.positions.receive(xlog_data.wal_end().into(), false)
+                        }
+                        ReplicationMessage::PrimaryKeepAlive(keep_alive) => this
+                            .positions
+                            .receive(keep_alive.wal_end().into(), keep_alive.reply() == 1),
+                        _ => {}
+                    }
+                    match CdcEventConverter::try_from_with_policy(
+                        msg,
+                        this.table_schemas,
+                        this.tuple_indexes,
+                        this.rows,
+                        this.conversion_policy,
+                    ) {
+                        Ok(CdcEvent::Relation(relation_body)) => {
+                            // A relation message precedes changes made after a schema
+                            // change, so the following rows must be decoded with it
+                            let table_id = relation_body.rel_id();
+                            if let Some(table_schema) = this.table_schemas.get(&table_id) {
+                                match relation_table_schema(&relation_body, table_schema) {
+                                    Ok((table_schema, tuple_indexes)) => {
+                                        this.table_schemas.insert(table_id, table_schema);
+                                        this.tuple_indexes.insert(table_id, tuple_indexes);
+                                    }
+                                    Err(e) => return Poll::Ready(Some(Err(e.into()))),
+                                }
+                            } else if *this.skip_unknown_tables {
+                                continue;
+                            }
+                            Poll::Ready(Some(Ok(CdcEvent::Relation(relation_body))))
+                        }
+                        Ok(mut event) => {
+                            if let CdcEvent::Begin(begin_body) = &event {
+                                *this.commit_timestamp =
+                                    Some(replication_timestamp(begin_body.timestamp()));
+                            }
+                            set_commit_timestamp(
+                                &mut event,
+                                this.table_schemas,
+                                *this.commit_timestamp,
+                            );
+                            if let Some(time_zone) = this.time_zone {
+                                localize_event_timestamps(
+                                    &mut event,
+                                    this.table_schemas,
+                                    *time_zone,
+                                );
+                            }
+                            if let Some(reader) = this.large_objects {
+                                if let Some(columns) =
+                                    event_large_object_columns(&event, this.table_schemas)
+                                {
+                                    let mut resolving =
+                                        resolve_event_large_objects(reader.clone(), event, columns);
+                                    return match resolving.as_mut().poll(cx) {
+                                        Poll::Ready(event) => {
+                                            Poll::Ready(Some(event.map_err(Into::into)))
+                                        }
+                                        Poll::Pending => {
+                                            *this.resolving = Some(resolving);
+                                            Poll::Pending
+                                        }
+                                    };
+                                }
+                            }
+                            Poll::Ready(Some(Ok(event)))
+                        }
+                        Err(CdcEventConversionError::MissingSchema(_))
+                            if *this.skip_unknown_tables =>
+                        {
+                            continue;
+                        }
+                        Err(e) => Poll::Ready(Some(Err(e.into()))),
+                    }
+                }
+                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+                None => Poll::Ready(None),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::{NaiveDateTime, TimeZone, Utc};
+    use chrono_tz::{America::New_York, Europe::Berlin, Tz};
+    use tokio_postgres::types::PgLsn;
+
+    use super::{localize, StandbyPositions, StandbyStatus};
+
+    fn status(flushed: u64, applied: u64) -> StandbyStatus {
+        StandbyStatus {
+            flushed: PgLsn::from(flushed),
+            applied: PgLsn::from(applied),
         }
     }
+
+    #[test]
+    fn updates_are_due_once_the_interval_elapsed() {
+        let start = Instant::now();
+        let mut positions =
+            StandbyPositions::new(PgLsn::from(100), Some(Duration::from_secs(10)), start);
+        assert!(!positions.is_due(start + Duration::from_secs(9)));
+        assert!(positions.is_due(start + Duration::from_secs(10)));
+
+        // the interval restarts with each update
+        positions.update(status(100, 100), start + Duration::from_secs(10));
+        assert!(!positions.is_due(start + Duration::from_secs(19)));
+        assert!(positions.is_due(start + Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn updates_are_due_when_a_keepalive_asks_for_a_reply() {
+        let start = Instant::now();
+        let mut positions = StandbyPositions::new(PgLsn::from(100), None, start);
+        positions.receive(PgLsn::from(200), false);
+        assert!(!positions.is_due(start + Duration::from_secs(3600)));
+
+        positions.receive(PgLsn::from(300), true);
+        assert!(positions.is_due(start));
+
+        // the reply is sent with the next update
+        positions.update(status(100, 100), start);
+        assert!(!positions.is_due(start));
+    }
+
+    #[test]
+    fn received_wal_is_written_while_flush_waits_for_the_sink() {
+        let start = Instant::now();
+        let mut positions = StandbyPositions::new(PgLsn::from(100), None, start);
+        positions.receive(PgLsn::from(500), false);
+        assert_eq!(
+            positions.update(status(100, 100), start),
+            (PgLsn::from(500), PgLsn::from(100), PgLsn::from(100))
+        );
+
+        positions.receive(PgLsn::from(800), true);
+        assert_eq!(
+            positions.update(status(100, 100), start),
+            (PgLsn::from(800), PgLsn::from(100), PgLsn::from(100))
+        );
+
+        assert_eq!(
+            positions.update(status(600, 600), start),
+            (PgLsn::from(800), PgLsn::from(600), PgLsn::from(600))
+        );
+    }
+
+    #[test]
+    fn applied_never_passes_flushed_and_positions_never_move_back() {
+        let start = Instant::now();
+        let mut positions = StandbyPositions::new(PgLsn::from(100), None, start);
+        assert_eq!(
+            positions.update(status(300, 400), start),
+            (PgLsn::from(300), PgLsn::from(300), PgLsn::from(300))
+        );
+        assert_eq!(
+            positions.update(status(200, 200), start),
+            (PgLsn::from(300), PgLsn::from(300), PgLsn::from(300))
+        );
+    }
+
+    /// Localizes a local time of the time zone and formats it in UTC
+    fn localized(local: &str, time_zone: Tz) -> String {
+        let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S").unwrap();
+        localize(Utc.from_utc_datetime(&local), time_zone)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+
+    #[test]
+    fn local_times_skipped_by_spring_forward_take_the_offset_before_it() {
+        // New York skips from 02:00 EST to 03:00 EDT on 2024-03-10
+        assert_eq!(
+            localized("2024-03-10 01:59:59", New_York),
+            "2024-03-10 06:59:59"
+        );
+        assert_eq!(
+            localized("2024-03-10 02:00:00", New_York),
+            "2024-03-10 07:00:00"
+        );
+        assert_eq!(
+            localized("2024-03-10 02:30:00", New_York),
+            "2024-03-10 07:30:00"
+        );
+        assert_eq!(
+            localized("2024-03-10 03:00:00", New_York),
+            "2024-03-10 07:00:00"
+        );
+
+        // Berlin, east of UTC, skips from 02:00 CET to 03:00 CEST on
+        // 2024-03-31
+        assert_eq!(
+            localized("2024-03-31 01:59:59", Berlin),
+            "2024-03-31 00:59:59"
+        );
+        assert_eq!(
+            localized("2024-03-31 02:00:00", Berlin),
+            "2024-03-31 01:00:00"
+        );
+        assert_eq!(
+            localized("2024-03-31 02:30:00", Berlin),
+            "2024-03-31 01:30:00"
+        );
+        assert_eq!(
+            localized("2024-03-31 03:00:00", Berlin),
+            "2024-03-31 01:00:00"
+        );
+    }
+
+    #[test]
+    fn local_times_repeated_by_fall_back_take_the_earlier_time() {
+        // New York repeats 01:00 to 02:00, first in EDT then in EST, on
+        // 2024-11-03
+        assert_eq!(
+            localized("2024-11-03 00:59:59", New_York),
+            "2024-11-03 04:59:59"
+        );
+        assert_eq!(
+            localized("2024-11-03 01:00:00", New_York),
+            "2024-11-03 05:00:00"
+        );
+        assert_eq!(
+            localized("2024-11-03 01:30:00", New_York),
+            "2024-11-03 05:30:00"
+        );
+        assert_eq!(
+            localized("2024-11-03 02:00:00", New_York),
+            "2024-11-03 07:00:00"
+        );
+
+        // Berlin repeats 02:00 to 03:00, first in CEST then in CET, on
+        // 2024-10-27
+        assert_eq!(
+            localized("2024-10-27 01:59:59", Berlin),
+            "2024-10-26 23:59:59"
+        );
+        assert_eq!(
+            localized("2024-10-27 02:30:00", Berlin),
+            "2024-10-27 00:30:00"
+        );
+        assert_eq!(
+            localized("2024-10-27 03:00:00", Berlin),
+            "2024-10-27 02:00:00"
+        );
+    }
 }
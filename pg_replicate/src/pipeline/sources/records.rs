@@ -0,0 +1,261 @@
+//! The records of the stdout sink's json lines format, which the replay and
+//! kafka sources convert back to rows and change events with the schemas of
+//! the tables.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tokio_postgres::types::{PgLsn, Type};
+
+use crate::{
+    conversions::{
+        cdc_event::CdcEvent,
+        table_row::TableRow,
+        text::{FromTextError, TextFormatConverter},
+        Cell,
+    },
+    pipeline::status::POSTGRES_EPOCH_UNIX_SECS,
+    table::{ColumnSchema, TableId, TableSchema},
+};
+
+use super::{begin_body, commit_body};
+
+#[derive(Debug, Error)]
+pub enum RecordError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("column {0} of table {1}: {2}")]
+    Conversion(String, String, FromTextError),
+}
+
+impl RecordError {
+    fn invalid(message: &str) -> RecordError {
+        RecordError::Invalid(message.to_string())
+    }
+}
+
+/// A record of the json lines format, a row or a transaction marker
+#[derive(Deserialize)]
+pub(crate) struct Record {
+    pub(crate) op: String,
+    #[serde(default)]
+    table: Option<String>,
+    #[serde(default)]
+    lsn: Option<String>,
+    #[serde(default)]
+    xid: Option<u32>,
+    #[serde(default)]
+    commit_timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    row: Option<Map<String, Value>>,
+}
+
+impl Record {
+    pub(crate) fn parse(json: &[u8]) -> Result<Record, RecordError> {
+        serde_json::from_slice(json).map_err(|e| RecordError::Invalid(e.to_string()))
+    }
+
+    /// Whether the record is a copied row of the table
+    pub(crate) fn is_copy_of(&self, table: &str) -> bool {
+        self.op == "copy" && self.table.as_deref() == Some(table)
+    }
+
+    pub(crate) fn lsn(&self) -> Result<Option<PgLsn>, RecordError> {
+        match &self.lsn {
+            Some(lsn) => match lsn.parse() {
+                Ok(lsn) => Ok(Some(lsn)),
+                Err(_) => Err(RecordError::invalid("invalid lsn")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The row of the record, converted with the table's columns
+    pub(crate) fn table_row(
+        &self,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<TableRow, RecordError> {
+        let (Some(table), Some(row)) = (&self.table, &self.row) else {
+            return Err(RecordError::invalid("a row has no table or no values"));
+        };
+        json_to_row(table, column_schemas, row)
+    }
+}
+
+/// A transaction of the records being converted
+struct RecordTransaction {
+    commit_timestamp: DateTime<Utc>,
+    /// Whether the transaction was committed before the start lsn, so that
+    /// its changes are skipped
+    skipped: bool,
+}
+
+/// Converts the records of transactions to change events, skipping the
+/// transactions committed before the start lsn, the copied rows and the
+/// changes of tables without a schema
+pub(crate) struct RecordConverter {
+    table_schemas: HashMap<TableId, TableSchema>,
+    /// Ids of the tables by their names, as written in the records
+    table_ids: HashMap<String, TableId>,
+    start_lsn: PgLsn,
+    transaction: Option<RecordTransaction>,
+}
+
+impl RecordConverter {
+    pub(crate) fn new(
+        table_schemas: HashMap<TableId, TableSchema>,
+        start_lsn: PgLsn,
+    ) -> RecordConverter {
+        let table_ids = table_schemas
+            .iter()
+            .map(|(table_id, table_schema)| (table_schema.table_name.to_string(), *table_id))
+            .collect();
+        RecordConverter {
+            table_schemas,
+            table_ids,
+            start_lsn,
+            transaction: None,
+        }
+    }
+
+    pub(crate) fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    pub(crate) fn add_table_schemas(&mut self, table_schemas: HashMap<TableId, TableSchema>) {
+        for (table_id, table_schema) in &table_schemas {
+            self.table_ids
+                .insert(table_schema.table_name.to_string(), *table_id);
+        }
+        self.table_schemas.extend(table_schemas);
+    }
+
+    /// Returns the event of a record, if it isn't skipped
+    pub(crate) fn event(&mut self, record: Record) -> Result<Option<CdcEvent>, RecordError> {
+        let lsn = record.lsn()?;
+
+        match record.op.as_str() {
+            "copy" => Ok(None),
+            "begin" | "commit" => {
+                let (Some(lsn), Some(commit_timestamp)) = (lsn, record.commit_timestamp) else {
+                    return Err(RecordError::invalid(
+                        "a transaction marker has no lsn or commit timestamp",
+                    ));
+                };
+                let timestamp = commit_timestamp.timestamp_micros()
+                    - POSTGRES_EPOCH_UNIX_SECS as i64 * 1_000_000;
+                if record.op == "begin" {
+                    let skipped = lsn <= self.start_lsn;
+                    self.transaction = Some(RecordTransaction {
+                        commit_timestamp,
+                        skipped,
+                    });
+                    let xid = record.xid.unwrap_or_default();
+                    Ok((!skipped).then(|| CdcEvent::Begin(begin_body(lsn.into(), timestamp, xid))))
+                } else {
+                    // a commit without a begin ends a transaction of which
+                    // only the end was recorded
+                    let skipped = match self.transaction.take() {
+                        Some(transaction) => transaction.skipped,
+                        None => true,
+                    };
+                    Ok((!skipped).then(|| CdcEvent::Commit(commit_body(lsn.into(), timestamp))))
+                }
+            }
+            op @ ("insert" | "update" | "delete") => {
+                let Some(transaction) = &self.transaction else {
+                    return Err(RecordError::invalid(
+                        "a change is outside of a transaction, the records need begin and commit markers",
+                    ));
+                };
+                if transaction.skipped {
+                    return Ok(None);
+                }
+                let commit_timestamp = transaction.commit_timestamp;
+                let Some(table) = &record.table else {
+                    return Err(RecordError::invalid("a change has no table"));
+                };
+                // changes of tables without a schema weren't selected
+                let Some(table_id) = self.table_ids.get(table).copied() else {
+                    return Ok(None);
+                };
+                let column_schemas = &self.table_schemas[&table_id].column_schemas;
+                let mut row = record.table_row(column_schemas)?;
+                row.commit_timestamp = Some(commit_timestamp);
+                Ok(Some(match op {
+                    "insert" => CdcEvent::Insert((table_id, row)),
+                    "update" => CdcEvent::Update((table_id, row)),
+                    _ => CdcEvent::Delete((table_id, row)),
+                }))
+            }
+            op => Err(RecordError::Invalid(format!("unknown operation {op}"))),
+        }
+    }
+}
+
+/// Converts the values of a recorded row to a row of the columns
+fn json_to_row(
+    table: &str,
+    column_schemas: &[ColumnSchema],
+    values: &Map<String, Value>,
+) -> Result<TableRow, RecordError> {
+    let mut table_row = TableRow::with_capacity(column_schemas.len());
+    for column_schema in column_schemas {
+        let value = values.get(&column_schema.name).unwrap_or(&Value::Null);
+        let cell = json_to_cell(&column_schema.typ, value).map_err(|e| {
+            RecordError::Conversion(column_schema.name.clone(), table.to_string(), e)
+        })?;
+        table_row.values.push(cell);
+    }
+    Ok(table_row)
+}
+
+/// Converts a value printed by the stdout sink back to a cell, through the
+/// value's text in Postgres's format
+fn json_to_cell(typ: &Type, value: &Value) -> Result<Cell, FromTextError> {
+    let text = match value {
+        Value::Null => return Ok(Cell::Null),
+        // json values are printed as they are
+        _ if matches!(*typ, Type::JSON | Type::JSONB) => return Ok(Cell::Json(value.clone())),
+        Value::Array(elements) => {
+            let elements: Vec<String> = elements
+                .iter()
+                .map(|element| match element {
+                    Value::Null => "NULL".to_string(),
+                    element => {
+                        let text = element_text(typ, element);
+                        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+                    }
+                })
+                .collect();
+            format!("{{{}}}", elements.join(","))
+        }
+        value => element_text(typ, value),
+    };
+    TextFormatConverter::try_from_str(typ, &text)
+}
+
+/// The text of a scalar value or of an element of an array
+fn element_text(typ: &Type, value: &Value) -> String {
+    match value {
+        // timestamps are printed in the rfc 3339 format, whose date and time
+        // Postgres separates with a space
+        Value::String(s)
+            if matches!(
+                *typ,
+                Type::TIMESTAMP
+                    | Type::TIMESTAMPTZ
+                    | Type::TIMESTAMP_ARRAY
+                    | Type::TIMESTAMPTZ_ARRAY
+            ) =>
+        {
+            s.replacen('T', " ", 1)
+        }
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
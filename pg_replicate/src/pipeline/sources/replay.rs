@@ -0,0 +1,257 @@
+//! A source replaying the rows and changes of dumps written by the stdout
+//! sink in its json lines format, to rebuild a sink's tables or backfill a
+//! new sink without reading from the source's slot.
+//!
+//! The dumps hold the copied rows of tables and the changes of transactions
+//! between `begin` and `commit` markers. The values are converted back with
+//! the schemas of the tables, which are usually read from the source's
+//! catalog, so the tables' columns must not have changed since the dumps
+//! were written. Types overridden in the source's column options are not
+//! converted back.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{future::BoxFuture, ready, Stream};
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader, Lines},
+};
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
+
+use super::{
+    postgres::{CdcStreamError, StatusUpdateError, TableCopier, TableCopyStreamError, TableFilter},
+    records::{Record, RecordConverter, RecordError},
+    CdcEventStream, Source, SourceError, StandbyStatus,
+};
+
+#[derive(Debug, Error)]
+pub enum ReplaySourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid record on line {0} of the dumps: {1}")]
+    InvalidRecord(u64, RecordError),
+
+    #[error("table {0} has no schema")]
+    UnknownTable(String),
+
+    #[error("the replay source can't add tables to its cdc stream")]
+    AddingTablesNotSupported,
+}
+
+impl SourceError for ReplaySourceError {}
+
+/// Reads the records of dump files one after the other
+struct RecordReader {
+    files: VecDeque<Lines<BufReader<File>>>,
+    /// Number of the line last read, counted across the files
+    line: u64,
+}
+
+impl RecordReader {
+    async fn open(paths: &[PathBuf]) -> Result<RecordReader, ReplaySourceError> {
+        let mut files = VecDeque::with_capacity(paths.len());
+        for path in paths {
+            files.push_back(BufReader::new(File::open(path).await?).lines());
+        }
+        Ok(RecordReader { files, line: 0 })
+    }
+
+    fn poll_next_record(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Record, ReplaySourceError>>> {
+        loop {
+            let Some(lines) = self.files.front_mut() else {
+                return Poll::Ready(None);
+            };
+            match ready!(Pin::new(lines).poll_next_line(cx)) {
+                Ok(Some(line)) => {
+                    self.line += 1;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record = Record::parse(line.as_bytes()).map_err(|e| self.invalid(e));
+                    return Poll::Ready(Some(record));
+                }
+                Ok(None) => {
+                    self.files.pop_front();
+                }
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            }
+        }
+    }
+
+    fn invalid(&self, error: RecordError) -> ReplaySourceError {
+        ReplaySourceError::InvalidRecord(self.line, error)
+    }
+}
+
+/// A source replaying dumps of the stdout sink's json lines format. Table
+/// copies return the dumped copied rows of the tables and the cdc stream the
+/// dumped transactions committed after its start lsn, ending with the dumps.
+pub struct ReplaySource {
+    paths: Vec<PathBuf>,
+    table_schemas: HashMap<TableId, TableSchema>,
+}
+
+impl ReplaySource {
+    /// Replays the dump files in order, converting the rows of the tables
+    /// with the schemas. Rows of other tables are skipped.
+    pub fn new(paths: Vec<PathBuf>, table_schemas: HashMap<TableId, TableSchema>) -> ReplaySource {
+        ReplaySource {
+            paths,
+            table_schemas,
+        }
+    }
+
+    fn find_table(&self, table_name: &TableName) -> Result<&TableSchema, ReplaySourceError> {
+        self.table_schemas
+            .values()
+            .find(|table_schema| {
+                table_schema.table_name.schema == table_name.schema
+                    && table_schema.table_name.name == table_name.name
+            })
+            .ok_or_else(|| ReplaySourceError::UnknownTable(table_name.to_string()))
+    }
+}
+
+#[async_trait]
+impl Source for ReplaySource {
+    type Error = ReplaySourceError;
+    type TableCopyStream = ReplayTableCopyStream;
+    type CdcStream = ReplayCdcStream;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<ReplayTableCopyStream, Self::Error> {
+        self.find_table(table_name)?;
+        Ok(ReplayTableCopyStream {
+            records: RecordReader::open(&self.paths).await?,
+            table: table_name.to_string(),
+            column_schemas: column_schemas.to_vec(),
+        })
+    }
+
+    async fn count_table_rows(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        self.find_table(table_name)?;
+        let table = table_name.to_string();
+        let mut records = RecordReader::open(&self.paths).await?;
+        let mut count = 0;
+        while let Some(record) = futures::future::poll_fn(|cx| records.poll_next_record(cx)).await {
+            let record = record?;
+            if record.is_copy_of(&table) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn get_table_copier(
+        &self,
+        _table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error> {
+        Err(ReplaySourceError::AddingTablesNotSupported)
+    }
+
+    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<ReplayCdcStream, Self::Error> {
+        Ok(ReplayCdcStream {
+            records: RecordReader::open(&self.paths).await?,
+            converter: RecordConverter::new(self.table_schemas.clone(), start_lsn),
+        })
+    }
+}
+
+/// The dumped copied rows of a table
+pub struct ReplayTableCopyStream {
+    records: RecordReader,
+    table: String,
+    column_schemas: Vec<ColumnSchema>,
+}
+
+impl Stream for ReplayTableCopyStream {
+    type Item = Result<TableRow, TableCopyStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let record = match ready!(this.records.poll_next_record(cx)) {
+                Some(Ok(record)) => record,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+            if !record.is_copy_of(&this.table) {
+                continue;
+            }
+            let row = record
+                .table_row(&this.column_schemas)
+                .map_err(|e| this.records.invalid(e).into());
+            return Poll::Ready(Some(row));
+        }
+    }
+}
+
+/// The dumped transactions committed after the start lsn
+pub struct ReplayCdcStream {
+    records: RecordReader,
+    converter: RecordConverter,
+}
+
+impl CdcEventStream for ReplayCdcStream {
+    fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        self.converter.table_schemas()
+    }
+
+    fn add_table_schemas(self: Pin<&mut Self>, table_schemas: HashMap<TableId, TableSchema>) {
+        self.get_mut().converter.add_table_schemas(table_schemas);
+    }
+
+    fn send_status_update(
+        self: Pin<&mut Self>,
+        _status: StandbyStatus,
+    ) -> BoxFuture<'_, Result<(), StatusUpdateError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Stream for ReplayCdcStream {
+    type Item = Result<CdcEvent, CdcStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let record = match ready!(this.records.poll_next_record(cx)) {
+                Some(Ok(record)) => record,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+            match this.converter.event(record) {
+                Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                Ok(None) => continue,
+                Err(e) => return Poll::Ready(Some(Err(this.records.invalid(e).into()))),
+            }
+        }
+    }
+}
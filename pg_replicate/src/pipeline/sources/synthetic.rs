@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use chrono::DateTime;
+use futures::{future::BoxFuture, ready, Stream};
+use thiserror::Error;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tokio_postgres::types::{PgLsn, Type};
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+    table::{ColumnSchema, TableId, TableName, TableSchema},
+};
+
+use super::{
+    begin_body, commit_body,
+    postgres::{CdcStreamError, StatusUpdateError, TableCopier, TableCopyStreamError, TableFilter},
+    CdcEventStream, Source, SourceError, StandbyStatus,
+};
+
+/// Types of the generated columns besides the primary key, in order
+const COLUMN_TYPES: [Type; 5] = [
+    Type::TEXT,
+    Type::INT4,
+    Type::FLOAT8,
+    Type::BOOL,
+    Type::TIMESTAMPTZ,
+];
+
+/// Bytes of WAL each generated change takes up
+const LSN_STEP: u64 = 100;
+
+/// Relative frequencies of the generated inserts, updates and deletes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeMix {
+    pub inserts: u32,
+    pub updates: u32,
+    pub deletes: u32,
+}
+
+impl Default for ChangeMix {
+    fn default() -> Self {
+        ChangeMix {
+            inserts: 70,
+            updates: 20,
+            deletes: 10,
+        }
+    }
+}
+
+/// Shape and rate of the data generated by a [`SyntheticSource`]
+#[derive(Debug, Clone)]
+pub struct SyntheticSourceConfig {
+    /// Number of tables, named `synthetic.table_<n>`
+    pub num_tables: u32,
+    /// Number of columns of each table besides its `id` primary key. Their
+    /// types cycle through text, integer, double, boolean and timestamp.
+    pub num_columns: usize,
+    /// Number of rows of each table returned by table copies
+    pub rows_per_table: u64,
+    /// Number of changes after which the cdc stream ends, unlimited if not
+    /// set
+    pub num_changes: Option<u64>,
+    /// Changes generated per second, as many as the pipeline consumes if
+    /// not set
+    pub changes_per_second: Option<u32>,
+    /// Number of changes in each transaction
+    pub changes_per_transaction: u32,
+    pub change_mix: ChangeMix,
+    /// Seed of the generated values, the same seed generates the same rows
+    pub seed: u64,
+}
+
+impl Default for SyntheticSourceConfig {
+    fn default() -> Self {
+        SyntheticSourceConfig {
+            num_tables: 1,
+            num_columns: 8,
+            rows_per_table: 10_000,
+            num_changes: Some(100_000),
+            changes_per_second: None,
+            changes_per_transaction: 10,
+            change_mix: ChangeMix::default(),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SyntheticSourceError {
+    #[error("table {0} is not generated by the synthetic source")]
+    UnknownTable(String),
+
+    #[error("the synthetic source can't add tables to its cdc stream")]
+    AddingTablesNotSupported,
+}
+
+impl SourceError for SyntheticSourceError {}
+
+/// A source generating rows and changes instead of reading them from
+/// Postgres, to benchmark sinks and pipelines without a database producing
+/// WAL
+pub struct SyntheticSource {
+    config: SyntheticSourceConfig,
+    table_schemas: HashMap<TableId, TableSchema>,
+}
+
+impl SyntheticSource {
+    pub fn new(config: SyntheticSourceConfig) -> SyntheticSource {
+        let table_schemas = (1..=config.num_tables)
+            .map(|table_id| (table_id, table_schema(table_id, config.num_columns)))
+            .collect();
+        SyntheticSource {
+            config,
+            table_schemas,
+        }
+    }
+
+    fn find_table(&self, table_name: &TableName) -> Result<&TableSchema, SyntheticSourceError> {
+        self.table_schemas
+            .values()
+            .find(|table_schema| {
+                table_schema.table_name.schema == table_name.schema
+                    && table_schema.table_name.name == table_name.name
+            })
+            .ok_or_else(|| SyntheticSourceError::UnknownTable(table_name.to_string()))
+    }
+}
+
+fn table_schema(table_id: TableId, num_columns: usize) -> TableSchema {
+    let mut column_schemas = Vec::with_capacity(num_columns + 1);
+    column_schemas.push(ColumnSchema {
+        name: "id".to_string(),
+        typ: Type::INT8,
+        modifier: -1,
+        nullable: false,
+        primary: true,
+    });
+    for i in 0..num_columns {
+        column_schemas.push(ColumnSchema {
+            name: format!("column_{i}"),
+            typ: COLUMN_TYPES[i % COLUMN_TYPES.len()].clone(),
+            modifier: -1,
+            nullable: true,
+            primary: false,
+        });
+    }
+
+    TableSchema {
+        table_name: TableName {
+            schema: "synthetic".to_string(),
+            name: format!("table_{table_id}"),
+        },
+        table_id,
+        column_schemas,
+        skipped_columns: vec![],
+        local_timestamp_columns: vec![],
+        large_object_columns: vec![],
+        type_overrides: HashMap::new(),
+        commit_timestamp_column: None,
+    }
+}
+
+#[async_trait]
+impl Source for SyntheticSource {
+    type Error = SyntheticSourceError;
+    type TableCopyStream = SyntheticTableCopyStream;
+    type CdcStream = SyntheticCdcStream;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<SyntheticTableCopyStream, Self::Error> {
+        let table_schema = self.find_table(table_name)?;
+        Ok(SyntheticTableCopyStream {
+            column_schemas: column_schemas.to_vec(),
+            next_id: 1,
+            last_id: self.config.rows_per_table as i64,
+            rng: Rng::new(self.config.seed.wrapping_add(table_schema.table_id as u64)),
+        })
+    }
+
+    async fn count_table_rows(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        self.find_table(table_name)?;
+        Ok(self.config.rows_per_table)
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn get_table_copier(
+        &self,
+        _table_filter: Option<&TableFilter>,
+    ) -> Result<TableCopier, Self::Error> {
+        Err(SyntheticSourceError::AddingTablesNotSupported)
+    }
+
+    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<SyntheticCdcStream, Self::Error> {
+        let mut table_ids: Vec<TableId> = self.table_schemas.keys().copied().collect();
+        table_ids.sort();
+        let ticks = self.config.changes_per_second.map(|changes_per_second| {
+            let period = Duration::from_secs(1) / changes_per_second.max(1);
+            let mut ticks = interval(period);
+            // timers are coarser than the period at high rates, missed ticks
+            // are caught up with to keep the rate
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Burst);
+            ticks
+        });
+
+        Ok(SyntheticCdcStream {
+            table_schemas: self.table_schemas.clone(),
+            table_ids,
+            next_ids: HashMap::new(),
+            first_inserted_id: self.config.rows_per_table as i64 + 1,
+            change_mix: self.config.change_mix,
+            changes_per_transaction: self.config.changes_per_transaction.max(1),
+            remaining_changes: self.config.num_changes,
+            transaction_changes: None,
+            lsn: start_lsn.into(),
+            commit_lsn: start_lsn.into(),
+            ticks,
+            rng: Rng::new(self.config.seed),
+        })
+    }
+}
+
+/// Rows of a generated table, with ids from one to the configured number of
+/// rows per table
+pub struct SyntheticTableCopyStream {
+    column_schemas: Vec<ColumnSchema>,
+    next_id: i64,
+    last_id: i64,
+    rng: Rng,
+}
+
+impl Stream for SyntheticTableCopyStream {
+    type Item = Result<TableRow, TableCopyStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.next_id > this.last_id {
+            return Poll::Ready(None);
+        }
+
+        let row = generate_row(&this.column_schemas, this.next_id, &mut this.rng);
+        this.next_id += 1;
+        Poll::Ready(Some(Ok(row)))
+    }
+}
+
+/// Transactions of generated changes to random rows of the generated tables
+pub struct SyntheticCdcStream {
+    table_schemas: HashMap<TableId, TableSchema>,
+    table_ids: Vec<TableId>,
+    /// Id of the next inserted row of each table
+    next_ids: HashMap<TableId, i64>,
+    first_inserted_id: i64,
+    change_mix: ChangeMix,
+    changes_per_transaction: u32,
+    remaining_changes: Option<u64>,
+    /// Changes left in the current transaction, none between transactions
+    transaction_changes: Option<u32>,
+    lsn: u64,
+    commit_lsn: u64,
+    ticks: Option<Interval>,
+    rng: Rng,
+}
+
+impl SyntheticCdcStream {
+    fn next_change(&mut self) -> CdcEvent {
+        let table_id = self.table_ids[self.rng.below(self.table_ids.len() as u64) as usize];
+        let column_schemas = &self.table_schemas[&table_id].column_schemas;
+        let next_id = self
+            .next_ids
+            .entry(table_id)
+            .or_insert(self.first_inserted_id);
+
+        let ChangeMix {
+            inserts,
+            updates,
+            deletes,
+        } = self.change_mix;
+        let total = (inserts as u64 + updates as u64 + deletes as u64).max(1);
+        let pick = self.rng.below(total);
+
+        // updates and deletes change existing rows, tables without rows
+        // get inserts instead
+        if pick < inserts as u64 || *next_id == 1 {
+            let id = *next_id;
+            *next_id += 1;
+            return CdcEvent::Insert((table_id, generate_row(column_schemas, id, &mut self.rng)));
+        }
+
+        let id = 1 + self.rng.below(*next_id as u64 - 1) as i64;
+        let row = generate_row(column_schemas, id, &mut self.rng);
+        if pick < inserts as u64 + updates as u64 {
+            CdcEvent::Update((table_id, row))
+        } else {
+            CdcEvent::Delete((table_id, row))
+        }
+    }
+}
+
+impl CdcEventStream for SyntheticCdcStream {
+    fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    fn add_table_schemas(self: Pin<&mut Self>, table_schemas: HashMap<TableId, TableSchema>) {
+        let this = self.get_mut();
+        this.table_schemas.extend(table_schemas);
+        this.table_ids = this.table_schemas.keys().copied().collect();
+        this.table_ids.sort();
+    }
+
+    fn send_status_update(
+        self: Pin<&mut Self>,
+        _status: StandbyStatus,
+    ) -> BoxFuture<'_, Result<(), StatusUpdateError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Stream for SyntheticCdcStream {
+    type Item = Result<CdcEvent, CdcStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let Some(changes_left) = this.transaction_changes else {
+            if this.remaining_changes == Some(0) || this.table_ids.is_empty() {
+                return Poll::Ready(None);
+            }
+            let num_changes = match this.remaining_changes {
+                Some(remaining_changes) => {
+                    remaining_changes.min(this.changes_per_transaction as u64) as u32
+                }
+                None => this.changes_per_transaction,
+            };
+            this.commit_lsn = this.lsn + (num_changes as u64 + 1) * LSN_STEP;
+            this.transaction_changes = Some(num_changes);
+            return Poll::Ready(Some(Ok(CdcEvent::Begin(begin_body(
+                this.commit_lsn,
+                postgres_timestamp(),
+                this.commit_lsn as u32,
+            )))));
+        };
+
+        if changes_left == 0 {
+            this.transaction_changes = None;
+            this.lsn = this.commit_lsn;
+            return Poll::Ready(Some(Ok(CdcEvent::Commit(commit_body(
+                this.commit_lsn,
+                postgres_timestamp(),
+            )))));
+        }
+
+        if let Some(ticks) = &mut this.ticks {
+            ready!(ticks.poll_tick(cx));
+        }
+        this.transaction_changes = Some(changes_left - 1);
+        if let Some(remaining_changes) = &mut this.remaining_changes {
+            *remaining_changes -= 1;
+        }
+        this.lsn += LSN_STEP;
+        Poll::Ready(Some(Ok(this.next_change())))
+    }
+}
+
+fn generate_row(column_schemas: &[ColumnSchema], id: i64, rng: &mut Rng) -> TableRow {
+    let mut table_row = TableRow::with_capacity(column_schemas.len());
+    for column_schema in column_schemas {
+        let value = if column_schema.primary {
+            Cell::I64(id)
+        } else {
+            match column_schema.typ {
+                Type::TEXT => Cell::String(format!("value {}", rng.below(1_000_000))),
+                Type::INT4 => Cell::I32(rng.next_u64() as i32),
+                Type::FLOAT8 => Cell::F64(rng.below(1_000_000) as f64 / 100.0),
+                Type::BOOL => Cell::Bool(rng.below(2) == 0),
+                Type::TIMESTAMPTZ => {
+                    let secs = 1_700_000_000 + rng.below(100_000_000) as i64;
+                    Cell::TimeStampTz(DateTime::from_timestamp(secs, 0).unwrap_or_default())
+                }
+                _ => Cell::Null,
+            }
+        };
+        table_row.values.push(value);
+    }
+    table_row
+}
+
+/// Microseconds since the Postgres epoch, 2000-01-01
+fn postgres_timestamp() -> i64 {
+    const TIME_SEC_CONVERSION: u64 = 946_684_800;
+    let postgres_epoch = UNIX_EPOCH + Duration::from_secs(TIME_SEC_CONVERSION);
+    SystemTime::now()
+        .duration_since(postgres_epoch)
+        .map(|elapsed| elapsed.as_micros() as i64)
+        .unwrap_or_default()
+}
+
+/// A xorshift generator, fast and good enough for generating values
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // the state must not be zero
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Returns a number in `0..n`
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+}
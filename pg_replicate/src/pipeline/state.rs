@@ -0,0 +1,90 @@
+//! Export and import of the state of a pipeline, to move it from one sink to
+//! another without copying the tables again. The state is the set of copied
+//! tables and the position in the source's WAL up to which changes were
+//! written.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::table::TableId;
+
+use super::{
+    sinks::{BatchSink, SinkError, StateSink},
+    PipelineResumptionState,
+};
+
+/// The state of a pipeline, as written to and read from files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineState {
+    #[serde(serialize_with = "serialize_lsn", deserialize_with = "deserialize_lsn")]
+    pub last_lsn: PgLsn,
+
+    pub copied_tables: BTreeSet<TableId>,
+}
+
+impl From<PipelineResumptionState> for PipelineState {
+    fn from(state: PipelineResumptionState) -> Self {
+        PipelineState {
+            last_lsn: state.last_lsn,
+            copied_tables: state.copied_tables.into_iter().collect(),
+        }
+    }
+}
+
+pub(crate) fn serialize_lsn<S: Serializer>(lsn: &PgLsn, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(lsn)
+}
+
+pub(crate) fn deserialize_lsn<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PgLsn, D::Error> {
+    let lsn = String::deserialize(deserializer)?;
+    lsn.parse()
+        .map_err(|_| serde::de::Error::custom(format!("invalid lsn {lsn}")))
+}
+
+#[derive(Debug, Error)]
+pub enum StateError<SnkErr: SinkError> {
+    #[error("sink error: {0}")]
+    Sink(#[source] SnkErr),
+
+    #[error("the sink already holds the state of a pipeline, with {0} copied tables and lsn {1}")]
+    NotEmpty(usize, PgLsn),
+}
+
+/// Returns the state of the pipeline kept by a sink
+pub async fn export_state<Snk: BatchSink>(sink: &mut Snk) -> Result<PipelineState, Snk::Error> {
+    Ok(sink.get_resumption_state().await?.into())
+}
+
+/// Writes the state of a pipeline to a sink, so that a pipeline started
+/// with the sink resumes where the exported one stopped. The sink must not
+/// hold a state yet, as copied tables can't be removed from it.
+pub async fn import_state<Snk: StateSink>(
+    sink: &mut Snk,
+    state: &PipelineState,
+) -> Result<(), StateError<Snk::Error>> {
+    let current_state = sink
+        .get_resumption_state()
+        .await
+        .map_err(StateError::Sink)?;
+    if !current_state.copied_tables.is_empty() || current_state.last_lsn != PgLsn::from(0) {
+        return Err(StateError::NotEmpty(
+            current_state.copied_tables.len(),
+            current_state.last_lsn,
+        ));
+    }
+
+    for table_id in &state.copied_tables {
+        sink.table_copied(*table_id)
+            .await
+            .map_err(StateError::Sink)?;
+    }
+    sink.set_last_lsn(state.last_lsn)
+        .await
+        .map_err(StateError::Sink)?;
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    clients::postgres::TableSizeEstimate,
+    table::{TableId, TableName},
+};
+
+use super::lag::WalPosition;
+
+/// Seconds from the unix epoch to the Postgres epoch, 2000-01-01
+pub(crate) const POSTGRES_EPOCH_UNIX_SECS: u64 = 946_684_800;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PipelinePhase {
+    #[default]
+    Starting,
+    CopyingTables,
+    Cdc,
+    Errored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCopyStatus {
+    pub table_name: TableName,
+    pub rows_copied: u64,
+    pub copied: bool,
+    /// Rows of the table estimated before its copy, none if the source
+    /// can't estimate them or the table was copied by an earlier run
+    pub estimated_rows: Option<u64>,
+    /// Bytes of the table estimated before its copy
+    pub estimated_bytes: Option<u64>,
+    /// Time spent copying the table so far
+    pub copy_duration: Duration,
+}
+
+impl TableCopyStatus {
+    pub(crate) fn new(
+        table_name: TableName,
+        copied: bool,
+        estimate: Option<TableSizeEstimate>,
+    ) -> TableCopyStatus {
+        TableCopyStatus {
+            table_name,
+            rows_copied: 0,
+            copied,
+            estimated_rows: estimate.and_then(|estimate| estimate.rows),
+            estimated_bytes: estimate.map(|estimate| estimate.bytes),
+            copy_duration: Duration::ZERO,
+        }
+    }
+
+    /// Rows left to copy, none if the table's rows can't be estimated. More
+    /// rows than estimated may be copied, none are left then
+    pub fn remaining_rows(&self) -> Option<u64> {
+        if self.copied {
+            return Some(0);
+        }
+        self.estimated_rows
+            .map(|estimated_rows| estimated_rows.saturating_sub(self.rows_copied))
+    }
+
+    /// Bytes copied, estimated from the rows copied and the table's average
+    /// row size
+    pub fn estimated_bytes_copied(&self) -> Option<u64> {
+        let estimated_bytes = self.estimated_bytes?;
+        if self.copied {
+            return Some(estimated_bytes);
+        }
+        let estimated_rows = self.estimated_rows?;
+        if estimated_rows == 0 {
+            return Some(0);
+        }
+        let bytes_copied =
+            self.rows_copied as u128 * estimated_bytes as u128 / estimated_rows as u128;
+        Some(bytes_copied.min(estimated_bytes as u128) as u64)
+    }
+
+    /// Time left to copy the table at the rate its rows were copied so far
+    pub fn eta(&self) -> Option<Duration> {
+        estimate_time_left(self.remaining_rows()?, self.rows_copied, self.copy_duration)
+    }
+}
+
+/// Progress of the copy of all of the pipeline's tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyProgress {
+    pub tables_copied: usize,
+    pub tables_total: usize,
+    pub rows_copied: u64,
+    /// Rows left to copy, none if some uncopied table's rows can't be
+    /// estimated
+    pub remaining_rows: Option<u64>,
+    /// Bytes copied, none if some table's bytes can't be estimated
+    pub estimated_bytes_copied: Option<u64>,
+    /// Bytes of the tables copied by this run, none if some table's bytes
+    /// can't be estimated
+    pub estimated_bytes: Option<u64>,
+    /// Time left to copy the remaining rows at the rate of the rows copied
+    /// so far. The tables are copied one after another
+    pub eta: Option<Duration>,
+}
+
+/// Time left to copy the remaining rows at the rate `rows_copied` were
+/// copied in `copy_duration`, none before any rows were copied
+fn estimate_time_left(
+    remaining_rows: u64,
+    rows_copied: u64,
+    copy_duration: Duration,
+) -> Option<Duration> {
+    if remaining_rows == 0 {
+        return Some(Duration::ZERO);
+    }
+    if rows_copied == 0 || copy_duration.is_zero() {
+        return None;
+    }
+    let secs_per_row = copy_duration.as_secs_f64() / rows_copied as f64;
+    Some(Duration::from_secs_f64(
+        remaining_rows as f64 * secs_per_row,
+    ))
+}
+
+/// Upper bounds of the buckets of the sink latency histograms
+pub const SINK_LATENCY_BUCKETS: [Duration; 10] = [
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// Histogram of the time the sink took to write batches, with the buckets
+/// of [`SINK_LATENCY_BUCKETS`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// Writes per bucket, not cumulative. Writes slower than the last bucket
+    /// are only counted in `count`
+    pub buckets: [u64; SINK_LATENCY_BUCKETS.len()],
+    pub sum: Duration,
+    pub count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, latency: Duration) {
+        if let Some(bucket) = SINK_LATENCY_BUCKETS
+            .iter()
+            .position(|upper_bound| latency <= *upper_bound)
+        {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += latency;
+        self.count += 1;
+    }
+
+    /// Returns the upper bounds of the buckets with the cumulative counts of
+    /// writes at most as slow
+    pub fn cumulative_buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        SINK_LATENCY_BUCKETS.iter().zip(self.buckets.iter()).scan(
+            0,
+            |cumulative, (upper_bound, count)| {
+                *cumulative += count;
+                Some((*upper_bound, *cumulative))
+            },
+        )
+    }
+}
+
+/// Counters of the changes of a table written to the sink since the
+/// pipeline started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableMetrics {
+    pub inserts: u64,
+    pub updates: u64,
+    pub deletes: u64,
+    /// Values of copied rows and changes which couldn't be converted
+    pub conversion_errors: u64,
+    /// Values which couldn't be converted by column
+    pub column_conversion_errors: BTreeMap<String, u64>,
+    /// Time the sink took to write the batches holding rows of the table,
+    /// copied rows included
+    pub sink_latency: LatencyHistogram,
+}
+
+impl TableMetrics {
+    /// Counts a value of the column which couldn't be converted
+    pub(crate) fn count_conversion_error(&mut self, column: &str) {
+        self.conversion_errors += 1;
+        *self
+            .column_conversion_errors
+            .entry(column.to_string())
+            .or_default() += 1;
+    }
+}
+
+/// Progress of a running pipeline, published by
+/// [BatchDataPipeline](crate::pipeline::batching::data_pipeline::BatchDataPipeline)
+/// after every batch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipelineStatus {
+    pub phase: PipelinePhase,
+    /// Lsn up to which changes have been written to the sink
+    pub last_flushed_lsn: Option<PgLsn>,
+    /// Bytes of WAL the source has written past `last_flushed_lsn`, as of
+    /// the last keepalive from the source
+    pub lag_bytes: Option<u64>,
+    /// Age of the last flushed transaction when it was flushed, zero if
+    /// the pipeline has caught up
+    pub lag: Option<Duration>,
+    /// Position of the source's WAL when it was last queried
+    pub source_wal_lsn: Option<PgLsn>,
+    /// Time the pipeline needs to catch up with the source if the source
+    /// keeps writing WAL at its recent rate
+    pub estimated_lag: Option<Duration>,
+    pub tables: BTreeMap<TableId, TableCopyStatus>,
+    /// Counters of the tables with changes since the pipeline started
+    pub table_metrics: BTreeMap<TableId, TableMetrics>,
+    pub last_error: Option<String>,
+}
+
+impl PipelineStatus {
+    /// Returns the progress of the copy of the tables, whose estimates are
+    /// only known for the tables copied by this run
+    pub fn copy_progress(&self) -> CopyProgress {
+        let copying: Vec<&TableCopyStatus> = self
+            .tables
+            .values()
+            .filter(|table| !table.copied || table.rows_copied > 0)
+            .collect();
+        let rows_copied = self.tables.values().map(|table| table.rows_copied).sum();
+        let copy_duration = self.tables.values().map(|table| table.copy_duration).sum();
+        let remaining_rows = copying
+            .iter()
+            .map(|table| table.remaining_rows())
+            .sum::<Option<u64>>();
+        CopyProgress {
+            tables_copied: self.tables.values().filter(|table| table.copied).count(),
+            tables_total: self.tables.len(),
+            rows_copied,
+            remaining_rows,
+            estimated_bytes_copied: copying
+                .iter()
+                .map(|table| table.estimated_bytes_copied())
+                .sum(),
+            estimated_bytes: copying.iter().map(|table| table.estimated_bytes).sum(),
+            eta: remaining_rows.and_then(|remaining_rows| {
+                estimate_time_left(remaining_rows, rows_copied, copy_duration)
+            }),
+        }
+    }
+
+    pub(crate) fn update_lag_bytes(&mut self, wal_end: PgLsn) {
+        if let Some(last_flushed_lsn) = self.last_flushed_lsn {
+            let lag_bytes = u64::from(wal_end).saturating_sub(last_flushed_lsn.into());
+            self.lag_bytes = Some(lag_bytes);
+            if lag_bytes == 0 {
+                self.lag = Some(Duration::ZERO);
+            }
+        }
+    }
+
+    pub(crate) fn update_wal_position(&mut self, position: WalPosition) {
+        self.source_wal_lsn = Some(position.lsn);
+        if let Some(last_flushed_lsn) = self.last_flushed_lsn {
+            let lag_bytes = u64::from(position.lsn).saturating_sub(last_flushed_lsn.into());
+            self.lag_bytes = Some(lag_bytes);
+            self.estimated_lag = position.estimate_lag(lag_bytes);
+        }
+    }
+
+    /// Adds the counters of a batch to the tables' metrics and records the
+    /// time the sink took to write it for the tables it changed
+    pub(crate) fn record_batch(
+        &mut self,
+        batch_metrics: BTreeMap<TableId, TableMetrics>,
+        sink_latency: Duration,
+    ) {
+        for (table_id, batch) in batch_metrics {
+            let metrics = self.table_metrics.entry(table_id).or_default();
+            metrics.inserts += batch.inserts;
+            metrics.updates += batch.updates;
+            metrics.deletes += batch.deletes;
+            metrics.conversion_errors += batch.conversion_errors;
+            for (column, errors) in batch.column_conversion_errors {
+                *metrics.column_conversion_errors.entry(column).or_default() += errors;
+            }
+            if batch.inserts + batch.updates + batch.deletes > 0 {
+                metrics.sink_latency.observe(sink_latency);
+            }
+        }
+    }
+
+    /// Sets the lag from a commit timestamp in microseconds since the
+    /// Postgres epoch
+    pub(crate) fn update_lag(&mut self, commit_timestamp: i64) {
+        let commit_time = UNIX_EPOCH
+            + Duration::from_secs(POSTGRES_EPOCH_UNIX_SECS)
+            + Duration::from_micros(commit_timestamp.max(0) as u64);
+        let lag = SystemTime::now()
+            .duration_since(commit_time)
+            .unwrap_or_default();
+        self.lag = Some(lag);
+    }
+}
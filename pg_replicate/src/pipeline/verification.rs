@@ -0,0 +1,446 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use tokio_postgres::types::Type;
+use tracing::info;
+
+use crate::{
+    pipeline::{
+        sinks::{ChecksumSink, RowCountSink},
+        sources::{ChecksumSource, Source},
+        PipelineError,
+    },
+    table::{TableId, TableName, TableSchema},
+};
+
+/// Row counts of a single table in the source and in the sink
+#[derive(Debug, Clone)]
+pub struct TableRowCounts {
+    pub table_id: TableId,
+    pub table_name: TableName,
+    pub source_rows: u64,
+    /// `None` if the table is missing from the sink
+    pub sink_rows: Option<u64>,
+}
+
+impl TableRowCounts {
+    /// Number of rows the sink has more (positive) or less (negative)
+    /// than the source. A missing sink table counts as zero rows.
+    pub fn drift(&self) -> i64 {
+        self.sink_rows.unwrap_or(0) as i64 - self.source_rows as i64
+    }
+
+    pub fn in_sync(&self) -> bool {
+        self.sink_rows == Some(self.source_rows)
+    }
+}
+
+/// Result of comparing the row counts of all tables of a source with a sink
+#[derive(Debug, Default)]
+pub struct DriftReport {
+    pub tables: Vec<TableRowCounts>,
+}
+
+impl DriftReport {
+    pub fn has_drift(&self) -> bool {
+        self.tables.iter().any(|t| !t.in_sync())
+    }
+
+    pub fn drifted_tables(&self) -> impl Iterator<Item = &TableRowCounts> {
+        self.tables.iter().filter(|t| !t.in_sync())
+    }
+}
+
+impl Display for DriftReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for table in &self.tables {
+            match table.sink_rows {
+                Some(sink_rows) => writeln!(
+                    f,
+                    "{}: source rows = {}, sink rows = {}, drift = {}",
+                    table.table_name,
+                    table.source_rows,
+                    sink_rows,
+                    table.drift()
+                )?,
+                None => writeln!(
+                    f,
+                    "{}: source rows = {}, missing in sink",
+                    table.table_name, table.source_rows
+                )?,
+            }
+        }
+        let drifted = self.drifted_tables().count();
+        write!(f, "{drifted} of {} tables drifted", self.tables.len())
+    }
+}
+
+/// Compares row counts of the source tables with their destinations in a sink.
+///
+/// Source counts are read in the source's snapshot while the sink is read at
+/// its latest state, so the verification is only meaningful when no changes
+/// are being replicated, e.g. after the table copies have finished or after
+/// the source has been quiesced.
+pub struct RowCountVerifier<Src: Source, Snk: RowCountSink> {
+    source: Src,
+    sink: Snk,
+}
+
+impl<Src: Source, Snk: RowCountSink> RowCountVerifier<Src, Snk> {
+    pub fn new(source: Src, sink: Snk) -> Self {
+        RowCountVerifier { source, sink }
+    }
+
+    pub async fn verify(&mut self) -> Result<DriftReport, PipelineError<Src::Error, Snk::Error>> {
+        let table_schemas = self.source.get_table_schemas();
+
+        let mut keys: Vec<TableId> = table_schemas.keys().copied().collect();
+        keys.sort();
+
+        let mut report = DriftReport::default();
+        for key in keys {
+            let table_schema = table_schemas.get(&key).expect("failed to get table key");
+
+            let source_rows = self
+                .source
+                .count_table_rows(&table_schema.table_name)
+                .await
+                .map_err(PipelineError::Source)?;
+
+            let sink_rows = self
+                .sink
+                .count_table_rows(table_schema)
+                .await
+                .map_err(PipelineError::Sink)?;
+
+            let table_row_counts = TableRowCounts {
+                table_id: table_schema.table_id,
+                table_name: table_schema.table_name.clone(),
+                source_rows,
+                sink_rows,
+            };
+
+            if !table_row_counts.in_sync() {
+                info!(
+                    "table {} drifted: source rows = {}, sink rows = {:?}",
+                    table_row_counts.table_name,
+                    table_row_counts.source_rows,
+                    table_row_counts.sink_rows
+                );
+            }
+
+            report.tables.push(table_row_counts);
+        }
+
+        Ok(report)
+    }
+}
+
+/// What the values of a column are hashed as. Source and sink render a
+/// value the same way for the row checksums to match, so only types whose
+/// values both render identically are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashedValue {
+    /// The value as text, for text, integer, boolean and uuid columns
+    Text,
+    /// Days since the Unix epoch
+    Date,
+    /// Microseconds since the Unix epoch
+    Timestamp,
+    /// Lowercase hex digits
+    Bytes,
+}
+
+impl HashedValue {
+    /// Returns how values of type `typ` are hashed, none for types which
+    /// aren't hashed, like floats, numerics, json, times and arrays
+    pub fn of(typ: &Type) -> Option<HashedValue> {
+        match *typ {
+            Type::BOOL
+            | Type::INT2
+            | Type::INT4
+            | Type::INT8
+            | Type::TEXT
+            | Type::VARCHAR
+            | Type::NAME
+            | Type::UUID => Some(HashedValue::Text),
+            Type::DATE => Some(HashedValue::Date),
+            Type::TIMESTAMP | Type::TIMESTAMPTZ => Some(HashedValue::Timestamp),
+            Type::BYTEA => Some(HashedValue::Bytes),
+            _ => None,
+        }
+    }
+}
+
+/// How the rows of a table are split into ranges and hashed, the same way in
+/// the source and in the sink.
+///
+/// A row's hash is the first 32 bits of the md5 of its hashed values joined
+/// by `|`, with `\N` for nulls. The checksum of a range is the sum of the
+/// hashes of its rows, which doesn't depend on their order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumSpec {
+    /// Integer column whose values split the table into ranges of
+    /// `range_size` values, the first column of the primary key if it's an
+    /// integer. Tables without one are a single range.
+    pub key_column: Option<String>,
+    pub range_size: i64,
+    /// Hashed columns, in the table's order
+    pub columns: Vec<(String, HashedValue)>,
+    /// Columns left out of the hashes, as their types aren't hashed or as
+    /// their values are converted before being written to the sink
+    pub unhashed_columns: Vec<String>,
+}
+
+impl ChecksumSpec {
+    pub fn new(table_schema: &TableSchema, range_size: i64) -> ChecksumSpec {
+        let key_column = table_schema
+            .column_schemas
+            .iter()
+            .find(|column_schema| column_schema.primary)
+            .filter(|column_schema| {
+                matches!(column_schema.typ, Type::INT2 | Type::INT4 | Type::INT8)
+                    && !table_schema
+                        .type_overrides
+                        .contains_key(&column_schema.name)
+            })
+            .map(|column_schema| column_schema.name.clone());
+        let mut columns = vec![];
+        let mut unhashed_columns = vec![];
+        for column_schema in &table_schema.column_schemas {
+            // the column of commit timestamps isn't in the source
+            if table_schema.commit_timestamp_column.as_ref() == Some(&column_schema.name) {
+                continue;
+            }
+            // values converted on their way to the sink don't match the
+            // source's anymore
+            let converted = table_schema
+                .type_overrides
+                .contains_key(&column_schema.name)
+                || table_schema
+                    .large_object_columns
+                    .contains(&column_schema.name)
+                || table_schema
+                    .local_timestamp_columns
+                    .contains(&column_schema.name);
+            let hashed_value = HashedValue::of(&column_schema.typ).filter(|_| !converted);
+            match hashed_value {
+                Some(hashed_value) => columns.push((column_schema.name.clone(), hashed_value)),
+                None => unhashed_columns.push(column_schema.name.clone()),
+            }
+        }
+        ChecksumSpec {
+            key_column,
+            range_size: range_size.max(1),
+            columns,
+            unhashed_columns,
+        }
+    }
+
+    /// Returns the first key of a range, none for tables without ranges
+    pub fn range_start(&self, range: i64) -> Option<i64> {
+        self.key_column
+            .as_ref()
+            .map(|_| range.saturating_mul(self.range_size))
+    }
+}
+
+/// Row count and checksum of the rows of a table in a range of its keys.
+/// Keys from `range * range_size` up to the next range's are in `range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeChecksum {
+    pub range: i64,
+    pub rows: u64,
+    pub checksum: u64,
+}
+
+/// A range of keys whose rows differ between the source and the sink
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeMismatch {
+    pub range: i64,
+    pub source_rows: u64,
+    pub sink_rows: u64,
+}
+
+/// Result of comparing the checksums of a table's ranges
+#[derive(Debug, Clone)]
+pub struct TableChecksums {
+    pub table_id: TableId,
+    pub table_name: TableName,
+    pub spec: ChecksumSpec,
+    /// Number of ranges with rows in the source or in the sink
+    pub ranges: usize,
+    /// `None` if the table is missing from the sink
+    pub mismatches: Option<Vec<RangeMismatch>>,
+}
+
+impl TableChecksums {
+    pub fn in_sync(&self) -> bool {
+        self.mismatches
+            .as_ref()
+            .is_some_and(|mismatches| mismatches.is_empty())
+    }
+}
+
+/// Result of comparing the checksums of all tables of a source with a sink
+#[derive(Debug, Default)]
+pub struct ChecksumReport {
+    pub tables: Vec<TableChecksums>,
+}
+
+impl ChecksumReport {
+    pub fn has_mismatches(&self) -> bool {
+        self.tables.iter().any(|t| !t.in_sync())
+    }
+}
+
+impl Display for ChecksumReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for table in &self.tables {
+            let Some(mismatches) = &table.mismatches else {
+                writeln!(f, "{}: missing in sink", table.table_name)?;
+                continue;
+            };
+            writeln!(
+                f,
+                "{}: {} of {} ranges differ",
+                table.table_name,
+                mismatches.len(),
+                table.ranges
+            )?;
+            for mismatch in mismatches {
+                match (
+                    &table.spec.key_column,
+                    table.spec.range_start(mismatch.range),
+                ) {
+                    (Some(key_column), Some(start)) => write!(
+                        f,
+                        "  {key_column} from {start} to {}",
+                        start.saturating_add(table.spec.range_size)
+                    )?,
+                    _ => write!(f, "  all rows")?,
+                }
+                writeln!(
+                    f,
+                    ": source rows = {}, sink rows = {}",
+                    mismatch.source_rows, mismatch.sink_rows
+                )?;
+            }
+            if !table.spec.unhashed_columns.is_empty() {
+                writeln!(
+                    f,
+                    "  not compared: {}",
+                    table.spec.unhashed_columns.join(", ")
+                )?;
+            }
+        }
+        let mismatched = self.tables.iter().filter(|t| !t.in_sync()).count();
+        write!(f, "{mismatched} of {} tables differ", self.tables.len())
+    }
+}
+
+/// Compares the checksums of ranges of the keys of the source tables with
+/// their destinations in a sink, to find the ranges which differ and copy
+/// only those again.
+///
+/// Like [`RowCountVerifier`], the source is read in its snapshot and the
+/// sink at its latest state, so the verification is only meaningful when
+/// no changes are being replicated.
+pub struct ChecksumVerifier<Src: ChecksumSource, Snk: ChecksumSink> {
+    source: Src,
+    sink: Snk,
+    range_size: i64,
+}
+
+impl<Src: ChecksumSource, Snk: ChecksumSink> ChecksumVerifier<Src, Snk> {
+    pub fn new(source: Src, sink: Snk, range_size: i64) -> Self {
+        ChecksumVerifier {
+            source,
+            sink,
+            range_size,
+        }
+    }
+
+    pub async fn verify(
+        &mut self,
+    ) -> Result<ChecksumReport, PipelineError<Src::Error, Snk::Error>> {
+        let table_schemas = self.source.get_table_schemas();
+
+        let mut keys: Vec<TableId> = table_schemas.keys().copied().collect();
+        keys.sort();
+
+        let mut report = ChecksumReport::default();
+        for key in keys {
+            let table_schema = table_schemas.get(&key).expect("failed to get table key");
+            let spec = ChecksumSpec::new(table_schema, self.range_size);
+
+            let source_ranges = self
+                .source
+                .checksum_ranges(&table_schema.table_name, &spec)
+                .await
+                .map_err(PipelineError::Source)?;
+            let sink_ranges = self
+                .sink
+                .checksum_ranges(table_schema, &spec)
+                .await
+                .map_err(PipelineError::Sink)?;
+
+            let (ranges, mismatches) = match sink_ranges {
+                Some(sink_ranges) => {
+                    let (ranges, mismatches) = compare_ranges(&source_ranges, &sink_ranges);
+                    (ranges, Some(mismatches))
+                }
+                None => (source_ranges.len(), None),
+            };
+            let table_checksums = TableChecksums {
+                table_id: table_schema.table_id,
+                table_name: table_schema.table_name.clone(),
+                spec,
+                ranges,
+                mismatches,
+            };
+
+            if !table_checksums.in_sync() {
+                info!(
+                    "table {} differs in {} ranges",
+                    table_checksums.table_name,
+                    table_checksums
+                        .mismatches
+                        .as_ref()
+                        .map_or(ranges, |mismatches| mismatches.len())
+                );
+            }
+
+            report.tables.push(table_checksums);
+        }
+
+        Ok(report)
+    }
+}
+
+/// Returns the number of ranges with rows in the source or in the sink and
+/// the ranges which differ
+fn compare_ranges(
+    source_ranges: &[RangeChecksum],
+    sink_ranges: &[RangeChecksum],
+) -> (usize, Vec<RangeMismatch>) {
+    let mut ranges: BTreeMap<i64, (Option<RangeChecksum>, Option<RangeChecksum>)> = BTreeMap::new();
+    for range in source_ranges {
+        ranges.entry(range.range).or_default().0 = Some(*range);
+    }
+    for range in sink_ranges {
+        ranges.entry(range.range).or_default().1 = Some(*range);
+    }
+
+    let mismatches = ranges
+        .iter()
+        .filter(|(_, (source, sink))| {
+            source.map(|r| (r.rows, r.checksum)) != sink.map(|r| (r.rows, r.checksum))
+        })
+        .map(|(range, (source, sink))| RangeMismatch {
+            range: *range,
+            source_rows: source.map_or(0, |r| r.rows),
+            sink_rows: sink.map_or(0, |r| r.rows),
+        })
+        .collect();
+    (ranges.len(), mismatches)
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
 use pg_escape::quote_identifier;
 use tokio_postgres::types::Type;
@@ -34,6 +34,64 @@ pub struct ColumnSchema {
     pub primary: bool,
 }
 
+/// A type a column is replicated as instead of its type in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TypeOverride {
+    /// JSON held in a text column, replicated as `jsonb`
+    Json,
+
+    /// Replicated as text, with the text Postgres sends for the column
+    Text,
+
+    /// Integer seconds since the Unix epoch, replicated as `timestamptz`
+    EpochSeconds,
+
+    /// Integer milliseconds since the Unix epoch, replicated as
+    /// `timestamptz`
+    EpochMillis,
+
+    /// Integer microseconds since the Unix epoch, replicated as
+    /// `timestamptz`
+    EpochMicros,
+}
+
+impl TypeOverride {
+    /// Returns the type the column is replicated as
+    pub fn typ(&self) -> Type {
+        match self {
+            TypeOverride::Json => Type::JSONB,
+            TypeOverride::Text => Type::TEXT,
+            TypeOverride::EpochSeconds | TypeOverride::EpochMillis | TypeOverride::EpochMicros => {
+                Type::TIMESTAMPTZ
+            }
+        }
+    }
+
+    /// Returns the type the column's values are parsed as before being
+    /// converted to [`TypeOverride::typ`]
+    pub fn parsed_typ(&self) -> Type {
+        match self {
+            TypeOverride::Json | TypeOverride::Text => self.typ(),
+            TypeOverride::EpochSeconds | TypeOverride::EpochMillis | TypeOverride::EpochMicros => {
+                Type::INT8
+            }
+        }
+    }
+
+    /// Returns whether columns of type `typ` can be overridden
+    pub fn applies_to(&self, typ: &Type) -> bool {
+        match self {
+            TypeOverride::Json => {
+                matches!(*typ, Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::JSON)
+            }
+            TypeOverride::Text => true,
+            TypeOverride::EpochSeconds | TypeOverride::EpochMillis | TypeOverride::EpochMicros => {
+                matches!(*typ, Type::INT2 | Type::INT4 | Type::INT8)
+            }
+        }
+    }
+}
+
 pub type TableId = u32;
 
 #[derive(Debug, Clone)]
@@ -41,6 +99,21 @@ pub struct TableSchema {
     pub table_name: TableName,
     pub table_id: TableId,
     pub column_schemas: Vec<ColumnSchema>,
+    /// Columns of the table which aren't replicated, like generated
+    /// columns. Their values are dropped from the changes of the table.
+    pub skipped_columns: Vec<String>,
+    /// Columns of type `timestamp` replicated as `timestamptz`, whose values
+    /// are local times of the source's time zone
+    pub local_timestamp_columns: Vec<String>,
+    /// Columns holding the oids of large objects, replicated as the
+    /// objects' contents or as pointers to them
+    pub large_object_columns: Vec<String>,
+    /// Columns replicated as another type than their source type, by name
+    pub type_overrides: HashMap<String, TypeOverride>,
+    /// Column added after the table's columns, which holds the commit
+    /// timestamp of the transaction of each change and is null in copied
+    /// rows. It isn't read from the source.
+    pub commit_timestamp_column: Option<String>,
 }
 
 impl TableSchema {
@@ -0,0 +1,259 @@
+//! A harness for integration tests of pipelines, which starts Postgres in a
+//! Docker container, changes the rows of its tables and runs pipelines
+//! writing to a [`MemorySink`], so that tests can assert on what they wrote.
+//! Docker must be running.
+//!
+//! ```no_run
+//! # async fn test() -> Result<(), pg_replicate::testing::TestingError> {
+//! use std::time::Duration;
+//!
+//! use pg_replicate::testing::TestDatabase;
+//!
+//! let database = TestDatabase::start().await?;
+//! database
+//!     .execute("create table users (id bigint primary key, name text)")
+//!     .await?;
+//! database
+//!     .create_publication("users_publication", &["public.users"])
+//!     .await?;
+//! let mut pipeline = database
+//!     .start_pipeline("users_slot", "users_publication")
+//!     .await?;
+//! database
+//!     .execute("insert into users values (1, 'alice')")
+//!     .await?;
+//! pipeline
+//!     .wait_until(Duration::from_secs(10), |contents| contents.changes.len() == 1)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    sync::MutexGuard,
+    time::{Duration, Instant},
+};
+
+use pg_escape::quote_identifier;
+use testcontainers_modules::{
+    postgres::Postgres,
+    testcontainers::{runners::AsyncRunner, ContainerAsync, ImageExt, TestcontainersError},
+};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio_postgres::{Client, NoTls};
+use tracing::warn;
+
+use crate::{
+    clients::postgres::{ColumnOptions, ConnectionOptions},
+    pipeline::{
+        batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+        sinks::memory::{MemorySink, MemorySinkContents},
+        sources::postgres::{PostgresSource, PostgresSourceError, TableNamesFrom},
+        PipelineAction,
+    },
+};
+
+const DATABASE: &str = "postgres";
+const USERNAME: &str = "postgres";
+const PASSWORD: &str = "postgres";
+
+/// Interval at which [`TestPipeline::wait_until`] checks its condition
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Error)]
+pub enum TestingError {
+    #[error("testcontainers error: {0}")]
+    Testcontainers(#[from] TestcontainersError),
+
+    #[error("tokio_postgres error: {0}")]
+    TokioPostgres(#[from] tokio_postgres::Error),
+
+    #[error("source error: {0}")]
+    Source(#[from] PostgresSourceError),
+
+    #[error("the pipeline stopped: {0}")]
+    PipelineStopped(String),
+
+    #[error("the condition wasn't met within {0:?}")]
+    Timeout(Duration),
+}
+
+/// A Postgres 16 database with `wal_level = logical` in a Docker container,
+/// which is removed when the database is dropped
+pub struct TestDatabase {
+    _container: ContainerAsync<Postgres>,
+    client: Client,
+    host: String,
+    port: u16,
+}
+
+impl TestDatabase {
+    /// Starts the container and connects to the database
+    pub async fn start() -> Result<TestDatabase, TestingError> {
+        let container = Postgres::default()
+            .with_tag("16-alpine")
+            .with_cmd(["postgres", "-c", "wal_level=logical"])
+            .start()
+            .await?;
+        let host = container.get_host().await?.to_string();
+        let port = container.get_host_port_ipv4(5432).await?;
+
+        let (client, connection) = tokio_postgres::Config::new()
+            .host(&host)
+            .port(port)
+            .dbname(DATABASE)
+            .user(USERNAME)
+            .password(PASSWORD)
+            .connect(NoTls)
+            .await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("test database connection error: {e}");
+            }
+        });
+
+        Ok(TestDatabase {
+            _container: container,
+            client,
+            host,
+            port,
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Runs statements separated by semicolons, e.g. to create tables or to
+    /// change their rows
+    pub async fn execute(&self, statements: &str) -> Result<(), TestingError> {
+        self.client.batch_execute(statements).await?;
+        Ok(())
+    }
+
+    /// Creates a publication of the tables, named like `public.users`
+    pub async fn create_publication(
+        &self,
+        publication: &str,
+        tables: &[&str],
+    ) -> Result<(), TestingError> {
+        let tables: Vec<String> = tables
+            .iter()
+            .map(|table| {
+                table
+                    .split('.')
+                    .map(quote_identifier)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .collect();
+        let statement = format!(
+            "create publication {} for table {}",
+            quote_identifier(publication),
+            tables.join(", ")
+        );
+        self.execute(&statement).await
+    }
+
+    /// Connects a source replicating the publication's tables over the
+    /// slot, which is created if it doesn't exist
+    pub async fn source(
+        &self,
+        slot_name: &str,
+        publication: &str,
+    ) -> Result<PostgresSource, TestingError> {
+        let source = PostgresSource::new(
+            &self.host,
+            self.port,
+            DATABASE,
+            USERNAME,
+            Some(PASSWORD.to_string()),
+            Some(slot_name.to_string()),
+            TableNamesFrom::Publication(publication.to_string()),
+            ColumnOptions::default(),
+            ConnectionOptions::default(),
+        )
+        .await?;
+        Ok(source)
+    }
+
+    /// Starts a pipeline in a task which copies the publication's tables to
+    /// a [`MemorySink`] and then streams their changes to it. The slot is
+    /// created before this returns, so the changes made after it are
+    /// streamed.
+    pub async fn start_pipeline(
+        &self,
+        slot_name: &str,
+        publication: &str,
+    ) -> Result<TestPipeline, TestingError> {
+        let source = self.source(slot_name, publication).await?;
+        let sink = MemorySink::new();
+        // small batches written right away keep the tests fast
+        let batch_config = BatchConfig::new(100, Duration::from_millis(100));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink.clone(), PipelineAction::Both, batch_config);
+        let task = tokio::spawn(async move { pipeline.start().await.map_err(|e| e.to_string()) });
+        Ok(TestPipeline {
+            sink,
+            task,
+            stopped: None,
+        })
+    }
+}
+
+/// A pipeline running in a task, which is stopped when it's dropped
+pub struct TestPipeline {
+    sink: MemorySink,
+    task: JoinHandle<Result<(), String>>,
+    /// Why the pipeline stopped, once it's known
+    stopped: Option<String>,
+}
+
+impl TestPipeline {
+    /// What the pipeline wrote so far
+    pub fn contents(&self) -> MutexGuard<'_, MemorySinkContents> {
+        self.sink.contents()
+    }
+
+    /// Waits until what the pipeline wrote meets the condition. Fails if it
+    /// isn't met within the timeout or if the pipeline stops
+    pub async fn wait_until(
+        &mut self,
+        timeout: Duration,
+        condition: impl Fn(&MemorySinkContents) -> bool,
+    ) -> Result<(), TestingError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if condition(&self.contents()) {
+                return Ok(());
+            }
+            if let Some(stopped) = &self.stopped {
+                return Err(TestingError::PipelineStopped(stopped.clone()));
+            }
+            if self.task.is_finished() {
+                let stopped = match (&mut self.task).await {
+                    Ok(Ok(())) => "the source ended".to_string(),
+                    Ok(Err(e)) => e,
+                    Err(e) => e.to_string(),
+                };
+                self.stopped = Some(stopped.clone());
+                return Err(TestingError::PipelineStopped(stopped));
+            }
+            if Instant::now() >= deadline {
+                return Err(TestingError::Timeout(timeout));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for TestPipeline {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
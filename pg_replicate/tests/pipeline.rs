@@ -0,0 +1,72 @@
+//! Pipelines replicating from Postgres in Docker, driven by the harness of
+//! the `testing` feature. Docker must be running.
+
+use std::time::Duration;
+
+use pg_replicate::{
+    conversions::json::cell_to_json,
+    pipeline::sinks::memory::MemorySinkContents,
+    testing::{TestDatabase, TestingError},
+};
+use serde_json::{json, Value};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Rows of the table as json, ordered by their first column
+fn table_rows(contents: &MemorySinkContents, table_name: &str) -> Vec<Value> {
+    let Some(table_id) = contents.table_id(table_name) else {
+        return vec![];
+    };
+    let mut rows: Vec<Value> = contents
+        .table_rows(table_id)
+        .iter()
+        .map(|values| Value::Array(values.iter().map(cell_to_json).collect()))
+        .collect();
+    rows.sort_by_key(|row| row[0].as_i64());
+    rows
+}
+
+#[tokio::test]
+async fn copied_rows_and_changes_reach_the_sink() -> Result<(), TestingError> {
+    // Arrange
+    let database = TestDatabase::start().await?;
+    database
+        .execute(
+            "create table users (id bigint primary key, name text);
+            insert into users values (1, 'alice'), (2, 'bob');",
+        )
+        .await?;
+    database
+        .create_publication("users_publication", &["public.users"])
+        .await?;
+    let mut pipeline = database
+        .start_pipeline("users_slot", "users_publication")
+        .await?;
+    pipeline
+        .wait_until(TIMEOUT, |contents| {
+            table_rows(contents, "public.users").len() == 2
+        })
+        .await?;
+
+    // Act
+    database
+        .execute(
+            "insert into users values (3, 'carol');
+            update users set name = 'robert' where id = 2;
+            delete from users where id = 1;",
+        )
+        .await?;
+
+    // Assert
+    pipeline
+        .wait_until(TIMEOUT, |contents| contents.changes.len() == 3)
+        .await?;
+    let contents = pipeline.contents();
+    assert_eq!(
+        table_rows(&contents, "public.users"),
+        [json!([2, "robert"]), json!([3, "carol"])]
+    );
+    assert_eq!(contents.commits.len(), 1);
+
+    Ok(())
+}
@@ -1,4 +1,20 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug};
+
+use pg_replicate::{
+    clients::{
+        bigquery::{BigQueryType, RetryConfig, TableOptions, TablePartitioning},
+        postgres::{ColumnOptions, ConnectionOptions},
+    },
+    pipeline::{
+        classification::ClassificationOptions,
+        encryption::EncryptionOptions,
+        sinks::{
+            bigquery::{CompactionOptions, NamingOptions, RemovedColumnPolicy},
+            RemovedTablePolicy, WriteMode,
+        },
+        sources::postgres::TableFilter,
+    },
+};
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub enum SourceSettings {
@@ -23,6 +39,23 @@ pub enum SourceSettings {
 
         /// Postgres publication name
         publication: String,
+
+        /// Tables of the publication to replicate. All of them are
+        /// replicated if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tables: Option<TableFilter>,
+
+        /// How generated, identity and serial columns, columns of
+        /// unsupported types, timestamps and large objects are replicated.
+        /// Generated columns are skipped, columns of unsupported types cast
+        /// to text and the others replicated as they are if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        columns: Option<ColumnOptions>,
+
+        /// Application name, session parameters and proxy of the
+        /// connections to the source
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        connection: Option<ConnectionOptions>,
     },
 }
 
@@ -37,6 +70,9 @@ impl Debug for SourceSettings {
                 password: _,
                 slot_name,
                 publication,
+                tables,
+                columns,
+                connection,
             } => f
                 .debug_struct("Postgres")
                 .field("host", host)
@@ -46,6 +82,9 @@ impl Debug for SourceSettings {
                 .field("password", &"REDACTED")
                 .field("slot_name", slot_name)
                 .field("publication", publication)
+                .field("tables", tables)
+                .field("columns", columns)
+                .field("connection", connection)
                 .finish(),
         }
     }
@@ -60,8 +99,72 @@ pub enum SinkSettings {
         /// BigQuery dataset id
         dataset_id: String,
 
-        /// BigQuery service account key
-        service_account_key: String,
+        /// BigQuery service account key. If not set Application Default
+        /// Credentials are used, e.g. workload identity on GKE
+        service_account_key: Option<String>,
+
+        /// Location used when creating missing datasets
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dataset_location: Option<String>,
+
+        /// Labels attached to datasets created by the replicator
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        dataset_labels: HashMap<String, String>,
+
+        /// Maps Postgres schema names to BigQuery dataset ids. Tables in
+        /// unmapped schemas are written to `dataset_id`
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        schema_datasets: HashMap<String, String>,
+
+        /// Options of tables, keyed by the Postgres table name in
+        /// `schema.name` form
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        table_options: HashMap<String, TableOptions>,
+
+        /// Options of tables without an entry in `table_options`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default_table_options: Option<TableOptions>,
+
+        /// Prefix, suffix and explicit names of replicated tables
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        naming: Option<NamingOptions>,
+
+        /// Periodic compaction of change logs into snapshot tables. Changes
+        /// are applied directly to the tables if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        compaction: Option<CompactionOptions>,
+
+        /// Retries of requests failing with retryable errors. Defaults
+        /// are used if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry: Option<RetryConfig>,
+
+        /// What to do with columns removed from source tables. Removed
+        /// columns are kept if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        removed_column_policy: Option<RemovedColumnPolicy>,
+
+        /// What to do with the tables of tables which are no longer
+        /// replicated, like tables removed from the publication, when the
+        /// replicator starts. They are kept if not set. Can't be set for a
+        /// sharded replicator, whose workers replicate some of the tables
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        removed_table_policy: Option<RemovedTablePolicy>,
+
+        /// Whether changes are applied to the tables or appended to change
+        /// logs. Changes are applied if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        write_mode: Option<WriteMode>,
+
+        /// How many tables' rows are appended at the same time. Defaults
+        /// to four if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_concurrent_streams: Option<usize>,
+
+        /// BigQuery types of the columns of Postgres types like `uuid`,
+        /// keyed by the types' names, replacing their default types
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        type_mappings: HashMap<String, BigQueryType>,
     },
 }
 
@@ -72,11 +175,37 @@ impl Debug for SinkSettings {
                 project_id,
                 dataset_id,
                 service_account_key: _,
+                dataset_location,
+                dataset_labels,
+                schema_datasets,
+                table_options,
+                default_table_options,
+                naming,
+                compaction,
+                retry,
+                removed_column_policy,
+                removed_table_policy,
+                write_mode,
+                max_concurrent_streams,
+                type_mappings,
             } => f
                 .debug_struct("BigQuery")
                 .field("project_id", project_id)
                 .field("dataset_id", dataset_id)
                 .field("service_account_key", &"REDACTED")
+                .field("dataset_location", dataset_location)
+                .field("dataset_labels", dataset_labels)
+                .field("schema_datasets", schema_datasets)
+                .field("table_options", table_options)
+                .field("default_table_options", default_table_options)
+                .field("naming", naming)
+                .field("compaction", compaction)
+                .field("retry", retry)
+                .field("removed_column_policy", removed_column_policy)
+                .field("removed_table_policy", removed_table_policy)
+                .field("write_mode", write_mode)
+                .field("max_concurrent_streams", max_concurrent_streams)
+                .field("type_mappings", type_mappings)
                 .finish(),
         }
     }
@@ -89,6 +218,56 @@ pub struct BatchSettings {
 
     /// maximum duration, in seconds, to wait for a batch to fill
     pub max_fill_secs: u64,
+
+    /// whether batches end only on the commits of transactions, so that a
+    /// transaction is never split across batches
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub transactional: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct StatusReportSettings {
+    /// Base url of the api the pipeline's status is reported to
+    pub api_url: String,
+
+    /// Key authenticating with the api
+    pub api_key: Option<String>,
+
+    /// Id of the tenant owning the pipeline
+    pub tenant_id: String,
+
+    /// Id of the pipeline in the api
+    pub pipeline_id: i64,
+
+    /// Seconds between reports
+    #[serde(default = "default_report_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Seconds between queries of the source's WAL position, which the
+    /// reported lag is computed from
+    #[serde(default = "default_wal_poll_secs")]
+    pub wal_poll_secs: u64,
+}
+
+fn default_report_interval_secs() -> u64 {
+    10
+}
+
+fn default_wal_poll_secs() -> u64 {
+    10
+}
+
+impl Debug for StatusReportSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatusReportSettings")
+            .field("api_url", &self.api_url)
+            .field("api_key", &"REDACTED")
+            .field("tenant_id", &self.tenant_id)
+            .field("pipeline_id", &self.pipeline_id)
+            .field("interval_secs", &self.interval_secs)
+            .field("wal_poll_secs", &self.wal_poll_secs)
+            .finish()
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -96,8 +275,155 @@ pub struct Settings {
     pub source: SourceSettings,
     pub sink: SinkSettings,
     pub batch: BatchSettings,
+
+    /// Periodic reports of the pipeline's status to the api. The status
+    /// is not reported if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_report: Option<StatusReportSettings>,
+
+    /// Splits the publication's tables between several replicators. All
+    /// tables are replicated by this replicator if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharding: Option<ShardingSettings>,
+
+    /// Runs this replicator as one of a group replicating the same slot, of
+    /// which only the leader replicates while the others stand by. Each
+    /// replicator replicates on its own if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high_availability: Option<HighAvailabilitySettings>,
+
+    /// Reports the error stopping the pipeline and the sink's failed
+    /// requests to Sentry. Needs the replicator to be built with the
+    /// `sentry` feature. Errors are only logged if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_reporting: Option<ErrorReportingSettings>,
+
+    /// Columns encrypted before their values are written to the sink.
+    /// Values are written as they are if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionOptions>,
+
+    /// Classification of the columns likely holding personal data when the
+    /// pipeline is started, whose results are logged. Columns aren't
+    /// classified if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classification: Option<ClassificationOptions>,
+
+    /// Settings of tables replacing the pipeline's, keyed by the Postgres
+    /// table name in `schema.name` form
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub table_overrides: HashMap<String, TableOverrides>,
+}
+
+/// Settings of a table replacing the pipeline's. Partitioning and
+/// clustering are only used when creating a missing table
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableOverrides {
+    /// Name of the BigQuery table, without the prefix and suffix, instead
+    /// of the name given by the naming options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+
+    /// Whether the table's changes are applied to it or appended to its
+    /// change log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_mode: Option<WriteMode>,
+
+    /// Partitioning of the table, replacing the one of its table options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<TablePartitioning>,
+
+    /// Columns to cluster the table by, replacing the ones of its table
+    /// options
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clustering: Vec<String>,
+
+    /// Columns of the table encrypted deterministically with the key of the
+    /// encryption settings, which must be set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked_columns: Vec<String>,
+
+    /// Batches of the table's copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<TableBatchSettings>,
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableBatchSettings {
+    /// maximum batch size in number of rows
+    pub max_size: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct ErrorReportingSettings {
+    /// Dsn of the Sentry project the errors are reported to
+    pub sentry_dsn: String,
+
+    /// Environment of the reported errors, e.g. `production`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+/// Leader election between replicators with the same settings. The leader
+/// holds an advisory lock in the source database named after the slot,
+/// which Postgres releases when the leader's connection closes. A standby
+/// acquiring the lock replicates next.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct HighAvailabilitySettings {
+    /// Seconds between a standby's attempts to acquire the lock, and between
+    /// the leader's checks that it still holds it
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+}
+
+fn default_retry_secs() -> u64 {
+    2
+}
+
+/// Replicating a publication with several replicators, the workers, each
+/// claiming a share of its tables. Claims are leases kept in the source
+/// database, in the `pg_replicate.table_leases` table.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct ShardingSettings {
+    /// Index of this worker, from 0 to `workers - 1`, e.g. the ordinal of a
+    /// StatefulSet's pod. Each worker streams changes over its own slot,
+    /// named `<slot_name>_<worker>`
+    pub worker: u32,
+
+    /// Number of workers, each claiming an even share of the tables
+    pub workers: u32,
+
+    /// Maximum number of tables claimed by this worker, its even share if
+    /// not set. A higher maximum lets the worker take over the tables of
+    /// stopped workers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tables: Option<usize>,
+
+    /// Seconds a claim lasts unless renewed. Claims are renewed three
+    /// times per lease and tables of a stopped worker can be claimed by
+    /// others once its claims expired
+    #[serde(default = "default_lease_secs")]
+    pub lease_secs: u64,
+}
+
+fn default_lease_secs() -> u64 {
+    60
+}
+
+/// Suffix of environment variables naming a file the setting is read from,
+/// e.g. a mounted Docker or Kubernetes secret
+const SECRET_FILE_SUFFIX: &str = "_FILE";
+
+/// Loads the settings from, in increasing order of precedence:
+///
+/// 1. `configuration/base.yaml`
+/// 2. `configuration/{APP_ENVIRONMENT}.yaml`
+/// 3. `APP_` environment variables, e.g. `APP_SOURCE__POSTGRES__HOST`
+/// 4. files named by `APP_` environment variables ending in `_FILE`, e.g.
+///    `APP_SOURCE__POSTGRES__PASSWORD_FILE=/run/secrets/db_password`
+///
+/// The configuration files are optional, so the replicator can be
+/// configured entirely from the environment.
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -110,23 +436,47 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .expect("Failed to parse APP_ENVIRONMENT.");
 
     let environment_filename = format!("{}.yaml", environment.as_str());
-    let settings = config::Config::builder()
-        .add_source(config::File::from(
-            configuration_directory.join("base.yaml"),
-        ))
-        .add_source(config::File::from(
-            configuration_directory.join(environment_filename),
-        ))
+    let mut builder = config::Config::builder()
+        .add_source(config::File::from(configuration_directory.join("base.yaml")).required(false))
+        .add_source(
+            config::File::from(configuration_directory.join(environment_filename)).required(false),
+        )
         // Add in settings from environment variables (with a prefix of APP and '__' as separator)
         // E.g. `APP_SINK__BIGQUERY__PROJECT_ID=my-project-id would set `Settings { sink: BigQuery { project_id }}` to my-project-id
         .add_source(
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
                 .separator("__"),
-        )
-        .build()?;
+        );
+    for (key, value) in secret_file_overrides(std::env::vars())? {
+        builder = builder.set_override(key, value)?;
+    }
 
-    settings.try_deserialize::<Settings>()
+    builder.build()?.try_deserialize::<Settings>()
+}
+
+/// Reads the files named by `APP_..._FILE` variables, returning the value of
+/// each keyed by the setting it overrides, e.g. `source.postgres.password`.
+/// Trailing newlines, which editors and `echo` add to secret files, are
+/// removed.
+fn secret_file_overrides(
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<(String, String)>, config::ConfigError> {
+    let mut overrides = vec![];
+    for (var, path) in vars {
+        let Some(setting) = var
+            .strip_prefix("APP_")
+            .and_then(|var| var.strip_suffix(SECRET_FILE_SUFFIX))
+        else {
+            continue;
+        };
+        let value = std::fs::read_to_string(&path).map_err(|e| {
+            config::ConfigError::Message(format!("failed to read {path} named by {var}: {e}"))
+        })?;
+        let key = setting.to_lowercase().replace("__", ".");
+        overrides.push((key, value.trim_end_matches(['\r', '\n']).to_string()));
+    }
+    Ok(overrides)
 }
 
 const DEV_ENV_NAME: &str = "dev";
@@ -163,7 +513,32 @@ impl TryFrom<String> for Environment {
 
 #[cfg(test)]
 mod tests {
-    use crate::{configuration::Settings, BatchSettings, SinkSettings, SourceSettings};
+    use std::collections::{BTreeMap, HashMap};
+
+    use pg_replicate::{
+        clients::{
+            bigquery::{BigQueryType, PartitionGranularity, TableOptions, TablePartitioning},
+            postgres::{
+                ColumnHandling, ColumnOptions, ConversionErrorPolicy, LargeObjectOptions,
+                Timestamps, UnknownTypes,
+            },
+        },
+        pipeline::{
+            sinks::{
+                bigquery::{CompactionOptions, NamingOptions},
+                NamingStrategy, WriteMode,
+            },
+            sources::postgres::TableFilter,
+        },
+    };
+
+    use crate::{
+        configuration::{
+            secret_file_overrides, ErrorReportingSettings, HighAvailabilitySettings, Settings,
+            ShardingSettings, StatusReportSettings, TableBatchSettings, TableOverrides,
+        },
+        BatchSettings, SinkSettings, SourceSettings,
+    };
 
     #[test]
     pub fn deserialize_settings_test() {
@@ -201,16 +576,40 @@ mod tests {
                 password: Some("postgres".to_string()),
                 slot_name: "replicator_slot".to_string(),
                 publication: "replicator_publication".to_string(),
+                tables: None,
+                columns: None,
+                connection: None,
             },
             sink: SinkSettings::BigQuery {
                 project_id: "project-id".to_string(),
                 dataset_id: "dataset-id".to_string(),
-                service_account_key: "key".to_string(),
+                service_account_key: Some("key".to_string()),
+                dataset_location: None,
+                dataset_labels: HashMap::new(),
+                schema_datasets: HashMap::new(),
+                table_options: HashMap::new(),
+                default_table_options: None,
+                naming: None,
+                compaction: None,
+                retry: None,
+                removed_column_policy: None,
+                removed_table_policy: None,
+                write_mode: None,
+                max_concurrent_streams: None,
+                type_mappings: HashMap::new(),
             },
             batch: BatchSettings {
                 max_size: 1000,
                 max_fill_secs: 10,
+                transactional: false,
             },
+            status_report: None,
+            sharding: None,
+            high_availability: None,
+            error_reporting: None,
+            encryption: None,
+            classification: None,
+            table_overrides: HashMap::new(),
         };
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
@@ -227,20 +626,538 @@ mod tests {
                 password: Some("postgres".to_string()),
                 slot_name: "replicator_slot".to_string(),
                 publication: "replicator_publication".to_string(),
+                tables: None,
+                columns: None,
+                connection: None,
             },
             sink: SinkSettings::BigQuery {
                 project_id: "project-id".to_string(),
                 dataset_id: "dataset-id".to_string(),
-                service_account_key: "key".to_string(),
+                service_account_key: Some("key".to_string()),
+                dataset_location: None,
+                dataset_labels: HashMap::new(),
+                schema_datasets: HashMap::new(),
+                table_options: HashMap::new(),
+                default_table_options: None,
+                naming: None,
+                compaction: None,
+                retry: None,
+                removed_column_policy: None,
+                removed_table_policy: None,
+                write_mode: None,
+                max_concurrent_streams: None,
+                type_mappings: HashMap::new(),
             },
             batch: BatchSettings {
                 max_size: 1000,
                 max_fill_secs: 10,
+                transactional: false,
             },
+            status_report: None,
+            sharding: None,
+            high_availability: None,
+            error_reporting: None,
+            encryption: None,
+            classification: None,
+            table_overrides: HashMap::new(),
         };
         let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","password":"postgres","slot_name":"replicator_slot","publication":"replicator_publication"}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id","service_account_key":"key"}},"batch":{"max_size":1000,"max_fill_secs":10}}"#;
         let actual = serde_json::to_string(&actual);
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
     }
+
+    #[test]
+    pub fn deserialize_bigquery_dataset_settings_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "service_account_key": "key",
+                "dataset_location": "EU",
+                "dataset_labels": {
+                    "team": "data"
+                },
+                "schema_datasets": {
+                    "public": "app_public"
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: Some("key".to_string()),
+            dataset_location: Some("EU".to_string()),
+            dataset_labels: HashMap::from([("team".to_string(), "data".to_string())]),
+            schema_datasets: HashMap::from([("public".to_string(), "app_public".to_string())]),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: None,
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: None,
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_table_options_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "service_account_key": "key",
+                "table_options": {
+                    "public.events": {
+                        "partitioning": {
+                            "Column": {
+                                "column": "created_at",
+                                "granularity": "day"
+                            }
+                        },
+                        "partition_expiration_days": 30,
+                        "clustering": ["id"],
+                        "description": "app events",
+                        "labels": {"team": "data"}
+                    }
+                },
+                "default_table_options": {
+                    "max_staleness_mins": 15
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: Some("key".to_string()),
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::from([(
+                "public.events".to_string(),
+                TableOptions {
+                    partitioning: Some(TablePartitioning::Column {
+                        column: "created_at".to_string(),
+                        granularity: PartitionGranularity::Day,
+                    }),
+                    partition_expiration_days: Some(30),
+                    clustering: vec!["id".to_string()],
+                    max_staleness_mins: None,
+                    description: Some("app events".to_string()),
+                    labels: HashMap::from([("team".to_string(), "data".to_string())]),
+                },
+            )]),
+            default_table_options: Some(TableOptions {
+                max_staleness_mins: Some(15),
+                ..Default::default()
+            }),
+            naming: None,
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: None,
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_postgres_tables_test() {
+        let source = r#"{
+            "Postgres": {
+                "host": "localhost",
+                "port": 5432,
+                "name": "postgres",
+                "username": "postgres",
+                "slot_name": "replicator_slot",
+                "publication": "replicator_publication",
+                "tables": {
+                    "include": ["public.*"],
+                    "exclude": ["public.audit_*"]
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SourceSettings>(source);
+        let expected = SourceSettings::Postgres {
+            host: "localhost".to_string(),
+            port: 5432,
+            name: "postgres".to_string(),
+            username: "postgres".to_string(),
+            password: None,
+            slot_name: "replicator_slot".to_string(),
+            publication: "replicator_publication".to_string(),
+            tables: Some(TableFilter {
+                include: vec!["public.*".to_string()],
+                exclude: vec!["public.audit_*".to_string()],
+            }),
+            columns: None,
+            connection: None,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_postgres_columns_test() {
+        let source = r#"{
+            "Postgres": {
+                "host": "localhost",
+                "port": 5432,
+                "name": "postgres",
+                "username": "postgres",
+                "slot_name": "replicator_slot",
+                "publication": "replicator_publication",
+                "columns": {
+                    "generated": "Replicate",
+                    "timestamps": "Utc"
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SourceSettings>(source);
+        let expected = SourceSettings::Postgres {
+            host: "localhost".to_string(),
+            port: 5432,
+            name: "postgres".to_string(),
+            username: "postgres".to_string(),
+            password: None,
+            slot_name: "replicator_slot".to_string(),
+            publication: "replicator_publication".to_string(),
+            tables: None,
+            columns: Some(ColumnOptions {
+                generated: ColumnHandling::Replicate,
+                identity: ColumnHandling::Replicate,
+                unknown_types: UnknownTypes::Text,
+                timestamps: Timestamps::Utc,
+                large_objects: LargeObjectOptions::default(),
+                types: vec![],
+                commit_timestamp: None,
+                conversion_errors: ConversionErrorPolicy::Fail,
+                max_value_size: None,
+            }),
+            connection: None,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_naming_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "naming": {
+                    "table_prefix": "pg_",
+                    "strategy": "PerSchema",
+                    "table_names": {
+                        "public.users": "customers"
+                    }
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: None,
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: Some(NamingOptions {
+                table_prefix: "pg_".to_string(),
+                table_suffix: "".to_string(),
+                table_names: BTreeMap::from([(
+                    "public.users".to_string(),
+                    "customers".to_string(),
+                )]),
+                strategy: NamingStrategy::PerSchema,
+            }),
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: None,
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_compaction_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "compaction": {
+                    "interval_secs": 600
+                }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: None,
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: None,
+            compaction: Some(CompactionOptions {
+                interval_secs: 600,
+                snapshot_suffix: "_snapshot".to_string(),
+            }),
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: None,
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_write_mode_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "write_mode": "AppendOnly"
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: None,
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: None,
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: Some(WriteMode::AppendOnly),
+            max_concurrent_streams: None,
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_table_overrides_test() {
+        let table_overrides = r#"{
+            "public.events": {
+                "destination": "events_log",
+                "write_mode": "AppendOnly",
+                "partitioning": {
+                    "Column": {
+                        "column": "created_at",
+                        "granularity": "day"
+                    }
+                },
+                "clustering": ["user_id"],
+                "masked_columns": ["email"],
+                "batch": {
+                    "max_size": 50000
+                }
+            },
+            "public.users": {}
+        }"#;
+        let actual = serde_json::from_str::<HashMap<String, TableOverrides>>(table_overrides);
+        let expected = HashMap::from([
+            (
+                "public.events".to_string(),
+                TableOverrides {
+                    destination: Some("events_log".to_string()),
+                    write_mode: Some(WriteMode::AppendOnly),
+                    partitioning: Some(TablePartitioning::Column {
+                        column: "created_at".to_string(),
+                        granularity: PartitionGranularity::Day,
+                    }),
+                    clustering: vec!["user_id".to_string()],
+                    masked_columns: vec!["email".to_string()],
+                    batch: Some(TableBatchSettings { max_size: 50000 }),
+                },
+            ),
+            ("public.users".to_string(), TableOverrides::default()),
+        ]);
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_max_concurrent_streams_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "max_concurrent_streams": 8
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: None,
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: None,
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: Some(8),
+            type_mappings: HashMap::new(),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_bigquery_type_mappings_test() {
+        let sink = r#"{
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": "dataset-id",
+                "type_mappings": { "uuid": "Bytes", "numeric": "Numeric" }
+            }
+        }"#;
+        let actual = serde_json::from_str::<SinkSettings>(sink);
+        let expected = SinkSettings::BigQuery {
+            project_id: "project-id".to_string(),
+            dataset_id: "dataset-id".to_string(),
+            service_account_key: None,
+            dataset_location: None,
+            dataset_labels: HashMap::new(),
+            schema_datasets: HashMap::new(),
+            table_options: HashMap::new(),
+            default_table_options: None,
+            naming: None,
+            compaction: None,
+            retry: None,
+            removed_column_policy: None,
+            removed_table_policy: None,
+            write_mode: None,
+            max_concurrent_streams: None,
+            type_mappings: HashMap::from([
+                ("uuid".to_string(), BigQueryType::Bytes),
+                ("numeric".to_string(), BigQueryType::Numeric),
+            ]),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_status_report_settings_test() {
+        let status_report = r#"{
+            "api_url": "http://api:8000",
+            "tenant_id": "abcdefghijklmnopqrst",
+            "pipeline_id": 1
+        }"#;
+        let actual = serde_json::from_str::<StatusReportSettings>(status_report);
+        let expected = StatusReportSettings {
+            api_url: "http://api:8000".to_string(),
+            api_key: None,
+            tenant_id: "abcdefghijklmnopqrst".to_string(),
+            pipeline_id: 1,
+            interval_secs: 10,
+            wal_poll_secs: 10,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_sharding_settings_test() {
+        let sharding = r#"{
+            "worker": 1,
+            "workers": 3
+        }"#;
+        let actual = serde_json::from_str::<ShardingSettings>(sharding);
+        let expected = ShardingSettings {
+            worker: 1,
+            workers: 3,
+            max_tables: None,
+            lease_secs: 60,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_high_availability_settings_test() {
+        let actual = serde_json::from_str::<HighAvailabilitySettings>("{}");
+        let expected = HighAvailabilitySettings { retry_secs: 2 };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn deserialize_error_reporting_settings_test() {
+        let error_reporting = r#"{"sentry_dsn": "https://key@sentry.example.com/1"}"#;
+        let actual = serde_json::from_str::<ErrorReportingSettings>(error_reporting);
+        let expected = ErrorReportingSettings {
+            sentry_dsn: "https://key@sentry.example.com/1".to_string(),
+            environment: None,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    pub fn secret_file_overrides_test() {
+        let path = std::env::temp_dir().join("replicator_secret_file_overrides_test");
+        std::fs::write(&path, "secret\n").unwrap();
+        let vars = vec![
+            (
+                "APP_SOURCE__POSTGRES__PASSWORD_FILE".to_string(),
+                path.to_string_lossy().to_string(),
+            ),
+            (
+                "APP_SOURCE__POSTGRES__HOST".to_string(),
+                "localhost".to_string(),
+            ),
+            ("OTHER_FILE".to_string(), "/missing".to_string()),
+        ];
+
+        let overrides = secret_file_overrides(vars.into_iter()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            overrides,
+            vec![("source.postgres.password".to_string(), "secret".to_string())]
+        );
+
+        let vars = vec![(
+            "APP_SOURCE__POSTGRES__PASSWORD_FILE".to_string(),
+            "/missing".to_string(),
+        )];
+        assert!(secret_file_overrides(vars.into_iter()).is_err());
+    }
 }
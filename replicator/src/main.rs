@@ -1,19 +1,42 @@
-use std::{error::Error, time::Duration};
-
-use configuration::{get_configuration, BatchSettings, SinkSettings, SourceSettings};
-use pg_replicate::pipeline::{
-    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
-    sinks::bigquery::BigQueryBatchSink,
-    sources::postgres::{PostgresSource, TableNamesFrom},
-    PipelineAction,
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
+
+use configuration::{
+    get_configuration, BatchSettings, Settings, SinkSettings, SourceSettings, TableOverrides,
+};
+#[cfg(feature = "sentry")]
+use pg_replicate::pipeline::reporting::sentry::SentryReporter;
+use pg_replicate::{
+    clients::bigquery::DatasetOptions,
+    pipeline::{
+        batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+        classification::{classify, ColumnClassification},
+        encryption::{ColumnEncryptor, EncryptedColumn, EncryptionOptions},
+        lag::WalPositionMonitor,
+        leadership::LeaderLock,
+        reporting::ErrorHook,
+        sinks::{
+            bigquery::{BigQueryBatchSink, DEFAULT_MAX_CONCURRENT_STREAMS},
+            RemovedTablePolicy,
+        },
+        sources::postgres::{PostgresSource, TableNamesFrom},
+        PipelineAction,
+    },
 };
-use tracing::{error, info};
+use sharding::Shard;
+use status_report::StatusReporter;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod configuration;
+mod sharding;
+mod status_report;
 
-// APP_SOURCE__POSTGRES__PASSWORD and APP_SINK__BIGQUERY__PROJECT_ID environment variables must be set
-// before running because these are sensitive values which can't be configured in the config files
+// The Postgres password and the BigQuery service account key are sensitive values which can't be configured in
+// the config files. Set them with the APP_SOURCE__POSTGRES__PASSWORD and APP_SINK__BIGQUERY__SERVICE_ACCOUNT_KEY
+// environment variables, or mount them as files named by APP_SOURCE__POSTGRES__PASSWORD_FILE and
+// APP_SINK__BIGQUERY__SERVICE_ACCOUNT_KEY_FILE. See `get_configuration` for the precedence of the settings' sources.
+// Without a service account key the BigQuery sink falls back to Application Default Credentials
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     if let Err(e) = main_impl().await {
@@ -39,6 +62,33 @@ fn set_log_level() {
     }
 }
 
+/// Returns the hook reporting errors to Sentry, tagged with the id of the
+/// pipeline if its status is reported
+fn error_hook(settings: &Settings) -> Result<Option<ErrorHook>, Box<dyn Error>> {
+    let Some(error_reporting) = &settings.error_reporting else {
+        return Ok(None);
+    };
+    #[cfg(feature = "sentry")]
+    {
+        let reporter = SentryReporter::init(
+            &error_reporting.sentry_dsn,
+            error_reporting.environment.clone(),
+        );
+        let error_hook = ErrorHook::new(reporter);
+        Ok(Some(match &settings.status_report {
+            Some(status_report) => {
+                error_hook.with_pipeline_id(status_report.pipeline_id.to_string())
+            }
+            None => error_hook,
+        }))
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        let _ = error_reporting;
+        Err("errors can't be reported to Sentry, the replicator was built without the sentry feature".into())
+    }
+}
+
 async fn main_impl() -> Result<(), Box<dyn Error>> {
     set_log_level();
     init_tracing();
@@ -51,6 +101,8 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
 
     info!("settings: {settings:#?}");
 
+    let error_hook = error_hook(&settings)?;
+
     let SourceSettings::Postgres {
         host,
         port,
@@ -59,7 +111,81 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
         password,
         slot_name,
         publication,
+        tables,
+        columns,
+        connection,
     } = settings.source;
+    let connection = connection.unwrap_or_default();
+
+    let table_names_from = |publication: &str| match &tables {
+        Some(table_filter) => {
+            TableNamesFrom::FilteredPublication(publication.to_string(), table_filter.clone())
+        }
+        None => TableNamesFrom::Publication(publication.to_string()),
+    };
+
+    // a worker of a sharded replicator streams changes over its own slot
+    let slot_name = match &settings.sharding {
+        Some(sharding) => format!("{slot_name}_{}", sharding.worker),
+        None => slot_name,
+    };
+
+    // a standby waits here until the leader replicating the slot stops
+    let mut leader_lock = match &settings.high_availability {
+        Some(high_availability) => Some(
+            LeaderLock::acquire(
+                &host,
+                port,
+                &name,
+                &username,
+                password.clone(),
+                &slot_name,
+                Duration::from_secs(high_availability.retry_secs.max(1)),
+                &connection,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    // a worker of a sharded replicator only replicates the tables it claimed
+    let (shard, table_names_from) = match &settings.sharding {
+        Some(sharding) => {
+            let shard = Shard::claim(
+                sharding,
+                &host,
+                port,
+                &name,
+                &username,
+                password.clone(),
+                &publication,
+                table_names_from(&publication),
+                &connection,
+            )
+            .await?;
+            let table_names_from =
+                TableNamesFrom::FilteredPublication(publication, shard.initial_filter());
+            (Some(shard), table_names_from)
+        }
+        None => (None, table_names_from(&publication)),
+    };
+
+    // the lag is only needed in the status reports
+    let wal_position_monitor = match &settings.status_report {
+        Some(status_report) => Some(
+            WalPositionMonitor::connect(
+                &host,
+                port,
+                &name,
+                &username,
+                password.clone(),
+                Duration::from_secs(status_report.wal_poll_secs.max(1)),
+                &connection,
+            )
+            .await?,
+        ),
+        None => None,
+    };
 
     let postgres_source = PostgresSource::new(
         &host,
@@ -68,7 +194,9 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
         &username,
         password,
         Some(slot_name),
-        TableNamesFrom::Publication(publication),
+        table_names_from,
+        columns.unwrap_or_default(),
+        connection,
     )
     .await?;
 
@@ -76,25 +204,223 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
         project_id,
         dataset_id,
         service_account_key,
+        dataset_location,
+        dataset_labels,
+        schema_datasets,
+        table_options,
+        default_table_options,
+        naming,
+        compaction,
+        retry,
+        removed_column_policy,
+        removed_table_policy,
+        write_mode,
+        max_concurrent_streams,
+        type_mappings,
     } = settings.sink;
 
-    let bigquery_sink =
-        BigQueryBatchSink::new_with_key(project_id, dataset_id, &service_account_key).await?;
+    // the workers of a sharded replicator share the copied tables, but
+    // each replicates only the tables it claimed
+    let removed_table_policy = removed_table_policy.unwrap_or_default();
+    if removed_table_policy != RemovedTablePolicy::Keep && settings.sharding.is_some() {
+        return Err("tables can't be pruned by the workers of a sharded replicator".into());
+    }
+
+    // the overrides of tables replace the pipeline's settings of the tables
+    let mut table_options = table_options;
+    let mut naming = naming.unwrap_or_default();
+    let mut table_write_modes = HashMap::new();
+    let mut table_batch_sizes = vec![];
+    let mut masked_columns = vec![];
+    for (table_name, table_overrides) in settings.table_overrides {
+        let TableOverrides {
+            destination,
+            write_mode,
+            partitioning,
+            clustering,
+            masked_columns: table_masked_columns,
+            batch,
+        } = table_overrides;
+        if let Some(destination) = destination {
+            naming.table_names.insert(table_name.clone(), destination);
+        }
+        if let Some(write_mode) = write_mode {
+            table_write_modes.insert(table_name.clone(), write_mode);
+        }
+        if partitioning.is_some() || !clustering.is_empty() {
+            let options = table_options
+                .entry(table_name.clone())
+                .or_insert_with(|| default_table_options.clone().unwrap_or_default());
+            if partitioning.is_some() {
+                options.partitioning = partitioning;
+            }
+            if !clustering.is_empty() {
+                options.clustering = clustering;
+            }
+        }
+        masked_columns.extend(
+            table_masked_columns
+                .into_iter()
+                .map(|column| EncryptedColumn {
+                    column: format!("{table_name}.{column}"),
+                    deterministic: true,
+                }),
+        );
+        if let Some(batch) = batch {
+            table_batch_sizes.push((table_name, batch.max_size));
+        }
+    }
+
+    let dataset_options = DatasetOptions {
+        location: dataset_location,
+        labels: dataset_labels,
+    };
+    let bigquery_sink = match service_account_key {
+        Some(service_account_key) => {
+            BigQueryBatchSink::new_with_key(project_id, dataset_id, &service_account_key).await?
+        }
+        None => BigQueryBatchSink::new_with_adc(project_id, dataset_id).await?,
+    };
+    let bigquery_sink = bigquery_sink
+        .with_dataset_options(dataset_options)
+        .with_schema_datasets(schema_datasets)
+        .with_table_options(table_options)
+        .with_default_table_options(default_table_options.unwrap_or_default())
+        .with_naming_options(naming)
+        .with_retry_config(retry.unwrap_or_default())
+        .with_removed_column_policy(removed_column_policy.unwrap_or_default())
+        .with_write_mode(write_mode.unwrap_or_default())
+        .with_table_write_modes(table_write_modes)
+        .with_type_mappings(type_mappings)
+        .with_max_concurrent_streams(
+            max_concurrent_streams.unwrap_or(DEFAULT_MAX_CONCURRENT_STREAMS),
+        );
+    let bigquery_sink = match compaction {
+        Some(compaction) => bigquery_sink.with_compaction_options(compaction),
+        None => bigquery_sink,
+    };
+    // the workers stream changes over different slots, so each keeps its
+    // position in its own row
+    let bigquery_sink = match &settings.sharding {
+        Some(sharding) => bigquery_sink.with_last_lsn_id(sharding.worker as i64 + 1),
+        None => bigquery_sink,
+    };
 
     let BatchSettings {
         max_size,
         max_fill_secs,
+        transactional,
     } = settings.batch;
 
-    let batch_config = BatchConfig::new(max_size, Duration::from_secs(max_fill_secs));
+    let bigquery_sink = match &error_hook {
+        Some(error_hook) => bigquery_sink.with_error_hook(error_hook.clone()),
+        None => bigquery_sink,
+    };
+
+    let mut encryption = settings.encryption;
+    if !masked_columns.is_empty() {
+        let Some(encryption) = &mut encryption else {
+            return Err(
+                "masked columns can only be encrypted with the encryption settings' key".into(),
+            );
+        };
+        encryption.columns.extend(masked_columns);
+    }
+    if let Some(classification) = &settings.classification {
+        let classifications = classify(&postgres_source, classification).await?;
+        for column_classification in &classifications {
+            info!("{column_classification}");
+        }
+        if classification.encrypt {
+            let Some(encryption) = &mut encryption else {
+                return Err(
+                    "classified columns can only be encrypted with the encryption settings' key"
+                        .into(),
+                );
+            };
+            encryption.columns.extend(
+                classifications
+                    .iter()
+                    .map(ColumnClassification::encrypted_column),
+            );
+        }
+    }
+
+    let mut batch_config = BatchConfig::new(max_size, Duration::from_secs(max_fill_secs))
+        .with_transactional(transactional);
+    for (table_name, max_size) in table_batch_sizes {
+        batch_config = batch_config.with_table_max_batch_size(table_name, max_size);
+    }
     let mut pipeline = BatchDataPipeline::new(
         postgres_source,
         bigquery_sink,
         PipelineAction::Both,
         batch_config,
-    );
+    )
+    .with_removed_table_policy(removed_table_policy);
+    if let Some(error_hook) = error_hook {
+        pipeline = pipeline.with_error_hook(error_hook);
+    }
+    if let Some(wal_position_monitor) = wal_position_monitor {
+        pipeline = pipeline.with_wal_position_monitor(wal_position_monitor);
+    }
+    if let Some(EncryptionOptions { key, columns }) = encryption {
+        let encryptor = ColumnEncryptor::new(&key, columns).await?;
+        pipeline = pipeline.with_column_encryptor(encryptor);
+    }
+
+    let status_reporter = settings.status_report.map(|status_report| {
+        let status_reporter = Arc::new(StatusReporter::new(status_report));
+        let status = pipeline.status();
+        let reporter = status_reporter.clone();
+        tokio::spawn(async move { reporter.report_periodically(status).await });
+        status_reporter
+    });
+
+    let mut shard = match shard {
+        Some(shard) => {
+            let (updates_tx, updates_rx) = mpsc::channel(1);
+            pipeline = pipeline.with_updates(updates_rx);
+            Some((shard, updates_tx))
+        }
+        None => None,
+    };
+
+    let run = async {
+        match &mut shard {
+            Some((shard, updates)) => {
+                let result = tokio::select! {
+                    result = pipeline.start() => result.map_err(Into::into),
+                    result = shard.keep_claiming(updates.clone()) => result,
+                };
+                if let Err(e) = shard.release().await {
+                    warn!("failed to release the claimed tables: {e}");
+                }
+                result
+            }
+            None => pipeline.start().await.map_err(Into::into),
+        }
+    };
+    let result: Result<(), Box<dyn Error>> = match &mut leader_lock {
+        Some(leader_lock) => tokio::select! {
+            result = run => result,
+            () = leader_lock.lost() => {
+                Err("lost the leader lock, a standby may be replicating the slot now".into())
+            }
+        },
+        None => run.await,
+    };
+
+    // report the final status, e.g. the error which stopped the pipeline,
+    // before exiting
+    if let Some(status_reporter) = status_reporter {
+        let status = pipeline.status().borrow().clone();
+        if let Err(e) = status_reporter.report(&status).await {
+            warn!("failed to report pipeline status: {e}");
+        }
+    }
 
-    pipeline.start().await?;
+    result?;
 
     Ok(())
 }
@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    time::Duration,
+};
+
+use pg_replicate::{
+    clients::postgres::{ColumnOptions, ConnectionOptions},
+    pipeline::{
+        sharding::TableLeases,
+        sources::{
+            postgres::{PostgresSource, TableFilter, TableNamesFrom},
+            Source,
+        },
+        PipelineUpdate,
+    },
+    table::{TableId, TableName},
+};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::configuration::ShardingSettings;
+
+/// The tables of the publication claimed by this worker
+pub struct Shard {
+    leases: TableLeases,
+    table_names: HashMap<TableId, TableName>,
+    max_tables: usize,
+    lease: Duration,
+    claimed: HashSet<TableId>,
+    /// Claimed tables which other workers replicated before
+    taken_over: HashSet<TableId>,
+}
+
+impl Shard {
+    /// Claims this worker's share of the tables selected by
+    /// `table_names_from`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn claim(
+        settings: &ShardingSettings,
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        publication: &str,
+        table_names_from: TableNamesFrom,
+        connection: &ConnectionOptions,
+    ) -> Result<Shard, Box<dyn Error>> {
+        if settings.worker >= settings.workers {
+            return Err(format!(
+                "worker {} is not one of the {} workers, workers are numbered from 0",
+                settings.worker, settings.workers
+            )
+            .into());
+        }
+
+        // a source without a slot only reads the tables' schemas
+        let source = PostgresSource::new(
+            host,
+            port,
+            database,
+            username,
+            password.clone(),
+            None,
+            table_names_from,
+            ColumnOptions::default(),
+            connection.clone(),
+        )
+        .await?;
+        let table_names: HashMap<TableId, TableName> = source
+            .get_table_schemas()
+            .iter()
+            .map(|(table_id, table_schema)| (*table_id, table_schema.table_name.clone()))
+            .collect();
+
+        let leases = TableLeases::connect(
+            host,
+            port,
+            database,
+            username,
+            password,
+            publication.to_string(),
+            settings.worker,
+            connection,
+        )
+        .await?;
+        let max_tables = settings
+            .max_tables
+            .unwrap_or_else(|| table_names.len().div_ceil(settings.workers as usize));
+
+        let mut shard = Shard {
+            leases,
+            table_names,
+            max_tables,
+            lease: Duration::from_secs(settings.lease_secs.max(1)),
+            claimed: HashSet::new(),
+            taken_over: HashSet::new(),
+        };
+        shard.claim_tables().await?;
+        info!(
+            "worker {} claimed {} of {} tables",
+            settings.worker,
+            shard.claimed.len(),
+            shard.table_names.len()
+        );
+        Ok(shard)
+    }
+
+    /// Claims the tables again, renewing the claims and claiming tables
+    /// whose claims expired, up to the maximum. Returns whether new tables
+    /// were claimed.
+    async fn claim_tables(&mut self) -> Result<bool, Box<dyn Error>> {
+        let mut table_ids: Vec<TableId> = self.table_names.keys().copied().collect();
+        table_ids.sort();
+        let claimed = self
+            .leases
+            .claim(&table_ids, self.max_tables, self.lease)
+            .await?;
+
+        let claimed_ids: HashSet<TableId> = claimed.iter().map(|table| table.table_id).collect();
+        if let Some(lost) = self.claimed.difference(&claimed_ids).next() {
+            return Err(format!(
+                "the claim on table {} expired and was taken by another worker",
+                self.table_names[lost]
+            )
+            .into());
+        }
+
+        let mut claimed_new_tables = false;
+        for table in claimed {
+            if self.claimed.insert(table.table_id) {
+                claimed_new_tables = true;
+                if table.taken_over {
+                    self.taken_over.insert(table.table_id);
+                }
+            }
+        }
+        Ok(claimed_new_tables)
+    }
+
+    fn filter(&self, table_ids: impl Iterator<Item = TableId>) -> TableFilter {
+        let mut include: Vec<String> = table_ids
+            .map(|table_id| self.table_names[&table_id].to_string())
+            .collect();
+        include.sort();
+        // a filter without include patterns selects all tables
+        let exclude = if include.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            vec![]
+        };
+        TableFilter { include, exclude }
+    }
+
+    /// Selects the tables the pipeline starts with. Tables taken over from
+    /// other workers are copied again once changes are streamed, see
+    /// [`Shard::keep_claiming`]
+    pub fn initial_filter(&self) -> TableFilter {
+        self.filter(self.claimed.difference(&self.taken_over).copied())
+    }
+
+    /// Renews the claims until one is lost, which returns an error as
+    /// another worker replicates the table now. Tables taken over from other
+    /// workers and tables claimed later are copied and added to the
+    /// pipeline by sending it an update.
+    pub async fn keep_claiming(
+        &mut self,
+        updates: mpsc::Sender<PipelineUpdate>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut claimed_new_tables = !self.taken_over.is_empty();
+        loop {
+            if claimed_new_tables {
+                let table_filter = self.filter(self.claimed.iter().copied());
+                if updates
+                    .send(PipelineUpdate::Tables(Some(table_filter)))
+                    .await
+                    .is_err()
+                {
+                    return Ok(());
+                }
+            }
+            tokio::time::sleep(self.lease / 3).await;
+            claimed_new_tables = self.claim_tables().await?;
+        }
+    }
+
+    /// Releases the claims so that other workers can claim the tables right
+    /// away
+    pub async fn release(&self) -> Result<(), Box<dyn Error>> {
+        self.leases.release().await?;
+        Ok(())
+    }
+}
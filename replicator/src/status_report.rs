@@ -0,0 +1,188 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use pg_replicate::{
+    pipeline::status::{PipelinePhase, PipelineStatus, TableMetrics},
+    table::TableId,
+};
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::configuration::StatusReportSettings;
+
+#[derive(serde::Serialize)]
+struct TableCopyReport {
+    table_name: String,
+    rows_copied: u64,
+    copied: bool,
+    estimated_rows: Option<u64>,
+    estimated_bytes: Option<u64>,
+    estimated_bytes_copied: Option<u64>,
+    copy_secs: f64,
+    eta_secs: Option<u64>,
+}
+
+#[derive(serde::Serialize)]
+struct LatencyBucket {
+    /// Upper bound of the bucket
+    le_secs: f64,
+    /// Cumulative count of writes at most as slow
+    count: u64,
+}
+
+#[derive(serde::Serialize)]
+struct TableMetricsReport {
+    table_name: String,
+    inserts: u64,
+    updates: u64,
+    deletes: u64,
+    conversion_errors: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    column_conversion_errors: BTreeMap<String, u64>,
+    sink_latency_buckets: Vec<LatencyBucket>,
+    sink_latency_sum_secs: f64,
+    sink_latency_count: u64,
+}
+
+impl TableMetricsReport {
+    fn new(table_name: String, metrics: &TableMetrics) -> Self {
+        TableMetricsReport {
+            table_name,
+            inserts: metrics.inserts,
+            updates: metrics.updates,
+            deletes: metrics.deletes,
+            conversion_errors: metrics.conversion_errors,
+            column_conversion_errors: metrics.column_conversion_errors.clone(),
+            sink_latency_buckets: metrics
+                .sink_latency
+                .cumulative_buckets()
+                .map(|(upper_bound, count)| LatencyBucket {
+                    le_secs: upper_bound.as_secs_f64(),
+                    count,
+                })
+                .collect(),
+            sink_latency_sum_secs: metrics.sink_latency.sum.as_secs_f64(),
+            sink_latency_count: metrics.sink_latency.count,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct StatusReport {
+    phase: &'static str,
+    last_flushed_lsn: Option<String>,
+    lag_bytes: Option<u64>,
+    lag_secs: Option<u64>,
+    source_wal_lsn: Option<String>,
+    estimated_lag_secs: Option<u64>,
+    tables: Vec<TableCopyReport>,
+    table_metrics: Vec<TableMetricsReport>,
+    last_error: Option<String>,
+    pid: u32,
+    host: Option<String>,
+}
+
+impl StatusReport {
+    fn new(status: &PipelineStatus, host: Option<String>) -> Self {
+        let phase = match status.phase {
+            PipelinePhase::Starting => "starting",
+            PipelinePhase::CopyingTables => "copying",
+            PipelinePhase::Cdc => "cdc",
+            PipelinePhase::Errored => "errored",
+        };
+        StatusReport {
+            phase,
+            last_flushed_lsn: status.last_flushed_lsn.map(|lsn| lsn.to_string()),
+            lag_bytes: status.lag_bytes,
+            lag_secs: status.lag.map(|lag| lag.as_secs()),
+            source_wal_lsn: status.source_wal_lsn.map(|lsn| lsn.to_string()),
+            estimated_lag_secs: status.estimated_lag.map(|lag| lag.as_secs()),
+            tables: status
+                .tables
+                .values()
+                .map(|table| TableCopyReport {
+                    table_name: table.table_name.to_string(),
+                    rows_copied: table.rows_copied,
+                    copied: table.copied,
+                    estimated_rows: table.estimated_rows,
+                    estimated_bytes: table.estimated_bytes,
+                    estimated_bytes_copied: table.estimated_bytes_copied(),
+                    copy_secs: table.copy_duration.as_secs_f64(),
+                    eta_secs: table.eta().map(|eta| eta.as_secs()),
+                })
+                .collect(),
+            table_metrics: status
+                .table_metrics
+                .iter()
+                .map(|(table_id, metrics)| {
+                    TableMetricsReport::new(table_name(status, *table_id), metrics)
+                })
+                .collect(),
+            last_error: status.last_error.clone(),
+            pid: std::process::id(),
+            host,
+        }
+    }
+}
+
+/// Returns the name of a table of the pipeline, or the id of a table which
+/// isn't replicated
+fn table_name(status: &PipelineStatus, table_id: TableId) -> String {
+    status
+        .tables
+        .get(&table_id)
+        .map(|table| table.table_name.to_string())
+        .unwrap_or_else(|| table_id.to_string())
+}
+
+/// Reports the status of the pipeline to the api, which stores it in the
+/// control database. The reports double as heartbeats: the api considers
+/// the pipeline stalled or crashed when they stop.
+pub struct StatusReporter {
+    client: reqwest::Client,
+    settings: StatusReportSettings,
+    /// Host the replicator runs on, the pod's name in kubernetes
+    host: Option<String>,
+}
+
+impl StatusReporter {
+    pub fn new(settings: StatusReportSettings) -> StatusReporter {
+        StatusReporter {
+            client: reqwest::Client::new(),
+            settings,
+            host: std::env::var("HOSTNAME").ok(),
+        }
+    }
+
+    pub async fn report(&self, status: &PipelineStatus) -> Result<(), reqwest::Error> {
+        let url = format!(
+            "{}/v1/pipelines/{}/status",
+            self.settings.api_url.trim_end_matches('/'),
+            self.settings.pipeline_id
+        );
+        let mut request = self
+            .client
+            .post(url)
+            .header("tenant_id", &self.settings.tenant_id)
+            .json(&StatusReport::new(status, self.host.clone()));
+        if let Some(api_key) = &self.settings.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Reports the status every `interval_secs` until the pipeline is dropped
+    pub async fn report_periodically(&self, mut status: watch::Receiver<PipelineStatus>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.interval_secs));
+        loop {
+            interval.tick().await;
+            let current_status = status.borrow_and_update().clone();
+            if let Err(e) = self.report(&current_status).await {
+                warn!("failed to report pipeline status: {e}");
+            }
+            if status.has_changed().is_err() {
+                return;
+            }
+        }
+    }
+}